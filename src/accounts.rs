@@ -0,0 +1,939 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{channel::Message, id::UserId},
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    chesscom, game,
+    lichess::{self, Account},
+    modules::BotModule,
+    settings::{self, UserSettings},
+    web, EMBED_SIDE_COLOR,
+};
+
+// Where the link token goes on each site.
+const LICHESS_PROFILE_URL: &str = "https://lichess.org/account/profile";
+const CHESSCOM_PROFILE_URL: &str = "https://www.chess.com/settings";
+
+// Profiles are looked up again once they're this old, and only this many are kept.
+const CACHE_TIME: Duration = Duration::from_secs(10 * 60);
+const CACHED_ACCOUNTS: usize = 200;
+
+// The ratings `.lichess` shows, by their key in the profile and their name.
+const PERFS: [(&str, &str); 5] = [
+    ("bullet", "Bullet"),
+    ("blitz", "Blitz"),
+    ("rapid", "Rapid"),
+    ("classical", "Classical"),
+    ("puzzle", "Puzzles"),
+];
+
+// Recent games `.chesscom` lists.
+const RECENT_GAMES: usize = 5;
+
+#[group]
+#[commands(link, unlink, lichess, chesscom, ratings)]
+struct Accounts;
+
+pub struct AccountsModule;
+
+impl BotModule for AccountsModule {
+    fn name(&self) -> &'static str {
+        "accounts"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lichess and chess.com accounts: linking them to members with `.link` and their ratings with `.lichess`, `.chesscom` and `.ratings`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&ACCOUNTS_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<PendingLinkContainer>(Mutex::default());
+        data.insert::<AccountCacheContainer>(Mutex::default());
+    }
+}
+
+// The sites members can link an account on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    Lichess,
+    ChessCom,
+}
+
+impl Site {
+    fn parse(name: &str) -> Option<Site> {
+        match name.to_lowercase().as_str() {
+            "lichess" | "lichess.org" => Some(Site::Lichess),
+            "chesscom" | "chess.com" => Some(Site::ChessCom),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Site::Lichess => "Lichess",
+            Site::ChessCom => "chess.com",
+        }
+    }
+
+    // How it's written in `.link`.
+    fn arg(self) -> &'static str {
+        match self {
+            Site::Lichess => "lichess",
+            Site::ChessCom => "chesscom",
+        }
+    }
+
+    fn is_username(self, name: &str) -> bool {
+        match self {
+            Site::Lichess => lichess::is_username(name),
+            Site::ChessCom => chesscom::is_username(name),
+        }
+    }
+
+    // The profile field the token goes in, and where it's edited.
+    fn token_place(self) -> (&'static str, &'static str) {
+        match self {
+            Site::Lichess => ("bio", LICHESS_PROFILE_URL),
+            Site::ChessCom => ("Location field", CHESSCOM_PROFILE_URL),
+        }
+    }
+
+    fn linked_mut(self, user: &mut UserSettings) -> &mut Option<String> {
+        match self {
+            Site::Lichess => &mut user.lichess,
+            Site::ChessCom => &mut user.chesscom,
+        }
+    }
+}
+
+// An account found on a site, with the profile text its token has to be in.
+struct FoundAccount {
+    username: String,
+    url: String,
+    proof: String,
+}
+
+async fn find_account(
+    client: &reqwest::Client,
+    site: Site,
+    username: &str,
+) -> reqwest::Result<Option<FoundAccount>> {
+    Ok(match site {
+        Site::Lichess => lichess::account(client, username)
+            .await?
+            .map(|account| FoundAccount {
+                url: account.url(),
+                proof: account
+                    .profile
+                    .as_ref()
+                    .and_then(|profile| profile.bio.clone())
+                    .unwrap_or_default(),
+                username: account.username,
+            }),
+        Site::ChessCom => chesscom::player(client, username)
+            .await?
+            .map(|player| FoundAccount {
+                username: player.username,
+                url: player.url,
+                proof: player.location.unwrap_or_default(),
+            }),
+    })
+}
+
+// A link waiting for its token to show up on the account's profile.
+pub struct PendingLink {
+    site: Site,
+    username: String,
+    token: String,
+}
+
+// Keyed by the member linking. Pending links don't last over a restart.
+pub struct PendingLinkContainer;
+
+impl TypeMapKey for PendingLinkContainer {
+    type Value = Mutex<HashMap<u64, PendingLink>>;
+}
+
+async fn with_pending<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut HashMap<u64, PendingLink>) -> T,
+{
+    let data = data.read().await;
+    let mut pending = data
+        .get::<PendingLinkContainer>()
+        .expect("Expected pending links in typemap.")
+        .lock()
+        .await;
+    f(&mut pending)
+}
+
+// Profiles looked up recently, keyed by lowercase username.
+#[derive(Default)]
+pub struct AccountCache {
+    accounts: HashMap<String, (Instant, Account)>,
+    order: VecDeque<String>,
+}
+
+impl AccountCache {
+    fn get(&self, username: &str) -> Option<Account> {
+        self.accounts
+            .get(&username.to_lowercase())
+            .filter(|(fetched, _)| fetched.elapsed() < CACHE_TIME)
+            .map(|(_, account)| account.clone())
+    }
+
+    fn insert(&mut self, account: Account) {
+        let key = account.username.to_lowercase();
+        let fresh = (Instant::now(), account);
+        if self.accounts.insert(key.clone(), fresh).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CACHED_ACCOUNTS {
+            if let Some(old) = self.order.pop_front() {
+                self.accounts.remove(&old);
+            }
+        }
+    }
+}
+
+pub struct AccountCacheContainer;
+
+impl TypeMapKey for AccountCacheContainer {
+    type Value = Mutex<AccountCache>;
+}
+
+// A Lichess profile, from the cache if it was looked up in the last few minutes.
+pub async fn cached_account(
+    data: &RwLock<TypeMap>,
+    username: &str,
+) -> reqwest::Result<Option<Account>> {
+    let cached = {
+        let data = data.read().await;
+        let cache = data
+            .get::<AccountCacheContainer>()
+            .expect("Expected account cache in typemap.")
+            .lock()
+            .await;
+        cache.get(username)
+    };
+    if let Some(account) = cached {
+        return Ok(Some(account));
+    }
+
+    let account = lichess::account(&web::client(data).await, username).await?;
+    if let Some(account) = &account {
+        let data = data.read().await;
+        let mut cache = data
+            .get::<AccountCacheContainer>()
+            .expect("Expected account cache in typemap.")
+            .lock()
+            .await;
+        cache.insert(account.clone());
+    }
+    Ok(account)
+}
+
+// The Lichess account a member linked, if they did.
+pub async fn lichess_username(data: &RwLock<TypeMap>, user_id: UserId) -> Option<String> {
+    settings::user(data, user_id).await.lichess
+}
+
+// The chess.com account a member linked, if they did.
+pub async fn chesscom_username(data: &RwLock<TypeMap>, user_id: UserId) -> Option<String> {
+    settings::user(data, user_id).await.chesscom
+}
+
+// Short enough to paste anywhere in a bio, unlikely to be there already.
+fn new_token() -> String {
+    format!("cutechess-{:06x}", rand::random::<u32>() & 0xff_ffff)
+}
+
+#[command]
+#[description(
+    "Link your Lichess or chess.com account, so commands can find it from your Discord name. To prove it's yours, I'll give you a token to put in your Lichess bio or chess.com location, then run the command again. With no account, shows the ones you linked."
+)]
+#[usage("[lichess|chesscom <username>]")]
+#[example("lichess DrNykterstein")]
+#[example("chesscom Hikaru")]
+async fn link(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user_id = msg.author.id;
+    let usage = "Use `.link lichess <username>` or `.link chesscom <username>`";
+    if args.is_empty() {
+        let user = settings::user(&ctx.data, user_id).await;
+        let mut linked = Vec::new();
+        if let Some(username) = &user.lichess {
+            linked.push(format!("<https://lichess.org/@/{}>", username));
+        }
+        if let Some(username) = &user.chesscom {
+            linked.push(format!("<https://www.chess.com/member/{}>", username));
+        }
+        let reply = if linked.is_empty() {
+            format!("You haven't linked an account. {}.", usage)
+        } else {
+            format!(
+                "You're linked to {}. `.unlink` removes them.",
+                linked.join(" and ")
+            )
+        };
+        msg.reply(&ctx.http, reply).await?;
+        return Ok(());
+    }
+
+    let site = match Site::parse(&args.single::<String>().unwrap_or_default()) {
+        Some(site) => site,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Only Lichess and chess.com accounts can be linked.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let username = match args.single::<String>() {
+        Ok(username) if site.is_username(&username) => username,
+        Ok(_) => {
+            msg.reply(
+                &ctx.http,
+                format!("That doesn't look like a {} username.", site.name()),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(_) => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+
+    let account = match find_account(&web::client(&ctx.data).await, site, &username).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                format!("There's no {} account called {}.", site.name(), username),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!(
+                "Error getting {} account {}: {:?}",
+                site.name(),
+                username,
+                why
+            );
+            msg.reply(
+                &ctx.http,
+                format!("I couldn't reach {} right now.", site.name()),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let (place, place_url) = site.token_place();
+
+    // The same account asked for again: check the profile for its token.
+    let token = with_pending(&ctx.data, |pending| {
+        pending
+            .get(&user_id.0)
+            .filter(|link| {
+                link.site == site && link.username.eq_ignore_ascii_case(&account.username)
+            })
+            .map(|link| link.token.clone())
+    })
+    .await;
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let token = new_token();
+            with_pending(&ctx.data, |pending| {
+                pending.insert(
+                    user_id.0,
+                    PendingLink {
+                        site,
+                        username: account.username.clone(),
+                        token: token.clone(),
+                    },
+                )
+            })
+            .await;
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "To show {} is yours, put `{}` anywhere in its {} at <{}>, then run `.link {} {}` again.",
+                    account.username,
+                    token,
+                    place,
+                    place_url,
+                    site.arg(),
+                    account.username
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    if !account.proof.contains(&token) {
+        msg.reply(
+            &ctx.http,
+            format!(
+                "I don't see `{}` in {}'s {} yet. Save it at <{}> and try again.",
+                token, account.username, place, place_url
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    with_pending(&ctx.data, |pending| pending.remove(&user_id.0)).await;
+    // An account is only ever linked to one member, whoever proved it last.
+    settings::update(&ctx.data, |settings| {
+        for user in settings.users.values_mut() {
+            let linked = site.linked_mut(user);
+            if linked
+                .as_ref()
+                .is_some_and(|linked| linked.eq_ignore_ascii_case(&account.username))
+            {
+                *linked = None;
+            }
+        }
+        let user = settings.users.entry(user_id.0).or_default();
+        *site.linked_mut(user) = Some(account.username.clone());
+    })
+    .await;
+    msg.reply(
+        &ctx.http,
+        format!(
+            "Linked to <{}>! You can take the token out of your {} now.",
+            account.url, place
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Remove the account linked to you on a site, or every account you linked if you leave it out."
+)]
+#[usage("[lichess|chesscom]")]
+async fn unlink(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let sites = match args.current() {
+        None => vec![Site::Lichess, Site::ChessCom],
+        Some(arg) => match Site::parse(arg) {
+            Some(site) => vec![site],
+            None => {
+                msg.reply(&ctx.http, "Use `.unlink lichess` or `.unlink chesscom`")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let user_id = msg.author.id.0;
+    let removed: Vec<String> = settings::update(&ctx.data, |settings| {
+        let user = match settings.users.get_mut(&user_id) {
+            Some(user) => user,
+            None => return Vec::new(),
+        };
+        sites
+            .iter()
+            .filter_map(|site| {
+                let username = site.linked_mut(user).take()?;
+                Some(format!("{} on {}", username, site.name()))
+            })
+            .collect()
+    })
+    .await;
+    let reply = if removed.is_empty() {
+        "You don't have an account linked there.".to_string()
+    } else {
+        format!("Unlinked {}.", removed.join(" and "))
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+// "**1850**? ▲ 12 · 1,204 games"
+fn perf_text(perf: &lichess::Perf) -> String {
+    let trend = match perf.prog {
+        0 => String::new(),
+        prog if prog > 0 => format!(" ▲ {}", prog),
+        prog => format!(" ▼ {}", -prog),
+    };
+    format!(
+        "**{}**{}{}\n{} {}",
+        perf.rating,
+        if perf.prov { "?" } else { "" },
+        trend,
+        perf.games,
+        if perf.games == 1 { "game" } else { "games" }
+    )
+}
+
+#[command]
+#[aliases("li")]
+#[description(
+    "Show someone's Lichess ratings, with how much each went up or down lately. Give a username or mention a member who linked their account, or leave it out for yours."
+)]
+#[usage("[username | @member]")]
+#[example("DrNykterstein")]
+async fn lichess(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let linked = |user_id: UserId| async move {
+        lichess_username(&ctx.data, user_id)
+            .await
+            .ok_or(if user_id == msg.author.id {
+                "Link your account with `.link lichess <username>`, or give a username."
+            } else {
+                "They haven't linked a Lichess account with `.link`."
+            })
+    };
+    let username = match args.current() {
+        None => linked(msg.author.id).await,
+        Some(arg) => match utils::parse_username(arg) {
+            Some(user_id) => linked(UserId(user_id)).await,
+            None if lichess::is_username(arg) => Ok(arg.to_string()),
+            None => Err("That doesn't look like a Lichess username."),
+        },
+    };
+    let username = match username {
+        Ok(username) => username,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    let account = match cached_account(&ctx.data, &username).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                format!("There's no Lichess account called {}.", username),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting Lichess account {}: {:?}", username, why);
+            msg.reply(&ctx.http, "I couldn't reach Lichess right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let ratings: Vec<(&str, String)> = PERFS
+        .iter()
+        .filter_map(|(key, name)| {
+            let perf = account.perfs.get(*key).filter(|perf| perf.games > 0)?;
+            Some((*name, perf_text(perf)))
+        })
+        .collect();
+    let title = match &account.title {
+        Some(title) => format!("{} {}", title, account.username),
+        None => account.username.clone(),
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.url(account.url());
+                e.color(EMBED_SIDE_COLOR);
+                if ratings.is_empty() {
+                    e.description("No rated games yet.");
+                }
+                for (name, text) in ratings {
+                    e.field(name, text, true);
+                }
+                e.footer(|f| {
+                    f.text("▲▼ over the last twelve games · ? provisional");
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// "**1850** · best 1904 <t:..:d>\n312 W · 280 L · 41 D"
+fn chesscom_text(stats: &chesscom::GameStats) -> String {
+    let best = match &stats.best {
+        Some(best) if best.rating > stats.last.rating => {
+            format!(" · best {} <t:{}:d>", best.rating, best.date)
+        }
+        _ => String::new(),
+    };
+    format!(
+        "**{}**{}\n{} W · {} L · {} D",
+        stats.last.rating, best, stats.record.win, stats.record.loss, stats.record.draw
+    )
+}
+
+// "Won vs hikaru (3120) · rated blitz · 2 hours ago", linked to the game.
+fn recent_game_line(game: &chesscom::ArchivedGame, username: &str) -> Option<String> {
+    let (me, them) = game.side_of(username)?;
+    let outcome = match me.result.as_str() {
+        "win" => "Won",
+        "agreed" | "repetition" | "stalemate" | "insufficient" | "50move"
+        | "timevsinsufficient" => "Drew",
+        _ => "Lost",
+    };
+    Some(format!(
+        "[{} vs {} ({})]({}) · {} {} · <t:{}:R>",
+        outcome,
+        them.username,
+        them.rating,
+        game.url,
+        if game.rated { "rated" } else { "casual" },
+        game.time_class,
+        game.end_time
+    ))
+}
+
+#[command]
+#[aliases("cc")]
+#[description(
+    "Show someone's chess.com ratings, their best ones and their latest games. Give a username or mention a member who linked their account, or leave it out for yours."
+)]
+#[usage("[username | @member]")]
+#[example("Hikaru")]
+async fn chesscom(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let linked = |user_id: UserId| async move {
+        chesscom_username(&ctx.data, user_id)
+            .await
+            .ok_or(if user_id == msg.author.id {
+                "Link your account with `.link chesscom <username>`, or give a username."
+            } else {
+                "They haven't linked a chess.com account with `.link`."
+            })
+    };
+    let username = match args.current() {
+        None => linked(msg.author.id).await,
+        Some(arg) => match utils::parse_username(arg) {
+            Some(user_id) => linked(UserId(user_id)).await,
+            None if chesscom::is_username(arg) => Ok(arg.to_string()),
+            None => Err("That doesn't look like a chess.com username."),
+        },
+    };
+    let username = match username {
+        Ok(username) => username,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    let client = web::client(&ctx.data).await;
+    let found = match chesscom::player(&client, &username).await {
+        Ok(Some(player)) => chesscom::stats(&client, &username)
+            .await
+            .map(|stats| Some((player, stats))),
+        Ok(None) => Ok(None),
+        Err(why) => Err(why),
+    };
+    let (player, stats) = match found {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                format!("There's no chess.com account called {}.", username),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting chess.com account {}: {:?}", username, why);
+            msg.reply(&ctx.http, "I couldn't reach chess.com right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+    // The ratings are worth showing without them.
+    let games = chesscom::recent_games(&client, &username, RECENT_GAMES)
+        .await
+        .unwrap_or_else(|why| {
+            println!("Error getting chess.com games of {}: {:?}", username, why);
+            Vec::new()
+        });
+
+    let mut ratings: Vec<(&str, String)> = [
+        ("Bullet", &stats.chess_bullet),
+        ("Blitz", &stats.chess_blitz),
+        ("Rapid", &stats.chess_rapid),
+        ("Daily", &stats.chess_daily),
+    ]
+    .iter()
+    .filter_map(|(name, stats)| Some((*name, chesscom_text(stats.as_ref()?))))
+    .collect();
+    if let Some(highest) = stats.tactics.as_ref().and_then(|t| t.highest.as_ref()) {
+        ratings.push(("Puzzles", format!("best **{}**", highest.rating)));
+    }
+    let recent: Vec<String> = games
+        .iter()
+        .filter_map(|game| recent_game_line(game, &player.username))
+        .collect();
+    let title = match &player.title {
+        Some(title) => format!("{} {}", title, player.username),
+        None => player.username.clone(),
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.url(&player.url);
+                e.color(EMBED_SIDE_COLOR);
+                if ratings.is_empty() {
+                    e.description("No rated games yet.");
+                }
+                for (name, text) in ratings {
+                    e.field(name, text, true);
+                }
+                if !recent.is_empty() {
+                    e.field("Recent games", recent.join("\n"), false);
+                }
+                e.footer(|f| {
+                    f.text("chess.com");
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// One site's ratings for `.ratings`.
+struct SiteRatings {
+    name: String,
+    text: String,
+    games: u32,
+    // The last time they played or were online there, in seconds since the epoch.
+    active_at: Option<i64>,
+}
+
+async fn lichess_ratings(data: &RwLock<TypeMap>, username: &str) -> SiteRatings {
+    let mut site = SiteRatings {
+        name: format!("Lichess · {}", username),
+        text: String::new(),
+        games: 0,
+        active_at: None,
+    };
+    let account = match cached_account(data, username).await {
+        Ok(Some(account)) => account,
+        Ok(None) => {
+            site.text = "That account is gone.".to_string();
+            return site;
+        }
+        Err(why) => {
+            println!("Error getting Lichess account {}: {:?}", username, why);
+            site.text = "I couldn't reach Lichess right now.".to_string();
+            return site;
+        }
+    };
+    let ratings: Vec<String> = PERFS
+        .iter()
+        .filter(|(key, _)| *key != "puzzle")
+        .filter_map(|(key, name)| {
+            let perf = account.perfs.get(*key).filter(|perf| perf.games > 0)?;
+            site.games += perf.games;
+            Some(format!(
+                "{} **{}**{}",
+                name,
+                perf.rating,
+                if perf.prov { "?" } else { "" }
+            ))
+        })
+        .collect();
+    site.text = ratings.join(" · ");
+    site.active_at = account.seen_at.map(|ms| ms / 1000);
+    site
+}
+
+async fn chesscom_ratings(client: &reqwest::Client, username: &str) -> SiteRatings {
+    let mut site = SiteRatings {
+        name: format!("chess.com · {}", username),
+        text: String::new(),
+        games: 0,
+        active_at: None,
+    };
+    let stats = match chesscom::stats(client, username).await {
+        Ok(stats) => stats,
+        Err(why) => {
+            println!("Error getting chess.com stats of {}: {:?}", username, why);
+            site.text = "I couldn't reach chess.com right now.".to_string();
+            return site;
+        }
+    };
+    let ratings: Vec<String> = [
+        ("Bullet", &stats.chess_bullet),
+        ("Blitz", &stats.chess_blitz),
+        ("Rapid", &stats.chess_rapid),
+        ("Daily", &stats.chess_daily),
+    ]
+    .iter()
+    .filter_map(|(name, stats)| {
+        let stats = stats.as_ref()?;
+        let record = &stats.record;
+        site.games += record.win + record.loss + record.draw;
+        // A rating changes with every rated game, so the newest one was played last.
+        site.active_at = site.active_at.max(Some(stats.last.date));
+        Some(format!("{} **{}**", name, stats.last.rating))
+    })
+    .collect();
+    site.text = ratings.join(" · ");
+    site
+}
+
+#[command]
+#[description(
+    "Show a member's Lichess, chess.com and server ratings side by side, from the accounts they linked with `.link`, with the site they play most and the one they were on last marked."
+)]
+#[usage("[@member]")]
+async fn ratings(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let user_id = match args.current() {
+        Some(arg) => match utils::parse_username(arg) {
+            Some(id) => UserId(id),
+            None => {
+                msg.reply(&ctx.http, "Use `.ratings` or `.ratings @member`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => msg.author.id,
+    };
+
+    let user = settings::user(&ctx.data, user_id).await;
+    let mut sites = Vec::new();
+    if let Some(username) = &user.lichess {
+        sites.push(lichess_ratings(&ctx.data, username).await);
+    }
+    if let Some(username) = &user.chesscom {
+        sites.push(chesscom_ratings(&web::client(&ctx.data).await, username).await);
+    }
+    if let Some(guild_id) = msg.guild_id {
+        let rating = game::read_games(&ctx.data, |store| {
+            store
+                .ratings
+                .get(&guild_id.0)
+                .and_then(|guild| guild.get(&user_id.0))
+                .cloned()
+        })
+        .await;
+        if let Some(rating) = rating {
+            sites.push(SiteRatings {
+                name: "This server".to_string(),
+                text: format!("**{}**", rating.rating),
+                games: rating.games(),
+                active_at: rating.history.last().map(|change| change.at),
+            });
+        }
+    }
+    if sites.is_empty() {
+        let reply = if user_id == msg.author.id {
+            "You haven't linked an account yet, use `.link lichess <username>` or `.link chesscom <username>`.".to_string()
+        } else {
+            format!(
+                "<@{}> hasn't linked an account or played a rated game here yet.",
+                user_id
+            )
+        };
+        msg.channel_id
+            .send_message(&ctx.http, |m| {
+                m.content(reply);
+                m.reference_message(msg);
+                m.allowed_mentions(|a| a.empty_users())
+            })
+            .await?;
+        return Ok(());
+    }
+
+    // Only worth marking when there's more than one to pick from.
+    let most_games = sites
+        .iter()
+        .enumerate()
+        .filter(|(_, site)| site.games > 0)
+        .max_by_key(|(_, site)| site.games)
+        .map(|(index, _)| index)
+        .filter(|_| sites.len() > 1);
+    let latest = sites
+        .iter()
+        .enumerate()
+        .filter_map(|(index, site)| Some((index, site.active_at?)))
+        .max_by_key(|(_, at)| *at)
+        .map(|(index, _)| index)
+        .filter(|_| sites.len() > 1);
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Ratings");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(format!("<@{}>", user_id));
+                for (index, site) in sites.iter().enumerate() {
+                    let mut name = site.name.clone();
+                    if most_games == Some(index) {
+                        name.push_str(" 🔥");
+                    }
+                    if latest == Some(index) {
+                        name.push_str(" 🕒");
+                    }
+                    let mut text = match site.text.as_str() {
+                        "" => "No rated games yet.".to_string(),
+                        text => text.to_string(),
+                    };
+                    let mut details = Vec::new();
+                    if site.games > 0 {
+                        details.push(format!(
+                            "{} {}",
+                            site.games,
+                            if site.games == 1 { "game" } else { "games" }
+                        ));
+                    }
+                    if let Some(at) = site.active_at {
+                        details.push(format!("active <t:{}:R>", at));
+                    }
+                    if !details.is_empty() {
+                        text.push_str(&format!("\n{}", details.join(" · ")));
+                    }
+                    e.field(name, text, false);
+                }
+                e.footer(|f| {
+                    f.text("🔥 most games · 🕒 active most recently");
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
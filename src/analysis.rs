@@ -0,0 +1,440 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::ChannelId,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    pgn, settings, EMBED_SIDE_COLOR,
+};
+
+const CLAIM_ID: &str = "analysis:claim";
+const RELEASE_ID: &str = "analysis:release";
+const DONE_ID: &str = "analysis:done";
+
+#[group]
+#[commands(requestanalysis, analysisqueue)]
+struct Analysis;
+
+pub struct AnalysisModule;
+
+const ANALYSIS_SETTINGS: &[ModuleSetting] = &[
+    ModuleSetting {
+        name: "Queue channel",
+        command: "analysisqueue",
+        value: |guild| modules::show_channel(guild.analysis_channel),
+    },
+    ModuleSetting {
+        name: "Coach role",
+        command: "analysisqueue",
+        value: |guild| modules::show_role(guild.coach_role),
+    },
+];
+
+#[async_trait]
+impl BotModule for AnalysisModule {
+    fn name(&self) -> &'static str {
+        "analysis"
+    }
+
+    fn description(&self) -> &'static str {
+        "Asking the coaches to look at a game."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&ANALYSIS_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        ANALYSIS_SETTINGS
+    }
+}
+
+// Someone asking the server's coaches to look at one of their games.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisRequest {
+    // The request's message in the queue channel, which carries the buttons.
+    pub queue_message_id: u64,
+    pub guild_id: u64,
+    // Where it was asked for, so the requester hears back there.
+    pub channel_id: u64,
+    pub requester: u64,
+    // A PGN, a link or an attachment URL.
+    pub game: String,
+    pub note: String,
+    pub claimed_by: Option<u64>,
+    // Seconds since the epoch.
+    pub requested_at: i64,
+    pub claimed_at: Option<i64>,
+    pub answered_at: Option<i64>,
+}
+
+fn format_duration(secs: i64) -> String {
+    let minutes = secs / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, m) if h < 48 => format!("{}h {}m", h, m),
+        (h, _) => format!("{}d {}h", h / 24, h % 24),
+    }
+}
+
+fn request_embed<'a>(e: &'a mut CreateEmbed, request: &AnalysisRequest) -> &'a mut CreateEmbed {
+    // Show what a pasted PGN is at a glance, links speak for themselves.
+    let game = match pgn::parse(&request.game) {
+        Ok(game) => format!(
+            "{}\n{} moves, {}\n```{}```",
+            game.players().unwrap_or_else(|| "Pasted game".to_string()),
+            game.ply_count().div_ceil(2),
+            game.result(),
+            request.game.chars().take(900).collect::<String>()
+        ),
+        Err(_) => request.game.clone(),
+    };
+
+    let mut desc = format!("From <@{}>\n\n{}", request.requester, game);
+    if !request.note.is_empty() {
+        desc.push_str(&format!("\n\n> {}", request.note));
+    }
+
+    let status = match (request.claimed_by, request.answered_at) {
+        (Some(coach), Some(answered_at)) => format!(
+            "Answered by <@{}> after {}",
+            coach,
+            format_duration(answered_at - request.requested_at)
+        ),
+        (Some(coach), None) => format!("Claimed by <@{}>", coach),
+        (None, _) => "Waiting for a coach".to_string(),
+    };
+    desc.push_str(&format!("\n\n**{}**", status));
+
+    e.title("Analysis request");
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e
+}
+
+fn request_buttons<'a>(
+    c: &'a mut CreateComponents,
+    request: &AnalysisRequest,
+) -> &'a mut CreateComponents {
+    if request.answered_at.is_some() {
+        return c;
+    }
+
+    c.create_action_row(|row| {
+        if request.claimed_by.is_some() {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Success);
+                b.label("Mark answered");
+                b.custom_id(DONE_ID);
+                b
+            });
+            row.create_button(|b| {
+                b.style(ButtonStyle::Secondary);
+                b.label("Release");
+                b.custom_id(RELEASE_ID);
+                b
+            });
+        } else {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Primary);
+                b.label("Claim");
+                b.custom_id(CLAIM_ID);
+                b
+            });
+        }
+        row
+    })
+}
+
+async fn reply_privately(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await;
+
+    if let Err(why) = result {
+        println!("Error replying to an analysis button: {:?}", why);
+    }
+}
+
+// Claim, release or close a request when a coach presses its buttons.
+// Returns false if the button isn't ours.
+pub async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if ![CLAIM_ID, RELEASE_ID, DONE_ID].contains(&id) {
+        return false;
+    }
+
+    let guild = match component.guild_id {
+        Some(guild_id) => settings::guild(&ctx.data, guild_id).await,
+        None => return true,
+    };
+    let is_coach = match (guild.coach_role, &component.member) {
+        (Some(role), Some(member)) => member.roles.iter().any(|r| r.0 == role),
+        _ => false,
+    };
+    if !is_coach {
+        reply_privately(ctx, component, "Only coaches can take analysis requests.").await;
+        return true;
+    }
+
+    let coach = component.user.id.0;
+    let now = Utc::now().timestamp();
+    let outcome = settings::update(&ctx.data, |settings| {
+        let request = settings
+            .analysis_requests
+            .iter_mut()
+            .find(|r| r.queue_message_id == component.message.id.0)?;
+
+        let allowed = match id {
+            CLAIM_ID => request.claimed_by.is_none(),
+            _ => request.claimed_by == Some(coach) && request.answered_at.is_none(),
+        };
+        if allowed {
+            match id {
+                CLAIM_ID => {
+                    request.claimed_by = Some(coach);
+                    request.claimed_at = Some(now);
+                }
+                RELEASE_ID => {
+                    request.claimed_by = None;
+                    request.claimed_at = None;
+                }
+                _ => request.answered_at = Some(now),
+            }
+        }
+        Some((allowed, request.clone()))
+    })
+    .await;
+
+    let request = match outcome {
+        Some((true, request)) => request,
+        Some((false, _)) => {
+            reply_privately(ctx, component, "Someone else has this one.").await;
+            return true;
+        }
+        None => {
+            reply_privately(ctx, component, "I don't know about this request anymore.").await;
+            return true;
+        }
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.create_embed(|e| request_embed(e, &request));
+                d.components(|c| request_buttons(c, &request))
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating analysis request: {:?}", why);
+    }
+
+    if request.answered_at.is_some() {
+        let link = component
+            .message
+            .id
+            .link(component.channel_id, component.guild_id);
+        let result = ChannelId(request.channel_id)
+            .send_message(&ctx.http, |m| {
+                m.content(format!(
+                    "<@{}>, <@{}> has answered your analysis request: {}",
+                    request.requester, coach, link
+                ));
+                m.allowed_mentions(|a| a.users(vec![request.requester]))
+            })
+            .await;
+        if let Err(why) = result {
+            println!(
+                "Could not tell the requester about their analysis: {:?}",
+                why
+            );
+        }
+    }
+
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Ask the server's coaches to look at one of your games. Paste a PGN or a game link, or attach a PGN file.")]
+#[usage("<pgn|link> [| note]")]
+#[example("https://lichess.org/abcdefgh | Where did I go wrong in the endgame?")]
+async fn requestanalysis(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let queue = match settings::guild(&ctx.data, guild_id).await.analysis_channel {
+        Some(queue) => ChannelId(queue),
+        None => {
+            msg.reply(&ctx.http, "This server doesn't take analysis requests.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let (game, note) = match args.rest().split_once('|') {
+        Some((game, note)) => (game.trim(), note.trim()),
+        None => (args.rest().trim(), ""),
+    };
+    let game = match (game, msg.attachments.first()) {
+        ("", Some(attachment)) => attachment.url.clone(),
+        ("", None) => {
+            msg.reply(
+                &ctx.http,
+                "Use `.requestanalysis <pgn or link>`, or attach a PGN file",
+            )
+            .await?;
+            return Ok(());
+        }
+        (game, _) => game.to_string(),
+    };
+
+    let mut request = AnalysisRequest {
+        queue_message_id: 0,
+        guild_id: guild_id.0,
+        channel_id: msg.channel_id.0,
+        requester: msg.author.id.0,
+        game,
+        note: note.to_string(),
+        claimed_by: None,
+        requested_at: Utc::now().timestamp(),
+        claimed_at: None,
+        answered_at: None,
+    };
+
+    let sent = queue
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| request_embed(e, &request));
+            m.components(|c| request_buttons(c, &request));
+            m
+        })
+        .await?;
+
+    request.queue_message_id = sent.id.0;
+    settings::update(&ctx.data, |settings| {
+        settings.analysis_requests.push(request)
+    })
+    .await;
+
+    msg.reply(
+        &ctx.http,
+        "Sent to the coaches! You'll hear back here once someone has looked at it.",
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Set the channel analysis requests go to and the role of the coaches who answer them, or show how the queue is doing.")]
+#[usage("[#channel @coach-role | off]")]
+async fn analysisqueue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+
+    let reply = match args.current() {
+        None => {
+            let requests = settings::read(&ctx.data, |settings| {
+                settings
+                    .analysis_requests
+                    .iter()
+                    .filter(|r| r.guild_id == guild_id.0)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .await;
+
+            let open = requests.iter().filter(|r| r.claimed_by.is_none()).count();
+            let claimed = requests
+                .iter()
+                .filter(|r| r.claimed_by.is_some() && r.answered_at.is_none())
+                .count();
+            let turnarounds = requests
+                .iter()
+                .filter_map(|r| Some(r.answered_at? - r.requested_at))
+                .collect::<Vec<_>>();
+
+            let mut reply = format!("{} waiting, {} being looked at.", open, claimed);
+            if !turnarounds.is_empty() {
+                let average = turnarounds.iter().sum::<i64>() / turnarounds.len() as i64;
+                reply.push_str(&format!(
+                    " {} answered, in {} on average.",
+                    turnarounds.len(),
+                    format_duration(average)
+                ));
+            }
+            reply
+        }
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => {
+            settings::update(&ctx.data, |settings| {
+                settings
+                    .guilds
+                    .entry(guild_id.0)
+                    .or_default()
+                    .analysis_channel = None;
+            })
+            .await;
+            "Analysis requests are off.".to_string()
+        }
+        Some(_) => {
+            let channel = args.single::<String>().ok().and_then(utils::parse_channel);
+            let role = args.current().and_then(utils::parse_role);
+            match (channel, role) {
+                (Some(channel), Some(role)) => {
+                    settings::update(&ctx.data, |settings| {
+                        let guild = settings.guilds.entry(guild_id.0).or_default();
+                        guild.analysis_channel = Some(channel);
+                        guild.coach_role = Some(role);
+                    })
+                    .await;
+                    format!(
+                        "Analysis requests will go to <#{}> for <@&{}> to pick up.",
+                        channel, role
+                    )
+                }
+                _ => {
+                    "Use `.analysisqueue #channel @coach-role` or `.analysisqueue off`".to_string()
+                }
+            }
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(reply);
+            m.allowed_mentions(|a| a.empty_roles());
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,414 @@
+use std::borrow::Cow;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::AttachmentType,
+    model::channel::Message,
+    prelude::*,
+};
+use shakmaty::{uci::UciMove, Board, Color, Position, Square};
+
+use crate::{
+    card::{self, Card},
+    diagram::{self, DiagramOptions},
+    eval, game,
+    gif::Animation,
+    modules::BotModule,
+    pgn, EMBED_SIDE_COLOR,
+};
+
+const MAX_GIF_PLIES: usize = 300;
+
+// Discord's upload limit in servers without boosts.
+const MAX_GIF_BYTES: usize = 8 * 1024 * 1024;
+
+// Board sizes in pixels, tried in order until the GIF fits under the upload limit.
+const GIF_SIZES: [u32; 3] = [360, 240, 160];
+
+const DEFAULT_DELAY_MS: u32 = 1000;
+const MIN_DELAY_MS: u32 = 200;
+const MAX_DELAY_MS: u32 = 5000;
+
+// The final position stays up this long before the GIF starts over, in hundredths of a second.
+const FINAL_DELAY: u16 = 300;
+
+const GIF_FILE: &str = "game.gif";
+
+// Layout of `.heatmap`, in pixels.
+const HEATMAP_BOARD: f32 = 320.0;
+const HEATMAP_MARGIN: f32 = 20.0;
+const HEATMAP_TOP: f32 = 76.0;
+const HEATMAP_TINTS: [&str; 2] = ["#e8590c", "#1864ab"];
+
+#[group]
+#[commands(gif, heatmap)]
+struct Animations;
+
+pub struct AnimationsModule;
+
+impl BotModule for AnimationsModule {
+    fn name(&self) -> &'static str {
+        "animations"
+    }
+
+    fn description(&self) -> &'static str {
+        "Animated GIFs and heatmaps of whole games, see `.gif` and `.heatmap`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&ANIMATIONS_GROUP)
+    }
+}
+
+// Every board of a game in order, with the move that led to it highlighted.
+struct Frames {
+    title: String,
+    boards: Vec<(Board, Option<(Square, Square)>)>,
+    flipped: bool,
+}
+
+// The boards of a game played here, seen from the author's side if they played black.
+fn stored_frames(game: &game::Game, author: u64) -> Frames {
+    let mut pos = game.start_position();
+    let mut boards = vec![(pos.board().clone(), None)];
+    for uci in &game.moves {
+        let uci = match uci.parse::<UciMove>() {
+            Ok(uci) => uci,
+            Err(_) => break,
+        };
+        let m = match uci.to_move(&pos) {
+            Ok(m) => m,
+            Err(_) => break,
+        };
+        let highlight = match uci {
+            UciMove::Normal { from, to, .. } => Some((from, to)),
+            UciMove::Put { to, .. } => Some((to, to)),
+            UciMove::Null => None,
+        };
+        pos.play_unchecked(m);
+        boards.push((pos.board().clone(), highlight));
+    }
+    Frames {
+        title: format!("Game #{}", game.id),
+        boards,
+        flipped: game.color_of(author) == Some(Color::Black),
+    }
+}
+
+fn pgn_frames(game: &pgn::PgnGame) -> Frames {
+    let mut pos = game.start.clone();
+    let mut boards = vec![(pos.board().clone(), None)];
+    for &m in &game.moves {
+        let highlight = m.from().map(|from| (from, m.to()));
+        pos.play_unchecked(m);
+        boards.push((pos.board().clone(), highlight));
+    }
+    Frames {
+        title: game.players().unwrap_or_else(|| "Game".to_string()),
+        boards,
+        flipped: false,
+    }
+}
+
+// The GIF at the biggest size that fits under the upload limit, if any does.
+fn render_gif(frames: &Frames, delay: u16) -> Option<Vec<u8>> {
+    for &size in &GIF_SIZES {
+        let mut animation = Animation::new(size as u16, size as u16);
+        for (board, highlight) in &frames.boards {
+            let options = DiagramOptions {
+                flipped: frames.flipped,
+                highlight: *highlight,
+            };
+            let pixmap = diagram::board_pixmap(board, &options, size, false)?;
+            let rgb: Vec<u8> = pixmap
+                .data()
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect();
+            animation.push(&rgb, delay);
+        }
+        animation.hold_last(FINAL_DELAY);
+
+        let gif = animation.encode();
+        if gif.len() <= MAX_GIF_BYTES {
+            return Some(gif);
+        }
+    }
+    None
+}
+
+// How often white's and black's pieces, in that order, stood on each square over the game, or with `moves`
+// how often they moved there, from 0 to 1 for the busiest square.
+fn square_heat(frames: &Frames, moves: bool) -> [[f32; 64]; 2] {
+    let mut counts = [[0u32; 64]; 2];
+    if moves {
+        for (board, highlight) in &frames.boards {
+            if let Some(piece) = highlight.and_then(|(_, to)| board.piece_at(to).map(|p| (to, p))) {
+                counts[piece.1.color.fold_wb(0, 1)][usize::from(piece.0)] += 1;
+            }
+        }
+    } else {
+        for (board, _) in &frames.boards {
+            for (sq, piece) in board.clone() {
+                counts[piece.color.fold_wb(0, 1)][usize::from(sq)] += 1;
+            }
+        }
+    }
+
+    let mut heat = [[0.0; 64]; 2];
+    for (side, counts) in counts.iter().enumerate() {
+        let most = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        for (sq, &count) in counts.iter().enumerate() {
+            // The square root keeps squares visited a few times from disappearing next to the
+            // king's home square.
+            heat[side][sq] = (count as f32 / most).sqrt();
+        }
+    }
+    heat
+}
+
+// White's heatmap next to black's.
+fn render_heatmap(frames: &Frames, moves: bool) -> Option<Vec<u8>> {
+    let heat = square_heat(frames, moves);
+    let width = HEATMAP_BOARD * 2.0 + HEATMAP_MARGIN * 3.0;
+    let height = HEATMAP_TOP + HEATMAP_BOARD + HEATMAP_MARGIN;
+    let mut card = Card::new(width as u32, height as u32);
+
+    let what = if moves {
+        "where the pieces moved"
+    } else {
+        "where the pieces stood"
+    };
+    let title = card::fit(
+        &format!("{} - {}", frames.title, what),
+        3.0,
+        width - HEATMAP_MARGIN * 2.0,
+    );
+    card.text(HEATMAP_MARGIN, 16.0, 3.0, card::TEXT, &title);
+
+    for (side, name) in ["White", "Black"].iter().enumerate() {
+        let x = HEATMAP_MARGIN + side as f32 * (HEATMAP_BOARD + HEATMAP_MARGIN);
+        card.text(x, 50.0, 2.0, card::MUTED, name);
+        card.push(&format!(
+            r#"<svg x="{}" y="{}" width="{}" height="{}" viewBox="0 0 800 800">{}</svg>"#,
+            x,
+            HEATMAP_TOP,
+            HEATMAP_BOARD,
+            HEATMAP_BOARD,
+            diagram::heatmap_squares(&heat[side], HEATMAP_TINTS[side], frames.flipped)
+        ));
+    }
+
+    card.png()
+}
+
+// A game played here by id, pasted PGN, or an attached PGN file.
+async fn find_frames(ctx: &Context, msg: &Message, arg: &str) -> Result<Frames, String> {
+    if let Ok(id) = arg.trim_start_matches('#').parse::<u32>() {
+        let game = eval::stored_game(&ctx.data, msg, id)
+            .await
+            .ok_or(format!("I don't know a game #{} here.", id))?;
+        return Ok(stored_frames(&game, msg.author.id.0));
+    }
+
+    let text = if arg.is_empty() {
+        let attachment = msg
+            .attachments
+            .first()
+            .ok_or("Use `.gif <game id or PGN>`, or attach a PGN file".to_string())?;
+        let bytes = attachment.download().await.map_err(|why| {
+            println!("Error downloading a PGN: {:?}", why);
+            "I couldn't read that file.".to_string()
+        })?;
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        arg.trim_matches('`').to_string()
+    };
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let game = pgn::parse_in(&text, notation)
+        .map_err(|why| format!("I can't read that game: {}.", why))?;
+    Ok(pgn_frames(&game))
+}
+
+#[command]
+#[description(
+    "Make an animated GIF of a whole game, one frame per move. Start with a delay like `0.5s` to change how long each move shows, and `flip` to see it from black's side."
+)]
+#[usage("[delay] [flip] <game id | PGN>")]
+#[example("12")]
+#[example("0.5s flip 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#")]
+async fn gif(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut arg = args.rest().trim();
+    let mut delay_ms = DEFAULT_DELAY_MS;
+    let mut flip = false;
+    while let Some(word) = arg.split_whitespace().next() {
+        let lower = word.to_lowercase();
+        if lower == "flip" {
+            flip = true;
+        } else if let Some(seconds) = lower
+            .strip_suffix('s')
+            .and_then(|seconds| seconds.parse::<f32>().ok())
+        {
+            let ms = (seconds * 1000.0).round() as u32;
+            if !(MIN_DELAY_MS..=MAX_DELAY_MS).contains(&ms) {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "The delay has to be between {}s and {}s.",
+                        MIN_DELAY_MS as f32 / 1000.0,
+                        MAX_DELAY_MS / 1000
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+            delay_ms = ms;
+        } else {
+            break;
+        }
+        arg = arg[word.len()..].trim_start();
+    }
+
+    let mut frames = match find_frames(ctx, msg, arg).await {
+        Ok(frames) => frames,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+    if frames.boards.len() > MAX_GIF_PLIES + 1 {
+        msg.reply(
+            &ctx.http,
+            format!(
+                "That game is too long, I make GIFs of games of up to {} moves.",
+                MAX_GIF_PLIES / 2
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    frames.flipped ^= flip;
+
+    let plies = frames.boards.len() - 1;
+    let title = frames.title.clone();
+    let delay = (delay_ms / 10) as u16;
+    let rendered = tokio::task::spawn_blocking(move || render_gif(&frames, delay)).await;
+    let gif = match rendered {
+        Ok(Some(gif)) => gif,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                "That game makes a GIF too big to upload here, try a shorter one.",
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error making a GIF: {:?}", why);
+            msg.reply(&ctx.http, "Something went wrong drawing that game.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.color(EMBED_SIDE_COLOR);
+                e.image(format!("attachment://{}", GIF_FILE));
+                e.footer(|f| {
+                    f.text(format!(
+                        "{} moves · {}s per move",
+                        plies.div_ceil(2),
+                        delay_ms as f32 / 1000.0
+                    ));
+                    f
+                });
+                e
+            });
+            m.add_file(AttachmentType::Bytes {
+                data: Cow::Owned(gif),
+                filename: GIF_FILE.to_string(),
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Color the squares each side's pieces stood on over a game, darker the longer they were there, or with `moves` the squares they moved to. Start with `flip` to see it from black's side."
+)]
+#[usage("[moves] [flip] <game id | PGN>")]
+#[example("12")]
+#[example("moves 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#")]
+async fn heatmap(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut arg = args.rest().trim();
+    let mut moves = false;
+    let mut flip = false;
+    while let Some(word) = arg.split_whitespace().next() {
+        match word.to_lowercase().as_str() {
+            "moves" | "moved" => moves = true,
+            "flip" => flip = true,
+            _ => break,
+        }
+        arg = arg[word.len()..].trim_start();
+    }
+
+    let mut frames = match find_frames(ctx, msg, arg).await {
+        Ok(frames) => frames,
+        Err(why) => {
+            msg.reply(&ctx.http, why.replace(".gif", ".heatmap"))
+                .await?;
+            return Ok(());
+        }
+    };
+    frames.flipped ^= flip;
+
+    let png = match render_heatmap(&frames, moves) {
+        Some(png) => png,
+        None => {
+            msg.reply(&ctx.http, "Something went wrong drawing that game.")
+                .await?;
+            return Ok(());
+        }
+    };
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.add_file(card::attachment(png, "heatmap.png"));
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busiest_squares_are_hottest() {
+        let game = pgn::parse("1. e4 e5 2. Nf3 Nc6 3. Ng1 Nb8 4. Nf3").unwrap();
+        let frames = pgn_frames(&game);
+
+        let stood = square_heat(&frames, false);
+        // White's rooks never moved.
+        assert_eq!(stood[0][usize::from(Square::A1)], 1.0);
+        assert_eq!(stood[0][usize::from(Square::E8)], 0.0);
+
+        let moved = square_heat(&frames, true);
+        assert_eq!(moved[0][usize::from(Square::F3)], 1.0);
+        assert_eq!(moved[0][usize::from(Square::G1)], 0.5f32.sqrt());
+        assert_eq!(moved[1][usize::from(Square::E5)], 1.0);
+        assert_eq!(moved[0][usize::from(Square::A1)], 0.0);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{env, error::Error, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use rusty_s3::{actions::ListObjectsV2, Bucket, Credentials, S3Action, UrlStyle};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    config::{self, Config},
+    game::{self, GameStore},
+    guesseval::{self, GuessStore},
+    history,
+    modules::BotModule,
+    puzzle::{self, PuzzleStore},
+    quotes::{self, QuoteStore},
+    scheduler::JobKind,
+    settings::{self, Settings},
+    web, EMBED_SIDE_COLOR,
+};
+
+// How long the signed S3 links stay valid.
+const SIGN_FOR: Duration = Duration::from_secs(10 * 60);
+
+// Backups shown by `.backup`, newest first.
+const LIST_LIMIT: usize = 15;
+
+// What the backups are called after the prefix, before their timestamp. Each kind is pruned to
+// `keep` on its own. Backups from before snapshots only have the settings.
+const SNAPSHOT: &str = "snapshot-";
+const ARCHIVE: &str = "games-";
+const SETTINGS_ONLY: &str = "settings-";
+
+type BackupResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Everything the bot keeps in its data dir, in one file. The puzzle database isn't in it, it can
+// be imported again.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    settings: Value,
+    // Stores missing from a snapshot are left alone by a restore.
+    #[serde(default)]
+    games: Option<Value>,
+    #[serde(default)]
+    puzzles: Option<Value>,
+    #[serde(default)]
+    quotes: Option<Value>,
+    #[serde(default)]
+    guesses: Option<Value>,
+}
+
+#[group]
+#[commands(backup)]
+struct Backups;
+
+pub struct BackupsModule;
+
+#[async_trait]
+impl BotModule for BackupsModule {
+    fn name(&self) -> &'static str {
+        "backups"
+    }
+
+    fn description(&self) -> &'static str {
+        "Off-site copies of the settings, games, puzzles and quotes, see `.backup`."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&BACKUPS_GROUP)
+    }
+
+    fn jobs(&self, config: &Config) -> Vec<(JobKind, String)> {
+        match config.backup.bucket {
+            Some(_) => vec![(
+                JobKind::Backup,
+                format!("0 {} * * *", config.backup.hour % 24),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::Backup {
+            if let Err(why) = upload(http, data).await {
+                println!("Could not upload a backup: {:?}", why);
+            }
+        }
+    }
+}
+
+// The bucket from the config and the keys to reach it.
+struct Storage {
+    bucket: Bucket,
+    credentials: Credentials,
+    prefix: String,
+    keep: usize,
+}
+
+async fn storage(data: &RwLock<TypeMap>) -> BackupResult<Storage> {
+    let config = config::get(data).await;
+    let config = &config.backup;
+    let name = config
+        .bucket
+        .clone()
+        .ok_or("backups aren't set up, see [backup] in config.toml")?;
+    let credentials = match (env::var("CUTE_BOT_S3_KEY"), env::var("CUTE_BOT_S3_SECRET")) {
+        (Ok(key), Ok(secret)) => Credentials::new(key, secret),
+        _ => return Err("CUTE_BOT_S3_KEY and CUTE_BOT_S3_SECRET need to be set".into()),
+    };
+    let style = if config.path_style {
+        UrlStyle::Path
+    } else {
+        UrlStyle::VirtualHost
+    };
+    let bucket = Bucket::new(config.endpoint.parse()?, style, name, config.region.clone())?;
+
+    Ok(Storage {
+        bucket,
+        credentials,
+        prefix: config.prefix.clone(),
+        keep: config.keep.max(1),
+    })
+}
+
+// Every backup's key, oldest first. The timestamps in the names sort by date.
+async fn list(client: &reqwest::Client, storage: &Storage) -> BackupResult<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let mut action = storage.bucket.list_objects_v2(Some(&storage.credentials));
+        action.with_prefix(storage.prefix.as_str());
+        if let Some(token) = &token {
+            action.with_continuation_token(token.as_str());
+        }
+        let url = action.sign(SIGN_FOR);
+
+        let text = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let response = ListObjectsV2::parse_response(&text)?;
+        keys.extend(response.contents.into_iter().map(|object| object.key));
+        match response.next_continuation_token {
+            Some(next) => token = Some(next),
+            None => break,
+        }
+    }
+
+    keys.sort();
+    Ok(keys)
+}
+
+// Delete the oldest backups of each kind past the number to keep.
+async fn prune(client: &reqwest::Client, storage: &Storage) -> BackupResult<usize> {
+    let keys = list(client, storage).await?;
+    let mut pruned = 0;
+    for kind in [SNAPSHOT, ARCHIVE, SETTINGS_ONLY] {
+        let start = format!("{}{}", storage.prefix, kind);
+        let of_kind: Vec<&String> = keys.iter().filter(|key| key.starts_with(&start)).collect();
+        let extra = of_kind.len().saturating_sub(storage.keep);
+        for key in &of_kind[..extra] {
+            let url = storage
+                .bucket
+                .delete_object(Some(&storage.credentials), key)
+                .sign(SIGN_FOR);
+            client.delete(url).send().await?.error_for_status()?;
+        }
+        pruned += extra;
+    }
+    Ok(pruned)
+}
+
+async fn put(
+    client: &reqwest::Client,
+    storage: &Storage,
+    key: &str,
+    content_type: &str,
+    body: String,
+) -> BackupResult<()> {
+    let url = storage
+        .bucket
+        .put_object(Some(&storage.credentials), key)
+        .sign(SIGN_FOR);
+    client
+        .put(url)
+        .header("Content-Type", content_type)
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn snapshot(data: &RwLock<TypeMap>) -> serde_json::Result<String> {
+    let snapshot = Snapshot {
+        settings: settings::read(data, |store| serde_json::to_value(store)).await?,
+        games: Some(game::read_games(data, |store| serde_json::to_value(store)).await?),
+        puzzles: Some(puzzle::read_puzzles(data, |store| serde_json::to_value(store)).await?),
+        quotes: Some(quotes::read_quotes(data, |store| serde_json::to_value(store)).await?),
+        guesses: Some(guesseval::read_guesses(data, |store| serde_json::to_value(store)).await?),
+    };
+    serde_json::to_string(&snapshot)
+}
+
+// Upload a snapshot of everything and a PGN archive of the finished games, and prune old ones.
+// Returns the snapshot's key.
+pub async fn upload(http: &Http, data: &RwLock<TypeMap>) -> BackupResult<String> {
+    let storage = storage(data).await?;
+    let client = web::client(data).await;
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+
+    let key = format!("{}{}{}.json", storage.prefix, SNAPSHOT, stamp);
+    put(
+        &client,
+        &storage,
+        &key,
+        "application/json",
+        snapshot(data).await?,
+    )
+    .await?;
+
+    let finished = game::read_games(data, |store| {
+        store
+            .games
+            .iter()
+            .filter(|game| game.is_over())
+            .cloned()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    if !finished.is_empty() {
+        let archive = history::archive_pgn(http, &finished).await;
+        let archive_key = format!("{}{}{}.pgn", storage.prefix, ARCHIVE, stamp);
+        put(
+            &client,
+            &storage,
+            &archive_key,
+            "application/x-chess-pgn",
+            archive,
+        )
+        .await?;
+    }
+
+    prune(&client, &storage).await?;
+    Ok(key)
+}
+
+// Replace what the bot keeps with a backup. Everything is uploaded first, so a restore can be
+// undone with another one.
+async fn restore(http: &Http, data: &RwLock<TypeMap>, name: &str) -> BackupResult<String> {
+    let storage = storage(data).await?;
+    let client = web::client(data).await;
+    let key = if name.starts_with(&storage.prefix) {
+        name.to_string()
+    } else {
+        format!("{}{}", storage.prefix, name)
+    };
+
+    let url = storage
+        .bucket
+        .get_object(Some(&storage.credentials), &key)
+        .sign(SIGN_FOR);
+    let text = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let snapshot = if key.contains(SETTINGS_ONLY) {
+        Snapshot {
+            settings: serde_json::from_str(&text)?,
+            games: None,
+            puzzles: None,
+            quotes: None,
+            guesses: None,
+        }
+    } else if key.contains(SNAPSHOT) {
+        serde_json::from_str(&text)?
+    } else {
+        return Err("only snapshots and settings backups can be restored".into());
+    };
+    // Read everything before changing anything, so a broken backup leaves it all alone.
+    let settings: Settings = serde_json::from_value(snapshot.settings)?;
+    let games: Option<GameStore> = snapshot.games.map(serde_json::from_value).transpose()?;
+    let puzzles: Option<PuzzleStore> = snapshot.puzzles.map(serde_json::from_value).transpose()?;
+    let quotes: Option<QuoteStore> = snapshot.quotes.map(serde_json::from_value).transpose()?;
+    let guesses: Option<GuessStore> = snapshot.guesses.map(serde_json::from_value).transpose()?;
+
+    let previous = upload(http, data).await?;
+    settings::update(data, |stored| stored.restore(settings)).await;
+    if let Some(games) = games {
+        game::with_games(data, |stored| stored.restore(games)).await;
+    }
+    if let Some(puzzles) = puzzles {
+        puzzle::with_puzzles(data, |stored| stored.restore(puzzles)).await;
+    }
+    if let Some(quotes) = quotes {
+        quotes::with_quotes(data, |stored| stored.restore(quotes)).await;
+    }
+    if let Some(guesses) = guesses {
+        guesseval::with_guesses(data, |stored| stored.restore(guesses)).await;
+    }
+    Ok(previous)
+}
+
+#[command]
+#[owners_only]
+#[sub_commands(now, restore_backup)]
+#[description(
+    "List the backups in off-site storage. Snapshots have the settings, games, puzzle stats and quotes, and each comes with a PGN archive of the finished games."
+)]
+async fn backup(ctx: &Context, msg: &Message) -> CommandResult {
+    let storage = match storage(&ctx.data).await {
+        Ok(storage) => storage,
+        Err(why) => {
+            msg.reply(&ctx.http, format!("Can't reach the backups, {}.", why))
+                .await?;
+            return Ok(());
+        }
+    };
+    let client = web::client(&ctx.data).await;
+    let desc = match list(&client, &storage).await {
+        Ok(keys) if keys.is_empty() => "There are no backups yet.".to_string(),
+        Ok(keys) => keys
+            .iter()
+            .rev()
+            .take(LIST_LIMIT)
+            .map(|key| format!("`{}`", key.trim_start_matches(storage.prefix.as_str())))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(why) => format!("Couldn't list the backups: {}", why),
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Backups");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| {
+                    f.text(".backup now · .backup restore <name>");
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description("Upload a backup right away.")]
+async fn now(ctx: &Context, msg: &Message) -> CommandResult {
+    let reply = match upload(&ctx.http, &ctx.data).await {
+        Ok(key) => format!("Backed up to `{}`.", key),
+        Err(why) => format!("Couldn't upload the backup: {}", why),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("restore")]
+#[owners_only]
+#[description(
+    "Replace the settings, games, puzzle stats and quotes with a snapshot. Everything as it is now is backed up first."
+)]
+#[usage("<name>")]
+#[example("snapshot-20240101-040000.json")]
+async fn restore_backup(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = match args.current() {
+        Some(name) => name.trim_matches('`'),
+        None => {
+            msg.reply(&ctx.http, "Use `.backup restore <name>`, see `.backup`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let reply = match restore(&ctx.http, &ctx.data, name).await {
+        Ok(previous) => format!(
+            "Restored `{}`. Everything from before is in `{}`.",
+            name, previous
+        ),
+        Err(why) => format!("Couldn't restore that backup: {}", why),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
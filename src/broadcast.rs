@@ -0,0 +1,329 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    settings, EMBED_SIDE_COLOR,
+};
+
+const SEND_ID: &str = "broadcast:send";
+const CANCEL_ID: &str = "broadcast:cancel";
+
+// Pause between announcements so a big broadcast doesn't run into rate limits.
+const SEND_DELAY: Duration = Duration::from_millis(1500);
+
+#[group]
+#[commands(broadcastmsg, announcechannel)]
+struct Broadcast;
+
+pub struct BroadcastModule;
+
+const BROADCAST_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Announcement channel",
+    command: "announcechannel",
+    value: |guild| modules::show_channel(guild.announcement_channel),
+}];
+
+#[async_trait]
+impl BotModule for BroadcastModule {
+    fn name(&self) -> &'static str {
+        "broadcast"
+    }
+
+    fn description(&self) -> &'static str {
+        "Announcements from the bot's owners."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&BROADCAST_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<BroadcastContainer>(Mutex::default());
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        BROADCAST_SETTINGS
+    }
+}
+
+// An announcement waiting for its author to confirm the preview.
+pub struct PendingBroadcast {
+    author: UserId,
+    text: String,
+}
+
+pub struct BroadcastContainer;
+
+impl TypeMapKey for BroadcastContainer {
+    type Value = Mutex<HashMap<MessageId, PendingBroadcast>>;
+}
+
+fn announcement_embed<'a>(e: &'a mut CreateEmbed, text: &str) -> &'a mut CreateEmbed {
+    e.title("Announcement");
+    e.color(EMBED_SIDE_COLOR);
+    e.description(text);
+    e
+}
+
+async fn announcement_channels(data: &RwLock<TypeMap>) -> Vec<ChannelId> {
+    settings::read(data, |settings| {
+        settings
+            .guilds
+            .values()
+            .filter_map(|guild| guild.announcement_channel)
+            .map(ChannelId)
+            .collect()
+    })
+    .await
+}
+
+// Send the announcement everywhere, one channel at a time, and report back on the preview.
+async fn send_all(http: Arc<Http>, channels: Vec<ChannelId>, text: String, preview: Message) {
+    let mut failed = 0;
+    for channel in &channels {
+        let result = channel
+            .send_message(&http, |m| {
+                m.embed(|e| announcement_embed(e, &text));
+                m
+            })
+            .await;
+        if let Err(why) = result {
+            println!("Could not send announcement to {}: {:?}", channel, why);
+            failed += 1;
+        }
+        tokio::time::sleep(SEND_DELAY).await;
+    }
+
+    let mut preview = preview;
+    let result = preview
+        .edit(&http, |m| {
+            m.content(format!(
+                "Sent to {} of {} channels.",
+                channels.len() - failed,
+                channels.len()
+            ));
+            m
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Could not update the broadcast preview: {:?}", why);
+    }
+}
+
+// Send or drop a broadcast when its author presses a button. Returns false if the button
+// isn't ours.
+pub async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if id != SEND_ID && id != CANCEL_ID {
+        return false;
+    }
+
+    let pending = {
+        let data = ctx.data.read().await;
+        let mut pending = data
+            .get::<BroadcastContainer>()
+            .expect("Expected broadcasts in typemap.")
+            .lock()
+            .await;
+        let author = pending.get(&component.message.id).map(|b| b.author);
+        match author {
+            Some(author) if author != component.user.id => None,
+            _ => Some(pending.remove(&component.message.id)),
+        }
+    };
+    let pending = match pending {
+        Some(pending) => pending,
+        None => {
+            let result = component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource);
+                    r.interaction_response_data(|d| {
+                        d.content("Only whoever wrote the announcement can send it.");
+                        d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    })
+                })
+                .await;
+            if let Err(why) = result {
+                println!("Error replying to a broadcast button: {:?}", why);
+            }
+            return true;
+        }
+    };
+
+    let channels = announcement_channels(&ctx.data).await;
+    let content = match (&pending, id) {
+        (None, _) => "This broadcast has expired.".to_string(),
+        (Some(_), CANCEL_ID) => "Broadcast cancelled.".to_string(),
+        (Some(_), _) => format!("Sending to {} channels...", channels.len()),
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.content(content);
+                d.components(|c| c)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating broadcast preview: {:?}", why);
+    }
+
+    if let (Some(broadcast), SEND_ID) = (pending, id) {
+        tokio::spawn(send_all(
+            ctx.http.clone(),
+            channels,
+            broadcast.text,
+            component.message.clone(),
+        ));
+    }
+
+    true
+}
+
+#[command]
+#[owners_only]
+#[description("Send an announcement to every server's announcement channel. You'll see a preview to confirm first.")]
+#[usage("<message>")]
+async fn broadcastmsg(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim().to_string();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.broadcastmsg <message>`")
+            .await?;
+        return Ok(());
+    }
+
+    let channels = announcement_channels(&ctx.data).await;
+    if channels.is_empty() {
+        msg.reply(
+            &ctx.http,
+            "No server has an announcement channel yet, see `.announcechannel`.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let preview = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!(
+                "This will go to {} channels, send it?",
+                channels.len()
+            ));
+            m.embed(|e| announcement_embed(e, &text));
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Primary);
+                        b.label("Send");
+                        b.custom_id(SEND_ID);
+                        b
+                    });
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Secondary);
+                        b.label("Cancel");
+                        b.custom_id(CANCEL_ID);
+                        b
+                    })
+                })
+            });
+            m
+        })
+        .await?;
+
+    let data = ctx.data.read().await;
+    data.get::<BroadcastContainer>()
+        .expect("Expected broadcasts in typemap.")
+        .lock()
+        .await
+        .insert(
+            preview.id,
+            PendingBroadcast {
+                author: msg.author.id,
+                text,
+            },
+        );
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Pick the channel where announcements from the bot's owners are posted.")]
+#[usage("<#channel|off>")]
+async fn announcechannel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let channel = match args.current() {
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => None,
+        Some(arg) => match utils::parse_channel(arg) {
+            Some(channel) => Some(channel),
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.announcechannel #channel` or `.announcechannel off`",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let reply = match settings::guild(&ctx.data, guild_id)
+                .await
+                .announcement_channel
+            {
+                Some(channel) => format!("Announcements go to <#{}>.", channel),
+                None => "This server doesn't get announcements.".to_string(),
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .announcement_channel = channel;
+    })
+    .await;
+
+    let reply = match channel {
+        Some(channel) => format!("Announcements will go to <#{}>.", channel),
+        None => "This server won't get announcements anymore.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,367 @@
+use std::borrow::Cow;
+
+use resvg::{tiny_skia, usvg};
+use serenity::{http::AttachmentType, model::user::User, prelude::*};
+
+use crate::web;
+
+// Text is drawn with a little bitmap font, since the SVG renderer is built without fonts. Each
+// glyph is 7 rows of 5 dots, with a dot's gap after it.
+const GLYPH_ROWS: usize = 7;
+const GLYPH_DOTS: u32 = 5;
+const ADVANCE: u32 = GLYPH_DOTS + 1;
+
+// The printable ASCII characters from ' ' to '~', each row's dots in the low five bits with
+// the leftmost dot highest. Anything else is drawn as '?'.
+const GLYPHS: [[u8; GLYPH_ROWS]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+    [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+    [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], // 'b'
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], // 'c'
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], // 'd'
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], // 'e'
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], // 'f'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // 'h'
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // 'k'
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'l'
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // 'n'
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], // 'o'
+    [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // 'r'
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], // 's'
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], // 'w'
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'y'
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // '~'
+];
+
+// Avatars are asked for at this size and scaled to fit.
+const AVATAR_SIZE: u32 = 128;
+
+pub const BACKGROUND: &str = "#2b2d31";
+pub const PANEL: &str = "#383a40";
+pub const TEXT: &str = "#f2f3f5";
+pub const MUTED: &str = "#b5bac1";
+// EMBED_SIDE_COLOR, for the cards to match the embeds.
+pub const ACCENT: &str = "#ffc0cb";
+pub const GREEN: &str = "#57f287";
+pub const RED: &str = "#ed4245";
+
+fn glyph(c: char) -> &'static [u8; GLYPH_ROWS] {
+    match c {
+        ' '..='~' => &GLYPHS[c as usize - ' ' as usize],
+        _ => &GLYPHS['?' as usize - ' ' as usize],
+    }
+}
+
+// How wide `text` comes out with dots `dot` pixels across.
+pub fn text_width(text: &str, dot: f32) -> f32 {
+    match text.chars().count() as u32 {
+        0 => 0.0,
+        n => (n * ADVANCE - 1) as f32 * dot,
+    }
+}
+
+// `text`, cut short with ".." if it's wider than `width`.
+pub fn fit(text: &str, dot: f32, width: f32) -> String {
+    if text_width(text, dot) <= width {
+        return text.to_string();
+    }
+    let mut fitted: String = text.chars().collect();
+    while !fitted.is_empty() && text_width(&format!("{}..", fitted), dot) > width {
+        fitted.pop();
+    }
+    format!("{}..", fitted.trim_end())
+}
+
+// A picture put together from shapes, text and avatars, and sent as a PNG.
+pub struct Card {
+    width: u32,
+    height: u32,
+    svg: String,
+    // PNGs to draw in circles on top, with their center and radius.
+    avatars: Vec<(Vec<u8>, f32, f32, f32)>,
+}
+
+impl Card {
+    pub fn new(width: u32, height: u32) -> Card {
+        let mut card = Card {
+            width,
+            height,
+            svg: format!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+                width, height, width, height
+            ),
+            avatars: Vec::new(),
+        };
+        card.rect(0.0, 0.0, width as f32, height as f32, 0.0, BACKGROUND);
+        card
+    }
+
+    // Any other SVG shapes, in the card's pixels.
+    pub fn push(&mut self, svg: &str) {
+        self.svg.push_str(svg);
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, radius: f32, fill: &str) {
+        self.push(&format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" fill="{}"/>"#,
+            x, y, width, height, radius, fill
+        ));
+    }
+
+    // `text` with its top left corner at `x`, `y`. It's `dot` times 7 pixels tall.
+    pub fn text(&mut self, x: f32, y: f32, dot: f32, fill: &str, text: &str) {
+        let mut path = String::new();
+        for (i, c) in text.chars().enumerate() {
+            let left = x + (i as u32 * ADVANCE) as f32 * dot;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                let top = y + row as f32 * dot;
+                // Runs of dots in a row become one rectangle.
+                let mut col = 0;
+                while col < GLYPH_DOTS {
+                    if bits & (1 << (GLYPH_DOTS - 1 - col)) == 0 {
+                        col += 1;
+                        continue;
+                    }
+                    let start = col;
+                    while col < GLYPH_DOTS && bits & (1 << (GLYPH_DOTS - 1 - col)) != 0 {
+                        col += 1;
+                    }
+                    path.push_str(&format!(
+                        "M{} {}h{}v{}h-{}z",
+                        left + start as f32 * dot,
+                        top,
+                        (col - start) as f32 * dot,
+                        dot,
+                        (col - start) as f32 * dot
+                    ));
+                }
+            }
+        }
+        if !path.is_empty() {
+            self.push(&format!(r#"<path d="{}" fill="{}"/>"#, path, fill));
+        }
+    }
+
+    // Text ending at `right` instead of starting somewhere.
+    pub fn text_right(&mut self, right: f32, y: f32, dot: f32, fill: &str, text: &str) {
+        self.text(right - text_width(text, dot), y, dot, fill, text);
+    }
+
+    // A ring split between the parts in proportion to their values, clockwise from the top.
+    // An empty ring if they're all zero.
+    pub fn donut(&mut self, cx: f32, cy: f32, radius: f32, width: f32, parts: &[(f32, &str)]) {
+        self.push(&format!(
+            r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+            cx, cy, radius, PANEL, width
+        ));
+        let total: f32 = parts.iter().map(|(value, _)| value).sum();
+        if total <= 0.0 {
+            return;
+        }
+        let circumference = 2.0 * std::f32::consts::PI * radius;
+        let mut done = 0.0;
+        for (value, color) in parts.iter().filter(|(value, _)| *value > 0.0) {
+            let length = value / total * circumference;
+            self.push(&format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="{}" stroke-width="{}" stroke-dasharray="{} {}" stroke-dashoffset="{}" transform="rotate(-90 {} {})"/>"#,
+                cx, cy, radius, color, width, length, circumference, -done, cx, cy
+            ));
+            done += length;
+        }
+    }
+
+    // Someone's avatar in a circle, or a plain circle if it couldn't be downloaded.
+    pub fn avatar(&mut self, png: Option<Vec<u8>>, cx: f32, cy: f32, radius: f32) {
+        self.push(&format!(
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            cx, cy, radius, PANEL
+        ));
+        if let Some(png) = png {
+            self.avatars.push((png, cx, cy, radius));
+        }
+    }
+
+    pub fn png(mut self) -> Option<Vec<u8>> {
+        self.svg.push_str("</svg>");
+        let tree = match usvg::Tree::from_str(&self.svg, &usvg::Options::default()) {
+            Ok(tree) => tree,
+            Err(why) => {
+                println!("Could not draw a card: {:?}", why);
+                return None;
+            }
+        };
+        let mut pixmap = tiny_skia::Pixmap::new(self.width, self.height)?;
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+
+        for (png, cx, cy, radius) in &self.avatars {
+            let image = match tiny_skia::Pixmap::decode_png(png) {
+                Ok(image) => image,
+                Err(why) => {
+                    println!("Could not read an avatar: {:?}", why);
+                    continue;
+                }
+            };
+            let scale = radius * 2.0 / image.width().max(1) as f32;
+            let paint = tiny_skia::Paint {
+                shader: tiny_skia::Pattern::new(
+                    image.as_ref(),
+                    tiny_skia::SpreadMode::Pad,
+                    tiny_skia::FilterQuality::Bicubic,
+                    1.0,
+                    tiny_skia::Transform::from_scale(scale, scale)
+                        .post_translate(cx - radius, cy - radius),
+                ),
+                anti_alias: true,
+                ..tiny_skia::Paint::default()
+            };
+            if let Some(circle) = tiny_skia::PathBuilder::from_circle(*cx, *cy, *radius) {
+                pixmap.fill_path(
+                    &circle,
+                    &paint,
+                    tiny_skia::FillRule::Winding,
+                    tiny_skia::Transform::identity(),
+                    None,
+                );
+            }
+        }
+
+        pixmap.encode_png().ok()
+    }
+}
+
+// Someone's avatar as a PNG, or None if it couldn't be downloaded.
+pub async fn avatar(data: &RwLock<TypeMap>, user: &User) -> Option<Vec<u8>> {
+    // Discord sends animated and WebP avatars as PNG when asked.
+    let url = match &user.avatar {
+        Some(hash) => format!(
+            "https://cdn.discordapp.com/avatars/{}/{}.png?size={}",
+            user.id, hash, AVATAR_SIZE
+        ),
+        None => user.default_avatar_url(),
+    };
+    let response = web::client(data)
+        .await
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let bytes = match response {
+        Ok(response) => response.bytes().await,
+        Err(why) => Err(why),
+    };
+    match bytes {
+        Ok(bytes) => Some(bytes.to_vec()),
+        Err(why) => {
+            println!("Could not download the avatar of {}: {:?}", user.id, why);
+            None
+        }
+    }
+}
+
+pub fn attachment(png: Vec<u8>, filename: &str) -> AttachmentType<'static> {
+    AttachmentType::Bytes {
+        data: Cow::Owned(png),
+        filename: filename.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitting_text() {
+        assert_eq!(text_width("", 2.0), 0.0);
+        assert_eq!(text_width("ab", 2.0), 22.0);
+        assert_eq!(fit("magnus", 1.0, 100.0), "magnus");
+        let fitted = fit("a very long name indeed", 1.0, 60.0);
+        assert!(fitted.ends_with("..") && text_width(&fitted, 1.0) <= 60.0);
+    }
+
+    #[test]
+    fn draws_a_card() {
+        let mut card = Card::new(40, 20);
+        card.text(2.0, 2.0, 1.0, TEXT, "Hi ♞");
+        card.avatar(None, 30.0, 10.0, 8.0);
+        card.donut(30.0, 10.0, 6.0, 2.0, &[(2.0, GREEN), (1.0, RED)]);
+        assert!(card.png().is_some());
+    }
+}
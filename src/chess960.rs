@@ -0,0 +1,172 @@
+use rand::prelude::*;
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        interactions::message_component::{ButtonStyle, MessageComponentInteraction},
+    },
+    prelude::*,
+};
+use shakmaty::{
+    fen::Fen, Board, CastlingMode, Chess, Color, EnPassantMode, Position, Rank, Setup, Square,
+};
+
+use crate::{
+    diagram::{self, DiagramOptions},
+    game,
+    modules::{self, BotModule},
+    render, settings, EMBED_SIDE_COLOR,
+};
+
+// Number of Fischer Random start positions. 518 is the standard setup.
+pub const POSITION_COUNT: u32 = 960;
+
+// Button under `.960` that offers a game from the position, followed by its number.
+const PLAY_PREFIX: &str = "chess960:play:";
+
+#[group]
+#[commands(chess960)]
+struct Chess960;
+
+pub struct Chess960Module;
+
+#[async_trait]
+impl BotModule for Chess960Module {
+    fn name(&self) -> &'static str {
+        "chess960"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fischer Random starting positions."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&CHESS960_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        let number = match component.data.custom_id.strip_prefix(PLAY_PREFIX) {
+            Some(number) => number.parse::<u32>().ok().filter(|n| *n < POSITION_COUNT),
+            None => return false,
+        };
+        if let Some(number) = number {
+            if let Err(why) = game::offer_open_challenge(ctx, component, number).await {
+                println!("Error offering a Chess960 game: {:?}", why);
+            }
+        }
+        true
+    }
+}
+
+pub fn random_number() -> u32 {
+    thread_rng().gen_range(0..POSITION_COUNT)
+}
+
+// Start position for a Scharnagl number, with castling rights on both rooks.
+pub fn start_position(number: u32) -> Chess {
+    let board = Board::chess960(number);
+    let setup = Setup {
+        castling_rights: board.rooks(),
+        board,
+        ..Setup::initial()
+    };
+
+    setup
+        .position(CastlingMode::Chess960)
+        .expect("every Chess960 start position is legal")
+}
+
+// White's back rank as piece letters, e.g. "RNBQKBNR" for position 518.
+pub fn back_rank(pos: &Chess) -> String {
+    Square::ALL
+        .iter()
+        .filter(|sq| sq.rank() == Rank::First)
+        .filter_map(|&sq| pos.board().piece_at(sq))
+        .filter(|piece| piece.color == Color::White)
+        .map(|piece| piece.role.upper_char())
+        .collect()
+}
+
+#[command("960")]
+#[aliases("chess960", "fischerandom")]
+#[description(
+    "Show a random Fischer Random start position, or the one with the given number (0-959)."
+)]
+#[usage("[number]")]
+async fn chess960(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let number = if args.is_empty() {
+        random_number()
+    } else {
+        match args.single::<u32>() {
+            Ok(n) if n < POSITION_COUNT => n,
+            _ => {
+                msg.reply(&ctx.http, "Chess960 positions are numbered 0 to 959!")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let pos = start_position(number);
+    let fen = Fen::from_position(&pos, EnPassantMode::Legal);
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let mut desc = match image {
+        Some(_) => String::new(),
+        None => format!("{}\n", render::board_for(pos.board(), &style)),
+    };
+    desc.push_str(&format!("**{}**\n`{}`", back_rank(&pos), fen));
+    let has_image = image.is_some();
+    // Games start in a thread off the challenge, so only in servers that play them.
+    let playable = match msg.guild_id {
+        Some(guild_id) => modules::enabled(
+            &settings::guild(&ctx.data, guild_id).await,
+            &game::GamesModule,
+        ),
+        None => false,
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(format!("Chess960 position #{}", number));
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            if playable {
+                m.components(|c| {
+                    c.create_action_row(|row| {
+                        row.create_button(|b| {
+                            b.style(ButtonStyle::Primary);
+                            b.label("Play this position");
+                            b.custom_id(format!("{}{}", PLAY_PREFIX, number));
+                            b
+                        })
+                    })
+                });
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
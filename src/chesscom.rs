@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct CallbackResponse {
+    game: CallbackGame,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackGame {
+    #[serde(rename = "pgnHeaders", default)]
+    pgn_headers: HashMap<String, serde_json::Value>,
+    // The final position, or the current one for games still going.
+    fen: Option<String>,
+}
+
+// The parts of a chess.com game the previews use, taken from its PGN headers.
+#[derive(Debug)]
+pub struct Game {
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub fen: Option<String>,
+}
+
+impl Game {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    // "Hikaru (3200)".
+    pub fn player(&self, color: &str) -> String {
+        let name = self.header(color).unwrap_or("?");
+        match self.header(&format!("{}Elo", color)) {
+            Some(elo) => format!("{} ({})", name, elo),
+            None => name.to_string(),
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        self.header("Result").unwrap_or("*")
+    }
+
+    // chess.com only gives the ECO code and a link to the opening page, so the name is taken
+    // from the link.
+    pub fn opening(&self) -> Option<String> {
+        let eco = self.header("ECO")?;
+        let name = self
+            .header("ECOUrl")
+            .and_then(|url| url.rsplit('/').next())
+            .map(|slug| slug.replace('-', " "));
+
+        Some(match name {
+            Some(name) => format!("{} {}", eco, name),
+            None => eco.to_string(),
+        })
+    }
+}
+
+// A link to a live or daily game, e.g. https://www.chess.com/game/live/123456789.
+// Returns the kind ("live" or "daily") and the id.
+pub fn game_from_url(url: &str) -> Option<(String, String)> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .strip_prefix("chess.com/")?;
+
+    let parts: Vec<&str> = rest.split(['/', '?', '#']).collect();
+    let (kind, id) = match parts.as_slice() {
+        ["game", kind, id, ..] => (*kind, *id),
+        [kind, "game", id, ..] => (*kind, *id),
+        _ => return None,
+    };
+
+    let valid = (kind == "live" || kind == "daily")
+        && !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_digit());
+    if valid {
+        Some((kind.to_string(), id.to_string()))
+    } else {
+        None
+    }
+}
+
+pub async fn fetch_game(client: &reqwest::Client, kind: &str, id: &str) -> reqwest::Result<Game> {
+    let response: CallbackResponse = client
+        .get(format!(
+            "https://www.chess.com/callback/{}/game/{}",
+            kind, id
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let headers = response
+        .game
+        .pgn_headers
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(text) => text,
+                other => other.to_string(),
+            };
+            (name, value)
+        })
+        .collect();
+
+    Ok(Game {
+        url: format!("https://www.chess.com/game/{}/{}", kind, id),
+        headers,
+        fen: response.game.fen,
+    })
+}
+
+const API_URL: &str = "https://api.chess.com/pub";
+
+// A chess.com account's public profile.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Player {
+    // Always lowercase here, whatever the account's capitals.
+    pub username: String,
+    pub title: Option<String>,
+    // The profile page.
+    pub url: String,
+    // Free text the player sets, used to prove an account is theirs.
+    pub location: Option<String>,
+}
+
+// Ratings by kind of game. Kinds someone never played are missing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Stats {
+    pub chess_bullet: Option<GameStats>,
+    pub chess_blitz: Option<GameStats>,
+    pub chess_rapid: Option<GameStats>,
+    pub chess_daily: Option<GameStats>,
+    pub tactics: Option<TacticsStats>,
+}
+
+impl Stats {
+    // The ratings for "bullet", "blitz", "rapid" or "daily" games.
+    pub fn speed(&self, speed: &str) -> Option<&GameStats> {
+        match speed {
+            "bullet" => self.chess_bullet.as_ref(),
+            "blitz" => self.chess_blitz.as_ref(),
+            "rapid" => self.chess_rapid.as_ref(),
+            "daily" => self.chess_daily.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GameStats {
+    pub last: Rating,
+    pub best: Option<Rating>,
+    pub record: Record,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rating {
+    pub rating: u32,
+    // Seconds since the epoch.
+    pub date: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Record {
+    pub win: u32,
+    pub loss: u32,
+    pub draw: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TacticsStats {
+    pub highest: Option<Rating>,
+}
+
+// A finished game from a player's monthly archive.
+#[derive(Debug, Deserialize)]
+pub struct ArchivedGame {
+    pub url: String,
+    // Seconds since the epoch.
+    pub end_time: i64,
+    #[serde(default)]
+    pub rated: bool,
+    // "bullet", "blitz", "rapid" or "daily".
+    pub time_class: String,
+    pub white: ArchivedPlayer,
+    pub black: ArchivedPlayer,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedPlayer {
+    pub username: String,
+    pub rating: u32,
+    // "win", or how the game was lost or drawn, like "checkmated", "timeout" or "agreed".
+    pub result: String,
+}
+
+impl ArchivedGame {
+    // Which side a player had, if they played at all.
+    pub fn side_of(&self, username: &str) -> Option<(&ArchivedPlayer, &ArchivedPlayer)> {
+        if self.white.username.eq_ignore_ascii_case(username) {
+            Some((&self.white, &self.black))
+        } else if self.black.username.eq_ignore_ascii_case(username) {
+            Some((&self.black, &self.white))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Archives {
+    archives: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveGames {
+    games: Vec<ArchivedGame>,
+}
+
+// chess.com usernames are letters, digits, `_` and `-`, from 3 to 25 of them.
+pub fn is_username(name: &str) -> bool {
+    (3..=25).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// A player's profile, or None if there's no such account.
+pub async fn player(client: &reqwest::Client, username: &str) -> reqwest::Result<Option<Player>> {
+    let response = client
+        .get(format!("{}/player/{}", API_URL, username.to_lowercase()))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+pub async fn stats(client: &reqwest::Client, username: &str) -> reqwest::Result<Stats> {
+    client
+        .get(format!(
+            "{}/player/{}/stats",
+            API_URL,
+            username.to_lowercase()
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// A player's last `count` finished games, newest first. Looks back over the last two months.
+pub async fn recent_games(
+    client: &reqwest::Client,
+    username: &str,
+    count: usize,
+) -> reqwest::Result<Vec<ArchivedGame>> {
+    let archives: Archives = client
+        .get(format!(
+            "{}/player/{}/games/archives",
+            API_URL,
+            username.to_lowercase()
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut games = Vec::new();
+    for url in archives.archives.iter().rev().take(2) {
+        let month: ArchiveGames = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        games.extend(month.games.into_iter().rev());
+        if games.len() >= count {
+            break;
+        }
+    }
+    games.truncate(count);
+    Ok(games)
+}
+
+// A chess.com daily puzzle, the day's or a random earlier one.
+#[derive(Debug, Deserialize)]
+pub struct DailyPuzzle {
+    // Seconds since the epoch, the day it was the daily puzzle.
+    pub publish_time: i64,
+    // The solution, with the position to solve in its FEN header.
+    pub pgn: String,
+}
+
+pub async fn daily_puzzle(client: &reqwest::Client) -> reqwest::Result<DailyPuzzle> {
+    client
+        .get(format!("{}/puzzle", API_URL))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+pub async fn random_puzzle(client: &reqwest::Client) -> reqwest::Result<DailyPuzzle> {
+    client
+        .get(format!("{}/puzzle/random", API_URL))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Club {
+    pub name: String,
+    // The club's page.
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClubMembers {
+    #[serde(default)]
+    weekly: Vec<ClubMember>,
+    #[serde(default)]
+    monthly: Vec<ClubMember>,
+    #[serde(default)]
+    all_time: Vec<ClubMember>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClubMember {
+    username: String,
+}
+
+// A club by its id, the last part of chess.com/club/..., or None if there's no such club.
+pub async fn club(client: &reqwest::Client, id: &str) -> reqwest::Result<Option<Club>> {
+    let response = client
+        .get(format!("{}/club/{}", API_URL, id))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+// Everyone in a club, grouped by how active they've been, the most active first.
+pub async fn club_members(client: &reqwest::Client, id: &str) -> reqwest::Result<Vec<String>> {
+    let members: ClubMembers = client
+        .get(format!("{}/club/{}/members", API_URL, id))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(members
+        .weekly
+        .into_iter()
+        .chain(members.monthly)
+        .chain(members.all_time)
+        .map(|member| member.username)
+        .collect())
+}
@@ -0,0 +1,497 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId},
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    chesscom,
+    config::Config,
+    lichess,
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings, web, EMBED_SIDE_COLOR,
+};
+
+// Players listed on the board, the rest are summed up.
+const MAX_LISTED: usize = 30;
+
+// chess.com sends ratings one player at a time, so only a club's most active members are looked
+// up.
+const MAX_CHESSCOM_MEMBERS: usize = 100;
+
+// The speeds boards can rank by, both sites have all of them.
+const SPEEDS: [&str; 3] = ["bullet", "blitz", "rapid"];
+const DEFAULT_SPEED: &str = "blitz";
+
+#[group]
+#[commands(clubboard, chesscomclub)]
+struct ClubBoards;
+
+pub struct ClubBoardModule;
+
+const CLUB_BOARD_SETTINGS: &[ModuleSetting] = &[
+    ModuleSetting {
+        name: "chess.com club",
+        command: "chesscomclub",
+        value: |guild| {
+            guild
+                .chesscom_club
+                .clone()
+                .unwrap_or_else(|| "not set".to_string())
+        },
+    },
+    ModuleSetting {
+        name: "Leaderboard channel",
+        command: "clubboard",
+        value: |guild| {
+            modules::show_channel(guild.club_board.as_ref().map(|board| board.channel_id))
+        },
+    },
+];
+
+#[async_trait]
+impl BotModule for ClubBoardModule {
+    fn name(&self) -> &'static str {
+        "clubboard"
+    }
+
+    fn description(&self) -> &'static str {
+        "A leaderboard of the server's Lichess team and chess.com club, kept up to date, see `.clubboard`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&CLUBBOARDS_GROUP)
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::ClubBoardSync, "0 * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::ClubBoardSync {
+            sync_all(http, data).await;
+        }
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        CLUB_BOARD_SETTINGS
+    }
+}
+
+// A server's club leaderboard and the message it's kept in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClubBoard {
+    pub channel_id: u64,
+    pub message_id: Option<u64>,
+    // "bullet", "blitz" or "rapid".
+    pub speed: String,
+}
+
+// Where a board's players come from: the server's Lichess team and chess.com club.
+struct Clubs {
+    team: Option<String>,
+    club: Option<String>,
+}
+
+// A member of the team or club and their rating.
+struct Entry {
+    username: String,
+    url: String,
+    site: &'static str,
+    rating: u32,
+}
+
+fn speed_title(speed: &str) -> String {
+    let mut chars = speed.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Provisional ratings are left off, a few lucky games shouldn't top the board.
+async fn lichess_entries(
+    client: &reqwest::Client,
+    team: &str,
+    speed: &str,
+) -> reqwest::Result<Vec<Entry>> {
+    Ok(lichess::team_members(client, team)
+        .await?
+        .into_iter()
+        .filter_map(|account| {
+            let rating = account
+                .perfs
+                .get(speed)
+                .filter(|perf| perf.games > 0 && !perf.prov)?
+                .rating;
+            Some(Entry {
+                url: account.url(),
+                username: account.username,
+                site: "Lichess",
+                rating,
+            })
+        })
+        .collect())
+}
+
+async fn chesscom_entries(
+    client: &reqwest::Client,
+    club: &str,
+    speed: &str,
+) -> reqwest::Result<Vec<Entry>> {
+    let members = chesscom::club_members(client, club).await?;
+    let mut entries = Vec::new();
+    for username in members.into_iter().take(MAX_CHESSCOM_MEMBERS) {
+        // One member's stats not coming through shouldn't keep the rest off the board.
+        let stats = match chesscom::stats(client, &username).await {
+            Ok(stats) => stats,
+            Err(why) => {
+                println!("Error getting chess.com stats of {}: {:?}", username, why);
+                continue;
+            }
+        };
+        if let Some(stats) = stats.speed(speed) {
+            entries.push(Entry {
+                url: format!("https://www.chess.com/member/{}", username),
+                username,
+                site: "chess.com",
+                rating: stats.last.rating,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn board_embed<'a>(
+    e: &'a mut CreateEmbed,
+    entries: &[Entry],
+    speed: &str,
+    sources: &[String],
+) -> &'a mut CreateEmbed {
+    let mut lines: Vec<String> = entries
+        .iter()
+        .take(MAX_LISTED)
+        .enumerate()
+        .map(|(index, entry)| {
+            format!(
+                "`{:>2}.` **{}** [{}]({}) · {}",
+                index + 1,
+                entry.rating,
+                entry.username,
+                entry.url,
+                entry.site
+            )
+        })
+        .collect();
+    if entries.len() > MAX_LISTED {
+        lines.push(format!("…and {} more", entries.len() - MAX_LISTED));
+    }
+    if lines.is_empty() {
+        lines.push(format!("Nobody in the club has a {} rating yet.", speed));
+    }
+
+    e.title(format!("🏆 Club leaderboard · {}", speed_title(speed)));
+    e.color(EMBED_SIDE_COLOR);
+    e.description(lines.join("\n"));
+    e.footer(|f| f.text(format!("{} · updated every hour", sources.join(" · "))));
+    e.timestamp(Utc::now());
+    e
+}
+
+// Rank every server's club members again. The scheduler runs this every hour.
+pub async fn sync_all(http: &Http, data: &RwLock<TypeMap>) {
+    let boards: Vec<(u64, ClubBoard, Clubs)> = settings::read(data, |settings| {
+        settings
+            .guilds
+            .iter()
+            .filter(|(_, guild)| modules::enabled(guild, &ClubBoardModule))
+            .filter_map(|(id, guild)| {
+                let clubs = Clubs {
+                    team: guild.lichess_team.as_ref().map(|team| team.team.clone()),
+                    club: guild.chesscom_club.clone(),
+                };
+                Some((*id, guild.club_board.clone()?, clubs))
+            })
+            .collect()
+    })
+    .await;
+    if boards.is_empty() {
+        return;
+    }
+
+    let client = web::client(data).await;
+    for (guild_id, board, clubs) in boards {
+        if let Err(why) = sync(http, data, &client, guild_id, &board, &clubs).await {
+            println!(
+                "Error updating the club leaderboard of server {}: {:?}",
+                guild_id, why
+            );
+        }
+    }
+}
+
+async fn sync(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    client: &reqwest::Client,
+    guild_id: u64,
+    board: &ClubBoard,
+    clubs: &Clubs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Left as it was if the team and club were both taken off since.
+    if clubs.team.is_none() && clubs.club.is_none() {
+        return Ok(());
+    }
+    let mut entries = Vec::new();
+    let mut sources = Vec::new();
+    if let Some(team) = &clubs.team {
+        entries.extend(lichess_entries(client, team, &board.speed).await?);
+        sources.push(format!("lichess.org/team/{}", team));
+    }
+    if let Some(club) = &clubs.club {
+        entries.extend(chesscom_entries(client, club, &board.speed).await?);
+        sources.push(format!("chess.com/club/{}", club));
+    }
+    entries.sort_by(|a, b| {
+        b.rating
+            .cmp(&a.rating)
+            .then_with(|| a.username.to_lowercase().cmp(&b.username.to_lowercase()))
+    });
+
+    let channel_id = ChannelId(board.channel_id);
+    let edited = match board.message_id {
+        Some(message_id) => channel_id
+            .edit_message(http, MessageId(message_id), |m| {
+                m.embed(|e| board_embed(e, &entries, &board.speed, &sources))
+            })
+            .await
+            .is_ok(),
+        None => false,
+    };
+    // Posted again if it was deleted.
+    if !edited {
+        let sent = channel_id
+            .send_message(http, |m| {
+                m.embed(|e| board_embed(e, &entries, &board.speed, &sources))
+            })
+            .await?;
+        settings::update(data, |settings| {
+            let stored = settings
+                .guilds
+                .get_mut(&guild_id)
+                .and_then(|guild| guild.club_board.as_mut())
+                .filter(|stored| stored.channel_id == board.channel_id);
+            // Left alone if the board was moved or turned off in the meantime.
+            if let Some(stored) = stored {
+                stored.message_id = Some(sent.id.0);
+            }
+        })
+        .await;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Keep a leaderboard of the members of the server's Lichess team (see `.lichessteam`) and chess.com club (see `.chesscomclub`), updated every hour. Give the channel it goes in, this one if left out, and the speed to rank by, blitz if left out."
+)]
+#[usage("[#channel] [bullet|blitz|rapid] | off")]
+#[example("#leaderboard rapid")]
+async fn clubboard(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let guild = settings::guild(&ctx.data, guild_id).await;
+
+    if args.is_empty() {
+        let reply = match &guild.club_board {
+            Some(board) => format!(
+                "The club leaderboard is kept in <#{}>, ranked by {} rating. `.clubboard off` stops it.",
+                board.channel_id, board.speed
+            ),
+            None => "This server has no club leaderboard, use `.clubboard #channel`.".to_string(),
+        };
+        msg.reply(&ctx.http, reply).await?;
+        return Ok(());
+    }
+
+    let mut channel_id = msg.channel_id.0;
+    let mut speed = DEFAULT_SPEED;
+    for arg in args.raw() {
+        if settings::parse_toggle(arg) == Some(false) {
+            settings::update(&ctx.data, |settings| {
+                settings.guilds.entry(guild_id.0).or_default().club_board = None;
+            })
+            .await;
+            msg.reply(&ctx.http, "I won't keep the club leaderboard anymore.")
+                .await?;
+            return Ok(());
+        } else if let Some(channel) = utils::parse_channel(arg) {
+            channel_id = channel;
+        } else if let Some(found) = SPEEDS.iter().find(|speed| speed.eq_ignore_ascii_case(arg)) {
+            speed = found;
+        } else {
+            msg.reply(
+                &ctx.http,
+                "Use `.clubboard [#channel] [bullet|blitz|rapid]` or `.clubboard off`",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    if guild.lichess_team.is_none() && guild.chesscom_club.is_none() {
+        msg.reply(
+            &ctx.http,
+            "Pick the server's Lichess team with `.lichessteam` or its chess.com club with `.chesscomclub` first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Moving the board to another channel posts it again there.
+    let board = ClubBoard {
+        channel_id,
+        message_id: guild
+            .club_board
+            .filter(|board| board.channel_id == channel_id)
+            .and_then(|board| board.message_id),
+        speed: speed.to_string(),
+    };
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().club_board = Some(board.clone());
+    })
+    .await;
+    msg.reply(
+        &ctx.http,
+        format!(
+            "Ranking the club by {} rating in <#{}>, this takes a moment. It's updated every hour.",
+            speed, channel_id
+        ),
+    )
+    .await?;
+
+    let clubs = Clubs {
+        team: guild.lichess_team.map(|team| team.team),
+        club: guild.chesscom_club,
+    };
+    let client = web::client(&ctx.data).await;
+    if let Err(why) = sync(&ctx.http, &ctx.data, &client, guild_id.0, &board, &clubs).await {
+        println!(
+            "Error updating the club leaderboard of server {}: {:?}",
+            guild_id, why
+        );
+        msg.reply(
+            &ctx.http,
+            "I couldn't make the leaderboard right now, I'll try again within the hour.",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Pick the server's chess.com club, whose members are ranked on the club leaderboard with the Lichess team's. Give the club's id from its link."
+)]
+#[usage("[<club id> | off]")]
+#[example("chess-com-developer-community")]
+async fn chesscomclub(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let arg = match args.current() {
+        Some(arg) => arg,
+        None => {
+            let reply = match settings::guild(&ctx.data, guild_id).await.chesscom_club {
+                Some(club) => format!(
+                    "The server's chess.com club is <https://www.chess.com/club/{}>.",
+                    club
+                ),
+                None => {
+                    "This server has no chess.com club, use `.chesscomclub <club id>`.".to_string()
+                }
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    if settings::parse_toggle(arg) == Some(false) {
+        settings::update(&ctx.data, |settings| {
+            settings.guilds.entry(guild_id.0).or_default().chesscom_club = None;
+        })
+        .await;
+        msg.reply(
+            &ctx.http,
+            "The server's chess.com club isn't ranked anymore.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Take the id out of a pasted link too.
+    let id = arg
+        .trim_matches(|c| c == '<' || c == '>')
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let club = match chesscom::club(&web::client(&ctx.data).await, &id).await {
+        Ok(Some(club)) => club,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                format!("There's no chess.com club with the id `{}`.", id),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting chess.com club {}: {:?}", id, why);
+            msg.reply(&ctx.http, "I couldn't reach chess.com right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let has_board = settings::update(&ctx.data, |settings| {
+        let guild = settings.guilds.entry(guild_id.0).or_default();
+        guild.chesscom_club = Some(id);
+        guild.club_board.is_some()
+    })
+    .await;
+    let reply = if has_board {
+        format!(
+            "{} (<{}>) is ranked on the club leaderboard from the next update.",
+            club.name, club.url
+        )
+    } else {
+        format!(
+            "{} (<{}>) is the server's club now. Post its leaderboard with `.clubboard #channel`.",
+            club.name, club.url
+        )
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
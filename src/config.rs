@@ -0,0 +1,238 @@
+use std::{collections::HashMap, env, fs, sync::Arc};
+
+use serde::Deserialize;
+use serenity::prelude::*;
+
+// Where the config is read from unless overridden with `CUTE_BOT_CONFIG`.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // The channel people are pointed to for bot commands.
+    pub bot_channel: u64,
+    // Quotes, answers and other text content, see content.toml.
+    pub content_file: String,
+    // Where to download the content from instead, like a raw file link from a git host. The
+    // content file is still used until the first download works.
+    pub content_url: Option<String>,
+    // How often the content is downloaded again, when the job is first scheduled.
+    pub content_refresh_hours: u32,
+    // Where settings and other state are saved.
+    pub data_dir: String,
+    // How often followed players are checked for new games, when the job is first scheduled.
+    // Change it afterwards with `.jobs reschedule`.
+    pub follow_poll_minutes: u64,
+    // Piece images uploaded by `.emojiset install`, named like wK.png, bn.png, light.png.
+    pub emoji_pieces_dir: String,
+    // Attach boards as pictures for the image theme. Turn it off to always send text diagrams.
+    pub board_images: bool,
+    pub moderation: ModerationConfig,
+    pub daily_position: DailyPositionConfig,
+    pub daily_puzzle: DailyPuzzleConfig,
+    pub backup: BackupConfig,
+    pub engine: EngineConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bot_channel: 855703545398427668,
+            content_file: "content.toml".to_string(),
+            content_url: None,
+            content_refresh_hours: 6,
+            data_dir: "data".to_string(),
+            follow_poll_minutes: 5,
+            emoji_pieces_dir: "assets/pieces".to_string(),
+            board_images: true,
+            moderation: ModerationConfig::default(),
+            daily_position: DailyPositionConfig::default(),
+            daily_puzzle: DailyPuzzleConfig::default(),
+            backup: BackupConfig::default(),
+            engine: EngineConfig::default(),
+        }
+    }
+}
+
+// Auto-moderation of the bot channel. Everything is off unless `enabled` is set.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    pub enabled: bool,
+    // Identical commands sent within this many seconds of each other are collapsed into one.
+    pub duplicate_window_secs: u64,
+    // Failed or unknown commands (and the bot's complaints about them) are deleted after this
+    // many seconds.
+    pub delete_failed_after_secs: u64,
+    // Per-command cooldown in seconds, e.g. `quote = 30`.
+    pub slow_mode: HashMap<String, u64>,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            enabled: false,
+            duplicate_window_secs: 10,
+            delete_failed_after_secs: 30,
+            slow_mode: HashMap::new(),
+        }
+    }
+}
+
+// A daily position posted for discussion, with its assessment revealed a day later.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DailyPositionConfig {
+    // Channel to post in. Nothing is posted if this isn't set.
+    pub channel: Option<u64>,
+    // Hour of the day to post at, in the server's timezone (see `.servertimezone`). Like
+    // `follow_poll_minutes`, only used when the job is first scheduled.
+    pub hour: u32,
+}
+
+impl Default for DailyPositionConfig {
+    fn default() -> Self {
+        DailyPositionConfig {
+            channel: None,
+            hour: 9,
+        }
+    }
+}
+
+// The daily puzzle, posted with its solution revealed later. It comes from the site picked for
+// the channel's server with `.puzzlesource`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DailyPuzzleConfig {
+    // Channel to post in. Nothing is posted if this isn't set.
+    pub channel: Option<u64>,
+    // Hour of the day to post at, in the server's timezone, like the daily position's.
+    pub hour: u32,
+    // Hours after posting to reveal the solution. Unset, it's revealed with the next puzzle.
+    pub reveal_after: Option<u32>,
+    // Start a thread under each puzzle to talk it over in, where the solution goes too.
+    pub thread: bool,
+}
+
+impl Default for DailyPuzzleConfig {
+    fn default() -> Self {
+        DailyPuzzleConfig {
+            channel: None,
+            hour: 8,
+            reveal_after: None,
+            thread: true,
+        }
+    }
+}
+
+// Off-site copies of the settings, games, puzzle stats and quotes in S3-compatible storage. The keys are read from
+// `CUTE_BOT_S3_KEY` and `CUTE_BOT_S3_SECRET`, and nothing is uploaded without a bucket.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub bucket: Option<String>,
+    pub endpoint: String,
+    pub region: String,
+    // Put the bucket in the path instead of the host name, which most S3 clones want.
+    pub path_style: bool,
+    // Backups are stored under this prefix, so a bucket can be shared.
+    pub prefix: String,
+    // How many backups of each kind to keep. Older ones are deleted after each upload.
+    pub keep: usize,
+    // Hour of the day (UTC) to upload at, only used when the job is first scheduled.
+    pub hour: u32,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            bucket: None,
+            endpoint: "https://s3.amazonaws.com".to_string(),
+            region: "us-east-1".to_string(),
+            path_style: true,
+            prefix: "cute-chess-bot/".to_string(),
+            keep: 14,
+            hour: 4,
+        }
+    }
+}
+
+// The UCI engine the bot plays with, like Stockfish.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    // The engine binary, a path or a name on the PATH.
+    pub path: String,
+    // The longest the engine thinks about a move, in milliseconds.
+    pub move_time_ms: u64,
+    pub threads: u32,
+    // How many engines may run at once. Requests past that wait for one to be free.
+    pub pool_size: usize,
+    // How deep `.eval` searches, and the most time it takes, in milliseconds.
+    pub eval_depth: u32,
+    pub eval_time_ms: u64,
+    // The same for each position `.analyze` goes over.
+    pub game_depth: u32,
+    pub game_time_ms: u64,
+    // How many `.eval` and `.analyze` requests run at once, the rest wait in a queue. Keep it
+    // below `pool_size` so games still get an engine.
+    pub jobs: usize,
+    // Where `.eval` looks first. Either way it tries the other when that doesn't work out.
+    pub eval_backend: EvalBackend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvalBackend {
+    // The bot's own engine, or Lichess while the queue is long.
+    Engine,
+    // Lichess' cloud evaluations, for positions analysed there before.
+    Cloud,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            path: "stockfish".to_string(),
+            move_time_ms: 1000,
+            threads: 1,
+            pool_size: 2,
+            eval_depth: 20,
+            eval_time_ms: 10000,
+            game_depth: 14,
+            game_time_ms: 500,
+            jobs: 1,
+            eval_backend: EvalBackend::Engine,
+        }
+    }
+}
+
+impl Config {
+    // Load the config file, falling back to the defaults if there is none.
+    pub fn load() -> Config {
+        let path = env::var("CUTE_BOT_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .unwrap_or_else(|why| panic!("Could not parse config file {}: {}", path, why)),
+            Err(_) => {
+                println!("No config file at {}, using defaults", path);
+                Config::default()
+            }
+        }
+    }
+}
+
+pub struct ConfigContainer;
+
+impl TypeMapKey for ConfigContainer {
+    type Value = Arc<Config>;
+}
+
+// Grab the config out of the client data.
+pub async fn get(data: &RwLock<TypeMap>) -> Arc<Config> {
+    let data = data.read().await;
+    data.get::<ConfigContainer>()
+        .expect("Expected config in typemap.")
+        .clone()
+}
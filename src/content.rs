@@ -0,0 +1,199 @@
+use std::{collections::HashMap, error::Error, fs, sync::Arc};
+
+use serde::Deserialize;
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{channel::Message, id::GuildId},
+    prelude::*,
+};
+
+use crate::{
+    config::{self, Config},
+    modules::BotModule,
+    scheduler::JobKind,
+    web,
+};
+
+// Shipped with the bot and used when the configured content file doesn't exist.
+const DEFAULT_CONTENT: &str = include_str!("../content.toml");
+
+#[group]
+#[commands(updatecontent)]
+struct ContentPacks;
+
+pub struct ContentModule;
+
+#[async_trait]
+impl BotModule for ContentModule {
+    fn name(&self) -> &'static str {
+        "content"
+    }
+
+    fn description(&self) -> &'static str {
+        "The bot's jokes, answers and positions, see `.updatecontent`."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&CONTENTPACKS_GROUP)
+    }
+
+    fn jobs(&self, config: &Config) -> Vec<(JobKind, String)> {
+        match config.content_url {
+            Some(_) => vec![(
+                JobKind::ContentRefresh,
+                format!("0 */{} * * *", config.content_refresh_hours.clamp(1, 23)),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    async fn run_job(&self, _http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::ContentRefresh {
+            if let Err(why) = refresh(data).await {
+                println!("Could not refresh the content: {:?}", why);
+            }
+        }
+    }
+}
+
+// Jokes, answers and other text the bot picks from, so servers can change them without a rebuild.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Content {
+    pub eightball: Vec<String>,
+    // Simple joke commands: command name -> possible responses (text or image links).
+    pub memes: HashMap<String, Vec<String>>,
+    // Positions for the daily position discussion.
+    pub positions: Vec<ContentPosition>,
+    // Extra content per guild, keyed by guild id.
+    pub guilds: HashMap<String, GuildContent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentPosition {
+    pub fen: String,
+    pub title: String,
+    // Shown when the discussion closes.
+    pub assessment: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GuildContent {
+    pub eightball: Vec<String>,
+    pub memes: HashMap<String, Vec<String>>,
+}
+
+impl Content {
+    pub fn load(path: &str) -> Content {
+        let text = fs::read_to_string(path).unwrap_or_else(|_| {
+            println!("No content file at {}, using the built-in content", path);
+            DEFAULT_CONTENT.to_string()
+        });
+
+        toml::from_str(&text)
+            .unwrap_or_else(|why| panic!("Could not parse content file {}: {}", path, why))
+    }
+
+    // "12 memes, 20 8-ball answers and 30 positions", for telling what changed after a reload.
+    fn summary(&self) -> String {
+        format!(
+            "{} memes, {} 8-ball answers and {} positions",
+            self.memes.len(),
+            self.eightball.len(),
+            self.positions.len()
+        )
+    }
+
+    pub fn guild(&self, guild_id: Option<GuildId>) -> Option<&GuildContent> {
+        guild_id.and_then(|id| self.guilds.get(&id.to_string()))
+    }
+
+    // The 8-ball answers for a guild: the shared ones plus the guild's own.
+    pub fn eightball_answers(&self, guild_id: Option<GuildId>) -> Vec<&str> {
+        let mut answers: Vec<&str> = self.eightball.iter().map(String::as_str).collect();
+        if let Some(guild) = self.guild(guild_id) {
+            answers.extend(guild.eightball.iter().map(String::as_str));
+        }
+        answers
+    }
+
+    // Responses for a meme command. A guild's own version of a meme replaces the shared one.
+    pub fn meme_responses(&self, guild_id: Option<GuildId>, name: &str) -> Option<&[String]> {
+        let name = name.to_lowercase();
+        self.guild(guild_id)
+            .and_then(|guild| guild.memes.get(&name))
+            .or_else(|| self.memes.get(&name))
+            .map(Vec::as_slice)
+    }
+
+    pub fn meme_names(&self, guild_id: Option<GuildId>) -> Vec<&str> {
+        let mut names: Vec<&str> = self.memes.keys().map(String::as_str).collect();
+        if let Some(guild) = self.guild(guild_id) {
+            names.extend(guild.memes.keys().map(String::as_str));
+        }
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+pub struct ContentContainer;
+
+impl TypeMapKey for ContentContainer {
+    type Value = Arc<Content>;
+}
+
+pub async fn get(data: &RwLock<TypeMap>) -> Arc<Content> {
+    let data = data.read().await;
+    data.get::<ContentContainer>()
+        .expect("Expected content in typemap.")
+        .clone()
+}
+
+// Load the content again and swap it in: from `content_url` if there is one, otherwise from
+// the content file. The old content stays if the new one can't be read.
+pub async fn refresh(data: &RwLock<TypeMap>) -> Result<Arc<Content>, Box<dyn Error + Send + Sync>> {
+    let config = config::get(data).await;
+    let text = match &config.content_url {
+        Some(url) => {
+            web::client(data)
+                .await
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        }
+        None => fs::read_to_string(&config.content_file)?,
+    };
+
+    let content = Arc::new(toml::from_str::<Content>(&text)?);
+    data.write()
+        .await
+        .insert::<ContentContainer>(content.clone());
+    Ok(content)
+}
+
+#[command]
+#[owners_only]
+#[description("Reload the jokes, answers and positions from the content URL or file.")]
+async fn updatecontent(ctx: &Context, msg: &Message) -> CommandResult {
+    let reply = match refresh(&ctx.data).await {
+        Ok(content) => format!("Content updated, there are {} now.", content.summary()),
+        Err(why) => format!("Couldn't update the content, keeping the old one: {}", why),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::borrow::Cow;
+
+use resvg::{tiny_skia, usvg};
+use serenity::{
+    http::{AttachmentType, Http},
+    model::{id::ChannelId, permissions::Permissions},
+    prelude::*,
+};
+use shakmaty::{Board, Color, File, Rank, Role, Square};
+
+use crate::{config, permissions, render::BoardStyle};
+
+// Embeds show the picture with `e.image(IMAGE_URL)` once it's attached to the message.
+pub const IMAGE_FILE: &str = "board.png";
+pub const IMAGE_URL: &str = "attachment://board.png";
+
+// Pixels per square in the PNG. The SVG works in units of 100 per square.
+const SQUARE_PX: u32 = 60;
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const HIGHLIGHT: &str = "#cdd26a";
+
+// Every piece is drawn in a 100 by 100 square. `{detail}` marks lines drawn in the
+// contrasting color, like the knight's eye.
+const BASE: &str = r#"<rect x="24" y="78" width="52" height="10" rx="3"/>"#;
+const PAWN: &str = r#"<circle cx="50" cy="34" r="12"/><path d="M38 78 L43 50 H57 L62 78 Z"/>"#;
+const KNIGHT: &str = r#"<path d="M34 78 C34 62 44 56 46 48 C40 52 32 54 28 50 C24 46 30 38 36 32 C42 24 48 18 56 18 C70 20 74 40 70 78 Z"/><circle cx="47" cy="30" r="3" fill="{detail}" stroke="none"/>"#;
+const BISHOP: &str = r#"<circle cx="50" cy="16" r="6"/><path d="M50 24 C34 38 34 60 40 70 H60 C66 60 66 38 50 24 Z"/><rect x="36" y="70" width="28" height="8" rx="2"/><path d="M45 42 L55 52" stroke="{detail}"/>"#;
+const ROOK: &str = r#"<path d="M30 22 H38 V30 H46 V22 H54 V30 H62 V22 H70 V40 H30 Z"/><path d="M35 40 H65 L62 78 H38 Z"/>"#;
+const QUEEN: &str = r#"<path d="M30 78 L22 34 L38 54 L42 26 L50 52 L58 26 L62 54 L78 34 L70 78 Z"/><circle cx="22" cy="30" r="5"/><circle cx="42" cy="22" r="5"/><circle cx="58" cy="22" r="5"/><circle cx="78" cy="30" r="5"/>"#;
+const KING: &str = r#"<path d="M30 78 C24 60 24 48 32 44 C40 40 46 48 50 54 C54 48 60 40 68 44 C76 48 76 60 70 78 Z"/><path d="M46 12 H54 V22 H62 V30 H54 V44 H46 V30 H38 V22 H46 Z"/>"#;
+
+// How the picture is drawn, on top of the pieces themselves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiagramOptions {
+    // Draw the board from black's side.
+    pub flipped: bool,
+    // Squares to highlight, usually the last move's from and to.
+    pub highlight: Option<(Square, Square)>,
+}
+
+fn piece_shapes(role: Role) -> &'static str {
+    match role {
+        Role::Pawn => PAWN,
+        Role::Knight => KNIGHT,
+        Role::Bishop => BISHOP,
+        Role::Rook => ROOK,
+        Role::Queen => QUEEN,
+        Role::King => KING,
+    }
+}
+
+// Where a square's top left corner is, in SVG units.
+fn square_origin(sq: Square, flipped: bool) -> (u32, u32) {
+    let (file, rank) = (sq.file().to_u32(), sq.rank().to_u32());
+    if flipped {
+        ((7 - file) * 100, rank * 100)
+    } else {
+        (file * 100, (7 - rank) * 100)
+    }
+}
+
+pub fn board_svg(board: &Board, options: &DiagramOptions) -> String {
+    let size = 8 * SQUARE_PX;
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 800 800">{}</svg>"#,
+        size,
+        size,
+        board_squares(board, options)
+    )
+}
+
+// The squares and pieces of `board_svg`, to go inside an element with its viewBox, like the
+// boards on a simul's dashboard.
+pub fn board_squares(board: &Board, options: &DiagramOptions) -> String {
+    let mut svg = String::new();
+    for &rank in Rank::ALL.iter() {
+        for &file in File::ALL.iter() {
+            let sq = Square::from_coords(file, rank);
+            let (x, y) = square_origin(sq, options.flipped);
+            let highlighted = options
+                .highlight
+                .is_some_and(|(from, to)| sq == from || sq == to);
+            let fill = match (highlighted, sq.is_light()) {
+                (true, _) => HIGHLIGHT,
+                (false, true) => LIGHT_SQUARE,
+                (false, false) => DARK_SQUARE,
+            };
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="100" height="100" fill="{}"/>"#,
+                x, y, fill
+            ));
+        }
+    }
+
+    for (sq, piece) in board.clone() {
+        let (x, y) = square_origin(sq, options.flipped);
+        let (fill, detail) = match piece.color {
+            Color::White => ("#ffffff", "#222222"),
+            Color::Black => ("#222222", "#dddddd"),
+        };
+        svg.push_str(&format!(
+            r##"<g transform="translate({} {})" fill="{}" stroke="#222222" stroke-width="4" stroke-linejoin="round" stroke-linecap="round">{}{}</g>"##,
+            x,
+            y,
+            fill,
+            piece_shapes(piece.role).replace("{detail}", detail),
+            BASE
+        ));
+    }
+    svg
+}
+
+// An empty board with each square tinted by how hot it is, from 0 to 1, for heatmaps. The
+// squares are in SVG units like `board_svg`'s, to go inside an element with its viewBox.
+pub fn heatmap_squares(heat: &[f32; 64], tint: &str, flipped: bool) -> String {
+    let mut svg = String::new();
+    for sq in Square::ALL {
+        let (x, y) = square_origin(sq, flipped);
+        let fill = if sq.is_light() {
+            LIGHT_SQUARE
+        } else {
+            DARK_SQUARE
+        };
+        svg.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="100" height="100" fill="{}"/>"#,
+            x, y, fill
+        ));
+        let heat = heat[usize::from(sq)];
+        if heat > 0.0 {
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="100" height="100" fill="{}" fill-opacity="{:.2}"/>"#,
+                x,
+                y,
+                tint,
+                0.9 * heat.min(1.0)
+            ));
+        }
+    }
+    svg
+}
+
+// The board drawn `size` pixels wide. Without `smooth` edges aren't anti-aliased, which
+// keeps the picture to a handful of colors for GIFs.
+pub fn board_pixmap(
+    board: &Board,
+    options: &DiagramOptions,
+    size: u32,
+    smooth: bool,
+) -> Option<tiny_skia::Pixmap> {
+    let mut svg = board_svg(board, options);
+    if !smooth {
+        svg = svg.replacen("<svg ", r#"<svg shape-rendering="crispEdges" "#, 1);
+    }
+    let tree = match usvg::Tree::from_str(&svg, &usvg::Options::default()) {
+        Ok(tree) => tree,
+        Err(why) => {
+            println!("Could not draw a board: {:?}", why);
+            return None;
+        }
+    };
+
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)?;
+    let scale = size as f32 / tree.size().width();
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    Some(pixmap)
+}
+
+// The board as a PNG, or None if it couldn't be drawn.
+pub fn board_png(board: &Board, options: &DiagramOptions) -> Option<Vec<u8>> {
+    board_pixmap(board, options, 8 * SQUARE_PX, true)?
+        .encode_png()
+        .ok()
+}
+
+// Whether the bot can send pictures in a channel: they're on in the config and it may attach
+// files there.
+pub async fn images_allowed(http: &Http, data: &RwLock<TypeMap>, channel_id: ChannelId) -> bool {
+    if !config::get(data).await.board_images {
+        return false;
+    }
+    let bot_id = crate::bot_id(data).await;
+    match permissions::channel_permissions(http, channel_id, bot_id).await {
+        Ok(permissions) => permissions.contains(Permissions::ATTACH_FILES),
+        Err(why) => {
+            println!("Could not check permissions in {}: {:?}", channel_id, why);
+            false
+        }
+    }
+}
+
+// The picture to attach for someone with this style in a channel, if they get pictures and it
+// could be drawn. Otherwise the board goes in the message as text, see `render::board_for`.
+pub async fn board_image(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    board: &Board,
+    style: &BoardStyle,
+    options: &DiagramOptions,
+) -> Option<Vec<u8>> {
+    if style.wants_image() && images_allowed(http, data, channel_id).await {
+        board_png(board, options)
+    } else {
+        None
+    }
+}
+
+// A board picture ready to attach to a message.
+pub fn attachment(png: Vec<u8>) -> AttachmentType<'static> {
+    AttachmentType::Bytes {
+        data: Cow::Owned(png),
+        filename: IMAGE_FILE.to_string(),
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{Timelike, Utc};
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{channel::Message, id::ChannelId},
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    config::Config,
+    eval,
+    game::{self, Game, GameResult},
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    puzzle,
+    rating::{self, Rating},
+    scheduler::JobKind,
+    settings, timezone, EMBED_SIDE_COLOR,
+};
+
+const DAY_SECS: i64 = 24 * 3600;
+
+// The digest goes out at this hour in the server's time.
+const DIGEST_HOUR: u32 = 22;
+
+// Beating someone rated this much higher is an upset worth mentioning.
+const MIN_UPSET: i32 = 100;
+
+// Going over games with the engine takes a while, so only the longest few of the day are looked
+// at for the most accurate one, and only ones long enough to say much.
+const GAMES_REVIEWED: usize = 3;
+const MIN_REVIEW_PLIES: usize = 30;
+
+#[group]
+#[commands(digest)]
+struct Digests;
+
+pub struct DigestModule;
+
+const DIGEST_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Digest channel",
+    command: "digest",
+    value: |guild| modules::show_channel(guild.digest_channel),
+}];
+
+#[async_trait]
+impl BotModule for DigestModule {
+    fn name(&self) -> &'static str {
+        "digest"
+    }
+
+    fn description(&self) -> &'static str {
+        "A summary of the day's games, puzzles and rating changes every evening, see `.digest`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&DIGESTS_GROUP)
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        // Servers are in different timezones, each one is looked at every hour.
+        vec![(JobKind::DailyDigest, "0 * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::DailyDigest {
+            post_all(http, data).await;
+        }
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        DIGEST_SETTINGS
+    }
+}
+
+// What happened in a server's games over a day.
+#[derive(Debug, Default, PartialEq)]
+struct Activity {
+    games: usize,
+    rated: usize,
+    // The winner and their rating going in, the loser and theirs, and the game.
+    upset: Option<(u64, i32, u64, i32, u32)>,
+    // Who won the most rating points and who lost the most, and how many.
+    riser: Option<(u64, i32)>,
+    faller: Option<(u64, i32)>,
+}
+
+// The games finished since `since`, and what they did to the ratings.
+fn activity(games: &[&Game], ratings: Option<&HashMap<u64, Rating>>, since: i64) -> Activity {
+    let mut activity = Activity::default();
+    let mut upset_gap = MIN_UPSET - 1;
+    for game in games.iter().filter(|game| {
+        game.ended_at.is_some_and(|at| at >= since)
+            && !matches!(game.result, None | Some(GameResult::Aborted))
+    }) {
+        activity.games += 1;
+        if !rating::is_rated(game) {
+            continue;
+        }
+        activity.rated += 1;
+
+        let (winner, loser) = match game.result {
+            Some(GameResult::WhiteWon) => (game.white, game.black),
+            Some(GameResult::BlackWon) => (game.black, game.white),
+            _ => continue,
+        };
+        let before = |player: u64| ratings?.get(&player)?.before(game.id);
+        if let (Some(winner_rating), Some(loser_rating)) = (before(winner), before(loser)) {
+            if loser_rating - winner_rating > upset_gap {
+                upset_gap = loser_rating - winner_rating;
+                activity.upset = Some((winner, winner_rating, loser, loser_rating, game.id));
+            }
+        }
+    }
+
+    let mut changes: Vec<(u64, i32)> = ratings
+        .into_iter()
+        .flatten()
+        .map(|(&player, rating)| {
+            let change = rating
+                .history
+                .iter()
+                .filter(|change| change.at >= since)
+                .map(|change| change.change)
+                .sum();
+            (player, change)
+        })
+        .collect();
+    changes.sort_by_key(|&(player, change)| (std::cmp::Reverse(change), player));
+    activity.riser = changes.first().copied().filter(|&(_, change)| change > 0);
+    activity.faller = changes.last().copied().filter(|&(_, change)| change < 0);
+    activity
+}
+
+// The day's longest rated games, and each side's accuracy in the best played of them.
+async fn most_accurate(data: &RwLock<TypeMap>, mut games: Vec<Game>) -> Option<(Game, f64, f64)> {
+    games.retain(|game| rating::is_rated(game) && game.moves.len() >= MIN_REVIEW_PLIES);
+    games.sort_by_key(|game| std::cmp::Reverse(game.moves.len()));
+    games.truncate(GAMES_REVIEWED);
+
+    let mut best: Option<(Game, f64, f64)> = None;
+    for game in games {
+        let (white, black) = match eval::game_accuracy(data, &game).await {
+            Some(accuracy) => accuracy,
+            None => continue,
+        };
+        if best.as_ref().is_none_or(|(_, w, b)| white + black > w + b) {
+            best = Some((game, white, black));
+        }
+    }
+    best
+}
+
+// The digest of a server's last day, or None if nothing happened.
+async fn digest_text(data: &RwLock<TypeMap>, guild_id: u64) -> Option<Vec<(&'static str, String)>> {
+    let since = Utc::now().timestamp() - DAY_SECS;
+    let (activity, day_games) = game::read_games(data, |store| {
+        let games: Vec<&Game> = store
+            .games
+            .iter()
+            .filter(|game| game.guild_id == Some(guild_id))
+            .collect();
+        let activity = activity(&games, store.ratings.get(&guild_id), since);
+        let day_games: Vec<Game> = games
+            .into_iter()
+            .filter(|game| game.ended_at.is_some_and(|at| at >= since))
+            .cloned()
+            .collect();
+        (activity, day_games)
+    })
+    .await;
+    let (tried, solved, top_solver) = puzzle::read_puzzles(data, |store| {
+        let mut tried = 0;
+        let mut solved = 0;
+        let mut top_solver: Option<(u64, usize)> = None;
+        for (&player, stats) in &store.players {
+            let attempts: Vec<_> = stats
+                .attempts
+                .iter()
+                .filter(|attempt| attempt.guild_id == Some(guild_id) && attempt.at >= since)
+                .collect();
+            let player_solved = attempts.iter().filter(|attempt| attempt.solved).count();
+            tried += attempts.len();
+            solved += player_solved;
+            if player_solved > top_solver.map_or(0, |(_, most)| most) {
+                top_solver = Some((player, player_solved));
+            }
+        }
+        (tried, solved, top_solver)
+    })
+    .await;
+    if activity.games == 0 && tried == 0 {
+        return None;
+    }
+
+    let mut fields = Vec::new();
+    fields.push((
+        "Games",
+        format!("{} finished, {} rated", activity.games, activity.rated),
+    ));
+    if let Some((winner, winner_rating, loser, loser_rating, game_id)) = activity.upset {
+        fields.push((
+            "Biggest upset",
+            format!(
+                "<@{}> ({}) beat <@{}> ({}) in game #{}",
+                winner, winner_rating, loser, loser_rating, game_id
+            ),
+        ));
+    }
+    if tried > 0 {
+        let mut text = format!("{} tried, {} solved", tried, solved);
+        if let Some((player, count)) = top_solver {
+            text.push_str(&format!(", most by <@{}> with {}", player, count));
+        }
+        fields.push(("Puzzles", text));
+    }
+    let movers: Vec<String> = [
+        activity
+            .riser
+            .map(|(player, change)| format!("📈 <@{}> {:+}", player, change)),
+        activity
+            .faller
+            .map(|(player, change)| format!("📉 <@{}> {:+}", player, change)),
+    ]
+    .iter()
+    .flatten()
+    .cloned()
+    .collect();
+    if !movers.is_empty() {
+        fields.push(("Rating movers", movers.join(" · ")));
+    }
+    if let Some((game, white, black)) = most_accurate(data, day_games).await {
+        fields.push((
+            "Most accurate game",
+            format!(
+                "Game #{}, ⚪ <@{}> {:.0}% · ⚫ <@{}> {:.0}%",
+                game.id, game.white, white, game.black, black
+            ),
+        ));
+    }
+    Some(fields)
+}
+
+// Post the digest in every server whose evening it is.
+async fn post_all(http: &Http, data: &RwLock<TypeMap>) {
+    let now = Utc::now().timestamp();
+    let targets = settings::read(data, |settings| {
+        settings
+            .guilds
+            .iter()
+            .filter_map(|(&guild_id, guild)| {
+                let channel = guild.digest_channel?;
+                // A restart around the hour shouldn't post it twice.
+                let posted = guild
+                    .digest_posted
+                    .is_some_and(|at| now - at < DAY_SECS / 2);
+                if posted {
+                    None
+                } else {
+                    Some((guild_id, channel))
+                }
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    for (guild_id, channel) in targets {
+        let channel_id = ChannelId(channel);
+        let hour = Utc::now()
+            .with_timezone(&timezone::for_channel(http, data, channel_id).await)
+            .hour();
+        if hour != DIGEST_HOUR || !modules::enabled_in(http, data, channel_id, &DigestModule).await
+        {
+            continue;
+        }
+        settings::update(data, |settings| {
+            settings.guilds.entry(guild_id).or_default().digest_posted = Some(now);
+        })
+        .await;
+
+        let fields = match digest_text(data, guild_id).await {
+            Some(fields) => fields,
+            None => continue,
+        };
+        let result = channel_id
+            .send_message(http, |m| {
+                m.embed(|e| {
+                    e.title("Today in chess");
+                    e.color(EMBED_SIDE_COLOR);
+                    for (name, value) in fields {
+                        e.field(name, value, false);
+                    }
+                    e
+                });
+                m
+            })
+            .await;
+        if let Err(why) = result {
+            println!(
+                "Error posting the daily digest in {}: {:?}",
+                channel_id, why
+            );
+        }
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Pick the channel for an evening digest of the server's day: games played, the biggest upset, puzzles solved, rating movers and the most accurate game."
+)]
+#[usage("<#channel|off>")]
+async fn digest(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let channel = match args.current() {
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => None,
+        Some(arg) => match utils::parse_channel(arg) {
+            Some(channel) => Some(channel),
+            None => {
+                msg.reply(&ctx.http, "Use `.digest #channel` or `.digest off`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let reply = match settings::guild(&ctx.data, guild_id).await.digest_channel {
+                Some(channel) => format!("The daily digest goes to <#{}>.", channel),
+                None => "This server doesn't get a daily digest.".to_string(),
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .digest_channel = channel;
+    })
+    .await;
+
+    let reply = match channel {
+        Some(channel) => format!(
+            "The day's digest will be posted in <#{}> at {}:00 every evening.",
+            channel, DIGEST_HOUR
+        ),
+        None => "I won't post the daily digest anymore.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::GameOptions, rating::ServerRatings};
+
+    fn game(id: u32, players: (u64, u64), result: GameResult, ended_at: i64) -> Game {
+        Game {
+            id,
+            white: players.0,
+            black: players.1,
+            result: Some(result),
+            ended_at: Some(ended_at),
+            ..GameOptions::default().new_game(Some(10), 20, players)
+        }
+    }
+
+    #[test]
+    fn days_activity() {
+        let now = Utc::now().timestamp();
+        let yesterday = now - DAY_SECS;
+        // 1 beat 2 a few times last week, then lost to them today.
+        let mut games: Vec<Game> = (1..=5)
+            .map(|id| game(id, (1, 2), GameResult::WhiteWon, now - 7 * DAY_SECS))
+            .collect();
+        games.push(game(6, (2, 1), GameResult::WhiteWon, now));
+        games.push(game(7, (1, 3), GameResult::Draw, now));
+        games.push(game(8, (3, 2), GameResult::Aborted, now));
+
+        let mut ratings = ServerRatings::new();
+        for game in &games {
+            rating::rate(&mut ratings, game);
+        }
+        for rating in ratings.get_mut(&10).unwrap().values_mut() {
+            for change in rating
+                .history
+                .iter_mut()
+                .filter(|change| change.game_id <= 5)
+            {
+                change.at = now - 7 * DAY_SECS;
+            }
+        }
+        let guild = &ratings[&10];
+        let games: Vec<&Game> = games.iter().collect();
+
+        let day = activity(&games, Some(guild), yesterday);
+        assert_eq!((day.games, day.rated), (2, 2));
+        let (winner, winner_rating, loser, loser_rating, game_id) = day.upset.unwrap();
+        assert_eq!((winner, loser, game_id), (2, 1, 6));
+        assert!(loser_rating - winner_rating >= MIN_UPSET);
+        assert_eq!(day.riser.map(|(player, _)| player), Some(2));
+        assert_eq!(day.faller.map(|(player, _)| player), Some(1));
+
+        // Nothing since.
+        assert_eq!(activity(&games, Some(guild), now + 1), Activity::default());
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{EmojiId, GuildId},
+    },
+    prelude::*,
+    utils,
+};
+use shakmaty::Board;
+
+use crate::{
+    config,
+    modules::BotModule,
+    permissions::ADMIN_CHECK,
+    render::{self, EmojiSet, Theme, EMOJI_KEYS},
+    settings, EMBED_SIDE_COLOR,
+};
+
+#[group]
+#[commands(emojiset)]
+struct EmojiPieces;
+
+pub struct EmojiPiecesModule;
+
+impl BotModule for EmojiPiecesModule {
+    fn name(&self) -> &'static str {
+        "emojipieces"
+    }
+
+    fn description(&self) -> &'static str {
+        "Custom emoji for the emoji board theme."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&EMOJIPIECES_GROUP)
+    }
+}
+
+// Custom emoji markup as it appears in messages: <:name:id> or <a:name:id>.
+fn parse_custom_emoji(text: &str) -> Option<(String, EmojiId)> {
+    let inner = text.strip_prefix('<')?.strip_suffix('>')?;
+    let inner = inner
+        .strip_prefix("a:")
+        .or_else(|| inner.strip_prefix(':'))?;
+    let (name, id) = inner.split_once(':')?;
+    Some((name.to_string(), EmojiId(id.parse().ok()?)))
+}
+
+// Accept "K" style FEN letters as well as "light" and "dark" for the empty squares.
+fn parse_key(text: &str) -> Option<&'static str> {
+    EMOJI_KEYS
+        .iter()
+        .copied()
+        .find(|key| *key == text || (key.len() > 1 && key.eq_ignore_ascii_case(text)))
+}
+
+fn key_description(key: &str) -> String {
+    match key {
+        "light" | "dark" => format!("{} squares", key),
+        piece => {
+            let color = if piece.chars().all(char::is_uppercase) {
+                "white"
+            } else {
+                "black"
+            };
+            let name = match piece.to_lowercase().as_str() {
+                "k" => "king",
+                "q" => "queen",
+                "r" => "rook",
+                "b" => "bishop",
+                "n" => "knight",
+                _ => "pawn",
+            };
+            format!("{} {}", color, name)
+        }
+    }
+}
+
+// File stem of a piece image: wK, bn, light...
+fn image_stem(key: &str) -> String {
+    match key {
+        "light" | "dark" => key.to_string(),
+        piece if piece.chars().all(char::is_uppercase) => format!("w{}", piece),
+        piece => format!("b{}", piece.to_uppercase()),
+    }
+}
+
+// Upload the piece images in `dir` as server emoji, reusing any that were installed before.
+// Returns the finished set and how many emoji were created.
+async fn install_set(
+    ctx: &Context,
+    guild_id: GuildId,
+    dir: &Path,
+) -> serenity::Result<(EmojiSet, usize)> {
+    let existing = guild_id.emojis(&ctx.http).await?;
+    let mut set = EmojiSet::new();
+    let mut created = 0;
+
+    for key in EMOJI_KEYS.iter() {
+        let stem = image_stem(key);
+        let name = format!("chess_{}", stem);
+        let emoji = match existing.iter().find(|e| e.name == name) {
+            Some(emoji) => emoji.clone(),
+            None => {
+                let image = utils::read_image(dir.join(format!("{}.png", stem)))?;
+                created += 1;
+                guild_id.create_emoji(&ctx.http, &name, &image).await?
+            }
+        };
+        set.insert(key.to_string(), emoji.to_string());
+    }
+
+    Ok((set, created))
+}
+
+// Drop emoji from the set that no longer exist in the guild. Returns how many were removed.
+async fn prune_missing(ctx: &Context, guild_id: GuildId) -> serenity::Result<usize> {
+    let emojis = guild_id.emojis(&ctx.http).await?;
+    let present: Vec<EmojiId> = emojis.iter().map(|e| e.id).collect();
+
+    let removed = settings::update(&ctx.data, |settings| {
+        let set = &mut settings.guilds.entry(guild_id.0).or_default().emoji_pieces;
+        let before = set.len();
+        set.retain(|_, markup| {
+            parse_custom_emoji(markup).is_none_or(|(_, id)| present.contains(&id))
+        });
+        before - set.len()
+    })
+    .await;
+
+    Ok(removed)
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Use this server's emoji as chess pieces. Pieces are FEN letters (K Q R B N P for white, k q r b n p for black), plus `light` and `dark` for empty squares. Then `.servertheme emoji` to use them, or `.emojiset install` to upload the bot's own set.")]
+#[usage("<piece> <emoji> | install | list | check | clear")]
+#[example("K :white_king:")]
+async fn emojiset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let first = args.single::<String>().unwrap_or_default();
+
+    match first.to_lowercase().as_str() {
+        "" | "list" => {
+            let style = settings::board_style(&ctx.data, Some(guild_id), None).await;
+            let set = style.emoji.unwrap_or_default();
+
+            let mut desc = EMOJI_KEYS
+                .iter()
+                .map(|key| match set.get(*key) {
+                    Some(emoji) => format!("`{}` {} {}", key, emoji, key_description(key)),
+                    None => format!("`{}` (missing) {}", key, key_description(key)),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            if render::emoji_set_complete(&set) {
+                let preview = render::emoji_board(&Board::default(), &set, false);
+                desc.push_str(&format!("\n\n{}", preview));
+            } else {
+                desc.push_str("\n\nBoards fall back to Unicode pieces until every emoji is set.");
+            }
+
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.embed(|e| {
+                        e.title("Emoji pieces");
+                        e.color(EMBED_SIDE_COLOR);
+                        e.description(desc);
+                        e
+                    });
+                    m
+                })
+                .await?;
+        }
+        "install" => {
+            let config = config::get(&ctx.data).await;
+            let dir = Path::new(&config.emoji_pieces_dir);
+            let missing = EMOJI_KEYS
+                .iter()
+                .map(|key| format!("{}.png", image_stem(key)))
+                .filter(|file| !dir.join(file).is_file())
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "The piece set is incomplete, missing {} in `{}`.",
+                        missing.join(", "),
+                        dir.display()
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let (set, created) = match install_set(ctx, guild_id, dir).await {
+                Ok(installed) => installed,
+                Err(why) => {
+                    println!("Could not install emoji pieces: {:?}", why);
+                    msg.reply(&ctx.http, "Couldn't upload the piece emoji. I need the Manage Emojis permission and 14 free emoji slots.")
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            settings::update(&ctx.data, |settings| {
+                let guild = settings.guilds.entry(guild_id.0).or_default();
+                guild.emoji_pieces = set;
+                guild.theme = Some(Theme::Emoji);
+            })
+            .await;
+
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "Installed the piece emoji ({} new) and switched the server theme to emoji. See them with `.emojiset list`.",
+                    created
+                ),
+            )
+            .await?;
+        }
+        "check" => {
+            let removed = prune_missing(ctx, guild_id).await?;
+            let reply = match removed {
+                0 => "All emoji pieces are still in the server.".to_string(),
+                n => format!("Removed {} emoji that are gone from the server.", n),
+            };
+            msg.reply(&ctx.http, reply).await?;
+        }
+        "clear" => {
+            settings::update(&ctx.data, |settings| {
+                settings
+                    .guilds
+                    .entry(guild_id.0)
+                    .or_default()
+                    .emoji_pieces
+                    .clear();
+            })
+            .await;
+            msg.reply(&ctx.http, "Cleared the emoji pieces.").await?;
+        }
+        _ => {
+            let key = match parse_key(&first) {
+                Some(key) => key,
+                None => {
+                    msg.reply(&ctx.http, "Pieces are FEN letters like `K` or `n`, or `light` and `dark` for empty squares.")
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let markup = args.single::<String>().unwrap_or_default();
+            let (name, id) = match parse_custom_emoji(&markup) {
+                Some(emoji) => emoji,
+                None => {
+                    msg.reply(
+                        &ctx.http,
+                        "That needs to be one of this server's custom emoji.",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+            if guild_id.emoji(&ctx.http, id).await.is_err() {
+                msg.reply(
+                    &ctx.http,
+                    format!("`:{}:` isn't an emoji from this server.", name),
+                )
+                .await?;
+                return Ok(());
+            }
+
+            settings::update(&ctx.data, |settings| {
+                settings
+                    .guilds
+                    .entry(guild_id.0)
+                    .or_default()
+                    .emoji_pieces
+                    .insert(key.to_string(), markup.clone());
+            })
+            .await;
+
+            msg.reply(
+                &ctx.http,
+                format!("{} is now the {}.", markup, key_description(key)),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
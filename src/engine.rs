@@ -0,0 +1,513 @@
+use std::{
+    io,
+    ops::{Deref, DerefMut},
+    process::Stdio,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use rand::prelude::*;
+use serenity::prelude::*;
+use shakmaty::{uci::UciMove, CastlingMode, Chess, Position};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::{Semaphore, SemaphorePermit},
+};
+
+use crate::{
+    config::{self, EngineConfig},
+    fen,
+};
+
+// Bot strength from 1 to 8, like Lichess' AI levels.
+pub const LEVELS: std::ops::RangeInclusive<u8> = 1..=8;
+
+// The engine's "Skill Level" option and search depth for each level.
+const SKILL: [u8; 8] = [0, 2, 5, 8, 11, 14, 17, 20];
+const DEPTH: [u8; 8] = [1, 2, 3, 4, 6, 8, 12, 18];
+
+// How often each level plays a random move instead, so beginners have mistakes to punish.
+const MISTAKE_CHANCE: [f64; 8] = [0.3, 0.15, 0.05, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+// The Elo ratings the engine can hold itself to. Stockfish goes a little lower and higher in
+// newer versions, but these work with all of them.
+pub const ELOS: std::ops::RangeInclusive<u32> = 1350..=2850;
+
+// The "Skill Level" for analysis, the engine at full strength.
+const FULL_SKILL: u8 = 20;
+
+// Give up on an engine that stops answering.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// How strong the bot plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strength {
+    // One of `LEVELS`.
+    Level(u8),
+    // Roughly this rating, using the engine's own strength limit.
+    Elo(u32),
+}
+
+// A UCI engine running as a child process, like Stockfish.
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Engine {
+    // Start the configured engine and wait until it's ready to take positions.
+    pub async fn start(config: &EngineConfig) -> io::Result<Engine> {
+        let mut child = Command::new(&config.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut engine = Engine {
+            child,
+            stdin,
+            lines: BufReader::new(stdout).lines(),
+        };
+
+        engine.send("uci").await?;
+        engine.wait_for("uciok").await?;
+        if config.threads > 1 {
+            engine
+                .send(&format!("setoption name Threads value {}", config.threads))
+                .await?;
+        }
+        Ok(engine)
+    }
+
+    async fn send(&mut self, command: &str) -> io::Result<()> {
+        self.stdin.write_all(command.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await
+    }
+
+    async fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+            .await
+    }
+
+    async fn next_line(&mut self) -> io::Result<String> {
+        let line = tokio::time::timeout(TIMEOUT, self.lines.next_line())
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the engine stopped answering"))?;
+        line?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the engine quit"))
+    }
+
+    // Read lines until one starts with `prefix`, and return it.
+    async fn wait_for(&mut self, prefix: &str) -> io::Result<String> {
+        loop {
+            let line = self.next_line().await?;
+            if line.starts_with(prefix) {
+                return Ok(line);
+            }
+        }
+    }
+
+    // Engines are shared, so whether castling is written as the king taking its rook is set for
+    // every position.
+    async fn set_castling(&mut self, mode: CastlingMode) -> io::Result<()> {
+        let chess960 = mode == CastlingMode::Chess960;
+        self.set_option("UCI_Chess960", &chess960.to_string()).await
+    }
+
+    async fn set_position(&mut self, fen: Option<&str>, moves: &[String]) -> io::Result<()> {
+        let mut position = match fen {
+            Some(fen) => format!("position fen {}", fen),
+            None => "position startpos".to_string(),
+        };
+        if !moves.is_empty() {
+            position.push_str(" moves ");
+            position.push_str(&moves.join(" "));
+        }
+        self.send(&position).await
+    }
+
+    // The engine's move in UCI for the position after `moves` (in UCI) from `fen`, or from the
+    // standard start if there is no FEN. Chess960 games need their start FEN and castle with
+    // the king taking its rook. Weaker levels search less and play worse on purpose.
+    pub async fn best_move(
+        &mut self,
+        fen: Option<&str>,
+        moves: &[String],
+        strength: Strength,
+        move_time: Duration,
+        mode: CastlingMode,
+    ) -> io::Result<String> {
+        self.set_castling(mode).await?;
+        // Engines are shared, so every option a strength uses is set every time.
+        let depth = match strength {
+            Strength::Level(level) => {
+                let index = level_index(level);
+                self.set_option("UCI_LimitStrength", "false").await?;
+                self.set_option("Skill Level", &SKILL[index].to_string())
+                    .await?;
+                Some(DEPTH[index])
+            }
+            Strength::Elo(elo) => {
+                let elo = elo.clamp(*ELOS.start(), *ELOS.end());
+                self.set_option("Skill Level", &FULL_SKILL.to_string())
+                    .await?;
+                self.set_option("UCI_LimitStrength", "true").await?;
+                self.set_option("UCI_Elo", &elo.to_string()).await?;
+                None
+            }
+        };
+        self.send("isready").await?;
+        self.wait_for("readyok").await?;
+
+        self.set_position(fen, moves).await?;
+        let go = match depth {
+            Some(depth) => format!("go depth {} movetime {}", depth, move_time.as_millis()),
+            None => format!("go movetime {}", move_time.as_millis()),
+        };
+        self.send(&go).await?;
+
+        let line = self.wait_for("bestmove").await?;
+        line.split_whitespace()
+            .nth(1)
+            .filter(|m| *m != "(none)")
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the engine has no move"))
+    }
+
+    // Search the position at full strength until `depth` or `time`, whichever comes first, and
+    // return the deepest line the engine reported.
+    pub async fn analyse(
+        &mut self,
+        fen: &str,
+        depth: u32,
+        time: Duration,
+        mode: CastlingMode,
+    ) -> io::Result<Analysis> {
+        self.set_castling(mode).await?;
+        self.set_option("UCI_LimitStrength", "false").await?;
+        self.set_option("Skill Level", &FULL_SKILL.to_string())
+            .await?;
+        self.send("isready").await?;
+        self.wait_for("readyok").await?;
+
+        self.set_position(Some(fen), &[]).await?;
+        self.send(&format!("go depth {} movetime {}", depth, time.as_millis()))
+            .await?;
+
+        let mut analysis = None;
+        loop {
+            let line = self.next_line().await?;
+            if line.starts_with("bestmove") {
+                break;
+            }
+            if let Some(info) = Analysis::from_info(&line) {
+                analysis = Some(info);
+            }
+        }
+        analysis.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the engine has no line"))
+    }
+
+    pub async fn quit(mut self) {
+        if self.send("quit").await.is_err() {
+            let _ = self.child.kill().await;
+        }
+        let _ = self.child.wait().await;
+    }
+}
+
+// An evaluation, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    // In hundredths of a pawn.
+    Centipawns(i32),
+    // Mate in this many moves, negative if the side to move gets mated.
+    Mate(i32),
+}
+
+impl Score {
+    // The same score for the other side.
+    pub fn flip(self) -> Score {
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(n) => Score::Mate(-n),
+        }
+    }
+
+    // "+1.25", "-0.40", "#3" or "#-2".
+    pub fn describe(self) -> String {
+        match self {
+            Score::Centipawns(cp) => format!("{:+.2}", f64::from(cp) / 100.0),
+            Score::Mate(n) => format!("#{}", n),
+        }
+    }
+}
+
+// What the engine thinks of a position.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub depth: u32,
+    pub score: Score,
+    // The best line, in UCI.
+    pub pv: Vec<String>,
+}
+
+impl Analysis {
+    // Read a line like "info depth 20 seldepth 28 multipv 1 score cp 31 nodes ... pv e2e4 e7e5".
+    // Bound scores and lines without a pv are skipped.
+    fn from_info(line: &str) -> Option<Analysis> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("info") {
+            return None;
+        }
+        let mut depth = None;
+        let mut score = None;
+        let mut pv = Vec::new();
+        while let Some(word) = words.next() {
+            match word {
+                "depth" => depth = words.next()?.parse().ok(),
+                "score" => {
+                    let kind = words.next()?;
+                    let value: i32 = words.next()?.parse().ok()?;
+                    score = match kind {
+                        "cp" => Some(Score::Centipawns(value)),
+                        "mate" => Some(Score::Mate(value)),
+                        _ => return None,
+                    };
+                }
+                "lowerbound" | "upperbound" => return None,
+                "pv" => {
+                    pv = words.by_ref().map(str::to_string).collect();
+                }
+                _ => {}
+            }
+        }
+        if pv.is_empty() {
+            return None;
+        }
+        Some(Analysis {
+            depth: depth?,
+            score: score?,
+            pv,
+        })
+    }
+}
+
+// Engines kept running between requests, so nobody waits for one to start up, and so a burst of
+// requests can't start more than `size` at a time. The rest wait their turn.
+pub struct EnginePool {
+    idle: StdMutex<Vec<Engine>>,
+    slots: Semaphore,
+}
+
+// An engine borrowed from the pool. It goes back when dropped, unless it broke.
+pub struct PooledEngine<'a> {
+    pool: &'a EnginePool,
+    engine: Option<Engine>,
+    _slot: SemaphorePermit<'a>,
+}
+
+impl EnginePool {
+    pub fn new(size: usize) -> EnginePool {
+        EnginePool {
+            idle: StdMutex::new(Vec::new()),
+            slots: Semaphore::new(size.max(1)),
+        }
+    }
+
+    // Wait for a free engine, starting one if none are idle.
+    pub async fn get(&self, config: &EngineConfig) -> io::Result<PooledEngine<'_>> {
+        let slot = self
+            .slots
+            .acquire()
+            .await
+            .map_err(|_| io::Error::other("the engine pool is closed"))?;
+        let idle = self.idle.lock().expect("engine pool poisoned").pop();
+        let engine = match idle {
+            Some(engine) => engine,
+            None => Engine::start(config).await?,
+        };
+        Ok(PooledEngine {
+            pool: self,
+            engine: Some(engine),
+            _slot: slot,
+        })
+    }
+}
+
+impl PooledEngine<'_> {
+    // Throw the engine away instead of handing it to the next request, after it failed.
+    pub async fn discard(mut self) {
+        if let Some(engine) = self.engine.take() {
+            engine.quit().await;
+        }
+    }
+}
+
+impl Deref for PooledEngine<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        self.engine.as_ref().expect("engine taken")
+    }
+}
+
+impl DerefMut for PooledEngine<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        self.engine.as_mut().expect("engine taken")
+    }
+}
+
+impl Drop for PooledEngine<'_> {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool
+                .idle
+                .lock()
+                .expect("engine pool poisoned")
+                .push(engine);
+        }
+    }
+}
+
+pub struct EnginePoolContainer;
+
+impl TypeMapKey for EnginePoolContainer {
+    type Value = Arc<EnginePool>;
+}
+
+pub async fn pool(data: &RwLock<TypeMap>) -> Arc<EnginePool> {
+    let data = data.read().await;
+    data.get::<EnginePoolContainer>()
+        .expect("Expected engine pool in typemap.")
+        .clone()
+}
+
+fn level_index(level: u8) -> usize {
+    usize::from(level.clamp(*LEVELS.start(), *LEVELS.end()) - 1)
+}
+
+// Now and then the weakest levels play any legal move, in UCI, instead of asking the engine.
+fn random_mistake(
+    fen: Option<&str>,
+    moves: &[String],
+    level: u8,
+    mode: CastlingMode,
+) -> Option<String> {
+    let mut rng = thread_rng();
+    if !rng.gen_bool(MISTAKE_CHANCE[level_index(level)]) {
+        return None;
+    }
+    let mut pos = match fen {
+        Some(fen) => fen::parse_position(fen)?,
+        None => Chess::default(),
+    };
+    for uci in moves {
+        let m = uci.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+        pos.play_unchecked(m);
+    }
+    let m = *pos.legal_moves().choose(&mut rng)?;
+    Some(m.to_uci(mode).to_string())
+}
+
+// Ask an engine from the pool for one move.
+pub async fn best_move(
+    data: &RwLock<TypeMap>,
+    fen: Option<&str>,
+    moves: &[String],
+    strength: Strength,
+    mode: CastlingMode,
+) -> io::Result<String> {
+    if let Strength::Level(level) = strength {
+        if let Some(mistake) = random_mistake(fen, moves, level, mode) {
+            return Ok(mistake);
+        }
+    }
+    let config = config::get(data).await;
+    let pool = pool(data).await;
+    let mut engine = pool.get(&config.engine).await?;
+    let result = engine
+        .best_move(
+            fen,
+            moves,
+            strength,
+            Duration::from_millis(config.engine.move_time_ms),
+            mode,
+        )
+        .await;
+    if result.is_err() {
+        engine.discard().await;
+    }
+    result
+}
+
+// Analyse a position with an engine from the pool, as deep as the config allows.
+pub async fn analyse(data: &RwLock<TypeMap>, fen: &str) -> io::Result<Analysis> {
+    analyse_with(data, fen, CastlingMode::Standard).await
+}
+
+// The same for a position from a Chess960 game, with `CastlingMode::Chess960`.
+pub async fn analyse_with(
+    data: &RwLock<TypeMap>,
+    fen: &str,
+    mode: CastlingMode,
+) -> io::Result<Analysis> {
+    let config = config::get(data).await;
+    let pool = pool(data).await;
+    let mut engine = pool.get(&config.engine).await?;
+    let result = engine
+        .analyse(
+            fen,
+            config.engine.eval_depth,
+            Duration::from_millis(config.engine.eval_time_ms),
+            mode,
+        )
+        .await;
+    if result.is_err() {
+        engine.discard().await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_info_lines() {
+        let analysis = Analysis::from_info(
+            "info depth 20 seldepth 28 multipv 1 score cp 31 nodes 1000 nps 500 pv e2e4 e7e5 g1f3",
+        )
+        .unwrap();
+        assert_eq!(analysis.depth, 20);
+        assert_eq!(analysis.score, Score::Centipawns(31));
+        assert_eq!(analysis.pv, ["e2e4", "e7e5", "g1f3"]);
+
+        let mate = Analysis::from_info("info depth 5 score mate -2 pv h7h8").unwrap();
+        assert_eq!(mate.score, Score::Mate(-2));
+    }
+
+    #[test]
+    fn skips_partial_lines() {
+        for line in [
+            "bestmove e2e4",
+            "info depth 20 currmove e2e4 currmovenumber 1",
+            "info depth 20 score cp 31",
+            "info depth 20 score cp 31 lowerbound pv e2e4",
+            "info depth 20 score wdl 500 pv e2e4",
+            "info score cp 31 pv e2e4",
+        ] {
+            assert!(Analysis::from_info(line).is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn describes_scores() {
+        assert_eq!(Score::Centipawns(125).describe(), "+1.25");
+        assert_eq!(Score::Centipawns(-40).describe(), "-0.40");
+        assert_eq!(Score::Mate(3).flip().describe(), "#-3");
+    }
+}
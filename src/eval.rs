@@ -0,0 +1,943 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    io,
+    time::Duration,
+};
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::AttachmentType,
+    model::channel::Message,
+    prelude::*,
+};
+use shakmaty::{
+    fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, Color, EnPassantMode, Move, Position,
+};
+
+use crate::{
+    config::{self, EvalBackend},
+    diagram::{self, DiagramOptions},
+    engine::{self, Analysis, Score},
+    fen,
+    game::{self, Game},
+    history,
+    jobs::{self, Job},
+    lichess,
+    modules::BotModule,
+    notation::Notation,
+    pgn::{self, PgnGame},
+    render, settings,
+    variant::Variant,
+    web, EMBED_SIDE_COLOR,
+};
+
+// Moves of the best line shown, counting both sides.
+const LINE_PLIES: usize = 12;
+
+// Longer games aren't analysed, they'd keep an engine busy for too long.
+const MAX_ANALYSIS_PLIES: usize = 300;
+
+// How many centipawns a move has to lose to count as an inaccuracy, a mistake or a blunder.
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 200;
+
+// Mates count as this much of an advantage when working out what a move lost, and so do
+// evaluations past it, so a move from +12 to +9 isn't a blunder.
+const DECISIVE: i32 = 1000;
+
+// Mistakes listed in the summary, the worst first.
+const MISTAKES_SHOWN: usize = 10;
+
+// While this many requests wait for the engine, `.eval` asks Lichess first.
+const LONG_QUEUE: usize = 2;
+
+// Evaluations remembered, so the same position asked for again is answered right away.
+const CACHED_EVALS: usize = 500;
+
+#[group]
+#[commands(eval, analyze)]
+struct Eval;
+
+pub struct EvalModule;
+
+impl BotModule for EvalModule {
+    fn name(&self) -> &'static str {
+        "eval"
+    }
+
+    fn description(&self) -> &'static str {
+        "Engine evaluations of positions and games, see `.eval` and `.analyze`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&EVAL_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<EvalCacheContainer>(Mutex::default());
+    }
+}
+
+// Where an evaluation came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Engine,
+    Cloud,
+}
+
+#[derive(Default)]
+pub struct EvalCache {
+    evals: HashMap<String, (Analysis, Source)>,
+    order: VecDeque<String>,
+}
+
+impl EvalCache {
+    // Positions are the same whatever the move counters say.
+    fn key(fen: &str) -> String {
+        fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    fn get(&self, fen: &str) -> Option<(Analysis, Source)> {
+        self.evals.get(&EvalCache::key(fen)).cloned()
+    }
+
+    fn insert(&mut self, fen: &str, analysis: Analysis, source: Source) {
+        let key = EvalCache::key(fen);
+        if self.evals.insert(key.clone(), (analysis, source)).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CACHED_EVALS {
+            if let Some(old) = self.order.pop_front() {
+                self.evals.remove(&old);
+            }
+        }
+    }
+}
+
+pub struct EvalCacheContainer;
+
+impl TypeMapKey for EvalCacheContainer {
+    type Value = Mutex<EvalCache>;
+}
+
+async fn cached(data: &RwLock<TypeMap>, fen: &str) -> Option<(Analysis, Source)> {
+    let data = data.read().await;
+    let cache = data
+        .get::<EvalCacheContainer>()
+        .expect("Expected eval cache in typemap.")
+        .lock()
+        .await;
+    cache.get(fen)
+}
+
+async fn remember(data: &RwLock<TypeMap>, fen: &str, analysis: &Analysis, source: Source) {
+    let data = data.read().await;
+    let mut cache = data
+        .get::<EvalCacheContainer>()
+        .expect("Expected eval cache in typemap.")
+        .lock()
+        .await;
+    cache.insert(fen, analysis.clone(), source);
+}
+
+// The position a Lichess game link points at: the ply after "#" if there is one, or the end of
+// the game.
+async fn lichess_position(data: &RwLock<TypeMap>, url: &str) -> Result<Chess, String> {
+    let id = lichess::game_id_from_url(url).ok_or("That isn't a Lichess game link.")?;
+    let game = lichess::export_game(&web::client(data).await, &id)
+        .await
+        .map_err(|why| {
+            println!("Error getting Lichess game {}: {:?}", id, why);
+            "I couldn't get that game from Lichess.".to_string()
+        })?;
+    let ply = url
+        .rsplit_once('#')
+        .and_then(|(_, ply)| ply.parse::<usize>().ok());
+
+    let mut pos = Chess::default();
+    for (i, san) in game.moves.split_whitespace().enumerate() {
+        if Some(i) == ply {
+            break;
+        }
+        let m = san
+            .parse::<SanPlus>()
+            .ok()
+            .and_then(|san| san.san.to_move(&pos).ok())
+            .ok_or("I can only follow standard chess games from Lichess.")?;
+        pos.play_unchecked(m);
+    }
+    Ok(pos)
+}
+
+// The best line in SAN with move numbers, like "12... Nf6 13. e5 Nd5".
+pub fn line_text(pos: &Chess, pv: &[String], notation: Notation) -> String {
+    numbered_line(pos, &pv[..pv.len().min(LINE_PLIES)], notation)
+}
+
+// UCI moves from `pos` in SAN with move numbers, stopping at the first one that isn't legal.
+pub fn numbered_line(pos: &Chess, moves: &[String], notation: Notation) -> String {
+    let mut pos = pos.clone();
+    let mut words = Vec::new();
+    for (i, uci) in moves.iter().enumerate() {
+        let m = match uci
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|m| m.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        let san = notation.localize(&SanPlus::from_move(pos.clone(), m).to_string());
+        let number = pos.fullmoves();
+        match pos.turn() {
+            Color::White => words.push(format!("{}. {}", number, san)),
+            Color::Black if i == 0 => words.push(format!("{}... {}", number, san)),
+            Color::Black => words.push(san),
+        }
+        pos.play_unchecked(m);
+    }
+    words.join(" ")
+}
+
+// Lichess' cloud evaluation of a position, if it has one, turned around for the side to move
+// like the engine's.
+async fn cloud_analysis(data: &RwLock<TypeMap>, pos: &Chess, fen: &str) -> Option<Analysis> {
+    let eval = match lichess::cloud_eval(&web::client(data).await, fen).await {
+        Ok(eval) => eval?,
+        Err(why) => {
+            println!("Error getting a cloud evaluation: {:?}", why);
+            return None;
+        }
+    };
+    let line = eval.pvs.into_iter().next()?;
+    let score = match (line.mate, line.cp) {
+        (Some(n), _) => Score::Mate(n),
+        (None, Some(cp)) => Score::Centipawns(cp),
+        (None, None) => return None,
+    };
+    Some(Analysis {
+        depth: eval.depth,
+        score: white_score(pos, score),
+        pv: line.moves.split_whitespace().map(str::to_string).collect(),
+    })
+}
+
+// Evaluate a position with the configured backend first and the other one if that doesn't
+// work out. The engine's turn in the queue comes back too, to be marked done once the answer
+// is posted. None if neither could, after saying so.
+async fn evaluate(
+    ctx: &Context,
+    msg: &Message,
+    pos: &Chess,
+    fen: &str,
+) -> serenity::Result<Option<(Analysis, Source, Option<Job>)>> {
+    let config = config::get(&ctx.data).await;
+    let cloud_first = config.engine.eval_backend == EvalBackend::Cloud
+        || jobs::queue(&ctx.data).await.waiting() >= LONG_QUEUE;
+    if cloud_first {
+        if let Some(analysis) = cloud_analysis(&ctx.data, pos, fen).await {
+            return Ok(Some((analysis, Source::Cloud, None)));
+        }
+    }
+
+    let job = jobs::start(ctx, msg, "Evaluating the position").await?;
+    match engine::analyse(&ctx.data, fen).await {
+        Ok(analysis) => return Ok(Some((analysis, Source::Engine, Some(job)))),
+        Err(why) => println!("Engine error evaluating {}: {:?}", fen, why),
+    }
+    if !cloud_first {
+        if let Some(analysis) = cloud_analysis(&ctx.data, pos, fen).await {
+            return Ok(Some((analysis, Source::Cloud, Some(job))));
+        }
+    }
+    job.fail("The engine couldn't look at that position.").await;
+    Ok(None)
+}
+
+// A score for the side to move as seen from white's side, and the other way around.
+pub fn white_score(pos: &Chess, score: Score) -> Score {
+    match pos.turn() {
+        Color::White => score,
+        Color::Black => score.flip(),
+    }
+}
+
+// "+1.25 (white is better)", always from white's side.
+fn describe_score(pos: &Chess, analysis: &Analysis) -> String {
+    let score = white_score(pos, analysis.score);
+    let verdict = match score {
+        engine::Score::Mate(n) if n > 0 => "white mates",
+        engine::Score::Mate(_) => "black mates",
+        engine::Score::Centipawns(cp) if cp >= 150 => "white is winning",
+        engine::Score::Centipawns(cp) if cp >= 50 => "white is better",
+        engine::Score::Centipawns(cp) if cp <= -150 => "black is winning",
+        engine::Score::Centipawns(cp) if cp <= -50 => "black is better",
+        engine::Score::Centipawns(_) => "about equal",
+    };
+    format!("**{}** ({})", score.describe(), verdict)
+}
+
+#[command]
+#[description(
+    "Have the engine evaluate a position, from a FEN or a Lichess game link. Links with a move number after # are evaluated at that move, others at the end of the game."
+)]
+#[usage("<FEN or Lichess game link>")]
+#[example("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")]
+#[example("https://lichess.org/abcdEFGH#24")]
+async fn eval(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    let pos = if text.contains("lichess.org/") {
+        match lichess_position(&ctx.data, text.trim_matches(|c| c == '<' || c == '>')).await {
+            Ok(pos) => pos,
+            Err(why) => {
+                msg.reply(&ctx.http, why).await?;
+                return Ok(());
+            }
+        }
+    } else {
+        match fen::find_fen(text) {
+            Some((_, pos)) => pos,
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.eval <FEN>` or `.eval <Lichess game link>`",
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    };
+    send_eval(ctx, msg, &pos).await
+}
+
+// Evaluate a position and reply with the score and the best line.
+pub async fn send_eval(ctx: &Context, msg: &Message, pos: &Chess) -> CommandResult {
+    if pos.is_game_over() {
+        msg.reply(&ctx.http, "There's nothing left to play in that position.")
+            .await?;
+        return Ok(());
+    }
+
+    let fen = Fen::from_position(pos, EnPassantMode::Legal).to_string();
+    let (analysis, source, job) = match cached(&ctx.data, &fen).await {
+        Some((analysis, source)) => (analysis, source, None),
+        None => match evaluate(ctx, msg, pos, &fen).await? {
+            Some((analysis, source, job)) => {
+                remember(&ctx.data, &fen, &analysis, source).await;
+                (analysis, source, job)
+            }
+            None => return Ok(()),
+        },
+    };
+
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let mut desc = String::new();
+    if image.is_none() {
+        desc.push_str(&render::board_for(pos.board(), &style));
+        desc.push('\n');
+    }
+    desc.push_str(&describe_score(pos, &analysis));
+    let line = line_text(pos, &analysis.pv, notation);
+    let has_image = image.is_some();
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Evaluation");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.field("Best line", line, false);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| match source {
+                    Source::Engine => f.text(format!("Depth {} · {}", analysis.depth, fen)),
+                    Source::Cloud => f.text(format!(
+                        "Depth {} · Lichess cloud · {}",
+                        analysis.depth, fen
+                    )),
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+    if let Some(job) = job {
+        job.done().await;
+    }
+
+    Ok(())
+}
+
+// A score in centipawns for the side to move, capped at `DECISIVE`.
+fn centipawns(score: Score) -> i32 {
+    match score {
+        Score::Centipawns(cp) => cp.clamp(-DECISIVE, DECISIVE),
+        Score::Mate(n) if n > 0 => DECISIVE,
+        // Mate in 0 is the side to move being mated already.
+        Score::Mate(_) => -DECISIVE,
+    }
+}
+
+// The chance of winning, from 0 to 100, that Lichess gives a side with this score. It flattens
+// out as the score grows, so dropping a pawn matters less in a won position.
+pub fn win_percent(score: Score) -> f64 {
+    let cp = f64::from(centipawns(score));
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * cp).exp()) - 1.0)
+}
+
+// How accurate a move was from how much of the mover's winning chances it threw away, 100 for
+// best play, the way Lichess works it out.
+fn move_accuracy(win_before: f64, win_after: f64) -> f64 {
+    let drop = (win_before - win_after).max(0.0);
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn from_loss(loss: i32) -> Option<Judgement> {
+        if loss >= BLUNDER {
+            Some(Judgement::Blunder)
+        } else if loss >= MISTAKE {
+            Some(Judgement::Mistake)
+        } else if loss >= INACCURACY {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+
+    // The annotation glyph PGN uses.
+    fn glyph(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+}
+
+// What the engine made of one move.
+struct MoveReview {
+    ply: usize,
+    // The evaluation after the move, from white's side.
+    score: Score,
+    // Centipawns the move lost for the side that played it.
+    loss: i32,
+    // From 0 to 100, see `move_accuracy`.
+    accuracy: f64,
+    judgement: Option<Judgement>,
+    // The engine's move instead, in SAN, if it wasn't the one played.
+    best: Option<String>,
+}
+
+// One side's totals.
+#[derive(Default)]
+struct SideReview {
+    moves: u32,
+    loss: i32,
+    accuracies: Vec<f64>,
+    inaccuracies: u32,
+    mistakes: u32,
+    blunders: u32,
+}
+
+impl SideReview {
+    fn add(&mut self, review: &MoveReview) {
+        self.moves += 1;
+        self.loss += review.loss;
+        self.accuracies.push(review.accuracy);
+        match review.judgement {
+            Some(Judgement::Inaccuracy) => self.inaccuracies += 1,
+            Some(Judgement::Mistake) => self.mistakes += 1,
+            Some(Judgement::Blunder) => self.blunders += 1,
+            None => {}
+        }
+    }
+
+    // The side's accuracy over the game. Lichess also weighs moves by how sharp the position
+    // was; this is the mean of the plain and the harmonic average, which keeps one blunder from
+    // hiding behind a lot of easy moves.
+    fn accuracy(&self) -> Option<f64> {
+        if self.accuracies.is_empty() {
+            return None;
+        }
+        let count = self.accuracies.len() as f64;
+        let mean = self.accuracies.iter().sum::<f64>() / count;
+        let harmonic = count
+            / self
+                .accuracies
+                .iter()
+                .map(|accuracy| 1.0 / accuracy.max(1.0))
+                .sum::<f64>();
+        Some((mean + harmonic) / 2.0)
+    }
+
+    // "Accuracy 87% · average loss 34 · 2 inaccuracies, 1 mistake, 0 blunders"
+    fn describe(&self) -> String {
+        let count =
+            |n: u32, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        let accuracy = match self.accuracy() {
+            Some(accuracy) => format!("Accuracy {:.0}% · average", accuracy),
+            None => "Average".to_string(),
+        };
+        format!(
+            "{} loss {} · {}, {}, {}",
+            accuracy,
+            self.loss / self.moves.max(1) as i32,
+            count(self.inaccuracies, "inaccuracy", "inaccuracies"),
+            count(self.mistakes, "mistake", "mistakes"),
+            count(self.blunders, "blunder", "blunders")
+        )
+    }
+}
+
+// Go over every move of the game, from the evaluations of each position in it.
+fn review_moves(
+    start: &Chess,
+    moves: &[Move],
+    analyses: &[Analysis],
+    notation: Notation,
+) -> Vec<MoveReview> {
+    let mut pos = start.clone();
+    let mut reviews = Vec::new();
+    for (ply, m) in moves.iter().enumerate() {
+        let before = &analyses[ply];
+        let after = &analyses[ply + 1];
+        let mut next = pos.clone();
+        next.play_unchecked(*m);
+
+        // Both from the point of view of the side that moved.
+        let loss = (centipawns(before.score) + centipawns(after.score)).max(0);
+        let accuracy = move_accuracy(win_percent(before.score), win_percent(after.score.flip()));
+        let judgement = Judgement::from_loss(loss);
+        let best = before
+            .pv
+            .first()
+            .and_then(|uci| uci.parse::<UciMove>().ok())
+            .and_then(|uci| uci.to_move(&pos).ok())
+            .filter(|best| best != m)
+            .map(|best| notation.localize(&SanPlus::from_move(pos.clone(), best).to_string()));
+
+        reviews.push(MoveReview {
+            ply,
+            score: white_score(&next, after.score),
+            loss,
+            accuracy,
+            judgement,
+            best,
+        });
+        pos = next;
+    }
+    reviews
+}
+
+// The engine's analysis of the final position, which it can't give when the game is over.
+fn final_analysis(pos: &Chess) -> Option<Analysis> {
+    if pos.is_checkmate() {
+        Some(Analysis {
+            depth: 0,
+            score: Score::Mate(0),
+            pv: Vec::new(),
+        })
+    } else if pos.is_game_over() {
+        Some(Analysis {
+            depth: 0,
+            score: Score::Centipawns(0),
+            pv: Vec::new(),
+        })
+    } else {
+        None
+    }
+}
+
+// The game with every move's evaluation as a comment, and the mistakes marked, like Lichess
+// writes its analysed games.
+fn annotated_pgn(game: &PgnGame, reviews: &[MoveReview]) -> String {
+    let mut headers = game.headers.clone();
+    headers.push(("Annotator".to_string(), "cute-chess-bot".to_string()));
+    let sans: Vec<String> = game
+        .sans
+        .iter()
+        .zip(reviews)
+        .map(|(san, review)| {
+            let mut text = san.to_string();
+            if let Some(judgement) = review.judgement {
+                text.push_str(judgement.glyph());
+            }
+            let eval = review.score.describe().replace('+', "");
+            text.push_str(&format!(" {{[%eval {}]", eval));
+            if let (Some(judgement), Some(best)) = (review.judgement, &review.best) {
+                text.push_str(&format!(" {}. {} was best.", judgement.name(), best));
+            }
+            text.push('}');
+            text
+        })
+        .collect();
+    pgn::write(&headers, &sans, game.result())
+}
+
+// Analyse every position of a game with the same engine, more shallowly than `.eval` since
+// there are a lot of them. Progress goes to the job, if someone's waiting on one.
+async fn analyse_all(
+    data: &RwLock<TypeMap>,
+    mut job: Option<&mut Job>,
+    fens: &[String],
+) -> io::Result<Vec<Analysis>> {
+    let config = config::get(data).await;
+    let pool = engine::pool(data).await;
+    let mut engine = pool.get(&config.engine).await?;
+    let time = Duration::from_millis(config.engine.game_time_ms);
+    let mut analyses = Vec::with_capacity(fens.len());
+    for (i, fen) in fens.iter().enumerate() {
+        if let Some(job) = job.as_deref_mut() {
+            job.progress(&format!(
+                "Analyzing the game, position {} of {}…",
+                i + 1,
+                fens.len()
+            ))
+            .await;
+        }
+        match engine
+            .analyse(fen, config.engine.game_depth, time, CastlingMode::Standard)
+            .await
+        {
+            Ok(analysis) => analyses.push(analysis),
+            Err(why) => {
+                engine.discard().await;
+                return Err(why);
+            }
+        }
+    }
+    Ok(analyses)
+}
+
+// White's and black's accuracy in a standard game played here, going over it like `.analyse`
+// does. None if it can't be analysed.
+pub async fn game_accuracy(data: &RwLock<TypeMap>, game: &Game) -> Option<(f64, f64)> {
+    if game.variant != Variant::Standard
+        || game.chess960.is_some()
+        || game.start_fen.is_some()
+        || game.moves.is_empty()
+        || game.moves.len() > MAX_ANALYSIS_PLIES
+    {
+        return None;
+    }
+    let mut positions = vec![Chess::default()];
+    let mut moves = Vec::new();
+    for uci in &game.moves {
+        let pos = positions.last().expect("there's always a start position");
+        let m = uci.parse::<UciMove>().ok()?.to_move(pos).ok()?;
+        let mut next = pos.clone();
+        next.play_unchecked(m);
+        moves.push(m);
+        positions.push(next);
+    }
+
+    let last = positions.last().expect("there's always a start position");
+    let ending = final_analysis(last);
+    let searched = if ending.is_some() {
+        &positions[..positions.len() - 1]
+    } else {
+        &positions[..]
+    };
+    let fens: Vec<String> = searched
+        .iter()
+        .map(|pos| Fen::from_position(pos, EnPassantMode::Legal).to_string())
+        .collect();
+    let mut analyses = match analyse_all(data, None, &fens).await {
+        Ok(analyses) => analyses,
+        Err(why) => {
+            println!("Engine error analysing game #{}: {:?}", game.id, why);
+            return None;
+        }
+    };
+    analyses.extend(ending);
+
+    let mut white = SideReview::default();
+    let mut black = SideReview::default();
+    for review in review_moves(&positions[0], &moves, &analyses, Notation::English) {
+        if positions[review.ply].turn() == Color::White {
+            white.add(&review);
+        } else {
+            black.add(&review);
+        }
+    }
+    Some((white.accuracy()?, black.accuracy()?))
+}
+
+// A game played here by id, if it's one the author may see.
+pub async fn stored_game(data: &RwLock<TypeMap>, msg: &Message, id: u32) -> Option<Game> {
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let author = msg.author.id.0;
+    game::read_games(data, |store| {
+        store
+            .games
+            .iter()
+            .find(|game| {
+                game.id == id && (game.guild_id == guild_id || game.color_of(author).is_some())
+            })
+            .cloned()
+    })
+    .await
+}
+
+// The PGN to analyse: a game played here, pasted PGN, or an attached PGN file.
+async fn game_text(ctx: &Context, msg: &Message, arg: &str) -> Result<String, String> {
+    if arg.is_empty() {
+        let attachment = msg
+            .attachments
+            .first()
+            .ok_or("Use `.analyze [pgn] <game id or PGN>`, or attach a PGN file".to_string())?;
+        let bytes = attachment.download().await.map_err(|why| {
+            println!("Error downloading a PGN: {:?}", why);
+            "I couldn't read that file.".to_string()
+        })?;
+        return Ok(String::from_utf8_lossy(&bytes).into_owned());
+    }
+
+    match arg.trim_start_matches('#').parse::<u32>() {
+        Ok(id) => {
+            let game = stored_game(&ctx.data, msg, id)
+                .await
+                .ok_or(format!("I don't know a game #{} here.", id))?;
+            if game.chess960.is_some() || game.variant != Variant::Standard {
+                return Err("I can only analyse standard chess games.".to_string());
+            }
+            Ok(history::game_pgn(&ctx.http, &game).await)
+        }
+        Err(_) => Ok(arg.trim_matches('`').to_string()),
+    }
+}
+
+#[command]
+#[aliases("analyse")]
+#[description(
+    "Have the engine go over a whole game and point out the inaccuracies, mistakes and blunders. Give a game played here by its id, paste a PGN or attach one. Start with `pgn` to get the annotated game back as a file too."
+)]
+#[usage("[pgn] <game id or PGN>")]
+#[example("12")]
+#[example("pgn 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#")]
+async fn analyze(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut arg = args.rest().trim();
+    let with_pgn = match arg.split_whitespace().next() {
+        Some(word) if word.eq_ignore_ascii_case("pgn") => {
+            arg = arg[word.len()..].trim_start();
+            true
+        }
+        _ => false,
+    };
+
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let text = match game_text(ctx, msg, arg).await {
+        Ok(text) => text,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+    let game = match pgn::parse_in(&text, notation) {
+        Ok(game) => game,
+        Err(why) => {
+            msg.reply(&ctx.http, format!("I can't read that game: {}.", why))
+                .await?;
+            return Ok(());
+        }
+    };
+    if game.ply_count() > MAX_ANALYSIS_PLIES {
+        msg.reply(
+            &ctx.http,
+            format!(
+                "That game is too long, I analyse games of up to {} moves.",
+                MAX_ANALYSIS_PLIES / 2
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let positions: Vec<Chess> = (0..=game.ply_count())
+        .map(|ply| game.position_after(ply))
+        .collect();
+    let last = positions.last().expect("there's always a start position");
+    let ending = final_analysis(last);
+    let searched = if ending.is_some() {
+        &positions[..positions.len() - 1]
+    } else {
+        &positions[..]
+    };
+    let fens: Vec<String> = searched
+        .iter()
+        .map(|pos| Fen::from_position(pos, EnPassantMode::Legal).to_string())
+        .collect();
+
+    let mut job = jobs::start(ctx, msg, "Analyzing the game").await?;
+    let mut analyses = match analyse_all(&ctx.data, Some(&mut job), &fens).await {
+        Ok(analyses) => analyses,
+        Err(why) => {
+            println!("Engine error analysing a game: {:?}", why);
+            job.fail("The engine couldn't go over that game.").await;
+            return Ok(());
+        }
+    };
+    analyses.extend(ending);
+
+    let reviews = review_moves(&game.start, &game.moves, &analyses, notation);
+    let mut white = SideReview::default();
+    let mut black = SideReview::default();
+    for review in &reviews {
+        if positions[review.ply].turn() == Color::White {
+            white.add(review);
+        } else {
+            black.add(review);
+        }
+    }
+
+    let mut worst: Vec<&MoveReview> = reviews
+        .iter()
+        .filter(|review| review.judgement.is_some())
+        .collect();
+    worst.sort_by_key(|review| std::cmp::Reverse(review.loss));
+    worst.truncate(MISTAKES_SHOWN);
+    worst.sort_by_key(|review| review.ply);
+    let mistakes = if worst.is_empty() {
+        "None, a clean game!".to_string()
+    } else {
+        worst
+            .iter()
+            .map(|review| {
+                let judgement = review.judgement.expect("filtered on judged moves");
+                let played = game
+                    .numbered_move(None, review.ply, notation)
+                    .unwrap_or_default();
+                let before = white_score(&positions[review.ply], analyses[review.ply].score);
+                let mut line = format!(
+                    "**{}{}** {} → {}",
+                    played,
+                    judgement.glyph(),
+                    before.describe(),
+                    review.score.describe()
+                );
+                if let Some(best) = &review.best {
+                    line.push_str(&format!(", {} was best", best));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mut desc = String::new();
+    if let Some(players) = game.players() {
+        desc.push_str(&format!("**{}**\n", players));
+    }
+    if let Some(opening) = game.opening() {
+        desc.push_str(&format!("{}\n", opening));
+    }
+    desc.push_str(&format!("Result: {}", game.result()));
+    let depth = analyses
+        .iter()
+        .map(|analysis| analysis.depth)
+        .max()
+        .unwrap_or(0);
+    let annotated = if with_pgn {
+        Some(annotated_pgn(&game, &reviews))
+    } else {
+        None
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Game analysis");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.field("White", white.describe(), false);
+                e.field("Black", black.describe(), false);
+                e.field("Mistakes", mistakes, false);
+                e.footer(|f| f.text(format!("Depth {} per move", depth)));
+                e
+            });
+            m.reference_message(msg);
+            if let Some(text) = annotated {
+                m.add_file(AttachmentType::Bytes {
+                    data: Cow::Owned(text.into_bytes()),
+                    filename: "analysis.pgn".to_string(),
+                });
+            }
+            m
+        })
+        .await?;
+    job.done().await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winning_chances() {
+        assert_eq!(win_percent(Score::Centipawns(0)), 50.0);
+        assert!((win_percent(Score::Centipawns(300)) - 75.1).abs() < 0.1);
+        assert!(win_percent(Score::Mate(2)) > 97.0);
+        assert!(win_percent(Score::Mate(-2)) < 3.0);
+    }
+
+    #[test]
+    fn move_accuracies() {
+        assert!((move_accuracy(50.0, 50.0) - 100.0).abs() < 0.01);
+        // Going from equal to lost is close to nothing.
+        assert!(move_accuracy(50.0, 5.0) < 15.0);
+        // Doing better than the engine expected isn't extra credit.
+        assert_eq!(move_accuracy(50.0, 60.0), move_accuracy(50.0, 50.0));
+    }
+
+    #[test]
+    fn one_blunder_shows() {
+        let mut side = SideReview {
+            accuracies: vec![100.0; 9],
+            ..SideReview::default()
+        };
+        let clean = side.accuracy().unwrap();
+        side.accuracies.push(5.0);
+        let blundered = side.accuracy().unwrap();
+        assert!(clean > 99.0);
+        assert!(blundered < 80.0);
+        assert!(SideReview::default().accuracy().is_none());
+    }
+}
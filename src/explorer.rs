@@ -0,0 +1,689 @@
+use std::collections::{HashMap, VecDeque};
+
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::MessageId,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+use shakmaty::{
+    fen::{Epd, Fen},
+    san::San,
+    uci::UciMove,
+    CastlingMode, Chess, EnPassantMode, Position,
+};
+
+use crate::{
+    eval, fen,
+    game::{self, Game, GameResult},
+    lichess::{self, Explorer, ExplorerDb, ExplorerMove},
+    modules::BotModule,
+    notation::Notation,
+    openings, pgn,
+    render::{self, BoardStyle},
+    settings,
+    variant::Variant,
+    web, EMBED_SIDE_COLOR,
+};
+
+// Old explorers stop answering their buttons once there are more than this many.
+const MAX_VIEWS: usize = 200;
+
+const MOVES_SHOWN: usize = 8;
+const GAMES_SHOWN: usize = 4;
+
+const MOVE_ID: &str = "explorer:move";
+const BACK_ID: &str = "explorer:back";
+const DB_ID: &str = "explorer:db";
+
+#[group]
+#[commands(explorer, ourexplorer)]
+struct Explorers;
+
+pub struct ExplorerModule;
+
+#[async_trait]
+impl BotModule for ExplorerModule {
+    fn name(&self) -> &'static str {
+        "explorer"
+    }
+
+    fn description(&self) -> &'static str {
+        "The Lichess opening explorer, and one of the games played here. See `.explorer` and `.ourexplorer`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&EXPLORERS_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<ViewContainer>(Mutex::default());
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// Where someone is in the explorer: the position they opened it at and the moves played since.
+#[derive(Clone)]
+pub struct View {
+    start: Chess,
+    path: Vec<String>,
+    db: ExplorerDb,
+    // The server whose own games to look through instead of Lichess's.
+    club: Option<u64>,
+    style: BoardStyle,
+    notation: Notation,
+}
+
+impl View {
+    fn position(&self) -> Chess {
+        let mut pos = self.start.clone();
+        for uci in &self.path {
+            match uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => pos.play_unchecked(m),
+                None => break,
+            }
+        }
+        pos
+    }
+
+    async fn fetch(&self, data: &RwLock<TypeMap>) -> reqwest::Result<Explorer> {
+        if let Some(guild_id) = self.club {
+            let pos = self.position();
+            return Ok(game::read_games(data, |store| {
+                let games: Vec<&Game> = store
+                    .games
+                    .iter()
+                    .filter(|game| game.guild_id == Some(guild_id))
+                    .collect();
+                club_explorer(&games, &pos)
+            })
+            .await);
+        }
+
+        let fen = Fen::from_position(&self.start, EnPassantMode::Legal).to_string();
+        lichess::explorer(
+            &web::client(data).await,
+            self.db,
+            &fen,
+            &self.path,
+            MOVES_SHOWN,
+            GAMES_SHOWN,
+        )
+        .await
+    }
+}
+
+#[derive(Default)]
+pub struct ViewStore {
+    views: HashMap<MessageId, View>,
+    order: VecDeque<MessageId>,
+}
+
+impl ViewStore {
+    pub fn insert(&mut self, message_id: MessageId, view: View) {
+        if self.views.insert(message_id, view).is_none() {
+            self.order.push_back(message_id);
+        }
+        while self.order.len() > MAX_VIEWS {
+            if let Some(old) = self.order.pop_front() {
+                self.views.remove(&old);
+            }
+        }
+    }
+}
+
+pub struct ViewContainer;
+
+impl TypeMapKey for ViewContainer {
+    type Value = Mutex<ViewStore>;
+}
+
+// How the position went in games played here, answered like the Lichess explorer would. A game
+// counts once, from the first time it reached the position however it got there. Only finished
+// standard games from the usual start are looked at.
+fn club_explorer(games: &[&Game], target: &Chess) -> Explorer {
+    let target = Epd::from_position(target, EnPassantMode::Legal);
+    let mut explorer = Explorer {
+        white: 0,
+        draws: 0,
+        black: 0,
+        moves: Vec::new(),
+        top_games: Vec::new(),
+        opening: None,
+    };
+
+    for game in games {
+        let confirmed = game
+            .recorded
+            .as_ref()
+            .is_none_or(|recorded| recorded.confirmed);
+        let counted = game.variant == Variant::Standard
+            && game.chess960.is_none()
+            && game.start_fen.is_none()
+            && confirmed;
+        let result = match game.result {
+            Some(result) if counted && result != GameResult::Aborted => result,
+            _ => continue,
+        };
+
+        let mut pos = Chess::default();
+        let mut reached = None;
+        for ply in 0..=game.moves.len() {
+            if Epd::from_position(&pos, EnPassantMode::Legal) == target {
+                reached = Some(ply);
+                break;
+            }
+            let m = match game.moves.get(ply).and_then(|uci| {
+                uci.parse::<UciMove>()
+                    .ok()
+                    .and_then(|uci| uci.to_move(&pos).ok())
+            }) {
+                Some(m) => m,
+                None => break,
+            };
+            pos.play_unchecked(m);
+        }
+        let ply = match reached {
+            Some(ply) => ply,
+            None => continue,
+        };
+
+        let score = |white: &mut u64, draws: &mut u64, black: &mut u64| match result {
+            GameResult::WhiteWon => *white += 1,
+            GameResult::BlackWon => *black += 1,
+            _ => *draws += 1,
+        };
+        score(
+            &mut explorer.white,
+            &mut explorer.draws,
+            &mut explorer.black,
+        );
+
+        let next = game.moves.get(ply).and_then(|uci| {
+            let m = uci.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+            Some((m.to_uci(CastlingMode::Standard).to_string(), m))
+        });
+        let (uci, m) = match next {
+            Some(next) => next,
+            None => continue,
+        };
+        let index = match explorer.moves.iter().position(|known| known.uci == uci) {
+            Some(index) => index,
+            None => {
+                explorer.moves.push(ExplorerMove {
+                    uci,
+                    san: San::from_move(&pos, m).to_string(),
+                    white: 0,
+                    draws: 0,
+                    black: 0,
+                });
+                explorer.moves.len() - 1
+            }
+        };
+        let known = &mut explorer.moves[index];
+        score(&mut known.white, &mut known.draws, &mut known.black);
+    }
+
+    explorer
+        .moves
+        .sort_by_key(|m| std::cmp::Reverse(m.white + m.draws + m.black));
+    explorer.moves.truncate(MOVES_SHOWN);
+    explorer
+}
+
+// "1.2M", "34k" or "812" games.
+fn count_text(count: u64) -> String {
+    if count >= 1_000_000 {
+        format!("{:.1}M", count as f64 / 1_000_000.0)
+    } else if count >= 10_000 {
+        format!("{}k", count / 1000)
+    } else {
+        count.to_string()
+    }
+}
+
+// "⚪ 33% · ½ 40% · ⚫ 27%"
+fn results_text(white: u64, draws: u64, black: u64) -> String {
+    let total = (white + draws + black).max(1) as f64;
+    let percent = |count: u64| (count as f64 * 100.0 / total).round();
+    format!(
+        "⚪ {}% · ½ {}% · ⚫ {}%",
+        percent(white),
+        percent(draws),
+        percent(black)
+    )
+}
+
+fn explorer_embed<'a>(
+    e: &'a mut CreateEmbed,
+    view: &View,
+    explorer: &Explorer,
+) -> &'a mut CreateEmbed {
+    let pos = view.position();
+    let title = match &explorer.opening {
+        Some(opening) => format!("{} {}", opening.eco, opening.name),
+        None => {
+            let moves: Vec<_> = view
+                .path
+                .iter()
+                .filter_map(|uci| uci.parse::<UciMove>().ok())
+                .scan(view.start.clone(), |pos, uci| {
+                    let m = uci.to_move(pos).ok()?;
+                    pos.play_unchecked(m);
+                    Some(m)
+                })
+                .collect();
+            openings::identify(&view.start, &moves)
+                .map(|(_, opening)| opening.describe())
+                .unwrap_or_else(|| "Opening explorer".to_string())
+        }
+    };
+
+    let mut desc = render::board_for(pos.board(), &view.style);
+    let line = eval::numbered_line(&view.start, &view.path, view.notation);
+    if line.is_empty() {
+        desc.push_str("\n**Starting position**");
+    } else {
+        desc.push_str(&format!("\n**{}**", line));
+    }
+    desc.push('\n');
+    if explorer.moves.is_empty() {
+        desc.push_str("\nNo games reached this position.");
+    }
+    for m in &explorer.moves {
+        desc.push_str(&format!(
+            "\n**{}** · {} games · {}",
+            view.notation.localize(&m.san),
+            count_text(m.white + m.draws + m.black),
+            results_text(m.white, m.draws, m.black)
+        ));
+    }
+
+    e.title(title);
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    if !explorer.top_games.is_empty() {
+        let games = explorer
+            .top_games
+            .iter()
+            .map(|game| {
+                let player = |player: &lichess::ExplorerPlayer| match player.rating {
+                    Some(rating) => format!("{} ({})", player.name, rating),
+                    None => player.name.clone(),
+                };
+                let result = match game.winner.as_deref() {
+                    Some("white") => "1-0",
+                    Some("black") => "0-1",
+                    _ => "½-½",
+                };
+                let year = game.year.map(|y| format!(", {}", y)).unwrap_or_default();
+                format!(
+                    "[{} {} {}{}](https://lichess.org/{})",
+                    player(&game.white),
+                    result,
+                    player(&game.black),
+                    year,
+                    game.id
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        e.field("Top games", games, false);
+    }
+    e.footer(|f| {
+        f.text(format!(
+            "{} · {} games · {}",
+            if view.club.is_some() {
+                "This server's games"
+            } else {
+                view.db.name()
+            },
+            count_text(explorer.white + explorer.draws + explorer.black),
+            results_text(explorer.white, explorer.draws, explorer.black)
+        ));
+        f
+    });
+    e
+}
+
+fn explorer_buttons<'a>(
+    c: &'a mut CreateComponents,
+    view: &View,
+    explorer: &Explorer,
+) -> &'a mut CreateComponents {
+    if !explorer.moves.is_empty() {
+        c.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id(MOVE_ID);
+                menu.placeholder("Play a move");
+                menu.options(|options| {
+                    for m in &explorer.moves {
+                        options.create_option(|o| {
+                            o.label(view.notation.localize(&m.san));
+                            o.description(format!(
+                                "{} games · {}",
+                                count_text(m.white + m.draws + m.black),
+                                results_text(m.white, m.draws, m.black)
+                            ));
+                            o.value(&m.uci);
+                            o
+                        });
+                    }
+                    options
+                })
+            })
+        });
+    }
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("◀ Back");
+            b.custom_id(BACK_ID);
+            b.disabled(view.path.is_empty());
+            b
+        });
+        if view.club.is_some() {
+            return row;
+        }
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label(match view.db {
+                ExplorerDb::Masters => "Lichess games",
+                ExplorerDb::Lichess => "Masters",
+            });
+            b.custom_id(DB_ID);
+            b
+        })
+    });
+    c
+}
+
+// Move around the explorer when its menu or buttons are used. Returns false if they aren't ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if ![MOVE_ID, BACK_ID, DB_ID].contains(&id) {
+        return false;
+    }
+
+    let view = {
+        let data = ctx.data.read().await;
+        let store = data
+            .get::<ViewContainer>()
+            .expect("Expected explorers in typemap.")
+            .lock()
+            .await;
+        store.views.get(&component.message.id).cloned()
+    };
+    let mut view = match view {
+        Some(view) => view,
+        None => {
+            let result = component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage);
+                    r.interaction_response_data(|d| {
+                        d.content("This explorer has expired, use `.explorer` again.");
+                        d.components(|c| c)
+                    })
+                })
+                .await;
+            if let Err(why) = result {
+                println!("Error updating explorer: {:?}", why);
+            }
+            return true;
+        }
+    };
+
+    match id {
+        MOVE_ID => {
+            let pos = view.position();
+            let choice = component.data.values.first().and_then(|value| {
+                let m = value.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+                Some(m.to_uci(CastlingMode::Standard).to_string())
+            });
+            if let Some(uci) = choice {
+                view.path.push(uci);
+            }
+        }
+        BACK_ID => {
+            view.path.pop();
+        }
+        DB_ID => {
+            view.db = match view.db {
+                ExplorerDb::Masters => ExplorerDb::Lichess,
+                ExplorerDb::Lichess => ExplorerDb::Masters,
+            };
+        }
+        _ => {}
+    }
+
+    // The explorer can take a moment, longer than Discord waits for an answer.
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating explorer: {:?}", why);
+        return true;
+    }
+
+    let explorer = match view.fetch(&ctx.data).await {
+        Ok(explorer) => explorer,
+        Err(why) => {
+            println!("Error getting the opening explorer: {:?}", why);
+            let result = component
+                .create_followup_message(&ctx.http, |f| {
+                    f.content("The Lichess explorer didn't answer, try again in a bit.");
+                    f.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    f
+                })
+                .await;
+            if let Err(why) = result {
+                println!("Error updating explorer: {:?}", why);
+            }
+            return true;
+        }
+    };
+
+    let result = component
+        .edit_original_interaction_response(&ctx.http, |r| {
+            r.create_embed(|e| explorer_embed(e, &view, &explorer));
+            r.components(|c| explorer_buttons(c, &view, &explorer));
+            r
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating explorer: {:?}", why);
+    }
+
+    let data = ctx.data.read().await;
+    data.get::<ViewContainer>()
+        .expect("Expected explorers in typemap.")
+        .lock()
+        .await
+        .insert(component.message.id, view);
+
+    true
+}
+
+#[command]
+#[aliases("explore")]
+#[description(
+    "Look up a position in the Lichess opening explorer: the most played moves, how they scored and the best games. Masters games by default, add `lichess` for games played on Lichess."
+)]
+#[usage("[masters|lichess] [moves | FEN]")]
+#[example("e4 c5 Nf3")]
+#[example("lichess rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")]
+async fn explorer(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut text = args.rest().trim();
+    let mut db = ExplorerDb::Masters;
+    if let Some(word) = text.split_whitespace().next() {
+        let picked = match word.to_lowercase().as_str() {
+            "masters" => Some(ExplorerDb::Masters),
+            "lichess" => Some(ExplorerDb::Lichess),
+            _ => None,
+        };
+        if let Some(picked) = picked {
+            db = picked;
+            text = text[word.len()..].trim_start();
+        }
+    }
+    open_explorer(ctx, msg, text, db, None).await
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("clubexplorer")]
+#[description(
+    "Like `.explorer`, but with the games played in this server: what we play from a position and how it scores for us."
+)]
+#[usage("[moves | FEN]")]
+#[example("e4 c5")]
+async fn ourexplorer(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)").0;
+    open_explorer(
+        ctx,
+        msg,
+        args.rest().trim(),
+        ExplorerDb::Masters,
+        Some(guild_id),
+    )
+    .await
+}
+
+// Post an explorer at the position after the moves or FEN in `text`.
+async fn open_explorer(
+    ctx: &Context,
+    msg: &Message,
+    text: &str,
+    db: ExplorerDb,
+    club: Option<u64>,
+) -> CommandResult {
+    let text = text.trim_matches('`');
+
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let (start, path) = if text.is_empty() {
+        (Chess::default(), Vec::new())
+    } else if let Some(pos) = fen::parse_position(text) {
+        (pos, Vec::new())
+    } else {
+        match pgn::parse_in(text, notation) {
+            Ok(game) => {
+                let path = game
+                    .moves
+                    .iter()
+                    .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                    .collect();
+                (game.start, path)
+            }
+            Err(why) => {
+                msg.reply(
+                    &ctx.http,
+                    format!("That isn't a FEN, and I can't read those moves: {}.", why),
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let view = View {
+        start,
+        path,
+        db,
+        club,
+        style: settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await,
+        notation,
+    };
+    let explorer = match view.fetch(&ctx.data).await {
+        Ok(explorer) => explorer,
+        Err(why) => {
+            println!("Error getting the opening explorer: {:?}", why);
+            msg.reply(
+                &ctx.http,
+                "The Lichess explorer didn't answer, try again in a bit.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| explorer_embed(e, &view, &explorer));
+            m.components(|c| explorer_buttons(c, &view, &explorer));
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    let data = ctx.data.read().await;
+    data.get::<ViewContainer>()
+        .expect("Expected explorers in typemap.")
+        .lock()
+        .await
+        .insert(sent.id, view);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameOptions;
+
+    fn game(moves: &[&str], result: GameResult) -> Game {
+        Game {
+            moves: moves.iter().map(|m| m.to_string()).collect(),
+            result: Some(result),
+            ..GameOptions::default().new_game(Some(10), 20, (1, 2))
+        }
+    }
+
+    #[test]
+    fn club_continuations() {
+        let games = [
+            game(&["e2e4", "c7c5", "g1f3", "d7d6"], GameResult::WhiteWon),
+            game(&["e2e4", "c7c5", "g1f3", "b8c6"], GameResult::Draw),
+            game(&["e2e4", "c7c5", "g1f3", "d7d6"], GameResult::BlackWon),
+            game(&["e2e4", "c7c5", "b1c3"], GameResult::WhiteWon),
+            game(&["e2e4", "c7c5", "g1f3"], GameResult::Aborted),
+            // Gets there in another order, and still counts.
+            game(&["g1f3", "c7c5", "e2e4", "d7d6"], GameResult::WhiteWon),
+        ];
+        let games: Vec<&Game> = games.iter().collect();
+        let mut pos = Chess::default();
+        for uci in ["e2e4", "c7c5", "g1f3"] {
+            let m = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+            pos.play_unchecked(m);
+        }
+
+        let explorer = club_explorer(&games, &pos);
+        assert_eq!((explorer.white, explorer.draws, explorer.black), (2, 1, 1));
+        let moves: Vec<_> = explorer
+            .moves
+            .iter()
+            .map(|m| (m.san.as_str(), m.white + m.draws + m.black))
+            .collect();
+        assert_eq!(moves, [("d6", 3), ("Nc6", 1)]);
+    }
+}
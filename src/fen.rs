@@ -0,0 +1,461 @@
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+use shakmaty::{
+    fen::{Fen, ParseFenError},
+    CastlingMode, CastlingSide, Chess, Color, EnPassantMode, Piece, Position, PositionErrorKinds,
+    Role,
+};
+
+use crate::{
+    diagram::{self, DiagramOptions},
+    modules::{on_off, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    render, settings, EMBED_SIDE_COLOR,
+};
+
+#[group]
+#[commands(fen, fenrender)]
+struct Fens;
+
+pub struct FensModule;
+
+const FEN_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Diagrams for pasted FENs",
+    command: "fenrender",
+    value: |guild| on_off(guild.render_fens),
+}];
+
+#[async_trait]
+impl BotModule for FensModule {
+    fn name(&self) -> &'static str {
+        "fens"
+    }
+
+    fn description(&self) -> &'static str {
+        "Diagrams for FENs, with `.fen` or automatically when posted in chat."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&FENS_GROUP)
+    }
+
+    async fn normal_message(&self, ctx: &Context, msg: &Message) {
+        render_detected(ctx, msg).await;
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        FEN_SETTINGS
+    }
+}
+
+// Parse a FEN into a position, accepting Chess960 castling rights too.
+pub fn parse_position(text: &str) -> Option<Chess> {
+    let fen: Fen = text.parse().ok()?;
+    match fen.clone().into_position(CastlingMode::Standard) {
+        Ok(pos) => Some(pos),
+        Err(_) => fen.into_position(CastlingMode::Chess960).ok(),
+    }
+}
+
+// What's wrong with the board part of a FEN, if anything we can point at.
+fn board_problem(board: &str) -> Option<String> {
+    let ranks: Vec<&str> = board.split('/').collect();
+    if ranks.len() != 8 {
+        return Some(format!(
+            "The board needs 8 ranks separated by `/`, this one has {}.",
+            ranks.len()
+        ));
+    }
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut squares = 0;
+        for c in rank.chars() {
+            match c.to_digit(10) {
+                Some(n) => squares += n,
+                None if "pnbrqkPNBRQK".contains(c) => squares += 1,
+                None => {
+                    return Some(format!(
+                        "`{}` isn't a piece. Pieces are `PNBRQK` for White and `pnbrqk` for Black, and numbers count empty squares.",
+                        c
+                    ))
+                }
+            }
+        }
+        if squares != 8 {
+            return Some(format!(
+                "Rank {} (`{}`) adds up to {} squares instead of 8.",
+                8 - i,
+                rank,
+                squares
+            ));
+        }
+    }
+    None
+}
+
+pub fn fen_error_text(text: &str, why: ParseFenError) -> String {
+    let board = text.split_whitespace().next().unwrap_or("");
+    match why {
+        ParseFenError::InvalidBoard | ParseFenError::InvalidFen => {
+            board_problem(board).unwrap_or_else(|| "That doesn't look like a FEN.".to_string())
+        }
+        ParseFenError::InvalidTurn => {
+            "The side to move, after the board, has to be `w` or `b`.".to_string()
+        }
+        ParseFenError::InvalidCastling => {
+            "Castling rights are written like `KQkq`, or `-` if nobody can castle.".to_string()
+        }
+        ParseFenError::InvalidEpSquare => {
+            "The en passant square has to be a square like `e3`, or `-`.".to_string()
+        }
+        ParseFenError::InvalidHalfmoveClock | ParseFenError::InvalidFullmoves => {
+            "The two move counters at the end have to be numbers.".to_string()
+        }
+        ParseFenError::InvalidPocket | ParseFenError::InvalidRemainingChecks => {
+            "That FEN has parts only chess variants use.".to_string()
+        }
+    }
+}
+
+fn position_error_text(kinds: PositionErrorKinds) -> String {
+    let reasons = [
+        (PositionErrorKinds::EMPTY_BOARD, "the board is empty"),
+        (PositionErrorKinds::MISSING_KING, "each side needs a king"),
+        (
+            PositionErrorKinds::TOO_MANY_KINGS,
+            "a side has more than one king",
+        ),
+        (
+            PositionErrorKinds::PAWNS_ON_BACKRANK,
+            "there are pawns on the first or last rank",
+        ),
+        (
+            PositionErrorKinds::INVALID_CASTLING_RIGHTS,
+            "the castling rights don't match where the kings and rooks are",
+        ),
+        (
+            PositionErrorKinds::INVALID_EP_SQUARE,
+            "the en passant square isn't behind a pawn that just moved two squares",
+        ),
+        (
+            PositionErrorKinds::OPPOSITE_CHECK,
+            "the side that just moved is still in check",
+        ),
+        (
+            PositionErrorKinds::IMPOSSIBLE_CHECK,
+            "the king is in a check no move could have given",
+        ),
+        (
+            PositionErrorKinds::TOO_MUCH_MATERIAL,
+            "there are more pieces than a game can have",
+        ),
+    ];
+    let found: Vec<&str> = reasons
+        .iter()
+        .filter(|(kind, _)| kinds.contains(*kind))
+        .map(|(_, reason)| *reason)
+        .collect();
+    if found.is_empty() {
+        return "That FEN reads fine, but it isn't a legal chess position.".to_string();
+    }
+    format!("That isn't a legal position: {}.", found.join(", and "))
+}
+
+// Parse a FEN like `parse_position`, but explain what's wrong when it doesn't work.
+pub fn check_fen(text: &str) -> Result<Chess, String> {
+    let fen: Fen = text.parse().map_err(|why| fen_error_text(text, why))?;
+    match fen.clone().into_position(CastlingMode::Standard) {
+        Ok(pos) => Ok(pos),
+        Err(why) => fen
+            .into_position(CastlingMode::Chess960)
+            .map_err(|_| position_error_text(why.kinds())),
+    }
+}
+
+// "**White to move**, in check", or how the game is over.
+pub fn status_text(pos: &Chess) -> String {
+    let to_move = if pos.turn() == Color::White {
+        "White"
+    } else {
+        "Black"
+    };
+    if pos.is_checkmate() {
+        format!("**{} is checkmated**", to_move)
+    } else if pos.is_stalemate() {
+        format!("**{} is stalemated**", to_move)
+    } else if pos.is_check() {
+        format!("**{} to move**, in check", to_move)
+    } else {
+        format!("**{} to move**", to_move)
+    }
+}
+
+// "White can castle both ways, Black kingside"
+fn castling_text(pos: &Chess) -> String {
+    let castles = pos.castles();
+    let side = |color: Color| match (
+        castles.has(color, CastlingSide::KingSide),
+        castles.has(color, CastlingSide::QueenSide),
+    ) {
+        (true, true) => "both ways",
+        (true, false) => "kingside",
+        (false, true) => "queenside",
+        (false, false) => "not at all",
+    };
+    format!(
+        "White can castle {}, Black {}",
+        side(Color::White),
+        side(Color::Black)
+    )
+}
+
+// "Q 2R B 6P" and its value in pawns, for one side.
+fn material(pos: &Chess, color: Color) -> (String, u32) {
+    let roles = [
+        (Role::Queen, 9),
+        (Role::Rook, 5),
+        (Role::Bishop, 3),
+        (Role::Knight, 3),
+        (Role::Pawn, 1),
+    ];
+    let mut pieces = Vec::new();
+    let mut points = 0;
+    for &(role, value) in &roles {
+        let count = pos.board().by_piece(Piece { color, role }).count() as u32;
+        points += count * value;
+        match count {
+            0 => {}
+            1 => pieces.push(role.upper_char().to_string()),
+            count => pieces.push(format!("{}{}", count, role.upper_char())),
+        }
+    }
+    if pieces.is_empty() {
+        pieces.push("K".to_string());
+    }
+    (pieces.join(" "), points)
+}
+
+fn material_text(pos: &Chess) -> String {
+    let (white, white_points) = material(pos, Color::White);
+    let (black, black_points) = material(pos, Color::Black);
+    let balance = if white_points > black_points {
+        format!("White is up {}", white_points - black_points)
+    } else if black_points > white_points {
+        format!("Black is up {}", black_points - white_points)
+    } else {
+        "even".to_string()
+    };
+    format!(
+        "White {} ({}) · Black {} ({}), {}",
+        white, white_points, black, black_points, balance
+    )
+}
+
+// Whether a word could be the board part of a FEN, like "rnbqkbnr/pppppppp/8/...".
+fn looks_like_board(word: &str) -> bool {
+    word.split('/').count() == 8
+        && word
+            .chars()
+            .all(|c| c == '/' || ('1'..='8').contains(&c) || "pnbrqkPNBRQK".contains(c))
+}
+
+// Find the first legal FEN in a message. Trailing fields are optional, so the longest version
+// that parses wins.
+pub fn find_fen(text: &str) -> Option<(String, Chess)> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    for (start, word) in words.iter().enumerate() {
+        let word = word.trim_matches('`');
+        if !looks_like_board(word) {
+            continue;
+        }
+
+        let end = (start + 6).min(words.len());
+        for len in (1..=end - start).rev() {
+            let mut candidate = words[start..start + len].join(" ");
+            candidate = candidate.trim_matches('`').to_string();
+            if let Some(pos) = parse_position(&candidate) {
+                return Some((candidate, pos));
+            }
+        }
+    }
+
+    None
+}
+
+// Reply with a diagram when someone posts a FEN, in guilds that turned it on.
+pub async fn render_detected(ctx: &Context, msg: &Message) {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    if !settings::guild(&ctx.data, guild_id).await.render_fens {
+        return;
+    }
+
+    let (fen, pos) = match find_fen(&msg.content) {
+        Some(found) => found,
+        None => return,
+    };
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let to_move = if pos.turn() == Color::White {
+        "White"
+    } else {
+        "Black"
+    };
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let desc = match image {
+        Some(_) => format!("{} to move", to_move),
+        None => format!(
+            "{}\n{} to move",
+            render::board_for(pos.board(), &style),
+            to_move
+        ),
+    };
+    let has_image = image.is_some();
+
+    let result = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| {
+                    f.text(fen);
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await;
+
+    if let Err(why) = result {
+        println!("Error rendering detected FEN: {:?}", why);
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Turn automatic diagrams for FENs posted in chat on or off.")]
+#[usage("<on|off>")]
+async fn fenrender(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let enabled = match args.current().and_then(settings::parse_toggle) {
+        Some(enabled) => enabled,
+        None => {
+            msg.reply(&ctx.http, "Use `.fenrender on` or `.fenrender off`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().render_fens = enabled;
+    })
+    .await;
+
+    let reply = if enabled {
+        "I'll draw any FEN posted in this server."
+    } else {
+        "I'll stop drawing FENs posted in this server."
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Draw a FEN and sum it up: side to move, castling rights and material. If the FEN doesn't work, I'll say what's wrong with it."
+)]
+#[usage("<fen>")]
+#[example("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")]
+async fn fen(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim().trim_matches('`').trim();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.fen <fen>`").await?;
+        return Ok(());
+    }
+    let pos = match check_fen(text) {
+        Ok(pos) => pos,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    let mut summary = format!(
+        "{}\nCastling: {}\nMaterial: {}",
+        status_text(&pos),
+        castling_text(&pos),
+        material_text(&pos)
+    );
+    if let Some(square) = pos.legal_ep_square() {
+        summary.push_str(&format!("\nEn passant on {}", square));
+    }
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let desc = match image {
+        Some(_) => summary,
+        None => format!("{}\n{}", render::board_for(pos.board(), &style), summary),
+    };
+    let has_image = image.is_some();
+    let fen = Fen::from_position(&pos, EnPassantMode::Legal).to_string();
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| {
+                    f.text(fen);
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
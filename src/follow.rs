@@ -0,0 +1,389 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, UserId},
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    accounts,
+    config::Config,
+    lichess,
+    modules::{self, BotModule},
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings, web, EMBED_SIDE_COLOR,
+};
+
+// Don't post a backlog of more games than this in one go.
+const MAX_GAMES_PER_POLL: u32 = 5;
+
+// Lichess statuses of games that can end without a winner. Running out of time against someone
+// who can't mate is a draw too.
+const DRAW_STATUSES: &[&str] = &["draw", "stalemate", "outoftime", "timeout"];
+
+// Wins against players rated this much higher get called out.
+const UPSET_RATING_GAP: u32 = 200;
+
+#[group]
+#[commands(follow, unfollow, following)]
+struct Follows;
+
+pub struct FollowsModule;
+
+#[async_trait]
+impl BotModule for FollowsModule {
+    fn name(&self) -> &'static str {
+        "follows"
+    }
+
+    fn description(&self) -> &'static str {
+        "New games of followed Lichess players."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&FOLLOWS_GROUP)
+    }
+
+    fn jobs(&self, config: &Config) -> Vec<(JobKind, String)> {
+        vec![(
+            JobKind::FollowPoll,
+            format!("*/{} * * * *", config.follow_poll_minutes.clamp(1, 59)),
+        )]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::FollowPoll {
+            poll_all(http, data).await;
+        }
+    }
+}
+
+// A channel following a player's games.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Follow {
+    pub channel_id: u64,
+    pub username: String,
+    // Games that started before this (milliseconds since the epoch) were already posted.
+    pub since: u64,
+}
+
+// "lichess/DrNykterstein" or just "DrNykterstein".
+fn parse_player(arg: &str) -> Result<String, &'static str> {
+    let (site, username) = match arg.split_once('/') {
+        Some((site, username)) => (site.to_lowercase(), username),
+        None => ("lichess".to_string(), arg),
+    };
+
+    if site != "lichess" {
+        return Err(
+            "Only Lichess players can be followed for now, like `.follow lichess/DrNykterstein`",
+        );
+    }
+    if !lichess::is_username(username) {
+        return Err("That doesn't look like a Lichess username.");
+    }
+
+    Ok(username.to_string())
+}
+
+// A player by name, or a member by the account they linked.
+async fn find_player(data: &RwLock<TypeMap>, arg: &str) -> Result<String, String> {
+    match utils::parse_username(arg) {
+        Some(user_id) => accounts::lichess_username(data, UserId(user_id))
+            .await
+            .ok_or_else(|| "They haven't linked a Lichess account with `.link`.".to_string()),
+        None => parse_player(arg).map_err(str::to_string),
+    }
+}
+
+// Check followed players for new games. The scheduler runs this every few minutes.
+pub async fn poll_all(http: &Http, data: &RwLock<TypeMap>) {
+    let follows = settings::read(data, |settings| settings.follows.clone()).await;
+    let client = web::client(data).await;
+    // Several players are often followed in the same channel.
+    let mut enabled = HashMap::new();
+    for follow in follows {
+        let channel_id = ChannelId(follow.channel_id);
+        let on = match enabled.get(&channel_id) {
+            Some(on) => *on,
+            None => {
+                let on = modules::enabled_in(http, data, channel_id, &FollowsModule).await;
+                enabled.insert(channel_id, on);
+                on
+            }
+        };
+        if !on {
+            continue;
+        }
+        if let Err(why) = poll(http, data, &client, &follow).await {
+            println!("Error checking games of {}: {:?}", follow.username, why);
+        }
+    }
+}
+
+async fn poll(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    client: &reqwest::Client,
+    follow: &Follow,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let games =
+        lichess::user_games_since(client, &follow.username, follow.since, MAX_GAMES_PER_POLL)
+            .await?;
+
+    // Games posted before an error are saved as seen, so the next poll doesn't repeat them.
+    let mut since = follow.since;
+    let mut result = Ok(());
+    for game in &games {
+        if let Err(why) =
+            post_game(http, ChannelId(follow.channel_id), &follow.username, game).await
+        {
+            result = Err(why.into());
+            break;
+        }
+        if let Some(created_at) = game.created_at {
+            since = since.max(created_at + 1);
+        }
+    }
+
+    if since != follow.since {
+        settings::update(data, |settings| {
+            let stored = settings
+                .follows
+                .iter_mut()
+                .find(|f| f.channel_id == follow.channel_id && f.username == follow.username);
+            if let Some(stored) = stored {
+                stored.since = since;
+            }
+        })
+        .await;
+    }
+
+    result
+}
+
+// How the game went for the followed player. None for aborted games and results that can't be
+// placed, which aren't worth a post.
+fn outcome(game: &lichess::Game, color: Option<&str>) -> Option<&'static str> {
+    match (game.winner.as_deref(), color) {
+        (Some(winner), Some(color)) if winner == color => Some("won against"),
+        (Some(_), Some(_)) => Some("lost to"),
+        (None, _) if DRAW_STATUSES.contains(&game.status.as_str()) => Some("drew with"),
+        _ => None,
+    }
+}
+
+async fn post_game(
+    http: &Http,
+    channel_id: ChannelId,
+    username: &str,
+    game: &lichess::Game,
+) -> serenity::Result<()> {
+    let color = game.players.color_of(username);
+    let (player, opponent) = match color {
+        Some("black") => (&game.players.black, &game.players.white),
+        _ => (&game.players.white, &game.players.black),
+    };
+
+    let outcome = match outcome(game, color) {
+        Some(outcome) => outcome,
+        None => return Ok(()),
+    };
+
+    // Beating someone much stronger, or winning quickly, is worth pointing out.
+    let upset = match (player.rating, opponent.rating) {
+        (Some(mine), Some(theirs)) => outcome == "won against" && theirs >= mine + UPSET_RATING_GAP,
+        _ => false,
+    };
+    let moves = game.moves.split_whitespace().count().div_ceil(2);
+    let miniature = outcome == "won against" && moves <= 25 && game.status == "mate";
+
+    let mut title = format!(
+        "{} {} {}",
+        player.display_name(),
+        outcome,
+        opponent.display_name()
+    );
+    if upset {
+        title = format!(":fire: Upset! {}", title);
+    } else if miniature {
+        title = format!(":zap: Miniature! {}", title);
+    }
+
+    let opening = game
+        .opening
+        .as_ref()
+        .map(|o| format!("{} {}", o.eco, o.name))
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    channel_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.url(game.url());
+                e.color(EMBED_SIDE_COLOR);
+                e.field("Result", game.result(), true);
+                e.field("Game", &game.speed, true);
+                e.field("Moves", moves, true);
+                e.field("Opening", opening, false);
+                e.thumbnail(game.thumbnail_url());
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Post a Lichess player's finished games in this channel. Mention a member to follow the account they linked."
+)]
+#[usage("<lichess/<username> | @member>")]
+#[example("lichess/DrNykterstein")]
+async fn follow(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let found = match args.current() {
+        Some(arg) => Some(find_player(&ctx.data, arg).await),
+        None => None,
+    };
+    let username = match found {
+        Some(Ok(username)) => username,
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Who should I follow? `.follow lichess/<username>`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let channel_id = msg.channel_id.0;
+    let since = Utc::now().timestamp_millis() as u64;
+    let added = settings::update(&ctx.data, |settings| {
+        let exists = settings
+            .follows
+            .iter()
+            .any(|f| f.channel_id == channel_id && f.username.eq_ignore_ascii_case(&username));
+        if !exists {
+            settings.follows.push(Follow {
+                channel_id,
+                username: username.clone(),
+                since,
+            });
+        }
+        !exists
+    })
+    .await;
+
+    let reply = if added {
+        format!("I'll post {}'s finished Lichess games here!", username)
+    } else {
+        format!("This channel already follows {}.", username)
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Stop posting a player's games in this channel.")]
+#[usage("<lichess/<username> | @member>")]
+async fn unfollow(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let found = match args.current() {
+        Some(arg) => Some(find_player(&ctx.data, arg).await),
+        None => None,
+    };
+    let username = match found {
+        Some(Ok(username)) => username,
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Who should I unfollow? `.unfollow lichess/<username>`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let channel_id = msg.channel_id.0;
+    let removed = settings::update(&ctx.data, |settings| {
+        let before = settings.follows.len();
+        settings.follows.retain(|f| {
+            !(f.channel_id == channel_id && f.username.eq_ignore_ascii_case(&username))
+        });
+        settings.follows.len() != before
+    })
+    .await;
+
+    let reply = if removed {
+        format!("I won't post {}'s games here anymore.", username)
+    } else {
+        format!("This channel doesn't follow {}.", username)
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("List the players this channel follows.")]
+async fn following(ctx: &Context, msg: &Message) -> CommandResult {
+    let channel_id = msg.channel_id.0;
+    let names: Vec<String> = settings::read(&ctx.data, |settings| {
+        settings
+            .follows
+            .iter()
+            .filter(|f| f.channel_id == channel_id)
+            .map(|f| format!("lichess/{}", f.username))
+            .collect()
+    })
+    .await;
+
+    let desc = if names.is_empty() {
+        "This channel doesn't follow anyone yet.".to_string()
+    } else {
+        names.join("\n")
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Followed players");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{content, modules::BotModule, random_index, EMBED_SIDE_COLOR};
+
+#[group]
+#[commands(eightball, memes)]
+struct Fun;
+
+pub struct FunModule;
+
+#[async_trait]
+impl BotModule for FunModule {
+    fn name(&self) -> &'static str {
+        "fun"
+    }
+
+    fn description(&self) -> &'static str {
+        "The 8-ball and the meme commands."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&FUN_GROUP)
+    }
+
+    async fn unknown_command(&self, ctx: &Context, msg: &Message, name: &str) -> bool {
+        meme_command(ctx, msg, name).await
+    }
+}
+
+#[command("8ball")]
+#[aliases("eightball")]
+#[description("Ask the chess spirits a yes or no question.")]
+#[usage("<question>")]
+async fn eightball(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let question = args.rest().trim();
+    if question.is_empty() {
+        msg.reply(&ctx.http, "Ask me a question! `.8ball <question>`")
+            .await?;
+        return Ok(());
+    }
+
+    let content = content::get(&ctx.data).await;
+    let answers = content.eightball_answers(msg.guild_id);
+    if answers.is_empty() {
+        msg.reply(&ctx.http, "The 8-ball has nothing to say.")
+            .await?;
+        return Ok(());
+    }
+
+    let answer = answers[random_index(answers.len())].to_string();
+    let title = format!(":8ball: {}", question);
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.color(EMBED_SIDE_COLOR);
+                e.title(title);
+                e.description(answer);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Content responses that link to one of these are shown as an image instead of a link.
+const IMAGE_EXTENSIONS: [&str; 5] = [".png", ".jpg", ".jpeg", ".gif", ".webp"];
+
+fn is_image_url(response: &str) -> bool {
+    let lower = response.to_lowercase();
+    lower.starts_with("http") && IMAGE_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+// Reply to a meme command from the content file. Returns false if there is no meme with
+// that name, so the caller can treat it as an unknown command.
+pub async fn meme_command(ctx: &Context, msg: &Message, name: &str) -> bool {
+    let content = content::get(&ctx.data).await;
+    let response = match content.meme_responses(msg.guild_id, name) {
+        Some(responses) if !responses.is_empty() => {
+            responses[random_index(responses.len())].clone()
+        }
+        _ => return false,
+    };
+
+    let result = if is_image_url(&response) {
+        msg.channel_id
+            .send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.color(EMBED_SIDE_COLOR);
+                    e.image(response);
+                    e
+                });
+                m
+            })
+            .await
+    } else {
+        msg.channel_id.say(&ctx.http, response).await
+    };
+
+    if let Err(why) = result {
+        println!("Error sending meme '{}': {:?}", name, why);
+    }
+
+    true
+}
+
+#[command]
+#[description("List the meme commands available in this server.")]
+async fn memes(ctx: &Context, msg: &Message) -> CommandResult {
+    let content = content::get(&ctx.data).await;
+    let names = content.meme_names(msg.guild_id);
+
+    let desc = if names.is_empty() {
+        "No meme commands here yet!".to_string()
+    } else {
+        names
+            .iter()
+            .map(|name| format!("`.{}`", name))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Meme commands");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
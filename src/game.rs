@@ -0,0 +1,3120 @@
+use std::{
+    collections::HashMap, error::Error, fmt, fs, io, path::PathBuf, sync::Arc, time::Duration,
+};
+
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, MessageId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+use shakmaty::{
+    fen::Fen,
+    san::{San, SanError, SanPlus},
+    uci::UciMove,
+    variant::VariantPosition,
+    CastlingMode, Chess, Color, EnPassantMode, KnownOutcome, Move, Position, Square,
+};
+
+use crate::{
+    chess960,
+    config::{self, Config, ConfigContainer},
+    diagram::{self, DiagramOptions},
+    engine::{self, Score, Strength},
+    fen,
+    modules::BotModule,
+    notation::Notation,
+    openings::{self, Opening},
+    permissions::ADMIN_CHECK,
+    rating::{self, ServerRatings},
+    reactionmoves,
+    render::{self, BoardStyle},
+    scheduler::JobKind,
+    settings,
+    simul::{self, Simul},
+    timecontrol::TimeControl,
+    variant::{self, Variant},
+    votechess::VoteGame,
+    EMBED_SIDE_COLOR,
+};
+
+const GAMES_DB: &str = "games.sqlite";
+// Where games were kept before the database. It's moved in on the first start and kept as
+// games.json.migrated.
+const GAMES_FILE: &str = "games.json";
+
+// A row per game, and the ratings, simuls and vote games as one JSON value each.
+const GAMES_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id INTEGER PRIMARY KEY,
+        finished INTEGER NOT NULL,
+        game TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS games_finished ON games (finished);
+    CREATE TABLE IF NOT EXISTS store (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+const ACCEPT_ID: &str = "game:accept";
+const DECLINE_ID: &str = "game:decline";
+
+// Buttons on resign, abort and draw prompts are "game:<action>:<user id>", for the player who
+// asked or offered.
+const BUTTON_PREFIX: &str = "game:";
+const RESIGN: &str = "resign";
+const ABORT: &str = "abort";
+const CANCEL: &str = "cancel";
+const DRAW_ACCEPT: &str = "draw-accept";
+const DRAW_DECLINE: &str = "draw-decline";
+
+// Moves shown under the board. Older ones are left out.
+const RECENT_MOVES: usize = 12;
+
+// Times each player in a blindfold game may look at the board with `.peek`.
+const PEEKS: u8 = 3;
+
+// Longer chat messages aren't read as moves. "exd8=Q+" is seven.
+const MAX_CHAT_MOVE: usize = 8;
+
+// Correspondence players are pinged when this much of their time is left.
+const REMIND_BEFORE_SECS: i64 = 12 * 3600;
+
+// Games without a clock that nobody has moved in for this long are left for the engine to
+// adjudicate.
+const ABANDONED_SECS: i64 = 14 * 24 * 3600;
+
+// How far ahead, in centipawns, the engine has to see a side for them to be given the game.
+// Anything closer is adjudicated a draw.
+const ADJUDICATION_MARGIN: i32 = 200;
+
+// The bot's strength when `.play bot` doesn't say.
+pub const DEFAULT_LEVEL: u8 = 3;
+
+// Seeks nobody takes are dropped after this long.
+const SEEK_TIMEOUT_SECS: u64 = 15 * 60;
+
+#[group]
+#[commands(
+    challenge,
+    seek,
+    rematch,
+    play,
+    play_move,
+    board,
+    peek,
+    resign,
+    draw,
+    abort,
+    adjudicate_now
+)]
+struct Games;
+
+pub struct GamesModule;
+
+#[async_trait]
+impl BotModule for GamesModule {
+    fn name(&self) -> &'static str {
+        "games"
+    }
+
+    fn description(&self) -> &'static str {
+        "Chess games between members, see `.challenge`. Moves can be typed straight in chat."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&GAMES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        let data_dir = data
+            .get::<ConfigContainer>()
+            .expect("Expected config in typemap.")
+            .data_dir
+            .clone();
+        data.insert::<GameContainer>(Mutex::new(GameStore::load(&data_dir)));
+    }
+
+    async fn normal_message(&self, ctx: &Context, msg: &Message) {
+        chat_move(ctx, msg).await;
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await || handle_game_button(ctx, component).await
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::GameDeadlines, "*/5 * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::GameDeadlines {
+            check_deadlines(http, data).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GameResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+    // Called off before both players moved. Nobody wins or loses.
+    Aborted,
+}
+
+impl GameResult {
+    pub fn score(self) -> &'static str {
+        match self {
+            GameResult::WhiteWon => "1-0",
+            GameResult::BlackWon => "0-1",
+            GameResult::Draw => "½-½",
+            GameResult::Aborted => "*",
+        }
+    }
+
+    fn winner(color: Color) -> GameResult {
+        match color {
+            Color::White => GameResult::WhiteWon,
+            Color::Black => GameResult::BlackWon,
+        }
+    }
+}
+
+// How an over-the-board game entered with `.record` came to be here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recorded {
+    // The moderator who entered it.
+    pub by: u64,
+    // Where it was played, like "Club night".
+    pub event: Option<String>,
+    // Whether a player said the result is right. It isn't rated until then.
+    pub confirmed: bool,
+}
+
+// A game between two members, played in a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Game {
+    pub id: u32,
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub white: u64,
+    pub black: u64,
+    // Whether `channel_id` is a thread made for the game, archived when it ends.
+    #[serde(default)]
+    pub in_thread: bool,
+    // The moves so far in UCI. The position is worked out by playing them again.
+    pub moves: Vec<String>,
+    pub result: Option<GameResult>,
+    // How it ended, like "checkmate".
+    pub ending: Option<String>,
+    // Set when one side is the bot, played by the engine at this level.
+    pub engine_level: Option<u8>,
+    // For bot games started with a rating instead of a level, the rating the engine holds
+    // itself to.
+    #[serde(default)]
+    pub engine_elo: Option<u32>,
+    #[serde(default)]
+    pub time_control: Option<TimeControl>,
+    // When the player to move runs out of time, in seconds since the epoch.
+    #[serde(default)]
+    pub deadline: Option<i64>,
+    // Whether the player to move was told their time is running out.
+    #[serde(default)]
+    pub reminded: bool,
+    #[serde(default)]
+    pub variant: Variant,
+    // The Chess960 start position's number, for Fischer Random games.
+    #[serde(default)]
+    pub chess960: Option<u32>,
+    // The FEN of the position the game started from, for games set up with `.setup`.
+    #[serde(default)]
+    pub start_fen: Option<String>,
+    // The player offering a draw, until their opponent answers or moves.
+    #[serde(default)]
+    pub draw_offer: Option<u64>,
+    // Milliseconds left on white's and black's clocks when their last turn started.
+    #[serde(default)]
+    pub clocks: Option<[i64; 2]>,
+    // When the player to move started thinking, in milliseconds since the epoch.
+    #[serde(default)]
+    pub turn_started: Option<i64>,
+    // Whether the players' ratings were updated for the result.
+    #[serde(default)]
+    pub rated: bool,
+    // The simul this is one of the boards of, by its summary message.
+    #[serde(default)]
+    pub simul: Option<u64>,
+    // Blindfold games show only the moves until they're over.
+    #[serde(default)]
+    pub blindfold: bool,
+    // Casual games don't count for ratings or the leaderboards.
+    #[serde(default)]
+    pub casual: bool,
+    // `.peek`s white and black have used.
+    #[serde(default)]
+    pub peeks: [u8; 2],
+    // The game this is a rematch of, linking a series of games between the same players.
+    #[serde(default)]
+    pub rematch_of: Option<u32>,
+    // When the game started and ended, in seconds since the epoch. Older games don't know.
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    #[serde(default)]
+    pub ended_at: Option<i64>,
+    // Set for games played over the board and entered by hand.
+    #[serde(default)]
+    pub recorded: Option<Recorded>,
+    // When the last move was made, in seconds since the epoch.
+    #[serde(default)]
+    pub moved_at: Option<i64>,
+    // For games where each player has a channel of their own, white's and black's: their DMs
+    // with the bot, or a thread in each one's server for games across servers. Moves come in from
+    // either one and the board goes to both.
+    #[serde(default, alias = "dm_channels")]
+    pub player_channels: Option<[u64; 2]>,
+    // Which of those are threads made for the game, archived when it ends. A player whose thread
+    // couldn't be made plays in the channel.
+    #[serde(default)]
+    pub player_threads: [bool; 2],
+}
+
+fn clock_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+impl Game {
+    // Start the time for the player to move.
+    fn start_turn(&mut self) {
+        let now = Utc::now().timestamp_millis();
+        let turn = self.position().turn();
+        self.reminded = false;
+        self.deadline = match self.time_control {
+            Some(TimeControl::Correspondence { hours }) => {
+                Some(now / 1000 + i64::from(hours) * 3600)
+            }
+            Some(TimeControl::Clock { minutes, .. }) => {
+                let clocks = self.clocks.get_or_insert([i64::from(minutes) * 60_000; 2]);
+                self.turn_started = Some(now);
+                Some((now + clocks[clock_index(turn)]) / 1000 + 1)
+            }
+            None => None,
+        };
+    }
+
+    // Take the time the player to move has used off their clock, and give them the increment
+    // if they made a move.
+    fn stop_clock(&mut self, moved: bool) {
+        let increment = match self.time_control {
+            Some(TimeControl::Clock { increment, .. }) if moved => i64::from(increment) * 1000,
+            Some(TimeControl::Clock { .. }) => 0,
+            _ => return,
+        };
+        let index = clock_index(self.position().turn());
+        if let (Some(left), Some(clocks)) = (self.clock_left(), self.clocks.as_mut()) {
+            clocks[index] = left + increment;
+        }
+    }
+
+    // Milliseconds the player to move has left on a game on the clock.
+    pub fn clock_left(&self) -> Option<i64> {
+        let (clocks, started) = (self.clocks?, self.turn_started?);
+        let left = clocks[clock_index(self.position().turn())];
+        Some((left - (Utc::now().timestamp_millis() - started)).max(0))
+    }
+
+    // How the bot plays, for games against it.
+    pub fn engine_strength(&self) -> Option<Strength> {
+        match (self.engine_elo, self.engine_level) {
+            (Some(elo), _) => Some(Strength::Elo(elo)),
+            (None, Some(level)) => Some(Strength::Level(level)),
+            (None, None) => None,
+        }
+    }
+
+    // Whether the player to move has run out of time and should lose on their next move.
+    pub fn out_of_time(&self) -> bool {
+        match self.time_control {
+            _ if self.is_over() => false,
+            Some(TimeControl::Clock { .. }) => self.clock_left() == Some(0),
+            Some(TimeControl::Correspondence { .. }) => self
+                .deadline
+                .is_some_and(|deadline| deadline <= Utc::now().timestamp()),
+            None => false,
+        }
+    }
+
+    pub fn start_position(&self) -> VariantPosition {
+        if let Some(pos) = self.start_fen.as_deref().and_then(fen::parse_position) {
+            return pos.into();
+        }
+        match self.chess960 {
+            Some(number) => chess960::start_position(number).into(),
+            None => self.variant.start_position(),
+        }
+    }
+
+    // Chess960 castling is stored as the king taking its rook, so it can't be misread.
+    pub fn castling_mode(&self) -> CastlingMode {
+        match self.chess960 {
+            Some(_) => CastlingMode::Chess960,
+            None => CastlingMode::Standard,
+        }
+    }
+
+    // The start position as the engine takes it, which only knows the standard one by itself.
+    pub fn engine_start_fen(&self) -> Option<String> {
+        match self.chess960 {
+            Some(_) if self.start_fen.is_none() => {
+                Some(Fen::from_position(&self.start_position(), EnPassantMode::Legal).to_string())
+            }
+            _ => self.start_fen.clone(),
+        }
+    }
+
+    pub fn position(&self) -> VariantPosition {
+        let mut pos = self.start_position();
+        for uci in &self.moves {
+            match uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => pos.play_unchecked(m),
+                None => break,
+            }
+        }
+        pos
+    }
+
+    // The opening a standard game went into, and the ply it got there.
+    pub fn opening(&self) -> Option<(usize, &'static Opening)> {
+        if self.variant != Variant::Standard || self.chess960.is_some() || self.start_fen.is_some()
+        {
+            return None;
+        }
+        let mut pos = Chess::default();
+        let mut moves = Vec::new();
+        for uci in &self.moves {
+            match uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => {
+                    pos.play_unchecked(m);
+                    moves.push(m);
+                }
+                None => break,
+            }
+        }
+        openings::identify(&Chess::default(), &moves)
+    }
+
+    // The squares the last move went from and to, for highlighting on pictures.
+    pub fn last_move(&self) -> Option<(Square, Square)> {
+        match self.moves.last()?.parse::<UciMove>().ok()? {
+            UciMove::Normal { from, to, .. } => Some((from, to)),
+            UciMove::Put { to, .. } => Some((to, to)),
+            UciMove::Null => None,
+        }
+    }
+
+    pub fn player(&self, color: Color) -> u64 {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+
+    // Whether moves for the game are taken in a channel: its own, or either player's.
+    pub fn plays_in(&self, channel_id: ChannelId) -> bool {
+        self.channel_id == channel_id.0
+            || self
+                .player_channels
+                .is_some_and(|channels| channels.contains(&channel_id.0))
+    }
+
+    // Where to tell a player something about the game.
+    pub fn channel_of(&self, player: u64) -> ChannelId {
+        match (self.player_channels, self.color_of(player)) {
+            (Some([_, black]), Some(Color::Black)) => ChannelId(black),
+            (Some([white, _]), _) => ChannelId(white),
+            (None, _) => ChannelId(self.channel_id),
+        }
+    }
+
+    pub fn color_of(&self, user_id: u64) -> Option<Color> {
+        if user_id == self.white {
+            Some(Color::White)
+        } else if user_id == self.black {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    // Whether the board is hidden. Blindfold games show it again once they're over.
+    pub fn is_blind(&self) -> bool {
+        self.blindfold && !self.is_over()
+    }
+
+    // The moves in SAN, written in `notation`.
+    pub fn sans(&self, notation: Notation) -> Vec<String> {
+        let mut pos = self.start_position();
+        let mut sans = Vec::new();
+        for uci in &self.moves {
+            let m = match uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => m,
+                None => break,
+            };
+            let san = SanPlus::from_move_and_play_unchecked(&mut pos, m);
+            sans.push(notation.localize(&san.to_string()));
+        }
+        sans
+    }
+
+    // "1. e4 e5 2. Nf3", only the last `limit` plies.
+    pub fn move_list(&self, notation: Notation, limit: usize) -> String {
+        let sans = self.sans(notation);
+        let start = sans.len().saturating_sub(limit);
+        let mut out = Vec::new();
+        if start > 0 {
+            out.push("…".to_string());
+        }
+        for (ply, san) in sans.iter().enumerate().skip(start) {
+            if ply % 2 == 0 {
+                out.push(format!("{}. {}", ply / 2 + 1, san));
+            } else if ply == start {
+                out.push(format!("{}... {}", ply / 2 + 1, san));
+            } else {
+                out.push(san.clone());
+            }
+        }
+        out.join(" ")
+    }
+
+    // Play a legal move and end the game if it's mate, a dead draw or a variant win.
+    pub fn play(&mut self, m: Move) {
+        let mut pos = self.position();
+        // Playing on turns down the other side's draw offer.
+        if self
+            .draw_offer
+            .is_some_and(|offer| offer != self.player(pos.turn()))
+        {
+            self.draw_offer = None;
+        }
+        self.stop_clock(true);
+        self.moves
+            .push(UciMove::from_move(m, self.castling_mode()).to_string());
+        self.moved_at = Some(Utc::now().timestamp());
+        pos.play_unchecked(m);
+        self.start_turn();
+
+        if let Some(outcome) = pos.outcome().known() {
+            let ending = if pos.is_variant_end() {
+                self.variant.ending()
+            } else if pos.is_checkmate() {
+                "checkmate"
+            } else if pos.is_stalemate() {
+                "stalemate"
+            } else {
+                "insufficient material"
+            };
+            self.finish(
+                match outcome {
+                    KnownOutcome::Decisive { winner } => GameResult::winner(winner),
+                    KnownOutcome::Draw => GameResult::Draw,
+                },
+                ending,
+            );
+        }
+    }
+
+    pub fn finish(&mut self, result: GameResult, ending: &str) {
+        self.result = Some(result);
+        self.ending = Some(ending.to_string());
+        self.ended_at = Some(Utc::now().timestamp());
+        self.stop_clock(false);
+        self.draw_offer = None;
+        self.deadline = None;
+        self.turn_started = None;
+    }
+
+    // Whether a game without a clock has gone so long without a move that it should be
+    // adjudicated.
+    pub fn is_abandoned(&self, now: i64) -> bool {
+        !self.is_over()
+            && self.time_control.is_none()
+            && self
+                .moved_at
+                .or(self.started_at)
+                .is_some_and(|at| now - at >= ABANDONED_SECS)
+    }
+
+    // Games can be aborted until both players have made a move.
+    pub fn can_abort(&self) -> bool {
+        !self.is_over() && self.moves.len() < 2
+    }
+
+    // The player to move ran out of time. They lose, unless the other side couldn't mate
+    // anyway.
+    pub fn time_out(&mut self) {
+        let pos = self.position();
+        let result = if pos.has_insufficient_material(!pos.turn()) {
+            GameResult::Draw
+        } else {
+            GameResult::winner(!pos.turn())
+        };
+        self.finish(result, "lost on time");
+    }
+}
+
+// The result the engine's score for the side to move gives a game: a win for whoever is clearly
+// ahead, a draw if it's close.
+pub fn adjudicated(turn: Color, score: Score) -> GameResult {
+    match score {
+        Score::Mate(n) if n > 0 => GameResult::winner(turn),
+        Score::Mate(_) => GameResult::winner(!turn),
+        Score::Centipawns(cp) if cp >= ADJUDICATION_MARGIN => GameResult::winner(turn),
+        Score::Centipawns(cp) if cp <= -ADJUDICATION_MARGIN => GameResult::winner(!turn),
+        Score::Centipawns(_) => GameResult::Draw,
+    }
+}
+
+// End a game nobody is playing any more. The engine gives it to whoever is clearly ahead, or
+// calls it a draw. Games hardly started are aborted, and variants the engine doesn't play, like
+// atomic, are drawn. None if the game is over or moved on while the engine looked.
+pub async fn adjudicate(data: &RwLock<TypeMap>, game_id: u32) -> Option<Game> {
+    let game = read_games(data, |store| {
+        store
+            .games
+            .iter()
+            .find(|game| game.id == game_id && !game.is_over())
+            .cloned()
+    })
+    .await?;
+    let pos = game.position();
+    let (result, ending) = if game.can_abort() {
+        (GameResult::Aborted, "abandoned".to_string())
+    } else if game.variant != Variant::Standard {
+        (GameResult::Draw, "adjudicated a draw".to_string())
+    } else {
+        let fen = Fen::from_position(&pos, EnPassantMode::Legal).to_string();
+        let score = match engine::analyse_with(data, &fen, game.castling_mode()).await {
+            Ok(analysis) => analysis.score,
+            Err(why) => {
+                println!("Engine error adjudicating game #{}: {:?}", game.id, why);
+                return None;
+            }
+        };
+        let white_score = match pos.turn() {
+            Color::White => score,
+            Color::Black => score.flip(),
+        };
+        (
+            adjudicated(pos.turn(), score),
+            format!("adjudicated by the engine at {}", white_score.describe()),
+        )
+    };
+
+    with_games(data, |store| {
+        let stored = store
+            .games
+            .iter_mut()
+            .find(|stored| stored.id == game.id && !stored.is_over())?;
+        if stored.moves.len() != game.moves.len() {
+            return None;
+        }
+        stored.finish(result, &ending);
+        Some(stored.clone())
+    })
+    .await
+}
+
+// Why a move someone typed can't be played.
+#[derive(Debug)]
+pub enum MoveError {
+    Unreadable,
+    Illegal,
+    // The moves it could mean, written out in full.
+    Ambiguous(Vec<String>),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::Unreadable => write!(
+                f,
+                "I can't read that move, try something like `e4`, `Nf3` or `e2e4`"
+            ),
+            MoveError::Illegal => write!(f, "that move isn't legal here"),
+            MoveError::Ambiguous(candidates) if candidates.len() > 1 => {
+                let candidates: Vec<String> =
+                    candidates.iter().map(|m| format!("`{}`", m)).collect();
+                write!(
+                    f,
+                    "more than one piece can make that move, say which one: {}",
+                    candidates.join(" or ")
+                )
+            }
+            MoveError::Ambiguous(_) => write!(
+                f,
+                "more than one piece can make that move, say which one, like `Nbd2`"
+            ),
+        }
+    }
+}
+
+// Read a move in SAN (in the guild's notation or English) or UCI.
+pub fn parse_move(
+    pos: &VariantPosition,
+    text: &str,
+    notation: Notation,
+) -> Result<Move, MoveError> {
+    let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+
+    if let Ok(uci) = text.parse::<UciMove>() {
+        return uci.to_move(pos).map_err(|_| MoveError::Illegal);
+    }
+
+    // English works too, unless a letter means another piece there. A French king move that
+    // can't be played mustn't turn into a rook move.
+    let english = Some(text.to_string()).filter(|_| !notation.clashes_with_english(text));
+    let mut error = MoveError::Unreadable;
+    for candidate in std::iter::once(notation.to_english(text)).chain(english) {
+        let san = match candidate.parse::<San>() {
+            Ok(san) => san,
+            Err(_) => continue,
+        };
+        match san.to_move(pos) {
+            Ok(m) => return Ok(m),
+            Err(SanError::AmbiguousSan) => {
+                let candidates = pos
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|m| san.matches(*m))
+                    .map(|m| notation.localize(&San::from_move(pos, m).to_string()))
+                    .collect();
+                error = MoveError::Ambiguous(candidates);
+            }
+            Err(SanError::IllegalSan) => {
+                if !matches!(error, MoveError::Ambiguous(_)) {
+                    error = MoveError::Illegal;
+                }
+            }
+        }
+    }
+    Err(error)
+}
+
+// The clock and rules asked for after `.challenge @user` or `.seek`, like "5+3 960".
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GameOptions {
+    pub time_control: Option<TimeControl>,
+    pub variant: Variant,
+    pub chess960: bool,
+    #[serde(default)]
+    pub blindfold: bool,
+    #[serde(default)]
+    pub casual: bool,
+    // Played in the players' DMs with the bot instead of the server, unrated.
+    #[serde(default)]
+    pub dm: bool,
+    // Seeks anyone in a server with the bot can take. Games across servers are unrated too.
+    #[serde(default)]
+    pub anywhere: bool,
+}
+
+impl GameOptions {
+    // None if an argument isn't a time control or variant.
+    pub fn parse(args: &mut Args) -> Option<GameOptions> {
+        let mut options = GameOptions::default();
+        for arg in args.iter::<String>().flatten() {
+            if arg == "960" || arg.eq_ignore_ascii_case("chess960") {
+                options.chess960 = true;
+            } else if arg.eq_ignore_ascii_case("blindfold") || arg.eq_ignore_ascii_case("blind") {
+                options.blindfold = true;
+            } else if arg.eq_ignore_ascii_case("casual") {
+                options.casual = true;
+            } else if arg.eq_ignore_ascii_case("dm") || arg.eq_ignore_ascii_case("private") {
+                options.dm = true;
+            } else if arg.eq_ignore_ascii_case("anywhere") || arg.eq_ignore_ascii_case("global") {
+                options.anywhere = true;
+            } else if let Some(named) = Variant::from_name(&arg) {
+                options.variant = named;
+            } else if let Some(tc) = TimeControl::parse(&arg) {
+                options.time_control = Some(tc);
+            } else {
+                return None;
+            }
+        }
+        Some(options)
+    }
+
+    // Like "a Chess960 correspondence game, 3 days per move".
+    pub fn describe(self) -> String {
+        let mut variant = match (self.variant, self.chess960) {
+            (_, true) => "Chess960 ".to_string(),
+            (Variant::Standard, false) => String::new(),
+            (variant, false) => format!("{} ", variant.title()),
+        };
+        if self.blindfold {
+            variant.push_str("blindfold ");
+        }
+        if self.casual {
+            variant.push_str("casual ");
+        }
+        let game = match self.time_control {
+            Some(tc @ TimeControl::Clock { .. }) => format!("a {} {}game", tc.describe(), variant),
+            Some(tc) => format!("a {}correspondence game, {}", variant, tc.describe()),
+            None => format!("a {}game", variant),
+        };
+        if self.dm {
+            format!("{} over DMs", game)
+        } else if self.anywhere {
+            format!("{} with any server", game)
+        } else {
+            game
+        }
+    }
+
+    // A new game between two people, with a coin flip for who gets white.
+    pub fn new_game(self, guild_id: Option<u64>, channel_id: u64, players: (u64, u64)) -> Game {
+        let (white, black) = if rand::random() {
+            players
+        } else {
+            (players.1, players.0)
+        };
+        Game {
+            id: 0,
+            guild_id,
+            channel_id,
+            white,
+            black,
+            in_thread: false,
+            moves: Vec::new(),
+            result: None,
+            ending: None,
+            engine_level: None,
+            engine_elo: None,
+            time_control: self.time_control,
+            deadline: None,
+            reminded: false,
+            variant: self.variant,
+            chess960: self.chess960.then(chess960::random_number),
+            start_fen: None,
+            draw_offer: None,
+            clocks: None,
+            turn_started: None,
+            rated: false,
+            simul: None,
+            blindfold: self.blindfold,
+            casual: self.casual,
+            peeks: [0; 2],
+            rematch_of: None,
+            started_at: None,
+            ended_at: None,
+            recorded: None,
+            moved_at: None,
+            player_channels: None,
+            player_threads: [false; 2],
+        }
+    }
+}
+
+// A challenge waiting for its opponent to accept.
+pub struct Challenge {
+    pub challenger: u64,
+    // None for open challenges, which anyone but the challenger can take.
+    pub opponent: Option<u64>,
+    pub options: GameOptions,
+    // For games from a position set up with `.setup`.
+    pub start_fen: Option<String>,
+    // The Chess960 start position, when it was picked with `.960`. Random otherwise.
+    pub chess960: Option<u32>,
+}
+
+// Someone waiting for a game in a server's `.seek` queue, until `SEEK_TIMEOUT_SECS` is up.
+pub struct Seek {
+    pub user: u64,
+    pub guild_id: u64,
+    pub channel_id: u64,
+    // The bot's message saying they're looking, which the game's thread starts from.
+    pub message_id: MessageId,
+    pub options: GameOptions,
+}
+
+// Every game, finished ones too, saved in an SQLite database in the data dir so games go on after
+// a restart. They're all loaded at start for the history and ratings, and only the games that
+// changed are written back.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameStore {
+    pub games: Vec<Game>,
+    // Keyed by the challenge message. Open challenges aren't worth keeping over a restart.
+    #[serde(skip)]
+    pub challenges: HashMap<MessageId, Challenge>,
+    // Oldest first, so the longest waiting seeker is paired first.
+    #[serde(skip)]
+    pub seeks: Vec<Seek>,
+    #[serde(default)]
+    pub ratings: ServerRatings,
+    #[serde(default)]
+    pub simuls: Vec<Simul>,
+    // One a channel at most.
+    #[serde(default)]
+    pub vote_games: Vec<VoteGame>,
+    #[serde(skip)]
+    db: Option<Connection>,
+    // What's in the database for each game and the rest, so saving skips what didn't change.
+    #[serde(skip)]
+    saved_games: HashMap<u32, String>,
+    #[serde(skip)]
+    saved_rest: HashMap<String, String>,
+}
+
+impl GameStore {
+    pub fn load(data_dir: &str) -> GameStore {
+        let dir = PathBuf::from(data_dir);
+        let path = dir.join(GAMES_DB);
+        let opened = fs::create_dir_all(&dir)
+            .map_err(Box::<dyn Error + Send + Sync>::from)
+            .and_then(|_| Ok(Connection::open(&path)?))
+            .and_then(|db| {
+                db.execute_batch(GAMES_SCHEMA)?;
+                GameStore::read(db)
+            });
+        let mut store = opened.unwrap_or_else(|why| {
+            panic!("Could not load games database {}: {}", path.display(), why)
+        });
+
+        // Games from before the database come over once, if it's still empty.
+        let old = dir.join(GAMES_FILE);
+        if store.saved_games.is_empty() && store.saved_rest.is_empty() {
+            if let Ok(text) = fs::read_to_string(&old) {
+                let games: GameStore = serde_json::from_str(&text).unwrap_or_else(|why| {
+                    panic!("Could not parse games file {}: {}", old.display(), why)
+                });
+                store.restore(games);
+                if let Err(why) = store.save() {
+                    panic!(
+                        "Could not move {} into the database: {}",
+                        old.display(),
+                        why
+                    );
+                }
+                if let Err(why) = fs::rename(&old, dir.join(format!("{}.migrated", GAMES_FILE))) {
+                    println!("Could not put away {}: {:?}", old.display(), why);
+                }
+                println!(
+                    "Moved {} games from {} into {}",
+                    store.games.len(),
+                    GAMES_FILE,
+                    GAMES_DB
+                );
+            }
+        }
+        store
+    }
+
+    fn read(db: Connection) -> Result<GameStore, Box<dyn Error + Send + Sync>> {
+        let mut store = GameStore::default();
+        {
+            let mut query = db.prepare("SELECT game FROM games ORDER BY id")?;
+            for text in query.query_map([], |row| row.get::<_, String>(0))? {
+                let text = text?;
+                let game: Game = serde_json::from_str(&text)?;
+                store.saved_games.insert(game.id, text);
+                store.games.push(game);
+            }
+
+            let mut query = db.prepare("SELECT name, value FROM store")?;
+            for row in query.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+                let (name, value): (String, String) = row?;
+                match name.as_str() {
+                    "ratings" => store.ratings = serde_json::from_str(&value)?,
+                    "simuls" => store.simuls = serde_json::from_str(&value)?,
+                    "vote_games" => store.vote_games = serde_json::from_str(&value)?,
+                    _ => continue,
+                }
+                store.saved_rest.insert(name, value);
+            }
+        }
+        store.db = Some(db);
+        Ok(store)
+    }
+
+    // Take everything from a backup, keeping the database. The next save writes the difference.
+    pub fn restore(&mut self, backup: GameStore) {
+        let db = self.db.take();
+        let saved_games = std::mem::take(&mut self.saved_games);
+        let saved_rest = std::mem::take(&mut self.saved_rest);
+        *self = backup;
+        self.db = db;
+        self.saved_games = saved_games;
+        self.saved_rest = saved_rest;
+    }
+
+    pub fn save(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let db = match self.db.as_mut() {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+
+        let mut changed = Vec::new();
+        for game in &self.games {
+            let text = serde_json::to_string(game)?;
+            if self.saved_games.get(&game.id) != Some(&text) {
+                changed.push((game.id, game.is_over(), text));
+            }
+        }
+        let games = &self.games;
+        let gone: Vec<u32> = self
+            .saved_games
+            .keys()
+            .copied()
+            .filter(|id| !games.iter().any(|game| game.id == *id))
+            .collect();
+        let mut rest = Vec::new();
+        for (name, value) in [
+            ("ratings", serde_json::to_string(&self.ratings)?),
+            ("simuls", serde_json::to_string(&self.simuls)?),
+            ("vote_games", serde_json::to_string(&self.vote_games)?),
+        ] {
+            if self.saved_rest.get(name) != Some(&value) {
+                rest.push((name, value));
+            }
+        }
+        if changed.is_empty() && gone.is_empty() && rest.is_empty() {
+            return Ok(());
+        }
+
+        let transaction = db.transaction()?;
+        for (id, finished, text) in &changed {
+            transaction.execute(
+                "INSERT INTO games (id, finished, game) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET finished = excluded.finished, game = excluded.game",
+                params![id, finished, text],
+            )?;
+        }
+        for id in &gone {
+            transaction.execute("DELETE FROM games WHERE id = ?1", [id])?;
+        }
+        for (name, value) in &rest {
+            transaction.execute(
+                "INSERT INTO store (name, value) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET value = excluded.value",
+                params![name, value],
+            )?;
+        }
+        transaction.commit()?;
+
+        for (id, _, text) in changed {
+            self.saved_games.insert(id, text);
+        }
+        for id in gone {
+            self.saved_games.remove(&id);
+        }
+        for (name, value) in rest {
+            self.saved_rest.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    // Rate the games that finished since the last look.
+    fn rate_finished(&mut self) {
+        for game in self.games.iter_mut() {
+            if !game.rated && rating::is_rated(game) {
+                rating::rate(&mut self.ratings, game);
+                game.rated = true;
+            }
+        }
+    }
+
+    pub fn active_in(&mut self, channel_id: ChannelId, user_id: UserId) -> Option<&mut Game> {
+        self.games.iter_mut().find(|game| {
+            !game.is_over() && game.plays_in(channel_id) && game.color_of(user_id.0).is_some()
+        })
+    }
+
+    pub fn active(&self, channel_id: ChannelId, user_id: UserId) -> Option<&Game> {
+        self.games.iter().find(|game| {
+            !game.is_over() && game.plays_in(channel_id) && game.color_of(user_id.0).is_some()
+        })
+    }
+
+    // The games of a series of rematches up to `game`, first one first.
+    pub fn series(&self, game: &Game) -> Vec<&Game> {
+        let mut series = Vec::new();
+        let mut previous = game.rematch_of;
+        while let Some(earlier) = previous.and_then(|id| self.games.iter().find(|g| g.id == id)) {
+            series.push(earlier);
+            previous = earlier.rematch_of;
+        }
+        series.reverse();
+        series
+    }
+
+    pub fn is_playing(&self, user_id: u64) -> bool {
+        self.games
+            .iter()
+            .any(|game| !game.is_over() && game.color_of(user_id).is_some())
+    }
+
+    // Take a seek out of the queue.
+    fn remove_seek(&mut self, matches: impl Fn(&Seek) -> bool) -> Option<Seek> {
+        let index = self.seeks.iter().position(matches)?;
+        Some(self.seeks.remove(index))
+    }
+
+    fn next_id(&self) -> u32 {
+        self.games.iter().map(|g| g.id).max().unwrap_or(0) + 1
+    }
+
+    // Keep a game that was played somewhere else and is already over.
+    pub fn record(&mut self, mut game: Game) -> Game {
+        game.id = self.next_id();
+        self.games.push(game.clone());
+        game
+    }
+
+    pub fn start(&mut self, mut game: Game) -> Game {
+        game.id = self.next_id();
+        game.started_at = Some(Utc::now().timestamp());
+        game.start_turn();
+        self.games.push(game.clone());
+        game
+    }
+}
+
+pub struct GameContainer;
+
+impl TypeMapKey for GameContainer {
+    type Value = Mutex<GameStore>;
+}
+
+// Look at the games, holding the lock only as long as it takes.
+pub async fn read_games<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&GameStore) -> T,
+{
+    let data = data.read().await;
+    let store = data
+        .get::<GameContainer>()
+        .expect("Expected games in typemap.")
+        .lock()
+        .await;
+    f(&store)
+}
+
+// Change the games, rate any that finished and save them if anything changed.
+pub async fn with_games<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut GameStore) -> T,
+{
+    let data = data.read().await;
+    let mut store = data
+        .get::<GameContainer>()
+        .expect("Expected games in typemap.")
+        .lock()
+        .await;
+    let result = f(&mut store);
+    store.rate_finished();
+    if let Err(why) = store.save() {
+        println!("Could not save games: {:?}", why);
+    }
+    result
+}
+
+pub async fn guild_notation(data: &RwLock<TypeMap>, guild_id: Option<u64>) -> Notation {
+    match guild_id {
+        Some(guild_id) => settings::guild(data, GuildId(guild_id))
+            .await
+            .notation
+            .unwrap_or_default(),
+        None => Notation::default(),
+    }
+}
+
+// Time on a clock, like "4:05" or "1:02:30".
+pub fn format_clock(ms: i64) -> String {
+    let secs = ms.max(0) / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// The game's embed. With `image` the board is left out of the text and shown from the
+// attached picture instead. Blindfold games show every move but no board until they're over.
+pub fn game_embed<'a>(
+    e: &'a mut CreateEmbed,
+    game: &Game,
+    style: &BoardStyle,
+    notation: Notation,
+    image: bool,
+) -> &'a mut CreateEmbed {
+    let pos = game.position();
+    let mut desc = format!("⚪ <@{}> vs ⚫ <@{}>", game.white, game.black);
+    if let Some(previous) = game.rematch_of {
+        desc.push_str(&format!(", rematch of game #{}", previous));
+    }
+    let blind = game.is_blind();
+    if image {
+        e.image(diagram::IMAGE_URL);
+    } else if !blind {
+        desc.push_str(&format!("\n{}", render::board_for(pos.board(), style)));
+    }
+    if let Some(pockets) = variant::pockets_text(&pos) {
+        desc.push_str(&format!("\n{}", pockets));
+    }
+    if !game.moves.is_empty() {
+        let limit = if blind { usize::MAX } else { RECENT_MOVES };
+        desc.push_str(&format!("\n{}", game.move_list(notation, limit)));
+    }
+    if let Some((_, opening)) = game.opening() {
+        desc.push_str(&format!("\n📖 {}", opening.describe()));
+    }
+
+    let status = match (&game.result, &game.ending) {
+        (Some(GameResult::Aborted), _) => "Aborted".to_string(),
+        (Some(result), Some(ending)) => format!("{}, {}", ending, result.score()),
+        (Some(result), None) => result.score().to_string(),
+        (None, _) if pos.is_check() => format!("Check! <@{}> to move", game.player(pos.turn())),
+        (None, _) => format!("<@{}> to move", game.player(pos.turn())),
+    };
+    desc.push_str(&format!("\n**{}**", status));
+    match (game.time_control, game.deadline) {
+        (Some(TimeControl::Correspondence { .. }), Some(deadline)) if !game.is_over() => {
+            desc.push_str(&format!(" by <t:{}:f>", deadline));
+        }
+        _ => {}
+    }
+    if let Some(clocks) = game.clocks {
+        let mut clocks = clocks;
+        if let Some(left) = game.clock_left() {
+            clocks[clock_index(pos.turn())] = left;
+        }
+        desc.push_str(&format!(
+            "\n⏱ ⚪ {} · ⚫ {}",
+            format_clock(clocks[0]),
+            format_clock(clocks[1])
+        ));
+    }
+
+    let mut title = format!("Game #{}", game.id);
+    match game.engine_strength() {
+        Some(Strength::Level(level)) => title.push_str(&format!(" · bot level {}", level)),
+        Some(Strength::Elo(elo)) => title.push_str(&format!(" · bot at {} Elo", elo)),
+        None => {}
+    }
+    if game.variant != Variant::Standard {
+        title.push_str(&format!(" · {}", game.variant.title()));
+    }
+    if let Some(number) = game.chess960 {
+        title.push_str(&format!(" · Chess960 #{}", number));
+    }
+    if let Some(tc) = game.time_control {
+        title.push_str(&format!(" · {}", tc.describe()));
+    }
+    if game.blindfold {
+        title.push_str(" · blindfold");
+    }
+    if game.casual {
+        title.push_str(" · casual");
+    }
+    e.title(title);
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    if blind {
+        e.footer(|f| {
+            f.text(".move <move> · .peek · .draw · .resign");
+            f
+        });
+    } else if !game.is_over() {
+        e.footer(|f| {
+            f.text(".move <move> · .board · .draw · .resign");
+            f
+        });
+    }
+    e
+}
+
+pub async fn send_game(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    game: &Game,
+) -> serenity::Result<()> {
+    let pos = game.position();
+    let notation = guild_notation(data, game.guild_id).await;
+    // Once a rated game is over, what it did to the players' ratings.
+    let ratings = if rating::is_rated(game) {
+        read_games(data, |store| rating::game_summary(&store.ratings, game)).await
+    } else {
+        None
+    };
+
+    // Games over DMs or across servers go to both players, each seeing the board from their side
+    // in their style.
+    // Against the bot the board is drawn from the player's side.
+    let boards = match game.player_channels {
+        Some([white, black]) => vec![
+            (ChannelId(white), game.white, false),
+            (ChannelId(black), game.black, true),
+        ],
+        None => vec![(
+            channel_id,
+            game.player(pos.turn()),
+            game.engine_level.is_some() && game.white == crate::bot_id(data).await.0,
+        )],
+    };
+    for (channel_id, viewer, flipped) in boards {
+        let style =
+            settings::board_style(data, game.guild_id.map(GuildId), Some(UserId(viewer))).await;
+        let options = DiagramOptions {
+            flipped,
+            highlight: game.last_move(),
+        };
+        let image = if game.is_blind() {
+            None
+        } else {
+            diagram::board_image(http, data, channel_id, pos.board(), &style, &options).await
+        };
+        let has_image = image.is_some();
+
+        let message = channel_id
+            .send_message(http, |m| {
+                m.embed(|e| {
+                    game_embed(e, game, &style, notation, has_image);
+                    if let Some(ratings) = &ratings {
+                        e.field("Ratings", ratings, false);
+                    }
+                    e
+                });
+                if let Some(png) = image {
+                    m.add_file(diagram::attachment(png));
+                }
+                m
+            })
+            .await?;
+        if !game.is_over() {
+            reactionmoves::add_reactions(http, data, &message, game).await;
+        }
+    }
+
+    // Finished games don't need their threads any more.
+    if game.is_over() {
+        let threads = match game.player_channels {
+            Some(channels) => channels
+                .iter()
+                .zip(game.player_threads)
+                .filter(|(_, thread)| *thread)
+                .map(|(channel, _)| *channel)
+                .collect(),
+            None if game.in_thread => vec![channel_id.0],
+            None => Vec::new(),
+        };
+        for thread in threads {
+            if let Err(why) = ChannelId(thread)
+                .edit_thread(http, |t| t.archived(true))
+                .await
+            {
+                println!("Error archiving a game thread: {:?}", why);
+            }
+        }
+    }
+    if let Some(simul) = game.simul {
+        simul::refresh_dashboard(http, data, simul, game.is_over()).await;
+        if game.is_over() {
+            simul::update_summary(http, data, simul).await;
+        }
+    }
+    Ok(())
+}
+
+// Flag the player to move when their clock runs out, unless they've moved or the game is over
+// by then.
+pub fn watch_clock(ctx: &Context, game: &Game) {
+    spawn_clock_watch(ctx.http.clone(), ctx.data.clone(), game);
+}
+
+fn spawn_clock_watch(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, game: &Game) {
+    let left = match game.clock_left() {
+        Some(left) if !game.is_over() => left,
+        _ => return,
+    };
+    let (id, plies) = (game.id, game.moves.len());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(left as u64)).await;
+        let flagged = with_games(&data, |store| {
+            let game = store
+                .games
+                .iter_mut()
+                .find(|game| game.id == id && game.moves.len() == plies && !game.is_over())?;
+            game.time_out();
+            Some(game.clone())
+        })
+        .await;
+
+        if let Some(game) = flagged {
+            if let Err(why) = send_game(&http, &data, ChannelId(game.channel_id), &game).await {
+                println!("Error posting a flagged game: {:?}", why);
+            }
+        }
+    });
+}
+
+// Watch the clocks of the games that were going on when the bot last stopped. Their time kept
+// running while it was away.
+pub async fn watch_clocks(http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
+    let running = read_games(&data, |store| {
+        store
+            .games
+            .iter()
+            .filter(|game| !game.is_over() && game.clocks.is_some())
+            .cloned()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    for game in running {
+        spawn_clock_watch(http.clone(), data.clone(), &game);
+    }
+}
+
+// Remind players whose time is running out, end games where it ran out and adjudicate
+// abandoned ones. Run by the scheduler every few minutes.
+pub async fn check_deadlines(http: &Http, data: &RwLock<TypeMap>) {
+    let now = Utc::now().timestamp();
+    let (reminders, timed_out, abandoned) = with_games(data, |store| {
+        let mut reminders = Vec::new();
+        let mut timed_out = Vec::new();
+        let abandoned: Vec<u32> = store
+            .games
+            .iter()
+            .filter(|game| game.is_abandoned(now))
+            .map(|game| game.id)
+            .collect();
+        for game in store.games.iter_mut().filter(|game| !game.is_over()) {
+            let (deadline, hours) = match (game.deadline, game.time_control) {
+                (Some(deadline), Some(TimeControl::Correspondence { hours })) => (deadline, hours),
+                // Clocks are watched by their own task, this only catches games left over from
+                // a restart.
+                (Some(deadline), Some(TimeControl::Clock { .. })) => (deadline, 0),
+                _ => continue,
+            };
+            if deadline <= now {
+                game.time_out();
+                timed_out.push(game.clone());
+            } else if !game.reminded
+                && deadline - now <= REMIND_BEFORE_SECS
+                && i64::from(hours) * 3600 > REMIND_BEFORE_SECS
+            {
+                game.reminded = true;
+                reminders.push(game.clone());
+            }
+        }
+        (reminders, timed_out, abandoned)
+    })
+    .await;
+
+    for game in reminders {
+        let to_move = game.player(game.position().turn());
+        let reminder = format!(
+            "<@{}>, it's your move in game #{}. Your time runs out <t:{}:R>.",
+            to_move,
+            game.id,
+            game.deadline.unwrap_or(now)
+        );
+        if let Err(why) = game.channel_of(to_move).say(http, reminder).await {
+            println!("Error reminding a player: {:?}", why);
+        }
+    }
+    for game in timed_out {
+        if let Err(why) = send_game(http, data, ChannelId(game.channel_id), &game).await {
+            println!("Error posting a game lost on time: {:?}", why);
+        }
+    }
+    for game_id in abandoned {
+        if let Some(game) = adjudicate(data, game_id).await {
+            if let Err(why) = send_game(http, data, ChannelId(game.channel_id), &game).await {
+                println!("Error posting an adjudicated game: {:?}", why);
+            }
+        }
+    }
+}
+
+// Have the engine move if it's the bot's turn in `game`.
+pub async fn engine_turn(
+    ctx: &Context,
+    channel_id: ChannelId,
+    game: &Game,
+) -> serenity::Result<()> {
+    let strength = match game.engine_strength() {
+        Some(strength) if !game.is_over() => strength,
+        _ => return Ok(()),
+    };
+    if game.player(game.position().turn()) != crate::bot_id(&ctx.data).await.0 {
+        return Ok(());
+    }
+
+    let typing = channel_id.start_typing(&ctx.http);
+    let best = engine::best_move(
+        &ctx.data,
+        game.engine_start_fen().as_deref(),
+        &game.moves,
+        strength,
+        game.castling_mode(),
+    )
+    .await;
+    if let Ok(typing) = typing {
+        typing.stop();
+    }
+    let uci = match best {
+        Ok(uci) => uci,
+        Err(why) => {
+            println!("Engine error in game #{}: {:?}", game.id, why);
+            channel_id
+                .say(
+                    &ctx.http,
+                    "I couldn't come up with a move, use `.board` to make me try again.",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let played = with_games(&ctx.data, |store| {
+        let game = store.games.iter_mut().find(|g| g.id == game.id)?;
+        let pos = game.position();
+        let m = uci.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+        game.play(m);
+        Some(game.clone())
+    })
+    .await;
+    match played {
+        Some(game) => send_game(&ctx.http, &ctx.data, channel_id, &game).await,
+        None => {
+            println!(
+                "The engine played an illegal move in game #{}: {}",
+                game.id, uci
+            );
+            Ok(())
+        }
+    }
+}
+
+async fn reply_privately(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error replying to a game button: {:?}", why);
+    }
+}
+
+// Start or turn down a game when a challenge button is pressed. Returns false if the button
+// isn't ours.
+pub async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if id != ACCEPT_ID && id != DECLINE_ID {
+        return false;
+    }
+
+    let user_id = component.user.id.0;
+    let message_id = component.message.id;
+    let challenge = with_games(&ctx.data, |store| {
+        let challenge = store.challenges.get(&message_id)?;
+        let allowed = match id {
+            ACCEPT_ID => challenge
+                .opponent
+                .map_or(user_id != challenge.challenger, |opponent| {
+                    opponent == user_id
+                }),
+            _ => challenge.opponent == Some(user_id) || user_id == challenge.challenger,
+        };
+        if allowed {
+            store.challenges.remove(&message_id).map(Ok)
+        } else {
+            Some(Err(()))
+        }
+    })
+    .await;
+
+    let challenge = match challenge {
+        Some(Ok(challenge)) => challenge,
+        Some(Err(())) => {
+            reply_privately(ctx, component, "This challenge isn't for you.").await;
+            return true;
+        }
+        None => {
+            reply_privately(ctx, component, "This challenge has expired.").await;
+            return true;
+        }
+    };
+
+    let opponent = challenge.opponent.unwrap_or(user_id);
+    let game = if id == ACCEPT_ID {
+        let game = challenge.options.new_game(
+            component.guild_id.map(|id| id.0),
+            component.channel_id.0,
+            (challenge.challenger, opponent),
+        );
+        let game = Game {
+            start_fen: challenge.start_fen.clone(),
+            chess960: challenge.chess960.or(game.chess960),
+            ..game
+        };
+        let game = with_games(&ctx.data, |store| store.start(game)).await;
+        if challenge.options.dm {
+            Some(move_to_dms(ctx, game).await)
+        } else {
+            Some(move_to_thread(ctx, component.channel_id, component.message.id, game).await)
+        }
+    } else {
+        None
+    };
+
+    let content = match &game {
+        Some(game) if game.player_channels.is_some() => format!(
+            "<@{}> accepted, game #{} is on in your DMs with me!",
+            opponent, game.id
+        ),
+        Some(game) if game.in_thread => format!(
+            "<@{}> accepted, game #{} is on in <#{}>!",
+            opponent, game.id, game.channel_id
+        ),
+        Some(game) => format!("<@{}> accepted, game #{} is on!", opponent, game.id),
+        None if user_id == challenge.challenger => "Challenge withdrawn.".to_string(),
+        None => format!("<@{}> declined the challenge.", opponent),
+    };
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.content(content);
+                d.embeds(Vec::new());
+                d.components(|c| c)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating challenge: {:?}", why);
+    }
+
+    if let Some(game) = game {
+        watch_clock(ctx, &game);
+        let channel_id = ChannelId(game.channel_id);
+        if let Err(why) = send_game(&ctx.http, &ctx.data, channel_id, &game).await {
+            println!("Error posting a new game: {:?}", why);
+        }
+    }
+
+    true
+}
+
+// Give a new game its own thread off `message_id`, so its moves don't flood the channel. The game
+// stays in the channel if the thread can't be made, like in DMs.
+pub async fn move_to_thread(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    game: Game,
+) -> Game {
+    if game.guild_id.is_none() {
+        return game;
+    }
+    let thread = match game_thread(ctx, channel_id, message_id, &game).await {
+        Some(thread) => thread,
+        None => return game,
+    };
+
+    with_games(&ctx.data, |store| {
+        match store.games.iter_mut().find(|stored| stored.id == game.id) {
+            Some(stored) => {
+                stored.channel_id = thread.0;
+                stored.in_thread = true;
+                stored.clone()
+            }
+            None => game,
+        }
+    })
+    .await
+}
+
+async fn game_thread(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    game: &Game,
+) -> Option<ChannelId> {
+    let name = match game.time_control {
+        Some(tc) => format!("Game #{} · {}", game.id, tc.describe()),
+        None => format!("Game #{}", game.id),
+    };
+    let thread = channel_id
+        .create_public_thread(&ctx.http, message_id, |t| {
+            t.name(name);
+            t.auto_archive_duration(1440);
+            t
+        })
+        .await;
+    match thread {
+        Ok(thread) => Some(thread.id),
+        Err(why) => {
+            println!("Could not make a thread for game #{}: {:?}", game.id, why);
+            None
+        }
+    }
+}
+
+// Give a game between two servers a thread in each, off white's and black's messages. A player
+// whose thread can't be made plays in the channel itself. It belongs to neither server, so
+// neither's ratings count it.
+async fn move_to_threads(ctx: &Context, game: Game, messages: [(ChannelId, MessageId); 2]) -> Game {
+    let mut channels = [0; 2];
+    let mut threads = [false; 2];
+    for (i, &(channel_id, message_id)) in messages.iter().enumerate() {
+        channels[i] = match game_thread(ctx, channel_id, message_id, &game).await {
+            Some(thread) => {
+                threads[i] = true;
+                thread.0
+            }
+            None => channel_id.0,
+        };
+    }
+
+    with_games(&ctx.data, |store| {
+        match store.games.iter_mut().find(|stored| stored.id == game.id) {
+            Some(stored) => {
+                stored.guild_id = None;
+                stored.channel_id = channels[0];
+                stored.player_channels = Some(channels);
+                stored.player_threads = threads;
+                stored.in_thread = threads[0];
+                stored.clone()
+            }
+            None => game,
+        }
+    })
+    .await
+}
+
+// Take a new game to the players' DMs with the bot, for `dm` challenges. Out of the server, it
+// doesn't count for its ratings. If DMs can't be opened it stays where it was.
+pub async fn move_to_dms(ctx: &Context, game: Game) -> Game {
+    let mut channels = [0; 2];
+    for (channel, player) in channels.iter_mut().zip([game.white, game.black]) {
+        match UserId(player).create_dm_channel(&ctx.http).await {
+            Ok(dm) => *channel = dm.id.0,
+            Err(why) => {
+                println!("Could not open DMs for game #{}: {:?}", game.id, why);
+                return game;
+            }
+        }
+    }
+
+    with_games(&ctx.data, |store| {
+        match store.games.iter_mut().find(|stored| stored.id == game.id) {
+            Some(stored) => {
+                stored.guild_id = None;
+                stored.channel_id = channels[0];
+                stored.player_channels = Some(channels);
+                stored.clone()
+            }
+            None => game,
+        }
+    })
+    .await
+}
+
+// Why a move didn't go through.
+pub enum Rejected {
+    NoGame,
+    NotYourTurn,
+    Move(MoveError),
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejected::NoGame => write!(f, "You don't have a game in this channel."),
+            Rejected::NotYourTurn => write!(f, "It's not your turn."),
+            Rejected::Move(why) => write!(f, "Hmm, {}.", why),
+        }
+    }
+}
+
+// Play `text` as the player's move in their game in this channel, however they entered it.
+pub async fn make_move(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    player: UserId,
+    text: &str,
+) -> Result<Game, Rejected> {
+    let notation = guild_notation(&ctx.data, guild_id.map(|id| id.0)).await;
+
+    with_games(&ctx.data, |store| {
+        let game = store
+            .active_in(channel_id, player)
+            .ok_or(Rejected::NoGame)?;
+        let pos = game.position();
+        if game.color_of(player.0) != Some(pos.turn()) {
+            return Err(Rejected::NotYourTurn);
+        }
+        if game.out_of_time() {
+            game.time_out();
+            return Ok(game.clone());
+        }
+        let m = parse_move(&pos, text, notation).map_err(Rejected::Move)?;
+        game.play(m);
+        Ok(game.clone())
+    })
+    .await
+    .inspect(|game| watch_clock(ctx, game))
+}
+
+// Whether chat could be a move. Every move names a square, so has a rank in it, except castling.
+// Most chat ("gg", "lol") is turned away here without looking at the games.
+pub fn looks_like_move(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= MAX_CHAT_MOVE
+        && !text.contains(char::is_whitespace)
+        && (text.contains(|c: char| ('1'..='8').contains(&c))
+            || text.starts_with("O-O")
+            || text.starts_with("0-0"))
+}
+
+// Moves typed in chat without `.move`, like "Nf3". Anything that doesn't read as a move, or
+// isn't from the player to move, is just chat and left alone.
+async fn chat_move(ctx: &Context, msg: &Message) {
+    let text = msg.content.trim();
+    if !looks_like_move(text) {
+        return;
+    }
+    let to_move = read_games(&ctx.data, |store| {
+        store
+            .active(msg.channel_id, msg.author.id)
+            .is_some_and(|game| game.color_of(msg.author.id.0) == Some(game.position().turn()))
+    })
+    .await;
+    if !to_move {
+        return;
+    }
+
+    let result = match make_move(ctx, msg.guild_id, msg.channel_id, msg.author.id, text).await {
+        Ok(game) => match send_game(&ctx.http, &ctx.data, msg.channel_id, &game).await {
+            Ok(()) => engine_turn(ctx, msg.channel_id, &game).await,
+            Err(why) => Err(why),
+        },
+        Err(why @ Rejected::Move(MoveError::Illegal | MoveError::Ambiguous(_))) => {
+            msg.reply(&ctx.http, why.to_string()).await.map(|_| ())
+        }
+        Err(_) => Ok(()),
+    };
+    if let Err(why) = result {
+        println!("Error playing a move from chat: {:?}", why);
+    }
+}
+
+fn button_id(action: &str, user_id: UserId) -> String {
+    format!("{}{}:{}", BUTTON_PREFIX, action, user_id.0)
+}
+
+// The action and player a resign, abort or draw button is for.
+fn parse_button_id(id: &str) -> Option<(&str, u64)> {
+    let (action, user_id) = id.strip_prefix(BUTTON_PREFIX)?.split_once(':')?;
+    Some((action, user_id.parse().ok()?))
+}
+
+// Answer a button by replacing its prompt with `text`.
+async fn close_prompt(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.components(|c| c)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error closing a game prompt: {:?}", why);
+    }
+}
+
+// Resign, abort or settle a draw when a button on one of their prompts is pressed. Returns false
+// if the button isn't ours.
+pub async fn handle_game_button(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let (action, user_id) = match parse_button_id(&component.data.custom_id) {
+        Some(button) => button,
+        None => return false,
+    };
+    let clicker = component.user.id.0;
+    let channel_id = component.channel_id;
+
+    // Resign and abort prompts are only for whoever asked. Draw offers are for the opponent,
+    // though the player offering can take theirs back.
+    let allowed = match action {
+        RESIGN | ABORT | CANCEL => clicker == user_id,
+        DRAW_ACCEPT => clicker != user_id,
+        DRAW_DECLINE => true,
+        _ => return false,
+    };
+    let answer = with_games(&ctx.data, |store| {
+        let game = store.active_in(channel_id, UserId(user_id))?;
+        if !allowed || game.color_of(clicker).is_none() {
+            return Some(Err("This isn't for you."));
+        }
+        let color = game.color_of(user_id)?;
+        match action {
+            RESIGN => game.finish(GameResult::winner(!color), "resignation"),
+            ABORT if game.can_abort() => game.finish(GameResult::Aborted, "aborted"),
+            ABORT => return Some(Err("It's too late to abort, both of you have moved.")),
+            DRAW_ACCEPT if game.draw_offer == Some(user_id) => {
+                game.finish(GameResult::Draw, "draw by agreement")
+            }
+            DRAW_ACCEPT => return Some(Err("That draw offer is no longer open.")),
+            DRAW_DECLINE => game.draw_offer = None,
+            _ => {}
+        }
+        Some(Ok(game.clone()))
+    })
+    .await;
+
+    let game = match answer {
+        Some(Ok(game)) => game,
+        Some(Err(why)) => {
+            reply_privately(ctx, component, why).await;
+            return true;
+        }
+        None => {
+            close_prompt(ctx, component, "That game is already over.").await;
+            return true;
+        }
+    };
+
+    let text = match action {
+        RESIGN => format!("<@{}> resigned game #{}.", user_id, game.id),
+        ABORT => format!("Game #{} was aborted.", game.id),
+        DRAW_ACCEPT => format!("Game #{} is drawn by agreement.", game.id),
+        DRAW_DECLINE if clicker == user_id => "Draw offer withdrawn.".to_string(),
+        DRAW_DECLINE => format!("<@{}> declined the draw.", clicker),
+        _ => "Cancelled, play on!".to_string(),
+    };
+    close_prompt(ctx, component, &text).await;
+    if game.is_over() {
+        if let Err(why) = send_game(&ctx.http, &ctx.data, channel_id, &game).await {
+            println!("Error posting a finished game: {:?}", why);
+        }
+    }
+
+    true
+}
+
+// Ask a player to confirm resigning or aborting with a button.
+async fn confirm(
+    ctx: &Context,
+    msg: &Message,
+    question: &str,
+    action: &str,
+    label: &str,
+) -> CommandResult {
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(question);
+            m.reference_message(msg);
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Danger);
+                        b.label(label);
+                        b.custom_id(button_id(action, msg.author.id));
+                        b
+                    });
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Secondary);
+                        b.label("Cancel");
+                        b.custom_id(button_id(CANCEL, msg.author.id));
+                        b
+                    })
+                })
+            });
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+// The author's game in this channel, after telling them if they don't have one.
+async fn own_game(ctx: &Context, msg: &Message) -> serenity::Result<Option<Game>> {
+    let game = read_games(&ctx.data, |store| {
+        store.active(msg.channel_id, msg.author.id).cloned()
+    })
+    .await;
+    if game.is_none() {
+        msg.reply(&ctx.http, "You don't have a game in this channel.")
+            .await?;
+    }
+    Ok(game)
+}
+
+#[command]
+#[description("Resign your game in this channel. You'll be asked to confirm.")]
+async fn resign(ctx: &Context, msg: &Message) -> CommandResult {
+    let game = match own_game(ctx, msg).await? {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    let question = format!("Resign game #{}?", game.id);
+    confirm(ctx, msg, &question, RESIGN, "Resign").await
+}
+
+#[command]
+#[description("Call off your game in this channel before both players have moved.")]
+async fn abort(ctx: &Context, msg: &Message) -> CommandResult {
+    let game = match own_game(ctx, msg).await? {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    if !game.can_abort() {
+        msg.reply(
+            &ctx.http,
+            "It's too late to abort, both of you have moved. You can `.resign` or offer a `.draw`.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let question = format!("Abort game #{}? It won't count for either of you.", game.id);
+    confirm(ctx, msg, &question, ABORT, "Abort").await
+}
+
+#[command("adjudicate")]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("End a game in this server that's been left, without waiting two weeks for it to count as abandoned. The engine gives it to whoever is clearly ahead, or calls it a draw.")]
+#[usage("<game id>")]
+#[example("42")]
+async fn adjudicate_now(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)").0;
+    let game_id = match args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| arg.trim_start_matches('#').parse::<u32>().ok())
+    {
+        Some(game_id) => game_id,
+        None => {
+            msg.reply(&ctx.http, "Use `.adjudicate <game id>`").await?;
+            return Ok(());
+        }
+    };
+    let here = read_games(&ctx.data, |store| {
+        store
+            .games
+            .iter()
+            .any(|game| game.id == game_id && game.guild_id == Some(guild_id) && !game.is_over())
+    })
+    .await;
+    if !here {
+        msg.reply(
+            &ctx.http,
+            format!("There's no game #{} going on in this server.", game_id),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    match adjudicate(&ctx.data, game_id).await {
+        Some(game) => {
+            send_game(&ctx.http, &ctx.data, ChannelId(game.channel_id), &game).await?;
+            let text = format!(
+                "Game #{} is over, {}.",
+                game.id,
+                game.ending.as_deref().unwrap_or("adjudicated")
+            );
+            msg.reply(&ctx.http, text).await?;
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "I couldn't adjudicate that game, try again in a bit.",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description("Offer a draw in your game in this channel, or accept your opponent's offer.")]
+async fn draw(ctx: &Context, msg: &Message) -> CommandResult {
+    let game = match own_game(ctx, msg).await? {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    if game.engine_level.is_some() {
+        msg.reply(&ctx.http, "The bot doesn't take draws, it plays on!")
+            .await?;
+        return Ok(());
+    }
+    let author = msg.author.id.0;
+    let opponent = match game.color_of(author) {
+        Some(color) => game.player(!color),
+        None => return Ok(()),
+    };
+
+    // Offering back when the opponent already offered settles it.
+    let game = with_games(&ctx.data, |store| {
+        let game = store.active_in(msg.channel_id, msg.author.id)?;
+        match game.draw_offer {
+            Some(offer) if offer == opponent => game.finish(GameResult::Draw, "draw by agreement"),
+            _ => game.draw_offer = Some(author),
+        }
+        Some(game.clone())
+    })
+    .await;
+    let game = match game {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    if game.is_over() {
+        send_game(&ctx.http, &ctx.data, msg.channel_id, &game).await?;
+        return Ok(());
+    }
+
+    game.channel_of(opponent)
+        .send_message(&ctx.http, |m| {
+            m.content(format!(
+                "<@{}>, <@{}> offers a draw in game #{}.",
+                opponent, author, game.id
+            ));
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Primary);
+                        b.label("Accept");
+                        b.custom_id(button_id(DRAW_ACCEPT, msg.author.id));
+                        b
+                    });
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Secondary);
+                        b.label("Decline");
+                        b.custom_id(button_id(DRAW_DECLINE, msg.author.id));
+                        b
+                    })
+                })
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Challenge someone to a game of chess in this channel. Give a clock like `5+3` (minutes and seconds a move), or a time per move like `3d` or `12h` for a correspondence game. Add `960` for Chess960, or `atomic`, `crazyhouse` or `koth` (King of the Hill) for a variant, `blindfold` to play without seeing the board, `casual` to leave ratings alone, and `dm` to play privately in your DMs with me (unrated).")]
+#[usage("<@user> [time control]")]
+#[example("@Magnus 5+3 960")]
+async fn challenge(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Use `.challenge @user`, with `5+3` for 5 minutes each and 3 seconds a move, or `3d` for a correspondence game with 3 days a move. Add `960` for Chess960, `blindfold` to play without seeing the board, `casual` to leave ratings alone, or `dm` to play over DMs";
+    let opponent = match args.single::<String>().ok().and_then(utils::parse_username) {
+        Some(opponent) => UserId(opponent),
+        None => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    let options = match GameOptions::parse(&mut args) {
+        Some(options) => options,
+        None => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    if options.chess960 && options.variant != Variant::Standard {
+        msg.reply(&ctx.http, "Chess960 only works with standard rules.")
+            .await?;
+        return Ok(());
+    }
+    if options.anywhere {
+        msg.reply(
+            &ctx.http,
+            "Use `.seek anywhere` to play someone from another server.",
+        )
+        .await?;
+        return Ok(());
+    }
+    offer_challenge(ctx, msg, opponent, options, None).await
+}
+
+// Post a challenge from the author to `opponent` with Accept and Decline buttons, unless one of
+// them can't play.
+pub async fn offer_challenge(
+    ctx: &Context,
+    msg: &Message,
+    opponent: UserId,
+    options: GameOptions,
+    start_fen: Option<String>,
+) -> CommandResult {
+    if opponent == msg.author.id {
+        msg.reply(&ctx.http, "You can't challenge yourself!")
+            .await?;
+        return Ok(());
+    }
+    if opponent.to_user(&ctx.http).await?.bot {
+        msg.reply(&ctx.http, "Bots don't play here, challenge a person.")
+            .await?;
+        return Ok(());
+    }
+    let busy = read_games(&ctx.data, |store| {
+        store.is_playing(msg.author.id.0) || store.is_playing(opponent.0)
+    })
+    .await;
+    if busy {
+        msg.reply(
+            &ctx.http,
+            "One of you is already in a game, finish that one first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            let from = match &start_fen {
+                Some(fen) => format!(" from this position: `{}`", fen),
+                None => String::new(),
+            };
+            m.content(format!(
+                "<@{}>, <@{}> challenges you to {}{}!",
+                opponent,
+                msg.author.id,
+                options.describe(),
+                from
+            ));
+            m.components(|c| challenge_buttons(c, "Decline"));
+            m
+        })
+        .await?;
+
+    with_games(&ctx.data, |store| {
+        store.challenges.insert(
+            sent.id,
+            Challenge {
+                challenger: msg.author.id.0,
+                opponent: Some(opponent.0),
+                options,
+                start_fen,
+                chess960: None,
+            },
+        )
+    })
+    .await;
+
+    Ok(())
+}
+
+fn challenge_buttons<'a>(c: &'a mut CreateComponents, decline: &str) -> &'a mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Primary);
+            b.label("Accept");
+            b.custom_id(ACCEPT_ID);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label(decline);
+            b.custom_id(DECLINE_ID);
+            b
+        })
+    })
+}
+
+// Post a challenge from whoever pressed the button that anyone in the channel can accept, for
+// the Chess960 start position `number`.
+pub async fn offer_open_challenge(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    number: u32,
+) -> serenity::Result<()> {
+    let user_id = component.user.id.0;
+    if read_games(&ctx.data, |store| store.is_playing(user_id)).await {
+        reply_privately(
+            ctx,
+            component,
+            "You're already in a game, finish that one first.",
+        )
+        .await;
+        return Ok(());
+    }
+
+    let options = GameOptions {
+        chess960: true,
+        ..GameOptions::default()
+    };
+    let sent = component
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!(
+                "<@{}> wants to play {} from position #{}, anyone can accept!",
+                user_id,
+                options.describe(),
+                number
+            ));
+            m.components(|c| challenge_buttons(c, "Withdraw"));
+            m
+        })
+        .await?;
+
+    with_games(&ctx.data, |store| {
+        store.challenges.insert(
+            sent.id,
+            Challenge {
+                challenger: user_id,
+                opponent: None,
+                options,
+                start_fen: None,
+                chess960: Some(number),
+            },
+        )
+    })
+    .await;
+
+    component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await
+}
+
+// Say on a seek's message that it's over.
+async fn close_seek(http: &Http, seek: &Seek, text: &str) {
+    let result = ChannelId(seek.channel_id)
+        .edit_message(http, seek.message_id, |m| m.content(text))
+        .await;
+    if let Err(why) = result {
+        println!("Error closing a seek: {:?}", why);
+    }
+}
+
+// Drop a seek if nobody has taken it when its time is up.
+fn expire_seek(ctx: &Context, message_id: MessageId) {
+    let (http, data) = (ctx.http.clone(), ctx.data.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(SEEK_TIMEOUT_SECS)).await;
+        let seek = with_games(&data, |store| {
+            store.remove_seek(|seek| seek.message_id == message_id)
+        })
+        .await;
+        if let Some(seek) = seek {
+            let text = format!(
+                "Nobody took <@{}>'s seek for {}.",
+                seek.user,
+                seek.options.describe()
+            );
+            close_seek(&http, &seek, &text).await;
+        }
+    });
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Wait for anyone in the server to play you. You're paired with the next person who seeks the same time control and rules, and the game starts in a thread. Add `anywhere` to play someone from any server I'm in, with a thread on each side (unrated). Seeks last 15 minutes, `.seek cancel` stops looking.")]
+#[usage("[time control] [variant]")]
+#[example("5+3")]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)").0;
+    let user = msg.author.id.0;
+
+    if args
+        .current()
+        .is_some_and(|arg| arg.eq_ignore_ascii_case("cancel"))
+    {
+        let seek = with_games(&ctx.data, |store| {
+            store.remove_seek(|seek| seek.user == user)
+        })
+        .await;
+        match seek {
+            Some(seek) => {
+                let text = format!("<@{}> stopped looking for a game.", user);
+                close_seek(&ctx.http, &seek, &text).await;
+                msg.reply(&ctx.http, "You're out of the queue.").await?;
+            }
+            None => {
+                msg.reply(&ctx.http, "You aren't looking for a game.")
+                    .await?;
+            }
+        }
+        return Ok(());
+    }
+
+    let usage = "Use `.seek`, with `5+3` for 5 minutes each and 3 seconds a move, or `3d` for a correspondence game with 3 days a move. Add `960` for Chess960, or `atomic`, `crazyhouse` or `koth` for a variant, `blindfold` to play without seeing the board, `casual` to leave ratings alone, and `anywhere` to take on players from other servers. `.seek cancel` stops looking.";
+    let options = match GameOptions::parse(&mut args) {
+        Some(options) => options,
+        None => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    if options.chess960 && options.variant != Variant::Standard {
+        msg.reply(&ctx.http, "Chess960 only works with standard rules.")
+            .await?;
+        return Ok(());
+    }
+    if options.dm {
+        msg.reply(
+            &ctx.http,
+            "Seeks are played here, use `.challenge @user dm` for a game over DMs.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Pair them with whoever has waited longest for the same game. A new seek replaces their
+    // old one.
+    let paired = with_games(&ctx.data, |store| {
+        if store.is_playing(user) {
+            return None;
+        }
+        let replaced = store.remove_seek(|seek| seek.user == user);
+        let opponent = store
+            .seeks
+            .iter()
+            .position(|seek| {
+                (seek.guild_id == guild_id || options.anywhere)
+                    && seek.options == options
+                    && !store.is_playing(seek.user)
+            })
+            .map(|index| store.seeks.remove(index));
+        Some((replaced, opponent))
+    })
+    .await;
+    let (replaced, opponent) = match paired {
+        Some(paired) => paired,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "You're already in a game, finish that one first.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if let Some(replaced) = replaced {
+        let text = format!("<@{}> is looking for a different game now.", user);
+        close_seek(&ctx.http, &replaced, &text).await;
+    }
+
+    let opponent = match opponent {
+        Some(opponent) => opponent,
+        None => {
+            let sent = msg
+                .channel_id
+                .send_message(&ctx.http, |m| {
+                    m.content(format!(
+                        "<@{}> is looking for {}. Type `{}` to play them!",
+                        user,
+                        options.describe(),
+                        format!(".seek {}", args.message().trim()).trim_end()
+                    ))
+                })
+                .await?;
+            with_games(&ctx.data, |store| {
+                store.seeks.push(Seek {
+                    user,
+                    guild_id,
+                    channel_id: msg.channel_id.0,
+                    message_id: sent.id,
+                    options,
+                })
+            })
+            .await;
+            expire_seek(ctx, sent.id);
+            return Ok(());
+        }
+    };
+
+    let game = options.new_game(Some(guild_id), opponent.channel_id, (opponent.user, user));
+    let game = with_games(&ctx.data, |store| store.start(game)).await;
+    let game = if opponent.guild_id == guild_id {
+        move_to_thread(
+            ctx,
+            ChannelId(opponent.channel_id),
+            opponent.message_id,
+            game,
+        )
+        .await
+    } else {
+        // Each player gets a thread in their own server, off their seek.
+        let theirs = (ChannelId(opponent.channel_id), opponent.message_id);
+        let ours = (msg.channel_id, msg.id);
+        let messages = if game.white == opponent.user {
+            [theirs, ours]
+        } else {
+            [ours, theirs]
+        };
+        move_to_threads(ctx, game, messages).await
+    };
+
+    let text = format!(
+        "<@{}> and <@{}> are playing game #{} in <#{}>!",
+        opponent.user,
+        user,
+        game.id,
+        game.channel_of(opponent.user)
+    );
+    close_seek(&ctx.http, &opponent, &text).await;
+    msg.reply(
+        &ctx.http,
+        format!(
+            "You're playing <@{}>, game #{} is on in <#{}>!",
+            opponent.user,
+            game.id,
+            game.channel_of(user)
+        ),
+    )
+    .await?;
+
+    let channel_id = ChannelId(game.channel_id);
+    game.channel_of(opponent.user)
+        .say(
+            &ctx.http,
+            format!(
+                "<@{}>, <@{}> took your seek for {}!",
+                opponent.user,
+                user,
+                options.describe()
+            ),
+        )
+        .await?;
+    watch_clock(ctx, &game);
+    send_game(&ctx.http, &ctx.data, channel_id, &game).await?;
+
+    Ok(())
+}
+
+// Like "2½".
+pub fn format_points(halves: u32) -> String {
+    match (halves / 2, halves % 2) {
+        (0, 1) => "½".to_string(),
+        (points, 1) => format!("{}½", points),
+        (points, _) => points.to_string(),
+    }
+}
+
+// Points a player has scored over `games`.
+fn series_score(games: &[&Game], player: u64) -> String {
+    let halves: u32 = games
+        .iter()
+        .map(|game| match (game.result, game.color_of(player)) {
+            (Some(GameResult::Draw), _) => 1,
+            (Some(GameResult::WhiteWon), Some(Color::White)) => 2,
+            (Some(GameResult::BlackWon), Some(Color::Black)) => 2,
+            _ => 0,
+        })
+        .sum();
+    format_points(halves)
+}
+
+#[command]
+#[description(
+    "Play your last opponent again, with colors swapped and the same time control and rules."
+)]
+async fn rematch(ctx: &Context, msg: &Message) -> CommandResult {
+    let user_id = msg.author.id.0;
+    let started = with_games(&ctx.data, |store| {
+        let last = store
+            .games
+            .iter()
+            .filter(|game| game.color_of(user_id).is_some())
+            .max_by_key(|game| game.id)?;
+        if !last.is_over() {
+            return Some(Err("Finish your game first!"));
+        }
+        let opponent = if last.white == user_id {
+            last.black
+        } else {
+            last.white
+        };
+        if last.engine_level.is_none() && store.is_playing(opponent) {
+            return Some(Err("Your opponent is in another game right now."));
+        }
+
+        let options = GameOptions {
+            time_control: last.time_control,
+            variant: last.variant,
+            chess960: false,
+            blindfold: last.blindfold,
+            casual: last.casual,
+            dm: false,
+            anywhere: false,
+        };
+        // Chess960 and set-up rematches keep the start position, so both players get it from either side.
+        let game = Game {
+            white: last.black,
+            black: last.white,
+            engine_level: last.engine_level,
+            engine_elo: last.engine_elo,
+            chess960: last.chess960,
+            start_fen: last.start_fen.clone(),
+            rematch_of: Some(last.id),
+            ..options.new_game(
+                msg.guild_id.map(|id| id.0),
+                msg.channel_id.0,
+                (last.black, last.white),
+            )
+        };
+        // A rematch over DMs or across servers stays there, with the colors swapped.
+        let game = match last.player_channels {
+            Some([white, black]) => Game {
+                guild_id: None,
+                channel_id: black,
+                in_thread: last.player_threads[1],
+                player_channels: Some([black, white]),
+                player_threads: [last.player_threads[1], last.player_threads[0]],
+                ..game
+            },
+            None => game,
+        };
+        let game = store.start(game);
+        let mut series = store.series(&game);
+        series.retain(|game| game.result != Some(GameResult::Aborted));
+        let score = format!(
+            "<@{}> {} – {} <@{}>",
+            game.white,
+            series_score(&series, game.white),
+            series_score(&series, game.black),
+            game.black
+        );
+        Some(Ok((game, score)))
+    })
+    .await;
+
+    let (game, score) = match started {
+        Some(Ok(started)) => started,
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(&ctx.http, "You haven't played a game yet.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let game = move_to_thread(ctx, msg.channel_id, msg.id, game).await;
+
+    let channel_id = ChannelId(game.channel_id);
+    let mut text = match game.engine_level {
+        Some(_) => "Rematch!".to_string(),
+        None => format!(
+            "<@{}>, rematch!",
+            game.player(!game.color_of(user_id).unwrap_or(Color::White))
+        ),
+    };
+    if game.in_thread {
+        text.push_str(&format!(
+            " Game #{} is on in <#{}>.",
+            game.id, game.channel_id
+        ));
+    } else {
+        text.push_str(&format!(" Game #{} is on.", game.id));
+    }
+    text.push_str(&format!(" The series so far: {}", score));
+    msg.reply(&ctx.http, text).await?;
+    watch_clock(ctx, &game);
+    send_game(&ctx.http, &ctx.data, channel_id, &game).await?;
+    engine_turn(ctx, channel_id, &game).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Play a game against the bot. Levels go from 1 (beginner, and it blunders now and then) to 8 (full strength). Or give a rating from 1350 to 2850 and the bot plays at about that Elo. Works in DMs with me too."
+)]
+#[usage("bot [level|elo]")]
+#[example("bot 3")]
+#[example("bot 1600")]
+async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Use `.play bot [level]` with a level from 1 to 8, or `.play bot <elo>` with a rating from 1350 to 2850. To play a person, use `.challenge @user`";
+    if !args
+        .single::<String>()
+        .is_ok_and(|opponent| opponent.eq_ignore_ascii_case("bot"))
+    {
+        msg.reply(&ctx.http, usage).await?;
+        return Ok(());
+    }
+    // Games at an Elo keep a level too, it's what marks them as bot games.
+    let (level, elo) = match args.current().map(str::parse::<u32>) {
+        None => (DEFAULT_LEVEL, None),
+        Some(Ok(level)) if level <= 8 && engine::LEVELS.contains(&(level as u8)) => {
+            (level as u8, None)
+        }
+        Some(Ok(elo)) if engine::ELOS.contains(&elo) => (DEFAULT_LEVEL, Some(elo)),
+        _ => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    if read_games(&ctx.data, |store| store.is_playing(msg.author.id.0)).await {
+        msg.reply(
+            &ctx.http,
+            "You're already in a game, finish that one first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let bot = crate::bot_id(&ctx.data).await.0;
+    let game = Game {
+        engine_level: Some(level),
+        engine_elo: elo,
+        ..GameOptions::default().new_game(
+            msg.guild_id.map(|id| id.0),
+            msg.channel_id.0,
+            (msg.author.id.0, bot),
+        )
+    };
+    let game = with_games(&ctx.data, |store| store.start(game)).await;
+    let game = move_to_thread(ctx, msg.channel_id, msg.id, game).await;
+
+    let channel_id = ChannelId(game.channel_id);
+    send_game(&ctx.http, &ctx.data, channel_id, &game).await?;
+    engine_turn(ctx, channel_id, &game).await?;
+
+    Ok(())
+}
+
+#[command("move")]
+#[aliases("m")]
+#[description("Make a move in your game in this channel.")]
+#[usage("<move>")]
+#[example("Nf3")]
+async fn play_move(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.move <move>`, like `.move e4`")
+            .await?;
+        return Ok(());
+    }
+    match make_move(ctx, msg.guild_id, msg.channel_id, msg.author.id, text).await {
+        Ok(game) => {
+            send_game(&ctx.http, &ctx.data, msg.channel_id, &game).await?;
+            engine_turn(ctx, msg.channel_id, &game).await?;
+        }
+        Err(why) => {
+            msg.reply(&ctx.http, why.to_string()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description("Show the board of your game in this channel.")]
+async fn board(ctx: &Context, msg: &Message) -> CommandResult {
+    let game = read_games(&ctx.data, |store| {
+        store.active(msg.channel_id, msg.author.id).cloned()
+    })
+    .await;
+
+    match game {
+        Some(game) => {
+            send_game(&ctx.http, &ctx.data, msg.channel_id, &game).await?;
+            engine_turn(ctx, msg.channel_id, &game).await?;
+        }
+        None => {
+            msg.reply(&ctx.http, "You don't have a game in this channel.")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description("Take a look at the board of your blindfold game in this channel. It's sent to you privately, and you get 3 looks a game.")]
+async fn peek(ctx: &Context, msg: &Message) -> CommandResult {
+    let user_id = msg.author.id.0;
+    let peeked = with_games(&ctx.data, |store| {
+        let game = store.active_in(msg.channel_id, msg.author.id)?;
+        let index = clock_index(game.color_of(user_id)?);
+        if !game.blindfold {
+            return Some(Err("You can see the board in this game, use `.board`."));
+        }
+        if game.peeks[index] >= PEEKS {
+            return Some(Err("You've used up your peeks, you're on your own now!"));
+        }
+        game.peeks[index] += 1;
+        Some(Ok((game.clone(), PEEKS - game.peeks[index])))
+    })
+    .await;
+
+    let (game, left) = match peeked {
+        Some(Ok(peeked)) => peeked,
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(&ctx.http, "You don't have a game in this channel.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let pos = game.position();
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let dm = msg.author.create_dm_channel(&ctx.http).await?;
+    let options = DiagramOptions {
+        flipped: game.black == user_id,
+        highlight: game.last_move(),
+    };
+    let image =
+        diagram::board_image(&ctx.http, &ctx.data, dm.id, pos.board(), &style, &options).await;
+    let left = match left {
+        0 => "That was your last peek.".to_string(),
+        1 => "You have 1 peek left.".to_string(),
+        left => format!("You have {} peeks left.", left),
+    };
+    let sent = dm
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(format!("Game #{} · peek", game.id));
+                e.color(EMBED_SIDE_COLOR);
+                if image.is_some() {
+                    e.image(diagram::IMAGE_URL);
+                    e.description(&left);
+                } else {
+                    e.description(format!(
+                        "{}\n{}",
+                        render::board_for(pos.board(), &style),
+                        left
+                    ));
+                }
+                e
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await;
+
+    match sent {
+        Ok(_) => {
+            msg.reply(&ctx.http, "I sent you the board, don't tell your opponent!")
+                .await?
+        }
+        // Their look isn't used up if it never arrived.
+        Err(_) => {
+            with_games(&ctx.data, |store| {
+                let stored = store.games.iter_mut().find(|g| g.id == game.id);
+                if let Some(stored) = stored {
+                    if let Some(color) = stored.color_of(user_id) {
+                        let peeks = &mut stored.peeks[clock_index(color)];
+                        *peeks = peeks.saturating_sub(1);
+                    }
+                }
+            })
+            .await;
+            msg.reply(
+                &ctx.http,
+                "I couldn't DM you the board, do you allow messages from server members?",
+            )
+            .await?
+        }
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::framework::standard::Delimiter;
+
+    fn position(fen: &str) -> VariantPosition {
+        VariantPosition::Chess(fen::parse_position(fen).unwrap())
+    }
+
+    fn uci(pos: &VariantPosition, text: &str, notation: Notation) -> Result<String, MoveError> {
+        parse_move(pos, text, notation).map(|m| m.to_uci(CastlingMode::Standard).to_string())
+    }
+
+    #[test]
+    fn san_and_uci() {
+        let start = VariantPosition::Chess(Chess::default());
+        assert_eq!(uci(&start, "e4", Notation::English).unwrap(), "e2e4");
+        assert_eq!(uci(&start, "e2e4", Notation::English).unwrap(), "e2e4");
+        assert_eq!(uci(&start, " Nf3!? ", Notation::English).unwrap(), "g1f3");
+    }
+
+    #[test]
+    fn other_notations() {
+        let start = VariantPosition::Chess(Chess::default());
+        assert_eq!(uci(&start, "Sf3", Notation::German).unwrap(), "g1f3");
+        assert_eq!(uci(&start, "Cf3", Notation::French).unwrap(), "g1f3");
+        // English still works for people who don't know the server's letters.
+        assert_eq!(uci(&start, "Nf3", Notation::German).unwrap(), "g1f3");
+
+        // R is the king in French, so Re2 isn't read as the English rook move.
+        let pos = position("7k/8/8/8/8/8/8/K3R3 w - - 0 1");
+        assert!(matches!(
+            parse_move(&pos, "Re2", Notation::French),
+            Err(MoveError::Illegal)
+        ));
+        assert_eq!(uci(&pos, "Te2", Notation::French).unwrap(), "e1e2");
+        assert_eq!(uci(&pos, "Re2", Notation::English).unwrap(), "e1e2");
+    }
+
+    #[test]
+    fn bad_moves() {
+        let start = VariantPosition::Chess(Chess::default());
+        assert!(matches!(
+            parse_move(&start, "e5", Notation::English),
+            Err(MoveError::Illegal)
+        ));
+        assert!(matches!(
+            parse_move(&start, "e2e5", Notation::English),
+            Err(MoveError::Illegal)
+        ));
+        assert!(matches!(
+            parse_move(&start, "hello", Notation::English),
+            Err(MoveError::Unreadable)
+        ));
+    }
+
+    #[test]
+    fn ambiguous_moves_list_the_options() {
+        let pos = position("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        match parse_move(&pos, "Nd2", Notation::English) {
+            Err(MoveError::Ambiguous(mut candidates)) => {
+                candidates.sort();
+                assert_eq!(candidates, ["Nbd2", "Nfd2"]);
+            }
+            other => panic!("{:?}", other.map(|m| m.to_string())),
+        }
+        assert_eq!(uci(&pos, "Nbd2", Notation::English).unwrap(), "b1d2");
+    }
+
+    #[test]
+    fn castling() {
+        let pos = position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(uci(&pos, "O-O", Notation::English).unwrap(), "e1g1");
+        assert_eq!(uci(&pos, "O-O-O", Notation::English).unwrap(), "e1c1");
+    }
+
+    #[test]
+    fn chat_moves() {
+        for text in ["e4", "Nxf3+", "O-O", "0-0-0", "e8=Q#"] {
+            assert!(looks_like_move(text), "{}", text);
+        }
+        for text in ["hello", "good game", "lol", "e4 is best"] {
+            assert!(!looks_like_move(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn dm_games() {
+        let game = Game {
+            white: 1,
+            black: 2,
+            player_channels: Some([100, 200]),
+            ..GameOptions::default().new_game(None, 100, (1, 2))
+        };
+        assert!(game.plays_in(ChannelId(100)) && game.plays_in(ChannelId(200)));
+        assert!(!game.plays_in(ChannelId(300)));
+        assert_eq!(game.channel_of(2), ChannelId(200));
+        // Games saved while this was called dm_channels keep their DMs.
+        let saved = serde_json::to_string(&game)
+            .unwrap()
+            .replace("player_channels", "dm_channels");
+        let loaded: Game = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.player_channels, Some([100, 200]));
+        assert!(!rating::is_rated(&Game {
+            result: Some(GameResult::Draw),
+            ..game.clone()
+        }));
+
+        let mut args = Args::new("5+3 dm", &[Delimiter::Single(' ')]);
+        let options = GameOptions::parse(&mut args).unwrap();
+        assert!(options.dm);
+        assert_eq!(options.describe(), "a 5+3 blitz game over DMs");
+
+        let mut args = Args::new("anywhere", &[Delimiter::Single(' ')]);
+        let options = GameOptions::parse(&mut args).unwrap();
+        assert!(options.anywhere && !options.dm);
+        assert_eq!(options.describe(), "a game with any server");
+    }
+
+    // Against a stand-in engine that writes down what it's told and always plays a2a3.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn chess960_engine_games() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("cute-chess-bot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands");
+        let path = dir.join("engine.sh");
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\nwhile read line; do\n  echo \"$line\" >> '{}'\n  case \"$line\" in\n    uci) echo uciok ;;\n    isready) echo readyok ;;\n    go*) echo 'bestmove a2a3' ;;\n    quit) exit ;;\n  esac\ndone\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let game = Game {
+            engine_level: Some(DEFAULT_LEVEL),
+            ..GameOptions {
+                chess960: true,
+                ..GameOptions::default()
+            }
+            .new_game(Some(10), 20, (1, 2))
+        };
+        let fen = game.engine_start_fen().unwrap();
+        assert_eq!(
+            fen::parse_position(&fen).unwrap().board(),
+            game.start_position().board()
+        );
+
+        let config = crate::config::EngineConfig {
+            path: path.display().to_string(),
+            ..Default::default()
+        };
+        let mut engine = engine::Engine::start(&config).await.unwrap();
+        let best = engine
+            .best_move(
+                Some(&fen),
+                &game.moves,
+                engine::Strength::Level(DEFAULT_LEVEL),
+                std::time::Duration::from_millis(10),
+                game.castling_mode(),
+            )
+            .await
+            .unwrap();
+        engine.quit().await;
+        assert_eq!(best, "a2a3");
+
+        let commands = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(commands.contains("setoption name UCI_Chess960 value true"));
+        assert!(commands.contains(&format!("position fen {}\n", fen)));
+    }
+
+    #[test]
+    fn games_database() {
+        let dir = std::env::temp_dir().join(format!("cute-chess-bot-games-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let data_dir = dir.to_str().unwrap();
+
+        // The old JSON file moves into the database on the first start.
+        let mut old = GameStore::default();
+        let game = old.start(GameOptions::default().new_game(Some(10), 20, (1, 2)));
+        std::fs::write(dir.join(GAMES_FILE), serde_json::to_string(&old).unwrap()).unwrap();
+        let mut store = GameStore::load(data_dir);
+        assert_eq!(store.games.len(), 1);
+        assert!(!dir.join(GAMES_FILE).exists());
+        assert!(dir.join("games.json.migrated").exists());
+
+        // A move after a restart is still there after the next one.
+        let m = parse_move(&store.games[0].position(), "e4", Notation::English).unwrap();
+        store.games[0].play(m);
+        store.save().unwrap();
+        drop(store);
+        let store = GameStore::load(data_dir);
+        assert_eq!(store.games[0].id, game.id);
+        assert_eq!(store.games[0].moves, ["e2e4"]);
+        assert!(store.active(ChannelId(20), UserId(2)).is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn adjudication() {
+        use Color::{Black, White};
+        assert_eq!(
+            adjudicated(White, Score::Centipawns(250)),
+            GameResult::WhiteWon
+        );
+        assert_eq!(
+            adjudicated(Black, Score::Centipawns(250)),
+            GameResult::BlackWon
+        );
+        assert_eq!(
+            adjudicated(Black, Score::Centipawns(-120)),
+            GameResult::Draw
+        );
+        assert_eq!(adjudicated(White, Score::Mate(-3)), GameResult::BlackWon);
+
+        let now = Utc::now().timestamp();
+        let game = Game {
+            started_at: Some(now - ABANDONED_SECS),
+            ..GameOptions::default().new_game(Some(10), 20, (1, 2))
+        };
+        assert!(game.is_abandoned(now));
+        assert!(!Game {
+            moved_at: Some(now - 3600),
+            ..game.clone()
+        }
+        .is_abandoned(now));
+        assert!(!Game {
+            time_control: TimeControl::parse("3d"),
+            ..game.clone()
+        }
+        .is_abandoned(now));
+
+        // The PGN says what the engine thought.
+        let mut game = game;
+        game.play(parse_move(&game.position(), "e4", Notation::English).unwrap());
+        game.finish(GameResult::WhiteWon, "adjudicated by the engine at +2.50");
+        assert!(crate::history::pgn_with_names(&game, "a", "b")
+            .contains("1. e4 {[%eval 2.50] Adjudicated by the engine at +2.50} 1-0"));
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Write;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        CommandGroup, CommandResult,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{config, modules::BotModule, random_index, EMBED_SIDE_COLOR};
+
+#[group]
+#[commands(whyrust, color)]
+struct General;
+
+pub struct GeneralModule;
+
+impl BotModule for GeneralModule {
+    fn name(&self) -> &'static str {
+        "general"
+    }
+
+    fn description(&self) -> &'static str {
+        "Color roles and other odds and ends."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&GENERAL_GROUP)
+    }
+}
+
+#[command]
+#[aliases("colour")]
+async fn color(ctx: &Context, msg: &Message) -> CommandResult {
+    let bot_channel_id = config::get(&ctx.data).await.bot_channel;
+    let desc = format!("You can get cute :sparkles: by using the color commands at <#{}>\nUse `/color list` to list all the available colors\nThen `/set color [number or color]` to set your role color!\nIf you'd like a color that is not on the list, let Lucy know!", bot_channel_id);
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Set your own role color!");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn whyrust(ctx: &Context, msg: &Message) -> CommandResult {
+    let title = "Why rust?!";
+    let reasons = [
+        "Why not?",
+        "cargo",
+        "match expressions",
+        "const is the default",
+        "Cute crab mascotte 🦀",
+    ];
+
+    let index = random_index(reasons.len());
+
+    let mut choice = String::default();
+    write!(choice, "{}", &reasons[index])?;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.color(EMBED_SIDE_COLOR);
+                e.title(title);
+                e.description(choice);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
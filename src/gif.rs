@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+// GIF codes never get longer than this many bits, the table is started over when it's full.
+const MAX_CODE_BITS: u8 = 12;
+
+// An animated GIF being put together from RGB frames of the same size. Repeated frames only
+// store the part that changed, which for a chess board is usually two squares.
+pub struct Animation {
+    width: u16,
+    height: u16,
+    palette: Vec<[u8; 3]>,
+    colors: HashMap<[u8; 3], u8>,
+    frames: Vec<Frame>,
+    previous: Option<Vec<u8>>,
+}
+
+struct Frame {
+    left: u16,
+    top: u16,
+    width: u16,
+    height: u16,
+    // In hundredths of a second.
+    delay: u16,
+    indices: Vec<u8>,
+}
+
+impl Animation {
+    pub fn new(width: u16, height: u16) -> Animation {
+        Animation {
+            width,
+            height,
+            palette: Vec::new(),
+            colors: HashMap::new(),
+            frames: Vec::new(),
+            previous: None,
+        }
+    }
+
+    // The palette entry for a color. Past 256 colors the closest one is used instead.
+    fn index_of(&mut self, color: [u8; 3]) -> u8 {
+        if let Some(&index) = self.colors.get(&color) {
+            return index;
+        }
+        if self.palette.len() < 256 {
+            let index = self.palette.len() as u8;
+            self.palette.push(color);
+            self.colors.insert(color, index);
+            return index;
+        }
+        let distance = |other: &[u8; 3]| {
+            (0..3)
+                .map(|i| (color[i] as i32 - other[i] as i32).pow(2))
+                .sum::<i32>()
+        };
+        let (index, _) = self
+            .palette
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, other)| distance(other))
+            .expect("the palette is full");
+        index as u8
+    }
+
+    // Add a frame shown for `delay` hundredths of a second. `rgb` has three bytes per pixel,
+    // row by row.
+    pub fn push(&mut self, rgb: &[u8], delay: u16) {
+        let indices: Vec<u8> = rgb
+            .chunks_exact(3)
+            .map(|pixel| self.index_of([pixel[0], pixel[1], pixel[2]]))
+            .collect();
+        let (width, height) = (self.width as usize, self.height as usize);
+
+        // The smallest rectangle holding every pixel that changed.
+        let (mut left, mut top, mut right, mut bottom) = (0, 0, width, height);
+        if let Some(previous) = &self.previous {
+            let changed = |x: usize, y: usize| indices[y * width + x] != previous[y * width + x];
+            match (0..height).find(|&y| (0..width).any(|x| changed(x, y))) {
+                Some(first) => {
+                    top = first;
+                    bottom = (0..height)
+                        .rev()
+                        .find(|&y| (0..width).any(|x| changed(x, y)))
+                        .expect("a row changed")
+                        + 1;
+                    left = (0..width)
+                        .find(|&x| (top..bottom).any(|y| changed(x, y)))
+                        .expect("a column changed");
+                    right = (0..width)
+                        .rev()
+                        .find(|&x| (top..bottom).any(|y| changed(x, y)))
+                        .expect("a column changed")
+                        + 1;
+                }
+                // Nothing changed, keep a single pixel so the delay still counts.
+                None => {
+                    right = 1;
+                    bottom = 1;
+                }
+            }
+        }
+
+        let mut cropped = Vec::with_capacity((right - left) * (bottom - top));
+        for y in top..bottom {
+            cropped.extend_from_slice(&indices[y * width + left..y * width + right]);
+        }
+        self.frames.push(Frame {
+            left: left as u16,
+            top: top as u16,
+            width: (right - left) as u16,
+            height: (bottom - top) as u16,
+            delay,
+            indices: cropped,
+        });
+        self.previous = Some(indices);
+    }
+
+    // Make the last frame stay up longer, so the final position can be seen before it loops.
+    pub fn hold_last(&mut self, delay: u16) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.delay = delay;
+        }
+    }
+
+    // The GIF file, looping forever.
+    pub fn encode(&self) -> Vec<u8> {
+        // The color table has a power of two entries, at least 4 of them.
+        let mut table_bits = 2;
+        while (1 << table_bits) < self.palette.len() {
+            table_bits += 1;
+        }
+
+        let mut out = b"GIF89a".to_vec();
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.push(0xf0 | (table_bits - 1));
+        out.extend_from_slice(&[0, 0]);
+        for i in 0..1 << table_bits {
+            out.extend_from_slice(self.palette.get(i).unwrap_or(&[0, 0, 0]));
+        }
+        out.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        for frame in &self.frames {
+            // Graphic control: leave the previous frame in place under this one.
+            out.extend_from_slice(&[0x21, 0xf9, 0x04, 0x04]);
+            out.extend_from_slice(&frame.delay.to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+
+            out.push(0x2c);
+            for value in [frame.left, frame.top, frame.width, frame.height] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            out.push(0);
+
+            out.push(table_bits);
+            let data = lzw(&frame.indices, table_bits);
+            for block in data.chunks(255) {
+                out.push(block.len() as u8);
+                out.extend_from_slice(block);
+            }
+            out.push(0);
+        }
+
+        out.push(0x3b);
+        out
+    }
+}
+
+// Packs codes of varying length into bytes, lowest bits first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// LZW compression the way GIF does it, starting with codes one bit longer than `min_bits`.
+fn lzw(indices: &[u8], min_bits: u8) -> Vec<u8> {
+    let clear = 1u16 << min_bits;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_bits + 1;
+    let mut next = clear + 2;
+    writer.write(clear, size);
+
+    let mut pixels = indices.iter();
+    let mut current = match pixels.next() {
+        Some(&first) => first as u16,
+        None => {
+            writer.write(end, size);
+            return writer.finish();
+        }
+    };
+
+    for &pixel in pixels {
+        if let Some(&code) = table.get(&(current, pixel)) {
+            current = code;
+            continue;
+        }
+
+        writer.write(current, size);
+        // The decoder reads the next code with one more bit once the table outgrows this size.
+        if next > (1 << size) - 1 && size < MAX_CODE_BITS {
+            size += 1;
+        }
+        if next < 1 << MAX_CODE_BITS {
+            table.insert((current, pixel), next);
+            next += 1;
+        } else {
+            writer.write(clear, size);
+            table.clear();
+            size = min_bits + 1;
+            next = clear + 2;
+        }
+        current = pixel as u16;
+    }
+
+    writer.write(current, size);
+    if next > (1 << size) - 1 && size < MAX_CODE_BITS {
+        size += 1;
+    }
+    writer.write(end, size);
+    writer.finish()
+}
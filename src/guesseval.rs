@@ -0,0 +1,851 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{channel::Message, id::ChannelId},
+    prelude::*,
+};
+use shakmaty::{
+    fen::Fen, san::San, uci::UciMove, variant::VariantPosition, CastlingMode, Chess, Color,
+    EnPassantMode, Position,
+};
+
+use crate::{
+    config::ConfigContainer,
+    diagram::{self, DiagramOptions},
+    engine::{self, Analysis, Score},
+    eval,
+    game::{self, Game},
+    jobs,
+    modules::BotModule,
+    puzzledb, render, settings,
+    variant::Variant,
+    EMBED_SIDE_COLOR,
+};
+
+const GUESSES_FILE: &str = "guesseval.json";
+
+// How long people have to guess.
+const ROUND_SECS: u64 = 60;
+
+// Positions come from this stretch of the games played here.
+const MIN_PLY: usize = 20;
+const MAX_PLY: usize = 60;
+// A middlegame still has most of its pieces.
+const MIN_PIECES: usize = 16;
+
+// Positions where someone is winning outright make for dull guessing, they're skipped.
+const MAX_CENTIPAWNS: i32 = 800;
+const SAMPLE_TRIES: usize = 3;
+
+// Points for the closest guess, the next closest and the one after.
+const POINTS: [u32; 3] = [3, 2, 1];
+
+// A quiz position is one where the move played in the game cost at least this much of the
+// mover's winning chances, in percent, in a game that wasn't already won.
+const CRITICAL_DROP: f64 = 15.0;
+const MAX_WIN: f64 = 90.0;
+const QUIZ_TRIES: usize = 6;
+// Points for the engine's move, and for another one costing no more than `CLOSE_ENOUGH`.
+const BEST_POINTS: u32 = 2;
+const GOOD_POINTS: u32 = 1;
+const CLOSE_ENOUGH: f64 = 3.0;
+
+#[group]
+#[commands(guesseval, guess, quiz, answer)]
+struct GuessEval;
+
+pub struct GuessEvalModule;
+
+impl BotModule for GuessEvalModule {
+    fn name(&self) -> &'static str {
+        "guesseval"
+    }
+
+    fn description(&self) -> &'static str {
+        "Guess what the engine thinks of a position, or find its best move. See `.guesseval` and `.quiz`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&GUESSEVAL_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        let data_dir = data
+            .get::<ConfigContainer>()
+            .expect("Expected config in typemap.")
+            .data_dir
+            .clone();
+        data.insert::<GuessContainer>(Mutex::new(GuessStore::load(&data_dir)));
+    }
+}
+
+// Points someone won in one round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuessWin {
+    pub guild_id: Option<u64>,
+    pub at: i64,
+    pub points: u32,
+}
+
+// A position up for guessing in a channel.
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub id: u32,
+    pub fen: String,
+    // The engine's evaluation from white's side, in pawns, and its depth and best line.
+    pub answer: f64,
+    pub depth: u32,
+    pub line: String,
+    pub guild_id: Option<u64>,
+    // Each guesser's latest guess, in pawns from white's side.
+    pub guesses: Vec<(u64, f64)>,
+}
+
+// A position from a game played here where the player missed something, up for finding the best
+// move in a channel.
+#[derive(Debug, Clone)]
+pub struct Quiz {
+    pub id: u32,
+    pub pos: Chess,
+    pub fen: String,
+    // The engine's move in UCI and SAN, the mover's winning chances after it, and what it thinks
+    // of the position from white's side.
+    pub best: String,
+    pub best_san: String,
+    pub best_win: f64,
+    pub score: Score,
+    pub line: String,
+    // The game it's from, and the move played there in SAN.
+    pub game_id: u32,
+    pub played: String,
+    pub guild_id: Option<u64>,
+    // Each answer's latest move in UCI and SAN.
+    pub answers: Vec<(u64, String, String)>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct GuessStore {
+    // Everything each player has won.
+    pub players: HashMap<u64, Vec<GuessWin>>,
+    // Points from the best move quiz, kept apart for its own leaderboard.
+    #[serde(default)]
+    pub quiz_players: HashMap<u64, Vec<GuessWin>>,
+    // Rounds and quizzes going on, by channel.
+    #[serde(skip)]
+    pub rounds: HashMap<u64, Round>,
+    #[serde(skip)]
+    pub quizzes: HashMap<u64, Quiz>,
+    #[serde(skip)]
+    last_round: u32,
+    #[serde(skip)]
+    path: PathBuf,
+    // What's in the file, so saving can skip writing it again when nothing changed.
+    #[serde(skip)]
+    saved: String,
+}
+
+impl GuessStore {
+    pub fn load(data_dir: &str) -> GuessStore {
+        let path = PathBuf::from(data_dir).join(GUESSES_FILE);
+        let mut store: GuessStore = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|why| {
+                panic!("Could not parse guesses file {}: {}", path.display(), why)
+            }),
+            Err(_) => GuessStore::default(),
+        };
+        store.path = path;
+        store.saved = serde_json::to_string(&store).unwrap_or_default();
+        store
+    }
+
+    // Take everything from a backup, keeping where the guesses are saved.
+    pub fn restore(&mut self, backup: GuessStore) {
+        let path = std::mem::take(&mut self.path);
+        *self = backup;
+        self.path = path;
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let text = serde_json::to_string(self)?;
+        if text == self.saved {
+            return Ok(());
+        }
+        settings::write_file(&self.path, &text)?;
+        self.saved = text;
+        Ok(())
+    }
+}
+
+pub struct GuessContainer;
+
+impl TypeMapKey for GuessContainer {
+    type Value = Mutex<GuessStore>;
+}
+
+pub async fn read_guesses<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&GuessStore) -> T,
+{
+    let data = data.read().await;
+    let store = data
+        .get::<GuessContainer>()
+        .expect("Expected guesses in typemap.")
+        .lock()
+        .await;
+    f(&store)
+}
+
+// Change the guesses and save them if anything kept on disk changed. Rounds aren't kept, so only
+// their ends write anything.
+pub async fn with_guesses<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut GuessStore) -> T,
+{
+    let data = data.read().await;
+    let mut store = data
+        .get::<GuessContainer>()
+        .expect("Expected guesses in typemap.")
+        .lock()
+        .await;
+    let result = f(&mut store);
+    if let Err(why) = store.save() {
+        println!("Could not save guesses: {:?}", why);
+    }
+    result
+}
+
+// "+1.5", "-0.25", "0"
+fn parse_guess(text: &str) -> Option<f64> {
+    let guess: f64 = text.trim().trim_start_matches('+').parse().ok()?;
+    Some(guess).filter(|guess| guess.is_finite() && guess.abs() < 100.0)
+}
+
+fn is_middlegame(pos: &Chess) -> bool {
+    !pos.is_game_over() && pos.board().occupied().count() >= MIN_PIECES
+}
+
+// A position from partway through one of the standard games played here, with the game's id and
+// the move played next in it.
+fn position_from_games(games: &[Game]) -> Option<(Chess, u32, String)> {
+    let mut rng = thread_rng();
+    let game = games
+        .iter()
+        .filter(|game| {
+            game.variant == Variant::Standard
+                && game.chess960.is_none()
+                && game.start_fen.is_none()
+                && game.moves.len() > MIN_PLY + 4
+        })
+        .choose(&mut rng)?;
+    let ply = rng.gen_range(MIN_PLY..=MAX_PLY.min(game.moves.len() - 4));
+    let mut pos = Chess::default();
+    for uci in &game.moves[..ply] {
+        let m = uci.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+        pos.play_unchecked(m);
+    }
+    Some((pos, game.id, game.moves[ply].clone())).filter(|(pos, _, _)| is_middlegame(pos))
+}
+
+// A middlegame to guess at, from the games played here or the puzzle database if there aren't
+// enough of those.
+async fn sample_position(data: &RwLock<TypeMap>) -> Option<Chess> {
+    let from_games = game::read_games(data, |store| {
+        position_from_games(&store.games).map(|(pos, _, _)| pos)
+    })
+    .await;
+    if from_games.is_some() {
+        return from_games;
+    }
+    puzzledb::get(data)
+        .await
+        .pick(None, Some("middlegame"))
+        .and_then(|puzzle| puzzle.position())
+        .filter(is_middlegame)
+}
+
+fn end_later(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, channel_id: u64, round_id: u32) {
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(ROUND_SECS)).await;
+        end_round(&http, &data, channel_id, round_id).await;
+    });
+}
+
+// Reveal the evaluation and give out the points.
+async fn end_round(http: &Http, data: &RwLock<TypeMap>, channel_id: u64, round_id: u32) {
+    let now = Utc::now().timestamp();
+    let ended = with_guesses(data, |store| {
+        if store.rounds.get(&channel_id)?.id != round_id {
+            return None;
+        }
+        let mut round = store.rounds.remove(&channel_id)?;
+        let answer = round.answer;
+        let off = |guess: f64| (guess - answer).abs();
+        round.guesses.sort_by(|a, b| off(a.1).total_cmp(&off(b.1)));
+
+        // Guesses as close as each other share a place.
+        let mut places = Vec::new();
+        let mut place = 0;
+        for (i, &(user, guess)) in round.guesses.iter().enumerate() {
+            if i > 0 && off(guess) != off(round.guesses[i - 1].1) {
+                place = i;
+            }
+            let points = POINTS.get(place).copied().unwrap_or(0);
+            if points > 0 {
+                store.players.entry(user).or_default().push(GuessWin {
+                    guild_id: round.guild_id,
+                    at: now,
+                    points,
+                });
+            }
+            places.push((place, user, guess, points));
+        }
+        Some((round, places))
+    })
+    .await;
+    let (round, places) = match ended {
+        Some(ended) => ended,
+        None => return,
+    };
+
+    let mut desc = format!(
+        "The engine says **{:+.2}**.\nBest line: {}",
+        round.answer, round.line
+    );
+    if places.is_empty() {
+        desc.push_str("\n\nNobody guessed!");
+    } else {
+        desc.push('\n');
+    }
+    for (place, user, guess, points) in places {
+        let medal = match place {
+            0 => "🥇".to_string(),
+            1 => "🥈".to_string(),
+            2 => "🥉".to_string(),
+            place => format!("**{}.**", place + 1),
+        };
+        desc.push_str(&format!(
+            "\n{} <@{}> {:+.2}, off by {:.2}",
+            medal,
+            user,
+            guess,
+            (guess - round.answer).abs()
+        ));
+        if points > 0 {
+            desc.push_str(&format!(" · **+{}**", points));
+        }
+    }
+
+    let result = ChannelId(channel_id)
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Guess the eval: results");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| f.text(format!("Depth {} · {}", round.depth, round.fen)));
+                e
+            });
+            m
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error ending a guess the eval round: {:?}", why);
+    }
+}
+
+// The mover's winning chances after a move in UCI, by the engine. None if the move isn't legal or
+// the engine couldn't say.
+async fn win_after(data: &RwLock<TypeMap>, pos: &Chess, uci: &str) -> Option<f64> {
+    let m = uci.parse::<UciMove>().ok()?.to_move(pos).ok()?;
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+    if after.is_checkmate() {
+        return Some(100.0);
+    }
+    if after.is_game_over() {
+        return Some(50.0);
+    }
+    let fen = Fen::from_position(&after, EnPassantMode::Legal).to_string();
+    match engine::analyse(data, &fen).await {
+        Ok(analysis) => Some(eval::win_percent(analysis.score.flip())),
+        Err(why) => {
+            println!("Engine error in the best move quiz: {:?}", why);
+            None
+        }
+    }
+}
+
+// A position from a game played here where the move played threw a good part of the game away,
+// so there was something better to find. With the game's id, the move played and what the engine
+// makes of the position.
+async fn critical_position(data: &RwLock<TypeMap>) -> Option<(Chess, u32, String, Analysis)> {
+    for _ in 0..QUIZ_TRIES {
+        let (pos, game_id, played) =
+            game::read_games(data, |store| position_from_games(&store.games)).await?;
+        let fen = Fen::from_position(&pos, EnPassantMode::Legal).to_string();
+        let analysis = match engine::analyse(data, &fen).await {
+            Ok(analysis) => analysis,
+            Err(why) => {
+                println!("Engine error in the best move quiz: {:?}", why);
+                return None;
+            }
+        };
+        let best_win = eval::win_percent(analysis.score);
+        if analysis.pv.first() == Some(&played) || best_win > MAX_WIN {
+            continue;
+        }
+        let played_win = win_after(data, &pos, &played).await?;
+        if best_win - played_win >= CRITICAL_DROP {
+            return Some((pos, game_id, played, analysis));
+        }
+    }
+    None
+}
+
+fn end_quiz_later(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, channel_id: u64, quiz_id: u32) {
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(ROUND_SECS)).await;
+        end_quiz(&http, &data, channel_id, quiz_id).await;
+    });
+}
+
+// Reveal the engine's move, check the answers and give out the points. The engine's own move
+// scores best, and others it thinks are about as good score too.
+async fn end_quiz(http: &Http, data: &RwLock<TypeMap>, channel_id: u64, quiz_id: u32) {
+    let quiz = with_guesses(data, |store| {
+        if store.quizzes.get(&channel_id)?.id != quiz_id {
+            return None;
+        }
+        store.quizzes.remove(&channel_id)
+    })
+    .await;
+    let quiz = match quiz {
+        Some(quiz) => quiz,
+        None => return,
+    };
+
+    let mut verdicts: HashMap<String, u32> = HashMap::new();
+    for (_, uci, _) in &quiz.answers {
+        if verdicts.contains_key(uci) {
+            continue;
+        }
+        let points = if *uci == quiz.best {
+            BEST_POINTS
+        } else {
+            match win_after(data, &quiz.pos, uci).await {
+                Some(win) if quiz.best_win - win <= CLOSE_ENOUGH => GOOD_POINTS,
+                _ => 0,
+            }
+        };
+        verdicts.insert(uci.clone(), points);
+    }
+
+    let now = Utc::now().timestamp();
+    let mut answers: Vec<(u64, String, u32)> = quiz
+        .answers
+        .iter()
+        .map(|(user, uci, san)| (*user, san.clone(), verdicts[uci]))
+        .collect();
+    answers.sort_by_key(|(_, _, points)| std::cmp::Reverse(*points));
+    with_guesses(data, |store| {
+        for &(user, _, points) in answers.iter().filter(|(_, _, points)| *points > 0) {
+            store.quiz_players.entry(user).or_default().push(GuessWin {
+                guild_id: quiz.guild_id,
+                at: now,
+                points,
+            });
+        }
+    })
+    .await;
+
+    let mut desc = format!(
+        "The engine plays **{}** ({}).\nBest line: {}\nIn game #{}, {} was played.",
+        quiz.best_san,
+        quiz.score.describe(),
+        quiz.line,
+        quiz.game_id,
+        quiz.played
+    );
+    if answers.is_empty() {
+        desc.push_str("\n\nNobody answered!");
+    } else {
+        desc.push('\n');
+    }
+    for (user, san, points) in answers {
+        if points > 0 {
+            desc.push_str(&format!("\n✅ <@{}> {} · **+{}**", user, san, points));
+        } else {
+            desc.push_str(&format!("\n❌ <@{}> {}", user, san));
+        }
+    }
+
+    let result = ChannelId(channel_id)
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Find the best move: results");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| f.text(quiz.fen));
+                e
+            });
+            m
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error ending a best move quiz: {:?}", why);
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("gte")]
+#[description(
+    "Post a middlegame position and have everyone guess the engine's evaluation with `.guess`. After a minute the closest guesses score 3, 2 and 1 points for the leaderboard."
+)]
+async fn guesseval(ctx: &Context, msg: &Message) -> CommandResult {
+    let channel_id = msg.channel_id.0;
+    let busy = read_guesses(&ctx.data, |store| store.rounds.contains_key(&channel_id)).await;
+    if busy {
+        msg.reply(
+            &ctx.http,
+            "There's a position up for guessing here already.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let job = jobs::start(ctx, msg, "Finding a position").await?;
+    let mut found = None;
+    for _ in 0..SAMPLE_TRIES {
+        let pos = match sample_position(&ctx.data).await {
+            Some(pos) => pos,
+            None => break,
+        };
+        let fen = Fen::from_position(&pos, EnPassantMode::Legal).to_string();
+        let analysis = match engine::analyse(&ctx.data, &fen).await {
+            Ok(analysis) => analysis,
+            Err(why) => {
+                println!("Engine error in guess the eval: {:?}", why);
+                break;
+            }
+        };
+        if let Score::Centipawns(cp) = eval::white_score(&pos, analysis.score) {
+            if cp.abs() <= MAX_CENTIPAWNS {
+                found = Some((pos, fen, cp, analysis));
+                break;
+            }
+        }
+    }
+    let (pos, fen, cp, analysis) = match found {
+        Some(found) => found,
+        None => {
+            job.fail("I couldn't find a position to guess at.").await;
+            return Ok(());
+        }
+    };
+
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let round = with_guesses(&ctx.data, |store| {
+        if store.rounds.contains_key(&channel_id) {
+            return None;
+        }
+        store.last_round += 1;
+        let round = Round {
+            id: store.last_round,
+            fen: fen.clone(),
+            answer: f64::from(cp) / 100.0,
+            depth: analysis.depth,
+            line: eval::line_text(&pos, &analysis.pv, notation),
+            guild_id: msg.guild_id.map(|id| id.0),
+            guesses: Vec::new(),
+        };
+        store.rounds.insert(channel_id, round.clone());
+        Some(round)
+    })
+    .await;
+    let round = match round {
+        Some(round) => round,
+        None => {
+            job.fail("There's a position up for guessing here already.")
+                .await;
+            return Ok(());
+        }
+    };
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, None).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions {
+            flipped: pos.turn() == Color::Black,
+            ..DiagramOptions::default()
+        },
+    )
+    .await;
+    let to_move = if pos.turn() == Color::White {
+        "White"
+    } else {
+        "Black"
+    };
+    let mut desc = String::new();
+    if image.is_none() {
+        desc.push_str(&render::board_for(pos.board(), &style));
+        desc.push('\n');
+    }
+    desc.push_str(&format!(
+        "{} to move. What does the engine think? Answer in pawns from white's side, like `.guess +1.5` or `.guess -0.3`. You have {} seconds!",
+        to_move, ROUND_SECS
+    ));
+    let has_image = image.is_some();
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Guess the eval");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+    job.done().await;
+
+    end_later(ctx.http.clone(), ctx.data.clone(), channel_id, round.id);
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description(
+    "Guess the evaluation of the position up for guessing, in pawns from white's side. Guessing again replaces your guess."
+)]
+#[usage("<evaluation>")]
+#[example("+1.5")]
+async fn guess(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guess = match parse_guess(args.rest()) {
+        Some(guess) => guess,
+        None => {
+            msg.reply(&ctx.http, "Use `.guess <evaluation>`, like `.guess -0.5`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let channel_id = msg.channel_id.0;
+    let user = msg.author.id.0;
+    let taken = with_guesses(&ctx.data, |store| {
+        let round = store.rounds.get_mut(&channel_id)?;
+        round.guesses.retain(|&(guesser, _)| guesser != user);
+        round.guesses.push((user, guess));
+        Some(())
+    })
+    .await;
+    match taken {
+        Some(()) => {
+            msg.react(&ctx.http, '✅').await?;
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "There's nothing to guess here, start a round with `.guesseval`.",
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("bestmove", "findthemove")]
+#[description(
+    "Post a critical moment from a game played here, where the player missed something, and have everyone find the best move with `.answer`. After a minute the engine's move scores 2 points for the quiz leaderboard, and any about as good scores 1."
+)]
+async fn quiz(ctx: &Context, msg: &Message) -> CommandResult {
+    let channel_id = msg.channel_id.0;
+    let busy = read_guesses(&ctx.data, |store| store.quizzes.contains_key(&channel_id)).await;
+    if busy {
+        msg.reply(&ctx.http, "There's a quiz going on here already.")
+            .await?;
+        return Ok(());
+    }
+
+    let job = jobs::start(ctx, msg, "Finding a critical position").await?;
+    let (pos, game_id, played, analysis) = match critical_position(&ctx.data).await {
+        Some(found) => found,
+        None => {
+            job.fail("I couldn't find a critical moment in the games played here.")
+                .await;
+            return Ok(());
+        }
+    };
+    let best = match analysis
+        .pv
+        .first()
+        .and_then(|uci| uci.parse::<UciMove>().ok())
+        .and_then(|uci| uci.to_move(&pos).ok())
+    {
+        Some(m) => m,
+        None => {
+            job.fail("I couldn't find a critical moment in the games played here.")
+                .await;
+            return Ok(());
+        }
+    };
+
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let san = |uci: &str| {
+        uci.parse::<UciMove>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+            .map(|m| notation.localize(&San::from_move(&pos, m).to_string()))
+            .unwrap_or_else(|| uci.to_string())
+    };
+    let quiz = with_guesses(&ctx.data, |store| {
+        if store.quizzes.contains_key(&channel_id) {
+            return None;
+        }
+        store.last_round += 1;
+        let quiz = Quiz {
+            id: store.last_round,
+            pos: pos.clone(),
+            fen: Fen::from_position(&pos, EnPassantMode::Legal).to_string(),
+            best: best.to_uci(CastlingMode::Standard).to_string(),
+            best_san: notation.localize(&San::from_move(&pos, best).to_string()),
+            best_win: eval::win_percent(analysis.score),
+            score: eval::white_score(&pos, analysis.score),
+            line: eval::line_text(&pos, &analysis.pv, notation),
+            game_id,
+            played: san(&played),
+            guild_id: msg.guild_id.map(|id| id.0),
+            answers: Vec::new(),
+        };
+        store.quizzes.insert(channel_id, quiz.clone());
+        Some(quiz)
+    })
+    .await;
+    let quiz = match quiz {
+        Some(quiz) => quiz,
+        None => {
+            job.fail("There's a quiz going on here already.").await;
+            return Ok(());
+        }
+    };
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, None).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions {
+            flipped: pos.turn() == Color::Black,
+            ..DiagramOptions::default()
+        },
+    )
+    .await;
+    let to_move = if pos.turn() == Color::White {
+        "White"
+    } else {
+        "Black"
+    };
+    let mut desc = String::new();
+    if image.is_none() {
+        desc.push_str(&render::board_for(pos.board(), &style));
+        desc.push('\n');
+    }
+    desc.push_str(&format!(
+        "From game #{}. {} to move, and there's something better than what was played. Find the best move with `.answer`, like `.answer Nf3`. You have {} seconds!",
+        quiz.game_id, to_move, ROUND_SECS
+    ));
+    let has_image = image.is_some();
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Find the best move");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+    job.done().await;
+
+    end_quiz_later(ctx.http.clone(), ctx.data.clone(), channel_id, quiz.id);
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description(
+    "Answer the best move quiz going on here with a move. Answering again replaces your answer."
+)]
+#[usage("<move>")]
+#[example("Nf3")]
+async fn answer(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.answer <move>`, like `.answer Nf3`")
+            .await?;
+        return Ok(());
+    }
+
+    let channel_id = msg.channel_id.0;
+    let user = msg.author.id.0;
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let taken = with_guesses(&ctx.data, |store| {
+        let quiz = store.quizzes.get_mut(&channel_id)?;
+        let pos = VariantPosition::Chess(quiz.pos.clone());
+        Some(game::parse_move(&pos, text, notation).map(|m| {
+            let uci = m.to_uci(CastlingMode::Standard).to_string();
+            let san = notation.localize(&San::from_move(&quiz.pos, m).to_string());
+            quiz.answers.retain(|(answerer, _, _)| *answerer != user);
+            quiz.answers.push((user, uci, san));
+        }))
+    })
+    .await;
+    match taken {
+        Some(Ok(())) => {
+            msg.react(&ctx.http, '✅').await?;
+        }
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why.to_string()).await?;
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "There's no quiz going on here, start one with `.quiz`.",
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,811 @@
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use chrono::{TimeZone, Utc};
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::{AttachmentType, Http},
+    model::{
+        channel::Message,
+        id::UserId,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+use shakmaty::{fen::Fen, uci::UciMove, CastlingMode, Chess, Color, EnPassantMode};
+
+use crate::{
+    game::{self, Game, GameOptions, GameResult, Recorded},
+    modules::BotModule,
+    notation::Notation,
+    permissions::ADMIN_CHECK,
+    pgn, rating, settings,
+    variant::Variant,
+    EMBED_SIDE_COLOR,
+};
+
+// Games listed by `.games`, newest first.
+const GAMES_SHOWN: usize = 15;
+
+// PGN files attached to `.record` that are bigger than this aren't read.
+const MAX_PGN_BYTES: u64 = 256 * 1024;
+
+// Buttons under a recorded game are "history:<action>:<game id>".
+const CONFIRM_PREFIX: &str = "history:confirm:";
+const REJECT_PREFIX: &str = "history:reject:";
+
+#[group]
+#[commands(games, pgn, h2h, record, event)]
+struct History;
+
+pub struct HistoryModule;
+
+#[async_trait]
+impl BotModule for HistoryModule {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn description(&self) -> &'static str {
+        "Finished games to look back on, see `.games`, `.pgn` and `.h2h`, and over the board ones, see `.record` and `.event`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&HISTORY_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        answer_record(ctx, component).await
+    }
+}
+
+async fn player_name(http: &Http, user_id: u64) -> String {
+    match UserId(user_id).to_user(http).await {
+        Ok(user) => user.name,
+        Err(_) => user_id.to_string(),
+    }
+}
+
+// The game as PGN, with the tags other chess software looks for.
+pub async fn game_pgn(http: &Http, game: &Game) -> String {
+    let white = player_name(http, game.white).await;
+    let black = player_name(http, game.black).await;
+    pgn_with_names(game, &white, &black)
+}
+
+// Every game in `games` as one PGN file, looking each player's name up once.
+pub async fn archive_pgn(http: &Http, games: &[Game]) -> String {
+    let mut names: HashMap<u64, String> = HashMap::new();
+    let mut archive = Vec::new();
+    for game in games {
+        for player in [game.white, game.black] {
+            if let Entry::Vacant(entry) = names.entry(player) {
+                entry.insert(player_name(http, player).await);
+            }
+        }
+        archive.push(pgn_with_names(
+            game,
+            &names[&game.white],
+            &names[&game.black],
+        ));
+    }
+    archive.join("\n\n")
+}
+
+pub fn pgn_with_names(game: &Game, white: &str, black: &str) -> String {
+    let result = game.result.map_or("*", GameResult::score);
+    // PGN writes a draw as "1/2-1/2".
+    let result = if result == "½-½" { "1/2-1/2" } else { result };
+    let date = match game
+        .started_at
+        .and_then(|at| Utc.timestamp_opt(at, 0).single())
+    {
+        Some(at) => at.format("%Y.%m.%d").to_string(),
+        None => "????.??.??".to_string(),
+    };
+
+    let (event, site) = match &game.recorded {
+        Some(recorded) => (
+            recorded
+                .event
+                .clone()
+                .unwrap_or_else(|| format!("Club game #{}", game.id)),
+            "Over the board",
+        ),
+        None => (format!("Discord game #{}", game.id), "Discord"),
+    };
+    let mut headers = vec![
+        ("Event".to_string(), event),
+        ("Site".to_string(), site.to_string()),
+        ("Date".to_string(), date),
+        ("Round".to_string(), "-".to_string()),
+        ("White".to_string(), white.to_string()),
+        ("Black".to_string(), black.to_string()),
+        ("Result".to_string(), result.to_string()),
+        (
+            "TimeControl".to_string(),
+            game.time_control.map_or("-".to_string(), |tc| tc.pgn_tag()),
+        ),
+    ];
+    if game.chess960.is_some() {
+        headers.push(("Variant".to_string(), "Chess960".to_string()));
+    } else if game.variant != Variant::Standard {
+        headers.push(("Variant".to_string(), game.variant.title().to_string()));
+    }
+    if game.chess960.is_some() || game.start_fen.is_some() {
+        let fen = Fen::from_position(&game.start_position(), EnPassantMode::Legal);
+        headers.push(("SetUp".to_string(), "1".to_string()));
+        headers.push(("FEN".to_string(), fen.to_string()));
+    }
+    if let Some(ending) = &game.ending {
+        let termination = match ending.as_str() {
+            "lost on time" => "Time forfeit",
+            "abandoned" => "Abandoned",
+            ending if ending.starts_with("adjudicated") => "Adjudication",
+            _ => "Normal",
+        };
+        headers.push(("Termination".to_string(), termination.to_string()));
+    }
+
+    // An adjudicated game ends on what the engine thought of it, like Lichess writes evaluations.
+    let mut sans = game.sans(Notation::English);
+    let adjudicated = game
+        .ending
+        .as_deref()
+        .and_then(|ending| ending.strip_prefix("adjudicated by the engine at "));
+    if let (Some(score), Some(last)) = (adjudicated, sans.last_mut()) {
+        last.push_str(&format!(
+            " {{[%eval {}] Adjudicated by the engine at {}}}",
+            score.replace('+', ""),
+            score
+        ));
+    }
+
+    pgn::write(&headers, &sans, result)
+}
+
+#[command]
+#[description("List someone's finished games in this server, newest first.")]
+#[usage("[@user]")]
+async fn games(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let user_id = match args.current() {
+        Some(arg) => match utils::parse_username(arg) {
+            Some(id) => id,
+            None => {
+                msg.reply(&ctx.http, "Use `.games` or `.games @user`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => msg.author.id.0,
+    };
+    let guild_id = msg.guild_id.map(|id| id.0);
+
+    let games = game::read_games(&ctx.data, |store| {
+        store
+            .games
+            .iter()
+            .rev()
+            .filter(|game| {
+                game.is_over() && game.guild_id == guild_id && game.color_of(user_id).is_some()
+            })
+            .take(GAMES_SHOWN)
+            .cloned()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    if games.is_empty() {
+        msg.reply(
+            &ctx.http,
+            format!("<@{}> hasn't finished a game here yet.", user_id),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut desc = format!("<@{}>", user_id);
+    for game in &games {
+        let (color, opponent) = if game.white == user_id {
+            ("⚪", game.black)
+        } else {
+            ("⚫", game.white)
+        };
+        let mut line = format!("\n**#{}** {} vs <@{}>", game.id, color, opponent);
+        if let Some(result) = game.result {
+            line.push_str(&format!(": {}", result.score()));
+        }
+        if let Some(ending) = &game.ending {
+            line.push_str(&format!(", {}", ending));
+        }
+        if let Some(tc) = game.time_control {
+            line.push_str(&format!(" · {}", tc.describe()));
+        }
+        if let Some(ended_at) = game.ended_at {
+            line.push_str(&format!(" · <t:{}:d>", ended_at));
+        }
+        if game.recorded.as_ref().is_some_and(|r| !r.confirmed) {
+            line.push_str(" · unconfirmed");
+        }
+        desc.push_str(&line);
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Games");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| f.text(".pgn <game id> to download one"));
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Download a game played here as a PGN file.")]
+#[usage("<game id>")]
+#[example("12")]
+async fn pgn(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = match args.single::<String>() {
+        Ok(id) => id.trim_start_matches('#').parse::<u32>().ok(),
+        Err(_) => None,
+    };
+    let id = match id {
+        Some(id) => id,
+        None => {
+            msg.reply(&ctx.http, "Use `.pgn <game id>`, like `.pgn 12`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Games from other servers stay private to their players.
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let author = msg.author.id.0;
+    let game = game::read_games(&ctx.data, |store| {
+        store
+            .games
+            .iter()
+            .find(|game| {
+                game.id == id && (game.guild_id == guild_id || game.color_of(author).is_some())
+            })
+            .cloned()
+    })
+    .await;
+    let game = match game {
+        Some(game) => game,
+        None => {
+            msg.reply(&ctx.http, format!("I don't know a game #{} here.", id))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let text = game_pgn(&ctx.http, &game).await;
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!("Game #{}", game.id));
+            m.add_file(AttachmentType::Bytes {
+                data: Cow::Owned(text.into_bytes()),
+                filename: format!("game-{}.pgn", game.id),
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// One player's record against the other, from one side of the board.
+#[derive(Default)]
+struct Record {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Record {
+    fn add(&mut self, result: GameResult, color: Color) {
+        match result {
+            GameResult::Draw => self.draws += 1,
+            GameResult::Aborted => {}
+            GameResult::WhiteWon if color == Color::White => self.wins += 1,
+            GameResult::BlackWon if color == Color::Black => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn halves(&self) -> u32 {
+        self.wins * 2 + self.draws
+    }
+
+    fn describe(&self) -> String {
+        match self.games() {
+            0 => "no games".to_string(),
+            games => format!(
+                "{}% won, +{} ={} -{}",
+                self.wins * 100 / games,
+                self.wins,
+                self.draws,
+                self.losses
+            ),
+        }
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("headtohead", "vs")]
+#[description("Compare two players' results against each other in this server's games.")]
+#[usage("@user [@user]")]
+#[example("@magnus @hikaru")]
+async fn h2h(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let users: Option<Vec<u64>> = args
+        .iter::<String>()
+        .map(|arg| arg.ok().and_then(|arg| utils::parse_username(&arg)))
+        .collect();
+    let (a, b) = match users.as_deref() {
+        Some(&[b]) => (msg.author.id.0, b),
+        Some(&[a, b]) => (a, b),
+        _ => {
+            msg.reply(&ctx.http, "Use `.h2h @user` or `.h2h @user1 @user2`")
+                .await?;
+            return Ok(());
+        }
+    };
+    if a == b {
+        msg.reply(&ctx.http, "Pick two different players.").await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let games = game::read_games(&ctx.data, |store| {
+        store
+            .games
+            .iter()
+            .filter(|game| {
+                game.guild_id == guild_id
+                    && game
+                        .result
+                        .is_some_and(|result| result != GameResult::Aborted)
+                    && game.color_of(a).is_some()
+                    && game.color_of(b).is_some()
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    if games.is_empty() {
+        msg.reply(
+            &ctx.http,
+            format!("<@{}> and <@{}> haven't finished a game here yet.", a, b),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Records of `a` as white and as black.
+    let mut as_white = Record::default();
+    let mut as_black = Record::default();
+    let mut plies = 0;
+    for game in &games {
+        let result = game.result.expect("filtered on finished games");
+        match game.color_of(a) {
+            Some(Color::White) => as_white.add(result, Color::White),
+            _ => as_black.add(result, Color::Black),
+        }
+        plies += game.moves.len();
+    }
+    let total = Record {
+        wins: as_white.wins + as_black.wins,
+        draws: as_white.draws + as_black.draws,
+        losses: as_white.losses + as_black.losses,
+    };
+    // Full moves, counting a last move by white as one.
+    let moves = (plies + games.len()) / 2 / games.len();
+
+    let desc = format!(
+        "<@{}> {} - {} <@{}>\n\n\
+         **<@{}> as white:** {}\n\
+         **<@{}> as black:** {}\n\n\
+         {} games, {} moves long on average",
+        a,
+        game::format_points(total.halves()),
+        game::format_points(total.games() * 2 - total.halves()),
+        b,
+        a,
+        as_white.describe(),
+        a,
+        as_black.describe(),
+        total.games(),
+        moves
+    );
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Head to head");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn parse_result(text: &str) -> Option<GameResult> {
+    match text {
+        "1-0" => Some(GameResult::WhiteWon),
+        "0-1" => Some(GameResult::BlackWon),
+        "1/2-1/2" | "½-½" | "1/2" | "½" | "draw" => Some(GameResult::Draw),
+        _ => None,
+    }
+}
+
+// The moves of an attached .pgn file, or why they can't be used for the game.
+async fn attached_moves(
+    msg: &Message,
+    notation: Notation,
+    result: GameResult,
+) -> Result<Option<Vec<String>>, String> {
+    let attachment = match msg.attachments.iter().find(|attachment| {
+        attachment.filename.to_lowercase().ends_with(".pgn") && attachment.size <= MAX_PGN_BYTES
+    }) {
+        Some(attachment) => attachment,
+        None => return Ok(None),
+    };
+    let bytes = attachment.download().await.map_err(|why| {
+        println!("Error downloading a PGN: {:?}", why);
+        "I couldn't download the PGN, try again?".to_string()
+    })?;
+    let game = pgn::parse_in(&String::from_utf8_lossy(&bytes), notation)
+        .map_err(|why| format!("I can't read the PGN: {}.", why))?;
+    if game.start != Chess::default() {
+        return Err("I can only record games from the usual starting position.".to_string());
+    }
+    let written = game.result();
+    if written != "*" && parse_result(written) != Some(result) {
+        return Err(format!(
+            "The PGN says the game ended {}, not {}.",
+            written,
+            result.score()
+        ));
+    }
+    Ok(Some(
+        game.moves
+            .iter()
+            .map(|m| UciMove::from_move(*m, CastlingMode::Standard).to_string())
+            .collect(),
+    ))
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Enter the result of a game played over the board, so it counts for ratings like games \
+     played here once one of the players confirms it. Attach the PGN to keep the moves too."
+)]
+#[usage("@white @black <1-0, 0-1 or 1/2-1/2> [event]")]
+#[example("@magnus @hikaru 1-0 Club night")]
+async fn record(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Use `.record @white @black 1-0 [event]`, with 0-1 or 1/2-1/2 for other results";
+    let white = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| utils::parse_username(&arg));
+    let black = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| utils::parse_username(&arg));
+    let result = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_result(&arg.to_lowercase()));
+    let (white, black, result) = match (white, black, result) {
+        (Some(white), Some(black), Some(result)) => (white, black, result),
+        _ => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    if white == black {
+        msg.reply(&ctx.http, "Pick two different players.").await?;
+        return Ok(());
+    }
+    let event = Some(args.rest().trim())
+        .filter(|event| !event.is_empty())
+        .map(str::to_string);
+
+    let guild_id = msg.guild_id.expect("only_in guilds");
+    let notation = settings::guild(&ctx.data, guild_id)
+        .await
+        .notation
+        .unwrap_or_default();
+    let moves = match attached_moves(msg, notation, result).await {
+        Ok(moves) => moves.unwrap_or_default(),
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    let mut game =
+        GameOptions::default().new_game(Some(guild_id.0), msg.channel_id.0, (white, black));
+    game.white = white;
+    game.black = black;
+    game.moves = moves;
+    game.finish(result, "played over the board");
+    game.recorded = Some(Recorded {
+        by: msg.author.id.0,
+        event: event.clone(),
+        confirmed: false,
+    });
+    let game = game::with_games(&ctx.data, |store| store.record(game)).await;
+
+    // A moderator entering their own game has it confirmed by their opponent.
+    let confirmers = match game.color_of(msg.author.id.0) {
+        Some(color) => format!("<@{}>", game.player(!color)),
+        None => format!("<@{}> or <@{}>", white, black),
+    };
+    let mut text = format!(
+        "Recorded game #{}: <@{}> {} <@{}>",
+        game.id,
+        white,
+        result.score(),
+        black
+    );
+    if let Some(event) = &event {
+        text.push_str(&format!(" at {}", event));
+    }
+    if !game.moves.is_empty() {
+        text.push_str(&format!(", {} moves", game.moves.len().div_ceil(2)));
+    }
+    text.push_str(&format!(
+        ".\n{}, is that right? It counts for ratings once you confirm it.",
+        confirmers
+    ));
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(text);
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.custom_id(format!("{}{}", CONFIRM_PREFIX, game.id));
+                        b.label("That's right");
+                        b.style(ButtonStyle::Success)
+                    });
+                    row.create_button(|b| {
+                        b.custom_id(format!("{}{}", REJECT_PREFIX, game.id));
+                        b.label("That's wrong");
+                        b.style(ButtonStyle::Danger)
+                    })
+                })
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn reply_privately(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error replying to a recorded game button: {:?}", why);
+    }
+}
+
+// Confirm or throw out a recorded game when one of its players presses a button under it.
+// Returns false if the button isn't ours.
+async fn answer_record(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    let (confirm, game_id) = match (
+        id.strip_prefix(CONFIRM_PREFIX),
+        id.strip_prefix(REJECT_PREFIX),
+    ) {
+        (Some(game_id), _) => (true, game_id),
+        (None, Some(game_id)) => (false, game_id),
+        (None, None) => return false,
+    };
+    let game_id = match game_id.parse::<u32>() {
+        Ok(game_id) => game_id,
+        Err(_) => return true,
+    };
+
+    let clicker = component.user.id.0;
+    let answer = game::with_games(&ctx.data, |store| {
+        let index = store.games.iter().position(|game| game.id == game_id)?;
+        let game = &mut store.games[index];
+        let player = game.color_of(clicker).is_some();
+        let recorded = game.recorded.as_mut().filter(|r| !r.confirmed)?;
+        // Whoever entered the game can't vouch for it themselves.
+        if !player || recorded.by == clicker {
+            return Some(Err("This is for the players to confirm."));
+        }
+        if confirm {
+            recorded.confirmed = true;
+        } else {
+            store.games.remove(index);
+        }
+        Some(Ok(()))
+    })
+    .await;
+
+    let text = match answer {
+        Some(Ok(())) if confirm => {
+            let mut text = format!("Game #{} is confirmed by <@{}>.", game_id, clicker);
+            let ratings = game::read_games(&ctx.data, |store| {
+                let game = store.games.iter().find(|game| game.id == game_id)?;
+                rating::game_summary(&store.ratings, game)
+            })
+            .await;
+            if let Some(ratings) = ratings {
+                text.push_str(&format!("\n{}", ratings));
+            }
+            text
+        }
+        Some(Ok(())) => format!(
+            "<@{}> says game #{} is wrong, so it was thrown out.",
+            clicker, game_id
+        ),
+        Some(Err(why)) => {
+            reply_privately(ctx, component, why).await;
+            return true;
+        }
+        None => "This game is already settled.".to_string(),
+    };
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.components(|c| c)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error answering a recorded game: {:?}", why);
+    }
+
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("standings")]
+#[description(
+    "Show the standings of an over the board event, from its games entered with `.record`: everyone's score and performance rating."
+)]
+#[usage("<event>")]
+#[example("Club night")]
+async fn event(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = args.rest().trim();
+    if name.is_empty() {
+        msg.reply(
+            &ctx.http,
+            "Use `.event <name>`, with the event the games were recorded at",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let (games, mut standings) = game::read_games(&ctx.data, |store| {
+        let games: Vec<&Game> = store
+            .games
+            .iter()
+            .filter(|game| {
+                game.guild_id == guild_id
+                    && game.recorded.as_ref().is_some_and(|recorded| {
+                        recorded.confirmed
+                            && recorded
+                                .event
+                                .as_deref()
+                                .is_some_and(|event| event.eq_ignore_ascii_case(name))
+                    })
+            })
+            .collect();
+
+        let mut records: Vec<(u64, Record)> = Vec::new();
+        for game in &games {
+            let result = match game.result {
+                Some(result) => result,
+                None => continue,
+            };
+            for color in [Color::White, Color::Black] {
+                let player = game.player(color);
+                let index = match records.iter().position(|(id, _)| *id == player) {
+                    Some(index) => index,
+                    None => {
+                        records.push((player, Record::default()));
+                        records.len() - 1
+                    }
+                };
+                records[index].1.add(result, color);
+            }
+        }
+        let standings: Vec<(u64, Record, Option<i32>)> = records
+            .into_iter()
+            .map(|(player, record)| {
+                let performance = rating::performance_in(&store.ratings, &games, player);
+                (player, record, performance)
+            })
+            .collect();
+        (games.len(), standings)
+    })
+    .await;
+    if games == 0 {
+        msg.reply(
+            &ctx.http,
+            format!("There are no confirmed games from {} here.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    standings.sort_by(|a, b| b.1.halves().cmp(&a.1.halves()).then(b.2.cmp(&a.2)));
+    let desc = standings
+        .iter()
+        .enumerate()
+        .map(|(place, (player, record, performance))| {
+            let mut line = format!(
+                "**{}.** <@{}> {}/{}",
+                place + 1,
+                player,
+                game::format_points(record.halves()),
+                record.games()
+            );
+            if let Some(performance) = performance {
+                line.push_str(&format!(" · performance {}", performance));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(name);
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| f.text(format!("{} games played over the board", games)));
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serenity::{http::Http, model::channel::Message, prelude::*};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Don't edit a job's status more often than this, Discord rate limits edits.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+
+// Heavy engine requests like `.eval` and `.analyze` wait here for their turn, so a few people
+// asking at once don't take every engine from the pool and stall the games.
+pub struct JobQueue {
+    turns: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+impl JobQueue {
+    pub fn new(size: usize) -> JobQueue {
+        JobQueue {
+            turns: Arc::new(Semaphore::new(size.max(1))),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    // How many jobs are waiting for a turn.
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+}
+
+pub struct JobQueueContainer;
+
+impl TypeMapKey for JobQueueContainer {
+    type Value = Arc<JobQueue>;
+}
+
+pub async fn queue(data: &RwLock<TypeMap>) -> Arc<JobQueue> {
+    let data = data.read().await;
+    data.get::<JobQueueContainer>()
+        .expect("Expected job queue in typemap.")
+        .clone()
+}
+
+// A job with its turn in the queue, and the status message it keeps up to date. The turn is
+// given up when the job is dropped.
+pub struct Job {
+    http: Arc<Http>,
+    status: Message,
+    last_update: Instant,
+    _turn: OwnedSemaphorePermit,
+}
+
+// Reply to `msg` with a status message and wait for a turn, saying how many are ahead while
+// queued. `what` is what's being done, like "Analyzing the game".
+pub async fn start(ctx: &Context, msg: &Message, what: &str) -> serenity::Result<Job> {
+    let queue = queue(&ctx.data).await;
+    if let Ok(turn) = queue.turns.clone().try_acquire_owned() {
+        let status = msg.reply(&ctx.http, format!("🔍 {}…", what)).await?;
+        return Ok(Job {
+            http: ctx.http.clone(),
+            status,
+            last_update: Instant::now(),
+            _turn: turn,
+        });
+    }
+
+    let ahead = queue.waiting.fetch_add(1, Ordering::SeqCst);
+    let text = match ahead {
+        0 => "⏳ Queued, you're next…".to_string(),
+        ahead => format!("⏳ Queued, {} ahead of you…", ahead),
+    };
+    let status = msg.reply(&ctx.http, text).await;
+    let turn = queue.turns.clone().acquire_owned().await;
+    queue.waiting.fetch_sub(1, Ordering::SeqCst);
+    let turn = turn.expect("the job queue is never closed");
+
+    let mut job = Job {
+        http: ctx.http.clone(),
+        status: status?,
+        last_update: Instant::now(),
+        _turn: turn,
+    };
+    job.set_status(&format!("🔍 {}…", what)).await;
+    Ok(job)
+}
+
+impl Job {
+    async fn set_status(&mut self, text: &str) {
+        let result = self.status.edit(&self.http, |m| m.content(text)).await;
+        if let Err(why) = result {
+            println!("Error updating a job's status: {:?}", why);
+        }
+        self.last_update = Instant::now();
+    }
+
+    // Say how far along the job is, if it hasn't in a while.
+    pub async fn progress(&mut self, text: &str) {
+        if self.last_update.elapsed() >= PROGRESS_INTERVAL {
+            self.set_status(&format!("🔍 {}", text)).await;
+        }
+    }
+
+    // Mark the job done, once its result is posted.
+    pub async fn done(mut self) {
+        self.set_status("✅ Done").await;
+    }
+
+    // Replace the status with why the job didn't work out.
+    pub async fn fail(mut self, text: &str) {
+        self.set_status(&format!("❌ {}", text)).await;
+    }
+}
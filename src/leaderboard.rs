@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+
+use crate::{
+    card::{self, Card},
+    diagram, game,
+    guesseval::{self, GuessWin},
+    modules::BotModule,
+    puzzle::{self, PuzzleStats},
+    rating::Rating,
+    EMBED_SIDE_COLOR,
+};
+
+const BUTTON_PREFIX: &str = "leaderboard:";
+
+const PAGE_SIZE: usize = 10;
+const WEEK_SECS: i64 = 7 * 24 * 3600;
+
+// Layout of `.leaderboard fancy`, in pixels.
+const CARD_WIDTH: f32 = 600.0;
+const CARD_HEADER: f32 = 76.0;
+const CARD_ROW: f32 = 56.0;
+
+#[group]
+#[commands(leaderboard)]
+struct Leaderboards;
+
+pub struct LeaderboardsModule;
+
+#[async_trait]
+impl BotModule for LeaderboardsModule {
+    fn name(&self) -> &'static str {
+        "leaderboards"
+    }
+
+    fn description(&self) -> &'static str {
+        "Who's on top in the server, see `.leaderboard`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&LEADERBOARDS_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// What players are ranked by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Board {
+    // Server rating, or rating gained over the week.
+    Rating,
+    // Rated games played.
+    Games,
+    // Puzzle rating, or puzzle rating gained over the week, of people who try puzzles here.
+    Puzzles,
+    // Points from guess the eval rounds here.
+    Guesses,
+    // Points from best move quizzes here.
+    Quiz,
+}
+
+impl Board {
+    const ALL: [Board; 5] = [
+        Board::Rating,
+        Board::Games,
+        Board::Puzzles,
+        Board::Guesses,
+        Board::Quiz,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Board::Rating => "ratings",
+            Board::Games => "games",
+            Board::Puzzles => "puzzles",
+            Board::Guesses => "guesses",
+            Board::Quiz => "quiz",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Board::Rating => "Top ratings",
+            Board::Games => "Most games",
+            Board::Puzzles => "Puzzle ratings",
+            Board::Guesses => "Guess the eval",
+            Board::Quiz => "Best move quiz",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Board> {
+        match name.to_lowercase().as_str() {
+            "rating" | "elo" => Some(Board::Rating),
+            "game" | "active" => Some(Board::Games),
+            "puzzle" | "tactics" => Some(Board::Puzzles),
+            "guess" | "guesseval" | "gte" => Some(Board::Guesses),
+            "quizzes" | "bestmove" => Some(Board::Quiz),
+            name => Board::ALL
+                .iter()
+                .copied()
+                .find(|board| board.name() == name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Period {
+    Week,
+    AllTime,
+}
+
+impl Period {
+    fn name(self) -> &'static str {
+        match self {
+            Period::Week => "week",
+            Period::AllTime => "all",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Period::Week => "This week",
+            Period::AllTime => "All time",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Period> {
+        match name.to_lowercase().as_str() {
+            "week" | "weekly" => Some(Period::Week),
+            "all" | "alltime" | "all-time" => Some(Period::AllTime),
+            _ => None,
+        }
+    }
+
+    fn other(self) -> Period {
+        match self {
+            Period::Week => Period::AllTime,
+            Period::AllTime => Period::Week,
+        }
+    }
+}
+
+// One page of one leaderboard. Buttons carry the page they lead to in their id, like
+// "leaderboard:games:week:2", so nothing needs to be kept about the message.
+#[derive(Debug, Clone, Copy)]
+struct View {
+    board: Board,
+    period: Period,
+    page: usize,
+}
+
+impl View {
+    fn button_id(self) -> String {
+        format!(
+            "{}{}:{}:{}",
+            BUTTON_PREFIX,
+            self.board.name(),
+            self.period.name(),
+            self.page
+        )
+    }
+
+    fn parse_button_id(id: &str) -> Option<View> {
+        let mut parts = id.strip_prefix(BUTTON_PREFIX)?.split(':');
+        let board = Board::from_name(parts.next()?)?;
+        let period = Period::from_name(parts.next()?)?;
+        let page = parts.next()?.parse().ok()?;
+        Some(View {
+            board,
+            period,
+            page,
+        })
+    }
+}
+
+// Players and their scores, best first.
+fn standings(ratings: &HashMap<u64, Rating>, board: Board, period: Period) -> Vec<(u64, i32)> {
+    let since = Utc::now().timestamp() - WEEK_SECS;
+    let mut standings: Vec<(u64, i32)> = ratings
+        .iter()
+        .filter_map(|(&user, rating)| {
+            let recent = rating.history.iter().filter(|change| change.at >= since);
+            let score = match (board, period) {
+                (Board::Rating, Period::AllTime) => rating.rating,
+                (Board::Rating, Period::Week) => recent.map(|change| change.change).sum(),
+                (Board::Games, Period::AllTime) => rating.games() as i32,
+                (Board::Games, Period::Week) => recent.count() as i32,
+                (Board::Puzzles | Board::Guesses | Board::Quiz, _) => return None,
+            };
+            let played = match period {
+                Period::AllTime => rating.games() > 0,
+                Period::Week => rating.history.iter().any(|change| change.at >= since),
+            };
+            if played {
+                Some((user, score))
+            } else {
+                None
+            }
+        })
+        .collect();
+    standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    standings
+}
+
+// Puzzle ratings are everyone's own, so a server's board has whoever tried puzzles there.
+fn puzzle_standings(
+    players: &HashMap<u64, PuzzleStats>,
+    guild_id: u64,
+    period: Period,
+) -> Vec<(u64, i32)> {
+    let since = match period {
+        Period::Week => Utc::now().timestamp() - WEEK_SECS,
+        Period::AllTime => i64::MIN,
+    };
+    let mut standings: Vec<(u64, i32)> = players
+        .iter()
+        .filter(|(_, stats)| {
+            stats
+                .attempts
+                .iter()
+                .any(|attempt| attempt.guild_id == Some(guild_id) && attempt.at >= since)
+        })
+        .map(|(&user, stats)| {
+            let score = match period {
+                Period::AllTime => stats.rating,
+                Period::Week => stats
+                    .attempts
+                    .iter()
+                    .filter(|attempt| attempt.at >= since)
+                    .map(|attempt| attempt.change)
+                    .sum(),
+            };
+            (user, score)
+        })
+        .collect();
+    standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    standings
+}
+
+// Points won in guess the eval rounds or best move quizzes played here.
+fn guess_standings(
+    players: &HashMap<u64, Vec<GuessWin>>,
+    guild_id: u64,
+    period: Period,
+) -> Vec<(u64, i32)> {
+    let since = match period {
+        Period::Week => Utc::now().timestamp() - WEEK_SECS,
+        Period::AllTime => i64::MIN,
+    };
+    let mut standings: Vec<(u64, i32)> = players
+        .iter()
+        .filter_map(|(&user, wins)| {
+            let points: u32 = wins
+                .iter()
+                .filter(|win| win.guild_id == Some(guild_id) && win.at >= since)
+                .map(|win| win.points)
+                .sum();
+            if points > 0 {
+                Some((user, points as i32))
+            } else {
+                None
+            }
+        })
+        .collect();
+    standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    standings
+}
+
+async fn board_standings(data: &RwLock<TypeMap>, guild_id: u64, view: View) -> Vec<(u64, i32)> {
+    match view.board {
+        Board::Puzzles => {
+            puzzle::read_puzzles(data, |store| {
+                puzzle_standings(&store.players, guild_id, view.period)
+            })
+            .await
+        }
+        Board::Guesses => {
+            guesseval::read_guesses(data, |store| {
+                guess_standings(&store.players, guild_id, view.period)
+            })
+            .await
+        }
+        Board::Quiz => {
+            guesseval::read_guesses(data, |store| {
+                guess_standings(&store.quiz_players, guild_id, view.period)
+            })
+            .await
+        }
+        _ => standings(
+            &guild_ratings(data, guild_id).await,
+            view.board,
+            view.period,
+        ),
+    }
+}
+
+fn page_count(standings: &[(u64, i32)]) -> usize {
+    standings.len().div_ceil(PAGE_SIZE).max(1)
+}
+
+fn format_score(board: Board, period: Period, score: i32) -> String {
+    match (board, period) {
+        (Board::Rating | Board::Puzzles, Period::AllTime) => score.to_string(),
+        (Board::Rating | Board::Puzzles, Period::Week) => format!("{:+}", score),
+        (Board::Games, _) if score == 1 => "1 game".to_string(),
+        (Board::Games, _) => format!("{} games", score),
+        (Board::Guesses | Board::Quiz, _) if score == 1 => "1 point".to_string(),
+        (Board::Guesses | Board::Quiz, _) => format!("{} points", score),
+    }
+}
+
+fn leaderboard_embed<'a>(
+    e: &'a mut CreateEmbed,
+    view: View,
+    standings: &[(u64, i32)],
+) -> &'a mut CreateEmbed {
+    let start = view.page * PAGE_SIZE;
+    let desc = if standings.is_empty() {
+        match (view.board, view.period) {
+            (Board::Puzzles, Period::Week) => "Nobody has tried a puzzle here this week.",
+            (Board::Puzzles, Period::AllTime) => "Nobody has tried a puzzle here yet.",
+            (Board::Guesses, Period::Week) => "Nobody has scored at guess the eval here this week.",
+            (Board::Guesses, Period::AllTime) => "Nobody has scored at guess the eval here yet.",
+            (Board::Quiz, Period::Week) => "Nobody has found a best move here this week.",
+            (Board::Quiz, Period::AllTime) => "Nobody has found a best move here yet.",
+            (_, Period::Week) => "Nobody has finished a rated game here this week.",
+            (_, Period::AllTime) => "Nobody has finished a rated game here yet.",
+        }
+        .to_string()
+    } else {
+        standings
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(PAGE_SIZE)
+            .map(|(i, &(user, score))| {
+                format!(
+                    "**{}.** <@{}> {}",
+                    i + 1,
+                    user,
+                    format_score(view.board, view.period, score)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    e.title(format!("{} · {}", view.board.title(), view.period.title()));
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e.footer(|f| {
+        f.text(format!(
+            "Page {} of {}",
+            view.page + 1,
+            page_count(standings)
+        ))
+    });
+    e
+}
+
+fn leaderboard_buttons(
+    c: &mut CreateComponents,
+    view: View,
+    pages: usize,
+) -> &mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("◀");
+            b.custom_id(
+                View {
+                    page: view.page.saturating_sub(1),
+                    ..view
+                }
+                .button_id(),
+            );
+            b.disabled(view.page == 0);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("▶");
+            b.custom_id(
+                View {
+                    page: view.page + 1,
+                    ..view
+                }
+                .button_id(),
+            );
+            b.disabled(view.page + 1 >= pages);
+            b
+        });
+        row.create_button(|b| {
+            let period = view.period.other();
+            b.style(ButtonStyle::Primary);
+            b.label(period.title());
+            b.custom_id(
+                View {
+                    period,
+                    page: 0,
+                    ..view
+                }
+                .button_id(),
+            );
+            b
+        });
+        row
+    });
+    // Discord allows five buttons a row, so the other boards get their own.
+    c.create_action_row(|row| {
+        for &board in Board::ALL.iter().filter(|&&board| board != view.board) {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Primary);
+                b.label(board.title());
+                b.custom_id(
+                    View {
+                        board,
+                        page: 0,
+                        ..view
+                    }
+                    .button_id(),
+                );
+                b
+            });
+        }
+        row
+    })
+}
+
+// Which way each player's score went this week, for the arrows on the picture. Weekly boards
+// are the change already, and games and guesses only ever go up.
+async fn trends(data: &RwLock<TypeMap>, guild_id: u64, view: View) -> HashMap<u64, i32> {
+    match (view.board, view.period) {
+        (Board::Rating | Board::Puzzles, Period::AllTime) => {
+            let week = View {
+                period: Period::Week,
+                ..view
+            };
+            board_standings(data, guild_id, week)
+                .await
+                .into_iter()
+                .collect()
+        }
+        _ => HashMap::new(),
+    }
+}
+
+// A ▲ or ▼ for a score that moved, or a dash, with its top left corner at x, y.
+fn trend_arrow(card: &mut Card, x: f32, y: f32, change: i32) {
+    let shape = match change {
+        0 => format!(
+            r#"<rect x="{}" y="{}" width="14" height="4" fill="{}"/>"#,
+            x,
+            y + 8.0,
+            card::MUTED
+        ),
+        change if change > 0 => format!(
+            r#"<path d="M{} {}l7 -12l7 12z" fill="{}"/>"#,
+            x,
+            y + 16.0,
+            card::GREEN
+        ),
+        _ => format!(
+            r#"<path d="M{} {}l7 12l7 -12z" fill="{}"/>"#,
+            x,
+            y + 4.0,
+            card::RED
+        ),
+    };
+    card.push(&shape);
+}
+
+// The top of a board as a picture, with everyone's avatar and name.
+async fn leaderboard_card(
+    ctx: &Context,
+    guild_id: GuildId,
+    view: View,
+    standings: &[(u64, i32)],
+) -> Option<Vec<u8>> {
+    let top = &standings[..standings.len().min(PAGE_SIZE)];
+    let trends = trends(&ctx.data, guild_id.0, view).await;
+    let height = CARD_HEADER + CARD_ROW * top.len().max(1) as f32 + 16.0;
+    let mut card = Card::new(CARD_WIDTH as u32, height as u32);
+
+    card.rect(0.0, 0.0, 8.0, height, 0.0, card::ACCENT);
+    let title = format!("{} - {}", view.board.title(), view.period.title());
+    card.text(28.0, 24.0, 4.0, card::TEXT, &title);
+    if top.is_empty() {
+        card.text(
+            28.0,
+            CARD_HEADER + 18.0,
+            2.0,
+            card::MUTED,
+            "Nobody here yet.",
+        );
+    }
+
+    for (i, &(user_id, score)) in top.iter().enumerate() {
+        let y = CARD_HEADER + CARD_ROW * i as f32;
+        if i % 2 == 0 {
+            card.rect(20.0, y, CARD_WIDTH - 36.0, CARD_ROW - 6.0, 8.0, card::PANEL);
+        }
+        let middle = y + (CARD_ROW - 6.0) / 2.0;
+        let rank_color = if i < 3 { card::ACCENT } else { card::MUTED };
+        card.text_right(64.0, middle - 10.5, 3.0, rank_color, &(i + 1).to_string());
+
+        // Members who left are still on the board under their account name.
+        let (name, user) = match guild_id.member(&ctx.http, user_id).await {
+            Ok(member) => (member.display_name().into_owned(), Some(member.user)),
+            Err(_) => match UserId(user_id).to_user(&ctx.http).await {
+                Ok(user) => (user.name.clone(), Some(user)),
+                Err(_) => (user_id.to_string(), None),
+            },
+        };
+        let avatar = match &user {
+            Some(user) => card::avatar(&ctx.data, user).await,
+            None => None,
+        };
+        card.avatar(avatar, 98.0, middle, 19.0);
+
+        let score_text = format_score(view.board, view.period, score);
+        let score_width = card::text_width(&score_text, 3.0);
+        let name = card::fit(&name, 3.0, CARD_WIDTH - 190.0 - score_width);
+        card.text(130.0, middle - 10.5, 3.0, card::TEXT, &name);
+        card.text_right(
+            CARD_WIDTH - 50.0,
+            middle - 10.5,
+            3.0,
+            card::TEXT,
+            &score_text,
+        );
+
+        let change = match (view.board, view.period) {
+            (Board::Rating | Board::Puzzles, Period::Week) => Some(score),
+            _ => trends.get(&user_id).copied(),
+        };
+        let arrow_x = CARD_WIDTH - 40.0;
+        match change {
+            Some(change) => trend_arrow(&mut card, arrow_x, middle - 10.0, change),
+            // Nothing this week on a board that has trends.
+            None if matches!(view.board, Board::Rating | Board::Puzzles) => {
+                trend_arrow(&mut card, arrow_x, middle - 10.0, 0)
+            }
+            None => {}
+        }
+    }
+
+    card.png()
+}
+
+async fn guild_ratings(data: &RwLock<TypeMap>, guild_id: u64) -> HashMap<u64, Rating> {
+    game::read_games(data, |store| {
+        store.ratings.get(&guild_id).cloned().unwrap_or_default()
+    })
+    .await
+}
+
+// Turn the page when one of a leaderboard's buttons is pressed. Returns false if the button isn't
+// ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let view = match View::parse_button_id(&component.data.custom_id) {
+        Some(view) => view,
+        None => return false,
+    };
+    let guild_id = match component.guild_id {
+        Some(guild_id) => guild_id,
+        None => return true,
+    };
+
+    let standings = board_standings(&ctx.data, guild_id.0, view).await;
+    let pages = page_count(&standings);
+    // The board may have shrunk since the buttons were made.
+    let view = View {
+        page: view.page.min(pages - 1),
+        ..view
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.create_embed(|e| leaderboard_embed(e, view, &standings));
+                d.components(|c| leaderboard_buttons(c, view, pages))
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error turning a leaderboard page: {:?}", why);
+    }
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("lb", "top")]
+#[description(
+    "Show the server's best rated and most active players, or its best puzzle solvers, eval guessers and quiz players, this week or of all time. `fancy` draws the top 10 as a picture."
+)]
+#[usage("[ratings|games|puzzles|guesses|quiz] [week|all] [fancy]")]
+#[example("games week")]
+async fn leaderboard(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let mut view = View {
+        board: Board::Rating,
+        period: Period::AllTime,
+        page: 0,
+    };
+    let mut fancy = false;
+    for arg in args.iter::<String>().flatten() {
+        if matches!(arg.to_lowercase().as_str(), "fancy" | "card" | "image") {
+            fancy = true;
+        } else if let Some(board) = Board::from_name(&arg) {
+            view.board = board;
+        } else if let Some(period) = Period::from_name(&arg) {
+            view.period = period;
+        } else {
+            msg.reply(
+                &ctx.http,
+                "Use `.leaderboard [ratings|games|puzzles|guesses|quiz] [week|all] [fancy]`",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let standings = board_standings(&ctx.data, guild_id.0, view).await;
+    let pages = page_count(&standings);
+
+    // Where pictures can't be posted, the plain board will do.
+    if fancy && diagram::images_allowed(&ctx.http, &ctx.data, msg.channel_id).await {
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let png = leaderboard_card(ctx, guild_id, view, &standings).await;
+        if let Ok(typing) = typing {
+            typing.stop();
+        }
+        if let Some(png) = png {
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.add_file(card::attachment(png, "leaderboard.png"));
+                    m
+                })
+                .await?;
+            return Ok(());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| leaderboard_embed(e, view, &standings));
+            m.components(|c| leaderboard_buttons(c, view, pages));
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,916 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+const LICHESS_URL: &str = "https://lichess.org";
+const EXPLORER_URL: &str = "https://explorer.lichess.ovh";
+
+#[derive(Debug, Deserialize)]
+pub struct Game {
+    pub id: String,
+    #[serde(default)]
+    pub rated: bool,
+    pub speed: String,
+    pub status: String,
+    pub players: Players,
+    // "white" or "black", missing for draws and unfinished games.
+    pub winner: Option<String>,
+    pub opening: Option<Opening>,
+    // Space-separated SAN moves.
+    #[serde(default)]
+    pub moves: String,
+    // When the game started, in milliseconds since the epoch.
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<u64>,
+    // The position the game ended in, or is at now.
+    #[serde(rename = "lastFen")]
+    pub last_fen: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Players {
+    pub white: Player,
+    pub black: Player,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Player {
+    pub user: Option<User>,
+    pub rating: Option<u32>,
+    #[serde(rename = "aiLevel")]
+    pub ai_level: Option<u8>,
+    // Only there for games that were analysed on Lichess.
+    pub analysis: Option<PlayerAnalysis>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayerAnalysis {
+    pub accuracy: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+}
+
+// A puzzle from the puzzle API, with the game it was taken from.
+#[derive(Debug, Deserialize)]
+pub struct PuzzleAndGame {
+    pub game: PuzzleGame,
+    pub puzzle: Puzzle,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PuzzleGame {
+    // Space-separated SAN moves up to the puzzle's position.
+    pub pgn: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Puzzle {
+    pub id: String,
+    pub rating: u32,
+    // UCI moves, the solver's first.
+    pub solution: Vec<String>,
+    #[serde(default)]
+    pub themes: Vec<String>,
+}
+
+impl Player {
+    // "GM DrNykterstein (3000)", "Stockfish level 8" or "Anonymous".
+    pub fn display_name(&self) -> String {
+        let name = match (&self.user, self.ai_level) {
+            (Some(user), _) => match &user.title {
+                Some(title) => format!("{} {}", title, user.name),
+                None => user.name.clone(),
+            },
+            (None, Some(level)) => format!("Stockfish level {}", level),
+            (None, None) => "Anonymous".to_string(),
+        };
+
+        match self.rating {
+            Some(rating) => format!("{} ({})", name, rating),
+            None => name,
+        }
+    }
+}
+
+impl Players {
+    // Which side a user played, if they played at all.
+    pub fn color_of(&self, username: &str) -> Option<&'static str> {
+        let is = |player: &Player| {
+            player
+                .user
+                .as_ref()
+                .is_some_and(|user| user.name.eq_ignore_ascii_case(username))
+        };
+
+        if is(&self.white) {
+            Some("white")
+        } else if is(&self.black) {
+            Some("black")
+        } else {
+            None
+        }
+    }
+}
+
+impl Game {
+    pub fn result(&self) -> &'static str {
+        match (self.winner.as_deref(), self.status.as_str()) {
+            (Some("white"), _) => "1-0",
+            (Some("black"), _) => "0-1",
+            (None, "draw") | (None, "stalemate") => "½-½",
+            _ => "*",
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}/{}", LICHESS_URL, self.id)
+    }
+
+    // Small GIF of the final position.
+    pub fn thumbnail_url(&self) -> String {
+        format!(
+            "https://lichess1.org/game/export/gif/thumbnail/{}.gif",
+            self.id
+        )
+    }
+}
+
+// Pull the game id out of a link like https://lichess.org/abcdEFGH or
+// https://lichess.org/abcdEFGHijkl/black#32.
+pub fn game_id_from_url(url: &str) -> Option<String> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .strip_prefix("lichess.org/")?;
+
+    let segment = rest.split(['/', '#', '?']).next()?;
+    let is_game_id = (segment.len() == 8 || segment.len() == 12)
+        && segment.chars().all(|c| c.is_ascii_alphanumeric());
+
+    if is_game_id {
+        Some(segment[..8].to_string())
+    } else {
+        None
+    }
+}
+
+pub async fn export_game(client: &reqwest::Client, id: &str) -> reqwest::Result<Game> {
+    client
+        .get(format!("{}/game/export/{}", LICHESS_URL, id))
+        .query(&[
+            ("moves", "true"),
+            ("opening", "true"),
+            ("clocks", "false"),
+            ("accuracy", "true"),
+            ("lastFen", "true"),
+        ])
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+pub async fn daily_puzzle(client: &reqwest::Client) -> reqwest::Result<PuzzleAndGame> {
+    client
+        .get(format!("{}/api/puzzle/daily", LICHESS_URL))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// A puzzle to train with. `angle` is a theme like "fork" or "mateIn2", and `difficulty` goes
+// from "easiest" to "hardest" around the solver's rating, 1500 for the bot.
+pub async fn next_puzzle(
+    client: &reqwest::Client,
+    angle: Option<&str>,
+    difficulty: &str,
+) -> reqwest::Result<PuzzleAndGame> {
+    let mut query = vec![("difficulty", difficulty)];
+    if let Some(angle) = angle {
+        query.push(("angle", angle));
+    }
+    client
+        .get(format!("{}/api/puzzle/next", LICHESS_URL))
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// A Lichess account's public profile.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Account {
+    pub username: String,
+    pub title: Option<String>,
+    pub profile: Option<Profile>,
+    // Ratings keyed by speed or variant, like "blitz" or "puzzle".
+    #[serde(default)]
+    pub perfs: HashMap<String, Perf>,
+    // When they were last online, in milliseconds since the epoch.
+    #[serde(rename = "seenAt")]
+    pub seen_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub bio: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Perf {
+    pub games: u32,
+    pub rating: u32,
+    // How much the rating went up or down over the last twelve games.
+    #[serde(default)]
+    pub prog: i32,
+    // Provisional ratings, from too few games to be sure of.
+    #[serde(default)]
+    pub prov: bool,
+}
+
+impl Account {
+    pub fn url(&self) -> String {
+        format!("{}/@/{}", LICHESS_URL, self.username)
+    }
+}
+
+// Lichess usernames are letters, digits, `_` and `-`.
+pub fn is_username(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 30
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// A user's profile, or None if there's no such account.
+pub async fn account(client: &reqwest::Client, username: &str) -> reqwest::Result<Option<Account>> {
+    let response = client
+        .get(format!("{}/api/user/{}", LICHESS_URL, username))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+// Lichess' stored evaluation of a position, shared by everyone who analysed it there.
+#[derive(Debug, Deserialize)]
+pub struct CloudEval {
+    pub depth: u32,
+    pub pvs: Vec<CloudLine>,
+}
+
+// One line of a cloud evaluation. Scores are from white's side.
+#[derive(Debug, Deserialize)]
+pub struct CloudLine {
+    // Space-separated UCI moves.
+    pub moves: String,
+    pub cp: Option<i32>,
+    pub mate: Option<i32>,
+}
+
+// The cloud evaluation of a FEN, or None if nobody has analysed the position on Lichess.
+pub async fn cloud_eval(client: &reqwest::Client, fen: &str) -> reqwest::Result<Option<CloudEval>> {
+    let response = client
+        .get(format!("{}/api/cloud-eval", LICHESS_URL))
+        .query(&[("fen", fen)])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+// Which games the opening explorer counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplorerDb {
+    Masters,
+    Lichess,
+}
+
+impl ExplorerDb {
+    pub fn name(self) -> &'static str {
+        match self {
+            ExplorerDb::Masters => "Masters",
+            ExplorerDb::Lichess => "Lichess games",
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            ExplorerDb::Masters => "masters",
+            ExplorerDb::Lichess => "lichess",
+        }
+    }
+}
+
+// How a position went in the explorer's games, and what was played from it.
+#[derive(Debug, Deserialize)]
+pub struct Explorer {
+    pub white: u64,
+    pub draws: u64,
+    pub black: u64,
+    pub moves: Vec<ExplorerMove>,
+    #[serde(rename = "topGames", default)]
+    pub top_games: Vec<ExplorerGame>,
+    pub opening: Option<Opening>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExplorerMove {
+    pub uci: String,
+    pub san: String,
+    pub white: u64,
+    pub draws: u64,
+    pub black: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExplorerGame {
+    pub id: String,
+    // "white" or "black", missing for draws.
+    pub winner: Option<String>,
+    pub white: ExplorerPlayer,
+    pub black: ExplorerPlayer,
+    pub year: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExplorerPlayer {
+    pub name: String,
+    pub rating: Option<u32>,
+}
+
+// The explorer's games from a FEN after some more UCI moves.
+pub async fn explorer(
+    client: &reqwest::Client,
+    db: ExplorerDb,
+    fen: &str,
+    play: &[String],
+    moves: usize,
+    games: usize,
+) -> reqwest::Result<Explorer> {
+    let mut query = vec![
+        ("fen", fen.to_string()),
+        ("play", play.join(",")),
+        ("moves", moves.to_string()),
+        ("topGames", games.to_string()),
+    ];
+    if db == ExplorerDb::Lichess {
+        query.push(("variant", "standard".to_string()));
+        query.push(("recentGames", "0".to_string()));
+    }
+    client
+        .get(format!("{}/{}", EXPLORER_URL, db.path()))
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// A user's finished games that started after `since` (milliseconds since the epoch), oldest
+// first.
+pub async fn user_games_since(
+    client: &reqwest::Client,
+    username: &str,
+    since: u64,
+    max: u32,
+) -> reqwest::Result<Vec<Game>> {
+    let request = client
+        .get(format!("{}/api/games/user/{}", LICHESS_URL, username))
+        .query(&[
+            ("since", since.to_string()),
+            ("max", max.to_string()),
+            ("opening", "true".to_string()),
+            ("moves", "true".to_string()),
+        ]);
+
+    let mut games: Vec<Game> = ndjson(request, "game").await?;
+    games.reverse();
+
+    Ok(games)
+}
+
+// One line of a game's move stream. The first has everything about the game, the ones after it
+// just the position, the move and the clocks.
+#[derive(Debug, Deserialize)]
+pub struct StreamEvent {
+    pub fen: Option<String>,
+    pub players: Option<Players>,
+    pub status: Option<StreamStatus>,
+    pub winner: Option<String>,
+    // The move that led to `fen` in UCI, `lastMove` in the first line and `lm` after.
+    #[serde(rename = "lastMove", alias = "lm")]
+    pub last_move: Option<String>,
+    // Seconds left on white's and black's clocks.
+    pub wc: Option<u32>,
+    pub bc: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamStatus {
+    // Like "started", "mate" or "resign".
+    pub name: String,
+}
+
+// Newline-delimited JSON read from a response as it comes in.
+pub struct LineStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+}
+
+impl LineStream {
+    // The next line that isn't blank, or None once the response ends. Safe to cancel: nothing
+    // read is lost if this is dropped while waiting.
+    pub async fn next_line(&mut self) -> reqwest::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None if self.buffer.iter().all(u8::is_ascii_whitespace) => return Ok(None),
+                None => self.buffer.push(b'\n'),
+            }
+        }
+    }
+}
+
+// Follow a game's moves as they're played. The stream ends with the game.
+pub async fn stream_game(client: &reqwest::Client, id: &str) -> reqwest::Result<LineStream> {
+    let response = client
+        .get(format!("{}/api/stream/game/{}", LICHESS_URL, id))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(LineStream {
+        response,
+        buffer: Vec::new(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct UserStatus {
+    // The username in lowercase.
+    id: String,
+    #[serde(rename = "playingId")]
+    playing_id: Option<String>,
+}
+
+// Lichess answers about this many users at a time.
+const MAX_STATUSES: usize = 100;
+
+// The games users are playing right now, keyed by lowercase username. Users who aren't playing
+// are left out.
+pub async fn playing_games(
+    client: &reqwest::Client,
+    usernames: &[String],
+) -> reqwest::Result<HashMap<String, String>> {
+    let mut playing = HashMap::new();
+    for batch in usernames.chunks(MAX_STATUSES) {
+        let statuses: Vec<UserStatus> = client
+            .get(format!("{}/api/users/status", LICHESS_URL))
+            .query(&[
+                ("ids", batch.join(",")),
+                ("withGameIds", "true".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        playing.extend(
+            statuses
+                .into_iter()
+                .filter_map(|status| Some((status.id, status.playing_id?))),
+        );
+    }
+    Ok(playing)
+}
+
+// The id of the game a user is playing right now, if any.
+pub async fn playing_game(
+    client: &reqwest::Client,
+    username: &str,
+) -> reqwest::Result<Option<String>> {
+    let mut playing = playing_games(client, &[username.to_string()]).await?;
+    Ok(playing.remove(&username.to_lowercase()))
+}
+
+// A picture of a position drawn by Lichess, for embeds that are edited and can't take new
+// attachments.
+pub fn position_image_url(fen: &str, last_move: Option<&str>, flipped: bool) -> String {
+    let mut params = vec![
+        ("fen", fen),
+        ("color", if flipped { "black" } else { "white" }),
+    ];
+    if let Some(last_move) = last_move {
+        params.push(("lastMove", last_move));
+    }
+    reqwest::Url::parse_with_params("https://lichess1.org/export/fen.gif", &params)
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+}
+
+// A team by its id, the last part of lichess.org/team/..., or None if there's no such team.
+pub async fn team(client: &reqwest::Client, id: &str) -> reqwest::Result<Option<Team>> {
+    let response = client
+        .get(format!("{}/api/team/{}", LICHESS_URL, id))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Clock {
+    // Both in seconds.
+    pub limit: u32,
+    pub increment: u32,
+}
+
+impl Clock {
+    // "3+2", or "½+0" for half a minute.
+    pub fn text(&self) -> String {
+        let minutes = match self.limit {
+            15 => "¼".to_string(),
+            30 => "½".to_string(),
+            45 => "¾".to_string(),
+            limit => (limit / 60).to_string(),
+        };
+        format!("{}+{}", minutes, self.increment)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Arena {
+    pub id: String,
+    #[serde(rename = "fullName")]
+    pub full_name: String,
+    // Milliseconds since the epoch.
+    #[serde(rename = "startsAt")]
+    pub starts_at: i64,
+    pub minutes: u32,
+    pub clock: Clock,
+    #[serde(default)]
+    pub rated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Swiss {
+    pub id: String,
+    pub name: String,
+    // Like "2024-03-01T18:00:00Z".
+    #[serde(rename = "startsAt")]
+    pub starts_at: String,
+    pub clock: Clock,
+    #[serde(rename = "nbRounds")]
+    pub rounds: u32,
+    #[serde(default)]
+    pub rated: bool,
+}
+
+impl Arena {
+    pub fn url(&self) -> String {
+        format!("{}/tournament/{}", LICHESS_URL, self.id)
+    }
+}
+
+impl Swiss {
+    pub fn url(&self) -> String {
+        format!("{}/swiss/{}", LICHESS_URL, self.id)
+    }
+}
+
+// Every line of a newline-delimited JSON response, skipping the ones that can't be read.
+async fn ndjson<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    what: &str,
+) -> reqwest::Result<Vec<T>> {
+    let text = request
+        .header("Accept", "application/x-ndjson")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(item) => Some(item),
+            Err(why) => {
+                println!("Could not read Lichess {}: {:?}", what, why);
+                None
+            }
+        })
+        .collect())
+}
+
+// A team's members with their ratings, the most recent to join first. Lichess sends at most
+// a thousand.
+pub async fn team_members(client: &reqwest::Client, team: &str) -> reqwest::Result<Vec<Account>> {
+    let request = client
+        .get(format!("{}/api/team/{}/users", LICHESS_URL, team))
+        .query(&[("full", "true")]);
+    ndjson(request, "team member").await
+}
+
+// A team's arenas that haven't started yet.
+pub async fn team_arenas(client: &reqwest::Client, team: &str) -> reqwest::Result<Vec<Arena>> {
+    let request = client
+        .get(format!("{}/api/team/{}/arena", LICHESS_URL, team))
+        .query(&[("status", "created"), ("max", "20")]);
+    ndjson(request, "arena").await
+}
+
+// A team's Swiss tournaments that haven't started yet.
+pub async fn team_swisses(client: &reqwest::Client, team: &str) -> reqwest::Result<Vec<Swiss>> {
+    let request = client
+        .get(format!("{}/api/team/{}/swiss", LICHESS_URL, team))
+        .query(&[("status", "created"), ("max", "20")]);
+    ndjson(request, "Swiss tournament").await
+}
+
+// Where an arena or Swiss tournament lives in the API.
+fn tournament_path(id: &str, swiss: bool) -> String {
+    if swiss {
+        format!("{}/api/swiss/{}", LICHESS_URL, id)
+    } else {
+        format!("{}/api/tournament/{}", LICHESS_URL, id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TournamentStatus {
+    // Arenas say this...
+    #[serde(rename = "isFinished", default)]
+    is_finished: bool,
+    // ...and Swiss tournaments this, "finished" once they are.
+    #[serde(default)]
+    status: String,
+}
+
+// Whether a tournament is over, or None if it's been deleted.
+pub async fn tournament_finished(
+    client: &reqwest::Client,
+    id: &str,
+    swiss: bool,
+) -> reqwest::Result<Option<bool>> {
+    let response = client.get(tournament_path(id, swiss)).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let status: TournamentStatus = response.error_for_status()?.json().await?;
+    Ok(Some(status.is_finished || status.status == "finished"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Standing {
+    pub username: String,
+    // Arenas count points, Swiss tournaments can have halves.
+    #[serde(alias = "points")]
+    pub score: f64,
+    pub rating: u32,
+    #[serde(default)]
+    pub performance: Option<u32>,
+}
+
+// The best `count` players of a tournament, the winner first.
+pub async fn tournament_results(
+    client: &reqwest::Client,
+    id: &str,
+    swiss: bool,
+    count: u32,
+) -> reqwest::Result<Vec<Standing>> {
+    let request = client
+        .get(format!("{}/results", tournament_path(id, swiss)))
+        .query(&[("nb", count)]);
+    ndjson(request, "tournament result").await
+}
+
+// What a Lichess TV channel is showing.
+#[derive(Debug, Deserialize)]
+pub struct TvChannel {
+    #[serde(rename = "gameId")]
+    pub game_id: String,
+    // The side of the player the channel follows, "white" or "black".
+    pub color: String,
+}
+
+// Every TV channel, keyed by names like "Blitz" or "Top Rated".
+pub async fn tv_channels(client: &reqwest::Client) -> reqwest::Result<HashMap<String, TvChannel>> {
+    client
+        .get(format!("{}/api/tv/channels", LICHESS_URL))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// A broadcast of an over the board event, like a world championship, and its rounds.
+#[derive(Debug, Deserialize)]
+pub struct Broadcast {
+    pub tour: BroadcastTour,
+    #[serde(default)]
+    pub rounds: Vec<BroadcastRoundInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastTour {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastRoundInfo {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    // Only there once they're true.
+    #[serde(default)]
+    pub ongoing: bool,
+    #[serde(default)]
+    pub finished: bool,
+}
+
+// A round with its games as they are now.
+#[derive(Debug, Deserialize)]
+pub struct BroadcastRound {
+    pub round: BroadcastRoundInfo,
+    pub tour: BroadcastTour,
+    #[serde(default)]
+    pub games: Vec<BroadcastGame>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastGame {
+    pub id: String,
+    pub fen: Option<String>,
+    // White, then black.
+    #[serde(default)]
+    pub players: Vec<BroadcastPlayer>,
+    #[serde(rename = "lastMove")]
+    pub last_move: Option<String>,
+    // "*" while it's being played, then "1-0", "0-1" or "½-½".
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastPlayer {
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub rating: Option<u32>,
+    // Centiseconds left.
+    pub clock: Option<u32>,
+}
+
+impl BroadcastPlayer {
+    // "GM Carlsen, Magnus (2830)"
+    pub fn display_name(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("Unknown");
+        let name = match &self.title {
+            Some(title) => format!("{} {}", title, name),
+            None => name.to_string(),
+        };
+        match self.rating {
+            Some(rating) => format!("{} ({})", name, rating),
+            None => name,
+        }
+    }
+}
+
+impl BroadcastGame {
+    pub fn is_over(&self) -> bool {
+        self.status.as_deref().is_some_and(|status| status != "*")
+    }
+}
+
+// A broadcast link names either a whole event or one of its rounds.
+#[derive(Debug, PartialEq)]
+pub enum BroadcastLink {
+    Tour(String),
+    Round(String),
+}
+
+// https://lichess.org/broadcast/<event>/<id> for an event,
+// https://lichess.org/broadcast/<event>/<round>/<id> for a round, maybe followed by a game id.
+pub fn broadcast_from_url(url: &str) -> Option<BroadcastLink> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .strip_prefix("lichess.org/broadcast/")?;
+    let segments: Vec<&str> = rest
+        .split(['#', '?'])
+        .next()?
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let is_id = |id: &str| id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric());
+
+    match segments.as_slice() {
+        [_, id] if is_id(id) => Some(BroadcastLink::Tour(id.to_string())),
+        [_, _, id] | [_, _, id, _] if is_id(id) => Some(BroadcastLink::Round(id.to_string())),
+        _ => None,
+    }
+}
+
+// An event and its rounds, or None if there's no such broadcast.
+pub async fn broadcast(client: &reqwest::Client, id: &str) -> reqwest::Result<Option<Broadcast>> {
+    let response = client
+        .get(format!("{}/api/broadcast/{}", LICHESS_URL, id))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+// A round's games, or None if there's no such round.
+pub async fn broadcast_round(
+    client: &reqwest::Client,
+    id: &str,
+) -> reqwest::Result<Option<BroadcastRound>> {
+    let response = client
+        .get(format!("{}/api/broadcast/-/-/{}", LICHESS_URL, id))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?.json().await.map(Some)
+}
+
+// The study and maybe the chapter in a link like https://lichess.org/study/abcdEFGH/ijklMNOP.
+pub fn study_from_url(url: &str) -> Option<(String, Option<String>)> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .strip_prefix("lichess.org/study/")?;
+    let mut segments = rest.split(['#', '?']).next()?.split('/');
+    let is_id = |id: &str| id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric());
+    let study = segments.next().filter(|id| is_id(id))?;
+    let chapter = segments.next().filter(|id| is_id(id));
+    Some((study.to_string(), chapter.map(str::to_string)))
+}
+
+// Every chapter of a public study as PGN, mainlines and comments only. None if there's no such
+// study or it's private.
+pub async fn study_pgn(client: &reqwest::Client, id: &str) -> reqwest::Result<Option<String>> {
+    let response = client
+        .get(format!("{}/api/study/{}.pgn", LICHESS_URL, id))
+        .query(&[
+            ("comments", "true"),
+            ("variations", "false"),
+            ("clocks", "false"),
+        ])
+        .send()
+        .await?;
+    if matches!(
+        response.status(),
+        reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::FORBIDDEN
+    ) {
+        return Ok(None);
+    }
+    response.error_for_status()?.text().await.map(Some)
+}
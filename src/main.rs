@@ -20,9 +20,10 @@ use serenity::{
     },
     http::Http,
     model::{
-        channel::{Channel, Message},
+        channel::{Channel, Message, Reaction},
         gateway::Ready,
         id::UserId,
+        interactions::Interaction,
         permissions::Permissions,
     },
     utils::Color,
@@ -34,6 +35,76 @@ use serenity::model::id::ChannelId;
 use serenity::utils::MessageBuilder;
 use tokio::sync::Mutex;
 
+mod accounts;
+mod analysis;
+mod animation;
+mod backup;
+mod broadcast;
+mod card;
+mod chess960;
+mod chesscom;
+mod clubboard;
+mod config;
+mod content;
+mod diagram;
+mod digest;
+mod emoji;
+mod engine;
+mod eval;
+mod explorer;
+mod fen;
+mod follow;
+mod fun;
+mod game;
+mod general;
+mod gif;
+mod guesseval;
+mod history;
+mod jobs;
+mod leaderboard;
+mod lichess;
+mod meetup;
+mod moderation;
+mod modules;
+mod notation;
+mod openings;
+mod permissions;
+mod pgn;
+mod playing;
+mod potd;
+mod puzzle;
+mod puzzledb;
+mod puzzlerace;
+mod quotes;
+mod previews;
+mod profile;
+mod rating;
+mod reactionmoves;
+mod relay;
+mod render;
+mod replay;
+mod repl;
+mod scheduler;
+mod settings;
+mod setup;
+mod simul;
+mod study;
+mod timecontrol;
+mod timezone;
+mod tournaments;
+mod tv;
+mod variant;
+mod votechess;
+mod watch;
+mod web;
+
+use config::{Config, ConfigContainer};
+use content::{Content, ContentContainer};
+use engine::{EnginePool, EnginePoolContainer};
+use jobs::{JobQueue, JobQueueContainer};
+use settings::{Settings, SettingsContainer};
+use web::WebClientContainer;
+
 const EMBED_SIDE_COLOR: Color = Color::from_rgb(255, 192, 203);
 
 // Generate a random index
@@ -51,25 +122,19 @@ impl TypeMapKey for ShardManagerContainer {
     type Value = Arc<Mutex<ShardManager>>;
 }
 
-#[derive(Debug)]
-struct BlitzQuote {
-    quote: String,
-    author: String,
-}
+// The bot's own user id, to tell its messages apart from everyone else's.
+struct BotIdContainer;
 
-impl From<(&str, &str)> for BlitzQuote {
-    fn from(q: (&str, &str)) -> Self {
-        BlitzQuote {
-            quote: q.0.to_string(),
-            author: q.1.to_string(),
-        }
-    }
+impl TypeMapKey for BotIdContainer {
+    type Value = UserId;
 }
 
-struct BlitzQuoteContainer;
-
-impl TypeMapKey for BlitzQuoteContainer {
-    type Value = Vec<BlitzQuote>;
+async fn bot_id(data: &RwLock<TypeMap>) -> UserId {
+    *data
+        .read()
+        .await
+        .get::<BotIdContainer>()
+        .expect("Expected bot id in typemap.")
 }
 
 struct Handler;
@@ -79,21 +144,95 @@ impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
     }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.id == bot_id(&ctx.data).await {
+            moderation::auto_delete(&ctx, &msg).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::MessageComponent(component) = interaction {
+            modules::component(&ctx, &component).await;
+        }
+    }
+
+    // Taking a reaction away counts the same as adding one: it's how a square is picked twice.
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id != Some(bot_id(&ctx.data).await) {
+            modules::reaction(&ctx, &reaction).await;
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id != Some(bot_id(&ctx.data).await) {
+            modules::reaction(&ctx, &reaction).await;
+        }
+    }
 }
 
-#[group]
-#[commands(blitz, whyrust, color)]
-struct General;
+#[hook]
+async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    modules::command_enabled(ctx, msg, command_name).await
+        && permissions::command_allowed(ctx, msg, command_name).await
+        && moderation::check_command(ctx, msg, command_name).await
+}
+
+#[hook]
+async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    moderation::auto_delete(ctx, msg).await;
+
+    if let Err(why) = command_result {
+        println!("Command '{}' returned error {:?}", command_name, why);
+        moderation::command_failed(ctx, msg).await;
+    }
+}
 
 #[hook]
-async fn unknown_command(_ctx: &Context, _msg: &Message, unknown_command_name: &str) {
+async fn unknown_command(ctx: &Context, msg: &Message, unknown_command_name: &str) {
+    if modules::unknown_command(ctx, msg, unknown_command_name).await {
+        moderation::auto_delete(ctx, msg).await;
+        return;
+    }
+
     println!("Could not find command named '{}'", unknown_command_name);
+    moderation::command_failed(ctx, msg).await;
+}
+
+#[hook]
+async fn normal_message(ctx: &Context, msg: &Message) {
+    modules::normal_message(ctx, msg).await;
+}
+
+#[hook]
+async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError) {
+    match error {
+        DispatchError::CheckFailed(_, Reason::User(reason)) => {
+            moderation::refuse(ctx, msg, &reason).await
+        }
+        DispatchError::OnlyForGuilds => {
+            moderation::refuse(ctx, msg, "This command only works in servers.").await
+        }
+        error => {
+            println!("Could not dispatch command: {:?}", error);
+            moderation::command_failed(ctx, msg).await;
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    // `--repl` tries the commands out locally without connecting to Discord.
+    if env::args().any(|arg| arg == "--repl") {
+        repl::run();
+        return;
+    }
+
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("CUTE_BOT_TOKEN").expect("Expected a token in the environment");
+    let config = Config::load();
+    let content = Content::load(&config.content_file);
+    let settings = Settings::load(&config.data_dir);
 
     let http = Http::new_with_token(&token);
 
@@ -114,7 +253,7 @@ async fn main() {
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
 
-    let framework = StandardFramework::new()
+    let mut framework = StandardFramework::new()
         .configure(|c| {
             c.with_whitespace(true)
                 .on_mention(Some(bot_id))
@@ -127,8 +266,16 @@ async fn main() {
                 // are owners only.
                 .owners(owners)
         })
+        .before(before)
+        .after(after)
         .unrecognised_command(unknown_command)
-        .group(&GENERAL_GROUP);
+        .on_dispatch_error(dispatch_error)
+        .normal_message(normal_message);
+    for module in modules::MODULES {
+        if let Some(group) = module.group() {
+            framework = framework.group(group);
+        }
+    }
     // Set a function that's called whenever a message is not a command.
 
     // Finally, start a single shard, and start listening to events.
@@ -144,111 +291,33 @@ async fn main() {
     {
         let mut data = client.data.write().await;
         data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
+        data.insert::<BotIdContainer>(bot_id);
+        data.insert::<EnginePoolContainer>(Arc::new(EnginePool::new(config.engine.pool_size)));
+        data.insert::<JobQueueContainer>(Arc::new(JobQueue::new(config.engine.jobs)));
+        data.insert::<ConfigContainer>(Arc::new(config));
+        data.insert::<ContentContainer>(Arc::new(content));
+        data.insert::<SettingsContainer>(Arc::new(Mutex::new(settings)));
+        data.insert::<WebClientContainer>(web::new_client());
+        modules::insert_data(&mut data);
+    }
 
-        let quotes : Vec<BlitzQuote> = vec![
-            ("Rapid and blitz chess is first of all for enjoyment.", "Magnus Carlsen").into(),
-            ("Playing rapid chess, one can lose the habit of concentrating for several hours in serious chess. That is why, if a player has big aims, he should limit his rapid play in favour of serious chess.", "Vladimir Kramnik").into(),
-            ("He who analyses blitz is stupid.", "Rashid Nezhmetdinov").into(),
-            ("Blitz chess kills your ideas.", "Bobby Fischer").into(),
-            ("To be honest, I consider [bullet chess] a bit moronic, and therefore I never play it.", "Vladimir Kramnik").into(),
-            ("I play way too much blitz chess. It rots the brain just as surely as alcohol.", "Nigel Short").into(),
-            ("Blitz is simply a waste of time.", "Vladimir Malakhov").into(),
-            ("[Blitz] is just getting positions where you can move fast. I mean, it's not chess.", "Hikaru Nakamura").into(),
-            ("Always sack the exchange!", "Ben F6gold").into(),
-        ];
-
-        data.insert::<BlitzQuoteContainer>(quotes);
+    tokio::spawn(scheduler::run(
+        client.cache_and_http.http.clone(),
+        client.data.clone(),
+    ));
+    game::watch_clocks(client.cache_and_http.http.clone(), client.data.clone()).await;
+
+    // The content file is only a fallback when the content is downloaded.
+    if config::get(&client.data).await.content_url.is_some() {
+        let data = client.data.clone();
+        tokio::spawn(async move {
+            if let Err(why) = content::refresh(&data).await {
+                println!("Could not download the content: {:?}", why);
+            }
+        });
     }
 
     if let Err(why) = client.start().await {
         println!("Client error: {:?}", why);
     }
 }
-
-#[command]
-#[aliases("colour")]
-async fn color(ctx: &Context, msg: &Message) -> CommandResult {
-    let bot_channel_id: i64 = 855703545398427668;
-    let desc = format!("You can get cute :sparkles: by using the color commands at <#{}>\nUse `/color list` to list all the available colors\nThen `/set color [number or color]` to set your role color!\nIf you'd like a color that is not on the list, let Lucy know!", bot_channel_id);
-
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                e.title("Set your own role color!");
-                e.color(EMBED_SIDE_COLOR);
-                e.description(desc);
-                e
-            });
-            m
-        })
-        .await
-        .expect("error making message");
-
-    Ok(())
-}
-
-#[command]
-async fn blitz(ctx: &Context, msg: &Message) -> CommandResult {
-    let data = ctx.data.read().await;
-    let quotes = data
-        .get::<BlitzQuoteContainer>()
-        .expect("Expected blitz quotes in typemap.");
-
-    let index = random_index(quotes.len());
-
-    let mut desc = String::default();
-    write!(desc, "\"{}\"", &quotes[index].quote)?;
-
-    let mut the_quote = String::default();
-    write!(the_quote, "- {}", &quotes[index].author)?;
-
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                e.color(EMBED_SIDE_COLOR);
-                e.description(desc);
-                e.footer(|f| {
-                    f.text(the_quote);
-                    f
-                });
-                e
-            });
-            m
-        })
-        .await
-        .expect("error making message");
-
-    Ok(())
-}
-
-#[command]
-async fn whyrust(ctx: &Context, msg: &Message) -> CommandResult {
-    let title = "Why rust?!";
-    let reasons = vec![
-        "Why not?",
-        "cargo",
-        "match expressions",
-        "const is the default",
-        "Cute crab mascotte 🦀",
-    ];
-
-    let index = random_index(reasons.len());
-
-    let mut choice = String::default();
-    write!(choice, "{}", &reasons[index])?;
-
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                e.color(EMBED_SIDE_COLOR);
-                e.title(title);
-                e.description(choice);
-                e
-            });
-            m
-        })
-        .await
-        .expect("error making message");
-
-    Ok(())
-}
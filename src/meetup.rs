@@ -0,0 +1,465 @@
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    settings, timezone, EMBED_SIDE_COLOR,
+};
+
+const RSVP_ID: &str = "meetup:rsvp";
+
+// Check-in opens this long before a meetup starts...
+const CHECKIN_EARLY_SECS: i64 = 60 * 60;
+// ...and stays open this long after.
+const CHECKIN_LATE_SECS: i64 = 4 * 60 * 60;
+
+#[group]
+#[commands(meetup)]
+struct Meetups;
+
+pub struct MeetupsModule;
+
+const MEETUP_SETTINGS: &[ModuleSetting] = &[
+    ModuleSetting {
+        name: "Regular role",
+        command: "regular",
+        value: |guild| modules::show_role(guild.regular_role),
+    },
+    ModuleSetting {
+        name: "Meetups to become a regular",
+        command: "regular",
+        value: |guild| guild.regular_after.to_string(),
+    },
+];
+
+#[async_trait]
+impl BotModule for MeetupsModule {
+    fn name(&self) -> &'static str {
+        "meetups"
+    }
+
+    fn description(&self) -> &'static str {
+        "Club meetups, RSVPs and check-ins."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&MEETUPS_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        MEETUP_SETTINGS
+    }
+}
+
+// A club meetup announced with `.meetup create`, saved in the settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meetup {
+    // The announcement message, which carries the RSVP button.
+    pub message_id: u64,
+    pub channel_id: u64,
+    pub guild_id: u64,
+    pub title: String,
+    // Seconds since the epoch.
+    pub starts_at: i64,
+    pub rsvps: Vec<u64>,
+    pub attended: Vec<u64>,
+}
+
+impl Meetup {
+    fn checkin_open(&self, now: i64) -> bool {
+        now >= self.starts_at - CHECKIN_EARLY_SECS && now <= self.starts_at + CHECKIN_LATE_SECS
+    }
+
+    fn checkin_closed(&self, now: i64) -> bool {
+        now > self.starts_at + CHECKIN_LATE_SECS
+    }
+}
+
+// How many meetups in a row someone has come to, and how many in total.
+pub fn attendance(
+    meetups: &[Meetup],
+    guild_id: GuildId,
+    user_id: UserId,
+    now: i64,
+) -> (usize, usize) {
+    let mut past = meetups
+        .iter()
+        .filter(|m| m.guild_id == guild_id.0 && now >= m.starts_at - CHECKIN_EARLY_SECS)
+        .collect::<Vec<_>>();
+    past.sort_by_key(|m| std::cmp::Reverse(m.starts_at));
+
+    let total = past
+        .iter()
+        .filter(|m| m.attended.contains(&user_id.0))
+        .count();
+
+    let mut streak = 0;
+    for meetup in past {
+        if meetup.attended.contains(&user_id.0) {
+            streak += 1;
+        } else if meetup.checkin_closed(now) {
+            break;
+        }
+    }
+
+    (streak, total)
+}
+
+fn meetup_embed<'a>(e: &'a mut CreateEmbed, meetup: &Meetup) -> &'a mut CreateEmbed {
+    e.title(&meetup.title);
+    e.color(EMBED_SIDE_COLOR);
+    e.description(format!(
+        "<t:{0}:F> (<t:{0}:R>)\n\n**{1}** going",
+        meetup.starts_at,
+        meetup.rsvps.len()
+    ));
+    e.footer(|f| {
+        f.text("Check in with .meetup checkin when you get there");
+        f
+    });
+    e
+}
+
+fn meetup_buttons(c: &mut CreateComponents) -> &mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Primary);
+            b.label("I'm going");
+            b.custom_id(RSVP_ID);
+            b
+        })
+    })
+}
+
+// "2021-07-02 18:00" in the server's timezone.
+fn parse_start(date: &str, time: &str, tz: Tz) -> Option<i64> {
+    let start =
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").ok()?;
+    Some(timezone::local_to_utc(tz, start).timestamp())
+}
+
+// Toggle someone's RSVP when they press the button. Returns false if the button isn't ours.
+pub async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    if component.data.custom_id != RSVP_ID {
+        return false;
+    }
+
+    let user_id = component.user.id.0;
+    let meetup = settings::update(&ctx.data, |settings| {
+        let meetup = settings
+            .meetups
+            .iter_mut()
+            .find(|m| m.message_id == component.message.id.0)?;
+        match meetup.rsvps.iter().position(|id| *id == user_id) {
+            Some(index) => {
+                meetup.rsvps.remove(index);
+            }
+            None => meetup.rsvps.push(user_id),
+        }
+        Some(meetup.clone())
+    })
+    .await;
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| match &meetup {
+                Some(meetup) => {
+                    d.create_embed(|e| meetup_embed(e, meetup));
+                    d
+                }
+                None => {
+                    d.content("This meetup is no longer around.");
+                    d.components(|c| c)
+                }
+            })
+        })
+        .await;
+
+    if let Err(why) = result {
+        println!("Error updating meetup: {:?}", why);
+    }
+
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[sub_commands(create, checkin, stats, regular)]
+#[description("Club meetups. Shows the upcoming ones.")]
+async fn meetup(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let now = Utc::now().timestamp();
+
+    let mut upcoming = settings::read(&ctx.data, |settings| {
+        settings
+            .meetups
+            .iter()
+            .filter(|m| m.guild_id == guild_id.0 && !m.checkin_closed(now))
+            .cloned()
+            .collect::<Vec<_>>()
+    })
+    .await;
+    upcoming.sort_by_key(|m| m.starts_at);
+
+    if upcoming.is_empty() {
+        msg.reply(&ctx.http, "No meetups coming up.").await?;
+        return Ok(());
+    }
+
+    let desc = upcoming
+        .iter()
+        .map(|m| {
+            format!(
+                "**{}** <t:{}:F>, {} going",
+                m.title,
+                m.starts_at,
+                m.rsvps.len()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Upcoming meetups");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Announce a meetup, with a button to RSVP. Times are in the server's timezone.")]
+#[usage("<YYYY-MM-DD> <HH:MM> <title>")]
+#[example("2021-07-02 18:00 Friday blitz night")]
+async fn create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let date = args.single::<String>().unwrap_or_default();
+    let time = args.single::<String>().unwrap_or_default();
+    let title = args.rest().trim().to_string();
+
+    let tz = timezone::for_guild(&ctx.data, Some(guild_id)).await;
+    let starts_at = match parse_start(&date, &time, tz) {
+        Some(starts_at) if !title.is_empty() => starts_at,
+        _ => {
+            let reply = format!(
+                "Use `.meetup create <YYYY-MM-DD> <HH:MM> <title>`, with the time in {}",
+                tz.name()
+            );
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    let mut meetup = Meetup {
+        message_id: 0,
+        channel_id: msg.channel_id.0,
+        guild_id: guild_id.0,
+        title,
+        starts_at,
+        rsvps: Vec::new(),
+        attended: Vec::new(),
+    };
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| meetup_embed(e, &meetup));
+            m.components(meetup_buttons);
+            m
+        })
+        .await?;
+
+    meetup.message_id = sent.id.0;
+    settings::update(&ctx.data, |settings| settings.meetups.push(meetup)).await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Check in to the meetup that's on right now.")]
+async fn checkin(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let user_id = msg.author.id;
+    let now = Utc::now().timestamp();
+
+    let checked_in = settings::update(&ctx.data, |settings| {
+        let meetup = settings
+            .meetups
+            .iter_mut()
+            .find(|m| m.guild_id == guild_id.0 && m.checkin_open(now))?;
+        let first_time = !meetup.attended.contains(&user_id.0);
+        if first_time {
+            meetup.attended.push(user_id.0);
+        }
+        Some((meetup.title.clone(), first_time))
+    })
+    .await;
+
+    let (title, first_time) = match checked_in {
+        Some(checked_in) => checked_in,
+        None => {
+            msg.reply(&ctx.http, "There's no meetup on right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+    if !first_time {
+        msg.reply(
+            &ctx.http,
+            format!("You're already checked in to {}.", title),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let (streak, total, guild) = settings::read(&ctx.data, |settings| {
+        let (streak, total) = attendance(&settings.meetups, guild_id, user_id, now);
+        (streak, total, settings.guild(guild_id))
+    })
+    .await;
+
+    let mut reply = format!(
+        "Checked in to {}! That's {} in a row and {} in total.",
+        title, streak, total
+    );
+
+    if let Some(role) = guild.regular_role {
+        let member = guild_id.member(&ctx.http, user_id).await?;
+        if total >= guild.regular_after && !member.roles.iter().any(|r| r.0 == role) {
+            match ctx.http.add_member_role(guild_id.0, user_id.0, role).await {
+                Ok(()) => reply.push_str(" You're a regular now!"),
+                Err(why) => println!("Could not grant the regular role: {:?}", why),
+            }
+        }
+    }
+
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Show how many meetups someone has come to.")]
+#[usage("[@user]")]
+async fn stats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let user_id = args
+        .current()
+        .and_then(utils::parse_username)
+        .map(UserId)
+        .unwrap_or(msg.author.id);
+    let now = Utc::now().timestamp();
+
+    let (streak, total) = settings::read(&ctx.data, |settings| {
+        attendance(&settings.meetups, guild_id, user_id, now)
+    })
+    .await;
+
+    let reply = match total {
+        0 => format!("<@{}> hasn't been to a meetup yet.", user_id),
+        total => format!(
+            "<@{}> has been to {} meetups, {} in a row.",
+            user_id, total, streak
+        ),
+    };
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(reply);
+            m.allowed_mentions(|a| a.empty_users());
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Give a role to people once they've come to enough meetups.")]
+#[usage("<count> <@role> | off")]
+#[example("5 @Regular")]
+async fn regular(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+
+    let role = match args.current() {
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => None,
+        _ => {
+            let count = args.single::<usize>().ok().filter(|count| *count > 0);
+            let role = args.current().and_then(utils::parse_role);
+            match (count, role) {
+                (Some(count), Some(role)) => Some((count, role)),
+                _ => {
+                    msg.reply(
+                        &ctx.http,
+                        "Use `.meetup regular <count> <@role>` or `.meetup regular off`",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        let guild = settings.guilds.entry(guild_id.0).or_default();
+        guild.regular_role = role.map(|(_, role)| role);
+        if let Some((count, _)) = role {
+            guild.regular_after = count;
+        }
+    })
+    .await;
+
+    let reply = match role {
+        Some((count, role)) => format!(
+            "People who come to {} meetups will get <@&{}>.",
+            count, role
+        ),
+        None => "Meetup regulars no longer get a role.".to_string(),
+    };
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(reply);
+            m.allowed_mentions(|a| a.empty_roles());
+            m
+        })
+        .await?;
+
+    Ok(())
+}
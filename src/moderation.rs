@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId},
+    },
+    prelude::*,
+};
+
+use crate::{
+    config::{self, Config},
+    modules::BotModule,
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings,
+};
+
+// The longest `.autodelete` waits, a week.
+const MAX_AUTODELETE_MINUTES: u64 = 7 * 24 * 60;
+
+// A deletion this far past due was lost to a restart, so the sweep does it.
+const OVERDUE_SECS: i64 = 60;
+
+#[group]
+#[commands(autodelete)]
+struct Moderation;
+
+pub struct ModerationModule;
+
+#[async_trait]
+impl BotModule for ModerationModule {
+    fn name(&self) -> &'static str {
+        "moderation"
+    }
+
+    fn description(&self) -> &'static str {
+        "Cleaning up after the bot, see `.autodelete`."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&MODERATION_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<ModerationContainer>(Mutex::default());
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::DeletionSweep, "* * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::DeletionSweep {
+            sweep(http, data).await;
+        }
+    }
+}
+
+// A message waiting to be deleted, saved so it still goes if the bot restarts first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDeletion {
+    pub channel_id: u64,
+    pub message_id: u64,
+    // Seconds since the epoch.
+    pub at: i64,
+}
+
+// Bookkeeping for the bot channel auto-moderation.
+#[derive(Default)]
+pub struct ModerationState {
+    // When each member last sent each command text in the bot channel.
+    recent: HashMap<(u64, String), Instant>,
+    // When each slow-moded command last ran.
+    last_run: HashMap<String, Instant>,
+}
+
+pub struct ModerationContainer;
+
+impl TypeMapKey for ModerationContainer {
+    type Value = Mutex<ModerationState>;
+}
+
+// What the auto-moderation decided to do with a command.
+enum Verdict {
+    Allow,
+    Duplicate,
+    SlowMode(u64),
+}
+
+// Delete a message after a delay without blocking the caller.
+pub async fn delete_later(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    delay: Duration,
+) {
+    let at = Utc::now().timestamp() + delay.as_secs() as i64;
+    settings::update(&ctx.data, |settings| {
+        settings.pending_deletions.push(PendingDeletion {
+            channel_id: channel_id.0,
+            message_id: message_id.0,
+            at,
+        });
+    })
+    .await;
+
+    let http = ctx.http.clone();
+    let data = ctx.data.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        delete(&http, &data, channel_id, message_id).await;
+    });
+}
+
+async fn delete(http: &Http, data: &RwLock<TypeMap>, channel_id: ChannelId, message_id: MessageId) {
+    if let Err(why) = channel_id.delete_message(http, message_id).await {
+        println!("Could not delete message {}: {:?}", message_id, why);
+    }
+    settings::update(data, |settings| {
+        settings
+            .pending_deletions
+            .retain(|pending| pending.message_id != message_id.0);
+    })
+    .await;
+}
+
+// Delete the messages whose wait was cut short by a restart. The scheduler runs this every
+// minute.
+async fn sweep(http: &Http, data: &RwLock<TypeMap>) {
+    let now = Utc::now().timestamp();
+    let overdue: Vec<PendingDeletion> = settings::read(data, |settings| {
+        settings
+            .pending_deletions
+            .iter()
+            .filter(|pending| pending.at + OVERDUE_SECS <= now)
+            .cloned()
+            .collect()
+    })
+    .await;
+    for pending in overdue {
+        delete(
+            http,
+            data,
+            ChannelId(pending.channel_id),
+            MessageId(pending.message_id),
+        )
+        .await;
+    }
+}
+
+// Decide whether a command sent in the bot channel should run. Returns false if the
+// command was collapsed into an identical one or hit a slow mode cooldown.
+pub async fn check_command(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let config = config::get(&ctx.data).await;
+    let moderation = &config.moderation;
+    if !moderation.enabled || msg.channel_id.0 != config.bot_channel {
+        return true;
+    }
+
+    let verdict = {
+        let data = ctx.data.read().await;
+        let mut state = data
+            .get::<ModerationContainer>()
+            .expect("Expected moderation state in typemap.")
+            .lock()
+            .await;
+
+        let now = Instant::now();
+        let window = Duration::from_secs(moderation.duplicate_window_secs);
+        state
+            .recent
+            .retain(|_, seen| now.duration_since(*seen) < window);
+
+        // Repeats keep the window open, so a burst of the same command collapses into one. Only
+        // a member's own repeats count, two people can both ask for `.board`.
+        let key = (msg.author.id.0, msg.content.trim().to_lowercase());
+        if state.recent.insert(key, now).is_some() {
+            Verdict::Duplicate
+        } else {
+            match moderation.slow_mode.get(command_name) {
+                Some(&cooldown) => {
+                    let cooldown = Duration::from_secs(cooldown);
+                    match state.last_run.get(command_name) {
+                        Some(last) if now.duration_since(*last) < cooldown => {
+                            let left = cooldown - now.duration_since(*last);
+                            Verdict::SlowMode(left.as_secs() + 1)
+                        }
+                        _ => {
+                            state.last_run.insert(command_name.to_string(), now);
+                            Verdict::Allow
+                        }
+                    }
+                }
+                None => Verdict::Allow,
+            }
+        }
+    };
+
+    match verdict {
+        Verdict::Allow => true,
+        Verdict::Duplicate => {
+            if let Err(why) = msg.delete(&ctx.http).await {
+                println!("Could not delete duplicate command: {:?}", why);
+            }
+            false
+        }
+        Verdict::SlowMode(wait) => {
+            let reply = format!(
+                "`.{}` is in slow mode here, try again in {}s",
+                command_name, wait
+            );
+            refuse(ctx, msg, &reply).await;
+            false
+        }
+    }
+}
+
+// Tell someone why their command didn't run. In the bot channel, with auto-moderation on, the
+// command and the reply are both deleted after a while.
+pub async fn refuse(ctx: &Context, msg: &Message, reply: &str) {
+    let sent = match msg.reply(&ctx.http, reply).await {
+        Ok(sent) => Some(sent),
+        Err(why) => {
+            println!("Error replying to a refused command: {:?}", why);
+            None
+        }
+    };
+
+    let config = config::get(&ctx.data).await;
+    let moderation = &config.moderation;
+    if !moderation.enabled || msg.channel_id.0 != config.bot_channel {
+        return;
+    }
+    let delay = Duration::from_secs(moderation.delete_failed_after_secs);
+    if let Some(sent) = sent {
+        delete_later(ctx, sent.channel_id, sent.id, delay).await;
+    }
+    delete_later(ctx, msg.channel_id, msg.id, delay).await;
+}
+
+// Clean up after a command that failed or wasn't recognised in the bot channel.
+pub async fn command_failed(ctx: &Context, msg: &Message) {
+    let config = config::get(&ctx.data).await;
+    let moderation = &config.moderation;
+    if !moderation.enabled || msg.channel_id.0 != config.bot_channel {
+        return;
+    }
+
+    let delay = Duration::from_secs(moderation.delete_failed_after_secs);
+    delete_later(ctx, msg.channel_id, msg.id, delay).await;
+}
+
+// Schedule a message for deletion if its channel has auto-delete turned on.
+pub async fn auto_delete(ctx: &Context, msg: &Message) {
+    if let Some(minutes) = settings::channel(&ctx.data, msg.channel_id)
+        .await
+        .autodelete_minutes
+    {
+        // Settings from before the limit can be longer.
+        let minutes = minutes.min(MAX_AUTODELETE_MINUTES);
+        delete_later(
+            ctx,
+            msg.channel_id,
+            msg.id,
+            Duration::from_secs(minutes * 60),
+        )
+        .await;
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Delete commands and the bot's replies in this channel after some minutes, up to a week (10080)."
+)]
+#[usage("<minutes|off>")]
+async fn autodelete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let minutes = match args.current() {
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => None,
+        Some(_) => match args.single::<u64>() {
+            Ok(minutes) if (1..=MAX_AUTODELETE_MINUTES).contains(&minutes) => Some(minutes),
+            _ => {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "Use `.autodelete <minutes>`, up to {} for a week, or `.autodelete off`",
+                        MAX_AUTODELETE_MINUTES
+                    ),
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let current = settings::channel(&ctx.data, msg.channel_id).await;
+            let reply = match current.autodelete_minutes {
+                Some(minutes) => format!("Messages here are deleted after {} minutes.", minutes),
+                None => "Auto-delete is off in this channel.".to_string(),
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .channels
+            .entry(msg.channel_id.0)
+            .or_default()
+            .autodelete_minutes = minutes;
+    })
+    .await;
+
+    let reply = match minutes {
+        Some(minutes) => format!(
+            "Commands and my replies in this channel will be deleted after {} minutes.",
+            minutes
+        ),
+        None => "Auto-delete is off in this channel.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
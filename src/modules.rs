@@ -0,0 +1,593 @@
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, Command, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::{Message, Reaction},
+        id::{ChannelId, GuildId},
+        interactions::message_component::MessageComponentInteraction,
+    },
+    prelude::*,
+};
+
+use crate::{
+    accounts, analysis, animation, backup, broadcast, chess960, clubboard,
+    config::{self, Config},
+    content, digest, emoji, eval, explorer, fen, follow, fun, game, general, guesseval, history,
+    leaderboard, meetup, moderation, openings,
+    permissions::{self, ADMIN_CHECK},
+    playing, potd, previews, profile, puzzle, puzzlerace, quotes, rating, reactionmoves, relay,
+    replay,
+    scheduler::{self, JobKind},
+    settings::{self, GuildSettings},
+    setup, simul, study, timezone, tournaments, tv, votechess, watch, EMBED_SIDE_COLOR,
+};
+
+#[group]
+#[commands(module)]
+struct Modules;
+
+// A server setting a module reads, listed by `.module show`.
+pub struct ModuleSetting {
+    pub name: &'static str,
+    // The command that changes it.
+    pub command: &'static str,
+    pub value: fn(&GuildSettings) -> String,
+}
+
+// A feature of the bot: its commands, what it does with chat messages and buttons, and the
+// state it keeps in the typemap. Servers can turn optional modules off with `.module`.
+#[async_trait]
+pub trait BotModule: Sync {
+    // The name admins use to turn it on and off.
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    // Core modules, like settings and permissions, are always on.
+    fn optional(&self) -> bool {
+        true
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        None
+    }
+
+    // Put the module's containers in the typemap before the bot connects.
+    fn insert_data(&self, _data: &mut TypeMap) {}
+
+    // A chat message that isn't a command.
+    async fn normal_message(&self, _ctx: &Context, _msg: &Message) {}
+
+    // A command no group has. Returns true if the module answered it.
+    async fn unknown_command(&self, _ctx: &Context, _msg: &Message, _name: &str) -> bool {
+        false
+    }
+
+    // Someone used a button or menu. Returns true if it was the module's.
+    async fn component(&self, _ctx: &Context, _component: &MessageComponentInteraction) -> bool {
+        false
+    }
+
+    // Someone added or took away a reaction. Returns true if it was for the module.
+    async fn reaction(&self, _ctx: &Context, _reaction: &Reaction) -> bool {
+        false
+    }
+
+    // The jobs the scheduler should add for the module, with their default cron schedules.
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        Vec::new()
+    }
+
+    // A scheduled job came due. Every module sees every job, so one can piggyback on another's.
+    async fn run_job(&self, _http: &Arc<Http>, _data: &Arc<RwLock<TypeMap>>, _kind: JobKind) {}
+
+    // The server settings the module reads.
+    fn settings(&self) -> &'static [ModuleSetting] {
+        &[]
+    }
+}
+
+// Every module, in the order they see messages and buttons.
+pub static MODULES: &[&dyn BotModule] = &[
+    &general::GeneralModule,
+    &quotes::QuotesModule,
+    &game::GamesModule,
+    &reactionmoves::ReactionMovesModule,
+    &rating::RatingsModule,
+    &leaderboard::LeaderboardsModule,
+    &history::HistoryModule,
+    &profile::ProfilesModule,
+    &puzzle::PuzzlesModule,
+    &puzzlerace::PuzzleRacesModule,
+    &simul::SimulsModule,
+    &votechess::VoteChessModule,
+    &chess960::Chess960Module,
+    &fun::FunModule,
+    &content::ContentModule,
+    &potd::PositionOfTheDayModule,
+    &settings::PreferencesModule,
+    &moderation::ModerationModule,
+    &fen::FensModule,
+    &setup::SetupModule,
+    &openings::OpeningsModule,
+    &explorer::ExplorerModule,
+    &previews::PreviewsModule,
+    &replay::ReplaysModule,
+    &study::StudiesModule,
+    &animation::AnimationsModule,
+    &accounts::AccountsModule,
+    &watch::WatchModule,
+    &tv::TvModule,
+    &playing::PlayingModule,
+    &follow::FollowsModule,
+    &relay::RelaysModule,
+    &tournaments::TournamentsModule,
+    &clubboard::ClubBoardModule,
+    &digest::DigestModule,
+    &emoji::EmojiPiecesModule,
+    &meetup::MeetupsModule,
+    &analysis::AnalysisModule,
+    &eval::EvalModule,
+    &guesseval::GuessEvalModule,
+    &timezone::TimezonesModule,
+    &scheduler::SchedulerModule,
+    &backup::BackupsModule,
+    &broadcast::BroadcastModule,
+    &permissions::CommandPermissionsModule,
+    &ModulesModule,
+];
+
+struct ModulesModule;
+
+impl BotModule for ModulesModule {
+    fn name(&self) -> &'static str {
+        "modules"
+    }
+
+    fn description(&self) -> &'static str {
+        "Turning modules on and off."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&MODULES_GROUP)
+    }
+}
+
+fn find(name: &str) -> Option<&'static dyn BotModule> {
+    MODULES
+        .iter()
+        .copied()
+        .find(|module| module.name().eq_ignore_ascii_case(name))
+}
+
+fn has_command(commands: &[&Command], name: &str) -> bool {
+    commands.iter().any(|command| {
+        command.options.names.contains(&name) || has_command(command.options.sub_commands, name)
+    })
+}
+
+fn group_has_command(group: &CommandGroup, name: &str) -> bool {
+    has_command(group.options.commands, name)
+        || group
+            .options
+            .sub_groups
+            .iter()
+            .any(|group| group_has_command(group, name))
+}
+
+// The module a command belongs to.
+fn owner(command_name: &str) -> Option<&'static dyn BotModule> {
+    MODULES.iter().copied().find(|module| {
+        module
+            .group()
+            .is_some_and(|group| group_has_command(group, command_name))
+    })
+}
+
+fn find_in_group(group: &'static CommandGroup, name: &str) -> Option<&'static Command> {
+    group
+        .options
+        .commands
+        .iter()
+        .copied()
+        .find(|command| command.options.names.contains(&name))
+        .or_else(|| {
+            group
+                .options
+                .sub_groups
+                .iter()
+                .find_map(|group| find_in_group(group, name))
+        })
+}
+
+// The command `words` start with, following subcommands: its module and the main names on the
+// way down, like ["module", "enable"]. Subcommand names like "clear" aren't unique on their own.
+pub fn find_command<'a>(
+    words: impl IntoIterator<Item = &'a str>,
+) -> Option<(&'static dyn BotModule, Vec<&'static str>)> {
+    let mut words = words.into_iter();
+    let first = words.next()?;
+    let (module, mut command) = MODULES.iter().copied().find_map(|module| {
+        find_in_group(module.group()?, first).map(|command| (module, command))
+    })?;
+
+    let mut path = vec![command.options.names[0]];
+    for word in words {
+        match command
+            .options
+            .sub_commands
+            .iter()
+            .find(|sub| sub.options.names.contains(&word))
+        {
+            Some(sub) => {
+                command = sub;
+                path.push(sub.options.names[0]);
+            }
+            None => break,
+        }
+    }
+    Some((module, path))
+}
+
+// The command a message runs, see `find_command`. The framework only tells hooks the last name.
+pub fn invoked_command(content: &str) -> Option<(&'static dyn BotModule, Vec<&'static str>)> {
+    let content = content.trim_start();
+    // Commands can start with a mention of the bot instead of the prefix.
+    let rest = match content.strip_prefix('.') {
+        Some(rest) => rest,
+        None => content.strip_prefix("<@")?.split_once('>')?.1,
+    };
+    find_command(rest.split_whitespace())
+}
+
+// The modules a guild turned off. Everything is on in DMs.
+async fn disabled(data: &RwLock<TypeMap>, guild_id: Option<GuildId>) -> Vec<String> {
+    match guild_id {
+        Some(guild_id) => settings::guild(data, guild_id)
+            .await
+            .disabled_modules
+            .into_iter()
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+async fn enabled_modules(
+    data: &RwLock<TypeMap>,
+    guild_id: Option<GuildId>,
+) -> Vec<&'static dyn BotModule> {
+    let disabled = disabled(data, guild_id).await;
+    MODULES
+        .iter()
+        .copied()
+        .filter(|module| !module.optional() || !disabled.iter().any(|name| name == module.name()))
+        .collect()
+}
+
+// Whether a guild has the module on. For pollers that go through every server's settings.
+pub fn enabled(guild: &GuildSettings, module: &dyn BotModule) -> bool {
+    !module.optional() || !guild.disabled_modules.contains(module.name())
+}
+
+// Whether the module is on in the server a channel belongs to. DMs have everything on.
+pub async fn enabled_in(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    module: &dyn BotModule,
+) -> bool {
+    let guild_id = match channel_id.to_channel(http).await {
+        Ok(channel) => channel.guild().map(|channel| channel.guild_id),
+        Err(why) => {
+            println!("Error looking up channel {}: {:?}", channel_id, why);
+            None
+        }
+    };
+    match guild_id {
+        Some(guild_id) => enabled(&settings::guild(data, guild_id).await, module),
+        None => true,
+    }
+}
+
+// A channel setting for `.module show`.
+pub fn show_channel(channel: Option<u64>) -> String {
+    channel.map_or("not set".to_string(), |id| format!("<#{}>", id))
+}
+
+pub fn on_off(on: bool) -> String {
+    if on { "on" } else { "off" }.to_string()
+}
+
+pub fn show_role(role: Option<u64>) -> String {
+    role.map_or("not set".to_string(), |id| format!("<@&{}>", id))
+}
+
+// The default jobs of every module.
+pub fn jobs(config: &Config) -> Vec<(JobKind, String)> {
+    MODULES
+        .iter()
+        .flat_map(|module| module.jobs(config))
+        .collect()
+}
+
+pub async fn run_job(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, kind: JobKind) {
+    for module in MODULES {
+        module.run_job(&http, &data, kind).await;
+    }
+}
+
+// Give every module a chance to set up its state.
+pub fn insert_data(data: &mut TypeMap) {
+    for module in MODULES {
+        module.insert_data(data);
+    }
+}
+
+// Stop commands from modules the guild turned off.
+pub async fn command_enabled(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let module = match invoked_command(&msg.content)
+        .map(|(module, _)| module)
+        .or_else(|| owner(command_name))
+    {
+        Some(module) if module.optional() => module,
+        _ => return true,
+    };
+    if !disabled(&ctx.data, msg.guild_id)
+        .await
+        .iter()
+        .any(|name| name == module.name())
+    {
+        return true;
+    }
+
+    let reply = format!(
+        "The {} module is turned off here, an admin can turn it back on with `.module enable {}`.",
+        module.name(),
+        module.name()
+    );
+    moderation::refuse(ctx, msg, &reply).await;
+    false
+}
+
+pub async fn normal_message(ctx: &Context, msg: &Message) {
+    for module in enabled_modules(&ctx.data, msg.guild_id).await {
+        module.normal_message(ctx, msg).await;
+    }
+}
+
+pub async fn unknown_command(ctx: &Context, msg: &Message, name: &str) -> bool {
+    for module in enabled_modules(&ctx.data, msg.guild_id).await {
+        if module.unknown_command(ctx, msg, name).await {
+            return true;
+        }
+    }
+    false
+}
+
+pub async fn component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    for module in enabled_modules(&ctx.data, component.guild_id).await {
+        if module.component(ctx, component).await {
+            return true;
+        }
+    }
+    false
+}
+
+pub async fn reaction(ctx: &Context, reaction: &Reaction) -> bool {
+    for module in enabled_modules(&ctx.data, reaction.guild_id).await {
+        if module.reaction(ctx, reaction).await {
+            return true;
+        }
+    }
+    false
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[sub_commands(show, enable, disable)]
+#[description("List the bot's modules and which ones are on in this server.")]
+async fn module(ctx: &Context, msg: &Message) -> CommandResult {
+    let disabled = disabled(&ctx.data, msg.guild_id).await;
+    let desc = MODULES
+        .iter()
+        .map(|module| {
+            let state = if !module.optional() {
+                "always on"
+            } else if disabled.iter().any(|name| name == module.name()) {
+                "off"
+            } else {
+                "on"
+            };
+            format!("**{}** ({}) {}", module.name(), state, module.description())
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Modules");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| {
+                    f.text(".module show <name> · .module enable <name> · .module disable <name>");
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Every command name in a group, top-level commands first.
+fn command_names(group: &CommandGroup) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = group
+        .options
+        .commands
+        .iter()
+        .filter_map(|command| command.options.names.first().copied())
+        .collect();
+    for group in group.options.sub_groups {
+        names.extend(command_names(group));
+    }
+    names
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Show a module's commands, scheduled jobs and settings in this server.")]
+#[usage("<module>")]
+#[example("puzzles")]
+async fn show(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let module = match args.current().and_then(find) {
+        Some(module) => module,
+        None => {
+            msg.reply(&ctx.http, "I don't have that module, see `.module`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let guild = settings::guild(&ctx.data, guild_id).await;
+
+    let mut desc = module.description().to_string();
+    desc.push_str(if enabled(&guild, module) {
+        "\n\nOn in this server."
+    } else {
+        "\n\nOff in this server."
+    });
+    if let Some(group) = module.group() {
+        let commands = command_names(group)
+            .iter()
+            .map(|name| format!("`.{}`", name))
+            .collect::<Vec<_>>()
+            .join(" ");
+        desc.push_str(&format!("\n\n**Commands:** {}", commands));
+    }
+
+    let kinds: Vec<JobKind> = module
+        .jobs(&*config::get(&ctx.data).await)
+        .into_iter()
+        .map(|(kind, _)| kind)
+        .collect();
+    let jobs = settings::read(&ctx.data, |settings| {
+        settings
+            .jobs
+            .iter()
+            .filter(|job| kinds.contains(&job.kind))
+            .map(scheduler::describe)
+            .collect::<Vec<_>>()
+    })
+    .await;
+    if !jobs.is_empty() {
+        desc.push_str("\n\n**Jobs:**");
+        for job in jobs {
+            desc.push_str(&format!("\n{}", job));
+        }
+    }
+
+    if !module.settings().is_empty() {
+        desc.push_str("\n\n**Settings:**");
+        for setting in module.settings() {
+            desc.push_str(&format!(
+                "\n{}: {} (`.{}`)",
+                setting.name,
+                (setting.value)(&guild),
+                setting.command
+            ));
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(format!("Module: {}", module.name()));
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Turn the module named in `args` on or off.
+async fn set_enabled(ctx: &Context, msg: &Message, args: Args, enable: bool) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let module = match args.current().and_then(find) {
+        Some(module) => module,
+        None => {
+            msg.reply(&ctx.http, "I don't have that module, see `.module`")
+                .await?;
+            return Ok(());
+        }
+    };
+    if !module.optional() {
+        msg.reply(
+            &ctx.http,
+            format!("The {} module can't be turned off.", module.name()),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    settings::update(&ctx.data, |settings| {
+        let disabled = &mut settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .disabled_modules;
+        if enable {
+            disabled.remove(module.name());
+        } else {
+            disabled.insert(module.name().to_string());
+        }
+    })
+    .await;
+
+    let state = if enable { "on" } else { "off" };
+    msg.reply(
+        &ctx.http,
+        format!("The {} module is {} in this server.", module.name(), state),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Turn a module back on.")]
+#[usage("<module>")]
+#[example("fun")]
+async fn enable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_enabled(ctx, msg, args, true).await
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Turn off a module and all of its commands in this server.")]
+#[usage("<module>")]
+#[example("meetups")]
+async fn disable(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_enabled(ctx, msg, args, false).await
+}
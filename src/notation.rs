@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+// Which letters stand for the pieces in SAN. Languages share the files, ranks and
+// castling, only the piece letters change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Notation {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 6] = [
+        Notation::English,
+        Notation::German,
+        Notation::French,
+        Notation::Spanish,
+        Notation::Italian,
+        Notation::Dutch,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::English => "en",
+            Notation::German => "de",
+            Notation::French => "fr",
+            Notation::Spanish => "es",
+            Notation::Italian => "it",
+            Notation::Dutch => "nl",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Notation> {
+        Notation::ALL
+            .iter()
+            .copied()
+            .find(|notation| notation.name().eq_ignore_ascii_case(name))
+    }
+
+    // King, queen, rook, bishop and knight, in that order.
+    fn letters(self) -> [char; 5] {
+        match self {
+            Notation::English => ['K', 'Q', 'R', 'B', 'N'],
+            Notation::German => ['K', 'D', 'T', 'L', 'S'],
+            Notation::French => ['R', 'D', 'T', 'F', 'C'],
+            Notation::Spanish | Notation::Italian => ['R', 'D', 'T', 'A', 'C'],
+            Notation::Dutch => ['K', 'D', 'T', 'L', 'P'],
+        }
+    }
+
+    // Swap every piece letter in `san` from one set of letters to another.
+    fn translate(san: &str, from: [char; 5], to: [char; 5]) -> String {
+        san.chars()
+            .map(|c| match from.iter().position(|&letter| letter == c) {
+                Some(index) => to[index],
+                None => c,
+            })
+            .collect()
+    }
+
+    // A move written in this notation, in English SAN. "Sf3" -> "Nf3" in German.
+    pub fn to_english(self, san: &str) -> String {
+        Notation::translate(san, self.letters(), Notation::English.letters())
+    }
+
+    // Whether a piece letter in `san` is another piece in English, like R for the king in French
+    // and the rook in English.
+    pub fn clashes_with_english(self, san: &str) -> bool {
+        let english = Notation::English.letters();
+        san.chars().any(|c| {
+            match (
+                self.letters().iter().position(|&letter| letter == c),
+                english.iter().position(|&letter| letter == c),
+            ) {
+                (Some(here), Some(there)) => here != there,
+                _ => false,
+            }
+        })
+    }
+
+    // An English SAN move written in this notation. "Nf3" -> "Cf3" in French.
+    pub fn localize(self, san: &str) -> String {
+        Notation::translate(san, Notation::English.letters(), self.letters())
+    }
+}
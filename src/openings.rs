@@ -0,0 +1,181 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+use shakmaty::{fen::Fen, Chess, EnPassantMode, Move, Position};
+
+use crate::{eval, game, modules::BotModule, pgn, EMBED_SIDE_COLOR};
+
+// ECO code, name and moves of each opening, shipped with the bot.
+const BOOK: &str = include_str!("../openings.tsv");
+
+#[group]
+#[commands(opening)]
+struct Openings;
+
+pub struct OpeningsModule;
+
+impl BotModule for OpeningsModule {
+    fn name(&self) -> &'static str {
+        "openings"
+    }
+
+    fn description(&self) -> &'static str {
+        "Opening names for games and move sequences, see `.opening`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&OPENINGS_GROUP)
+    }
+}
+
+#[derive(Debug)]
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+    // The book line, like "1. e4 c5 2. Nf3".
+    pub moves: String,
+}
+
+impl Opening {
+    // "B90 Sicilian Defense: Najdorf Variation"
+    pub fn describe(&self) -> String {
+        format!("{} {}", self.eco, self.name)
+    }
+}
+
+// Openings are looked up by the position their moves reach, so transpositions find them too.
+struct Book {
+    openings: HashMap<String, Opening>,
+    // The longest line in the book, in plies. Games aren't looked at past it.
+    depth: usize,
+}
+
+// The board, side to move, castling and en passant fields of a position's FEN.
+fn position_key(pos: &Chess) -> String {
+    Fen::from_position(pos, EnPassantMode::Legal)
+        .to_string()
+        .split(' ')
+        .take(4)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn book() -> &'static Book {
+    static BOOK_POSITIONS: OnceLock<Book> = OnceLock::new();
+    BOOK_POSITIONS.get_or_init(|| {
+        let mut book = Book {
+            openings: HashMap::new(),
+            depth: 0,
+        };
+        // The first line names the columns.
+        for line in BOOK.lines().skip(1) {
+            let mut fields = line.split('\t');
+            let (eco, name, moves) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(eco), Some(name), Some(moves)) => (eco, name, moves),
+                _ => continue,
+            };
+            let game = match pgn::parse(moves) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Bad line in the opening book, {}: {}", name, why);
+                    continue;
+                }
+            };
+            book.depth = book.depth.max(game.ply_count());
+            book.openings
+                .entry(position_key(&game.position_after(game.ply_count())))
+                .or_insert(Opening {
+                    eco: eco.to_string(),
+                    name: name.to_string(),
+                    moves: moves.to_string(),
+                });
+        }
+        book
+    })
+}
+
+// The opening a game went into: the last position it reached that's in the book, with the
+// number of plies it took to get there.
+pub fn identify(start: &Chess, moves: &[Move]) -> Option<(usize, &'static Opening)> {
+    let book = book();
+    let mut pos = start.clone();
+    let mut found = None;
+    for (ply, m) in moves.iter().take(book.depth).enumerate() {
+        pos.play_unchecked(*m);
+        if let Some(opening) = book.openings.get(&position_key(&pos)) {
+            found = Some((ply + 1, opening));
+        }
+    }
+    found
+}
+
+#[command]
+#[aliases("eco")]
+#[description("Name the opening of a game played here, a PGN, or some moves.")]
+#[usage("<game id | PGN | moves>")]
+#[example("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6")]
+async fn opening(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim().trim_matches('`');
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.opening <game id, PGN or moves>`")
+            .await?;
+        return Ok(());
+    }
+
+    let found = match text.trim_start_matches('#').parse::<u32>() {
+        Ok(id) => match eval::stored_game(&ctx.data, msg, id).await {
+            Some(game) => game.opening(),
+            None => {
+                msg.reply(&ctx.http, format!("I don't know a game #{} here.", id))
+                    .await?;
+                return Ok(());
+            }
+        },
+        Err(_) => {
+            let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+            match pgn::parse_in(text, notation) {
+                Ok(game) => identify(&game.start, &game.moves),
+                Err(why) => {
+                    msg.reply(&ctx.http, format!("I can't read those moves: {}.", why))
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let (ply, opening) = match found {
+        Some(found) => found,
+        None => {
+            msg.reply(&ctx.http, "That isn't an opening I know.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(opening.describe());
+                e.color(EMBED_SIDE_COLOR);
+                e.description(&opening.moves);
+                e.footer(|f| {
+                    f.text(format!("Reached on move {}", ply.div_ceil(2)));
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
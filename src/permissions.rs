@@ -0,0 +1,442 @@
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::{
+        macros::{check, command, group},
+        Args, CommandGroup, CommandOptions, CommandResult, Reason,
+    },
+    http::Http,
+    model::{
+        channel::{ChannelType, Message, PermissionOverwriteType},
+        id::{ChannelId, GuildId, RoleId, UserId},
+        permissions::Permissions,
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    moderation,
+    modules::{self, BotModule, ModuleSetting},
+    settings, EMBED_SIDE_COLOR,
+};
+
+#[group]
+#[commands(perm)]
+struct CommandPermissions;
+
+pub struct CommandPermissionsModule;
+
+const PERMISSION_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Commands with role rules",
+    command: "perm",
+    value: |guild| guild.command_overrides.len().to_string(),
+}];
+
+impl BotModule for CommandPermissionsModule {
+    fn name(&self) -> &'static str {
+        "permissions"
+    }
+
+    fn description(&self) -> &'static str {
+        "Which roles can use which commands, see `.perm`."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&COMMANDPERMISSIONS_GROUP)
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        PERMISSION_SETTINGS
+    }
+}
+
+// Which roles may or may not use a command in a guild.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandOverride {
+    // If any roles are allowed, only they can use the command.
+    pub allow: Vec<u64>,
+    pub deny: Vec<u64>,
+}
+
+impl CommandOverride {
+    fn allows(&self, roles: &[RoleId]) -> bool {
+        let has = |list: &[u64]| roles.iter().any(|role| list.contains(&role.0));
+        !has(&self.deny) && (self.allow.is_empty() || has(&self.allow))
+    }
+}
+
+// "Perm" or ".perm" -> "perm".
+fn command_key(name: &str) -> String {
+    name.trim_start_matches('.').to_lowercase()
+}
+
+// Overrides are keyed on the command's full path, like "module enable", so subcommands that
+// share a name don't share their rules.
+fn invoked_key(msg: &Message, command_name: &str) -> String {
+    match modules::invoked_command(&msg.content) {
+        Some((_, path)) => path.join(" "),
+        None => command_key(command_name),
+    }
+}
+
+// Work out a member's guild-wide permissions over HTTP, since the bot runs without a cache.
+pub async fn guild_permissions(
+    http: &Http,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> serenity::Result<Permissions> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    if guild.owner_id == user_id {
+        return Ok(Permissions::all());
+    }
+
+    let member = guild_id.member(http, user_id).await?;
+    let mut permissions = guild
+        .roles
+        .get(&RoleId(guild_id.0))
+        .map(|everyone| everyone.permissions)
+        .unwrap_or_else(Permissions::empty);
+
+    for role in member.roles.iter().filter_map(|id| guild.roles.get(id)) {
+        permissions |= role.permissions;
+    }
+
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        Ok(Permissions::all())
+    } else {
+        Ok(permissions)
+    }
+}
+
+// A member's permissions in one channel, with the channel's overwrites applied. Threads use their
+// parent channel's overwrites, and everything is allowed in DMs.
+pub async fn channel_permissions(
+    http: &Http,
+    channel_id: ChannelId,
+    user_id: UserId,
+) -> serenity::Result<Permissions> {
+    let mut channel = match channel_id.to_channel(http).await?.guild() {
+        Some(channel) => channel,
+        None => return Ok(Permissions::all()),
+    };
+    if matches!(
+        channel.kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    ) {
+        if let Some(parent) = channel.category_id {
+            if let Some(parent) = parent.to_channel(http).await?.guild() {
+                channel = parent;
+            }
+        }
+    }
+
+    let mut permissions = guild_permissions(http, channel.guild_id, user_id).await?;
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        return Ok(permissions);
+    }
+    let member = channel.guild_id.member(http, user_id).await?;
+
+    // @everyone first, then every role the member has together, then the member's own.
+    let everyone = RoleId(channel.guild_id.0);
+    let mut role_allow = Permissions::empty();
+    let mut role_deny = Permissions::empty();
+    let mut member_overwrite = None;
+    for overwrite in &channel.permission_overwrites {
+        match overwrite.kind {
+            PermissionOverwriteType::Role(role) if role == everyone => {
+                permissions.remove(overwrite.deny);
+                permissions.insert(overwrite.allow);
+            }
+            PermissionOverwriteType::Role(role) if member.roles.contains(&role) => {
+                role_allow |= overwrite.allow;
+                role_deny |= overwrite.deny;
+            }
+            PermissionOverwriteType::Member(id) if id == user_id => {
+                member_overwrite = Some(overwrite);
+            }
+            _ => {}
+        }
+    }
+    permissions.remove(role_deny);
+    permissions.insert(role_allow);
+    if let Some(overwrite) = member_overwrite {
+        permissions.remove(overwrite.deny);
+        permissions.insert(overwrite.allow);
+    }
+
+    Ok(permissions)
+}
+
+async fn is_admin(ctx: &Context, guild_id: GuildId, user_id: UserId) -> serenity::Result<bool> {
+    let permissions = guild_permissions(&ctx.http, guild_id, user_id).await?;
+    Ok(permissions.contains(Permissions::MANAGE_GUILD))
+}
+
+// The override for a command in a guild, if one was set with `.perm`.
+async fn command_override(
+    ctx: &Context,
+    guild_id: GuildId,
+    command_name: &str,
+) -> Option<CommandOverride> {
+    settings::guild(&ctx.data, guild_id)
+        .await
+        .command_overrides
+        .remove(&command_key(command_name))
+        .filter(|o| !o.allow.is_empty() || !o.deny.is_empty())
+}
+
+// Whether a role override lets someone use a command. Admins are never locked out, and
+// commands without overrides are left alone.
+pub async fn command_allowed(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return true,
+    };
+    let key = invoked_key(msg, command_name);
+    let command_override = match command_override(ctx, guild_id, &key).await {
+        Some(command_override) => command_override,
+        None => return true,
+    };
+
+    // Without their roles there's no telling whether a deny applies, so only admins get through.
+    let allowed = match guild_id.member(&ctx.http, msg.author.id).await {
+        Ok(member) => command_override.allows(&member.roles),
+        Err(why) => {
+            println!("Could not get member for permission override: {:?}", why);
+            false
+        }
+    };
+    if allowed
+        || is_admin(ctx, guild_id, msg.author.id)
+            .await
+            .unwrap_or(false)
+    {
+        return true;
+    }
+
+    let reply = format!("You don't have a role that can use `.{}` here.", key);
+    moderation::refuse(ctx, msg, &reply).await;
+    false
+}
+
+// Only lets server admins (anyone who can manage the server) through, plus any roles the
+// command was granted to with `.perm allow`.
+#[check]
+#[name = "Admin"]
+async fn admin_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    let guild_id = msg
+        .guild_id
+        .ok_or_else(|| Reason::User("This command only works in servers.".to_string()))?;
+
+    let admin = is_admin(ctx, guild_id, msg.author.id)
+        .await
+        .map_err(|why| Reason::Log(format!("Could not get permissions: {:?}", why)))?;
+    if admin {
+        return Ok(());
+    }
+
+    let key = invoked_key(msg, options.names.first().copied().unwrap_or_default());
+    if let Some(command_override) = command_override(ctx, guild_id, &key).await {
+        let member = guild_id
+            .member(&ctx.http, msg.author.id)
+            .await
+            .map_err(|why| Reason::Log(format!("Could not get member: {:?}", why)))?;
+        if !command_override.allow.is_empty() && command_override.allows(&member.roles) {
+            return Ok(());
+        }
+    }
+
+    Err(Reason::User(
+        "You need the Manage Server permission for that.".to_string(),
+    ))
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[sub_commands(allow, deny, clear)]
+#[description("Show which roles can use which commands here.")]
+async fn perm(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let mut overrides = settings::guild(&ctx.data, guild_id)
+        .await
+        .command_overrides
+        .into_iter()
+        .filter(|(_, o)| !o.allow.is_empty() || !o.deny.is_empty())
+        .collect::<Vec<_>>();
+    overrides.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let roles = |list: &[u64]| {
+        list.iter()
+            .map(|role| format!("<@&{}>", role))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let desc = if overrides.is_empty() {
+        "Every command follows its usual rules.".to_string()
+    } else {
+        overrides
+            .iter()
+            .map(|(name, o)| {
+                let mut line = format!("`.{}`", name);
+                if !o.allow.is_empty() {
+                    line.push_str(&format!(" only {}", roles(&o.allow)));
+                }
+                if !o.deny.is_empty() {
+                    line.push_str(&format!(" not {}", roles(&o.deny)));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Command permissions");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| {
+                    f.text(".perm allow <command> @role · .perm deny <command> @role · .perm clear <command>");
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// The override key of a command named in words, like "module enable". None if the bot doesn't
+// have the command, or a word isn't one of its subcommands.
+fn command_path(words: &[String]) -> Option<String> {
+    let words = words
+        .iter()
+        .map(|word| command_key(word))
+        .collect::<Vec<_>>();
+    let (_, path) = modules::find_command(words.iter().map(String::as_str))?;
+    (path.len() == words.len()).then(|| path.join(" "))
+}
+
+// Change the override of the command and role named in `args`.
+async fn edit_override<F>(ctx: &Context, msg: &Message, mut args: Args, f: F) -> CommandResult
+where
+    F: FnOnce(&mut CommandOverride, u64),
+{
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let mut words = args.iter::<String>().flatten().collect::<Vec<_>>();
+    let role = words.pop().as_deref().and_then(utils::parse_role);
+    let role = match role {
+        Some(role) if !words.is_empty() => role,
+        _ => {
+            msg.reply(
+                &ctx.http,
+                "Name a command and a role, like `.perm allow record @Arbiter`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let name = match command_path(&words) {
+        Some(name) => name,
+        None => {
+            msg.reply(
+                &ctx.http,
+                format!("I don't have a `.{}` command.", words.join(" ")),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        let guild = settings.guilds.entry(guild_id.0).or_default();
+        let command_override = guild.command_overrides.entry(name.clone()).or_default();
+        command_override.allow.retain(|r| *r != role);
+        command_override.deny.retain(|r| *r != role);
+        f(command_override, role);
+    })
+    .await;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!("Updated who can use `.{}`, see `.perm`.", name));
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Let a role use a command. Once any role is allowed, only allowed roles (and admins) can use it.")]
+#[usage("<command> [subcommand] <@role>")]
+#[example("fenrender @Moderator")]
+async fn allow(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    edit_override(ctx, msg, args, |o, role| o.allow.push(role)).await
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Stop a role from using a command.")]
+#[usage("<command> [subcommand] <@role>")]
+#[example("module enable @Muted")]
+async fn deny(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    edit_override(ctx, msg, args, |o, role| o.deny.push(role)).await
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Remove every role override from a command.")]
+#[usage("<command> [subcommand]")]
+async fn clear(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let words = args.iter::<String>().flatten().collect::<Vec<_>>();
+    if words.is_empty() {
+        msg.reply(&ctx.http, "Use `.perm clear <command>`").await?;
+        return Ok(());
+    }
+    // Rules for commands the bot no longer has can still be cleared by their name.
+    let name = command_path(&words).unwrap_or_else(|| {
+        words
+            .iter()
+            .map(|word| command_key(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .command_overrides
+            .remove(&name);
+    })
+    .await;
+
+    msg.reply(
+        &ctx.http,
+        format!("`.{}` follows its usual rules again.", name),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,524 @@
+use std::{collections::HashMap, fmt};
+
+use shakmaty::{
+    san::{San, SanPlus},
+    Chess, Color, Move, Position,
+};
+
+use crate::{fen, notation::Notation, openings};
+
+// A game read from PGN: its tags, where it started and the mainline moves.
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub headers: Vec<(String, String)>,
+    pub start: Chess,
+    pub moves: Vec<Move>,
+    pub sans: Vec<SanPlus>,
+    // The result written after the moves, if any.
+    pub result: Option<String>,
+    // Sidelines, each branching off the mainline or another sideline.
+    pub variations: Vec<Variation>,
+    // Comments on the mainline, keyed by how many moves were played before them.
+    pub comments: HashMap<usize, String>,
+}
+
+// A sideline in parentheses, replacing one move of the line it branches off.
+#[derive(Debug, Clone)]
+pub struct Variation {
+    // The line this branches off. None for the mainline.
+    pub parent: Option<usize>,
+    // Index of the move in the parent line that this replaces.
+    pub branch_ply: usize,
+    pub moves: Vec<Move>,
+    pub sans: Vec<SanPlus>,
+}
+
+// A piece of movetext: a word, or the start or end of a variation.
+enum Token {
+    Word(String),
+    Comment(String),
+    Open,
+    Close,
+}
+
+// Where parsing is in one line of the game.
+struct Frame {
+    line: Option<usize>,
+    pos: Chess,
+    // The position before the last move, where a variation starting now branches from.
+    before_last: Option<Chess>,
+    // Set when a sideline has an illegal move, the rest of it is skipped.
+    dead: bool,
+}
+
+#[derive(Debug)]
+pub enum PgnError {
+    NoMoves,
+    BadFen(String),
+    IllegalMove { ply: usize, san: String },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::NoMoves => write!(f, "there are no moves in it"),
+            PgnError::BadFen(fen) => write!(f, "the starting FEN `{}` isn't valid", fen),
+            PgnError::IllegalMove { ply, san } => write!(
+                f,
+                "`{}` isn't legal on move {}{}",
+                san,
+                ply / 2 + 1,
+                if ply.is_multiple_of(2) {
+                    ""
+                } else {
+                    " for black"
+                }
+            ),
+        }
+    }
+}
+
+const RESULTS: [&str; 5] = ["1-0", "0-1", "1/2-1/2", "½-½", "*"];
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn ply_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    // The position after the first `ply` half-moves.
+    pub fn position_after(&self, ply: usize) -> Chess {
+        let mut pos = self.start.clone();
+        for m in self.moves.iter().take(ply) {
+            pos.play_unchecked(*m);
+        }
+        pos
+    }
+
+    pub fn result(&self) -> &str {
+        self.header("Result")
+            .filter(|result| *result != "*")
+            .or(self.result.as_deref())
+            .unwrap_or("*")
+    }
+
+    pub fn players(&self) -> Option<String> {
+        match (self.header("White"), self.header("Black")) {
+            (Some(white), Some(black)) => Some(format!("{} vs {}", white, black)),
+            _ => None,
+        }
+    }
+
+    // The opening from the headers, or from the opening book when they don't say.
+    pub fn opening(&self) -> Option<String> {
+        match (self.header("ECO"), self.header("Opening")) {
+            (Some(eco), Some(name)) => Some(format!("{} {}", eco, name)),
+            (None, Some(name)) => Some(name.to_string()),
+            (Some(eco), None) => Some(eco.to_string()),
+            (None, None) => {
+                openings::identify(&self.start, &self.moves).map(|(_, opening)| opening.describe())
+            }
+        }
+    }
+
+    // The half-move at index `ply` of a line written with its move number, e.g. "12. Nf3" or
+    // "12... Nf6".
+    pub fn numbered_move(
+        &self,
+        line: Option<usize>,
+        ply: usize,
+        notation: Notation,
+    ) -> Option<String> {
+        let sans = match line {
+            None => &self.sans,
+            Some(index) => &self.variations.get(index)?.sans,
+        };
+        let san = notation.localize(&sans.get(ply)?.to_string());
+        let start_black = self.start.turn() == Color::Black;
+        let offset = self.game_ply(line, ply) + start_black as usize;
+        let number = self.start.fullmoves().get() as usize + offset / 2;
+
+        Some(if offset.is_multiple_of(2) {
+            format!("{}. {}", number, san)
+        } else {
+            format!("{}... {}", number, san)
+        })
+    }
+
+    // How many moves a line has.
+    pub fn line_len(&self, line: Option<usize>) -> usize {
+        match line {
+            None => self.moves.len(),
+            Some(index) => self.variations[index].moves.len(),
+        }
+    }
+
+    // How many half-moves from the start of the game a point in a line is.
+    fn game_ply(&self, line: Option<usize>, ply: usize) -> usize {
+        match line {
+            None => ply,
+            Some(index) => {
+                let variation = &self.variations[index];
+                self.game_ply(variation.parent, variation.branch_ply) + ply
+            }
+        }
+    }
+
+    // The position after the first `ply` moves of a line.
+    pub fn position_in(&self, line: Option<usize>, ply: usize) -> Chess {
+        match line {
+            None => self.position_after(ply),
+            Some(index) => {
+                let variation = &self.variations[index];
+                let mut pos = self.position_in(variation.parent, variation.branch_ply);
+                for m in variation.moves.iter().take(ply) {
+                    pos.play_unchecked(*m);
+                }
+                pos
+            }
+        }
+    }
+
+    // Sidelines that replace the move at `ply` of a line.
+    pub fn branches_at(&self, line: Option<usize>, ply: usize) -> Vec<usize> {
+        (0..self.variations.len())
+            .filter(|&index| {
+                let variation = &self.variations[index];
+                variation.parent == line && variation.branch_ply == ply
+            })
+            .collect()
+    }
+
+    // The sidelines from the mainline down to `line`, outermost first.
+    pub fn line_path(&self, line: Option<usize>) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut current = line;
+        while let Some(index) = current {
+            path.push(index);
+            current = self.variations[index].parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+// Tag pairs like `[White "Lucy"]`.
+fn parse_header(tag: &str) -> Option<(String, String)> {
+    let tag = tag.trim();
+    let (name, value) = tag.split_once(char::is_whitespace)?;
+    let value = value.trim().trim_matches('"');
+    Some((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+// Take the move number off the front of a token, "12.Nf3" -> "Nf3", "12..." -> "".
+fn strip_move_number(token: &str) -> &str {
+    let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && token[digits..].starts_with('.') {
+        token[digits..].trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+// A single SAN move, if it's legal in `pos`.
+fn parse_move(pos: &Chess, san: &str) -> Option<Move> {
+    let san: San = san.trim_end_matches(['+', '#']).parse().ok()?;
+    san.to_move(pos).ok()
+}
+
+// Leave the innermost variation. One without any legal moves is dropped, nothing can branch
+// off it so it's always the last one.
+fn close_variation(stack: &mut Vec<Frame>, variations: &mut Vec<Variation>) {
+    if let Some(Frame {
+        line: Some(index), ..
+    }) = stack.pop()
+    {
+        if variations[index].moves.is_empty() {
+            variations.truncate(index);
+        }
+    }
+}
+
+// Read a PGN (or just its movetext) and check every mainline move is legal. Annotation glyphs
+// and comments on sidelines are skipped, and sidelines are kept up to their first illegal move.
+pub fn parse(text: &str) -> Result<PgnGame, PgnError> {
+    parse_in(text, Notation::English)
+}
+
+// Like `parse`, but moves may also use the piece letters of `notation`. Where a move reads
+// differently in the two, the localized reading wins.
+pub fn parse_in(text: &str, notation: Notation) -> Result<PgnGame, PgnError> {
+    let game = read(text, notation)?;
+    if game.moves.is_empty() {
+        return Err(PgnError::NoMoves);
+    }
+    Ok(game)
+}
+
+// Like `parse`, but a game without moves, just a starting position, is fine too.
+pub fn parse_any(text: &str) -> Result<PgnGame, PgnError> {
+    read(text, Notation::English)
+}
+
+fn read(text: &str, notation: Notation) -> Result<PgnGame, PgnError> {
+    let mut headers = Vec::new();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                headers.extend(parse_header(&tag));
+            }
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(comment));
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "[]{}();".contains(next) {
+                        break;
+                    }
+                    token.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(token));
+            }
+        }
+    }
+
+    let start = match headers.iter().find(|(tag, _)| tag == "FEN") {
+        Some((_, text)) => {
+            fen::parse_position(text).ok_or_else(|| PgnError::BadFen(text.clone()))?
+        }
+        None => Chess::default(),
+    };
+
+    let mut moves = Vec::new();
+    let mut sans = Vec::new();
+    let mut variations: Vec<Variation> = Vec::new();
+    let mut result = None;
+    let mut comments: HashMap<usize, String> = HashMap::new();
+    let mut stack = vec![Frame {
+        line: None,
+        pos: start.clone(),
+        before_last: None,
+        dead: false,
+    }];
+
+    for token in &tokens {
+        let word = match token {
+            Token::Open => {
+                let top = stack.last().expect("the mainline is never popped");
+                let frame = match (&top.before_last, top.dead) {
+                    (Some(pos), false) => {
+                        variations.push(Variation {
+                            parent: top.line,
+                            branch_ply: match top.line {
+                                None => moves.len() - 1,
+                                Some(index) => variations[index].moves.len() - 1,
+                            },
+                            moves: Vec::new(),
+                            sans: Vec::new(),
+                        });
+                        Frame {
+                            line: Some(variations.len() - 1),
+                            pos: pos.clone(),
+                            before_last: None,
+                            dead: false,
+                        }
+                    }
+                    // A variation with nothing to branch from.
+                    _ => Frame {
+                        line: None,
+                        pos: Chess::default(),
+                        before_last: None,
+                        dead: true,
+                    },
+                };
+                stack.push(frame);
+                continue;
+            }
+            Token::Close => {
+                if stack.len() > 1 {
+                    close_variation(&mut stack, &mut variations);
+                }
+                continue;
+            }
+            Token::Comment(comment) => {
+                let comment = clean_comment(comment);
+                if stack.len() == 1 && !comment.is_empty() {
+                    comments
+                        .entry(moves.len())
+                        .and_modify(|before| {
+                            before.push(' ');
+                            before.push_str(&comment);
+                        })
+                        .or_insert(comment);
+                }
+                continue;
+            }
+            Token::Word(word) => word.trim_matches('`'),
+        };
+
+        let in_mainline = stack.len() == 1;
+        let frame = stack.last_mut().expect("the mainline is never popped");
+        if RESULTS.contains(&word) {
+            if in_mainline {
+                result = Some(word.to_string());
+                break;
+            }
+            continue;
+        }
+
+        let word = strip_move_number(word).trim_end_matches(['!', '?']);
+        if word.is_empty() || word.starts_with('$') || frame.dead {
+            continue;
+        }
+
+        let localized = match notation {
+            Notation::English => None,
+            notation => parse_move(&frame.pos, &notation.to_english(word)),
+        };
+        let m = match localized.or_else(|| parse_move(&frame.pos, word)) {
+            Some(m) => m,
+            None if in_mainline => {
+                return Err(PgnError::IllegalMove {
+                    ply: moves.len(),
+                    san: word.to_string(),
+                })
+            }
+            None => {
+                frame.dead = true;
+                continue;
+            }
+        };
+
+        frame.before_last = Some(frame.pos.clone());
+        let san = SanPlus::from_move_and_play_unchecked(&mut frame.pos, m);
+        match frame.line {
+            None => {
+                sans.push(san);
+                moves.push(m);
+            }
+            Some(index) => {
+                variations[index].sans.push(san);
+                variations[index].moves.push(m);
+            }
+        }
+    }
+
+    while stack.len() > 1 {
+        close_variation(&mut stack, &mut variations);
+    }
+
+    Ok(PgnGame {
+        headers,
+        start,
+        moves,
+        sans,
+        result,
+        variations,
+        comments,
+    })
+}
+
+// A comment without the commands Lichess keeps in them, like `[%clk 0:03:00]` or
+// `[%cal Ge2e4]`.
+fn clean_comment(comment: &str) -> String {
+    let mut clean = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        clean.push_str(&rest[..start]);
+        rest = match rest[start..].find(']') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    clean.push_str(rest);
+    clean.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The games in a PGN file with several, each starting at its tags.
+pub fn split_games(text: &str) -> Vec<String> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut in_movetext = false;
+    for line in text.lines() {
+        let is_tag = line.trim_start().starts_with('[');
+        if is_tag && in_movetext {
+            games.push(std::mem::take(&mut current));
+            in_movetext = false;
+        }
+        if !is_tag && !line.trim().is_empty() {
+            in_movetext = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.push(current);
+    }
+    games
+}
+
+// Whether a chat message looks like pasted PGN movetext, e.g. "1. e4 e5 2. Nf3 Nc6".
+pub fn find_movetext(text: &str, notation: Notation) -> Option<PgnGame> {
+    let text = text.trim().trim_matches('`');
+    if !text.contains("1.") || !text.contains("2.") {
+        return None;
+    }
+
+    parse_in(text, notation)
+        .ok()
+        .filter(|game| game.ply_count() >= 4)
+}
+
+// PGN for a game with these tags and SAN moves, wrapped at 80 columns like most exports.
+pub fn write(headers: &[(String, String)], sans: &[String], result: &str) -> String {
+    let mut pgn = String::new();
+    for (name, value) in headers {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+    pgn.push('\n');
+
+    let mut words: Vec<String> = Vec::new();
+    for (ply, san) in sans.iter().enumerate() {
+        if ply % 2 == 0 {
+            words.push(format!("{}.", ply / 2 + 1));
+        }
+        words.push(san.clone());
+    }
+    words.push(result.to_string());
+
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > 80 {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    config::Config,
+    lichess,
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings, web,
+};
+
+// A member is announced at most this often, however many games they start.
+const ANNOUNCE_COOLDOWN: Duration = Duration::from_secs(30 * 60);
+
+#[group]
+#[commands(playingalerts, playingchannel)]
+struct Playing;
+
+pub struct PlayingModule;
+
+const PLAYING_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Alert channel",
+    command: "playingchannel",
+    value: |guild| modules::show_channel(guild.playing_channel),
+}];
+
+#[async_trait]
+impl BotModule for PlayingModule {
+    fn name(&self) -> &'static str {
+        "playing"
+    }
+
+    fn description(&self) -> &'static str {
+        "Posts when members start a rated Lichess game, for those who turn it on with `.playingalerts`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PLAYING_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<SeenContainer>(Mutex::default());
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::PlayingPoll, "*/2 * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::PlayingPoll {
+            poll_all(http, data).await;
+        }
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        PLAYING_SETTINGS
+    }
+}
+
+// The last game seen for a member, and when they were last announced.
+#[derive(Default)]
+pub struct Seen {
+    game_id: String,
+    announced: Option<Instant>,
+}
+
+// Keyed by member. Starts over on a restart, which at worst announces a game twice.
+pub struct SeenContainer;
+
+impl TypeMapKey for SeenContainer {
+    type Value = Mutex<HashMap<u64, Seen>>;
+}
+
+async fn with_seen<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut HashMap<u64, Seen>) -> T,
+{
+    let data = data.read().await;
+    let mut seen = data
+        .get::<SeenContainer>()
+        .expect("Expected seen games in typemap.")
+        .lock()
+        .await;
+    f(&mut seen)
+}
+
+// A member who turned alerts on and the channels they're announced in.
+struct Watched {
+    user_id: u64,
+    username: String,
+    channels: Vec<u64>,
+}
+
+// Check whether members who turned alerts on started a game. The scheduler runs this every couple
+// of minutes.
+pub async fn poll_all(http: &Http, data: &RwLock<TypeMap>) {
+    let watched: Vec<Watched> = settings::read(data, |settings| {
+        let channels: HashMap<u64, u64> = settings
+            .guilds
+            .iter()
+            .filter(|(_, guild)| modules::enabled(guild, &PlayingModule))
+            .filter_map(|(id, guild)| Some((*id, guild.playing_channel?)))
+            .collect();
+        settings
+            .users
+            .iter()
+            .filter_map(|(user_id, user)| {
+                let channels: Vec<u64> = user
+                    .playing_alerts
+                    .iter()
+                    .filter_map(|guild_id| channels.get(guild_id).copied())
+                    .collect();
+                if channels.is_empty() {
+                    return None;
+                }
+                Some(Watched {
+                    user_id: *user_id,
+                    username: user.lichess.clone()?,
+                    channels,
+                })
+            })
+            .collect()
+    })
+    .await;
+    if watched.is_empty() {
+        return;
+    }
+
+    let client = web::client(data).await;
+    let usernames: Vec<String> = watched.iter().map(|w| w.username.clone()).collect();
+    let playing = match lichess::playing_games(&client, &usernames).await {
+        Ok(playing) => playing,
+        Err(why) => {
+            println!("Error getting Lichess statuses: {:?}", why);
+            return;
+        }
+    };
+
+    for member in watched {
+        let game_id = match playing.get(&member.username.to_lowercase()) {
+            Some(game_id) => game_id,
+            None => continue,
+        };
+        // Each game is only looked at once.
+        let due = with_seen(data, |seen| {
+            let seen = seen.entry(member.user_id).or_default();
+            if &seen.game_id == game_id {
+                return false;
+            }
+            seen.game_id = game_id.clone();
+            seen.announced
+                .is_none_or(|at| at.elapsed() >= ANNOUNCE_COOLDOWN)
+        })
+        .await;
+        if !due {
+            continue;
+        }
+
+        let game = match lichess::export_game(&client, game_id).await {
+            Ok(game) => game,
+            Err(why) => {
+                println!("Error getting Lichess game {}: {:?}", game_id, why);
+                continue;
+            }
+        };
+        if !game.rated {
+            continue;
+        }
+
+        with_seen(data, |seen| {
+            seen.entry(member.user_id).or_default().announced = Some(Instant::now())
+        })
+        .await;
+        for channel in &member.channels {
+            if let Err(why) = announce(http, ChannelId(*channel), &member, &game).await {
+                println!("Error announcing a game being played: {:?}", why);
+            }
+        }
+    }
+}
+
+async fn announce(
+    http: &Http,
+    channel_id: ChannelId,
+    member: &Watched,
+    game: &lichess::Game,
+) -> serenity::Result<()> {
+    let (player, opponent) = match game.players.color_of(&member.username) {
+        Some("black") => (&game.players.black, &game.players.white),
+        _ => (&game.players.white, &game.players.black),
+    };
+    channel_id
+        .send_message(http, |m| {
+            m.content(format!(
+                "🔴 <@{}> is playing now: {} vs {}, rated {}. Spectate here: {}",
+                member.user_id,
+                player.display_name(),
+                opponent.display_name(),
+                game.speed,
+                game.url()
+            ));
+            m.allowed_mentions(|a| a.empty_users())
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description(
+    "Have the bot post here when you start a rated game on the Lichess account you linked, so others can watch. At most once every half hour."
+)]
+#[usage("[on|off]")]
+async fn playingalerts(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)").0;
+    let user = settings::user(&ctx.data, msg.author.id).await;
+    let on = match args.current() {
+        Some(arg) => match settings::parse_toggle(arg) {
+            Some(on) => on,
+            None => {
+                msg.reply(&ctx.http, "Use `.playingalerts on` or `.playingalerts off`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let reply = if user.playing_alerts.contains(&guild_id) {
+                "Your rated Lichess games are announced here. `.playingalerts off` stops it."
+            } else {
+                "Your games aren't announced here, `.playingalerts on` starts it."
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    if on && user.lichess.is_none() {
+        msg.reply(
+            &ctx.http,
+            "Link your account with `.link lichess <username>` first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let author = msg.author.id.0;
+    settings::update(&ctx.data, |settings| {
+        let alerts = &mut settings.users.entry(author).or_default().playing_alerts;
+        if on {
+            alerts.insert(guild_id);
+        } else {
+            alerts.remove(&guild_id);
+        }
+    })
+    .await;
+
+    let channel = settings::guild(&ctx.data, GuildId(guild_id))
+        .await
+        .playing_channel;
+    let reply = match (on, channel) {
+        (false, _) => "I won't announce your games here anymore.".to_string(),
+        (true, Some(channel)) => format!(
+            "I'll post in <#{}> when you start a rated game on Lichess.",
+            channel
+        ),
+        (true, None) => "I'll announce your rated games here once an admin picks a channel with `.playingchannel`.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Pick the channel where members' rated Lichess games are announced, for those who turned on `.playingalerts`."
+)]
+#[usage("<#channel|off>")]
+async fn playingchannel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let channel = match args.current() {
+        Some(arg) if settings::parse_toggle(arg) == Some(false) => None,
+        Some(arg) => match utils::parse_channel(arg) {
+            Some(channel) => Some(channel),
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.playingchannel #channel` or `.playingchannel off`",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let reply = match settings::guild(&ctx.data, guild_id).await.playing_channel {
+                Some(channel) => format!("Games being played are announced in <#{}>.", channel),
+                None => "This server doesn't announce games being played.".to_string(),
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .playing_channel = channel;
+    })
+    .await;
+
+    let reply = match channel {
+        Some(channel) => format!(
+            "Members' rated Lichess games will be announced in <#{}>. They turn it on with `.playingalerts on`.",
+            channel
+        ),
+        None => "I won't announce games being played anymore.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
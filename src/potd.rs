@@ -0,0 +1,238 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    http::Http,
+    model::{
+        channel::{Message, ReactionType},
+        id::ChannelId,
+    },
+    prelude::*,
+};
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, Position};
+
+use crate::{
+    config::{self, Config},
+    content,
+    diagram::{self, DiagramOptions},
+    modules::{self, BotModule},
+    render,
+    scheduler::JobKind,
+    settings, EMBED_SIDE_COLOR,
+};
+
+// People upvote suggestions in the thread with this.
+const UPVOTE: &str = "👍";
+
+pub struct PositionOfTheDayModule;
+
+#[async_trait]
+impl BotModule for PositionOfTheDayModule {
+    fn name(&self) -> &'static str {
+        "potd"
+    }
+
+    fn description(&self) -> &'static str {
+        "A position from the content to assess every day, with the verdict the day after."
+    }
+
+    fn jobs(&self, config: &Config) -> Vec<(JobKind, String)> {
+        match config.daily_position.channel {
+            Some(_) => vec![(
+                JobKind::DailyPosition,
+                format!("0 {} * * *", config.daily_position.hour % 24),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::DailyPosition {
+            post_daily(http, data).await;
+        }
+    }
+}
+
+// The position that was posted last, waiting for its assessment to be revealed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostedPosition {
+    pub index: usize,
+    pub channel_id: u64,
+    pub thread_id: u64,
+}
+
+// Reveal the previous day's assessment, then post a new position. The scheduler runs this
+// once a day.
+pub async fn post_daily(http: &Http, data: &RwLock<TypeMap>) {
+    let channel_id = match config::get(data).await.daily_position.channel {
+        Some(channel) => ChannelId(channel),
+        None => return,
+    };
+    if !modules::enabled_in(http, data, channel_id, &PositionOfTheDayModule).await {
+        return;
+    }
+
+    if let Err(why) = reveal(http, data).await {
+        println!("Error revealing the position of the day: {:?}", why);
+    }
+    if let Err(why) = post(http, data, channel_id).await {
+        println!("Error posting the position of the day: {:?}", why);
+    }
+}
+
+async fn post(http: &Http, data: &RwLock<TypeMap>, channel_id: ChannelId) -> serenity::Result<()> {
+    let content = content::get(data).await;
+    if content.positions.is_empty() {
+        return Ok(());
+    }
+
+    // Walk through the positions one day at a time.
+    let day = Utc::now().timestamp() / (24 * 60 * 60);
+    let index = day as usize % content.positions.len();
+    let position = &content.positions[index];
+
+    let pos: Chess = match position
+        .fen
+        .parse::<Fen>()
+        .ok()
+        .and_then(|fen| fen.into_position(CastlingMode::Standard).ok())
+    {
+        Some(pos) => pos,
+        None => {
+            println!("Skipping invalid position of the day: {}", position.fen);
+            return Ok(());
+        }
+    };
+
+    let guild_id = channel_id
+        .to_channel(http)
+        .await?
+        .guild()
+        .map(|channel| channel.guild_id);
+    let style = settings::board_style(data, guild_id, None).await;
+
+    let to_move = if pos.turn() == Color::White {
+        "White"
+    } else {
+        "Black"
+    };
+    let image = diagram::board_image(
+        http,
+        data,
+        channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let mut desc = match image {
+        Some(_) => String::new(),
+        None => format!("{}\n", render::board_for(pos.board(), &style)),
+    };
+    desc.push_str(&format!(
+        "**{} to move.** What would you play? Share your ideas in the thread and upvote the best ones with {}!",
+        to_move, UPVOTE
+    ));
+    let has_image = image.is_some();
+
+    let message = channel_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title(format!("Position of the day: {}", position.title));
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| {
+                    f.text("The assessment is revealed in 24 hours");
+                    f
+                });
+                e
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    let thread = channel_id
+        .create_public_thread(http, message.id, |t| {
+            t.name(format!("Position of the day: {}", position.title));
+            t.auto_archive_duration(1440);
+            t
+        })
+        .await?;
+
+    let posted = PostedPosition {
+        index,
+        channel_id: channel_id.0,
+        thread_id: thread.id.0,
+    };
+    settings::update(data, |settings| settings.daily_position = Some(posted)).await;
+
+    Ok(())
+}
+
+fn upvotes(message: &Message) -> u64 {
+    message
+        .reactions
+        .iter()
+        .filter(|r| r.reaction_type == ReactionType::Unicode(UPVOTE.to_string()))
+        .map(|r| r.count)
+        .sum()
+}
+
+// Post the assessment and the most upvoted suggestion in the last position's thread.
+async fn reveal(http: &Http, data: &RwLock<TypeMap>) -> serenity::Result<()> {
+    let posted = match settings::update(data, |settings| settings.daily_position.take()).await {
+        Some(posted) => posted,
+        None => return Ok(()),
+    };
+
+    let content = content::get(data).await;
+    let position = match content.positions.get(posted.index) {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let thread_id = ChannelId(posted.thread_id);
+    let messages = thread_id.messages(http, |r| r.limit(100)).await?;
+    let best = messages
+        .iter()
+        .filter(|m| !m.author.bot && !m.content.is_empty())
+        .max_by_key(|m| (upvotes(m), std::cmp::Reverse(m.id)));
+
+    let assessment = position
+        .assessment
+        .clone()
+        .unwrap_or_else(|| "No assessment for this one, it's up to you!".to_string());
+    let suggestion = match best {
+        Some(best) => format!(
+            "**{}** with {} {}:\n{}",
+            best.author.name,
+            upvotes(best),
+            UPVOTE,
+            best.content
+        ),
+        None => "Nobody made a suggestion this time.".to_string(),
+    };
+
+    thread_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Position of the day: the verdict");
+                e.color(EMBED_SIDE_COLOR);
+                e.field("Assessment", assessment, false);
+                e.field("Most upvoted suggestion", suggestion, false);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
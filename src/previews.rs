@@ -0,0 +1,290 @@
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+use shakmaty::Position;
+
+use crate::{
+    chesscom,
+    diagram::{self, DiagramOptions},
+    fen, lichess,
+    modules::{on_off, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    settings, web, EMBED_SIDE_COLOR,
+};
+
+// Don't flood the channel when someone pastes a whole list of games.
+const MAX_PREVIEWS: usize = 3;
+
+#[group]
+#[commands(linkpreviews)]
+struct Previews;
+
+pub struct PreviewsModule;
+
+const PREVIEW_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Game link previews",
+    command: "linkpreviews",
+    value: |guild| on_off(guild.link_previews),
+}];
+
+#[async_trait]
+impl BotModule for PreviewsModule {
+    fn name(&self) -> &'static str {
+        "previews"
+    }
+
+    fn description(&self) -> &'static str {
+        "Previews of Lichess and chess.com game links."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PREVIEWS_GROUP)
+    }
+
+    async fn normal_message(&self, ctx: &Context, msg: &Message) {
+        preview_links(ctx, msg).await;
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        PREVIEW_SETTINGS
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum GameLink {
+    Lichess(String),
+    // Kind of game ("live" or "daily") and its id.
+    ChessCom(String, String),
+}
+
+// Game links in a message, each game only once.
+fn find_links(text: &str) -> Vec<GameLink> {
+    let mut links = Vec::new();
+
+    for word in text.split_whitespace() {
+        let url = word.trim_matches(|c| c == '<' || c == '>' || c == '(' || c == ')');
+        if !url.starts_with("http") {
+            continue;
+        }
+
+        let link = if let Some(id) = lichess::game_id_from_url(url) {
+            GameLink::Lichess(id)
+        } else if let Some((kind, id)) = chesscom::game_from_url(url) {
+            GameLink::ChessCom(kind, id)
+        } else {
+            continue;
+        };
+
+        if !links.contains(&link) {
+            links.push(link);
+        }
+        if links.len() == MAX_PREVIEWS {
+            break;
+        }
+    }
+
+    links
+}
+
+// Reply with a preview of each Lichess or chess.com game linked in a message, in guilds that
+// turned it on.
+pub async fn preview_links(ctx: &Context, msg: &Message) {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    if !settings::guild(&ctx.data, guild_id).await.link_previews {
+        return;
+    }
+
+    let links = find_links(&msg.content);
+    if links.is_empty() {
+        return;
+    }
+
+    let client = web::client(&ctx.data).await;
+    for link in links {
+        let result = match link {
+            GameLink::Lichess(id) => match lichess::export_game(&client, &id).await {
+                Ok(game) => send_lichess_preview(ctx, msg, &game).await,
+                Err(why) => {
+                    println!("Could not fetch Lichess game {}: {:?}", id, why);
+                    continue;
+                }
+            },
+            GameLink::ChessCom(kind, id) => match chesscom::fetch_game(&client, &kind, &id).await {
+                Ok(game) => send_chesscom_preview(ctx, msg, &game).await,
+                Err(why) => {
+                    println!("Could not fetch chess.com game {}: {:?}", id, why);
+                    continue;
+                }
+            },
+        };
+
+        if let Err(why) = result {
+            println!("Error sending game preview: {:?}", why);
+        }
+    }
+}
+
+// A diagram of the position a game ended in, if it can be drawn as an image.
+async fn final_position(ctx: &Context, msg: &Message, fen: Option<&str>) -> Option<Vec<u8>> {
+    let pos = fen::parse_position(fen?)?;
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        pos.board(),
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await
+}
+
+async fn send_lichess_preview(
+    ctx: &Context,
+    msg: &Message,
+    game: &lichess::Game,
+) -> serenity::Result<()> {
+    let title = format!(
+        "{} vs {}",
+        game.players.white.display_name(),
+        game.players.black.display_name()
+    );
+    let opening = game
+        .opening
+        .as_ref()
+        .map(|o| format!("{} {}", o.eco, o.name))
+        .unwrap_or_else(|| "Unknown".to_string());
+    let kind = format!(
+        "{} {}",
+        if game.rated { "Rated" } else { "Casual" },
+        game.speed
+    );
+    let moves = game.moves.split_whitespace().count().div_ceil(2);
+    let accuracy = |player: &lichess::Player| {
+        player
+            .analysis
+            .as_ref()
+            .and_then(|analysis| analysis.accuracy)
+    };
+    let accuracy = match (accuracy(&game.players.white), accuracy(&game.players.black)) {
+        (Some(white), Some(black)) => Some(format!("White {}% · Black {}%", white, black)),
+        _ => None,
+    };
+    let image = final_position(ctx, msg, game.last_fen.as_deref()).await;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.url(game.url());
+                e.color(EMBED_SIDE_COLOR);
+                e.field("Result", game.result(), true);
+                e.field("Game", kind, true);
+                e.field("Moves", moves, true);
+                e.field("Opening", opening, false);
+                if let Some(accuracy) = accuracy {
+                    e.field("Accuracy", accuracy, false);
+                }
+                if image.is_some() {
+                    e.image(diagram::IMAGE_URL);
+                } else {
+                    e.thumbnail(game.thumbnail_url());
+                }
+                e.footer(|f| {
+                    f.text("lichess.org");
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn send_chesscom_preview(
+    ctx: &Context,
+    msg: &Message,
+    game: &chesscom::Game,
+) -> serenity::Result<()> {
+    let title = format!("{} vs {}", game.player("White"), game.player("Black"));
+    let opening = game.opening().unwrap_or_else(|| "Unknown".to_string());
+    let event = game.header("Event").unwrap_or("chess.com game").to_string();
+    let image = final_position(ctx, msg, game.fen.as_deref()).await;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(title);
+                e.url(&game.url);
+                e.color(EMBED_SIDE_COLOR);
+                e.field("Result", game.result(), true);
+                e.field("Game", event, true);
+                if let Some(time_control) = game.header("TimeControl") {
+                    e.field("Time control", time_control, true);
+                }
+                e.field("Opening", opening, false);
+                if image.is_some() {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| {
+                    f.text("chess.com");
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Turn previews for Lichess and chess.com game links on or off.")]
+#[usage("<on|off>")]
+async fn linkpreviews(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let enabled = match args.current().and_then(settings::parse_toggle) {
+        Some(enabled) => enabled,
+        None => {
+            msg.reply(&ctx.http, "Use `.linkpreviews on` or `.linkpreviews off`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().link_previews = enabled;
+    })
+    .await;
+
+    let reply = if enabled {
+        "I'll preview Lichess and chess.com games linked in this server."
+    } else {
+        "I'll stop previewing game links in this server."
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
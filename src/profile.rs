@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+        user::User,
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    card::{self, Card},
+    diagram,
+    game::{self, Game, GameResult},
+    meetup,
+    modules::BotModule,
+    puzzle::{self, PuzzleStats},
+    rating::Rating,
+    settings, EMBED_SIDE_COLOR,
+};
+
+const WEEK_SECS: i64 = 7 * 24 * 3600;
+
+// Badges for rated games played here, the highest one earned.
+const GAME_BADGES: [u32; 3] = [100, 50, 10];
+// Solved puzzles and daily puzzle streaks that earn a badge.
+const PUZZLE_BADGE: usize = 100;
+const STREAK_BADGE: u32 = 7;
+
+// Layout of `.profile card`, in pixels.
+const CARD_WIDTH: f32 = 640.0;
+const CARD_HEIGHT: f32 = 320.0;
+
+// `.openingstats` shows this many openings, as rows of a bar chart this many pixels wide.
+const OPENINGS_SHOWN: usize = 8;
+const CHART_WIDTH: f32 = 720.0;
+const CHART_TOP: f32 = 96.0;
+const CHART_ROW: f32 = 36.0;
+
+#[group]
+#[commands(profile, openingstats)]
+struct Profiles;
+
+pub struct ProfilesModule;
+
+impl BotModule for ProfilesModule {
+    fn name(&self) -> &'static str {
+        "profiles"
+    }
+
+    fn description(&self) -> &'static str {
+        "Everything about a member at a glance, see `.profile`, and the openings played here, see `.openingstats`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PROFILES_GROUP)
+    }
+}
+
+// What `.profile` shows about someone in a server.
+struct Profile {
+    rating: Option<Rating>,
+    // Rating points won or lost over the last week.
+    week_change: i32,
+    puzzles: Option<PuzzleStats>,
+    streak: u32,
+    // Finished games here, rated or not.
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    // The opening played most, and in how many games.
+    favorite: Option<(String, u32)>,
+    lichess: Option<String>,
+    chesscom: Option<String>,
+    badges: Vec<String>,
+}
+
+impl Profile {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    fn rating_text(&self) -> String {
+        match &self.rating {
+            Some(rating) if self.week_change != 0 => {
+                format!("{} ({:+} this week)", rating.rating, self.week_change)
+            }
+            Some(rating) => rating.rating.to_string(),
+            None => "not rated yet".to_string(),
+        }
+    }
+
+    fn puzzle_text(&self) -> String {
+        match &self.puzzles {
+            Some(stats) if !stats.attempts.is_empty() => {
+                let mut text = format!("{}, {} solved", stats.rating, stats.solved());
+                if self.streak > 0 {
+                    text.push_str(&format!(", {} day streak", self.streak));
+                }
+                text
+            }
+            _ => "none tried yet".to_string(),
+        }
+    }
+
+    fn record_text(&self) -> String {
+        match self.games() {
+            0 => "none finished yet".to_string(),
+            games => format!("{}: +{} ={} -{}", games, self.wins, self.draws, self.losses),
+        }
+    }
+}
+
+// Variations count towards their opening, so "Sicilian Defense: Najdorf" is a Sicilian.
+fn family(opening: &str) -> &str {
+    opening.split(':').next().unwrap_or(opening)
+}
+
+// How an opening went in the games `.openingstats` looks at.
+#[derive(Debug, PartialEq)]
+struct OpeningLine {
+    name: String,
+    games: u32,
+    // Half points, for the player asked about or else for white.
+    halves: u32,
+    // Moves by both sides, over all the games.
+    plies: usize,
+}
+
+impl OpeningLine {
+    fn score(&self) -> u32 {
+        self.halves * 50 / self.games.max(1)
+    }
+
+    // The average game length in moves, "1. e4 e5" being one.
+    fn length(&self) -> u32 {
+        (self.plies as f32 / self.games.max(1) as f32 / 2.0).round() as u32
+    }
+}
+
+// The openings of finished games, most played first. With `player`, only their games, scored
+// from their side. Also all the games together, as a line of their own.
+fn opening_stats(games: &[&Game], player: Option<u64>) -> (Vec<OpeningLine>, OpeningLine) {
+    let mut lines: Vec<OpeningLine> = Vec::new();
+    let mut total = OpeningLine {
+        name: "All games".to_string(),
+        games: 0,
+        halves: 0,
+        plies: 0,
+    };
+    for game in games {
+        let color = match player {
+            Some(player) => match game.color_of(player) {
+                Some(color) => color,
+                None => continue,
+            },
+            None => shakmaty::Color::White,
+        };
+        let halves = match game.result {
+            Some(GameResult::Draw) => 1,
+            Some(GameResult::WhiteWon) if color.is_white() => 2,
+            Some(GameResult::BlackWon) if color.is_black() => 2,
+            Some(GameResult::WhiteWon | GameResult::BlackWon) => 0,
+            None | Some(GameResult::Aborted) => continue,
+        };
+        total.games += 1;
+        total.halves += halves;
+        total.plies += game.moves.len();
+
+        let name = match game.opening() {
+            Some((_, opening)) => family(&opening.name),
+            None => continue,
+        };
+        let line = match lines.iter().position(|line| line.name == name) {
+            Some(index) => &mut lines[index],
+            None => {
+                lines.push(OpeningLine {
+                    name: name.to_string(),
+                    games: 0,
+                    halves: 0,
+                    plies: 0,
+                });
+                lines.last_mut().expect("just pushed")
+            }
+        };
+        line.games += 1;
+        line.halves += halves;
+        line.plies += game.moves.len();
+    }
+
+    lines.sort_by(|a, b| b.games.cmp(&a.games).then(a.name.cmp(&b.name)));
+    lines.truncate(OPENINGS_SHOWN);
+    (lines, total)
+}
+
+// The openings as a bar chart of how often each was played, with its score and game length.
+fn openings_chart(
+    title: &str,
+    whose: &str,
+    lines: &[OpeningLine],
+    total: &OpeningLine,
+) -> Option<Vec<u8>> {
+    let height = CHART_TOP + lines.len() as f32 * CHART_ROW + 24.0;
+    let mut card = Card::new(CHART_WIDTH as u32, height as u32);
+    card.rect(0.0, 0.0, 8.0, height, 0.0, card::ACCENT);
+
+    card.text(
+        32.0,
+        24.0,
+        3.0,
+        card::TEXT,
+        &card::fit(title, 3.0, CHART_WIDTH - 56.0),
+    );
+    let summary = format!(
+        "{} games, {}% for {}, {} moves on average",
+        total.games,
+        total.score(),
+        whose,
+        total.length()
+    );
+    card.text(
+        32.0,
+        56.0,
+        2.0,
+        card::MUTED,
+        &card::fit(&summary, 2.0, CHART_WIDTH - 56.0),
+    );
+
+    let most = lines.iter().map(|line| line.games).max().unwrap_or(1) as f32;
+    for (i, line) in lines.iter().enumerate() {
+        let y = CHART_TOP + i as f32 * CHART_ROW;
+        card.text(
+            32.0,
+            y + 7.0,
+            2.0,
+            card::TEXT,
+            &card::fit(&line.name, 2.0, 232.0),
+        );
+        let width = (line.games as f32 / most * 240.0).max(4.0);
+        card.rect(272.0, y, width, 26.0, 4.0, card::ACCENT);
+        card.text(
+            280.0 + width,
+            y + 7.0,
+            2.0,
+            card::MUTED,
+            &line.games.to_string(),
+        );
+        let color = match line.score() {
+            score if score > 50 => card::GREEN,
+            score if score < 50 => card::RED,
+            _ => card::TEXT,
+        };
+        card.text_right(616.0, y + 7.0, 2.0, color, &format!("{}%", line.score()));
+        card.text_right(
+            CHART_WIDTH - 24.0,
+            y + 7.0,
+            2.0,
+            card::MUTED,
+            &format!("{} mv", line.length()),
+        );
+    }
+
+    card.png()
+}
+
+async fn load_profile(data: &RwLock<TypeMap>, guild_id: GuildId, user_id: UserId) -> Profile {
+    let since = Utc::now().timestamp() - WEEK_SECS;
+    let (rating, top_rated, wins, draws, losses, favorite) = game::read_games(data, |store| {
+        let ratings = store.ratings.get(&guild_id.0);
+        let rating = ratings.and_then(|ratings| ratings.get(&user_id.0)).cloned();
+        let top_rated = ratings
+            .and_then(|ratings| {
+                ratings
+                    .iter()
+                    .filter(|(_, rating)| rating.games() > 0)
+                    .max_by_key(|(&id, rating)| (rating.rating, std::cmp::Reverse(id)))
+            })
+            .is_some_and(|(&id, _)| id == user_id.0);
+
+        let (mut wins, mut draws, mut losses) = (0, 0, 0);
+        let mut openings: HashMap<String, u32> = HashMap::new();
+        for game in store
+            .games
+            .iter()
+            .filter(|game| game.guild_id == Some(guild_id.0))
+        {
+            let color = match game.color_of(user_id.0) {
+                Some(color) => color,
+                None => continue,
+            };
+            match game.result {
+                Some(GameResult::Draw) => draws += 1,
+                Some(GameResult::WhiteWon) if color.is_white() => wins += 1,
+                Some(GameResult::BlackWon) if color.is_black() => wins += 1,
+                Some(GameResult::WhiteWon | GameResult::BlackWon) => losses += 1,
+                None | Some(GameResult::Aborted) => continue,
+            }
+            if let Some((_, opening)) = game.opening() {
+                *openings
+                    .entry(family(&opening.name).to_string())
+                    .or_default() += 1;
+            }
+        }
+        let favorite = openings
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+        (rating, top_rated, wins, draws, losses, favorite)
+    })
+    .await;
+    let week_change = rating.as_ref().map_or(0, |rating| {
+        rating
+            .history
+            .iter()
+            .filter(|change| change.at >= since)
+            .map(|change| change.change)
+            .sum()
+    });
+
+    let (puzzles, latest) = (
+        puzzle::read_puzzles(data, |store| store.players.get(&user_id.0).cloned()).await,
+        settings::read(data, |settings| settings.daily_puzzles_posted).await,
+    );
+    let streak = puzzles
+        .as_ref()
+        .map_or(0, |stats| stats.current_streak(latest));
+
+    let user = settings::user(data, user_id).await;
+    let (meetups, regular_after) = settings::read(data, |settings| {
+        let now = Utc::now().timestamp();
+        let (_, total) = meetup::attendance(&settings.meetups, guild_id, user_id, now);
+        (total, settings.guild(guild_id).regular_after)
+    })
+    .await;
+
+    let mut badges = Vec::new();
+    if top_rated {
+        badges.push("Top rated".to_string());
+    }
+    let rated = rating.as_ref().map_or(0, Rating::games);
+    if let Some(games) = GAME_BADGES.iter().find(|&&games| rated >= games) {
+        badges.push(format!("{} games", games));
+    }
+    if let Some(stats) = &puzzles {
+        if stats.solved() >= PUZZLE_BADGE {
+            badges.push(format!("{} puzzles", PUZZLE_BADGE));
+        }
+        if stats.best_streak >= STREAK_BADGE {
+            badges.push(format!("{} day streak", stats.best_streak));
+        }
+    }
+    if regular_after > 0 && meetups >= regular_after {
+        badges.push("Regular".to_string());
+    }
+
+    Profile {
+        rating,
+        week_change,
+        puzzles,
+        streak,
+        wins,
+        draws,
+        losses,
+        favorite,
+        lichess: user.lichess,
+        chesscom: user.chesscom,
+        badges,
+    }
+}
+
+// The profile as a picture to share: avatar and name, badges, the numbers and a ring of the
+// games won, drawn and lost.
+fn profile_card(avatar: Option<Vec<u8>>, name: &str, profile: &Profile) -> Option<Vec<u8>> {
+    let mut card = Card::new(CARD_WIDTH as u32, CARD_HEIGHT as u32);
+    card.rect(0.0, 0.0, 8.0, CARD_HEIGHT, 0.0, card::ACCENT);
+
+    card.avatar(avatar, 84.0, 80.0, 48.0);
+    card.text(
+        152.0,
+        40.0,
+        4.0,
+        card::TEXT,
+        &card::fit(name, 4.0, CARD_WIDTH - 176.0),
+    );
+    let accounts = [
+        profile
+            .lichess
+            .as_ref()
+            .map(|name| format!("lichess {}", name)),
+        profile
+            .chesscom
+            .as_ref()
+            .map(|name| format!("chess.com {}", name)),
+    ]
+    .iter()
+    .flatten()
+    .cloned()
+    .collect::<Vec<_>>()
+    .join("   ");
+    card.text(
+        152.0,
+        80.0,
+        2.0,
+        card::MUTED,
+        &card::fit(&accounts, 2.0, CARD_WIDTH - 176.0),
+    );
+
+    // Badges as pills under the name, as many as fit.
+    let mut x = 152.0;
+    for badge in &profile.badges {
+        let width = card::text_width(badge, 2.0) + 20.0;
+        if x + width > CARD_WIDTH - 24.0 {
+            break;
+        }
+        card.rect(x, 102.0, width, 26.0, 13.0, card::PANEL);
+        card.text(x + 10.0, 108.0, 2.0, card::ACCENT, badge);
+        x += width + 8.0;
+    }
+
+    let favorite = match &profile.favorite {
+        Some((opening, games)) => format!("{} ({})", opening, games),
+        None => "-".to_string(),
+    };
+    let lines = [
+        ("Rating", profile.rating_text()),
+        ("Puzzles", profile.puzzle_text()),
+        ("Games", profile.record_text()),
+        ("Favorite", favorite),
+    ];
+    card.rect(24.0, 152.0, 408.0, 144.0, 10.0, card::PANEL);
+    for (i, (label, value)) in lines.iter().enumerate() {
+        let y = 168.0 + i as f32 * 32.0;
+        card.text(40.0, y, 2.0, card::MUTED, label);
+        card.text(152.0, y, 2.0, card::TEXT, &card::fit(value, 2.0, 268.0));
+    }
+
+    let (cx, cy) = (536.0, 214.0);
+    card.donut(
+        cx,
+        cy,
+        56.0,
+        20.0,
+        &[
+            (profile.wins as f32, card::GREEN),
+            (profile.draws as f32, card::MUTED),
+            (profile.losses as f32, card::RED),
+        ],
+    );
+    let won = match profile.games() {
+        0 => "-".to_string(),
+        games => format!("{}%", profile.wins * 100 / games),
+    };
+    card.text(
+        cx - card::text_width(&won, 3.0) / 2.0,
+        cy - 18.0,
+        3.0,
+        card::TEXT,
+        &won,
+    );
+    card.text(
+        cx - card::text_width("won", 2.0) / 2.0,
+        cy + 8.0,
+        2.0,
+        card::MUTED,
+        "won",
+    );
+
+    card.png()
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("me", "whois")]
+#[description(
+    "Show someone's ratings, record, favorite opening and badges in this server. `card` makes it a picture to share."
+)]
+#[usage("[@user] [card]")]
+#[example("@magnus card")]
+async fn profile(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let mut user_id = msg.author.id;
+    let mut as_card = false;
+    for arg in args.iter::<String>().flatten() {
+        if matches!(arg.to_lowercase().as_str(), "card" | "image" | "fancy") {
+            as_card = true;
+        } else if let Some(id) = utils::parse_username(&arg) {
+            user_id = UserId(id);
+        } else {
+            msg.reply(&ctx.http, "Use `.profile [@user] [card]`")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let (name, user) = match guild_id.member(&ctx.http, user_id).await {
+        Ok(member) => (member.display_name().into_owned(), member.user),
+        Err(_) => match user_id.to_user(&ctx.http).await {
+            Ok(user) => (user.name.clone(), user),
+            Err(_) => {
+                msg.reply(&ctx.http, "I don't know who that is.").await?;
+                return Ok(());
+            }
+        },
+    };
+    let profile = load_profile(&ctx.data, guild_id, user_id).await;
+
+    // Where pictures can't be posted, the embed will do.
+    if as_card && diagram::images_allowed(&ctx.http, &ctx.data, msg.channel_id).await {
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let avatar = card::avatar(&ctx.data, &user).await;
+        let png = profile_card(avatar, &name, &profile);
+        if let Ok(typing) = typing {
+            typing.stop();
+        }
+        if let Some(png) = png {
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.add_file(card::attachment(png, "profile.png"));
+                    m
+                })
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let mut accounts = Vec::new();
+    if let Some(name) = &profile.lichess {
+        accounts.push(format!(
+            "[lichess {}](https://lichess.org/@/{})",
+            name, name
+        ));
+    }
+    if let Some(name) = &profile.chesscom {
+        accounts.push(format!(
+            "[chess.com {}](https://www.chess.com/member/{})",
+            name, name
+        ));
+    }
+    let favorite = match &profile.favorite {
+        Some((opening, 1)) => format!("{} (1 game)", opening),
+        Some((opening, games)) => format!("{} ({} games)", opening, games),
+        None => "None yet".to_string(),
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(&name);
+                e.color(EMBED_SIDE_COLOR);
+                e.thumbnail(user.face());
+                e.field("Server rating", profile.rating_text(), true);
+                e.field("Puzzles", profile.puzzle_text(), true);
+                e.field("Games here", profile.record_text(), true);
+                e.field("Favorite opening", favorite, true);
+                if !accounts.is_empty() {
+                    e.field("Accounts", accounts.join("\n"), true);
+                }
+                if !profile.badges.is_empty() {
+                    e.field("Badges", profile.badges.join(", "), false);
+                }
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("openings")]
+#[description(
+    "The openings played most in this server's games, or someone's: how often, how they scored and how long the games went."
+)]
+#[usage("[@user]")]
+#[example("@magnus")]
+async fn openingstats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let player = match args.rest().trim() {
+        "" => None,
+        arg => match utils::parse_username(arg) {
+            Some(id) => Some(id),
+            None => {
+                msg.reply(&ctx.http, "Use `.openingstats [@user]`").await?;
+                return Ok(());
+            }
+        },
+    };
+    let (title, whose) = match player {
+        Some(id) => {
+            let name = match guild_id.member(&ctx.http, id).await {
+                Ok(member) => member.display_name().into_owned(),
+                Err(_) => match UserId(id).to_user(&ctx.http).await {
+                    Ok(user) => user.name,
+                    Err(_) => {
+                        msg.reply(&ctx.http, "I don't know who that is.").await?;
+                        return Ok(());
+                    }
+                },
+            };
+            (format!("{}'s openings", name), name)
+        }
+        None => ("This server's openings".to_string(), "white".to_string()),
+    };
+
+    let (lines, total) = game::read_games(&ctx.data, |store| {
+        let games: Vec<&Game> = store
+            .games
+            .iter()
+            .filter(|game| game.guild_id == Some(guild_id.0))
+            .collect();
+        opening_stats(&games, player)
+    })
+    .await;
+    if lines.is_empty() {
+        msg.reply(&ctx.http, "No finished games with a known opening yet.")
+            .await?;
+        return Ok(());
+    }
+
+    // Where pictures can't be posted, the embed will do.
+    if diagram::images_allowed(&ctx.http, &ctx.data, msg.channel_id).await {
+        if let Some(png) = openings_chart(&title, &whose, &lines, &total) {
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.add_file(card::attachment(png, "openings.png"));
+                    m
+                })
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let desc = lines
+        .iter()
+        .map(|line| {
+            format!(
+                "**{}** · {} games · {}% · {} moves",
+                line.name,
+                line.games,
+                line.score(),
+                line.length()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(&title);
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| {
+                    f.text(format!(
+                        "{} games · {}% for {} · {} moves on average",
+                        total.games,
+                        total.score(),
+                        whose,
+                        total.length()
+                    ));
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameOptions;
+
+    fn game(moves: &[&str], result: GameResult) -> Game {
+        Game {
+            white: 1,
+            black: 2,
+            moves: moves.iter().map(|m| m.to_string()).collect(),
+            result: Some(result),
+            ..GameOptions::default().new_game(Some(10), 20, (1, 2))
+        }
+    }
+
+    #[test]
+    fn openings_played() {
+        let sicilian = ["e2e4", "c7c5", "g1f3", "d7d6"];
+        let games = [
+            game(&sicilian, GameResult::WhiteWon),
+            game(&sicilian[..2], GameResult::Draw),
+            game(&["d2d4", "d7d5"], GameResult::BlackWon),
+            game(&sicilian, GameResult::Aborted),
+        ];
+        let games: Vec<&Game> = games.iter().collect();
+
+        let (lines, total) = opening_stats(&games, None);
+        assert_eq!((total.games, total.score(), total.length()), (3, 50, 1));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].name, "Sicilian Defense");
+        assert_eq!(
+            (lines[0].games, lines[0].score(), lines[0].length()),
+            (2, 75, 2)
+        );
+
+        // Scored from black's side for player 2.
+        let (lines, _) = opening_stats(&games, Some(2));
+        assert_eq!(lines[0].score(), 25);
+        assert_eq!(lines[1].score(), 100);
+    }
+}
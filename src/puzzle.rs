@@ -0,0 +1,1903 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{TimeZone, Timelike, Utc};
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, MessageId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+    utils,
+};
+use shakmaty::{
+    fen::Fen, san::SanPlus, uci::UciMove, variant::VariantPosition, CastlingMode, Chess, Color,
+    EnPassantMode, Move, Position, Role, Square,
+};
+
+use crate::{
+    chesscom,
+    config::{self, Config, ConfigContainer},
+    diagram::{self, DiagramOptions},
+    fen,
+    game::{self, MoveError},
+    lichess,
+    modules::{self, BotModule, ModuleSetting},
+    notation::Notation,
+    permissions::ADMIN_CHECK,
+    pgn,
+    puzzledb::{self, PuzzleDb, PuzzleDbContainer},
+    puzzlerace::{self, Race},
+    rating::{self, START_RATING},
+    render,
+    scheduler::JobKind,
+    settings, timezone, web, EMBED_SIDE_COLOR,
+};
+
+const PUZZLES_FILE: &str = "puzzles.json";
+
+const SOLUTION_PREFIX: &str = "puzzle:solution:";
+
+// Puzzles asked for before settling on the closest to a rating range.
+const FETCH_TRIES: usize = 5;
+// Anonymous solvers get puzzles around this rating from Lichess.
+const BASE_RATING: i64 = 1500;
+
+// Puzzle ratings move faster for the first puzzles, like game ratings.
+const NEW_SOLVER_PUZZLES: usize = 30;
+const NEW_SOLVER_K: f64 = 40.0;
+const K: f64 = 20.0;
+
+// The piece, then its square, then the move.
+const MAX_HINTS: u8 = 3;
+
+// Puzzles kept by `.importpuzzles` unless told otherwise.
+const IMPORT_MAX: usize = 200_000;
+
+// Attempts listed by `.puzzlestats`.
+const ATTEMPTS_SHOWN: usize = 10;
+
+// Hour of the evening, in their own timezone, that `.puzzlereminder` DMs people at.
+const REMINDER_HOUR: u32 = 19;
+
+// The themes Lichess tags puzzles with.
+pub const THEMES: &[&str] = &[
+    "advancedPawn",
+    "advantage",
+    "anastasiaMate",
+    "arabianMate",
+    "attackingF2F7",
+    "attraction",
+    "backRankMate",
+    "bishopEndgame",
+    "bodenMate",
+    "capturingDefender",
+    "castling",
+    "clearance",
+    "crushing",
+    "defensiveMove",
+    "deflection",
+    "discoveredAttack",
+    "doubleBishopMate",
+    "doubleCheck",
+    "dovetailMate",
+    "enPassant",
+    "endgame",
+    "equality",
+    "exposedKing",
+    "fork",
+    "hangingPiece",
+    "hookMate",
+    "interference",
+    "intermezzo",
+    "kingsideAttack",
+    "knightEndgame",
+    "long",
+    "master",
+    "masterVsMaster",
+    "mate",
+    "mateIn1",
+    "mateIn2",
+    "mateIn3",
+    "mateIn4",
+    "mateIn5",
+    "middlegame",
+    "oneMove",
+    "opening",
+    "pawnEndgame",
+    "pin",
+    "promotion",
+    "queenEndgame",
+    "queenRookEndgame",
+    "queensideAttack",
+    "quietMove",
+    "rookEndgame",
+    "sacrifice",
+    "short",
+    "skewer",
+    "smotheredMate",
+    "superGM",
+    "trappedPiece",
+    "underPromotion",
+    "veryLong",
+    "xRayAttack",
+    "zugzwang",
+];
+
+#[group]
+#[commands(
+    puzzle,
+    solve,
+    hint,
+    solution,
+    puzzlestats,
+    puzzlereminder,
+    importpuzzles,
+    puzzlesource
+)]
+struct Puzzles;
+
+pub struct PuzzlesModule;
+
+const PUZZLE_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Puzzle source",
+    command: "puzzlesource",
+    value: |guild| guild.puzzle_source.name().to_string(),
+}];
+
+#[async_trait]
+impl BotModule for PuzzlesModule {
+    fn name(&self) -> &'static str {
+        "puzzles"
+    }
+
+    fn description(&self) -> &'static str {
+        "Tactics puzzles, see `.puzzle`, and the daily puzzle from Lichess or chess.com."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PUZZLES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        let data_dir = data
+            .get::<ConfigContainer>()
+            .expect("Expected config in typemap.")
+            .data_dir
+            .clone();
+        data.insert::<PuzzleContainer>(Mutex::new(PuzzleStore::load(&data_dir)));
+        data.insert::<PuzzleDbContainer>(Arc::new(PuzzleDb::load(&data_dir)));
+    }
+
+    async fn normal_message(&self, ctx: &Context, msg: &Message) {
+        chat_move(ctx, msg).await;
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+
+    fn jobs(&self, config: &Config) -> Vec<(JobKind, String)> {
+        match config.daily_puzzle.channel {
+            // Reminders go out hourly, to whoever's evening it is.
+            Some(_) => vec![
+                (
+                    JobKind::DailyPuzzle,
+                    format!("0 {} * * *", config.daily_puzzle.hour % 24),
+                ),
+                (JobKind::PuzzleReminder, "0 * * * *".to_string()),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        match kind {
+            JobKind::DailyPuzzle => post_daily(http, data).await,
+            JobKind::PuzzleReminder => {
+                reveal_due(http, data).await;
+                remind(http, data).await;
+            }
+            _ => {}
+        }
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        PUZZLE_SETTINGS
+    }
+}
+
+// A tactics puzzle: a position and the only line that wins in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub id: String,
+    // The position the solver moves in.
+    pub fen: String,
+    // The opponent's move that led to it in UCI, highlighted on the board.
+    pub last_move: Option<String>,
+    // UCI moves, the solver's first, then the opponent's replies in between.
+    pub solution: Vec<String>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+    // Who made it with `.setup puzzle`. Those aren't from Lichess and have no rating.
+    #[serde(default)]
+    pub set_by: Option<u64>,
+    // The day it was chess.com's daily puzzle, like "2024-05-21", for puzzles from there. Those
+    // have no rating either.
+    #[serde(default)]
+    pub chesscom_day: Option<String>,
+}
+
+// Where a server's puzzles come from, see `.puzzlesource`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PuzzleSource {
+    #[default]
+    Lichess,
+    ChessCom,
+}
+
+impl PuzzleSource {
+    fn from_name(name: &str) -> Option<PuzzleSource> {
+        match name.to_lowercase().as_str() {
+            "lichess" | "lichess.org" => Some(PuzzleSource::Lichess),
+            "chesscom" | "chess.com" => Some(PuzzleSource::ChessCom),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PuzzleSource::Lichess => "Lichess",
+            PuzzleSource::ChessCom => "chess.com",
+        }
+    }
+}
+
+// The source picked for a server, or Lichess in DMs.
+pub async fn puzzle_source(data: &RwLock<TypeMap>, guild_id: Option<GuildId>) -> PuzzleSource {
+    match guild_id {
+        Some(guild_id) => settings::guild(data, guild_id).await.puzzle_source,
+        None => PuzzleSource::default(),
+    }
+}
+
+impl Puzzle {
+    // Lichess sends the game up to the puzzle instead of its position.
+    pub fn from_lichess(response: lichess::PuzzleAndGame) -> Option<Puzzle> {
+        let game = pgn::parse(&response.game.pgn).ok()?;
+        let mut pos = game.start;
+        for m in &game.moves {
+            pos.play_unchecked(*m);
+        }
+
+        Some(Puzzle {
+            id: response.puzzle.id,
+            fen: Fen::from_position(&pos, EnPassantMode::Legal).to_string(),
+            last_move: game
+                .moves
+                .last()
+                .map(|m| m.to_uci(CastlingMode::Standard).to_string()),
+            solution: response.puzzle.solution,
+            rating: response.puzzle.rating,
+            themes: response.puzzle.themes,
+            set_by: None,
+            chesscom_day: None,
+        })
+    }
+
+    // chess.com gives the solution as a PGN from the puzzle's position.
+    pub fn from_chesscom(response: chesscom::DailyPuzzle) -> Option<Puzzle> {
+        let game = pgn::parse(&response.pgn).ok()?;
+        let day = Utc
+            .timestamp_opt(response.publish_time, 0)
+            .single()?
+            .format("%Y-%m-%d")
+            .to_string();
+
+        Some(Puzzle {
+            id: format!("chesscom-{}", day),
+            fen: Fen::from_position(&game.start, EnPassantMode::Legal).to_string(),
+            last_move: None,
+            solution: game
+                .moves
+                .iter()
+                .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                .collect(),
+            rating: 0,
+            themes: Vec::new(),
+            set_by: None,
+            chesscom_day: Some(day),
+        })
+    }
+
+    pub fn url(&self) -> Option<String> {
+        match (&self.set_by, &self.chesscom_day) {
+            (Some(_), _) => None,
+            (None, Some(day)) => Some(format!("https://www.chess.com/daily-chess-puzzle/{}", day)),
+            (None, None) => Some(format!("https://lichess.org/training/{}", self.id)),
+        }
+    }
+
+    // "Rated 1500", or where it came from if it has no rating.
+    fn rating_text(&self) -> String {
+        match (&self.set_by, &self.chesscom_day) {
+            (Some(_), _) => "Set up with .setup".to_string(),
+            (None, Some(day)) => format!("chess.com daily puzzle of {}", day),
+            (None, None) => format!("Rated {}", self.rating),
+        }
+    }
+
+    pub fn position(&self) -> Option<Chess> {
+        fen::parse_position(&self.fen)
+    }
+
+    fn highlight(&self) -> Option<(Square, Square)> {
+        match self.last_move.as_deref()?.parse::<UciMove>().ok()? {
+            UciMove::Normal { from, to, .. } => Some((from, to)),
+            _ => None,
+        }
+    }
+
+    // The position after the first `plies` moves of the solution, and the squares of the last
+    // move to get there.
+    fn position_after(&self, plies: usize) -> Option<(Chess, Option<(Square, Square)>)> {
+        let mut pos = self.position()?;
+        let mut highlight = self.highlight();
+        for uci in self.solution.iter().take(plies) {
+            let m = uci.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+            highlight = m.from().map(|from| (from, m.to()));
+            pos.play_unchecked(m);
+        }
+        Some((pos, highlight))
+    }
+
+    // The solution with move numbers, like "24. Qxf7+ Kxf7 25. Ng5+".
+    pub fn solution_text(&self, notation: Notation) -> String {
+        let mut pos = match self.position() {
+            Some(pos) => pos,
+            None => return String::new(),
+        };
+        let mut out = Vec::new();
+        for (i, uci) in self.solution.iter().enumerate() {
+            let m = match uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => m,
+                None => break,
+            };
+            let number = pos.fullmoves().get();
+            let san = notation.localize(&SanPlus::from_move(pos.clone(), m).to_string());
+            if pos.turn() == Color::White {
+                out.push(format!("{}. {}", number, san));
+            } else if i == 0 {
+                out.push(format!("{}... {}", number, san));
+            } else {
+                out.push(san);
+            }
+            pos.play_unchecked(m);
+        }
+        out.join(" ")
+    }
+}
+
+// How far in someone is on a puzzle from `.puzzle`.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub puzzle: Puzzle,
+    // Solution moves played so far, the solver's and the replies.
+    pub ply: usize,
+    pub mistakes: u32,
+    // Set once the try has gone into the solver's puzzle rating. Puzzles they've seen before
+    // start out counted, and so do races.
+    pub counted: bool,
+    // The race and which of its puzzles this is, see puzzlerace.rs.
+    pub race: Option<(u32, usize)>,
+    // Hints given so far, see `.hint`, and how many of them were for the move to find now.
+    pub hints: u8,
+    pub move_hints: u8,
+    // The daily puzzle's number, for sessions started with `.puzzle daily`.
+    pub daily: Option<u32>,
+}
+
+// What a move did to a puzzle.
+enum Outcome {
+    // Right, and the opponent answered with `reply`.
+    Correct { played: String, reply: String },
+    Solved { played: String },
+    Wrong,
+}
+
+impl Session {
+    pub fn new(puzzle: Puzzle, counted: bool) -> Session {
+        Session {
+            puzzle,
+            ply: 0,
+            mistakes: 0,
+            counted,
+            race: None,
+            hints: 0,
+            move_hints: 0,
+            daily: None,
+        }
+    }
+
+    // The hint for the move to find now, telling more the more hints were asked for.
+    fn hint(&self, notation: Notation) -> Option<String> {
+        let (pos, _) = self.position()?;
+        let m = self
+            .puzzle
+            .solution
+            .get(self.ply)?
+            .parse::<UciMove>()
+            .ok()?
+            .to_move(&pos)
+            .ok()?;
+        let piece = role_name(m.role());
+        Some(match self.move_hints {
+            0 | 1 => format!("Move a **{}**.", piece),
+            2 => format!("Move the {} on **{}**.", piece, m.from()?),
+            _ => format!(
+                "Play **{}**.",
+                notation.localize(&SanPlus::from_move(pos, m).to_string())
+            ),
+        })
+    }
+
+    fn position(&self) -> Option<(Chess, Option<(Square, Square)>)> {
+        self.puzzle.position_after(self.ply)
+    }
+
+    // Check a move against the solution, and play the opponent's answer if it's right. Any mate
+    // counts, puzzles can have more than one.
+    fn try_move(&mut self, text: &str, notation: Notation) -> Result<Outcome, MoveError> {
+        let (pos, _) = self.position().ok_or(MoveError::Illegal)?;
+        let m = game::parse_move(&VariantPosition::Chess(pos.clone()), text, notation)?;
+        let played = notation.localize(&SanPlus::from_move(pos.clone(), m).to_string());
+
+        let expected = self.puzzle.solution.get(self.ply).map(String::as_str);
+        let uci = m.to_uci(CastlingMode::Standard).to_string();
+        if Some(uci.as_str()) != expected && !gives_mate(&pos, m) {
+            self.mistakes += 1;
+            return Ok(Outcome::Wrong);
+        }
+        if gives_mate(&pos, m) || self.ply + 1 >= self.puzzle.solution.len() {
+            self.ply = self.puzzle.solution.len();
+            return Ok(Outcome::Solved { played });
+        }
+
+        self.ply += 1;
+        self.move_hints = 0;
+        let (pos, _) = self.position().ok_or(MoveError::Illegal)?;
+        let reply = self.puzzle.solution[self.ply]
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+            .ok_or(MoveError::Illegal)?;
+        self.ply += 1;
+        Ok(Outcome::Correct {
+            played,
+            reply: notation.localize(&SanPlus::from_move(pos, reply).to_string()),
+        })
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::King => "king",
+        Role::Queen => "queen",
+        Role::Rook => "rook",
+        Role::Bishop => "bishop",
+        Role::Knight => "knight",
+        Role::Pawn => "pawn",
+    }
+}
+
+fn gives_mate(pos: &Chess, m: Move) -> bool {
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+    after.is_checkmate()
+}
+
+// One go at a puzzle, as it counted for the solver's rating.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PuzzleAttempt {
+    pub puzzle_id: String,
+    pub puzzle_rating: u32,
+    // Where it was tried, for server leaderboards. None in DMs.
+    pub guild_id: Option<u64>,
+    // Seconds since the epoch.
+    pub at: i64,
+    pub solved: bool,
+    pub rating: i32,
+    pub change: i32,
+}
+
+// One go at a chess.com puzzle. They have no rating, so they're only counted as solved or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChessComAttempt {
+    pub puzzle_id: String,
+    pub guild_id: Option<u64>,
+    // Seconds since the epoch.
+    pub at: i64,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PuzzleStats {
+    pub rating: i32,
+    pub attempts: Vec<PuzzleAttempt>,
+    #[serde(default)]
+    pub chesscom: Vec<ChessComAttempt>,
+    // Daily puzzles solved one after the other, the longest run of them and the number of the
+    // last one solved.
+    #[serde(default)]
+    pub streak: u32,
+    #[serde(default)]
+    pub best_streak: u32,
+    #[serde(default)]
+    pub last_daily: Option<u32>,
+}
+
+impl Default for PuzzleStats {
+    fn default() -> Self {
+        PuzzleStats {
+            rating: START_RATING,
+            attempts: Vec::new(),
+            chesscom: Vec::new(),
+            streak: 0,
+            best_streak: 0,
+            last_daily: None,
+        }
+    }
+}
+
+impl PuzzleStats {
+    pub fn solved(&self) -> usize {
+        self.attempts
+            .iter()
+            .filter(|attempt| attempt.solved)
+            .count()
+    }
+
+    fn chesscom_solved(&self) -> usize {
+        self.chesscom
+            .iter()
+            .filter(|attempt| attempt.solved)
+            .count()
+    }
+
+    // Count a solved daily puzzle towards the streak, once. Returns the streak.
+    fn solved_daily(&mut self, number: u32) -> u32 {
+        if self.last_daily == Some(number) {
+            return self.streak;
+        }
+        self.streak = match self.last_daily {
+            Some(last) if last + 1 == number => self.streak + 1,
+            _ => 1,
+        };
+        self.best_streak = self.best_streak.max(self.streak);
+        self.last_daily = Some(number);
+        self.streak
+    }
+
+    // The streak as it stands with daily puzzle `latest` up. It's still going until the
+    // puzzle after the last one solved is replaced.
+    pub fn current_streak(&self, latest: u32) -> u32 {
+        match self.last_daily {
+            Some(last) if last == latest || last + 1 == latest => self.streak,
+            _ => 0,
+        }
+    }
+
+    fn k(&self) -> f64 {
+        if self.attempts.len() < NEW_SOLVER_PUZZLES {
+            NEW_SOLVER_K
+        } else {
+            K
+        }
+    }
+
+    // Rate a puzzle like a game against an opponent with the puzzle's rating. Each hint takes a
+    // third off what solving it earns, so three hints earn nothing.
+    fn record(&mut self, puzzle: &Puzzle, guild_id: Option<u64>, solved: bool, hints: u8) -> i32 {
+        let expected = rating::expected_score(self.rating, puzzle.rating as i32);
+        let change = if solved {
+            let hints = f64::from(hints.min(MAX_HINTS));
+            self.k() * (1.0 - expected) * (1.0 - hints / f64::from(MAX_HINTS))
+        } else {
+            -self.k() * expected
+        };
+        let change = change.round() as i32;
+        self.rating += change;
+        self.attempts.push(PuzzleAttempt {
+            puzzle_id: puzzle.id.clone(),
+            puzzle_rating: puzzle.rating,
+            guild_id,
+            at: Utc::now().timestamp(),
+            solved,
+            rating: self.rating,
+            change,
+        });
+        change
+    }
+}
+
+// Everyone's puzzle ratings, saved as JSON in the data dir, and the puzzles people are working
+// on, which aren't worth keeping over a restart.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PuzzleStore {
+    pub players: HashMap<u64, PuzzleStats>,
+    // Keyed by channel and solver.
+    #[serde(skip)]
+    pub sessions: HashMap<(u64, u64), Session>,
+    #[serde(skip)]
+    pub races: Vec<Race>,
+    #[serde(skip)]
+    path: PathBuf,
+    // What's in the file, so saving can skip writing it again when nothing changed.
+    #[serde(skip)]
+    saved: String,
+}
+
+impl PuzzleStore {
+    pub fn load(data_dir: &str) -> PuzzleStore {
+        let path = PathBuf::from(data_dir).join(PUZZLES_FILE);
+        let mut store: PuzzleStore = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|why| {
+                panic!("Could not parse puzzles file {}: {}", path.display(), why)
+            }),
+            Err(_) => PuzzleStore::default(),
+        };
+        store.path = path;
+        store.saved = serde_json::to_string(&store).unwrap_or_default();
+        store
+    }
+
+    // Take everything from a backup, keeping where the puzzles are saved.
+    pub fn restore(&mut self, backup: PuzzleStore) {
+        let path = std::mem::take(&mut self.path);
+        *self = backup;
+        self.path = path;
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let text = serde_json::to_string(self)?;
+        if text == self.saved {
+            return Ok(());
+        }
+        settings::write_file(&self.path, &text)?;
+        self.saved = text;
+        Ok(())
+    }
+
+    pub fn has_tried(&self, user_id: u64, puzzle_id: &str) -> bool {
+        self.players.get(&user_id).is_some_and(|stats| {
+            stats
+                .attempts
+                .iter()
+                .any(|attempt| attempt.puzzle_id == puzzle_id)
+                || stats
+                    .chesscom
+                    .iter()
+                    .any(|attempt| attempt.puzzle_id == puzzle_id)
+        })
+    }
+
+    // Count a session's puzzle for its solver, once. Returns the rating change and new rating.
+    fn count(
+        &mut self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        session: &mut Session,
+        solved: bool,
+    ) -> Option<(i32, i32)> {
+        if session.counted {
+            return None;
+        }
+        session.counted = true;
+        let stats = self.players.entry(user_id).or_default();
+        if session.puzzle.chesscom_day.is_some() {
+            stats.chesscom.push(ChessComAttempt {
+                puzzle_id: session.puzzle.id.clone(),
+                guild_id,
+                at: Utc::now().timestamp(),
+                solved,
+            });
+            return None;
+        }
+        let change = stats.record(&session.puzzle, guild_id, solved, session.hints);
+        Some((change, stats.rating))
+    }
+}
+
+pub struct PuzzleContainer;
+
+impl TypeMapKey for PuzzleContainer {
+    type Value = Mutex<PuzzleStore>;
+}
+
+pub async fn read_puzzles<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&PuzzleStore) -> T,
+{
+    let data = data.read().await;
+    let store = data
+        .get::<PuzzleContainer>()
+        .expect("Expected puzzles in typemap.")
+        .lock()
+        .await;
+    f(&store)
+}
+
+// Change the puzzles and save them if anything kept on disk changed. Sessions and races aren't
+// kept, so most solving doesn't write anything.
+pub async fn with_puzzles<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut PuzzleStore) -> T,
+{
+    let data = data.read().await;
+    let mut store = data
+        .get::<PuzzleContainer>()
+        .expect("Expected puzzles in typemap.")
+        .lock()
+        .await;
+    let result = f(&mut store);
+    if let Err(why) = store.save() {
+        println!("Could not save puzzles: {:?}", why);
+    }
+    result
+}
+
+// The daily puzzle that was posted last, until the next one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostedPuzzle {
+    pub puzzle: Puzzle,
+    pub channel_id: u64,
+    pub message_id: u64,
+    // Counting up from the first daily puzzle, see `PuzzleStats::streak`.
+    #[serde(default)]
+    pub number: u32,
+    // Seconds since the epoch.
+    #[serde(default)]
+    pub posted_at: i64,
+    // The thread under it for talking it over.
+    #[serde(default)]
+    pub thread_id: Option<u64>,
+    // Whether the solution was posted.
+    #[serde(default)]
+    pub revealed: bool,
+}
+
+// The board and whose move it is. The picture, if there is one, is attached by the caller.
+pub fn puzzle_embed<'a>(
+    e: &'a mut CreateEmbed,
+    title: &str,
+    puzzle: &Puzzle,
+    prompt: &str,
+    text_board: Option<String>,
+) -> &'a mut CreateEmbed {
+    let mut desc = match &text_board {
+        Some(board) => format!("{}\n", board),
+        None => String::new(),
+    };
+    desc.push_str(prompt);
+
+    e.title(title);
+    if let Some(url) = puzzle.url() {
+        e.url(url);
+    }
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    if text_board.is_none() {
+        e.image(diagram::IMAGE_URL);
+    }
+    e.footer(|f| f.text(puzzle.rating_text()));
+    e
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+pub fn to_move_prompt(pos: &Chess) -> String {
+    format!(
+        "**{} to move.** Find the best move!",
+        color_name(pos.turn())
+    )
+}
+
+// Show where a puzzle is at, from the solver's side.
+pub async fn send_board(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    session: &Session,
+    prompt: &str,
+) -> serenity::Result<()> {
+    let (pos, highlight) = match session.position() {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+    let style = settings::board_style(data, guild_id, Some(user_id)).await;
+    let options = DiagramOptions {
+        flipped: pos.turn() == Color::Black,
+        highlight,
+    };
+    let image = diagram::board_image(http, data, channel_id, pos.board(), &style, &options).await;
+    let text_board = match image {
+        Some(_) => None,
+        None => Some(render::board_for(pos.board(), &style)),
+    };
+
+    channel_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                puzzle_embed(e, "Puzzle", &session.puzzle, prompt, text_board);
+                e.footer(|f| {
+                    f.text(format!(
+                        "{} · .solve <move> or type it in chat · .hint",
+                        session.puzzle.rating_text()
+                    ))
+                })
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+// Post the last puzzle's solution, then a new puzzle. The scheduler runs this once a day.
+pub async fn post_daily(http: &Http, data: &RwLock<TypeMap>) {
+    let channel_id = match config::get(data).await.daily_puzzle.channel {
+        Some(channel) => ChannelId(channel),
+        None => return,
+    };
+    if !modules::enabled_in(http, data, channel_id, &PuzzlesModule).await {
+        return;
+    }
+
+    if let Err(why) = reveal(http, data, "Yesterday's puzzle").await {
+        println!("Error revealing the daily puzzle: {:?}", why);
+    }
+
+    // From the site picked for the channel's server. An imported puzzle stands in when it can't
+    // be reached.
+    let guild_id = match channel_id.to_channel(http).await {
+        Ok(channel) => channel.guild().map(|channel| channel.guild_id),
+        Err(_) => None,
+    };
+    let client = web::client(data).await;
+    let fetched = match puzzle_source(data, guild_id).await {
+        PuzzleSource::Lichess => lichess::daily_puzzle(&client)
+            .await
+            .map(Puzzle::from_lichess),
+        PuzzleSource::ChessCom => chesscom::daily_puzzle(&client)
+            .await
+            .map(Puzzle::from_chesscom),
+    };
+    let puzzle = match fetched {
+        Ok(puzzle) => puzzle,
+        Err(why) => {
+            println!("Could not get the daily puzzle: {:?}", why);
+            None
+        }
+    };
+    let puzzle = match puzzle {
+        Some(puzzle) => Some(puzzle),
+        None => puzzledb::get(data).await.pick(None, None),
+    };
+    let puzzle = match puzzle {
+        Some(puzzle) => puzzle,
+        None => {
+            println!("No daily puzzle to post");
+            return;
+        }
+    };
+    if let Err(why) = post(http, data, channel_id, puzzle).await {
+        println!("Error posting the daily puzzle: {:?}", why);
+    }
+}
+
+async fn post(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    puzzle: Puzzle,
+) -> serenity::Result<()> {
+    let pos = match puzzle.position() {
+        Some(pos) => pos,
+        None => {
+            println!("Skipping daily puzzle with a bad position: {}", puzzle.fen);
+            return Ok(());
+        }
+    };
+
+    let guild_id = channel_id
+        .to_channel(http)
+        .await?
+        .guild()
+        .map(|channel| channel.guild_id);
+    let style = settings::board_style(data, guild_id, None).await;
+    let options = DiagramOptions {
+        flipped: pos.turn() == Color::Black,
+        highlight: puzzle.highlight(),
+    };
+    let image = diagram::board_image(http, data, channel_id, pos.board(), &style, &options).await;
+    let text_board = match image {
+        Some(_) => None,
+        None => Some(render::board_for(pos.board(), &style)),
+    };
+
+    let message = channel_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                puzzle_embed(
+                    e,
+                    "Daily puzzle",
+                    &puzzle,
+                    &to_move_prompt(&pos),
+                    text_board,
+                )
+            });
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Secondary);
+                        b.label("Show solution");
+                        b.custom_id(format!("{}{}", SOLUTION_PREFIX, puzzle.id));
+                        b
+                    })
+                })
+            });
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    let number = settings::read(data, |settings| settings.daily_puzzles_posted).await + 1;
+    let thread_id = if config::get(data).await.daily_puzzle.thread {
+        let thread = channel_id
+            .create_public_thread(http, message.id, |t| {
+                t.name(format!("Daily puzzle #{}", number));
+                t.auto_archive_duration(1440);
+                t
+            })
+            .await;
+        match thread {
+            Ok(thread) => Some(thread.id.0),
+            Err(why) => {
+                println!("Could not make a thread for the daily puzzle: {:?}", why);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    settings::update(data, |settings| {
+        settings.daily_puzzles_posted = number;
+        settings.daily_puzzle = Some(PostedPuzzle {
+            puzzle,
+            channel_id: channel_id.0,
+            message_id: message.id.0,
+            number,
+            posted_at: Utc::now().timestamp(),
+            thread_id,
+            revealed: false,
+        });
+    })
+    .await;
+
+    Ok(())
+}
+
+// Post the last puzzle's solution in its thread or under it, in spoilers for anyone still
+// working on it.
+async fn reveal(http: &Http, data: &RwLock<TypeMap>, label: &str) -> serenity::Result<()> {
+    let posted = settings::update(data, |settings| {
+        let posted = settings.daily_puzzle.as_mut()?;
+        if posted.revealed {
+            return None;
+        }
+        posted.revealed = true;
+        Some(posted.clone())
+    })
+    .await;
+    let posted = match posted {
+        Some(posted) => posted,
+        None => return Ok(()),
+    };
+    let channel_id = ChannelId(posted.channel_id);
+    let message_id = MessageId(posted.message_id);
+
+    let guild_id = channel_id
+        .to_channel(http)
+        .await?
+        .guild()
+        .map(|channel| channel.guild_id.0);
+    let notation = game::guild_notation(data, guild_id).await;
+    let solution = posted.puzzle.solution_text(notation);
+
+    let text = format!("{}: ||{}||", label, solution);
+    match posted.thread_id {
+        Some(thread_id) => {
+            ChannelId(thread_id).say(http, text).await?;
+        }
+        None => {
+            channel_id
+                .send_message(http, |m| {
+                    m.content(text);
+                    m.reference_message((channel_id, message_id));
+                    m
+                })
+                .await?;
+        }
+    }
+    // Nothing left to peek at.
+    channel_id
+        .edit_message(http, message_id, |m| m.components(|c| c))
+        .await?;
+
+    Ok(())
+}
+
+// Reveal the daily puzzle's solution once `reveal_after` hours have gone by. Runs with the
+// hourly reminders.
+async fn reveal_due(http: &Http, data: &RwLock<TypeMap>) {
+    let hours = match config::get(data).await.daily_puzzle.reveal_after {
+        Some(hours) => hours,
+        None => return,
+    };
+    let due = settings::read(data, |settings| {
+        settings.daily_puzzle.as_ref().is_some_and(|posted| {
+            !posted.revealed && Utc::now().timestamp() - posted.posted_at >= hours as i64 * 3600
+        })
+    })
+    .await;
+    if due {
+        if let Err(why) = reveal(http, data, "The solution").await {
+            println!("Error revealing the daily puzzle: {:?}", why);
+        }
+    }
+}
+
+// DM everyone who asked for reminders and hasn't solved the daily puzzle, when it's evening
+// where they are. The scheduler runs this every hour.
+async fn remind(http: &Http, data: &RwLock<TypeMap>) {
+    let posted = settings::read(data, |settings| settings.daily_puzzle.clone()).await;
+    let posted = match posted {
+        Some(posted) => posted,
+        None => return,
+    };
+    let channel_id = ChannelId(posted.channel_id);
+    if !modules::enabled_in(http, data, channel_id, &PuzzlesModule).await {
+        return;
+    }
+
+    let wanted = settings::read(data, |settings| {
+        settings
+            .users
+            .iter()
+            .filter(|(_, user)| user.puzzle_reminder)
+            .map(|(id, user)| (*id, user.timezone))
+            .collect::<Vec<_>>()
+    })
+    .await;
+    if wanted.is_empty() {
+        return;
+    }
+    let channel_tz = timezone::for_channel(http, data, channel_id).await;
+    let streaks = read_puzzles(data, |store| {
+        wanted
+            .iter()
+            .filter(|(id, tz)| {
+                let hour = Utc::now().with_timezone(&tz.unwrap_or(channel_tz)).hour();
+                let solved = store
+                    .players
+                    .get(id)
+                    .is_some_and(|stats| stats.last_daily == Some(posted.number));
+                hour == REMINDER_HOUR && !solved
+            })
+            .map(|(id, _)| {
+                let streak = store
+                    .players
+                    .get(id)
+                    .map_or(0, |stats| stats.current_streak(posted.number));
+                (*id, streak)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    for (user_id, streak) in streaks {
+        let mut text = format!(
+            "🧩 You haven't solved today's daily puzzle in <#{}> yet. Try it with `.puzzle daily`!",
+            channel_id
+        );
+        if streak > 0 {
+            text.push_str(&format!(
+                " Your streak of {} is on the line.",
+                plural(streak, "day")
+            ));
+        }
+        text.push_str("\n`.puzzlereminder off` stops these.");
+        let sent = match UserId(user_id).create_dm_channel(http).await {
+            Ok(dm) => dm.say(http, text).await.map(|_| ()),
+            Err(why) => Err(why),
+        };
+        if let Err(why) = sent {
+            println!("Error reminding {} of the daily puzzle: {:?}", user_id, why);
+        }
+    }
+}
+
+fn plural(n: u32, word: &str) -> String {
+    format!("{} {}{}", n, word, if n == 1 { "" } else { "s" })
+}
+
+async fn reply_privately(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error replying to a puzzle button: {:?}", why);
+    }
+}
+
+// Show the daily puzzle's solution to whoever asked. Returns false if the button isn't ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = match component.data.custom_id.strip_prefix(SOLUTION_PREFIX) {
+        Some(id) => id,
+        None => return false,
+    };
+
+    let posted = settings::read(&ctx.data, |settings| settings.daily_puzzle.clone()).await;
+    let text = match posted.filter(|posted| posted.puzzle.id == id) {
+        Some(posted) => {
+            let notation = game::guild_notation(&ctx.data, component.guild_id.map(|id| id.0)).await;
+            format!("||{}||", posted.puzzle.solution_text(notation))
+        }
+        None => "This puzzle's solution has been posted already.".to_string(),
+    };
+    reply_privately(ctx, component, &text).await;
+    true
+}
+
+// Lichess picks puzzles by difficulty, not rating, so aim for the middle of the range.
+fn difficulty_for(range: (u32, u32)) -> &'static str {
+    let middle = i64::from(range.0 + range.1) / 2;
+    match middle - BASE_RATING {
+        d if d <= -450 => "easiest",
+        d if d <= -150 => "easier",
+        d if d < 150 => "normal",
+        d if d < 450 => "harder",
+        _ => "hardest",
+    }
+}
+
+// "1500-1800", or "1600" for 1500 to 1700.
+fn parse_range(arg: &str) -> Option<(u32, u32)> {
+    let (min, max) = match arg.split_once('-') {
+        Some((min, max)) => (min.parse().ok()?, max.parse().ok()?),
+        None => {
+            let rating: u32 = arg.parse().ok()?;
+            (rating.saturating_sub(100), rating + 100)
+        }
+    };
+    if min <= max && max <= 4000 {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+pub fn find_theme(name: &str) -> Option<&'static str> {
+    THEMES
+        .iter()
+        .copied()
+        .find(|theme| theme.eq_ignore_ascii_case(name))
+}
+
+// A puzzle from the imported ones if there are any, otherwise from Lichess.
+pub async fn find_puzzle(
+    data: &RwLock<TypeMap>,
+    range: Option<(u32, u32)>,
+    theme: Option<&str>,
+) -> reqwest::Result<Option<Puzzle>> {
+    let db = puzzledb::get(data).await;
+    if !db.is_empty() {
+        return Ok(db.pick(range, theme));
+    }
+    fetch_puzzle(&web::client(data).await, range, theme).await
+}
+
+// A puzzle in the rating range if one turns up in a few tries, otherwise the closest one.
+async fn fetch_puzzle(
+    client: &reqwest::Client,
+    range: Option<(u32, u32)>,
+    theme: Option<&str>,
+) -> reqwest::Result<Option<Puzzle>> {
+    let (min, max) = match range {
+        Some(range) => range,
+        None => {
+            let response = lichess::next_puzzle(client, theme, "normal").await?;
+            return Ok(Puzzle::from_lichess(response));
+        }
+    };
+    let distance =
+        |puzzle: &Puzzle| min.saturating_sub(puzzle.rating) + puzzle.rating.saturating_sub(max);
+
+    let mut best: Option<Puzzle> = None;
+    for _ in 0..FETCH_TRIES {
+        let response = lichess::next_puzzle(client, theme, difficulty_for((min, max))).await?;
+        let puzzle = match Puzzle::from_lichess(response) {
+            Some(puzzle) => puzzle,
+            None => continue,
+        };
+        if distance(&puzzle) == 0 {
+            return Ok(Some(puzzle));
+        }
+        if best
+            .as_ref()
+            .is_none_or(|best| distance(&puzzle) < distance(best))
+        {
+            best = Some(puzzle);
+        }
+    }
+    Ok(best)
+}
+
+// Play a move on the author's puzzle in this channel and say how it went. Returns false if they
+// don't have one going.
+async fn submit(ctx: &Context, msg: &Message, text: &str) -> serenity::Result<bool> {
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let key = (msg.channel_id.0, msg.author.id.0);
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let tried = with_puzzles(&ctx.data, |store| {
+        let mut session = store.sessions.remove(&key)?;
+        let outcome = session.try_move(text, notation);
+        // A wrong move fails the puzzle for the rating, even if they go on to find the line.
+        let change = match outcome {
+            Ok(Outcome::Wrong) => store.count(key.1, guild_id, &mut session, false),
+            Ok(Outcome::Solved { .. }) => store.count(key.1, guild_id, &mut session, true),
+            _ => None,
+        };
+        let streak = match (&outcome, session.daily) {
+            (Ok(Outcome::Solved { .. }), Some(number)) => {
+                Some(store.players.entry(key.1).or_default().solved_daily(number))
+            }
+            _ => None,
+        };
+        if !matches!(outcome, Ok(Outcome::Solved { .. })) {
+            store.sessions.insert(key, session.clone());
+        }
+        Some((outcome, session, change, streak))
+    })
+    .await;
+    let (outcome, session, change, streak) = match tried {
+        Some(tried) => tried,
+        None => return Ok(false),
+    };
+
+    let race_won = match (&outcome, session.race) {
+        (Ok(Outcome::Solved { .. }), Some(race)) => Some(race),
+        _ => None,
+    };
+    match outcome {
+        Err(why) => {
+            msg.reply(&ctx.http, why.to_string()).await?;
+        }
+        Ok(Outcome::Wrong) => {
+            msg.reply(
+                &ctx.http,
+                format!("❌ That's not it, try again!{}", describe_change(change)),
+            )
+            .await?;
+        }
+        Ok(Outcome::Correct { played, reply }) => {
+            let turn = session
+                .position()
+                .map_or(Color::White, |(pos, _)| pos.turn());
+            let prompt = format!(
+                "✅ **{}** is right! {} answers **{}**. Your move.",
+                played,
+                color_name(!turn),
+                reply
+            );
+            send_board(
+                &ctx.http,
+                &ctx.data,
+                msg.channel_id,
+                msg.guild_id,
+                msg.author.id,
+                &session,
+                &prompt,
+            )
+            .await?;
+        }
+        Ok(Outcome::Solved { played }) => {
+            let mistakes = match session.mistakes {
+                0 => "without a mistake".to_string(),
+                1 => "after 1 wrong try".to_string(),
+                n => format!("after {} wrong tries", n),
+            };
+            let mut text = format!(
+                "🎉 **{}** solves it, {}! The whole line: {}{}",
+                played,
+                mistakes,
+                session.puzzle.solution_text(notation),
+                describe_change(change)
+            );
+            if let Some(streak) = streak {
+                text.push_str(&format!(
+                    "\n🔥 Daily puzzle streak: {}",
+                    plural(streak, "day")
+                ));
+            }
+            msg.reply(&ctx.http, text).await?;
+        }
+    }
+    if let Some((race_id, index)) = race_won {
+        puzzlerace::advance(
+            ctx.http.clone(),
+            ctx.data.clone(),
+            race_id,
+            index,
+            Some(msg.author.id.0),
+        )
+        .await;
+    }
+    Ok(true)
+}
+
+// " (+8, now 1508)" after a puzzle counted for the rating.
+fn describe_change(change: Option<(i32, i32)>) -> String {
+    match change {
+        Some((change, rating)) => format!(" ({:+}, puzzle rating now {})", change, rating),
+        None => String::new(),
+    }
+}
+
+// Moves typed in chat by someone solving a puzzle here, like "Qxf7+". Players in a game in the
+// channel are left to game.rs.
+async fn chat_move(ctx: &Context, msg: &Message) {
+    let text = msg.content.trim();
+    if !game::looks_like_move(text) {
+        return;
+    }
+    let key = (msg.channel_id.0, msg.author.id.0);
+    if !read_puzzles(&ctx.data, |store| store.sessions.contains_key(&key)).await {
+        return;
+    }
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let readable = read_puzzles(&ctx.data, |store| {
+        let (pos, _) = store.sessions.get(&key)?.position()?;
+        game::parse_move(&VariantPosition::Chess(pos), text, notation).ok()
+    })
+    .await
+    .is_some();
+    if !readable {
+        return;
+    }
+    let in_game = game::read_games(&ctx.data, |store| {
+        store.active(msg.channel_id, msg.author.id).is_some()
+    })
+    .await;
+    if in_game {
+        return;
+    }
+
+    if let Err(why) = submit(ctx, msg, text).await {
+        println!("Error playing a puzzle move from chat: {:?}", why);
+    }
+}
+
+#[command]
+#[aliases("tactic")]
+#[description(
+    "Get a tactics puzzle to solve, optionally in a rating range or with a theme like `fork` or `mateIn2`. `.puzzle daily` is the daily puzzle, and solving it every day builds a streak. Play your moves with `.solve`, or type them in chat."
+)]
+#[usage("[daily] [rating range] [theme]")]
+#[example("1500-1800 fork")]
+async fn puzzle(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut range = None;
+    let mut theme = None;
+    let mut daily = false;
+    for arg in args.iter::<String>().flatten() {
+        if arg.eq_ignore_ascii_case("daily") {
+            daily = true;
+        } else if let Some(parsed) = parse_range(&arg) {
+            range = Some(parsed);
+        } else if let Some(found) = find_theme(&arg) {
+            theme = Some(found);
+        } else {
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "Use `.puzzle [rating range] [theme]`, like `.puzzle 1500-1800 fork`. Themes are: {}",
+                    THEMES.join(", ")
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let key = (msg.channel_id.0, msg.author.id.0);
+    let racing = read_puzzles(&ctx.data, |store| {
+        store
+            .sessions
+            .get(&key)
+            .is_some_and(|session| session.race.is_some())
+    })
+    .await;
+    if racing {
+        msg.reply(&ctx.http, "Finish your puzzle race first!")
+            .await?;
+        return Ok(());
+    }
+
+    let source = puzzle_source(&ctx.data, msg.guild_id).await;
+    let posted = settings::read(&ctx.data, |settings| settings.daily_puzzle.clone()).await;
+    let found = match source {
+        _ if daily => match &posted {
+            Some(posted) => Ok(Some(posted.puzzle.clone())),
+            None => {
+                msg.reply(&ctx.http, "There's no daily puzzle up right now.")
+                    .await?;
+                return Ok(());
+            }
+        },
+        PuzzleSource::ChessCom if range.is_some() || theme.is_some() => {
+            msg.reply(
+                &ctx.http,
+                "This server's puzzles come from chess.com, which can't pick them by rating or theme.",
+            )
+            .await?;
+            return Ok(());
+        }
+        PuzzleSource::ChessCom => chesscom::random_puzzle(&web::client(&ctx.data).await)
+            .await
+            .map(Puzzle::from_chesscom),
+        PuzzleSource::Lichess => find_puzzle(&ctx.data, range, theme).await,
+    };
+    let puzzle = match found {
+        Ok(Some(puzzle)) => puzzle,
+        Ok(None) => {
+            msg.reply(&ctx.http, "I couldn't find a puzzle like that, try again?")
+                .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Could not get a puzzle: {:?}", why);
+            msg.reply(
+                &ctx.http,
+                format!("I couldn't get a puzzle from {} right now.", source.name()),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let seen = read_puzzles(&ctx.data, |store| store.has_tried(key.1, &puzzle.id)).await;
+    let mut session = Session::new(puzzle, seen);
+    if daily {
+        session.daily = posted.map(|posted| posted.number);
+    }
+    let mut prompt = match session.position() {
+        Some((pos, _)) => to_move_prompt(&pos),
+        None => {
+            msg.reply(&ctx.http, "That puzzle came out broken, try another one.")
+                .await?;
+            return Ok(());
+        }
+    };
+    if let Some((min, max)) = range {
+        if session.puzzle.rating < min || session.puzzle.rating > max {
+            prompt.push_str("\nThis is the closest to your range I could find.");
+        }
+    }
+    if seen && session.puzzle.chesscom_day.is_some() {
+        prompt.push_str("\nYou've tried this one before, so it won't count as solved again.");
+    } else if seen {
+        prompt.push_str("\nYou've tried this one before, so it won't change your puzzle rating.");
+    }
+    // A new puzzle replaces the one they had going here.
+    with_puzzles(&ctx.data, |store| {
+        store.sessions.insert(key, session.clone());
+    })
+    .await;
+
+    send_board(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        msg.guild_id,
+        msg.author.id,
+        &session,
+        &prompt,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Play a move on your puzzle in this channel.")]
+#[usage("<move>")]
+#[example("Qxf7+")]
+async fn solve(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.solve <move>`, like `.solve Qxf7+`")
+            .await?;
+        return Ok(());
+    }
+    if !submit(ctx, msg, text).await? {
+        msg.reply(
+            &ctx.http,
+            "You don't have a puzzle going here, get one with `.puzzle`.",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Get a hint on your puzzle in this channel: first the piece to move, then where it is, then the move itself. Each hint takes a third off the rating points solving it earns."
+)]
+async fn hint(ctx: &Context, msg: &Message) -> CommandResult {
+    let key = (msg.channel_id.0, msg.author.id.0);
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+    let session = with_puzzles(&ctx.data, |store| {
+        let session = store.sessions.get_mut(&key)?;
+        if session.race.is_none() && session.move_hints < MAX_HINTS {
+            session.move_hints += 1;
+            session.hints = session.hints.saturating_add(1);
+        }
+        Some(session.clone())
+    })
+    .await;
+
+    let reply = match session {
+        None => "You don't have a puzzle going here, get one with `.puzzle`.".to_string(),
+        Some(session) if session.race.is_some() => "No hints during a race!".to_string(),
+        Some(session) => {
+            let hint = session.hint(notation).unwrap_or_default();
+            match session.move_hints {
+                MAX_HINTS => format!("💡 {} That's the last hint for this move.", hint),
+                n => format!("💡 {} ({} of {} hints for this move)", hint, n, MAX_HINTS),
+            }
+        }
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("giveup")]
+#[description("Give up on your puzzle in this channel and see the answer.")]
+async fn solution(ctx: &Context, msg: &Message) -> CommandResult {
+    let key = (msg.channel_id.0, msg.author.id.0);
+    let guild_id = msg.guild_id.map(|id| id.0);
+    // Giving up counts as failing, unless nothing was played yet.
+    let ended = with_puzzles(&ctx.data, |store| {
+        let mut session = store.sessions.remove(&key)?;
+        if session.race.is_some() {
+            store.sessions.insert(key, session);
+            return Some(Err(()));
+        }
+        let change = match session.ply {
+            0 if session.mistakes == 0 && session.hints == 0 => None,
+            _ => store.count(key.1, guild_id, &mut session, false),
+        };
+        Some(Ok((session, change)))
+    })
+    .await;
+    let reply = match ended {
+        Some(Err(())) => "No peeking during a race!".to_string(),
+        Some(Ok((session, change))) => {
+            let notation = game::guild_notation(&ctx.data, guild_id).await;
+            let link = match session.puzzle.url() {
+                Some(url) => format!("\n<{}>", url),
+                None => String::new(),
+            };
+            format!(
+                "The solution was {}{}{}",
+                session.puzzle.solution_text(notation),
+                describe_change(change),
+                link
+            )
+        }
+        None => "You don't have a puzzle going here.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("puzzlerating")]
+#[description("Show someone's puzzle rating and the puzzles they tried last.")]
+#[usage("[@user]")]
+async fn puzzlestats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let user_id = match args.current() {
+        Some(arg) => match utils::parse_username(arg) {
+            Some(id) => id,
+            None => {
+                msg.reply(&ctx.http, "Use `.puzzlestats` or `.puzzlestats @user`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => msg.author.id.0,
+    };
+
+    let stats = read_puzzles(&ctx.data, |store| store.players.get(&user_id).cloned()).await;
+    let stats = match stats {
+        Some(stats) if !stats.attempts.is_empty() || !stats.chesscom.is_empty() => stats,
+        _ => {
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "<@{}> hasn't tried a puzzle yet. Everyone starts at {}, get one with `.puzzle`!",
+                    user_id, START_RATING
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut desc = format!("<@{}>", user_id);
+    let tried = stats.attempts.len();
+    if tried > 0 {
+        let solved = stats.solved();
+        desc.push_str(&format!(
+            "\n**{}** after {} puzzles, {} solved ({}%)",
+            stats.rating,
+            tried,
+            solved,
+            solved * 100 / tried
+        ));
+    }
+    if !stats.chesscom.is_empty() {
+        desc.push_str(&format!(
+            "\n{} of {} chess.com puzzles solved",
+            stats.chesscom_solved(),
+            stats.chesscom.len()
+        ));
+    }
+    if stats.best_streak > 0 {
+        let latest = settings::read(&ctx.data, |settings| settings.daily_puzzles_posted).await;
+        desc.push_str(&format!(
+            "\n🔥 Daily puzzle streak: {} (best {})",
+            plural(stats.current_streak(latest), "day"),
+            stats.best_streak
+        ));
+    }
+    for attempt in stats.attempts.iter().rev().take(ATTEMPTS_SHOWN) {
+        desc.push_str(&format!(
+            "\n{} <t:{}:d> [{}](https://lichess.org/training/{}) rated {}: {} ({:+})",
+            if attempt.solved { "✅" } else { "❌" },
+            attempt.at,
+            attempt.puzzle_id,
+            attempt.puzzle_id,
+            attempt.puzzle_rating,
+            attempt.rating,
+            attempt.change
+        ));
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Puzzle rating");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Get a DM in the evening, in your timezone, on days you haven't solved the daily puzzle yet."
+)]
+#[usage("[on|off]")]
+async fn puzzlereminder(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let on = match args.current() {
+        Some(arg) => match settings::parse_toggle(arg) {
+            Some(on) => on,
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.puzzlereminder on` or `.puzzlereminder off`",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let reply = if settings::user(&ctx.data, msg.author.id)
+                .await
+                .puzzle_reminder
+            {
+                "You get a DM on evenings you haven't solved the daily puzzle. `.puzzlereminder off` stops it."
+            } else {
+                "You don't get daily puzzle reminders, `.puzzlereminder on` starts them."
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    let author = msg.author.id.0;
+    settings::update(&ctx.data, |settings| {
+        settings.users.entry(author).or_default().puzzle_reminder = on;
+    })
+    .await;
+
+    let reply = if on {
+        format!(
+            "I'll DM you at {}:00 on days you haven't solved the daily puzzle. Set your timezone with `.timezone` if that's not your evening.",
+            REMINDER_HOUR
+        )
+    } else {
+        "No more daily puzzle reminders.".to_string()
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description(
+    "Import puzzles from the Lichess puzzle dump so they can be served without Lichess. Give the path of the unpacked lichess_db_puzzle.csv on the bot's machine, and optionally how many to keep."
+)]
+#[usage("<path> [max puzzles]")]
+#[example("/srv/lichess_db_puzzle.csv 200000")]
+async fn importpuzzles(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let source = match args.single::<String>() {
+        Ok(source) => source,
+        Err(_) => {
+            msg.reply(&ctx.http, "Use `.importpuzzles <path> [max puzzles]`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let max = args.single::<usize>().unwrap_or(IMPORT_MAX);
+    let data_dir = config::get(&ctx.data).await.data_dir.clone();
+
+    msg.reply(&ctx.http, "Importing puzzles, this takes a while…")
+        .await?;
+    let imported = {
+        let data_dir = data_dir.clone();
+        tokio::task::spawn_blocking(move || PuzzleDb::import(Path::new(&source), &data_dir, max))
+            .await
+    };
+    let reply = match imported {
+        Ok(Ok(summary)) => {
+            let db = Arc::new(PuzzleDb::load(&data_dir));
+            let loaded = db.len();
+            ctx.data.write().await.insert::<PuzzleDbContainer>(db);
+            format!(
+                "Imported {} puzzles and skipped {}. `.puzzle` serves these {} from now on.",
+                summary.imported, summary.skipped, loaded
+            )
+        }
+        Ok(Err(why)) => format!("Couldn't import the puzzles: {}", why),
+        Err(why) => format!("The import crashed: {}", why),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Pick where the server's puzzles come from, for `.puzzle` and the daily puzzle. chess.com puzzles have no rating, so they're kept apart in `.puzzlestats`."
+)]
+#[usage("[lichess|chesscom]")]
+async fn puzzlesource(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let source = match args.current() {
+        Some(arg) => match PuzzleSource::from_name(arg) {
+            Some(source) => source,
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.puzzlesource lichess` or `.puzzlesource chesscom`",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        None => {
+            let source = puzzle_source(&ctx.data, Some(guild_id)).await;
+            msg.reply(
+                &ctx.http,
+                format!("This server's puzzles come from {}.", source.name()),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().puzzle_source = source;
+    })
+    .await;
+    msg.reply(
+        &ctx.http,
+        format!("Puzzles in this server will come from {}.", source.name()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daily_streaks() {
+        let mut stats = PuzzleStats::default();
+        assert_eq!(stats.solved_daily(4), 1);
+        assert_eq!(stats.solved_daily(5), 2);
+        // Solving the same one again doesn't count twice.
+        assert_eq!(stats.solved_daily(5), 2);
+        assert_eq!(stats.current_streak(6), 2);
+        assert_eq!(stats.current_streak(7), 0);
+        assert_eq!(stats.solved_daily(7), 1);
+        assert_eq!(stats.best_streak, 2);
+    }
+}
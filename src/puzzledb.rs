@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rand::prelude::*;
+use serenity::prelude::*;
+use shakmaty::{fen::Fen, uci::UciMove, CastlingMode, Chess, EnPassantMode, Position};
+
+use crate::{fen, puzzle::Puzzle};
+
+// The imported puzzles in the data dir, one per line, already in the bot's own form:
+// id, FEN, the move before, the solution, rating and themes.
+const DB_FILE: &str = "puzzle_db.csv";
+
+// Puzzles from the Lichess dump people didn't like, or hardly anyone played, are left out.
+const MIN_POPULARITY: i32 = 50;
+const MIN_PLAYS: u32 = 100;
+
+// Puzzles kept offline, sorted by rating, with an index by theme.
+#[derive(Default)]
+pub struct PuzzleDb {
+    puzzles: Vec<Puzzle>,
+    // Indexes into `puzzles` for each theme, in rating order too.
+    themes: HashMap<String, Vec<usize>>,
+}
+
+// What an import did.
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+impl PuzzleDb {
+    fn new(mut puzzles: Vec<Puzzle>) -> PuzzleDb {
+        puzzles.sort_by_key(|puzzle| puzzle.rating);
+        let mut themes: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, puzzle) in puzzles.iter().enumerate() {
+            for theme in &puzzle.themes {
+                themes.entry(theme.clone()).or_default().push(i);
+            }
+        }
+        PuzzleDb { puzzles, themes }
+    }
+
+    fn path(data_dir: &str) -> PathBuf {
+        PathBuf::from(data_dir).join(DB_FILE)
+    }
+
+    // The imported puzzles, or none if nothing was imported yet.
+    pub fn load(data_dir: &str) -> PuzzleDb {
+        let file = match File::open(PuzzleDb::path(data_dir)) {
+            Ok(file) => file,
+            Err(_) => return PuzzleDb::default(),
+        };
+        let puzzles = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| read_line(&line))
+            .collect();
+        PuzzleDb::new(puzzles)
+    }
+
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+
+    // A random puzzle in the rating range with the theme, if there is one.
+    pub fn pick(&self, range: Option<(u32, u32)>, theme: Option<&str>) -> Option<Puzzle> {
+        let (min, max) = range.unwrap_or((0, u32::MAX));
+        let start = self.puzzles.partition_point(|puzzle| puzzle.rating < min);
+        let end = self.puzzles.partition_point(|puzzle| puzzle.rating <= max);
+        let mut rng = thread_rng();
+
+        let index = match theme {
+            Some(theme) => {
+                let indexes = self.themes.get(theme)?;
+                let from = indexes.partition_point(|&i| i < start);
+                let to = indexes.partition_point(|&i| i < end);
+                *indexes.get(from..to)?.choose(&mut rng)?
+            }
+            None if start < end => rng.gen_range(start..end),
+            None => return None,
+        };
+        self.puzzles.get(index).cloned()
+    }
+
+    // Read the Lichess puzzle dump (the CSV inside lichess_db_puzzle.csv.zst) and save up to
+    // `max` of its puzzles in the data dir, replacing the last import.
+    pub fn import(source: &Path, data_dir: &str, max: usize) -> io::Result<ImportSummary> {
+        let reader = BufReader::new(File::open(source)?);
+        let path = PuzzleDb::path(data_dir);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written next to the old one first, so a failed import leaves it alone.
+        let temp = path.with_extension("csv.tmp");
+        let mut out = BufWriter::new(File::create(&temp)?);
+
+        let mut summary = ImportSummary {
+            imported: 0,
+            skipped: 0,
+        };
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with("PuzzleId") || line.trim().is_empty() {
+                continue;
+            }
+            match read_lichess_line(&line) {
+                Some(puzzle) => {
+                    writeln!(out, "{}", write_line(&puzzle))?;
+                    summary.imported += 1;
+                    if summary.imported >= max {
+                        break;
+                    }
+                }
+                None => summary.skipped += 1,
+            }
+        }
+        out.flush()?;
+        drop(out);
+        fs::rename(&temp, &path)?;
+
+        Ok(summary)
+    }
+}
+
+// PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags. The
+// FEN is before the opponent's move, which is the first of the moves.
+fn read_lichess_line(line: &str) -> Option<Puzzle> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() < 8 {
+        return None;
+    }
+    let popularity: i32 = fields[5].parse().ok()?;
+    let plays: u32 = fields[6].parse().ok()?;
+    if popularity < MIN_POPULARITY || plays < MIN_PLAYS {
+        return None;
+    }
+
+    let mut moves = fields[2].split_whitespace();
+    let last_move = moves.next()?;
+    let mut pos: Chess = fen::parse_position(fields[1])?;
+    let m = last_move.parse::<UciMove>().ok()?.to_move(&pos).ok()?;
+    pos.play_unchecked(m);
+    let solution: Vec<String> = moves.map(str::to_string).collect();
+    if solution.is_empty() {
+        return None;
+    }
+
+    Some(Puzzle {
+        id: fields[0].to_string(),
+        fen: Fen::from_position(&pos, EnPassantMode::Legal).to_string(),
+        last_move: Some(m.to_uci(CastlingMode::Standard).to_string()),
+        solution,
+        rating: fields[3].parse().ok()?,
+        themes: fields[7].split_whitespace().map(str::to_string).collect(),
+        set_by: None,
+        chesscom_day: None,
+    })
+}
+
+fn write_line(puzzle: &Puzzle) -> String {
+    format!(
+        "{},{},{},{},{},{}",
+        puzzle.id,
+        puzzle.fen,
+        puzzle.last_move.as_deref().unwrap_or(""),
+        puzzle.solution.join(" "),
+        puzzle.rating,
+        puzzle.themes.join(" ")
+    )
+}
+
+fn read_line(line: &str) -> Option<Puzzle> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    Some(Puzzle {
+        id: fields[0].to_string(),
+        fen: fields[1].to_string(),
+        last_move: Some(fields[2].to_string()).filter(|m| !m.is_empty()),
+        solution: fields[3].split_whitespace().map(str::to_string).collect(),
+        rating: fields[4].parse().ok()?,
+        themes: fields[5].split_whitespace().map(str::to_string).collect(),
+        set_by: None,
+        chesscom_day: None,
+    })
+}
+
+pub struct PuzzleDbContainer;
+
+impl TypeMapKey for PuzzleDbContainer {
+    type Value = Arc<PuzzleDb>;
+}
+
+pub async fn get(data: &RwLock<TypeMap>) -> Arc<PuzzleDb> {
+    let data = data.read().await;
+    data.get::<PuzzleDbContainer>()
+        .expect("Expected puzzle database in typemap.")
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LICHESS_LINE: &str = "00008,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1913,75,94,6230,crushing hangingPiece long middlegame,https://lichess.org/787zsVup/black#48,";
+
+    #[test]
+    fn reads_the_lichess_export() {
+        let puzzle = read_lichess_line(LICHESS_LINE).unwrap();
+        assert_eq!(puzzle.id, "00008");
+        // The opponent's first move is played, the solver starts after it.
+        assert_eq!(puzzle.last_move.as_deref(), Some("f2g3"));
+        assert_eq!(
+            puzzle.fen,
+            "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2b1/PqP3PP/7K w - - 0 25"
+        );
+        assert_eq!(puzzle.solution, ["e6e7", "b2b1", "b3c1", "b1c1", "h6c1"]);
+        assert_eq!(puzzle.rating, 1913);
+        assert_eq!(
+            puzzle.themes,
+            ["crushing", "hangingPiece", "long", "middlegame"]
+        );
+    }
+
+    #[test]
+    fn skips_unpopular_and_broken_lines() {
+        let unpopular = LICHESS_LINE.replace(",94,6230,", ",10,6230,");
+        assert!(read_lichess_line(&unpopular).is_none());
+        let rarely_played = LICHESS_LINE.replace(",94,6230,", ",94,20,");
+        assert!(read_lichess_line(&rarely_played).is_none());
+        let illegal = LICHESS_LINE.replace("f2g3 ", "a1a8 ");
+        assert!(read_lichess_line(&illegal).is_none());
+        assert!(read_lichess_line("PuzzleId,FEN,Moves,Rating").is_none());
+    }
+
+    #[test]
+    fn round_trips_its_own_format() {
+        let puzzle = read_lichess_line(LICHESS_LINE).unwrap();
+        let read = read_line(&write_line(&puzzle)).unwrap();
+        assert_eq!(read.id, puzzle.id);
+        assert_eq!(read.fen, puzzle.fen);
+        assert_eq!(read.last_move, puzzle.last_move);
+        assert_eq!(read.solution, puzzle.solution);
+        assert_eq!(read.rating, puzzle.rating);
+        assert_eq!(read.themes, puzzle.themes);
+        assert!(read_line("too,few,fields").is_none());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    modules::BotModule,
+    puzzle::{self, Puzzle, PuzzleStore, Session},
+    EMBED_SIDE_COLOR,
+};
+
+const DEFAULT_PUZZLES: usize = 5;
+const MAX_PUZZLES: usize = 10;
+const MAX_RACERS: usize = 8;
+
+// Nobody scores a puzzle that goes unsolved this long.
+const PUZZLE_SECS: u64 = 180;
+
+#[group]
+#[commands(puzzlerace)]
+struct PuzzleRaces;
+
+pub struct PuzzleRacesModule;
+
+impl BotModule for PuzzleRacesModule {
+    fn name(&self) -> &'static str {
+        "puzzleraces"
+    }
+
+    fn description(&self) -> &'static str {
+        "Race each other through the same puzzles, see `.puzzlerace`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PUZZLERACES_GROUP)
+    }
+}
+
+// Players solving the same puzzles in their DMs, with a point for whoever solves each first.
+#[derive(Debug, Clone)]
+pub struct Race {
+    pub id: u32,
+    // Where the race was started, and where the results go.
+    pub channel_id: u64,
+    pub guild_id: Option<u64>,
+    pub players: Vec<u64>,
+    // Each player's DM channel and score, in the same order.
+    pub dms: Vec<u64>,
+    pub scores: Vec<u32>,
+    pub puzzles: Vec<Puzzle>,
+    // The puzzle everyone is on.
+    pub current: usize,
+}
+
+impl Race {
+    // "<@1> 2 · <@2> 1"
+    fn score_line(&self) -> String {
+        self.players
+            .iter()
+            .zip(&self.scores)
+            .map(|(player, score)| format!("<@{}> {}", player, score))
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
+
+// Give every racer the race's current puzzle. Race puzzles don't count for puzzle ratings.
+fn start_puzzle(store: &mut PuzzleStore, race: &Race) {
+    for (&player, &dm) in race.players.iter().zip(&race.dms) {
+        let mut session = Session::new(race.puzzles[race.current].clone(), true);
+        session.race = Some((race.id, race.current));
+        store.sessions.insert((dm, player), session);
+    }
+}
+
+// Send everyone the current puzzle, and move on if nobody solves it in time.
+async fn show_puzzle(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, race: &Race, note: &str) {
+    let puzzle = &race.puzzles[race.current];
+    let mut session = Session::new(puzzle.clone(), true);
+    session.race = Some((race.id, race.current));
+    let prompt = match puzzle.position() {
+        Some(pos) => format!(
+            "{}**Puzzle {} of {}.** {}",
+            note,
+            race.current + 1,
+            race.puzzles.len(),
+            puzzle::to_move_prompt(&pos)
+        ),
+        None => return,
+    };
+
+    for (&player, &dm) in race.players.iter().zip(&race.dms) {
+        let result = puzzle::send_board(
+            &http,
+            &data,
+            ChannelId(dm),
+            race.guild_id.map(GuildId),
+            UserId(player),
+            &session,
+            &prompt,
+        )
+        .await;
+        if let Err(why) = result {
+            println!("Error sending a race puzzle: {:?}", why);
+        }
+    }
+
+    time_limit(http, data, race.id, race.current);
+}
+
+fn time_limit(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, race_id: u32, index: usize) {
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(PUZZLE_SECS)).await;
+        advance(http, data, race_id, index, None).await;
+    });
+}
+
+// Move a race on from puzzle `index`, with a point for `winner` if someone solved it. Does
+// nothing if the race is past that puzzle already.
+pub async fn advance(
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    race_id: u32,
+    index: usize,
+    winner: Option<u64>,
+) {
+    let moved = puzzle::with_puzzles(&data, |store| {
+        let position = store
+            .races
+            .iter()
+            .position(|race| race.id == race_id && race.current == index)?;
+        let race = &mut store.races[position];
+        if let Some(slot) = winner.and_then(|winner| race.players.iter().position(|&p| p == winner))
+        {
+            race.scores[slot] += 1;
+        }
+        race.current += 1;
+        let race = race.clone();
+
+        store
+            .sessions
+            .retain(|_, session| session.race != Some((race_id, index)));
+        let finished = race.current >= race.puzzles.len();
+        if finished {
+            store.races.remove(position);
+        } else {
+            start_puzzle(store, &race);
+        }
+        Some((race, finished))
+    })
+    .await;
+    let (race, finished) = match moved {
+        Some(moved) => moved,
+        None => return,
+    };
+
+    let mut note = match winner {
+        Some(winner) => format!("<@{}> solved puzzle {} first!", winner, index + 1),
+        None => format!("Nobody solved puzzle {} in time.", index + 1),
+    };
+    note.push_str(&format!(" Score: {}\n", race.score_line()));
+
+    if !finished {
+        show_puzzle(http, data, &race, &note).await;
+        return;
+    }
+
+    for &dm in &race.dms {
+        let text = format!(
+            "{}That's the race! Results are in <#{}>.",
+            note, race.channel_id
+        );
+        if let Err(why) = ChannelId(dm).say(&http, text).await {
+            println!("Error ending a puzzle race: {:?}", why);
+        }
+    }
+    if let Err(why) = post_results(&http, &race).await {
+        println!("Error posting puzzle race results: {:?}", why);
+    }
+}
+
+async fn post_results(http: &Http, race: &Race) -> serenity::Result<()> {
+    let mut standings: Vec<(u64, u32)> = race
+        .players
+        .iter()
+        .copied()
+        .zip(race.scores.iter().copied())
+        .collect();
+    standings.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+    let mut desc = standings
+        .iter()
+        .enumerate()
+        .map(|(place, (player, score))| {
+            let medal = match place {
+                0 => "🥇".to_string(),
+                1 => "🥈".to_string(),
+                2 => "🥉".to_string(),
+                place => format!("**{}.**", place + 1),
+            };
+            format!("{} <@{}> **{}**", medal, player, score)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let verdict = match standings.as_slice() {
+        [(_, first), (_, second), ..] if first == second => "It's a tie!".to_string(),
+        [(winner, _), ..] => format!("<@{}> wins!", winner),
+        [] => String::new(),
+    };
+    desc.push_str(&format!("\n\n{}", verdict));
+
+    ChannelId(race.channel_id)
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Puzzle race results");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| f.text(format!("{} puzzles", race.puzzles.len())));
+                e
+            });
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("race")]
+#[description(
+    "Race people through the same puzzles in your DMs. Whoever solves a puzzle first scores a point, and nobody does if it goes unsolved for 3 minutes."
+)]
+#[usage("@user [@user...] [puzzles]")]
+#[example("@lucy 5")]
+async fn puzzlerace(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = format!(
+        "Use `.puzzlerace @user [@user...] [puzzles]`, with up to {} racers and {} puzzles.",
+        MAX_RACERS, MAX_PUZZLES
+    );
+    let mut players = vec![msg.author.id.0];
+    let mut count = DEFAULT_PUZZLES;
+    for arg in args.iter::<String>().flatten() {
+        if let Some(id) = utils::parse_username(&arg) {
+            if !players.contains(&id) {
+                players.push(id);
+            }
+        } else if let Ok(n) = arg.parse::<usize>() {
+            count = n;
+        } else {
+            msg.reply(&ctx.http, &usage).await?;
+            return Ok(());
+        }
+    }
+    if players.len() < 2 || players.len() > MAX_RACERS || !(1..=MAX_PUZZLES).contains(&count) {
+        msg.reply(&ctx.http, &usage).await?;
+        return Ok(());
+    }
+
+    let busy = puzzle::read_puzzles(&ctx.data, |store| {
+        players
+            .iter()
+            .copied()
+            .find(|player| store.races.iter().any(|race| race.players.contains(player)))
+    })
+    .await;
+    if let Some(busy) = busy {
+        msg.reply(&ctx.http, format!("<@{}> is in a race already.", busy))
+            .await?;
+        return Ok(());
+    }
+
+    let mut dms = Vec::new();
+    for &player in &players {
+        match UserId(player).create_dm_channel(&ctx.http).await {
+            Ok(dm) => dms.push(dm.id.0),
+            Err(_) => {
+                msg.reply(
+                    &ctx.http,
+                    format!("I can't DM <@{}>, so they can't race.", player),
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    }
+
+    // The same puzzle can come up twice, so ask a few extra times.
+    let mut puzzles: Vec<Puzzle> = Vec::new();
+    for _ in 0..count * 2 {
+        if puzzles.len() == count {
+            break;
+        }
+        match puzzle::find_puzzle(&ctx.data, None, None).await {
+            Ok(Some(puzzle)) if !puzzles.iter().any(|p| p.id == puzzle.id) => puzzles.push(puzzle),
+            Ok(_) => {}
+            Err(why) => {
+                println!("Could not get puzzles for a race: {:?}", why);
+                break;
+            }
+        }
+    }
+    if puzzles.is_empty() {
+        msg.reply(&ctx.http, "I couldn't get puzzles from Lichess right now.")
+            .await?;
+        return Ok(());
+    }
+
+    let race = puzzle::with_puzzles(&ctx.data, |store| {
+        let race = Race {
+            id: store.races.iter().map(|race| race.id).max().unwrap_or(0) + 1,
+            channel_id: msg.channel_id.0,
+            guild_id: msg.guild_id.map(|id| id.0),
+            scores: vec![0; players.len()],
+            players,
+            dms,
+            puzzles,
+            current: 0,
+        };
+        start_puzzle(store, &race);
+        store.races.push(race.clone());
+        race
+    })
+    .await;
+
+    let players = race
+        .players
+        .iter()
+        .map(|player| format!("<@{}>", player))
+        .collect::<Vec<_>>()
+        .join(", ");
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "🏁 Puzzle race between {}, {} puzzles. Check your DMs!",
+                players,
+                race.puzzles.len()
+            ),
+        )
+        .await?;
+    show_puzzle(ctx.http.clone(), ctx.data.clone(), &race, "").await;
+
+    Ok(())
+}
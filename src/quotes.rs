@@ -0,0 +1,645 @@
+use std::{fs, io, path::PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+
+use crate::{
+    config::ConfigContainer, modules::BotModule, permissions::ADMIN_CHECK, random_index, settings,
+    EMBED_SIDE_COLOR,
+};
+
+const QUOTES_FILE: &str = "quotes.json";
+
+const BUTTON_PREFIX: &str = "quotes:";
+
+// Quotes on one page of `.quote search`, and how much of each is shown there.
+const PAGE_SIZE: usize = 5;
+const SHOWN_CHARS: usize = 300;
+// Searches have to fit in a button id, which Discord keeps to 100 characters.
+const MAX_SEARCH_LEN: usize = 80;
+
+// Longest quote `.addquote` takes, so one fits in an embed with room to spare.
+const MAX_QUOTE_CHARS: usize = 1000;
+const MAX_AUTHOR_CHARS: usize = 100;
+
+// What the quotes file starts with when there isn't one yet.
+const DEFAULT_QUOTES: &[(&str, &str)] = &[
+    ("Rapid and blitz chess is first of all for enjoyment.", "Magnus Carlsen"),
+    ("Playing rapid chess, one can lose the habit of concentrating for several hours in serious chess. That is why, if a player has big aims, he should limit his rapid play in favour of serious chess.", "Vladimir Kramnik"),
+    ("He who analyses blitz is stupid.", "Rashid Nezhmetdinov"),
+    ("Blitz chess kills your ideas.", "Bobby Fischer"),
+    ("To be honest, I consider [bullet chess] a bit moronic, and therefore I never play it.", "Vladimir Kramnik"),
+    ("I play way too much blitz chess. It rots the brain just as surely as alcohol.", "Nigel Short"),
+    ("Blitz is simply a waste of time.", "Vladimir Malakhov"),
+    ("[Blitz] is just getting positions where you can move fast. I mean, it's not chess.", "Hikaru Nakamura"),
+    ("Always sack the exchange!", "Ben F6gold"),
+];
+
+#[group]
+#[commands(quote, addquote, removequote)]
+struct Quotes;
+
+pub struct QuotesModule;
+
+#[async_trait]
+impl BotModule for QuotesModule {
+    fn name(&self) -> &'static str {
+        "quotes"
+    }
+
+    fn description(&self) -> &'static str {
+        "Chess quotes, see `.quote` and `.quote search`, and the ones members add with `.addquote`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&QUOTES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        let data_dir = data
+            .get::<ConfigContainer>()
+            .expect("Expected config in typemap.")
+            .data_dir
+            .clone();
+        data.insert::<QuoteContainer>(Mutex::new(QuoteStore::load(&data_dir)));
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quote {
+    // Shown with the quote, and what `.removequote` takes.
+    pub id: u32,
+    pub text: String,
+    pub author: String,
+    // The server it was added in, the only one it shows up in. None for the built-in quotes,
+    // which show up everywhere.
+    pub guild_id: Option<u64>,
+    pub added_by: Option<u64>,
+    // Seconds since the epoch.
+    pub added_at: Option<i64>,
+}
+
+impl Quote {
+    fn shows_in(&self, guild_id: Option<u64>) -> bool {
+        self.guild_id.is_none() || self.guild_id == guild_id
+    }
+}
+
+// Every quote, saved as JSON in the data dir.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuoteStore {
+    pub quotes: Vec<Quote>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl QuoteStore {
+    pub fn load(data_dir: &str) -> QuoteStore {
+        let path = PathBuf::from(data_dir).join(QUOTES_FILE);
+        let mut store: QuoteStore = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|why| {
+                panic!("Could not parse quotes file {}: {}", path.display(), why)
+            }),
+            Err(_) => QuoteStore::with_defaults(),
+        };
+        store.path = path;
+        store
+    }
+
+    // Take everything from a backup, keeping where the quotes are saved.
+    pub fn restore(&mut self, backup: QuoteStore) {
+        let path = std::mem::take(&mut self.path);
+        *self = backup;
+        self.path = path;
+    }
+
+    fn with_defaults() -> QuoteStore {
+        let mut store = QuoteStore::default();
+        for (text, author) in DEFAULT_QUOTES {
+            store.add(text.to_string(), author.to_string(), None, None);
+        }
+        store
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        settings::write_file(&self.path, &text)
+    }
+
+    fn add(
+        &mut self,
+        text: String,
+        author: String,
+        guild_id: Option<u64>,
+        added_by: Option<u64>,
+    ) -> u32 {
+        let id = self.quotes.iter().map(|quote| quote.id).max().unwrap_or(0) + 1;
+        self.quotes.push(Quote {
+            id,
+            text,
+            author,
+            guild_id,
+            added_by,
+            added_at: added_by.map(|_| Utc::now().timestamp()),
+        });
+        id
+    }
+}
+
+pub struct QuoteContainer;
+
+impl TypeMapKey for QuoteContainer {
+    type Value = Mutex<QuoteStore>;
+}
+
+pub async fn read_quotes<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&QuoteStore) -> T,
+{
+    let data = data.read().await;
+    let store = data
+        .get::<QuoteContainer>()
+        .expect("Expected quotes in typemap.")
+        .lock()
+        .await;
+    f(&store)
+}
+
+// Change the quotes and save them.
+pub async fn with_quotes<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut QuoteStore) -> T,
+{
+    let data = data.read().await;
+    let mut store = data
+        .get::<QuoteContainer>()
+        .expect("Expected quotes in typemap.")
+        .lock()
+        .await;
+    let result = f(&mut store);
+    if let Err(why) = store.save() {
+        println!("Could not save quotes: {:?}", why);
+    }
+    result
+}
+
+// `"Blitz kills your ideas." - Bobby Fischer`, with any kind of quote marks and dash.
+fn parse_quote(text: &str) -> Option<(String, String)> {
+    let text = text.trim();
+    let open = text.chars().next().filter(|c| matches!(c, '"' | '“'))?;
+    let close = if open == '“' { '”' } else { '"' };
+    let rest = &text[open.len_utf8()..];
+    let end = rest.rfind(close)?;
+    let quote = rest[..end].trim();
+    let author = rest[end + close.len_utf8()..]
+        .trim_start()
+        .strip_prefix(|c| matches!(c, '-' | '–' | '—'))?
+        .trim();
+    if quote.is_empty() || author.is_empty() {
+        return None;
+    }
+    Some((quote.to_string(), author.to_string()))
+}
+
+// How many typos a word can have and still match, by its length.
+fn allowed_typos(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// How far a quote is from a search, or None if it doesn't match. Every word searched for has to
+// be in the quote or its author, give or take a typo or two; a word that starts one counts too,
+// so "kasp" finds Kasparov.
+fn search_distance(quote: &Quote, search: &[String]) -> Option<usize> {
+    let quote_words = words(&format!("{} {}", quote.text, quote.author));
+    search.iter().try_fold(0, |total, word| {
+        let best = quote_words
+            .iter()
+            .map(|quote_word| {
+                if quote_word.starts_with(word.as_str()) {
+                    0
+                } else {
+                    edit_distance(word, quote_word)
+                }
+            })
+            .min()?;
+        if best <= allowed_typos(word) {
+            Some(total + best)
+        } else {
+            None
+        }
+    })
+}
+
+// The quotes shown in a server that match a search, closest first.
+async fn search_quotes(data: &RwLock<TypeMap>, guild_id: Option<u64>, search: &str) -> Vec<Quote> {
+    let search = words(search);
+    let mut found = read_quotes(data, |store| {
+        store
+            .quotes
+            .iter()
+            .filter(|quote| quote.shows_in(guild_id))
+            .filter_map(|quote| Some((search_distance(quote, &search)?, quote.clone())))
+            .collect::<Vec<_>>()
+    })
+    .await;
+    found.sort_by_key(|(distance, quote)| (*distance, quote.id));
+    found.into_iter().map(|(_, quote)| quote).collect()
+}
+
+// One page of a search. Buttons carry the page they lead to and the search, like
+// "quotes:2:fischer", so nothing needs to be kept about the message.
+struct SearchView {
+    page: usize,
+    search: String,
+}
+
+impl SearchView {
+    fn button_id(&self, page: usize) -> String {
+        format!("{}{}:{}", BUTTON_PREFIX, page, self.search)
+    }
+
+    fn parse_button_id(id: &str) -> Option<SearchView> {
+        let (page, search) = id.strip_prefix(BUTTON_PREFIX)?.split_once(':')?;
+        Some(SearchView {
+            page: page.parse().ok()?,
+            search: search.to_string(),
+        })
+    }
+}
+
+fn page_count(found: &[Quote]) -> usize {
+    found.len().div_ceil(PAGE_SIZE).max(1)
+}
+
+fn search_embed<'a>(
+    e: &'a mut CreateEmbed,
+    view: &SearchView,
+    found: &[Quote],
+) -> &'a mut CreateEmbed {
+    let desc = found
+        .iter()
+        .skip(view.page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|quote| {
+            let mut text: String = quote.text.chars().take(SHOWN_CHARS).collect();
+            if text.len() < quote.text.len() {
+                text.push('…');
+            }
+            format!("**#{}** \"{}\"\n- {}", quote.id, text, quote.author)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    e.title(format!("Quotes matching \"{}\"", view.search));
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e.footer(|f| {
+        f.text(format!(
+            "{} found · Page {} of {}",
+            found.len(),
+            view.page + 1,
+            page_count(found)
+        ))
+    });
+    e
+}
+
+fn search_buttons<'a>(
+    c: &'a mut CreateComponents,
+    view: &SearchView,
+    pages: usize,
+) -> &'a mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("◀");
+            b.custom_id(view.button_id(view.page.saturating_sub(1)));
+            b.disabled(view.page == 0);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("▶");
+            b.custom_id(view.button_id(view.page + 1));
+            b.disabled(view.page + 1 >= pages);
+            b
+        });
+        row
+    })
+}
+
+// Turn the page when one of a search's buttons is pressed. Returns false if the button isn't
+// ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let view = match SearchView::parse_button_id(&component.data.custom_id) {
+        Some(view) => view,
+        None => return false,
+    };
+
+    let guild_id = component.guild_id.map(|id| id.0);
+    let found = search_quotes(&ctx.data, guild_id, &view.search).await;
+    let pages = page_count(&found);
+    // Quotes may have been removed since the buttons were made.
+    let view = SearchView {
+        page: view.page.min(pages - 1),
+        ..view
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| {
+                d.create_embed(|e| search_embed(e, &view, &found));
+                d.components(|c| search_buttons(c, &view, pages))
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error turning a quote search page: {:?}", why);
+    }
+    true
+}
+
+#[command]
+#[aliases("blitz")]
+#[sub_commands(search)]
+#[description("Get a random chess quote.")]
+async fn quote(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let picked = read_quotes(&ctx.data, |store| {
+        let shown: Vec<&Quote> = store
+            .quotes
+            .iter()
+            .filter(|quote| quote.shows_in(guild_id))
+            .collect();
+        if shown.is_empty() {
+            return None;
+        }
+        Some(shown[random_index(shown.len())].clone())
+    })
+    .await;
+    let quote = match picked {
+        Some(quote) => quote,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "There are no quotes yet, add one with `.addquote`.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.color(EMBED_SIDE_COLOR);
+                e.description(format!("\"{}\"", quote.text));
+                e.footer(|f| {
+                    f.text(format!("- {} · #{}", quote.author, quote.id));
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description(
+    "Add a quote for `.quote` to pick from in this server. Put the quote in quote marks, then a dash and who said it. Admins can keep it to some roles with `.perm`."
+)]
+#[usage("\"<quote>\" - <author>")]
+#[example("\"Blitz chess kills your ideas.\" - Bobby Fischer")]
+async fn addquote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (text, author) = match parse_quote(args.rest()) {
+        Some(parsed) => parsed,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Use `.addquote \"<quote>\" - <author>`, like `.addquote \"Blitz chess kills your ideas.\" - Bobby Fischer`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if text.chars().count() > MAX_QUOTE_CHARS || author.chars().count() > MAX_AUTHOR_CHARS {
+        msg.reply(
+            &ctx.http,
+            format!(
+                "That's a bit long, quotes can have up to {} characters and authors {}.",
+                MAX_QUOTE_CHARS, MAX_AUTHOR_CHARS
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let added_by = msg.author.id.0;
+    let id = with_quotes(&ctx.data, |store| {
+        let repeated = store
+            .quotes
+            .iter()
+            .any(|quote| quote.shows_in(guild_id) && quote.text.eq_ignore_ascii_case(&text));
+        if repeated {
+            return None;
+        }
+        Some(store.add(text, author, guild_id, Some(added_by)))
+    })
+    .await;
+
+    let reply = match id {
+        Some(id) => format!("Added quote #{}.", id),
+        None => "That quote is in already.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Remove a quote added in this server, by the number shown under it. The built-in quotes stay."
+)]
+#[usage("<number>")]
+#[example("12")]
+async fn removequote(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = match args.single::<String>() {
+        Ok(arg) => match arg.trim_start_matches('#').parse::<u32>() {
+            Ok(id) => id,
+            Err(_) => {
+                msg.reply(&ctx.http, "Use `.removequote <number>`").await?;
+                return Ok(());
+            }
+        },
+        Err(_) => {
+            msg.reply(&ctx.http, "Use `.removequote <number>`").await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let removed = with_quotes(&ctx.data, |store| {
+        let index = store.quotes.iter().position(|quote| {
+            quote.id == id && quote.guild_id.is_some() && quote.guild_id == guild_id
+        })?;
+        Some(store.quotes.remove(index))
+    })
+    .await;
+
+    let reply = match removed {
+        Some(quote) => format!("Removed quote #{} by {}.", quote.id, quote.author),
+        None => format!("There's no quote #{} added in this server.", id),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("find")]
+#[description("Find quotes by words in them or by who said it. Small typos are fine.")]
+#[usage("<keyword or author>")]
+#[example("fischer")]
+#[example("blitz ideas")]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let search = args.rest().trim().to_string();
+    if words(&search).is_empty() {
+        msg.reply(&ctx.http, "Use `.quote search <keyword or author>`")
+            .await?;
+        return Ok(());
+    }
+    if search.len() > MAX_SEARCH_LEN {
+        msg.reply(
+            &ctx.http,
+            format!("Keep the search under {} characters.", MAX_SEARCH_LEN),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.map(|id| id.0);
+    let found = search_quotes(&ctx.data, guild_id, &search).await;
+    if found.is_empty() {
+        msg.reply(&ctx.http, format!("No quotes match \"{}\".", search))
+            .await?;
+        return Ok(());
+    }
+
+    let view = SearchView { page: 0, search };
+    let pages = page_count(&found);
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| search_embed(e, &view, &found));
+            if pages > 1 {
+                m.components(|c| search_buttons(c, &view, pages));
+            }
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(text: &str, author: &str) -> Quote {
+        Quote {
+            id: 1,
+            text: text.to_string(),
+            author: author.to_string(),
+            guild_id: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    fn distance(quote: &Quote, search: &str) -> Option<usize> {
+        search_distance(quote, &words(search))
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kasparov", "kasparov"), 0);
+        assert_eq!(edit_distance("kasparov", "kasparow"), 1);
+        assert_eq!(edit_distance("tal", "tala"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let quote = quote("Tactics flow from a superior position.", "Bobby Fischer");
+        assert_eq!(distance(&quote, "tactics fischer"), Some(0));
+        // Word starts count, so does a typo in a longer word.
+        assert_eq!(distance(&quote, "fisch"), Some(0));
+        assert_eq!(distance(&quote, "fsicher"), None);
+        assert_eq!(distance(&quote, "superoir"), Some(2));
+        assert_eq!(distance(&quote, "positon"), Some(1));
+        // Every word has to match, and short words have to be exact.
+        assert_eq!(distance(&quote, "tactics kasparov"), None);
+        assert_eq!(distance(&quote, "flaw"), Some(1));
+        assert_eq!(distance(&quote, "bob"), Some(0));
+        assert_eq!(distance(&quote, "rob"), None);
+    }
+}
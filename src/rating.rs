@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{channel::Message, id::UserId},
+    prelude::*,
+    utils,
+};
+use shakmaty::Color;
+
+use crate::{
+    game::{self, Game, GameResult},
+    modules::BotModule,
+    EMBED_SIDE_COLOR,
+};
+
+// Where everyone starts.
+pub const START_RATING: i32 = 1500;
+
+// Ratings move faster for new players, like FIDE's K-factor.
+const NEW_PLAYER_GAMES: u32 = 30;
+const NEW_PLAYER_K: f64 = 40.0;
+const K: f64 = 20.0;
+
+// Rating changes shown by `.rating`.
+const HISTORY_SHOWN: usize = 10;
+
+#[group]
+#[commands(rating)]
+struct Ratings;
+
+pub struct RatingsModule;
+
+impl BotModule for RatingsModule {
+    fn name(&self) -> &'static str {
+        "ratings"
+    }
+
+    fn description(&self) -> &'static str {
+        "Server Elo ratings from games between members, see `.rating`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&RATINGS_GROUP)
+    }
+}
+
+// A player's rating after one game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RatingChange {
+    pub game_id: u32,
+    pub opponent: u64,
+    // Seconds since the epoch.
+    pub at: i64,
+    pub rating: i32,
+    pub change: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rating {
+    pub rating: i32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub history: Vec<RatingChange>,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: START_RATING,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            history: Vec::new(),
+        }
+    }
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // What the player was rated going into a game, if it counted for them.
+    pub fn before(&self, game_id: u32) -> Option<i32> {
+        self.history
+            .iter()
+            .find(|change| change.game_id == game_id)
+            .map(|change| change.rating - change.change)
+    }
+
+    fn k(&self) -> f64 {
+        if self.games() < NEW_PLAYER_GAMES {
+            NEW_PLAYER_K
+        } else {
+            K
+        }
+    }
+
+    // Count a game that scored `score` (1, ½ or 0) against someone rated `opponent_rating`.
+    fn record(&mut self, game: &Game, opponent: u64, opponent_rating: i32, score: f64) {
+        let expected = expected_score(self.rating, opponent_rating);
+        let change = (self.k() * (score - expected)).round() as i32;
+        self.rating += change;
+        match score {
+            s if s > 0.5 => self.wins += 1,
+            s if s < 0.5 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+        self.history.push(RatingChange {
+            game_id: game.id,
+            opponent,
+            at: Utc::now().timestamp(),
+            rating: self.rating,
+            change,
+        });
+    }
+}
+
+// The score a player rated `rating` is expected to make against `opponent_rating`, from 0 to 1.
+pub fn expected_score(rating: i32, opponent_rating: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(f64::from(opponent_rating - rating) / 400.0))
+}
+
+// The rating that would expect someone's results, by the "rule of 400": what their opponents were
+// rated on average, plus 400 for each win more than losses per game. Each game is the opponent's
+// rating and the score made, 1, ½ or 0.
+pub fn performance(games: &[(i32, f64)]) -> Option<i32> {
+    if games.is_empty() {
+        return None;
+    }
+    let count = games.len() as f64;
+    let average = games
+        .iter()
+        .map(|&(rating, _)| f64::from(rating))
+        .sum::<f64>()
+        / count;
+    let score: f64 = games.iter().map(|&(_, score)| score).sum();
+    Some((average + 400.0 * (2.0 * score - count) / count).round() as i32)
+}
+
+// White's score in a finished game.
+fn white_score(result: Option<GameResult>) -> Option<f64> {
+    match result {
+        Some(GameResult::WhiteWon) => Some(1.0),
+        Some(GameResult::BlackWon) => Some(0.0),
+        Some(GameResult::Draw) => Some(0.5),
+        _ => None,
+    }
+}
+
+// Someone's performance over some games, from what each opponent was rated going in. Games that
+// weren't rated are left out.
+pub fn performance_in(ratings: &ServerRatings, games: &[&Game], player: u64) -> Option<i32> {
+    let results: Vec<(i32, f64)> = games
+        .iter()
+        .filter_map(|game| {
+            let color = game.color_of(player)?;
+            let opponent = ratings
+                .get(&game.guild_id?)?
+                .get(&game.player(!color))?
+                .before(game.id)?;
+            let white = white_score(game.result)?;
+            Some((opponent, color.fold_wb(white, 1.0 - white)))
+        })
+        .collect();
+    performance(&results)
+}
+
+// "⚪ 1520 (+20), performance 1900 · ⚫ 1480 (-20), performance 1100", for under a rated game's
+// result.
+pub fn game_summary(ratings: &ServerRatings, game: &Game) -> Option<String> {
+    let guild = ratings.get(&game.guild_id?)?;
+    let sides = [(Color::White, "⚪"), (Color::Black, "⚫")]
+        .iter()
+        .map(|&(color, icon)| {
+            let player = game.player(color);
+            let change = guild
+                .get(&player)?
+                .history
+                .iter()
+                .find(|change| change.game_id == game.id)?;
+            Some(format!(
+                "{} {} ({:+}), performance {}",
+                icon,
+                change.rating,
+                change.change,
+                performance_in(ratings, &[game], player)?
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(sides.join(" · "))
+}
+
+// Every guild's ratings, by guild and then by player.
+pub type ServerRatings = HashMap<u64, HashMap<u64, Rating>>;
+
+// Whether a finished game counts for ratings: played out between two members in a server, and
+// not agreed on as casual.
+pub fn is_rated(game: &Game) -> bool {
+    game.guild_id.is_some()
+        && !game.casual
+        && game.engine_level.is_none()
+        && game.start_fen.is_none()
+        && game
+            .recorded
+            .as_ref()
+            .is_none_or(|recorded| recorded.confirmed)
+        && !matches!(game.result, None | Some(GameResult::Aborted))
+}
+
+// Update both players' ratings for a finished game.
+pub fn rate(ratings: &mut ServerRatings, game: &Game) {
+    let (guild_id, white_score) = match (game.guild_id, white_score(game.result)) {
+        (Some(guild_id), Some(score)) => (guild_id, score),
+        _ => return,
+    };
+    let guild = ratings.entry(guild_id).or_default();
+    let white_rating = guild.get(&game.white).map_or(START_RATING, |r| r.rating);
+    let black_rating = guild.get(&game.black).map_or(START_RATING, |r| r.rating);
+
+    guild
+        .entry(game.white)
+        .or_default()
+        .record(game, game.black, black_rating, white_score);
+    guild
+        .entry(game.black)
+        .or_default()
+        .record(game, game.white, white_rating, 1.0 - white_score);
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("elo")]
+#[description(
+    "Show someone's rating in this server from games played here, and how it changed lately."
+)]
+#[usage("[@user]")]
+async fn rating(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let user_id = match args.current() {
+        Some(arg) => match utils::parse_username(arg) {
+            Some(id) => UserId(id),
+            None => {
+                msg.reply(&ctx.http, "Use `.rating` or `.rating @user`")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => msg.author.id,
+    };
+
+    let rating = game::read_games(&ctx.data, |store| {
+        store
+            .ratings
+            .get(&guild_id.0)
+            .and_then(|guild| guild.get(&user_id.0))
+            .cloned()
+    })
+    .await;
+    let rating = match rating {
+        Some(rating) => rating,
+        None => {
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "<@{}> hasn't played a rated game here yet. Everyone starts at {}.",
+                    user_id, START_RATING
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut desc = format!(
+        "**{}** after {} games: {} won, {} drawn, {} lost",
+        rating.rating,
+        rating.games(),
+        rating.wins,
+        rating.draws,
+        rating.losses
+    );
+    for change in rating.history.iter().rev().take(HISTORY_SHOWN) {
+        desc.push_str(&format!(
+            "\n<t:{}:d> game #{} vs <@{}>: {} ({:+})",
+            change.at, change.game_id, change.opponent, change.rating, change.change
+        ));
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Server rating");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(format!("<@{}>\n{}", user_id, desc));
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameOptions, Recorded};
+
+    fn game(result: GameResult) -> Game {
+        Game {
+            white: 1,
+            black: 2,
+            result: Some(result),
+            ..GameOptions::default().new_game(Some(10), 20, (1, 2))
+        }
+    }
+
+    #[test]
+    fn expected_scores() {
+        assert_eq!(expected_score(1500, 1500), 0.5);
+        assert!((expected_score(1900, 1500) - 0.909).abs() < 0.001);
+        assert!((expected_score(1500, 1900) + expected_score(1900, 1500) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn new_players_move_fast() {
+        let mut ratings = ServerRatings::new();
+        rate(&mut ratings, &game(GameResult::WhiteWon));
+        let guild = &ratings[&10];
+        assert_eq!(guild[&1].rating, START_RATING + 20);
+        assert_eq!(guild[&2].rating, START_RATING - 20);
+        assert_eq!((guild[&1].wins, guild[&2].losses), (1, 1));
+        assert_eq!(guild[&1].history[0].change, 20);
+    }
+
+    #[test]
+    fn draws_between_equals_change_nothing() {
+        let mut ratings = ServerRatings::new();
+        rate(&mut ratings, &game(GameResult::Draw));
+        let guild = &ratings[&10];
+        assert_eq!(guild[&1].rating, START_RATING);
+        assert_eq!(guild[&2].draws, 1);
+    }
+
+    #[test]
+    fn settled_players_move_slower() {
+        let mut ratings = ServerRatings::new();
+        let settled = Rating {
+            wins: NEW_PLAYER_GAMES,
+            ..Rating::default()
+        };
+        ratings.entry(10).or_default().insert(1, settled.clone());
+        ratings.entry(10).or_default().insert(2, settled);
+        rate(&mut ratings, &game(GameResult::BlackWon));
+        assert_eq!(ratings[&10][&1].rating, START_RATING - 10);
+        assert_eq!(ratings[&10][&2].rating, START_RATING + 10);
+    }
+
+    #[test]
+    fn performances() {
+        assert_eq!(performance(&[]), None);
+        assert_eq!(performance(&[(1500, 1.0)]), Some(1900));
+        assert_eq!(performance(&[(1600, 0.5), (1400, 0.5)]), Some(1500));
+        assert_eq!(
+            performance(&[(1500, 1.0), (1700, 1.0), (1600, 0.0)]),
+            Some(1733)
+        );
+
+        let mut ratings = ServerRatings::new();
+        let first = game(GameResult::WhiteWon);
+        rate(&mut ratings, &first);
+        let second = Game {
+            id: first.id + 1,
+            ..game(GameResult::Draw)
+        };
+        rate(&mut ratings, &second);
+        // Player 2 went into the second game at 1480.
+        assert_eq!(performance_in(&ratings, &[&first, &second], 1), Some(1690));
+        assert_eq!(
+            game_summary(&ratings, &first).as_deref(),
+            Some("⚪ 1520 (+20), performance 1900 · ⚫ 1480 (-20), performance 1100")
+        );
+    }
+
+    #[test]
+    fn what_counts() {
+        assert!(is_rated(&game(GameResult::WhiteWon)));
+        assert!(!is_rated(&game(GameResult::Aborted)));
+        assert!(!is_rated(&Game {
+            guild_id: None,
+            ..game(GameResult::Draw)
+        }));
+        assert!(!is_rated(&Game {
+            casual: true,
+            ..game(GameResult::WhiteWon)
+        }));
+        assert!(!is_rated(&Game {
+            engine_level: Some(3),
+            ..game(GameResult::Draw)
+        }));
+        assert!(!is_rated(&Game {
+            start_fen: Some("4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string()),
+            ..game(GameResult::Draw)
+        }));
+
+        let recorded = |confirmed| Game {
+            recorded: Some(Recorded {
+                by: 3,
+                event: None,
+                confirmed,
+            }),
+            ..game(GameResult::WhiteWon)
+        };
+        assert!(!is_rated(&recorded(false)));
+        assert!(is_rated(&recorded(true)));
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::{Message, Reaction, ReactionType},
+        id::MessageId,
+    },
+    prelude::*,
+};
+use shakmaty::Position;
+
+use crate::{
+    game::{self, Game, MoveError, Rejected},
+    modules::{self, on_off, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    settings,
+};
+
+// The files, then the ranks. A move is four picks: the square a piece is on, then where it goes.
+const FILE_EMOJI: [&str; 8] = ["🇦", "🇧", "🇨", "🇩", "🇪", "🇫", "🇬", "🇭"];
+const RANK_EMOJI: [&str; 8] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣"];
+
+#[group]
+#[commands(reactionmoves)]
+struct ReactionMoves;
+
+pub struct ReactionMovesModule;
+
+const REACTION_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Moves by reaction",
+    command: "reactionmoves",
+    value: |guild| on_off(guild.reaction_moves),
+}];
+
+#[async_trait]
+impl BotModule for ReactionMovesModule {
+    fn name(&self) -> &'static str {
+        "reactionmoves"
+    }
+
+    fn description(&self) -> &'static str {
+        "Moving by reacting to the board with a square's file and rank, see `.reactionmoves`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&REACTIONMOVES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<PicksContainer>(Mutex::new(HashMap::new()));
+    }
+
+    async fn reaction(&self, ctx: &Context, reaction: &Reaction) -> bool {
+        pick(ctx, reaction).await
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        REACTION_SETTINGS
+    }
+}
+
+// What the player to move picked so far on a board, like "e2e", by game.
+struct PicksContainer;
+
+impl TypeMapKey for PicksContainer {
+    type Value = Mutex<HashMap<u32, (MessageId, String)>>;
+}
+
+// The file letter or rank digit a reaction stands for.
+fn coordinate(emoji: &ReactionType) -> Option<char> {
+    let name = match emoji {
+        ReactionType::Unicode(name) => name.as_str(),
+        _ => return None,
+    };
+    if let Some(file) = FILE_EMOJI.iter().position(|&e| e == name) {
+        return Some((b'a' + file as u8) as char);
+    }
+    RANK_EMOJI
+        .iter()
+        .position(|&e| e == name)
+        .map(|rank| (b'1' + rank as u8) as char)
+}
+
+// Add a pick to the ones so far: files go first and third, ranks second and fourth. A file out
+// of turn starts the move over, a rank out of turn is ignored.
+fn add_pick(picks: &mut String, c: char) {
+    let wants_file = picks.len().is_multiple_of(2);
+    if c.is_ascii_alphabetic() == wants_file {
+        picks.push(c);
+    } else if c.is_ascii_alphabetic() {
+        picks.clear();
+        picks.push(c);
+    }
+}
+
+// React to a board with every file and rank, where the server moves that way.
+pub async fn add_reactions(http: &Http, data: &RwLock<TypeMap>, message: &Message, game: &Game) {
+    let guild_id = match game.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    if game.engine_level.is_some()
+        && game.player(game.position().turn()) == crate::bot_id(data).await.0
+    {
+        return;
+    }
+    let on = settings::read(data, |settings| {
+        settings
+            .guilds
+            .get(&guild_id)
+            .is_some_and(|guild| guild.reaction_moves)
+    })
+    .await;
+    if !on || !modules::enabled_in(http, data, message.channel_id, &ReactionMovesModule).await {
+        return;
+    }
+
+    for emoji in FILE_EMOJI.iter().chain(RANK_EMOJI.iter()) {
+        let result = message
+            .react(http, ReactionType::Unicode(emoji.to_string()))
+            .await;
+        if let Err(why) = result {
+            println!("Error adding move reactions: {:?}", why);
+            return;
+        }
+    }
+}
+
+// A file or rank from the player to move. Returns false for reactions that aren't that.
+async fn pick(ctx: &Context, reaction: &Reaction) -> bool {
+    let (c, player) = match (coordinate(&reaction.emoji), reaction.user_id) {
+        (Some(c), Some(player)) => (c, player),
+        _ => return false,
+    };
+    let game_id = game::read_games(&ctx.data, |store| {
+        store
+            .active(reaction.channel_id, player)
+            .filter(|game| game.color_of(player.0) == Some(game.position().turn()))
+            .map(|game| game.id)
+    })
+    .await;
+    let game_id = match game_id {
+        Some(game_id) => game_id,
+        None => return false,
+    };
+
+    let uci = {
+        let data = ctx.data.read().await;
+        let mut picks = data
+            .get::<PicksContainer>()
+            .expect("Expected move picks in typemap.")
+            .lock()
+            .await;
+        let (message_id, so_far) = picks
+            .entry(game_id)
+            .or_insert_with(|| (reaction.message_id, String::new()));
+        // Picks on an older board of the game don't carry over.
+        if *message_id != reaction.message_id {
+            *message_id = reaction.message_id;
+            so_far.clear();
+        }
+        add_pick(so_far, c);
+        if so_far.len() < 4 {
+            return true;
+        }
+        let uci = so_far.clone();
+        picks.remove(&game_id);
+        uci
+    };
+
+    // A pawn picked onto the last rank becomes a queen.
+    let mut played =
+        game::make_move(ctx, reaction.guild_id, reaction.channel_id, player, &uci).await;
+    if let Err(Rejected::Move(MoveError::Illegal)) = played {
+        played = game::make_move(
+            ctx,
+            reaction.guild_id,
+            reaction.channel_id,
+            player,
+            &format!("{}q", uci),
+        )
+        .await;
+    }
+    let result = match played {
+        Ok(game) => match game::send_game(&ctx.http, &ctx.data, reaction.channel_id, &game).await {
+            Ok(()) => game::engine_turn(ctx, reaction.channel_id, &game).await,
+            Err(why) => Err(why),
+        },
+        Err(why) => reaction
+            .channel_id
+            .say(&ctx.http, format!("<@{}> {} ({})", player, why, uci))
+            .await
+            .map(|_| ()),
+    };
+    if let Err(why) = result {
+        println!("Error playing a move from reactions: {:?}", why);
+    }
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Turn moving by reactions on or off. Boards get a reaction for every file and rank, and the player to move picks the square a piece is on, then where it goes: 🇪 2️⃣ 🇪 4️⃣ for e4. To pick one twice, take the reaction away again."
+)]
+#[usage("<on|off>")]
+async fn reactionmoves(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let enabled = match args.current().and_then(settings::parse_toggle) {
+        Some(enabled) => enabled,
+        None => {
+            msg.reply(&ctx.http, "Use `.reactionmoves on` or `.reactionmoves off`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings
+            .guilds
+            .entry(guild_id.0)
+            .or_default()
+            .reaction_moves = enabled;
+    })
+    .await;
+
+    let reply = if enabled {
+        "Boards in this server will have reactions to move with."
+    } else {
+        "Boards in this server won't have move reactions anymore."
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picking_squares() {
+        let mut picks = String::new();
+        for emoji in ["🇪", "2️⃣", "🇪", "4️⃣"] {
+            let c = coordinate(&ReactionType::Unicode(emoji.to_string())).unwrap();
+            add_pick(&mut picks, c);
+        }
+        assert_eq!(picks, "e2e4");
+
+        // A stray rank is left out, a file out of turn starts again.
+        let mut picks = String::new();
+        for c in ['3', 'g', '1', 'b', 'f', '3'] {
+            add_pick(&mut picks, c);
+        }
+        assert_eq!(picks, "f3");
+        assert_eq!(coordinate(&ReactionType::Unicode("👍".to_string())), None);
+    }
+}
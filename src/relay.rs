@@ -0,0 +1,430 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId},
+    },
+    prelude::*,
+};
+
+use crate::{
+    config::Config,
+    game,
+    lichess::{self, BroadcastGame, BroadcastLink, BroadcastRound},
+    modules::{self, BotModule},
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings, web, EMBED_SIDE_COLOR,
+};
+
+// Games listed in a snapshot, the rest are summed up.
+const MAX_LISTED_GAMES: usize = 20;
+
+#[group]
+#[commands(relay)]
+struct Relays;
+
+pub struct RelaysModule;
+
+#[async_trait]
+impl BotModule for RelaysModule {
+    fn name(&self) -> &'static str {
+        "relays"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lichess broadcasts of over the board events followed round by round, see `.relay`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&RELAYS_GROUP)
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::RelayPoll, "* * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::RelayPoll {
+            poll_all(http, data).await;
+        }
+    }
+}
+
+// A channel following a Lichess broadcast.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relay {
+    pub channel_id: u64,
+    pub tour_id: String,
+    pub tour_name: String,
+    // Only this round, for round links. The event's rounds one after another otherwise.
+    pub single_round: bool,
+    // The round being relayed, and the message with its snapshot.
+    pub round_id: Option<String>,
+    pub message_id: Option<u64>,
+    // Games of the round whose results were posted.
+    pub finished_games: Vec<String>,
+    pub done_rounds: Vec<String>,
+}
+
+// "1-0", or "▶ 1:02:13 | 58:40" while it's being played.
+fn game_state(game: &BroadcastGame) -> String {
+    if game.is_over() {
+        return format!("`{}`", game.status.as_deref().unwrap_or("*"));
+    }
+    let clocks: Vec<String> = game
+        .players
+        .iter()
+        .filter_map(|player| player.clock)
+        .map(|clock| game::format_clock(i64::from(clock) * 10))
+        .collect();
+    if clocks.len() == 2 {
+        format!("▶ {}", clocks.join(" | "))
+    } else {
+        "▶".to_string()
+    }
+}
+
+fn pairing(game: &BroadcastGame) -> String {
+    match game.players.as_slice() {
+        [white, black, ..] => format!("{} – {}", white.display_name(), black.display_name()),
+        _ => "Unknown players".to_string(),
+    }
+}
+
+fn game_url(round: &BroadcastRound, game: &BroadcastGame) -> String {
+    format!("{}/{}", round.round.url, game.id)
+}
+
+// The round as it is now, with the top board drawn.
+fn snapshot_embed<'a>(e: &'a mut CreateEmbed, round: &BroadcastRound) -> &'a mut CreateEmbed {
+    let mut lines: Vec<String> = round
+        .games
+        .iter()
+        .take(MAX_LISTED_GAMES)
+        .map(|game| {
+            format!(
+                "{} [{}]({})",
+                game_state(game),
+                pairing(game),
+                game_url(round, game)
+            )
+        })
+        .collect();
+    if round.games.len() > MAX_LISTED_GAMES {
+        lines.push(format!(
+            "…and {} more boards",
+            round.games.len() - MAX_LISTED_GAMES
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("No games yet.".to_string());
+    }
+    let playing = round.games.iter().filter(|game| !game.is_over()).count();
+
+    e.title(format!("{} · {}", round.tour.name, round.round.name));
+    e.url(&round.round.url);
+    e.color(EMBED_SIDE_COLOR);
+    e.description(lines.join("\n"));
+    if let Some((top, fen)) = round
+        .games
+        .iter()
+        .find_map(|game| Some((game, game.fen.as_deref()?)))
+    {
+        e.image(lichess::position_image_url(
+            fen,
+            top.last_move.as_deref(),
+            false,
+        ));
+        e.footer(|f| {
+            if round.round.finished {
+                f.text(format!("Round over · board 1: {}", pairing(top)))
+            } else {
+                f.text(format!(
+                    "{} still playing · board 1: {} · updated every minute",
+                    playing,
+                    pairing(top)
+                ))
+            }
+        });
+    }
+    e
+}
+
+fn summary_embed<'a>(e: &'a mut CreateEmbed, round: &BroadcastRound) -> &'a mut CreateEmbed {
+    let results: Vec<String> = round
+        .games
+        .iter()
+        .map(|game| match game.players.as_slice() {
+            [white, black, ..] => format!(
+                "{} **{}** {}",
+                white.display_name(),
+                game.status.as_deref().unwrap_or("*"),
+                black.display_name()
+            ),
+            _ => format!("**{}**", game.status.as_deref().unwrap_or("*")),
+        })
+        .collect();
+    let mut desc = results.join("\n");
+    if desc.len() > 4000 {
+        desc = format!("See all the results at {}", round.round.url);
+    }
+
+    e.title(format!(
+        "Results · {} · {}",
+        round.tour.name, round.round.name
+    ));
+    e.url(&round.round.url);
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e
+}
+
+// Follow every relay. The scheduler runs this every minute.
+pub async fn poll_all(http: &Http, data: &RwLock<TypeMap>) {
+    let relays = settings::read(data, |settings| settings.relays.clone()).await;
+    if relays.is_empty() {
+        return;
+    }
+    let client = web::client(data).await;
+    for relay in relays {
+        if !modules::enabled_in(http, data, ChannelId(relay.channel_id), &RelaysModule).await {
+            continue;
+        }
+        if let Err(why) = poll(http, data, &client, relay.clone()).await {
+            println!("Error relaying broadcast {}: {:?}", relay.tour_name, why);
+        }
+    }
+}
+
+async fn save(data: &RwLock<TypeMap>, relay: Option<Relay>, channel_id: u64) {
+    settings::update(data, |settings| match relay {
+        Some(relay) => {
+            let stored = settings
+                .relays
+                .iter_mut()
+                .find(|stored| stored.channel_id == channel_id);
+            // Left alone if it was stopped in the meantime.
+            if let Some(stored) = stored {
+                *stored = relay;
+            }
+        }
+        None => settings
+            .relays
+            .retain(|stored| stored.channel_id != channel_id),
+    })
+    .await;
+}
+
+async fn poll(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    client: &reqwest::Client,
+    mut relay: Relay,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let channel_id = ChannelId(relay.channel_id);
+    let round_id = match &relay.round_id {
+        Some(round_id) => round_id.clone(),
+        None => {
+            let broadcast = lichess::broadcast(client, &relay.tour_id)
+                .await?
+                .ok_or("the broadcast is gone")?;
+            let next = broadcast
+                .rounds
+                .iter()
+                .find(|round| round.ongoing && !relay.done_rounds.contains(&round.id));
+            match next {
+                Some(round) => round.id.clone(),
+                None if broadcast.rounds.iter().all(|round| round.finished) => {
+                    channel_id
+                        .say(
+                            http,
+                            format!("That was the last round of {}.", relay.tour_name),
+                        )
+                        .await?;
+                    save(data, None, relay.channel_id).await;
+                    return Ok(());
+                }
+                // Waiting for the next round to start.
+                None => return Ok(()),
+            }
+        }
+    };
+    let round = lichess::broadcast_round(client, &round_id)
+        .await?
+        .ok_or("the round is gone")?;
+
+    // A relay joining in the middle of a round doesn't post the results it missed.
+    let fresh = relay.message_id.is_none();
+    for game in round.games.iter().filter(|game| game.is_over()) {
+        if relay.finished_games.contains(&game.id) {
+            continue;
+        }
+        relay.finished_games.push(game.id.clone());
+        if fresh {
+            continue;
+        }
+        channel_id
+            .say(
+                http,
+                format!(
+                    "**{}** {} · <{}>",
+                    game.status.as_deref().unwrap_or("*"),
+                    pairing(game),
+                    game_url(&round, game)
+                ),
+            )
+            .await?;
+    }
+
+    let edited = match relay.message_id {
+        Some(message_id) => channel_id
+            .edit_message(http, MessageId(message_id), |m| {
+                m.embed(|e| snapshot_embed(e, &round))
+            })
+            .await
+            .is_ok(),
+        None => false,
+    };
+    // Posted again if it was deleted.
+    if !edited {
+        let sent = channel_id
+            .send_message(http, |m| m.embed(|e| snapshot_embed(e, &round)))
+            .await?;
+        relay.message_id = Some(sent.id.0);
+    }
+    relay.round_id = Some(round_id.clone());
+
+    if round.round.finished {
+        channel_id
+            .send_message(http, |m| m.embed(|e| summary_embed(e, &round)))
+            .await?;
+        if relay.single_round {
+            save(data, None, relay.channel_id).await;
+            return Ok(());
+        }
+        relay.done_rounds.push(round_id);
+        relay.round_id = None;
+        relay.message_id = None;
+        relay.finished_games.clear();
+    }
+
+    let channel = relay.channel_id;
+    save(data, Some(relay), channel).await;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Follow a Lichess broadcast in this channel: a snapshot of the boards kept up to date every minute, each result as it comes in and a summary when the round ends. An event link follows all its rounds, a round link just that one."
+)]
+#[usage("<broadcast link | stop>")]
+#[example("https://lichess.org/broadcast/fide-world-championship-2024/0lwkcSxY")]
+async fn relay(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let channel_id = msg.channel_id.0;
+    let arg = args.rest().trim().trim_matches(|c| c == '<' || c == '>');
+    let current = settings::read(&ctx.data, |settings| {
+        settings
+            .relays
+            .iter()
+            .find(|relay| relay.channel_id == channel_id)
+            .cloned()
+    })
+    .await;
+
+    if arg.is_empty() || arg.eq_ignore_ascii_case("stop") {
+        let reply = match (current, arg.is_empty()) {
+            (Some(relay), true) => format!(
+                "This channel follows {}. `.relay stop` stops it.",
+                relay.tour_name
+            ),
+            (Some(relay), false) => {
+                save(&ctx.data, None, channel_id).await;
+                format!("Stopped following {}.", relay.tour_name)
+            }
+            (None, _) => "This channel doesn't follow a broadcast, use `.relay <broadcast link>`."
+                .to_string(),
+        };
+        msg.reply(&ctx.http, reply).await?;
+        return Ok(());
+    }
+
+    if let Some(relay) = current {
+        msg.reply(
+            &ctx.http,
+            format!(
+                "This channel follows {} already, `.relay stop` first.",
+                relay.tour_name
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let client = web::client(&ctx.data).await;
+    let found = match lichess::broadcast_from_url(arg) {
+        Some(BroadcastLink::Tour(id)) => lichess::broadcast(&client, &id)
+            .await
+            .map(|broadcast| broadcast.map(|broadcast| (broadcast.tour, None))),
+        Some(BroadcastLink::Round(id)) => lichess::broadcast_round(&client, &id)
+            .await
+            .map(|round| round.map(|round| (round.tour, Some(round.round)))),
+        None => {
+            msg.reply(
+                &ctx.http,
+                "That doesn't look like a Lichess broadcast link.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let (tour, round) = match found {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            msg.reply(&ctx.http, "I couldn't find that broadcast on Lichess.")
+                .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting a Lichess broadcast: {:?}", why);
+            msg.reply(&ctx.http, "I couldn't reach Lichess right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let reply = match &round {
+        Some(round) => format!(
+            "Following {} of {} here, the boards show up within a minute.",
+            round.name, tour.name
+        ),
+        None => format!("Following {} here, each round as it starts.", tour.name),
+    };
+    settings::update(&ctx.data, |settings| {
+        settings.relays.push(Relay {
+            channel_id,
+            tour_id: tour.id,
+            tour_name: tour.name,
+            single_round: round.is_some(),
+            round_id: round.map(|round| round.id),
+            message_id: None,
+            finished_games: Vec::new(),
+            done_rounds: Vec::new(),
+        });
+    })
+    .await;
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{Board, Color, File, Piece, Rank, Role, Square};
+
+// A guild's emoji for each piece (keyed by FEN letter, "K" or "q") and for the "light" and
+// "dark" squares.
+pub type EmojiSet = HashMap<String, String>;
+
+// Keys an emoji set needs before the emoji theme can use it.
+pub const EMOJI_KEYS: [&str; 14] = [
+    "K", "Q", "R", "B", "N", "P", "k", "q", "r", "b", "n", "p", "light", "dark",
+];
+
+const RANK_EMOJI: [&str; 8] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣"];
+const FILE_EMOJI: [&str; 8] = ["🇦", "🇧", "🇨", "🇩", "🇪", "🇫", "🇬", "🇭"];
+
+// How pieces are drawn on text diagrams.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    // ♔ ♕ ♖ glyphs.
+    Unicode,
+    // K Q R for white, k q r for black. Lines up in every font.
+    Letters,
+    // The server's own emoji pieces, see `.emojiset`.
+    Emoji,
+    // A PNG picture of the board attached to the message. Where the bot can't attach files,
+    // like edited replays, the console or channels without the permission, boards are drawn
+    // with the server's emoji or Unicode pieces instead.
+    Image,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Image, Theme::Unicode, Theme::Letters, Theme::Emoji];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Unicode => "unicode",
+            Theme::Letters => "letters",
+            Theme::Emoji => "emoji",
+            Theme::Image => "image",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Theme> {
+        Theme::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.name().eq_ignore_ascii_case(name))
+    }
+
+    fn piece_char(self, piece: Piece) -> char {
+        match self {
+            Theme::Letters => piece.char(),
+            Theme::Unicode | Theme::Emoji | Theme::Image => piece.unicode_char(),
+        }
+    }
+
+    fn empty_char(self) -> char {
+        match self {
+            Theme::Letters => '.',
+            Theme::Unicode | Theme::Emoji | Theme::Image => '·',
+        }
+    }
+}
+
+// Everything that decides how a board is shown to someone.
+#[derive(Debug, Clone)]
+pub struct BoardStyle {
+    pub theme: Theme,
+    pub coordinates: bool,
+    // Describe the position in words instead of drawing it.
+    pub screen_reader: bool,
+    // The guild's emoji pieces, for the emoji theme.
+    pub emoji: Option<EmojiSet>,
+}
+
+impl Default for BoardStyle {
+    fn default() -> Self {
+        BoardStyle {
+            theme: Theme::Image,
+            coordinates: true,
+            screen_reader: false,
+            emoji: None,
+        }
+    }
+}
+
+impl BoardStyle {
+    // Whether boards should be sent as a picture. Screen reader mode always gets words.
+    pub fn wants_image(&self) -> bool {
+        self.theme == Theme::Image && !self.screen_reader
+    }
+}
+
+// Whether an emoji set has everything needed to draw a board.
+pub fn emoji_set_complete(set: &EmojiSet) -> bool {
+    EMOJI_KEYS.iter().all(|key| set.contains_key(*key))
+}
+
+// Render a board with a guild's emoji, outside of a code block.
+pub fn emoji_board(board: &Board, set: &EmojiSet, coordinates: bool) -> String {
+    let mut rows = Vec::new();
+
+    for &rank in Rank::ALL.iter().rev() {
+        let mut row = String::new();
+        if coordinates {
+            row.push_str(RANK_EMOJI[rank.to_usize()]);
+        }
+        for &file in File::ALL.iter() {
+            let sq = Square::from_coords(file, rank);
+            let key = match board.piece_at(sq) {
+                Some(piece) => piece.char().to_string(),
+                None if sq.is_light() => "light".to_string(),
+                None => "dark".to_string(),
+            };
+            row.push_str(&set[&key]);
+        }
+        rows.push(row);
+    }
+    if coordinates {
+        rows.push(format!("⬛{}", FILE_EMOJI.concat()));
+    }
+
+    rows.join("\n")
+}
+
+// Render a board as a text diagram from white's side, ready to go in a code block.
+pub fn text_board(board: &Board, style: &BoardStyle) -> String {
+    let mut out = String::new();
+
+    for &rank in Rank::ALL.iter().rev() {
+        if style.coordinates {
+            out.push(rank.char());
+            out.push(' ');
+        }
+        let squares: Vec<String> = File::ALL
+            .iter()
+            .map(
+                |&file| match board.piece_at(Square::from_coords(file, rank)) {
+                    Some(piece) => style.theme.piece_char(piece).to_string(),
+                    None => style.theme.empty_char().to_string(),
+                },
+            )
+            .collect();
+        out.push_str(&squares.join(" "));
+        out.push('\n');
+    }
+    if style.coordinates {
+        out.push_str("  a b c d e f g h");
+    }
+
+    out.trim_end().to_string()
+}
+
+// Pieces are listed biggest first, pawns last, like "White: Ke1, Qd1, pawns a2 b2".
+const DESCRIBE_ORDER: [Role; 5] = [
+    Role::King,
+    Role::Queen,
+    Role::Rook,
+    Role::Bishop,
+    Role::Knight,
+];
+
+// Describe a board in plain text for screen readers.
+pub fn describe_board(board: &Board) -> String {
+    Color::ALL
+        .iter()
+        .map(|&color| {
+            let mut pieces: Vec<String> = Vec::new();
+            for &role in DESCRIBE_ORDER.iter() {
+                for sq in board.by_piece(role.of(color)) {
+                    pieces.push(format!("{}{}", role.upper_char(), sq));
+                }
+            }
+
+            let pawns: Vec<String> = board
+                .by_piece(Role::Pawn.of(color))
+                .into_iter()
+                .map(|sq| sq.to_string())
+                .collect();
+            if !pawns.is_empty() {
+                pieces.push(format!("pawns {}", pawns.join(" ")));
+            }
+
+            let name = if color == Color::White {
+                "White"
+            } else {
+                "Black"
+            };
+            if pieces.is_empty() {
+                format!("{}: no pieces", name)
+            } else {
+                format!("{}: {}", name, pieces.join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// How a board should be shown to someone: a diagram in a code block, or a description when
+// they use screen reader mode.
+pub fn board_for(board: &Board, style: &BoardStyle) -> String {
+    if style.screen_reader {
+        return describe_board(board);
+    }
+
+    // Without a full emoji set the emoji theme falls back to Unicode pieces. Pictures that
+    // can't be sent use the server's emoji when it has them.
+    match (&style.theme, &style.emoji) {
+        (Theme::Emoji | Theme::Image, Some(set)) if emoji_set_complete(set) => {
+            emoji_board(board, set, style.coordinates)
+        }
+        _ => format!("```\n{}\n```", text_board(board, style)),
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{self, BufRead, Write};
+
+use chrono::Utc;
+use shakmaty::Position;
+
+use crate::{
+    chess960,
+    config::Config,
+    content::Content,
+    fen,
+    notation::Notation,
+    pgn::{self, PgnGame},
+    random_index,
+    render::{self, BoardStyle, Theme},
+    scheduler::Cron,
+    settings,
+};
+
+const HELP: &str = "\
+Commands work like in Discord, without a token or a connection:
+  .fen <fen>              draw a position
+  .960 [number]           a Fischer Random start position
+  .pgn <movetext>         read a game and list its moves
+  .8ball <question>       ask the 8-ball
+  .memes                  list the meme commands, then run them like .bongcloud
+  .cron <expression>      when a job schedule runs next
+  .theme <theme>          unicode, letters or emoji
+  .coordinates <on|off>
+  .screenreader <on|off>
+  .notation <language>    piece letters for reading and writing moves
+  .quit
+Anything else is treated like a chat message, so FENs and movetext are picked up.";
+
+// Board and move preferences for the session, like a user's settings.
+struct Session {
+    content: Content,
+    style: BoardStyle,
+    notation: Notation,
+}
+
+fn show_game(session: &Session, game: &PgnGame) -> String {
+    // "1. e4 e5 2. Nf3", only numbering black's moves when the game starts with one.
+    let moves = (0..game.ply_count())
+        .filter_map(|ply| {
+            let numbered = game.numbered_move(None, ply, session.notation)?;
+            Some(match numbered.split_once("... ") {
+                Some((_, san)) if ply > 0 => san.to_string(),
+                _ => numbered,
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut out = format!(
+        "{}\n{} moves, {}",
+        game.players().unwrap_or_else(|| "Game".to_string()),
+        game.ply_count().div_ceil(2),
+        game.result()
+    );
+    if let Some(opening) = game.opening() {
+        out.push_str(&format!("\n{}", opening));
+    }
+    if !game.variations.is_empty() {
+        out.push_str(&format!("\n{} sidelines", game.variations.len()));
+    }
+    out.push_str(&format!(
+        "\n{}\n{}",
+        moves,
+        render::board_for(
+            game.position_after(game.ply_count()).board(),
+            &session.style
+        )
+    ));
+    out
+}
+
+fn toggle(arg: &str, name: &str, value: &mut bool) -> String {
+    match settings::parse_toggle(arg) {
+        Some(on) => {
+            *value = on;
+            format!("{} {}", name, if on { "on" } else { "off" })
+        }
+        None => format!("Use .{} on or .{} off", name, name),
+    }
+}
+
+// What the bot would reply to a line, or None if it would stay quiet.
+fn respond(session: &mut Session, line: &str) -> Option<String> {
+    let (command, args) = match line.strip_prefix('.') {
+        Some(rest) => {
+            let (command, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            (command.to_lowercase(), args.trim())
+        }
+        None => {
+            // Plain chat: reply the way the detectors would.
+            if let Some((_, pos)) = fen::find_fen(line) {
+                return Some(render::board_for(pos.board(), &session.style));
+            }
+            return pgn::find_movetext(line, session.notation)
+                .map(|game| show_game(session, &game));
+        }
+    };
+
+    let reply = match command.as_str() {
+        "help" => HELP.to_string(),
+        "fen" => match fen::parse_position(args) {
+            Some(pos) => render::board_for(pos.board(), &session.style),
+            None => "That isn't a valid FEN.".to_string(),
+        },
+        "960" | "chess960" | "fischerandom" => {
+            let number = match args {
+                "" => chess960::random_number(),
+                arg => match arg.parse::<u32>() {
+                    Ok(number) if number < chess960::POSITION_COUNT => number,
+                    _ => return Some("Pick a number from 0 to 959.".to_string()),
+                },
+            };
+            let pos = chess960::start_position(number);
+            format!(
+                "Chess960 #{} {}\n{}",
+                number,
+                chess960::back_rank(&pos),
+                render::board_for(pos.board(), &session.style)
+            )
+        }
+        "pgn" => match pgn::parse_in(args, session.notation) {
+            Ok(game) => show_game(session, &game),
+            Err(why) => format!("Couldn't read that game, {}.", why),
+        },
+        "8ball" => {
+            let answers = session.content.eightball_answers(None);
+            match answers.len() {
+                0 => "The 8-ball has nothing to say.".to_string(),
+                n => answers[random_index(n)].to_string(),
+            }
+        }
+        "memes" => session.content.meme_names(None).join(", "),
+        "cron" => match Cron::parse(args) {
+            Ok(cron) => {
+                let mut next = Utc::now().naive_utc();
+                let mut runs = Vec::new();
+                for _ in 0..3 {
+                    match cron.next_after(next) {
+                        Some(run) => {
+                            runs.push(format!("{} UTC", run.format("%Y-%m-%d %H:%M")));
+                            next = run;
+                        }
+                        None => break,
+                    }
+                }
+                if runs.is_empty() {
+                    "That schedule never comes up.".to_string()
+                } else {
+                    runs.join("\n")
+                }
+            }
+            Err(why) => why.to_string(),
+        },
+        "theme" => match Theme::from_name(args) {
+            Some(theme) => {
+                session.style.theme = theme;
+                format!("theme {}", theme.name())
+            }
+            None => "Use .theme unicode, .theme letters or .theme emoji".to_string(),
+        },
+        "coordinates" => toggle(args, "coordinates", &mut session.style.coordinates),
+        "screenreader" => toggle(args, "screenreader", &mut session.style.screen_reader),
+        "notation" => match Notation::from_name(args) {
+            Some(notation) => {
+                session.notation = notation;
+                format!("moves are written like {}", notation.localize("Nf3"))
+            }
+            None => "Use .notation en, de, fr, es, it or nl".to_string(),
+        },
+        name => match session.content.meme_responses(None, name) {
+            Some(responses) if !responses.is_empty() => {
+                responses[random_index(responses.len())].clone()
+            }
+            _ => format!("The `.{}` command doesn't work offline.", name),
+        },
+    };
+
+    Some(reply)
+}
+
+// Read commands from stdin and print what the bot would say, for trying things locally.
+pub fn run() {
+    let config = Config::load();
+    let mut session = Session {
+        content: Content::load(&config.content_file),
+        style: BoardStyle::default(),
+        notation: Notation::default(),
+    };
+
+    println!("cute-chess-bot offline, .help for commands");
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim();
+        if line == ".quit" || line == ".exit" {
+            break;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(reply) = respond(&mut session, line) {
+            println!("{}", reply);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{HashMap, VecDeque};
+
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    model::{
+        channel::Message,
+        id::MessageId,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+
+use shakmaty::Position;
+
+use crate::{
+    modules::BotModule,
+    notation::Notation,
+    pgn::PgnGame,
+    render::{self, BoardStyle},
+    settings, EMBED_SIDE_COLOR,
+};
+
+// Old replays are forgotten once there are more than this many.
+const MAX_REPLAYS: usize = 200;
+
+const OPEN_ID: &str = "replay:open";
+const FIRST_ID: &str = "replay:first";
+const PREV_ID: &str = "replay:prev";
+const NEXT_ID: &str = "replay:next";
+const LAST_ID: &str = "replay:last";
+const MAINLINE_ID: &str = "replay:mainline";
+const BRANCH_ID: &str = "replay:branch";
+
+// Bigger PGN files than this aren't worth downloading, a single game is a few kilobytes.
+const MAX_PGN_BYTES: u64 = 256 * 1024;
+
+// The value of the branch menu option that stays on the current line.
+const CONTINUE_VALUE: &str = "continue";
+
+// A game someone can step through, attached to a bot message.
+pub struct Replay {
+    pub game: PgnGame,
+    // The sideline being looked at, None for the mainline.
+    pub line: Option<usize>,
+    pub ply: usize,
+    pub style: BoardStyle,
+    pub notation: Notation,
+}
+
+#[derive(Default)]
+pub struct ReplayStore {
+    replays: HashMap<MessageId, Replay>,
+    order: VecDeque<MessageId>,
+}
+
+impl ReplayStore {
+    pub fn insert(&mut self, message_id: MessageId, replay: Replay) {
+        self.replays.insert(message_id, replay);
+        self.order.push_back(message_id);
+        while self.order.len() > MAX_REPLAYS {
+            if let Some(old) = self.order.pop_front() {
+                self.replays.remove(&old);
+            }
+        }
+    }
+}
+
+pub struct ReplayContainer;
+
+impl TypeMapKey for ReplayContainer {
+    type Value = Mutex<ReplayStore>;
+}
+
+pub struct ReplaysModule;
+
+#[async_trait]
+impl BotModule for ReplaysModule {
+    fn name(&self) -> &'static str {
+        "replays"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stepping through games pasted or uploaded in chat."
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<ReplayContainer>(Mutex::default());
+    }
+
+    async fn normal_message(&self, ctx: &Context, msg: &Message) {
+        offer_replay(ctx, msg).await;
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// Fill in the embed for a replay at its current move.
+pub fn replay_embed<'a>(e: &'a mut CreateEmbed, replay: &Replay) -> &'a mut CreateEmbed {
+    let game = &replay.game;
+    let pos = game.position_in(replay.line, replay.ply);
+
+    let last_move = match (replay.ply, replay.line) {
+        (0, None) => "Starting position".to_string(),
+        (0, Some(_)) => "Start of the sideline".to_string(),
+        (ply, line) => game
+            .numbered_move(line, ply - 1, replay.notation)
+            .expect("ply is within the line"),
+    };
+
+    let mut desc = render::board_for(pos.board(), &replay.style);
+    if replay.line.is_some() {
+        let path = game
+            .line_path(replay.line)
+            .into_iter()
+            .map(|index| {
+                game.numbered_move(Some(index), 0, replay.notation)
+                    .expect("sidelines have moves")
+            })
+            .collect::<Vec<_>>();
+        desc.push_str(&format!("\nMainline › {}", path.join(" › ")));
+    }
+    desc.push_str(&format!("\n**{}**", last_move));
+
+    e.title(game.players().unwrap_or_else(|| "Game replay".to_string()));
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+
+    let mut footer = format!(
+        "Move {} of {} · {}",
+        replay.ply,
+        game.line_len(replay.line),
+        game.result()
+    );
+    if let Some(opening) = game.opening() {
+        footer.push_str(&format!(" · {}", opening));
+    }
+    e.footer(|f| {
+        f.text(footer);
+        f
+    });
+    e
+}
+
+pub fn replay_buttons<'a>(
+    c: &'a mut CreateComponents,
+    replay: &Replay,
+) -> &'a mut CreateComponents {
+    let game = &replay.game;
+    let at_start = replay.ply == 0;
+    let at_end = replay.ply == game.line_len(replay.line);
+
+    c.create_action_row(|row| {
+        for (id, label, disabled) in [
+            (FIRST_ID, "⏮", at_start),
+            (PREV_ID, "◀", at_start),
+            (NEXT_ID, "▶", at_end),
+            (LAST_ID, "⏭", at_end),
+        ] {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Secondary);
+                b.label(label);
+                b.custom_id(id);
+                b.disabled(disabled);
+                b
+            });
+        }
+        if replay.line.is_some() {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Primary);
+                b.label("Back to mainline");
+                b.custom_id(MAINLINE_ID);
+                b
+            });
+        }
+        row
+    });
+
+    // Offer the sidelines that branch off the next move.
+    let branches = game.branches_at(replay.line, replay.ply);
+    if !branches.is_empty() {
+        c.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id(BRANCH_ID);
+                menu.placeholder("Sidelines from here");
+                menu.options(|options| {
+                    if let Some(next) = game.numbered_move(replay.line, replay.ply, replay.notation)
+                    {
+                        options.create_option(|o| {
+                            o.label(format!("Continue with {}", next));
+                            o.value(CONTINUE_VALUE);
+                            o
+                        });
+                    }
+                    // Discord only shows 25 options.
+                    for &index in branches.iter().take(24) {
+                        let first = game
+                            .numbered_move(Some(index), 0, replay.notation)
+                            .expect("sidelines have moves");
+                        options.create_option(|o| {
+                            o.label(first);
+                            o.value(index);
+                            o
+                        });
+                    }
+                    options
+                })
+            })
+        });
+    }
+    c
+}
+
+// A game from an attached .pgn file, if the message has one.
+async fn attached_game(msg: &Message, notation: Notation) -> Option<PgnGame> {
+    let attachment = msg.attachments.iter().find(|attachment| {
+        attachment.filename.to_lowercase().ends_with(".pgn") && attachment.size <= MAX_PGN_BYTES
+    })?;
+    let bytes = match attachment.download().await {
+        Ok(bytes) => bytes,
+        Err(why) => {
+            println!("Error downloading a PGN: {:?}", why);
+            return None;
+        }
+    };
+    crate::pgn::parse_in(&String::from_utf8_lossy(&bytes), notation)
+        .ok()
+        .filter(|game| game.ply_count() > 0)
+}
+
+// Offer to turn PGN pasted in chat or uploaded as a .pgn file into a replay.
+pub async fn offer_replay(ctx: &Context, msg: &Message) {
+    let notation = match msg.guild_id {
+        Some(guild_id) => settings::guild(&ctx.data, guild_id)
+            .await
+            .notation
+            .unwrap_or_default(),
+        None => Notation::default(),
+    };
+    let game = match crate::pgn::find_movetext(&msg.content, notation) {
+        Some(game) => game,
+        None => match attached_game(msg, notation).await {
+            Some(game) => game,
+            None => return,
+        },
+    };
+
+    let mut summary = format!("{} moves, {}", game.ply_count().div_ceil(2), game.result());
+    if let Some(opening) = game.opening() {
+        summary.push_str(&format!("\n{}", opening));
+    }
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("That looks like a game!");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(summary);
+                e
+            });
+            m.components(|c| {
+                c.create_action_row(|row| {
+                    row.create_button(|b| {
+                        b.style(ButtonStyle::Primary);
+                        b.label("Replay it");
+                        b.custom_id(OPEN_ID);
+                        b
+                    })
+                })
+            });
+            m.reference_message(msg);
+            m
+        })
+        .await;
+
+    let sent = match sent {
+        Ok(sent) => sent,
+        Err(why) => {
+            println!("Error offering a replay: {:?}", why);
+            return;
+        }
+    };
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    start_replay(ctx, sent.id, game, style, notation).await;
+}
+
+// Keep track of a replay attached to a message, starting from its first move.
+pub async fn start_replay(
+    ctx: &Context,
+    message_id: MessageId,
+    game: PgnGame,
+    style: BoardStyle,
+    notation: Notation,
+) {
+    let data = ctx.data.read().await;
+    let mut store = data
+        .get::<ReplayContainer>()
+        .expect("Expected replays in typemap.")
+        .lock()
+        .await;
+    store.insert(
+        message_id,
+        Replay {
+            game,
+            line: None,
+            ply: 0,
+            style,
+            notation,
+        },
+    );
+}
+
+// Step a replay when one of its buttons is pressed. Returns false if the button isn't ours.
+pub async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if ![
+        OPEN_ID,
+        FIRST_ID,
+        PREV_ID,
+        NEXT_ID,
+        LAST_ID,
+        MAINLINE_ID,
+        BRANCH_ID,
+    ]
+    .contains(&id)
+    {
+        return false;
+    }
+
+    let data = ctx.data.read().await;
+    let mut store = data
+        .get::<ReplayContainer>()
+        .expect("Expected replays in typemap.")
+        .lock()
+        .await;
+
+    let result = match store.replays.get_mut(&component.message.id) {
+        Some(replay) => {
+            let total = replay.game.line_len(replay.line);
+            match id {
+                FIRST_ID => replay.ply = 0,
+                PREV_ID => replay.ply = replay.ply.saturating_sub(1),
+                NEXT_ID => replay.ply = (replay.ply + 1).min(total),
+                LAST_ID => replay.ply = total,
+                MAINLINE_ID => {
+                    if let Some(&outermost) = replay.game.line_path(replay.line).first() {
+                        replay.ply = replay.game.variations[outermost].branch_ply;
+                        replay.line = None;
+                    }
+                }
+                BRANCH_ID => {
+                    let choice = component.data.values.first().map(String::as_str);
+                    let branch = choice.and_then(|value| value.parse::<usize>().ok());
+                    match branch {
+                        Some(index)
+                            if replay
+                                .game
+                                .branches_at(replay.line, replay.ply)
+                                .contains(&index) =>
+                        {
+                            replay.line = Some(index);
+                            replay.ply = 1;
+                        }
+                        _ if choice == Some(CONTINUE_VALUE) => {
+                            replay.ply = (replay.ply + 1).min(total)
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+
+            component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage);
+                    r.interaction_response_data(|d| {
+                        d.create_embed(|e| replay_embed(e, replay));
+                        d.components(|c| replay_buttons(c, replay));
+                        d
+                    })
+                })
+                .await
+        }
+        None => {
+            component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage);
+                    r.interaction_response_data(|d| {
+                        d.content("This replay has expired, paste the game again to replay it.");
+                        d.components(|c| c)
+                    })
+                })
+                .await
+        }
+    };
+
+    if let Err(why) = result {
+        println!("Error updating replay: {:?}", why);
+    }
+
+    true
+}
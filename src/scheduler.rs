@@ -0,0 +1,585 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use chrono::{Datelike, NaiveDateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{channel::Message, id::ChannelId},
+    prelude::*,
+};
+
+use crate::{
+    config,
+    modules::{self, BotModule},
+    settings, timezone, EMBED_SIDE_COLOR,
+};
+
+// Jobs are checked at least this often, so changes from `.jobs` are picked up quickly.
+const MAX_SLEEP_SECS: u64 = 60;
+
+// Give up looking for the next run after this many days, e.g. for "0 0 31 2 *".
+const MAX_LOOKAHEAD_DAYS: i64 = 4 * 366;
+
+#[group]
+#[commands(jobs)]
+struct Scheduler;
+
+pub struct SchedulerModule;
+
+impl BotModule for SchedulerModule {
+    fn name(&self) -> &'static str {
+        "scheduler"
+    }
+
+    fn description(&self) -> &'static str {
+        "Everything the bot does on a timer, see `.jobs`."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&SCHEDULER_GROUP)
+    }
+}
+
+// Everything the bot does on a timer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    // Post the position of the day, see potd.rs.
+    DailyPosition,
+    // Post the Lichess daily puzzle, see puzzle.rs.
+    DailyPuzzle,
+    // Remind members who asked for it that they haven't solved the daily puzzle, see puzzle.rs.
+    PuzzleReminder,
+    // Check followed players for new games, see follow.rs.
+    FollowPoll,
+    // Download the content from `content_url` again, see content.rs.
+    ContentRefresh,
+    // Upload a backup of the settings, see backup.rs.
+    Backup,
+    // Remind correspondence players and end games that ran out of time, see game.rs. Also
+    // catches up on vote chess polls left open over a restart.
+    GameDeadlines,
+    // Announce the tournaments of servers' Lichess teams, see tournaments.rs.
+    TournamentPoll,
+    // Check whether members who asked for it started a game, see playing.rs.
+    PlayingPoll,
+    // Update the Lichess broadcasts channels follow, see relay.rs.
+    RelayPoll,
+    // Rank the members of servers' Lichess teams and chess.com clubs again, see clubboard.rs.
+    ClubBoardSync,
+    // Post the day's digest in servers where it's evening, see digest.rs.
+    DailyDigest,
+    // Delete the messages auto-delete was still waiting on when the bot restarted, see
+    // moderation.rs.
+    DeletionSweep,
+}
+
+impl JobKind {
+    fn name(self) -> &'static str {
+        match self {
+            JobKind::DailyPosition => "daily position",
+            JobKind::DailyPuzzle => "daily puzzle",
+            JobKind::PuzzleReminder => "puzzle reminder",
+            JobKind::FollowPoll => "follow poll",
+            JobKind::ContentRefresh => "content refresh",
+            JobKind::Backup => "backup",
+            JobKind::GameDeadlines => "game deadlines",
+            JobKind::TournamentPoll => "tournament poll",
+            JobKind::PlayingPoll => "playing poll",
+            JobKind::RelayPoll => "relay poll",
+            JobKind::ClubBoardSync => "club leaderboard sync",
+            JobKind::DailyDigest => "daily digest",
+            JobKind::DeletionSweep => "deletion sweep",
+        }
+    }
+}
+
+// A job and when it runs next, saved in the settings so restarts don't lose track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u32,
+    pub kind: JobKind,
+    // Cron expression: minute, hour, day of month, month and day of week.
+    pub schedule: String,
+    // Seconds since the epoch. None if the job was cancelled.
+    pub next_run: Option<i64>,
+}
+
+// One cron field as a bit set of allowed values.
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    allowed: u64,
+    any: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32) -> Option<Field> {
+        let mut allowed = 0u64;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                    None => {
+                        let value = range.parse().ok()?;
+                        // "5/15" means every 15 starting at 5.
+                        (value, if part.contains('/') { max } else { value })
+                    }
+                },
+            };
+            if start < min || end > max || start > end {
+                return None;
+            }
+            for value in (start..=end).step_by(step as usize) {
+                allowed |= 1 << value;
+            }
+        }
+
+        Some(Field {
+            allowed,
+            any: text.starts_with('*'),
+        })
+    }
+
+    fn matches(self, value: u32) -> bool {
+        self.allowed & (1 << value) != 0
+    }
+}
+
+// A parsed cron expression, like "0 9 * * *" for every day at 9:00.
+#[derive(Debug, Clone, Copy)]
+pub struct Cron {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+#[derive(Debug)]
+pub struct CronError;
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "schedules are five cron fields: minute, hour, day of month, month and day of week"
+        )
+    }
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Cron, CronError> {
+        let fields = text.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(CronError);
+        }
+
+        let mut weekdays = Field::parse(fields[4], 0, 7).ok_or(CronError)?;
+        // Both 0 and 7 are Sunday.
+        if weekdays.matches(7) {
+            weekdays.allowed |= 1;
+        }
+
+        Ok(Cron {
+            minutes: Field::parse(fields[0], 0, 59).ok_or(CronError)?,
+            hours: Field::parse(fields[1], 0, 23).ok_or(CronError)?,
+            days: Field::parse(fields[2], 1, 31).ok_or(CronError)?,
+            months: Field::parse(fields[3], 1, 12).ok_or(CronError)?,
+            weekdays,
+        })
+    }
+
+    fn matches_day(&self, time: NaiveDateTime) -> bool {
+        let day = self.days.matches(time.day());
+        let weekday = self.weekdays.matches(time.weekday().num_days_from_sunday());
+        // Like cron, a restricted day of month and day of week means either will do.
+        let day_ok = match (self.days.any, self.weekdays.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day_ok && self.months.matches(time.month())
+    }
+
+    // The first matching minute after `after`, on the wall clock.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.date().and_hms_opt(after.hour(), after.minute(), 0)?
+            + chrono::Duration::minutes(1);
+
+        for day in 0..MAX_LOOKAHEAD_DAYS {
+            let date = start.date() + chrono::Duration::days(day);
+            let midnight = date.and_hms_opt(0, 0, 0)?;
+            if !self.matches_day(midnight) {
+                continue;
+            }
+            for hour in (0..24).filter(|h| self.hours.matches(*h)) {
+                for minute in (0..60).filter(|m| self.minutes.matches(*m)) {
+                    let time = date.and_hms_opt(hour, minute, 0)?;
+                    if time >= start {
+                        return Some(time);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// The timezone a job's schedule is read in.
+async fn job_timezone(http: &Http, data: &RwLock<TypeMap>, kind: JobKind) -> Tz {
+    match kind {
+        JobKind::DailyPosition => match config::get(data).await.daily_position.channel {
+            Some(channel) => timezone::for_channel(http, data, ChannelId(channel)).await,
+            None => Tz::UTC,
+        },
+        JobKind::DailyPuzzle => match config::get(data).await.daily_puzzle.channel {
+            Some(channel) => timezone::for_channel(http, data, ChannelId(channel)).await,
+            None => Tz::UTC,
+        },
+        JobKind::PuzzleReminder
+        | JobKind::FollowPoll
+        | JobKind::ContentRefresh
+        | JobKind::Backup
+        | JobKind::GameDeadlines
+        | JobKind::TournamentPoll
+        | JobKind::PlayingPoll
+        | JobKind::RelayPoll
+        | JobKind::ClubBoardSync
+        | JobKind::DailyDigest
+        | JobKind::DeletionSweep => Tz::UTC,
+    }
+}
+
+// A line about the job for `.jobs` and `.module show`.
+pub fn describe(job: &Job) -> String {
+    let next = match job.next_run {
+        Some(at) => format!("next <t:{}:R>", at),
+        None => "cancelled".to_string(),
+    };
+    format!(
+        "`{}` **{}** `{}`, {}",
+        job.id,
+        job.kind.name(),
+        job.schedule,
+        next
+    )
+}
+
+// When a job should next run after now, in seconds since the epoch.
+async fn next_run(http: &Http, data: &RwLock<TypeMap>, job: &Job) -> Option<i64> {
+    let cron = Cron::parse(&job.schedule).ok()?;
+    let tz = job_timezone(http, data, job.kind).await;
+    let now = Utc::now().with_timezone(&tz).naive_local();
+    let next = cron.next_after(now)?;
+    Some(timezone::local_to_utc(tz, next).timestamp())
+}
+
+// Add the built-in jobs the modules ask for, if they aren't scheduled already.
+async fn register_defaults(http: &Http, data: &RwLock<TypeMap>) {
+    let wanted = modules::jobs(&*config::get(data).await);
+    for (kind, schedule) in wanted {
+        let exists = settings::read(data, |settings| {
+            settings.jobs.iter().any(|job| job.kind == kind)
+        })
+        .await;
+        if exists {
+            continue;
+        }
+
+        let mut job = Job {
+            id: 0,
+            kind,
+            schedule,
+            next_run: None,
+        };
+        job.next_run = next_run(http, data, &job).await;
+        settings::update(data, |settings| {
+            job.id = settings.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+            settings.jobs.push(job);
+        })
+        .await;
+    }
+}
+
+// Run every job when it's due. Jobs that came due while the bot was down run once on start.
+pub async fn run(http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
+    register_defaults(&http, &data).await;
+
+    loop {
+        let now = Utc::now().timestamp();
+        let jobs = settings::read(&data, |settings| settings.jobs.clone()).await;
+
+        for job in jobs
+            .iter()
+            .filter(|job| job.next_run.is_some_and(|at| at <= now))
+        {
+            let next = next_run(&http, &data, job).await;
+            settings::update(&data, |settings| {
+                if let Some(stored) = settings.jobs.iter_mut().find(|j| j.id == job.id) {
+                    stored.next_run = next;
+                }
+            })
+            .await;
+
+            tokio::spawn(modules::run_job(http.clone(), data.clone(), job.kind));
+        }
+
+        let soonest = jobs
+            .iter()
+            .filter_map(|job| job.next_run)
+            .filter(|at| *at > now)
+            .min();
+        let wait = match soonest {
+            Some(at) => ((at - now) as u64).min(MAX_SLEEP_SECS),
+            None => MAX_SLEEP_SECS,
+        };
+        tokio::time::sleep(Duration::from_secs(wait.max(1))).await;
+    }
+}
+
+#[command]
+#[owners_only]
+#[sub_commands(cancel, resume, reschedule)]
+#[description("List the bot's scheduled jobs.")]
+async fn jobs(ctx: &Context, msg: &Message) -> CommandResult {
+    let jobs = settings::read(&ctx.data, |settings| settings.jobs.clone()).await;
+    let desc = if jobs.is_empty() {
+        "Nothing is scheduled.".to_string()
+    } else {
+        jobs.iter().map(describe).collect::<Vec<_>>().join("\n")
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Scheduled jobs");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                e.footer(|f| {
+                    f.text(".jobs cancel <id> · .jobs resume <id> · .jobs reschedule <id> <cron>");
+                    f
+                });
+                e
+            });
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// The job an id argument points at.
+async fn find_job(ctx: &Context, msg: &Message, args: &mut Args) -> serenity::Result<Option<Job>> {
+    let id = args.single::<u32>().ok();
+    let job = settings::read(&ctx.data, |settings| {
+        settings.jobs.iter().find(|job| Some(job.id) == id).cloned()
+    })
+    .await;
+
+    if job.is_none() {
+        msg.reply(&ctx.http, "There's no job with that id, see `.jobs`.")
+            .await?;
+    }
+    Ok(job)
+}
+
+async fn set_job(ctx: &Context, job: &Job) {
+    settings::update(&ctx.data, |settings| {
+        if let Some(stored) = settings.jobs.iter_mut().find(|j| j.id == job.id) {
+            *stored = job.clone();
+        }
+    })
+    .await;
+}
+
+#[command]
+#[owners_only]
+#[description("Stop a job from running until it's resumed.")]
+#[usage("<id>")]
+async fn cancel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut job = match find_job(ctx, msg, &mut args).await? {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+
+    job.next_run = None;
+    set_job(ctx, &job).await;
+    msg.reply(&ctx.http, format!("Cancelled the {} job.", job.kind.name()))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description("Start a cancelled job again on its schedule.")]
+#[usage("<id>")]
+async fn resume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut job = match find_job(ctx, msg, &mut args).await? {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+
+    job.next_run = next_run(&ctx.http, &ctx.data, &job).await;
+    set_job(ctx, &job).await;
+    let reply = match job.next_run {
+        Some(at) => format!("The {} job runs next <t:{}:R>.", job.kind.name(), at),
+        None => format!("The {} job's schedule never comes up.", job.kind.name()),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description("Change when a job runs, as a cron expression.")]
+#[usage("<id> <minute> <hour> <day> <month> <weekday>")]
+#[example("2 0 18 * * 1-5")]
+async fn reschedule(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut job = match find_job(ctx, msg, &mut args).await? {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+
+    let schedule = args.rest().trim().to_string();
+    if let Err(why) = Cron::parse(&schedule) {
+        msg.reply(&ctx.http, format!("That doesn't work, {}.", why))
+            .await?;
+        return Ok(());
+    }
+
+    job.schedule = schedule;
+    job.next_run = next_run(&ctx.http, &ctx.data, &job).await;
+    set_job(ctx, &job).await;
+    let reply = match job.next_run {
+        Some(at) => format!(
+            "The {} job now runs on `{}`, next <t:{}:R>.",
+            job.kind.name(),
+            job.schedule,
+            at
+        ),
+        None => format!(
+            "The {} job is set to `{}`, which never comes up.",
+            job.kind.name(),
+            job.schedule
+        ),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    fn next(schedule: &str, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        Cron::parse(schedule).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn rejects_bad_schedules() {
+        for schedule in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(Cron::parse(schedule).is_err(), "{:?}", schedule);
+        }
+    }
+
+    #[test]
+    fn daily() {
+        assert_eq!(
+            next("0 9 * * *", at(2024, 1, 1, 8, 30)),
+            Some(at(2024, 1, 1, 9, 0))
+        );
+        // Never the minute it's already in.
+        assert_eq!(
+            next("0 9 * * *", at(2024, 1, 1, 9, 0)),
+            Some(at(2024, 1, 2, 9, 0))
+        );
+    }
+
+    #[test]
+    fn steps_lists_and_ranges() {
+        assert_eq!(
+            next("*/15 * * * *", at(2024, 1, 1, 10, 7)),
+            Some(at(2024, 1, 1, 10, 15))
+        );
+        assert_eq!(
+            next("5/20 * * * *", at(2024, 1, 1, 10, 30)),
+            Some(at(2024, 1, 1, 10, 45))
+        );
+        assert_eq!(
+            next("0 8,20 * * *", at(2024, 1, 1, 9, 0)),
+            Some(at(2024, 1, 1, 20, 0))
+        );
+        assert_eq!(
+            next("0 0 * * 1-5", at(2024, 1, 6, 12, 0)),
+            Some(at(2024, 1, 8, 0, 0))
+        );
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        // 2024-01-03 is a Wednesday.
+        let sunday = Some(at(2024, 1, 7, 0, 0));
+        assert_eq!(next("0 0 * * 0", at(2024, 1, 3, 0, 0)), sunday);
+        assert_eq!(next("0 0 * * 7", at(2024, 1, 3, 0, 0)), sunday);
+    }
+
+    #[test]
+    fn day_of_month_or_weekday() {
+        // Either the 13th or a Friday, like cron. 2024-01-05 is the first Friday.
+        assert_eq!(
+            next("0 0 13 * 5", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 5, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 13 * *", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 13, 0, 0))
+        );
+    }
+
+    #[test]
+    fn impossible_dates() {
+        assert_eq!(next("0 0 31 2 *", at(2024, 1, 1, 0, 0)), None);
+        assert_eq!(
+            next("0 0 29 2 *", at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
+    prelude::*,
+};
+
+use crate::{
+    analysis::AnalysisRequest,
+    clubboard::ClubBoard,
+    follow::Follow,
+    meetup::Meetup,
+    moderation::PendingDeletion,
+    modules::{on_off, BotModule, ModuleSetting},
+    notation::Notation,
+    permissions::{CommandOverride, ADMIN_CHECK},
+    potd::PostedPosition,
+    puzzle::{PostedPuzzle, PuzzleSource},
+    relay::Relay,
+    render::{BoardStyle, EmojiSet, Theme},
+    scheduler::Job,
+    tournaments::{AnnouncedTournament, TeamAnnouncements},
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[group]
+#[commands(
+    screenreader,
+    theme,
+    coordinates,
+    servertheme,
+    servercoordinates,
+    servernotation
+)]
+struct Preferences;
+
+pub struct PreferencesModule;
+
+const PREFERENCE_SETTINGS: &[ModuleSetting] = &[
+    ModuleSetting {
+        name: "Board theme",
+        command: "servertheme",
+        value: |guild| guild.theme.map_or("default", Theme::name).to_string(),
+    },
+    ModuleSetting {
+        name: "Coordinates",
+        command: "servercoordinates",
+        value: |guild| guild.coordinates.map_or("default".to_string(), on_off),
+    },
+    ModuleSetting {
+        name: "Notation",
+        command: "servernotation",
+        value: |guild| guild.notation.unwrap_or_default().name().to_string(),
+    },
+];
+
+impl BotModule for PreferencesModule {
+    fn name(&self) -> &'static str {
+        "preferences"
+    }
+
+    fn description(&self) -> &'static str {
+        "Board themes, coordinates and other preferences."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&PREFERENCES_GROUP)
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        PREFERENCE_SETTINGS
+    }
+}
+
+// Everything people can configure about the bot at runtime, saved as JSON in the data dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub users: HashMap<u64, UserSettings>,
+    pub channels: HashMap<u64, ChannelSettings>,
+    pub guilds: HashMap<u64, GuildSettings>,
+    // The last position of the day, until its discussion closes.
+    pub daily_position: Option<PostedPosition>,
+    // The last daily puzzle, until its solution is posted.
+    pub daily_puzzle: Option<PostedPuzzle>,
+    // How many daily puzzles were posted, numbering them for solving streaks.
+    pub daily_puzzles_posted: u32,
+    // Players whose games are posted in channels.
+    pub follows: Vec<Follow>,
+    // Lichess broadcasts followed in channels.
+    pub relays: Vec<Relay>,
+    // Club meetups, past and upcoming.
+    pub meetups: Vec<Meetup>,
+    // Games people have asked the coaches to look at.
+    pub analysis_requests: Vec<AnalysisRequest>,
+    // Everything the bot does on a timer, see `.jobs`.
+    pub jobs: Vec<Job>,
+    // Team tournaments announced in servers, until a day after they start.
+    pub announced_tournaments: Vec<AnnouncedTournament>,
+    // Commands and replies waiting to be deleted, see moderation.rs.
+    pub pending_deletions: Vec<PendingDeletion>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    // Describe positions in plain text instead of drawing them.
+    pub screen_reader: bool,
+    // Board preferences, overriding the server's defaults.
+    pub theme: Option<Theme>,
+    pub coordinates: Option<bool>,
+    // For anything sent in DMs. The server's timezone if unset.
+    pub timezone: Option<Tz>,
+    // Their Lichess username, once `.link` has checked the account is theirs.
+    pub lichess: Option<String>,
+    // Their chess.com username, checked the same way.
+    pub chesscom: Option<String>,
+    // Servers to post in when they start a rated Lichess game, see `.playingalerts`.
+    pub playing_alerts: HashSet<u64>,
+    // DM them in the evening when they haven't solved the daily puzzle, see `.puzzlereminder`.
+    pub puzzle_reminder: bool,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelSettings {
+    // Delete commands and the bot's replies after this many minutes.
+    pub autodelete_minutes: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    // Reply with a diagram when someone posts a FEN.
+    pub render_fens: bool,
+    // Boards get square reactions to move with, for members who can't use buttons or type moves.
+    pub reaction_moves: bool,
+    // Reply with a preview when someone links a Lichess or chess.com game.
+    pub link_previews: bool,
+    // Default board look for everyone in the server.
+    pub theme: Option<Theme>,
+    pub coordinates: Option<bool>,
+    // The server's emoji pieces for the emoji theme.
+    pub emoji_pieces: EmojiSet,
+    // Piece letters for moves people paste and the moves the bot writes. English if unset.
+    pub notation: Option<Notation>,
+    // Schedules like the position of the day and meetups follow this. UTC if unset.
+    pub timezone: Option<Tz>,
+    // Role given to people who have come to `regular_after` meetups.
+    pub regular_role: Option<u64>,
+    pub regular_after: usize,
+    // Where analysis requests are queued, and who can answer them.
+    pub analysis_channel: Option<u64>,
+    pub coach_role: Option<u64>,
+    // Where announcements from the bot's owners go, see `.broadcastmsg`.
+    pub announcement_channel: Option<u64>,
+    // The Lichess team whose tournaments are announced, see `.lichessteam`.
+    pub lichess_team: Option<TeamAnnouncements>,
+    // Given to the winner of each of the team's tournaments, see `.prizerole`.
+    pub prize_role: Option<u64>,
+    // Take it back once the next tournament is won.
+    pub prize_until_next: bool,
+    // Who was given it last.
+    pub champion: Option<u64>,
+    // The chess.com club ranked with the Lichess team, see `.chesscomclub`.
+    pub chesscom_club: Option<String>,
+    // The leaderboard of their members, see `.clubboard`.
+    pub club_board: Option<ClubBoard>,
+    // Where members' rated Lichess games are announced as they start, see `.playingchannel`.
+    pub playing_channel: Option<u64>,
+    // Where the evening digest of the server's day goes, see `.digest`.
+    pub digest_channel: Option<u64>,
+    // When it was last posted, so it goes out once a day.
+    pub digest_posted: Option<i64>,
+    // Where `.puzzle` and the daily puzzle come from, see `.puzzlesource`.
+    pub puzzle_source: PuzzleSource,
+    // Role overrides for commands, keyed by the command's path like "module enable", see `.perm`.
+    pub command_overrides: HashMap<String, CommandOverride>,
+    // Modules turned off in this server, see `.module`.
+    pub disabled_modules: HashSet<String>,
+}
+
+impl Settings {
+    pub fn load(data_dir: &str) -> Settings {
+        let path = PathBuf::from(data_dir).join(SETTINGS_FILE);
+        let mut settings: Settings = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|why| {
+                panic!("Could not parse settings file {}: {}", path.display(), why)
+            }),
+            Err(_) => Settings::default(),
+        };
+        settings.path = path;
+        settings
+    }
+
+    // Take everything from a backup, keeping where the settings are saved.
+    pub fn restore(&mut self, backup: Settings) {
+        let path = std::mem::take(&mut self.path);
+        *self = backup;
+        self.path = path;
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        write_file(&self.path, &text)
+    }
+
+    pub fn user(&self, user_id: UserId) -> UserSettings {
+        self.users.get(&user_id.0).cloned().unwrap_or_default()
+    }
+
+    pub fn channel(&self, channel_id: ChannelId) -> ChannelSettings {
+        self.channels
+            .get(&channel_id.0)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn guild(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds.get(&guild_id.0).cloned().unwrap_or_default()
+    }
+
+    // How to draw boards for someone: their own preferences first, then the server's.
+    pub fn board_style(&self, guild_id: Option<GuildId>, user_id: Option<UserId>) -> BoardStyle {
+        let defaults = BoardStyle::default();
+        let guild = guild_id.map(|id| self.guild(id)).unwrap_or_default();
+        let user = user_id.map(|id| self.user(id)).unwrap_or_default();
+
+        BoardStyle {
+            theme: user.theme.or(guild.theme).unwrap_or(defaults.theme),
+            coordinates: user
+                .coordinates
+                .or(guild.coordinates)
+                .unwrap_or(defaults.coordinates),
+            screen_reader: user.screen_reader,
+            emoji: Some(guild.emoji_pieces).filter(|set| !set.is_empty()),
+        }
+    }
+}
+
+pub struct SettingsContainer;
+
+impl TypeMapKey for SettingsContainer {
+    type Value = Arc<Mutex<Settings>>;
+}
+
+async fn settings(data: &RwLock<TypeMap>) -> Arc<Mutex<Settings>> {
+    let data = data.read().await;
+    data.get::<SettingsContainer>()
+        .expect("Expected settings in typemap.")
+        .clone()
+}
+
+pub async fn user(data: &RwLock<TypeMap>, user_id: UserId) -> UserSettings {
+    settings(data).await.lock().await.user(user_id)
+}
+
+pub async fn guild(data: &RwLock<TypeMap>, guild_id: GuildId) -> GuildSettings {
+    settings(data).await.lock().await.guild(guild_id)
+}
+
+pub async fn board_style(
+    data: &RwLock<TypeMap>,
+    guild_id: Option<GuildId>,
+    user_id: Option<UserId>,
+) -> BoardStyle {
+    settings(data)
+        .await
+        .lock()
+        .await
+        .board_style(guild_id, user_id)
+}
+
+pub async fn channel(data: &RwLock<TypeMap>, channel_id: ChannelId) -> ChannelSettings {
+    settings(data).await.lock().await.channel(channel_id)
+}
+
+// Look something up in the settings without changing them.
+pub async fn read<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&Settings) -> T,
+{
+    let settings = settings(data).await;
+    let settings = settings.lock().await;
+    f(&settings)
+}
+
+// Change the settings and write them to disk straight away.
+pub async fn update<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut Settings) -> T,
+{
+    let settings = settings(data).await;
+    let mut settings = settings.lock().await;
+    let result = f(&mut settings);
+    if let Err(why) = settings.save() {
+        println!("Could not save settings: {:?}", why);
+    }
+    result
+}
+
+// Write one of the data files. It's written next to the old one and renamed over it, so a crash
+// or a full disk halfway through leaves the old file whole instead of one that can't be loaded.
+pub fn write_file(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, text)?;
+    fs::rename(&temp, path)
+}
+
+// Parse an on/off style argument.
+pub fn parse_toggle(arg: &str) -> Option<bool> {
+    match arg.to_lowercase().as_str() {
+        "on" | "yes" | "true" | "enable" => Some(true),
+        "off" | "no" | "false" | "disable" => Some(false),
+        _ => None,
+    }
+}
+
+#[command]
+#[aliases("a11y", "textmode")]
+#[description("Describe positions in plain text instead of drawing them, for screen readers.")]
+#[usage("[on|off]")]
+async fn screenreader(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let current = user(&ctx.data, msg.author.id).await.screen_reader;
+    let enabled = match args.current() {
+        None => !current,
+        Some(arg) => match parse_toggle(arg) {
+            Some(enabled) => enabled,
+            None => {
+                msg.reply(&ctx.http, "Use `.screenreader on` or `.screenreader off`")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    update(&ctx.data, |settings| {
+        settings
+            .users
+            .entry(msg.author.id.0)
+            .or_default()
+            .screen_reader = enabled;
+    })
+    .await;
+
+    let reply = if enabled {
+        "Screen reader mode is on, positions will be described in plain text."
+    } else {
+        "Screen reader mode is off, positions will be drawn as boards."
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+fn theme_names() -> String {
+    Theme::ALL
+        .iter()
+        .map(|theme| format!("`{}`", theme.name()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// What a theme argument asks for: Some(None) means "go back to the default".
+fn parse_theme_arg(arg: Option<&str>) -> Option<Option<Theme>> {
+    match arg? {
+        "default" | "reset" => Some(None),
+        name => Theme::from_name(name).map(Some),
+    }
+}
+
+fn parse_coordinates_arg(arg: Option<&str>) -> Option<Option<bool>> {
+    match arg? {
+        "default" | "reset" => Some(None),
+        arg => parse_toggle(arg).map(Some),
+    }
+}
+
+#[command]
+#[aliases("boardtheme")]
+#[description("Pick how boards are drawn for you, or go back to the server's default.")]
+#[usage("<theme|default>")]
+async fn theme(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let theme = match parse_theme_arg(args.current()) {
+        Some(theme) => theme,
+        None => {
+            let reply = format!(
+                "Use `.theme <theme>` with one of {}, or `.theme default`",
+                theme_names()
+            );
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    update(&ctx.data, |settings| {
+        settings.users.entry(msg.author.id.0).or_default().theme = theme;
+    })
+    .await;
+
+    let reply = match theme {
+        Some(theme) => format!(
+            "Boards will be drawn with the {} theme for you.",
+            theme.name()
+        ),
+        None => "Boards will use the server's theme for you.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Show or hide coordinates on boards drawn for you.")]
+#[usage("<on|off|default>")]
+async fn coordinates(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let coordinates = match parse_coordinates_arg(args.current()) {
+        Some(coordinates) => coordinates,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Use `.coordinates on`, `.coordinates off` or `.coordinates default`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    update(&ctx.data, |settings| {
+        settings
+            .users
+            .entry(msg.author.id.0)
+            .or_default()
+            .coordinates = coordinates;
+    })
+    .await;
+
+    let reply = match coordinates {
+        Some(true) => "Boards will show coordinates for you.",
+        Some(false) => "Boards won't show coordinates for you.",
+        None => "Boards will follow the server's coordinates setting for you.",
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Set the default board theme for everyone in the server.")]
+#[usage("<theme|default>")]
+async fn servertheme(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let theme = match parse_theme_arg(args.current()) {
+        Some(theme) => theme,
+        None => {
+            let reply = format!(
+                "Use `.servertheme <theme>` with one of {}, or `.servertheme default`",
+                theme_names()
+            );
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+    };
+
+    update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().theme = theme;
+    })
+    .await;
+
+    let name = theme.unwrap_or(BoardStyle::default().theme).name();
+    msg.reply(
+        &ctx.http,
+        format!("The server's board theme is now {}.", name),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Show or hide coordinates on boards for everyone in the server.")]
+#[usage("<on|off|default>")]
+async fn servercoordinates(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let coordinates = match parse_coordinates_arg(args.current()) {
+        Some(coordinates) => coordinates,
+        None => {
+            msg.reply(&ctx.http, "Use `.servercoordinates on`, `.servercoordinates off` or `.servercoordinates default`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().coordinates = coordinates;
+    })
+    .await;
+
+    let shown = coordinates.unwrap_or(BoardStyle::default().coordinates);
+    let reply = if shown {
+        "Boards in this server will show coordinates."
+    } else {
+        "Boards in this server won't show coordinates."
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Read and write moves with this language's piece letters, e.g. `de` for S, L, T and D. English moves are still understood.")]
+#[usage("<en|de|fr|es|it|nl>")]
+async fn servernotation(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let notation = match args.current().and_then(Notation::from_name) {
+        Some(notation) => notation,
+        None => {
+            let names = Notation::ALL
+                .iter()
+                .map(|notation| format!("`{}`", notation.name()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            msg.reply(
+                &ctx.http,
+                format!("Use `.servernotation <language>` with one of {}", names),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().notation = Some(notation);
+    })
+    .await;
+
+    msg.reply(
+        &ctx.http,
+        format!(
+            "Moves in this server will be written like {}.",
+            notation.localize("Nf3")
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,492 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{channel::Message, id::UserId},
+    prelude::*,
+    utils,
+};
+use shakmaty::{
+    fen::{Fen, LossyFenError},
+    Bitboard, Board, Chess, Color, Piece, Position, Setup, Square,
+};
+
+use crate::{
+    diagram::{self, DiagramOptions},
+    engine::{self, Score},
+    eval, fen,
+    game::{self, GameOptions},
+    jobs,
+    modules::BotModule,
+    puzzle::{self, Puzzle, Session},
+    render, settings,
+    variant::Variant,
+    EMBED_SIDE_COLOR,
+};
+
+const HELP: &str = "`.place Ke1 qd8` puts pieces down, capitals for white · `.remove e4` takes them off · `.tomove black` · `.setup eval`, `.setup play @user` or `.setup puzzle @user` when it's ready";
+
+#[group]
+#[commands(setup, place, remove, tomove)]
+struct Setups;
+
+pub struct SetupModule;
+
+impl BotModule for SetupModule {
+    fn name(&self) -> &'static str {
+        "setup"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set up any position with `.setup`, then analyse it, play it out or give it as a puzzle."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&SETUPS_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<DraftContainer>(Mutex::default());
+    }
+}
+
+// The positions being set up, one per member and channel. They're gone after a restart.
+pub struct DraftContainer;
+
+impl TypeMapKey for DraftContainer {
+    type Value = Mutex<HashMap<(u64, u64), Setup>>;
+}
+
+async fn with_drafts<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut HashMap<(u64, u64), Setup>) -> T,
+{
+    let data = data.read().await;
+    let mut drafts = data
+        .get::<DraftContainer>()
+        .expect("Expected drafts in typemap.")
+        .lock()
+        .await;
+    f(&mut drafts)
+}
+
+fn key(msg: &Message) -> (u64, u64) {
+    (msg.channel_id.0, msg.author.id.0)
+}
+
+fn draft_fen(draft: &Setup) -> String {
+    Fen::try_from(draft.clone())
+        .unwrap_or_else(LossyFenError::ignore)
+        .to_string()
+}
+
+// Castling is allowed wherever a king and rook are still on their starting squares.
+fn castling_rights(board: &Board) -> Bitboard {
+    let mut rights = Bitboard::EMPTY;
+    let homes = [
+        (Color::White, Square::E1, [Square::A1, Square::H1]),
+        (Color::Black, Square::E8, [Square::A8, Square::H8]),
+    ];
+    for (color, king, rooks) in homes {
+        if board.piece_at(king) != Some(color.king()) {
+            continue;
+        }
+        for rook in rooks {
+            if board.piece_at(rook) == Some(color.rook()) {
+                rights.add(rook);
+            }
+        }
+    }
+    rights
+}
+
+// After pieces are moved around by hand, castling rights are worked out again and en passant
+// is gone, since no move just happened.
+fn board_changed(draft: &mut Setup) {
+    draft.castling_rights = castling_rights(&draft.board);
+    draft.ep_square = None;
+    draft.promoted = Bitboard::EMPTY;
+    draft.halfmoves = 0;
+}
+
+// "Ke1" to a white king on e1, "qd8" to a black queen on d8.
+fn parse_placement(word: &str) -> Option<(Piece, Square)> {
+    let mut chars = word.chars();
+    let piece = Piece::from_char(chars.next()?)?;
+    let square = chars.as_str().parse::<Square>().ok()?;
+    Some((piece, square))
+}
+
+// Words like "Ke1, qd8" from a command, split on spaces and commas.
+fn words(args: &Args) -> Vec<String> {
+    args.rest()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// The position the author is setting up here, as a legal position, or why it isn't one yet.
+async fn ready_position(ctx: &Context, msg: &Message) -> Result<(Chess, String), String> {
+    let draft = with_drafts(&ctx.data, |drafts| drafts.get(&key(msg)).cloned())
+        .await
+        .ok_or("You aren't setting up a position here, start with `.setup`.")?;
+    let fen = draft_fen(&draft);
+    let pos = fen::check_fen(&fen)?;
+    Ok((pos, fen))
+}
+
+// Show the position being set up and what's left to make it legal.
+async fn show(ctx: &Context, msg: &Message, draft: &Setup) -> CommandResult {
+    let fen = draft_fen(draft);
+    let status = match fen::check_fen(&fen) {
+        Ok(pos) => fen::status_text(&pos),
+        Err(why) => format!("⚠️ {}", why),
+    };
+
+    let style = settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await;
+    let image = diagram::board_image(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        &draft.board,
+        &style,
+        &DiagramOptions::default(),
+    )
+    .await;
+    let desc = match image {
+        Some(_) => format!("{}\n{}", status, HELP),
+        None => format!(
+            "{}\n{}\n{}",
+            render::board_for(&draft.board, &style),
+            status,
+            HELP
+        ),
+    };
+    let has_image = image.is_some();
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Position setup");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(desc);
+                if has_image {
+                    e.image(diagram::IMAGE_URL);
+                }
+                e.footer(|f| {
+                    f.text(fen);
+                    f
+                });
+                e
+            });
+            m.reference_message(msg);
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+// Change the author's position here and show it, or say they need to start one.
+async fn edit<F>(ctx: &Context, msg: &Message, f: F) -> CommandResult
+where
+    F: FnOnce(&mut Setup),
+{
+    let draft = with_drafts(&ctx.data, |drafts| {
+        let draft = drafts.get_mut(&key(msg))?;
+        f(draft);
+        Some(draft.clone())
+    })
+    .await;
+    match draft {
+        Some(draft) => show(ctx, msg, &draft).await,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "You aren't setting up a position here, start with `.setup`.",
+            )
+            .await?;
+            Ok(())
+        }
+    }
+}
+
+// Challenge someone to play the position out. Colors are a coin flip, like any challenge.
+async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Use `.setup play @user`, with a time control like `5+3` or `3d` if you want one";
+    let opponent = match args.single::<String>().ok().and_then(utils::parse_username) {
+        Some(opponent) => UserId(opponent),
+        None => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    let options = match GameOptions::parse(&mut args) {
+        Some(options) if options.variant == Variant::Standard && !options.chess960 => options,
+        Some(_) => {
+            msg.reply(
+                &ctx.http,
+                "Games from a set-up position are played with standard rules.",
+            )
+            .await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    let (pos, fen) = match ready_position(ctx, msg).await {
+        Ok(ready) => ready,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+    if pos.is_game_over() {
+        msg.reply(&ctx.http, "There's nothing left to play in that position.")
+            .await?;
+        return Ok(());
+    }
+    game::offer_challenge(ctx, msg, opponent, options, Some(fen)).await
+}
+
+// The moves the solver has to find: the whole line when the engine sees a forced mate, and just
+// the best move otherwise.
+fn puzzle_solution(score: Score, pv: &[String]) -> Option<Vec<String>> {
+    let plies = match score {
+        Score::Mate(moves) if moves > 0 => moves as usize * 2 - 1,
+        Score::Mate(_) => return None,
+        Score::Centipawns(_) => 1,
+    };
+    Some(pv.iter().take(plies).cloned().collect()).filter(|line: &Vec<String>| !line.is_empty())
+}
+
+// Give the position as a puzzle to the members mentioned, with the engine's answer as the
+// solution. Puzzles made this way don't count for anyone's puzzle rating.
+async fn set_puzzle(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let solvers: Option<Vec<u64>> = words(&args).iter().map(utils::parse_username).collect();
+    let solvers = match solvers {
+        Some(solvers) if !solvers.is_empty() => solvers,
+        _ => {
+            msg.reply(
+                &ctx.http,
+                "Use `.setup puzzle @user`, mentioning everyone to solve it",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let (pos, fen) = match ready_position(ctx, msg).await {
+        Ok(ready) => ready,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+    if pos.is_game_over() {
+        msg.reply(&ctx.http, "There's nothing left to find in that position.")
+            .await?;
+        return Ok(());
+    }
+
+    let job = jobs::start(ctx, msg, "Working out the solution").await?;
+    let analysis = match engine::analyse(&ctx.data, &fen).await {
+        Ok(analysis) => analysis,
+        Err(why) => {
+            println!("Engine error solving {}: {:?}", fen, why);
+            job.fail("The engine couldn't look at that position.").await;
+            return Ok(());
+        }
+    };
+    let solution = match puzzle_solution(analysis.score, &analysis.pv) {
+        Some(solution) => solution,
+        None => {
+            job.fail("The side to move is getting mated there, so there's nothing to find.")
+                .await;
+            return Ok(());
+        }
+    };
+
+    let puzzle = Puzzle {
+        id: format!("setup-{}", msg.id),
+        fen,
+        last_move: None,
+        solution,
+        rating: 0,
+        themes: Vec::new(),
+        set_by: Some(msg.author.id.0),
+        chesscom_day: None,
+    };
+    let session = Session::new(puzzle, true);
+    puzzle::with_puzzles(&ctx.data, |store| {
+        for &solver in &solvers {
+            store
+                .sessions
+                .insert((msg.channel_id.0, solver), session.clone());
+        }
+    })
+    .await;
+
+    let mentions: Vec<String> = solvers.iter().map(|id| format!("<@{}>", id)).collect();
+    let prompt = format!(
+        "{}, <@{}> set you a puzzle.\n{}",
+        mentions.join(" "),
+        msg.author.id,
+        puzzle::to_move_prompt(&pos)
+    );
+    puzzle::send_board(
+        &ctx.http,
+        &ctx.data,
+        msg.channel_id,
+        msg.guild_id,
+        msg.author.id,
+        &session,
+        &prompt,
+    )
+    .await?;
+    job.done().await;
+
+    Ok(())
+}
+
+#[command]
+#[description(
+    "Set up a position by hand. Start from the usual position, an empty board or a FEN, then put pieces down with `.place`, take them off with `.remove` and pick who moves with `.tomove`. Once it's legal, `.setup eval` has the engine look at it, `.setup play @user` challenges someone to play it out, and `.setup puzzle @user` gives it to them as a puzzle. Castling is allowed wherever a king and rook haven't left their squares."
+)]
+#[usage("[start | empty | <FEN> | eval | play @user [time control] | puzzle @user... | done]")]
+#[example("empty")]
+#[example("play @Magnus 5+3")]
+async fn setup(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let sub = args.current().unwrap_or("").to_lowercase();
+    match sub.as_str() {
+        "play" => {
+            args.advance();
+            return play(ctx, msg, args).await;
+        }
+        "puzzle" => {
+            args.advance();
+            return set_puzzle(ctx, msg, args).await;
+        }
+        "eval" | "analyze" | "analyse" => {
+            return match ready_position(ctx, msg).await {
+                Ok((pos, _)) => eval::send_eval(ctx, msg, &pos).await,
+                Err(why) => {
+                    msg.reply(&ctx.http, why).await?;
+                    Ok(())
+                }
+            };
+        }
+        "done" | "cancel" => {
+            let removed = with_drafts(&ctx.data, |drafts| drafts.remove(&key(msg))).await;
+            let reply = match removed {
+                Some(_) => "Position put away.",
+                None => "You aren't setting up a position here.",
+            };
+            msg.reply(&ctx.http, reply).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let text = args.rest().trim().trim_matches('`').trim();
+    let draft = match text.to_lowercase().as_str() {
+        // Carry on with the one they have, if any.
+        "" => with_drafts(&ctx.data, |drafts| drafts.get(&key(msg)).cloned())
+            .await
+            .unwrap_or_else(Setup::initial),
+        "start" => Setup::initial(),
+        "empty" | "clear" => Setup::empty(),
+        _ => match text.parse::<Fen>() {
+            Ok(parsed) => parsed.as_setup().clone(),
+            Err(why) => {
+                msg.reply(&ctx.http, fen::fen_error_text(text, why)).await?;
+                return Ok(());
+            }
+        },
+    };
+    with_drafts(&ctx.data, |drafts| drafts.insert(key(msg), draft.clone())).await;
+    show(ctx, msg, &draft).await
+}
+
+#[command]
+#[description(
+    "Put pieces on the position you're setting up, by letter and square: capitals for white, lowercase for black. A piece already on the square is replaced."
+)]
+#[usage("<pieces>")]
+#[example("Ke1 Qd1 ke8 pe5")]
+async fn place(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let words = words(&args);
+    let placements: Option<Vec<(Piece, Square)>> =
+        words.iter().map(|word| parse_placement(word)).collect();
+    let placements = match placements {
+        Some(placements) if !placements.is_empty() => placements,
+        _ => {
+            msg.reply(
+                &ctx.http,
+                "Use `.place Ke1 qd8`, a piece letter and a square for each piece. Capitals are white (K Q R B N P) and lowercase black.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    edit(ctx, msg, |draft| {
+        for (piece, square) in placements {
+            draft.board.set_piece_at(square, piece);
+        }
+        board_changed(draft);
+    })
+    .await
+}
+
+#[command]
+#[description("Take pieces off the position you're setting up.")]
+#[usage("<squares>")]
+#[example("e4 d5")]
+async fn remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let squares: Option<Vec<Square>> = words(&args)
+        .iter()
+        .map(|word| word.parse::<Square>().ok())
+        .collect();
+    let squares = match squares {
+        Some(squares) if !squares.is_empty() => squares,
+        _ => {
+            msg.reply(&ctx.http, "Use `.remove e4 d5`").await?;
+            return Ok(());
+        }
+    };
+    edit(ctx, msg, |draft| {
+        for square in squares {
+            draft.board.discard_piece_at(square);
+        }
+        board_changed(draft);
+    })
+    .await
+}
+
+#[command]
+#[description("Pick who moves first in the position you're setting up.")]
+#[usage("<white|black>")]
+async fn tomove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let turn = match args.rest().trim().to_lowercase().as_str() {
+        "white" | "w" => Color::White,
+        "black" | "b" => Color::Black,
+        _ => {
+            msg.reply(&ctx.http, "Use `.tomove white` or `.tomove black`")
+                .await?;
+            return Ok(());
+        }
+    };
+    edit(ctx, msg, |draft| {
+        draft.turn = turn;
+        // An en passant square only makes sense for the side that was going to move.
+        draft.ep_square = None;
+    })
+    .await
+}
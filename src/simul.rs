@@ -0,0 +1,728 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+use shakmaty::Position;
+
+use crate::{
+    card::{self, Card},
+    diagram::{self, DiagramOptions},
+    engine,
+    game::{self, Game, GameOptions, GameResult},
+    modules::BotModule,
+    rating,
+    variant::Variant,
+    EMBED_SIDE_COLOR,
+};
+
+const JOIN_ID: &str = "simul:join";
+const LEAVE_ID: &str = "simul:leave";
+const BEGIN_ID: &str = "simul:begin";
+const CANCEL_ID: &str = "simul:cancel";
+
+// More boards than this is more than anyone can keep up with in Discord.
+const MAX_BOARDS: usize = 20;
+
+// The dashboard picture has this many boards to a row, each this many pixels wide.
+const DASHBOARD_COLUMNS: usize = 4;
+const DASHBOARD_BOARD: f32 = 240.0;
+const DASHBOARD_MARGIN: f32 = 16.0;
+const DASHBOARD_LABEL: f32 = 24.0;
+// Moves come in fast over twenty boards, so the dashboard is redrawn at most this often, and
+// whenever a game ends.
+const DASHBOARD_SECS: u64 = 30;
+const DASHBOARD_FILE: &str = "simul.png";
+
+#[group]
+#[commands(simul)]
+struct Simuls;
+
+pub struct SimulsModule;
+
+#[async_trait]
+impl BotModule for SimulsModule {
+    fn name(&self) -> &'static str {
+        "simuls"
+    }
+
+    fn description(&self) -> &'static str {
+        "Simultaneous exhibitions, one host against everyone who signs up. See `.simul`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&SIMULS_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// One host playing everyone who signed up at once, each game in its own thread. The host has
+// white on every board. In engine simuls the host is the bot, and the member who set it up runs
+// the signups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Simul {
+    // The signup message, which shows the results once the games begin.
+    pub message_id: u64,
+    pub channel_id: u64,
+    pub guild_id: u64,
+    pub host: u64,
+    pub options: GameOptions,
+    pub players: Vec<u64>,
+    // Set when the host begins, and signups close.
+    pub started: bool,
+    pub games: Vec<u32>,
+    // The message with every board in one picture, reposted as the games go on.
+    #[serde(default)]
+    pub dashboard: Option<u64>,
+    #[serde(skip)]
+    drawn_at: Option<Instant>,
+    // The level the bot plays every board at, for engine simuls.
+    #[serde(default)]
+    pub engine_level: Option<u8>,
+    // Who set up an engine simul.
+    #[serde(default)]
+    pub organizer: Option<u64>,
+}
+
+impl Simul {
+    // Who can begin or call off the simul.
+    fn runs(&self) -> u64 {
+        self.organizer.unwrap_or(self.host)
+    }
+}
+
+// The players who haven't lost to the host, with how their game went.
+fn survivors(games: &[Game]) -> Vec<String> {
+    games
+        .iter()
+        .filter_map(|game| {
+            let how = match game.result {
+                None => "playing",
+                Some(GameResult::BlackWon) => "won",
+                Some(GameResult::Draw) => "drew",
+                Some(GameResult::WhiteWon | GameResult::Aborted) => return None,
+            };
+            Some(format!("<@{}> ({})", game.black, how))
+        })
+        .collect()
+}
+
+// How a board is going, from the host's side.
+fn board_status(simul: &Simul, game: &Game) -> String {
+    let player = game.black;
+    let status = match game.result {
+        None => "playing".to_string(),
+        Some(GameResult::WhiteWon) => format!("<@{}> won", simul.host),
+        Some(GameResult::BlackWon) => format!("<@{}> won", player),
+        Some(GameResult::Draw) => "drawn".to_string(),
+        Some(GameResult::Aborted) => "aborted".to_string(),
+    };
+    if game.in_thread {
+        format!(
+            "<#{}> #{} vs <@{}>: {}",
+            game.channel_id, game.id, player, status
+        )
+    } else {
+        format!("#{} vs <@{}>: {}", game.id, player, status)
+    }
+}
+
+// How a board is going, short enough to fit under it on the dashboard.
+fn board_label(game: &Game) -> &'static str {
+    match game.result {
+        None if game.position().turn().is_white() => "white to move",
+        None => "black to move",
+        Some(GameResult::WhiteWon) => "1-0",
+        Some(GameResult::BlackWon) => "0-1",
+        Some(GameResult::Draw) => "1/2",
+        Some(GameResult::Aborted) => "aborted",
+    }
+}
+
+// Every board of a simul in one picture, numbered like the threads.
+fn render_dashboard(games: &[Game]) -> Option<Vec<u8>> {
+    let columns = games.len().clamp(1, DASHBOARD_COLUMNS);
+    let rows = games.len().div_ceil(columns);
+    let cell = DASHBOARD_BOARD + DASHBOARD_MARGIN;
+    let width = DASHBOARD_MARGIN + columns as f32 * cell;
+    let height = DASHBOARD_MARGIN + rows as f32 * (DASHBOARD_LABEL + cell);
+    let mut card = Card::new(width as u32, height as u32);
+
+    for (board, game) in games.iter().enumerate() {
+        let x = DASHBOARD_MARGIN + (board % columns) as f32 * cell;
+        let y = DASHBOARD_MARGIN + (board / columns) as f32 * (DASHBOARD_LABEL + cell);
+        let color = if game.is_over() {
+            card::MUTED
+        } else {
+            card::TEXT
+        };
+        card.text(
+            x,
+            y,
+            2.0,
+            card::ACCENT,
+            &format!("{}. #{}", board + 1, game.id),
+        );
+        card.text_right(x + DASHBOARD_BOARD, y, 2.0, color, board_label(game));
+
+        let options = DiagramOptions {
+            flipped: false,
+            highlight: game.last_move(),
+        };
+        card.push(&format!(
+            r#"<svg x="{}" y="{}" width="{}" height="{}" viewBox="0 0 800 800">{}</svg>"#,
+            x,
+            y + DASHBOARD_LABEL,
+            DASHBOARD_BOARD,
+            DASHBOARD_BOARD,
+            diagram::board_squares(game.position().board(), &options)
+        ));
+    }
+
+    card.png()
+}
+
+// The signup, or once the simul has begun how each board is going and the host's score and
+// performance rating so far.
+fn simul_embed<'a>(
+    e: &'a mut CreateEmbed,
+    simul: &Simul,
+    games: &[Game],
+    performance: Option<i32>,
+) -> &'a mut CreateEmbed {
+    let mut desc = match simul.engine_level {
+        Some(level) => format!(
+            "I play white against everyone at level {}, {}. Set up by <@{}>.",
+            level,
+            simul.options.describe(),
+            simul.runs()
+        ),
+        None => format!(
+            "<@{}> plays white against everyone, {}.",
+            simul.host,
+            simul.options.describe()
+        ),
+    };
+
+    if !simul.started {
+        desc.push_str("\n\n**Signed up**");
+        if simul.players.is_empty() {
+            desc.push_str("\nNobody yet, press Join to play!");
+        }
+        for player in &simul.players {
+            desc.push_str(&format!("\n<@{}>", player));
+        }
+    } else {
+        desc.push('\n');
+        for game in games {
+            desc.push_str(&format!("\n{}", board_status(simul, game)));
+        }
+
+        let finished: Vec<&Game> = games
+            .iter()
+            .filter(|game| matches!(game.result, Some(result) if result != GameResult::Aborted))
+            .collect();
+        let halves: u32 = finished
+            .iter()
+            .map(|game| match game.result {
+                Some(GameResult::WhiteWon) => 2,
+                Some(GameResult::Draw) => 1,
+                _ => 0,
+            })
+            .sum();
+        let score = format!(
+            "\n\n**<@{}> has {} out of {}**",
+            simul.host,
+            game::format_points(halves),
+            finished.len()
+        );
+        desc.push_str(&score);
+        if let Some(performance) = performance {
+            desc.push_str(&format!(", a performance of {}", performance));
+        }
+        if simul.engine_level.is_some() {
+            let survivors = survivors(games);
+            let title = if games.iter().all(Game::is_over) {
+                "Survivors"
+            } else {
+                "Still standing"
+            };
+            let names = if survivors.is_empty() {
+                "nobody".to_string()
+            } else {
+                survivors.join(", ")
+            };
+            desc.push_str(&format!("\n**{}:** {}", title, names));
+        }
+        if games.iter().all(Game::is_over) {
+            desc.push_str("\nThe simul is over, thanks for playing!");
+        }
+    }
+
+    e.title("Simul");
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e
+}
+
+fn signup_buttons(c: &mut CreateComponents) -> &mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Primary);
+            b.label("Join");
+            b.custom_id(JOIN_ID);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("Leave");
+            b.custom_id(LEAVE_ID);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Success);
+            b.label("Begin");
+            b.custom_id(BEGIN_ID);
+            b
+        });
+        row.create_button(|b| {
+            b.style(ButtonStyle::Danger);
+            b.label("Cancel");
+            b.custom_id(CANCEL_ID);
+            b
+        })
+    })
+}
+
+// A simul and its games, as they are now.
+async fn simul_state(data: &RwLock<TypeMap>, message_id: u64) -> Option<(Simul, Vec<Game>)> {
+    game::read_games(data, |store| {
+        let simul = store
+            .simuls
+            .iter()
+            .find(|simul| simul.message_id == message_id)?
+            .clone();
+        let games = simul
+            .games
+            .iter()
+            .filter_map(|id| store.games.iter().find(|game| game.id == *id))
+            .cloned()
+            .collect();
+        Some((simul, games))
+    })
+    .await
+}
+
+// Show the simul's latest results on its message. Called whenever one of its games ends.
+pub async fn update_summary(http: &Http, data: &RwLock<TypeMap>, message_id: u64) {
+    let (simul, games) = match simul_state(data, message_id).await {
+        Some(state) => state,
+        None => return,
+    };
+    let performance = game::read_games(data, |store| {
+        let games: Vec<&Game> = games.iter().collect();
+        rating::performance_in(&store.ratings, &games, simul.host)
+    })
+    .await;
+    let result = ChannelId(simul.channel_id)
+        .edit_message(http, MessageId(message_id), |m| {
+            m.embed(|e| simul_embed(e, &simul, &games, performance))
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating a simul summary: {:?}", why);
+    }
+}
+
+// Repost the simul's dashboard with its boards as they are now, unless it was drawn moments ago.
+// Called after every move on its boards, with `force` when a game ends.
+pub async fn refresh_dashboard(http: &Http, data: &RwLock<TypeMap>, message_id: u64, force: bool) {
+    let due = game::with_games(data, |store| {
+        let simul = store
+            .simuls
+            .iter_mut()
+            .find(|simul| simul.message_id == message_id)?;
+        let recent = simul
+            .drawn_at
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(DASHBOARD_SECS));
+        if recent && !force {
+            return None;
+        }
+        simul.drawn_at = Some(Instant::now());
+        Some(())
+    })
+    .await;
+    if due.is_none() {
+        return;
+    }
+
+    let (simul, games) = match simul_state(data, message_id).await {
+        Some(state) => state,
+        None => return,
+    };
+    let channel_id = ChannelId(simul.channel_id);
+    if games.is_empty() || !diagram::images_allowed(http, data, channel_id).await {
+        return;
+    }
+    let png = match render_dashboard(&games) {
+        Some(png) => png,
+        None => return,
+    };
+
+    let boards: Vec<String> = games
+        .iter()
+        .enumerate()
+        .map(|(board, game)| format!("{}. {}", board + 1, board_status(&simul, game)))
+        .collect();
+    let sent = channel_id
+        .send_message(http, |m| {
+            m.embed(|e| {
+                e.title("Simul boards");
+                e.color(EMBED_SIDE_COLOR);
+                e.description(boards.join("\n"));
+                e.image(format!("attachment://{}", DASHBOARD_FILE));
+                e
+            });
+            m.add_file(card::attachment(png, DASHBOARD_FILE));
+            m
+        })
+        .await;
+    let sent = match sent {
+        Ok(sent) => sent,
+        Err(why) => {
+            println!("Error posting a simul dashboard: {:?}", why);
+            return;
+        }
+    };
+
+    let old = game::with_games(data, |store| {
+        let simul = store
+            .simuls
+            .iter_mut()
+            .find(|simul| simul.message_id == message_id)?;
+        simul.dashboard.replace(sent.id.0)
+    })
+    .await;
+    if let Some(old) = old {
+        if let Err(why) = channel_id.delete_message(http, MessageId(old)).await {
+            println!("Error removing an old simul dashboard: {:?}", why);
+        }
+    }
+}
+
+async fn reply_privately(ctx: &Context, component: &MessageComponentInteraction, text: &str) {
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource);
+            r.interaction_response_data(|d| {
+                d.content(text);
+                d.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error replying to a simul button: {:?}", why);
+    }
+}
+
+// Start a game on every board, each in a thread off its own message.
+async fn begin(ctx: &Context, simul: &Simul) -> Vec<u32> {
+    let channel_id = ChannelId(simul.channel_id);
+    let mut ids = Vec::new();
+    for (board, &player) in simul.players.iter().enumerate() {
+        let game = Game {
+            white: simul.host,
+            black: player,
+            simul: Some(simul.message_id),
+            engine_level: simul.engine_level,
+            ..simul
+                .options
+                .new_game(Some(simul.guild_id), simul.channel_id, (simul.host, player))
+        };
+        let game = game::with_games(&ctx.data, |store| {
+            if store.is_playing(player) {
+                None
+            } else {
+                Some(store.start(game))
+            }
+        })
+        .await;
+        let game = match game {
+            Some(game) => game,
+            None => continue,
+        };
+        ids.push(game.id);
+
+        let text = format!(
+            "Simul board {}: <@{}> vs <@{}>",
+            board + 1,
+            simul.host,
+            player
+        );
+        let game = match channel_id.say(&ctx.http, text).await {
+            Ok(sent) => game::move_to_thread(ctx, channel_id, sent.id, game).await,
+            Err(why) => {
+                println!("Error posting a simul board: {:?}", why);
+                game
+            }
+        };
+        game::watch_clock(ctx, &game);
+        let game_channel = ChannelId(game.channel_id);
+        if let Err(why) = game::send_game(&ctx.http, &ctx.data, game_channel, &game).await {
+            println!("Error posting a simul game: {:?}", why);
+        }
+        if let Err(why) = game::engine_turn(ctx, game_channel, &game).await {
+            println!("Error making the bot's first simul move: {:?}", why);
+        }
+    }
+    ids
+}
+
+// Sign up, drop out, begin or call off a simul when a button on its signup is pressed. Returns
+// false if the button isn't ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if ![JOIN_ID, LEAVE_ID, BEGIN_ID, CANCEL_ID].contains(&id) {
+        return false;
+    }
+
+    let user = component.user.id.0;
+    let message_id = component.message.id.0;
+    let answer = game::with_games(&ctx.data, |store| {
+        let busy = store.is_playing(user);
+        let index = store
+            .simuls
+            .iter()
+            .position(|simul| simul.message_id == message_id && !simul.started)?;
+        let simul = &mut store.simuls[index];
+        let is_host = user == simul.host;
+        let runs = user == simul.runs();
+        let answer = match id {
+            JOIN_ID if is_host => Err("You're the host, you play every board!"),
+            JOIN_ID if simul.players.contains(&user) => Err("You're already signed up."),
+            JOIN_ID if busy => Err("You're in a game, finish that one first."),
+            JOIN_ID if simul.players.len() >= MAX_BOARDS => Err("This simul is full."),
+            JOIN_ID => {
+                simul.players.push(user);
+                Ok(simul.clone())
+            }
+            LEAVE_ID if !simul.players.contains(&user) => Err("You aren't signed up."),
+            LEAVE_ID => {
+                simul.players.retain(|&player| player != user);
+                Ok(simul.clone())
+            }
+            _ if !runs => Err("Only the host can do that."),
+            BEGIN_ID if simul.players.is_empty() => Err("Nobody has signed up yet."),
+            BEGIN_ID => {
+                simul.started = true;
+                Ok(simul.clone())
+            }
+            _ => Ok(store.simuls.remove(index)),
+        };
+        Some(answer)
+    })
+    .await;
+
+    let simul = match answer {
+        Some(Ok(simul)) => simul,
+        Some(Err(why)) => {
+            reply_privately(ctx, component, why).await;
+            return true;
+        }
+        None => {
+            reply_privately(ctx, component, "This simul has already begun.").await;
+            return true;
+        }
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::UpdateMessage);
+            r.interaction_response_data(|d| match id {
+                CANCEL_ID => {
+                    d.content(format!("<@{}> called off the simul.", simul.runs()));
+                    d.embeds(Vec::new());
+                    d.components(|c| c)
+                }
+                BEGIN_ID => {
+                    d.content("The simul has begun, good luck everyone!");
+                    d.components(|c| c)
+                }
+                _ => {
+                    d.create_embed(|e| simul_embed(e, &simul, &[], None));
+                    d.components(signup_buttons)
+                }
+            })
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error updating a simul: {:?}", why);
+    }
+
+    if id == BEGIN_ID {
+        let ids = begin(ctx, &simul).await;
+        game::with_games(&ctx.data, |store| {
+            if let Some(stored) = store
+                .simuls
+                .iter_mut()
+                .find(|stored| stored.message_id == simul.message_id)
+            {
+                stored.games = ids;
+            }
+        })
+        .await;
+        update_summary(&ctx.http, &ctx.data, simul.message_id).await;
+        refresh_dashboard(&ctx.http, &ctx.data, simul.message_id, true).await;
+    }
+
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+#[sub_commands(start, engine)]
+#[description("Play everyone at once! `.simul start` opens signups for a simultaneous exhibition, and you play white against everyone who joins, each game in its own thread. `.simul engine` has me take on everyone instead. A picture of every board is kept up to date in the channel.")]
+async fn simul(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        &ctx.http,
+        "Use `.simul start [time control] [variant]` to host a simul, or `.simul engine [level]` for one against me.",
+    )
+    .await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Open signups for a simul you host. Press Begin once everyone's in.")]
+#[usage("[time control] [variant]")]
+#[example("3d")]
+async fn start(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let options = match GameOptions::parse(&mut args) {
+        Some(options) => options,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Use `.simul start`, with a time control like `3d` or `10+5`, and `960`, `atomic`, `crazyhouse` or `koth` for other rules.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    open_signups(ctx, msg, options, None).await
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Open signups for a simul against me, at a level from 1 to 8. Everyone who joins gets a board, and the survivors are named at the end. Press Begin once everyone's in.")]
+#[usage("[level] [time control]")]
+#[example("5 3d")]
+async fn engine(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage =
+        "Use `.simul engine`, with a level from 1 to 8 and a time control like `3d` or `10+5`.";
+    let level = match args.current().map(str::parse::<u8>) {
+        Some(Ok(level)) if engine::LEVELS.contains(&level) => {
+            args.advance();
+            level
+        }
+        Some(Ok(_)) => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+        _ => game::DEFAULT_LEVEL,
+    };
+    let options = match GameOptions::parse(&mut args) {
+        Some(options) if options.variant == Variant::Standard => options,
+        _ => {
+            msg.reply(&ctx.http, usage).await?;
+            return Ok(());
+        }
+    };
+    open_signups(ctx, msg, options, Some(level)).await
+}
+
+// Post a simul's signup, hosted by the author or at `engine_level` by the bot.
+async fn open_signups(
+    ctx: &Context,
+    msg: &Message,
+    options: GameOptions,
+    engine_level: Option<u8>,
+) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)").0;
+    let author = msg.author.id.0;
+    if options.chess960 && options.variant != Variant::Standard {
+        msg.reply(&ctx.http, "Chess960 only works with standard rules.")
+            .await?;
+        return Ok(());
+    }
+
+    // A host runs one simul at a time.
+    let hosting = game::read_games(&ctx.data, |store| {
+        store.simuls.iter().any(|simul| {
+            simul.runs() == author
+                && (!simul.started
+                    || simul.games.iter().any(|id| {
+                        store
+                            .games
+                            .iter()
+                            .any(|game| game.id == *id && !game.is_over())
+                    }))
+        })
+    })
+    .await;
+    if hosting {
+        msg.reply(&ctx.http, "You're already hosting a simul.")
+            .await?;
+        return Ok(());
+    }
+
+    let host = match engine_level {
+        Some(_) => crate::bot_id(&ctx.data).await.0,
+        None => author,
+    };
+    let mut simul = Simul {
+        message_id: 0,
+        channel_id: msg.channel_id.0,
+        guild_id,
+        host,
+        options,
+        players: Vec::new(),
+        started: false,
+        games: Vec::new(),
+        dashboard: None,
+        drawn_at: None,
+        engine_level,
+        organizer: engine_level.map(|_| author),
+    };
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| simul_embed(e, &simul, &[], None));
+            m.components(signup_buttons);
+            m
+        })
+        .await?;
+
+    simul.message_id = sent.id.0;
+    game::with_games(&ctx.data, |store| store.simuls.push(simul)).await;
+
+    Ok(())
+}
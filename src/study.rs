@@ -0,0 +1,395 @@
+use std::collections::{HashMap, VecDeque};
+
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::MessageId,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+use shakmaty::Position;
+
+use crate::{
+    game, lichess,
+    modules::BotModule,
+    notation::Notation,
+    pgn::{self, PgnGame},
+    render::{self, BoardStyle},
+    settings, web, EMBED_SIDE_COLOR,
+};
+
+// Old studies stop answering their buttons once there are more than this many.
+const MAX_STUDIES: usize = 100;
+
+// Discord's limit for options in a select menu.
+const MAX_MENU_CHAPTERS: usize = 25;
+
+// Long comments are cut off here, the embed has the board to fit too.
+const MAX_COMMENT_CHARS: usize = 1500;
+
+const PREV_CHAPTER_ID: &str = "study:prevchapter";
+const PREV_ID: &str = "study:prev";
+const NEXT_ID: &str = "study:next";
+const NEXT_CHAPTER_ID: &str = "study:nextchapter";
+const CHAPTER_ID: &str = "study:chapter";
+
+#[group]
+#[commands(study)]
+struct Studies;
+
+pub struct StudiesModule;
+
+#[async_trait]
+impl BotModule for StudiesModule {
+    fn name(&self) -> &'static str {
+        "studies"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lichess studies browsed chapter by chapter, see `.study`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&STUDIES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<StudyContainer>(Mutex::default());
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// A study someone is reading, attached to a bot message.
+pub struct Study {
+    id: String,
+    chapters: Vec<PgnGame>,
+    chapter: usize,
+    ply: usize,
+    style: BoardStyle,
+    notation: Notation,
+}
+
+impl Study {
+    fn current(&self) -> &PgnGame {
+        &self.chapters[self.chapter]
+    }
+
+    fn open_chapter(&mut self, chapter: usize) {
+        self.chapter = chapter.min(self.chapters.len() - 1);
+        self.ply = 0;
+    }
+}
+
+#[derive(Default)]
+pub struct StudyStore {
+    studies: HashMap<MessageId, Study>,
+    order: VecDeque<MessageId>,
+}
+
+impl StudyStore {
+    pub fn insert(&mut self, message_id: MessageId, study: Study) {
+        if self.studies.insert(message_id, study).is_none() {
+            self.order.push_back(message_id);
+        }
+        while self.order.len() > MAX_STUDIES {
+            if let Some(old) = self.order.pop_front() {
+                self.studies.remove(&old);
+            }
+        }
+    }
+}
+
+pub struct StudyContainer;
+
+impl TypeMapKey for StudyContainer {
+    type Value = Mutex<StudyStore>;
+}
+
+// Lichess names chapters "Study: Chapter" in the Event tag, newer exports have their own tags.
+fn chapter_name(chapter: &PgnGame) -> String {
+    if let Some(name) = chapter.header("ChapterName") {
+        return name.to_string();
+    }
+    match chapter.header("Event") {
+        Some(event) => event
+            .split_once(": ")
+            .map_or(event, |(_, name)| name)
+            .to_string(),
+        None => "Chapter".to_string(),
+    }
+}
+
+fn study_name(chapter: &PgnGame) -> String {
+    if let Some(name) = chapter.header("StudyName") {
+        return name.to_string();
+    }
+    match chapter.header("Event") {
+        Some(event) => event
+            .split_once(": ")
+            .map_or(event, |(name, _)| name)
+            .to_string(),
+        None => "Lichess study".to_string(),
+    }
+}
+
+fn cut(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn study_embed<'a>(e: &'a mut CreateEmbed, study: &Study) -> &'a mut CreateEmbed {
+    let chapter = study.current();
+    let pos = chapter.position_after(study.ply);
+
+    let mut desc = render::board_for(pos.board(), &study.style);
+    let last_move = match study.ply {
+        0 => "Starting position".to_string(),
+        ply => chapter
+            .numbered_move(None, ply - 1, study.notation)
+            .expect("ply is within the chapter"),
+    };
+    desc.push_str(&format!("\n**{}**", last_move));
+    if let Some(comment) = chapter.comments.get(&study.ply) {
+        desc.push_str(&format!("\n> {}", cut(comment, MAX_COMMENT_CHARS)));
+    }
+
+    e.title(chapter_name(chapter));
+    e.url(
+        chapter
+            .header("ChapterURL")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("https://lichess.org/study/{}", study.id)),
+    );
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    e.footer(|f| {
+        f.text(format!(
+            "{} · chapter {} of {} · move {} of {}",
+            study_name(chapter),
+            study.chapter + 1,
+            study.chapters.len(),
+            study.ply,
+            chapter.ply_count()
+        ));
+        f
+    });
+    e
+}
+
+fn study_buttons<'a>(c: &'a mut CreateComponents, study: &Study) -> &'a mut CreateComponents {
+    let last_chapter = study.chapter + 1 == study.chapters.len();
+    c.create_action_row(|row| {
+        let buttons = [
+            (PREV_CHAPTER_ID, "⏮ Chapter", study.chapter == 0),
+            (PREV_ID, "◀", study.ply == 0),
+            (NEXT_ID, "▶", study.ply == study.current().ply_count()),
+            (NEXT_CHAPTER_ID, "Chapter ⏭", last_chapter),
+        ];
+        for (id, label, disabled) in buttons {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Secondary);
+                b.label(label);
+                b.custom_id(id);
+                b.disabled(disabled);
+                b
+            });
+        }
+        row
+    });
+    if study.chapters.len() > 1 {
+        c.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id(CHAPTER_ID);
+                menu.placeholder("Go to a chapter");
+                menu.options(|options| {
+                    for (index, chapter) in
+                        study.chapters.iter().enumerate().take(MAX_MENU_CHAPTERS)
+                    {
+                        options.create_option(|o| {
+                            o.label(cut(
+                                &format!("{}. {}", index + 1, chapter_name(chapter)),
+                                95,
+                            ));
+                            o.value(index);
+                            o.default_selection(index == study.chapter);
+                            o
+                        });
+                    }
+                    options
+                })
+            })
+        });
+    }
+    c
+}
+
+// Move through a study when its buttons or menu are used. Returns false if they aren't ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let id = component.data.custom_id.as_str();
+    if ![
+        PREV_CHAPTER_ID,
+        PREV_ID,
+        NEXT_ID,
+        NEXT_CHAPTER_ID,
+        CHAPTER_ID,
+    ]
+    .contains(&id)
+    {
+        return false;
+    }
+
+    let data = ctx.data.read().await;
+    let mut store = data
+        .get::<StudyContainer>()
+        .expect("Expected studies in typemap.")
+        .lock()
+        .await;
+
+    let result = match store.studies.get_mut(&component.message.id) {
+        Some(study) => {
+            match id {
+                PREV_CHAPTER_ID => study.open_chapter(study.chapter.saturating_sub(1)),
+                PREV_ID => study.ply = study.ply.saturating_sub(1),
+                NEXT_ID => study.ply = (study.ply + 1).min(study.current().ply_count()),
+                NEXT_CHAPTER_ID => study.open_chapter(study.chapter + 1),
+                CHAPTER_ID => {
+                    let choice = component
+                        .data
+                        .values
+                        .first()
+                        .and_then(|value| value.parse::<usize>().ok());
+                    if let Some(chapter) = choice {
+                        study.open_chapter(chapter);
+                    }
+                }
+                _ => {}
+            }
+
+            component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage);
+                    r.interaction_response_data(|d| {
+                        d.create_embed(|e| study_embed(e, study));
+                        d.components(|c| study_buttons(c, study));
+                        d
+                    })
+                })
+                .await
+        }
+        None => {
+            component
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage);
+                    r.interaction_response_data(|d| {
+                        d.content("This study has expired, use `.study` again.");
+                        d.components(|c| c)
+                    })
+                })
+                .await
+        }
+    };
+
+    if let Err(why) = result {
+        println!("Error updating study: {:?}", why);
+    }
+
+    true
+}
+
+#[command]
+#[description(
+    "Read a public Lichess study here: step through each chapter's moves with its comments, and jump between chapters. A chapter link opens at that chapter."
+)]
+#[usage("<study link>")]
+#[example("https://lichess.org/study/8ZknXoVi")]
+async fn study(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let arg = args.rest().trim().trim_matches(|c| c == '<' || c == '>');
+    let (id, chapter_id) = match lichess::study_from_url(arg) {
+        Some(found) => found,
+        None => {
+            msg.reply(&ctx.http, "Use `.study <Lichess study link>`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let text = match lichess::study_pgn(&web::client(&ctx.data).await, &id).await {
+        Ok(Some(text)) => text,
+        Ok(None) => {
+            msg.reply(&ctx.http, "I couldn't find that study, is it public?")
+                .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting Lichess study {}: {:?}", id, why);
+            msg.reply(&ctx.http, "I couldn't reach Lichess right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let chapters: Vec<PgnGame> = pgn::split_games(&text)
+        .iter()
+        .filter_map(|chapter| pgn::parse_any(chapter).ok())
+        .collect();
+    if chapters.is_empty() {
+        msg.reply(
+            &ctx.http,
+            "That study doesn't have any chapters I can read.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Open at the linked chapter, if there was one.
+    let chapter = chapter_id
+        .and_then(|chapter_id| {
+            chapters.iter().position(|chapter| {
+                chapter
+                    .header("ChapterURL")
+                    .is_some_and(|url| url.ends_with(&chapter_id))
+            })
+        })
+        .unwrap_or(0);
+    let study = Study {
+        id,
+        chapters,
+        chapter,
+        ply: 0,
+        style: settings::board_style(&ctx.data, msg.guild_id, Some(msg.author.id)).await,
+        notation: game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await,
+    };
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| study_embed(e, &study));
+            m.components(|c| study_buttons(c, &study));
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    let data = ctx.data.read().await;
+    data.get::<StudyContainer>()
+        .expect("Expected studies in typemap.")
+        .lock()
+        .await
+        .insert(sent.id, study);
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+// Correspondence games can't give more than two weeks a move.
+const MAX_HOURS_PER_MOVE: u32 = 14 * 24;
+
+// Limits for games on the clock.
+const MAX_CLOCK_MINUTES: u32 = 180;
+const MAX_INCREMENT_SECS: u32 = 60;
+
+// How long players have to make their moves.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimeControl {
+    // A move every so many hours, for games played over days.
+    Correspondence { hours: u32 },
+    // Minutes on each clock, and seconds added after every move. "5+3" is 5 minutes with 3
+    // seconds a move.
+    Clock { minutes: u32, increment: u32 },
+}
+
+impl TimeControl {
+    // "3d" or "12h" for a correspondence game, "5+3" for a game on the clock.
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let text = text.trim().to_lowercase();
+        if let Some((minutes, increment)) = text.split_once('+') {
+            let minutes: u32 = minutes.parse().ok()?;
+            let increment: u32 = increment.parse().ok()?;
+            if minutes == 0 || minutes > MAX_CLOCK_MINUTES || increment > MAX_INCREMENT_SECS {
+                return None;
+            }
+            return Some(TimeControl::Clock { minutes, increment });
+        }
+
+        let (number, unit_hours) = if let Some(days) = text.strip_suffix('d') {
+            (days, 24)
+        } else if let Some(hours) = text.strip_suffix('h') {
+            (hours, 1)
+        } else {
+            return None;
+        };
+        let hours = number.parse::<u32>().ok()?.checked_mul(unit_hours)?;
+        if hours == 0 || hours > MAX_HOURS_PER_MOVE {
+            return None;
+        }
+        Some(TimeControl::Correspondence { hours })
+    }
+
+    // The PGN TimeControl tag: "300+3" for a clock, "1/259200" for a move every three days.
+    pub fn pgn_tag(self) -> String {
+        match self {
+            TimeControl::Correspondence { hours } => format!("1/{}", hours * 3600),
+            TimeControl::Clock { minutes, increment } => {
+                format!("{}+{}", minutes * 60, increment)
+            }
+        }
+    }
+
+    // Like Lichess, games are named by how long they take with 40 moves each.
+    fn speed(minutes: u32, increment: u32) -> &'static str {
+        match minutes * 60 + increment * 40 {
+            0..=179 => "bullet",
+            180..=479 => "blitz",
+            480..=1499 => "rapid",
+            _ => "classical",
+        }
+    }
+
+    // Like "3 days per move".
+    pub fn describe(self) -> String {
+        match self {
+            TimeControl::Correspondence { hours } if hours % 24 == 0 => match hours / 24 {
+                1 => "1 day per move".to_string(),
+                days => format!("{} days per move", days),
+            },
+            TimeControl::Correspondence { hours: 1 } => "1 hour per move".to_string(),
+            TimeControl::Correspondence { hours } => format!("{} hours per move", hours),
+            TimeControl::Clock { minutes, increment } => format!(
+                "{}+{} {}",
+                minutes,
+                increment,
+                TimeControl::speed(minutes, increment)
+            ),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serenity::{
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
+    prelude::*,
+};
+
+use crate::{
+    modules::{BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    settings,
+};
+
+#[group]
+#[commands(timezone, servertimezone)]
+struct Timezones;
+
+pub struct TimezonesModule;
+
+const TIMEZONE_SETTINGS: &[ModuleSetting] = &[ModuleSetting {
+    name: "Timezone",
+    command: "servertimezone",
+    value: |guild| guild.timezone.unwrap_or(Tz::UTC).name().to_string(),
+}];
+
+impl BotModule for TimezonesModule {
+    fn name(&self) -> &'static str {
+        "timezones"
+    }
+
+    fn description(&self) -> &'static str {
+        "Timezones for schedules and DMs."
+    }
+
+    fn optional(&self) -> bool {
+        false
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&TIMEZONES_GROUP)
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        TIMEZONE_SETTINGS
+    }
+}
+
+// "Europe/Madrid", case doesn't matter.
+fn parse_timezone(name: &str) -> Option<Tz> {
+    chrono_tz::TZ_VARIANTS
+        .iter()
+        .copied()
+        .find(|tz| tz.name().eq_ignore_ascii_case(name))
+}
+
+// The timezone of a guild's schedules. UTC unless an admin picked one.
+pub async fn for_guild(data: &RwLock<TypeMap>, guild_id: Option<GuildId>) -> Tz {
+    match guild_id {
+        Some(guild_id) => settings::guild(data, guild_id)
+            .await
+            .timezone
+            .unwrap_or(Tz::UTC),
+        None => Tz::UTC,
+    }
+}
+
+// The timezone for someone's DMs: their own, then their server's.
+pub async fn for_user(data: &RwLock<TypeMap>, user_id: UserId, guild_id: Option<GuildId>) -> Tz {
+    match settings::user(data, user_id).await.timezone {
+        Some(tz) => tz,
+        None => for_guild(data, guild_id).await,
+    }
+}
+
+// The timezone of whichever guild a channel is in.
+pub async fn for_channel(http: &Http, data: &RwLock<TypeMap>, channel_id: ChannelId) -> Tz {
+    let guild_id = match channel_id.to_channel(http).await {
+        Ok(channel) => channel.guild().map(|channel| channel.guild_id),
+        Err(why) => {
+            println!("Could not look up channel {}: {:?}", channel_id, why);
+            None
+        }
+    };
+    for_guild(data, guild_id).await
+}
+
+// A wall clock time in `tz`. Times skipped by a DST change are moved past the gap, and
+// repeated ones use the first occurrence.
+pub fn local_to_utc(tz: Tz, time: NaiveDateTime) -> DateTime<Utc> {
+    let mut time = time;
+    loop {
+        if let Some(local) = tz.from_local_datetime(&time).earliest() {
+            return local.with_timezone(&Utc);
+        }
+        time += Duration::minutes(30);
+    }
+}
+
+#[command]
+#[aliases("tz")]
+#[description("Set your timezone, used for anything the bot sends you in DMs.")]
+#[usage("[set <zone> | reset]")]
+#[example("set Europe/Madrid")]
+async fn timezone(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let action = args.single::<String>().unwrap_or_default().to_lowercase();
+    let timezone = match action.as_str() {
+        "" => {
+            let tz = for_user(&ctx.data, msg.author.id, msg.guild_id).await;
+            msg.reply(&ctx.http, format!("Your timezone is {}.", tz.name()))
+                .await?;
+            return Ok(());
+        }
+        "reset" | "default" => None,
+        "set" => match args.current().and_then(parse_timezone) {
+            Some(tz) => Some(tz),
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "I don't know that timezone. Use a name like `Europe/Madrid` or `America/New_York`",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            msg.reply(
+                &ctx.http,
+                "Use `.timezone set <zone>`, like `.timezone set Europe/Madrid`, or `.timezone reset`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.users.entry(msg.author.id.0).or_default().timezone = timezone;
+    })
+    .await;
+
+    let reply = match timezone {
+        Some(tz) => format!("Your timezone is now {}.", tz.name()),
+        None => "You'll use the server's timezone again.".to_string(),
+    };
+    msg.reply(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Set the timezone for the server's schedules, like the position of the day and meetups."
+)]
+#[usage("<zone|reset>")]
+#[example("Europe/Madrid")]
+async fn servertimezone(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let timezone = match args.current() {
+        Some("reset") | Some("default") => None,
+        arg => match arg.and_then(parse_timezone) {
+            Some(tz) => Some(tz),
+            None => {
+                msg.reply(
+                    &ctx.http,
+                    "Use `.servertimezone <zone>` with a name like `Europe/Madrid`, or `.servertimezone reset` for UTC",
+                )
+                .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().timezone = timezone;
+    })
+    .await;
+
+    let tz = timezone.unwrap_or(Tz::UTC);
+    msg.reply(
+        &ctx.http,
+        format!(
+            "The server's schedules now run on {} time, it's {} there.",
+            tz.name(),
+            Utc::now().with_timezone(&tz).format("%H:%M")
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
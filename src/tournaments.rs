@@ -0,0 +1,712 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+    },
+    prelude::*,
+    utils,
+};
+
+use crate::{
+    config::Config,
+    lichess,
+    modules::{self, BotModule, ModuleSetting},
+    permissions::ADMIN_CHECK,
+    scheduler::JobKind,
+    settings::{self, UserSettings},
+    web, EMBED_SIDE_COLOR,
+};
+
+// Tournaments are announced once they're this close, so a team's weekly schedule doesn't all show
+// up at once.
+const ANNOUNCE_AHEAD_SECS: i64 = 7 * 24 * 60 * 60;
+
+// The role is pinged when a tournament is this close to starting.
+const REMINDER_SECS: i64 = 15 * 60;
+
+// Announced tournaments are forgotten this long after they start...
+const FORGET_AFTER_SECS: i64 = 24 * 60 * 60;
+// ...unless they're still waiting on a winner, up to this long.
+const CROWN_WITHIN_SECS: i64 = 7 * 24 * 60 * 60;
+
+const MEDALS: [&str; 3] = ["🥇", "🥈", "🥉"];
+
+#[group]
+#[commands(lichessteam, prizerole)]
+struct Tournaments;
+
+pub struct TournamentsModule;
+
+const TOURNAMENT_SETTINGS: &[ModuleSetting] = &[
+    ModuleSetting {
+        name: "Lichess team",
+        command: "lichessteam",
+        value: |guild| match &guild.lichess_team {
+            Some(team) => format!("{} in <#{}>", team.team, team.channel_id),
+            None => "not set".to_string(),
+        },
+    },
+    ModuleSetting {
+        name: "Prize role",
+        command: "prizerole",
+        value: |guild| match guild.prize_role {
+            Some(role) if guild.prize_until_next => format!("<@&{}> until the next winner", role),
+            role => modules::show_role(role),
+        },
+    },
+];
+
+#[async_trait]
+impl BotModule for TournamentsModule {
+    fn name(&self) -> &'static str {
+        "tournaments"
+    }
+
+    fn description(&self) -> &'static str {
+        "Upcoming arenas and Swiss tournaments of the server's Lichess team and who won them, see `.lichessteam`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&TOURNAMENTS_GROUP)
+    }
+
+    fn jobs(&self, _config: &Config) -> Vec<(JobKind, String)> {
+        vec![(JobKind::TournamentPoll, "*/5 * * * *".to_string())]
+    }
+
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::TournamentPoll {
+            poll_all(http, data).await;
+        }
+    }
+
+    fn settings(&self) -> &'static [ModuleSetting] {
+        TOURNAMENT_SETTINGS
+    }
+}
+
+// The Lichess team a server announces tournaments from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamAnnouncements {
+    pub team: String,
+    pub channel_id: u64,
+    // Pinged a little before each tournament starts.
+    pub role_id: Option<u64>,
+}
+
+// A tournament already announced in a server, kept until a day after it starts and its winner
+// has been announced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnouncedTournament {
+    pub guild_id: u64,
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub swiss: bool,
+    // Seconds since the epoch.
+    pub starts_at: i64,
+    pub reminded: bool,
+    // Its winner was announced, or there won't be one.
+    #[serde(default)]
+    pub crowned: bool,
+}
+
+// An arena or Swiss tournament, whichever it is.
+struct Upcoming {
+    id: String,
+    name: String,
+    url: String,
+    swiss: bool,
+    starts_at: i64,
+    // "3+2 arena · 60 minutes · rated"
+    format: String,
+}
+
+fn rated_text(rated: bool) -> &'static str {
+    if rated {
+        "rated"
+    } else {
+        "casual"
+    }
+}
+
+impl From<lichess::Arena> for Upcoming {
+    fn from(arena: lichess::Arena) -> Upcoming {
+        Upcoming {
+            url: arena.url(),
+            swiss: false,
+            format: format!(
+                "{} arena · {} minutes · {}",
+                arena.clock.text(),
+                arena.minutes,
+                rated_text(arena.rated)
+            ),
+            starts_at: arena.starts_at / 1000,
+            id: arena.id,
+            name: arena.full_name,
+        }
+    }
+}
+
+// None if the start time can't be read.
+fn from_swiss(swiss: lichess::Swiss) -> Option<Upcoming> {
+    let starts_at = DateTime::parse_from_rfc3339(&swiss.starts_at).ok()?;
+    Some(Upcoming {
+        url: swiss.url(),
+        swiss: true,
+        format: format!(
+            "{} Swiss · {} rounds · {}",
+            swiss.clock.text(),
+            swiss.rounds,
+            rated_text(swiss.rated)
+        ),
+        starts_at: starts_at.timestamp(),
+        id: swiss.id,
+        name: swiss.name,
+    })
+}
+
+async fn upcoming(client: &reqwest::Client, team: &str) -> reqwest::Result<Vec<Upcoming>> {
+    let mut upcoming: Vec<Upcoming> = lichess::team_arenas(client, team)
+        .await?
+        .into_iter()
+        .map(Upcoming::from)
+        .collect();
+    upcoming.extend(
+        lichess::team_swisses(client, team)
+            .await?
+            .into_iter()
+            .filter_map(from_swiss),
+    );
+    upcoming.sort_by_key(|tournament| tournament.starts_at);
+    Ok(upcoming)
+}
+
+// Announce new tournaments of every server's team and remind them of the ones about to start.
+// The scheduler runs this every few minutes.
+pub async fn poll_all(http: &Http, data: &RwLock<TypeMap>) {
+    let now = Utc::now().timestamp();
+    let teams: Vec<(u64, TeamAnnouncements)> = settings::update(data, |settings| {
+        settings.announced_tournaments.retain(|announced| {
+            let kept = if announced.crowned {
+                FORGET_AFTER_SECS
+            } else {
+                CROWN_WITHIN_SECS
+            };
+            announced.starts_at + kept > now
+        });
+        settings
+            .guilds
+            .iter()
+            .filter(|(_, guild)| modules::enabled(guild, &TournamentsModule))
+            .filter_map(|(id, guild)| Some((*id, guild.lichess_team.clone()?)))
+            .collect()
+    })
+    .await;
+    if teams.is_empty() {
+        return;
+    }
+
+    let client = web::client(data).await;
+    for (guild_id, team) in teams {
+        if let Err(why) = poll(http, data, &client, guild_id, &team).await {
+            println!(
+                "Error checking tournaments of team {}: {:?}",
+                team.team, why
+            );
+        }
+    }
+}
+
+async fn poll(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    client: &reqwest::Client,
+    guild_id: u64,
+    team: &TeamAnnouncements,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let now = Utc::now().timestamp();
+    let channel_id = ChannelId(team.channel_id);
+    let announced: Vec<AnnouncedTournament> = settings::read(data, |settings| {
+        settings
+            .announced_tournaments
+            .iter()
+            .filter(|announced| announced.guild_id == guild_id)
+            .cloned()
+            .collect()
+    })
+    .await;
+
+    for tournament in upcoming(client, &team.team).await? {
+        let soon = tournament.starts_at - now;
+        if soon <= 0 || soon > ANNOUNCE_AHEAD_SECS {
+            continue;
+        }
+        if announced
+            .iter()
+            .any(|announced| announced.id == tournament.id)
+        {
+            continue;
+        }
+
+        // One that's starting already gets its ping with the announcement.
+        let remind = soon <= REMINDER_SECS;
+        announce(
+            http,
+            channel_id,
+            &tournament,
+            team.role_id.filter(|_| remind),
+        )
+        .await?;
+        settings::update(data, |settings| {
+            settings.announced_tournaments.push(AnnouncedTournament {
+                guild_id,
+                id: tournament.id.clone(),
+                name: tournament.name.clone(),
+                url: tournament.url.clone(),
+                swiss: tournament.swiss,
+                starts_at: tournament.starts_at,
+                reminded: remind,
+                crowned: false,
+            });
+        })
+        .await;
+    }
+
+    for tournament in announced.iter().filter(|announced| {
+        !announced.reminded
+            && announced.starts_at > now
+            && announced.starts_at - now <= REMINDER_SECS
+    }) {
+        remind(http, channel_id, tournament, team.role_id).await?;
+        settings::update(data, |settings| {
+            let stored = settings
+                .announced_tournaments
+                .iter_mut()
+                .find(|stored| stored.guild_id == guild_id && stored.id == tournament.id);
+            if let Some(stored) = stored {
+                stored.reminded = true;
+            }
+        })
+        .await;
+    }
+
+    for tournament in announced
+        .iter()
+        .filter(|announced| !announced.crowned && announced.starts_at <= now)
+    {
+        if !crown(http, data, client, guild_id, channel_id, tournament).await? {
+            continue;
+        }
+        settings::update(data, |settings| {
+            let stored = settings
+                .announced_tournaments
+                .iter_mut()
+                .find(|stored| stored.guild_id == guild_id && stored.id == tournament.id);
+            if let Some(stored) = stored {
+                stored.crowned = true;
+            }
+        })
+        .await;
+    }
+
+    Ok(())
+}
+
+// The member who linked the Lichess account of a tournament's winner.
+fn champion(users: &HashMap<u64, UserSettings>, username: &str) -> Option<u64> {
+    users
+        .iter()
+        .find(|(_, user)| {
+            user.lichess
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(username))
+        })
+        .map(|(id, _)| *id)
+}
+
+// "**Winner** wins with 30 points!", their rating and performance, then the podium.
+fn champion_text(podium: &[lichess::Standing]) -> String {
+    let mut text = String::new();
+    if let Some(winner) = podium.first() {
+        text.push_str(&format!(
+            "**{}** wins with {} points!\nRated {}",
+            winner.username, winner.score, winner.rating
+        ));
+        if let Some(performance) = winner.performance {
+            text.push_str(&format!(", performance {}", performance));
+        }
+        text.push('\n');
+    }
+    for (medal, standing) in MEDALS.iter().zip(podium) {
+        text.push_str(&format!(
+            "\n{} {} · {} points",
+            medal, standing.username, standing.score
+        ));
+    }
+    text
+}
+
+// Announce the winner of a tournament once it's over and give them the prize role, taking it
+// back from the last one if it only lasts until the next tournament. Returns false while the
+// tournament is still going.
+async fn crown(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    client: &reqwest::Client,
+    guild_id: u64,
+    channel_id: ChannelId,
+    tournament: &AnnouncedTournament,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    match lichess::tournament_finished(client, &tournament.id, tournament.swiss).await? {
+        Some(true) => {}
+        Some(false) => return Ok(false),
+        // Deleted, so nobody won it.
+        None => return Ok(true),
+    }
+    let podium = lichess::tournament_results(
+        client,
+        &tournament.id,
+        tournament.swiss,
+        MEDALS.len() as u32,
+    )
+    .await?;
+    let winner = match podium.first() {
+        Some(winner) => winner,
+        None => return Ok(true),
+    };
+
+    let guild_id = GuildId(guild_id);
+    let (linked, guild) = settings::read(data, |settings| {
+        (
+            champion(&settings.users, &winner.username),
+            settings.guild(guild_id),
+        )
+    })
+    .await;
+    // Only someone who's still in the server.
+    let member = match linked {
+        Some(user) => guild_id.member(http, user).await.ok().map(|_| user),
+        None => None,
+    };
+
+    if let Some(role) = guild.prize_role {
+        let previous = guild
+            .champion
+            .filter(|previous| guild.prize_until_next && Some(*previous) != member);
+        if let Some(previous) = previous {
+            if let Err(why) = http.remove_member_role(guild_id.0, previous, role).await {
+                println!("Could not take back the prize role: {:?}", why);
+            }
+        }
+        if let Some(user) = member {
+            if let Err(why) = http.add_member_role(guild_id.0, user, role).await {
+                println!("Could not grant the prize role: {:?}", why);
+            }
+        }
+        settings::update(data, |settings| {
+            settings.guilds.entry(guild_id.0).or_default().champion = member;
+        })
+        .await;
+    }
+
+    channel_id
+        .send_message(http, |m| {
+            if let Some(user) = member {
+                m.content(format!("🏆 Congratulations <@{}>!", user));
+                m.allowed_mentions(|a| a.users(vec![user]));
+            }
+            m.embed(|e| {
+                e.title(&tournament.name);
+                e.url(&tournament.url);
+                e.color(EMBED_SIDE_COLOR);
+                e.description(champion_text(&podium));
+                e
+            });
+            m
+        })
+        .await?;
+    Ok(true)
+}
+
+async fn announce(
+    http: &Http,
+    channel_id: ChannelId,
+    tournament: &Upcoming,
+    ping: Option<u64>,
+) -> serenity::Result<()> {
+    channel_id
+        .send_message(http, |m| {
+            if let Some(role) = ping {
+                m.content(format!("<@&{}> starting soon!", role));
+                m.allowed_mentions(|a| a.roles(vec![role]));
+            }
+            m.embed(|e| {
+                e.title(&tournament.name);
+                e.url(&tournament.url);
+                e.color(EMBED_SIDE_COLOR);
+                e.description(format!(
+                    "Starts <t:{0}:F> (<t:{0}:R>)\n{1}\n\n[Join on Lichess]({2})",
+                    tournament.starts_at, tournament.format, tournament.url
+                ));
+                e
+            });
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+async fn remind(
+    http: &Http,
+    channel_id: ChannelId,
+    tournament: &AnnouncedTournament,
+    role: Option<u64>,
+) -> serenity::Result<()> {
+    let who = match role {
+        Some(role) => format!("<@&{}> ", role),
+        None => String::new(),
+    };
+    channel_id
+        .send_message(http, |m| {
+            m.content(format!(
+                "{}**{}** starts <t:{}:R>, join at <{}>",
+                who, tournament.name, tournament.starts_at, tournament.url
+            ));
+            m.allowed_mentions(|a| a.roles(role))
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Announce the upcoming arenas and Swiss tournaments of a Lichess team, with a reminder shortly before each starts. Give the team's id from its link, then optionally the channel (this one if left out) and a role to ping."
+)]
+#[usage("[<team id> [#channel] [@role] | off]")]
+#[example("lichess-discord-bot-club #tournaments @players")]
+async fn lichessteam(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let arg = match args.single::<String>() {
+        Ok(arg) => arg,
+        Err(_) => {
+            let reply = match settings::guild(&ctx.data, guild_id).await.lichess_team {
+                Some(team) => format!(
+                    "Tournaments of <https://lichess.org/team/{}> are announced in <#{}>{}.",
+                    team.team,
+                    team.channel_id,
+                    match team.role_id {
+                        Some(role) => format!(", pinging <@&{}>", role),
+                        None => String::new(),
+                    }
+                ),
+                None => "This server doesn't announce a Lichess team's tournaments, use `.lichessteam <team id>`.".to_string(),
+            };
+            msg.channel_id
+                .send_message(&ctx.http, |m| {
+                    m.content(reply);
+                    m.allowed_mentions(|a| a.empty_roles());
+                    m.reference_message(msg);
+                    m
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if settings::parse_toggle(&arg) == Some(false) {
+        settings::update(&ctx.data, |settings| {
+            settings.guilds.entry(guild_id.0).or_default().lichess_team = None;
+        })
+        .await;
+        msg.reply(&ctx.http, "I won't announce tournaments here anymore.")
+            .await?;
+        return Ok(());
+    }
+
+    // Take the id out of a pasted link too.
+    let id = arg
+        .trim_matches(|c| c == '<' || c == '>')
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut channel_id = msg.channel_id.0;
+    let mut role_id = None;
+    for arg in args.iter::<String>().flatten() {
+        if let Some(channel) = utils::parse_channel(&arg) {
+            channel_id = channel;
+        } else if let Some(role) = utils::parse_role(&arg) {
+            role_id = Some(role);
+        } else {
+            msg.reply(
+                &ctx.http,
+                "Use `.lichessteam <team id> [#channel] [@role]` or `.lichessteam off`",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let team = match lichess::team(&web::client(&ctx.data).await, &id).await {
+        Ok(Some(team)) => team,
+        Ok(None) => {
+            msg.reply(
+                &ctx.http,
+                format!("There's no Lichess team with the id `{}`.", id),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            println!("Error getting Lichess team {}: {:?}", id, why);
+            msg.reply(&ctx.http, "I couldn't reach Lichess right now.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        settings.guilds.entry(guild_id.0).or_default().lichess_team = Some(TeamAnnouncements {
+            team: team.id.clone(),
+            channel_id,
+            role_id,
+        });
+    })
+    .await;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!(
+                "I'll announce {}'s upcoming tournaments in <#{}>{}, within a few minutes of them being made.",
+                team.name,
+                channel_id,
+                match role_id {
+                    Some(role) => format!(" and ping <@&{}> before they start", role),
+                    None => String::new(),
+                }
+            ));
+            m.allowed_mentions(|a| a.empty_roles());
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+// The role to give and whether it's only until the next winner, from `<@role> [untilnext]` or
+// `off`.
+fn parse_prize(args: &[&str]) -> Option<(Option<u64>, bool)> {
+    match args {
+        [off] if settings::parse_toggle(off) == Some(false) => Some((None, false)),
+        [role] => Some((Some(utils::parse_role(role)?), false)),
+        [role, until] if until.eq_ignore_ascii_case("untilnext") => {
+            Some((Some(utils::parse_role(role)?), true))
+        }
+        _ => None,
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description(
+    "Give a role to the winner of each of the team's tournaments, if they've linked their Lichess account. With `untilnext` it's taken back once the next tournament has a winner."
+)]
+#[usage("<@role> [untilnext] | off")]
+#[example("@Champion untilnext")]
+async fn prizerole(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.expect("only_in(guilds)");
+    let (role, until_next) = match parse_prize(&args.raw().collect::<Vec<_>>()) {
+        Some(prize) => prize,
+        None => {
+            msg.reply(
+                &ctx.http,
+                "Use `.prizerole <@role> [untilnext]` or `.prizerole off`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    settings::update(&ctx.data, |settings| {
+        let guild = settings.guilds.entry(guild_id.0).or_default();
+        guild.prize_role = role;
+        guild.prize_until_next = until_next;
+    })
+    .await;
+
+    let reply = match role {
+        Some(role) if until_next => format!(
+            "The winner of each tournament will have <@&{}> until the next one is won.",
+            role
+        ),
+        Some(role) => format!("The winner of each tournament will get <@&{}>.", role),
+        None => "Tournament winners no longer get a role.".to_string(),
+    };
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(reply);
+            m.allowed_mentions(|a| a.empty_roles());
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(username: &str, score: f64) -> lichess::Standing {
+        lichess::Standing {
+            username: username.to_string(),
+            score,
+            rating: 2000,
+            performance: None,
+        }
+    }
+
+    #[test]
+    fn crowning_winners() {
+        let mut users = HashMap::new();
+        users.insert(
+            1,
+            UserSettings {
+                lichess: Some("DrNykterstein".to_string()),
+                ..Default::default()
+            },
+        );
+        users.insert(2, UserSettings::default());
+        assert_eq!(champion(&users, "drnykterstein"), Some(1));
+        assert_eq!(champion(&users, "someone"), None);
+
+        let podium = [standing("a", 5.5), standing("b", 5.0), standing("c", 4.0)];
+        assert_eq!(
+            champion_text(&podium),
+            "**a** wins with 5.5 points!\nRated 2000\n\n🥇 a · 5.5 points\n🥈 b · 5 points\n🥉 c · 4 points"
+        );
+
+        assert_eq!(parse_prize(&["<@&5>", "untilnext"]), Some((Some(5), true)));
+        assert_eq!(parse_prize(&["off"]), Some((None, false)));
+        assert_eq!(parse_prize(&["off", "untilnext"]), None);
+        assert_eq!(parse_prize(&[]), None);
+    }
+}
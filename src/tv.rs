@@ -0,0 +1,247 @@
+use serenity::{
+    async_trait,
+    builder::{CreateComponents, CreateEmbed},
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+
+use crate::{lichess, modules::BotModule, web, EMBED_SIDE_COLOR};
+
+const DEFAULT_CHANNEL: &str = "Top Rated";
+
+// Followed by the channel's name.
+const REFRESH_PREFIX: &str = "tv:refresh:";
+
+#[group]
+#[commands(tv)]
+struct Tv;
+
+pub struct TvModule;
+
+#[async_trait]
+impl BotModule for TvModule {
+    fn name(&self) -> &'static str {
+        "tv"
+    }
+
+    fn description(&self) -> &'static str {
+        "The games on Lichess TV, see `.tv`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&TV_GROUP)
+    }
+
+    async fn component(&self, ctx: &Context, component: &MessageComponentInteraction) -> bool {
+        handle_component(ctx, component).await
+    }
+}
+
+// The game a TV channel is showing, seen from the side of the player it follows.
+struct Featured {
+    channel: String,
+    game: lichess::Game,
+    flipped: bool,
+}
+
+// "Top Rated", "top-rated" and "toprated" are all the same channel.
+fn simplify(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+async fn featured(data: &RwLock<TypeMap>, wanted: &str) -> Result<Featured, String> {
+    let client = web::client(data).await;
+    let unreachable = |why: reqwest::Error| {
+        println!("Error getting Lichess TV: {:?}", why);
+        "I couldn't reach Lichess right now.".to_string()
+    };
+    let channels = lichess::tv_channels(&client).await.map_err(unreachable)?;
+
+    let wanted = match simplify(wanted).as_str() {
+        "best" | "top" => simplify(DEFAULT_CHANNEL),
+        wanted => wanted.to_string(),
+    };
+    let (channel, tv) = match channels
+        .into_iter()
+        .find(|(name, _)| simplify(name) == wanted)
+    {
+        Some(found) => found,
+        None => {
+            return Err("There's no Lichess TV channel called that, see `.tv list`.".to_string())
+        }
+    };
+
+    let game = lichess::export_game(&client, &tv.game_id)
+        .await
+        .map_err(unreachable)?;
+    Ok(Featured {
+        channel,
+        game,
+        flipped: tv.color == "black",
+    })
+}
+
+fn tv_embed<'a>(e: &'a mut CreateEmbed, featured: &Featured) -> &'a mut CreateEmbed {
+    let game = &featured.game;
+    let (top, bottom) = if featured.flipped {
+        (&game.players.white, &game.players.black)
+    } else {
+        (&game.players.black, &game.players.white)
+    };
+    let (top_square, bottom_square) = if featured.flipped {
+        ("⬜", "⬛")
+    } else {
+        ("⬛", "⬜")
+    };
+
+    e.title(format!("📺 Lichess TV · {}", featured.channel));
+    e.url(game.url());
+    e.color(EMBED_SIDE_COLOR);
+    e.description(format!(
+        "{} {}\n{} {}",
+        top_square,
+        top.display_name(),
+        bottom_square,
+        bottom.display_name()
+    ));
+    e.field(
+        "Game",
+        format!(
+            "{} {}",
+            if game.rated { "Rated" } else { "Casual" },
+            game.speed
+        ),
+        true,
+    );
+    e.field(
+        "Moves",
+        game.moves.split_whitespace().count().div_ceil(2),
+        true,
+    );
+    if let Some(fen) = &game.last_fen {
+        e.image(lichess::position_image_url(fen, None, featured.flipped));
+    }
+    e.footer(|f| {
+        if game.status == "started" {
+            f.text("lichess.org/tv · Refresh for the latest position")
+        } else {
+            f.text(format!("{} · {}", game.result(), game.status))
+        }
+    });
+    e
+}
+
+fn tv_buttons<'a>(c: &'a mut CreateComponents, featured: &Featured) -> &'a mut CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b.style(ButtonStyle::Secondary);
+            b.label("🔄 Refresh");
+            b.custom_id(format!("{}{}", REFRESH_PREFIX, featured.channel));
+            b
+        })
+    })
+}
+
+// Show the channel's game as it is now, which may be a new one. Returns false if the button
+// isn't ours.
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) -> bool {
+    let channel = match component.data.custom_id.strip_prefix(REFRESH_PREFIX) {
+        Some(channel) => channel,
+        None => return false,
+    };
+
+    let result = component
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Error refreshing Lichess TV: {:?}", why);
+        return true;
+    }
+
+    let result = match featured(&ctx.data, channel).await {
+        Ok(featured) => component
+            .edit_original_interaction_response(&ctx.http, |r| {
+                r.create_embed(|e| tv_embed(e, &featured));
+                r.components(|c| tv_buttons(c, &featured));
+                r
+            })
+            .await
+            .map(|_| ()),
+        Err(why) => component
+            .create_followup_message(&ctx.http, |f| {
+                f.content(why);
+                f.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                f
+            })
+            .await
+            .map(|_| ()),
+    };
+    if let Err(why) = result {
+        println!("Error refreshing Lichess TV: {:?}", why);
+    }
+
+    true
+}
+
+#[command]
+#[description(
+    "Show the game on a Lichess TV channel, like blitz, bullet or the top rated one, with a button to catch up on the position. `.tv list` shows the channels."
+)]
+#[usage("[channel | list]")]
+#[example("blitz")]
+async fn tv(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let wanted = match args.rest().trim() {
+        "" => DEFAULT_CHANNEL,
+        wanted => wanted,
+    };
+
+    if wanted.eq_ignore_ascii_case("list") {
+        let reply = match lichess::tv_channels(&web::client(&ctx.data).await).await {
+            Ok(channels) => {
+                let mut names: Vec<String> = channels.into_keys().collect();
+                names.sort();
+                format!("Lichess TV channels: {}", names.join(", "))
+            }
+            Err(why) => {
+                println!("Error getting Lichess TV: {:?}", why);
+                "I couldn't reach Lichess right now.".to_string()
+            }
+        };
+        msg.reply(&ctx.http, reply).await?;
+        return Ok(());
+    }
+
+    let featured = match featured(&ctx.data, wanted).await {
+        Ok(featured) => featured,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| tv_embed(e, &featured));
+            m.components(|c| tv_buttons(c, &featured));
+            m.reference_message(msg);
+            m
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    variant::{Variant as Rules, VariantPosition},
+    Color, Position, Role,
+};
+
+// Rules a game can be played by. The variants are the ones from Lichess, and shakmaty knows
+// their legal moves and how they end.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    #[default]
+    Standard,
+    // Captures explode, taking out every piece around them but pawns. Blow up the king to win.
+    Atomic,
+    // Captured pieces change sides and can be dropped back on the board, like "N@f3".
+    Crazyhouse,
+    // Bring your king to the middle four squares to win.
+    KingOfTheHill,
+}
+
+const POCKET_ORDER: [Role; 5] = [
+    Role::Queen,
+    Role::Rook,
+    Role::Bishop,
+    Role::Knight,
+    Role::Pawn,
+];
+
+impl Variant {
+    pub const ALL: [Variant; 4] = [
+        Variant::Standard,
+        Variant::Atomic,
+        Variant::Crazyhouse,
+        Variant::KingOfTheHill,
+    ];
+
+    // What to type, like `.challenge @user koth`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "standard",
+            Variant::Atomic => "atomic",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::KingOfTheHill => "koth",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Atomic => "Atomic",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::KingOfTheHill => "King of the Hill",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name.to_lowercase().as_str() {
+            "zh" | "house" => Some(Variant::Crazyhouse),
+            "kingofthehill" | "hill" => Some(Variant::KingOfTheHill),
+            name => Variant::ALL
+                .iter()
+                .copied()
+                .find(|variant| variant.name() == name),
+        }
+    }
+
+    fn rules(self) -> Rules {
+        match self {
+            Variant::Standard => Rules::Chess,
+            Variant::Atomic => Rules::Atomic,
+            Variant::Crazyhouse => Rules::Crazyhouse,
+            Variant::KingOfTheHill => Rules::KingOfTheHill,
+        }
+    }
+
+    pub fn start_position(self) -> VariantPosition {
+        VariantPosition::new(self.rules())
+    }
+
+    // How a game that ended by the variant's own rule is described.
+    pub fn ending(self) -> &'static str {
+        match self {
+            Variant::Atomic => "king exploded",
+            Variant::KingOfTheHill => "king reached the hill",
+            Variant::Standard | Variant::Crazyhouse => "variant end",
+        }
+    }
+}
+
+// The pieces each side can drop in crazyhouse, like "Pockets: ⚪ QNP · ⚫ none". None for
+// variants without pockets.
+pub fn pockets_text(pos: &VariantPosition) -> Option<String> {
+    let pockets = pos.pockets()?;
+    let side = |color: Color| {
+        let pocket = pockets.get(color);
+        let pieces: String = POCKET_ORDER
+            .iter()
+            .flat_map(|&role| {
+                std::iter::repeat_n(role.upper_char(), usize::from(*pocket.get(role)))
+            })
+            .collect();
+        if pieces.is_empty() {
+            "none".to_string()
+        } else {
+            pieces
+        }
+    };
+    Some(format!(
+        "Pockets: ⚪ {} · ⚫ {}",
+        side(Color::White),
+        side(Color::Black)
+    ))
+}
@@ -0,0 +1,698 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::{Message, ReactionType},
+        id::{ChannelId, GuildId, MessageId, UserId},
+    },
+    prelude::*,
+};
+use shakmaty::{san::SanPlus, uci::UciMove, CastlingMode, Color, Position};
+
+use crate::{
+    config,
+    diagram::{self, DiagramOptions},
+    engine::{self, Strength},
+    game::{self, Game, GameOptions, GameResult},
+    modules::BotModule,
+    notation::Notation,
+    permissions::ADMIN_CHECK,
+    render::{self, BoardStyle},
+    scheduler::JobKind,
+    settings, EMBED_SIDE_COLOR,
+};
+
+// Each candidate move gets one of these to vote with, so there can be ten.
+const VOTE_EMOJI: [&str; 10] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+
+const DEFAULT_MINUTES: u64 = 5;
+const MAX_MINUTES: u64 = 24 * 60;
+const DEFAULT_LEVEL: u8 = 3;
+
+// Moves shown under the board.
+const RECENT_MOVES: usize = 12;
+
+#[group]
+#[commands(votechess, suggest)]
+struct VoteChess;
+
+pub struct VoteChessModule;
+
+#[async_trait]
+impl BotModule for VoteChessModule {
+    fn name(&self) -> &'static str {
+        "votechess"
+    }
+
+    fn description(&self) -> &'static str {
+        "The whole channel plays the bot, voting on every move. See `.votechess`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&VOTECHESS_GROUP)
+    }
+
+    // Polls left open over a restart are closed along with the games' deadlines.
+    async fn run_job(&self, http: &Arc<Http>, data: &Arc<RwLock<TypeMap>>, kind: JobKind) {
+        if kind == JobKind::GameDeadlines {
+            close_overdue(http.clone(), data.clone()).await;
+        }
+    }
+}
+
+// A channel playing one side against the bot. Every turn members suggest moves and vote on them
+// with reactions, and when the poll closes the move with the most votes is played.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteGame {
+    // The channel's side has no one player, so it's 0 in the game.
+    pub game: Game,
+    pub window_secs: u64,
+    // Moves in UCI suggested this turn, in the order of their emoji.
+    pub candidates: Vec<String>,
+    // This turn's poll, and when it closes in seconds since the epoch.
+    pub poll_message: Option<u64>,
+    pub closes_at: i64,
+}
+
+impl VoteGame {
+    fn team(&self) -> Color {
+        if self.game.white == 0 {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+
+    fn channel_id(&self) -> ChannelId {
+        ChannelId(self.game.channel_id)
+    }
+
+    // A candidate in SAN, like "Nf3".
+    fn candidate_san(&self, uci: &str, notation: Notation) -> String {
+        let pos = self.game.position();
+        match uci
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|m| m.to_move(&pos).ok())
+        {
+            Some(m) => notation.localize(&SanPlus::from_move(pos, m).to_string()),
+            None => uci.to_string(),
+        }
+    }
+
+    fn candidates_text(&self, notation: Notation) -> String {
+        if self.candidates.is_empty() {
+            return "No moves suggested yet, use `.suggest <move>`".to_string();
+        }
+        let candidates: Vec<String> = self
+            .candidates
+            .iter()
+            .zip(VOTE_EMOJI.iter())
+            .map(|(uci, emoji)| format!("{} {}", emoji, self.candidate_san(uci, notation)))
+            .collect();
+        format!("**Candidates:** {}", candidates.join(" · "))
+    }
+}
+
+fn vote_embed<'a>(
+    e: &'a mut CreateEmbed,
+    vote: &VoteGame,
+    style: &BoardStyle,
+    notation: Notation,
+    image: bool,
+) -> &'a mut CreateEmbed {
+    let game = &vote.game;
+    let pos = game.position();
+    let team = match vote.team() {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+    let mut desc = format!(
+        "This channel plays {} against the bot at level {}.",
+        team,
+        game.engine_level.unwrap_or(DEFAULT_LEVEL)
+    );
+    if image {
+        e.image(diagram::IMAGE_URL);
+    } else {
+        desc.push_str(&format!("\n{}", render::board_for(pos.board(), style)));
+    }
+    if !game.moves.is_empty() {
+        desc.push_str(&format!("\n{}", game.move_list(notation, RECENT_MOVES)));
+    }
+    match (game.result, &game.ending) {
+        (Some(result), Some(ending)) => {
+            desc.push_str(&format!("\n**{}, {}**", ending, result.score()))
+        }
+        (Some(result), None) => desc.push_str(&format!("\n**{}**", result.score())),
+        (None, _) => desc.push_str(&format!(
+            "\n**Your move!** The poll closes <t:{}:R>.",
+            vote.closes_at
+        )),
+    }
+
+    e.title(format!("Vote chess · move {}", game.moves.len() / 2 + 1));
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    if !game.is_over() {
+        e.footer(|f| {
+            f.text(".suggest <move> · react to vote, one vote each");
+            f
+        });
+    }
+    e
+}
+
+// Do something with the channel's vote game, if it has one.
+async fn with_vote<F, T>(data: &RwLock<TypeMap>, channel_id: ChannelId, f: F) -> Option<T>
+where
+    F: FnOnce(&mut VoteGame) -> T,
+{
+    game::with_games(data, |store| {
+        store
+            .vote_games
+            .iter_mut()
+            .find(|vote| vote.game.channel_id == channel_id.0)
+            .map(f)
+    })
+    .await
+}
+
+// Let the bot move if it's its turn, then post the board with a new poll for the channel's
+// move, and close it when the window is up.
+async fn open_poll(
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    channel_id: ChannelId,
+) -> serenity::Result<()> {
+    let vote = match with_vote(&data, channel_id, |vote| vote.clone()).await {
+        Some(vote) => vote,
+        None => return Ok(()),
+    };
+    let vote = if vote.game.position().turn() != vote.team() && !vote.game.is_over() {
+        engine_move(&data, channel_id, &vote).await
+    } else {
+        vote
+    };
+    if vote.game.is_over() {
+        return finish(&http, &data, channel_id, &vote).await;
+    }
+    if vote.game.position().turn() != vote.team() {
+        channel_id
+            .say(
+                &http,
+                "I couldn't come up with a move, I'll try again in a few minutes.",
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let closes_at = Utc::now().timestamp();
+    let vote = with_vote(&data, channel_id, |vote| {
+        vote.candidates.clear();
+        vote.closes_at = closes_at + vote.window_secs as i64;
+        vote.clone()
+    })
+    .await;
+    let vote = match vote {
+        Some(vote) => vote,
+        None => return Ok(()),
+    };
+
+    let guild_id = vote.game.guild_id.map(GuildId);
+    let style = settings::board_style(&data, guild_id, None).await;
+    let notation = game::guild_notation(&data, vote.game.guild_id).await;
+    let pos = vote.game.position();
+    let options = DiagramOptions {
+        flipped: vote.team() == Color::Black,
+        highlight: vote.game.last_move(),
+    };
+    let image = diagram::board_image(&http, &data, channel_id, pos.board(), &style, &options).await;
+    let has_image = image.is_some();
+
+    let sent = channel_id
+        .send_message(&http, |m| {
+            m.content(vote.candidates_text(notation));
+            m.embed(|e| vote_embed(e, &vote, &style, notation, has_image));
+            if let Some(png) = image {
+                m.add_file(diagram::attachment(png));
+            }
+            m
+        })
+        .await?;
+
+    let plies = vote.game.moves.len();
+    with_vote(&data, channel_id, |vote| {
+        vote.poll_message = Some(sent.id.0);
+    })
+    .await;
+    close_later(http, data, channel_id, plies, vote.window_secs);
+    Ok(())
+}
+
+fn close_later(
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    channel_id: ChannelId,
+    plies: usize,
+    secs: u64,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+        if let Err(why) = close_poll(http, data, channel_id, plies).await {
+            println!("Error closing a vote chess poll: {:?}", why);
+        }
+    });
+}
+
+// Everyone's vote on the poll. People who picked more than one move don't count, and neither
+// does the bot's own reaction.
+async fn tally(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    candidates: usize,
+) -> serenity::Result<(Vec<u32>, usize)> {
+    let bot = crate::bot_id(data).await;
+    let mut votes: HashMap<UserId, Vec<usize>> = HashMap::new();
+    for (index, emoji) in VOTE_EMOJI.iter().enumerate().take(candidates) {
+        let mut after = None;
+        loop {
+            let users = channel_id
+                .reaction_users(
+                    http,
+                    message_id,
+                    ReactionType::Unicode(emoji.to_string()),
+                    Some(100),
+                    after,
+                )
+                .await?;
+            for user in &users {
+                if user.id != bot {
+                    votes.entry(user.id).or_default().push(index);
+                }
+            }
+            match users.last() {
+                Some(last) if users.len() == 100 => after = Some(last.id),
+                _ => break,
+            }
+        }
+    }
+
+    let mut counts = vec![0; candidates];
+    let mut doubled = 0;
+    for picks in votes.values() {
+        match picks.as_slice() {
+            [index] => counts[*index] += 1,
+            _ => doubled += 1,
+        }
+    }
+    Ok((counts, doubled))
+}
+
+// Play the winning move of the poll for the channel's turn `plies`, then the bot's answer, and
+// open the next poll. Polls without votes stay open for another window.
+async fn close_poll(
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    channel_id: ChannelId,
+    plies: usize,
+) -> serenity::Result<()> {
+    let vote = with_vote(&data, channel_id, |vote| vote.clone()).await;
+    let vote = match vote {
+        Some(vote) if vote.game.moves.len() == plies && !vote.game.is_over() => vote,
+        _ => return Ok(()),
+    };
+    let message_id = match vote.poll_message {
+        Some(message_id) => MessageId(message_id),
+        None => return Ok(()),
+    };
+
+    let (counts, doubled) =
+        tally(&http, &data, channel_id, message_id, vote.candidates.len()).await?;
+    // Ties go to the move suggested first.
+    let winner = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)));
+    let (index, count) = match winner {
+        Some((index, &count)) => (index, count),
+        None => {
+            let closes_at = Utc::now().timestamp() + vote.window_secs as i64;
+            with_vote(&data, channel_id, |vote| vote.closes_at = closes_at).await;
+            channel_id
+                .say(
+                    &http,
+                    format!(
+                        "Nobody voted, so the poll stays open until <t:{}:t>.",
+                        closes_at
+                    ),
+                )
+                .await?;
+            close_later(http, data, channel_id, plies, vote.window_secs);
+            return Ok(());
+        }
+    };
+
+    let notation = game::guild_notation(&data, vote.game.guild_id).await;
+    let uci = vote.candidates[index].clone();
+    let san = vote.candidate_san(&uci, notation);
+    let played = with_vote(&data, channel_id, |vote| {
+        if vote.game.moves.len() != plies {
+            return None;
+        }
+        let m = uci
+            .parse::<UciMove>()
+            .ok()?
+            .to_move(&vote.game.position())
+            .ok()?;
+        vote.game.play(m);
+        vote.poll_message = None;
+        Some(vote.clone())
+    })
+    .await
+    .flatten();
+    let vote = match played {
+        Some(vote) => vote,
+        None => return Ok(()),
+    };
+
+    let mut text = match count {
+        1 => format!("The channel plays **{}** with 1 vote.", san),
+        count => format!("The channel plays **{}** with {} votes.", san, count),
+    };
+    if doubled > 0 {
+        text.push_str(&format!(
+            " {} didn't count for voting more than once.",
+            match doubled {
+                1 => "1 person".to_string(),
+                doubled => format!("{} people", doubled),
+            }
+        ));
+    }
+    channel_id.say(&http, text).await?;
+
+    if vote.game.is_over() {
+        finish(&http, &data, channel_id, &vote).await
+    } else {
+        open_poll(http, data, channel_id).await
+    }
+}
+
+// The bot's move, played in the stored game. The game is unchanged if the engine fails.
+async fn engine_move(data: &RwLock<TypeMap>, channel_id: ChannelId, vote: &VoteGame) -> VoteGame {
+    let level = vote.game.engine_level.unwrap_or(DEFAULT_LEVEL);
+    let plies = vote.game.moves.len();
+    let uci = match engine::best_move(
+        data,
+        None,
+        &vote.game.moves,
+        Strength::Level(level),
+        CastlingMode::Standard,
+    )
+    .await
+    {
+        Ok(uci) => uci,
+        Err(why) => {
+            println!("Engine error in vote chess: {:?}", why);
+            return vote.clone();
+        }
+    };
+    with_vote(data, channel_id, |stored| {
+        if stored.game.moves.len() == plies {
+            let pos = stored.game.position();
+            if let Some(m) = uci
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|m| m.to_move(&pos).ok())
+            {
+                stored.game.play(m);
+            }
+        }
+        stored.clone()
+    })
+    .await
+    .unwrap_or_else(|| vote.clone())
+}
+
+// Show the final position and forget the game.
+async fn finish(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    channel_id: ChannelId,
+    vote: &VoteGame,
+) -> serenity::Result<()> {
+    game::with_games(data, |store| {
+        store
+            .vote_games
+            .retain(|stored| stored.game.channel_id != channel_id.0)
+    })
+    .await;
+
+    let style = settings::board_style(data, vote.game.guild_id.map(GuildId), None).await;
+    let notation = game::guild_notation(data, vote.game.guild_id).await;
+    let won = match (vote.game.result, vote.team()) {
+        (Some(GameResult::WhiteWon), Color::White) | (Some(GameResult::BlackWon), Color::Black) => {
+            "The channel wins, well played everyone!"
+        }
+        (Some(GameResult::Draw), _) => "It's a draw!",
+        _ => "The bot wins this time.",
+    };
+    channel_id
+        .send_message(http, |m| {
+            m.content(won);
+            m.embed(|e| vote_embed(e, vote, &style, notation, false));
+            m
+        })
+        .await?;
+    Ok(())
+}
+
+// Close polls a restart left open. Run by the scheduler with the game deadlines.
+pub async fn close_overdue(http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
+    let now = Utc::now().timestamp();
+    let overdue: Vec<(ChannelId, usize, bool)> = game::read_games(&data, |store| {
+        store
+            .vote_games
+            .iter()
+            .filter(|vote| vote.closes_at < now)
+            .map(|vote| {
+                (
+                    vote.channel_id(),
+                    vote.game.moves.len(),
+                    vote.poll_message.is_some(),
+                )
+            })
+            .collect()
+    })
+    .await;
+    for (channel_id, plies, has_poll) in overdue {
+        let result = if has_poll {
+            close_poll(http.clone(), data.clone(), channel_id, plies).await
+        } else {
+            open_poll(http.clone(), data.clone(), channel_id).await
+        };
+        if let Err(why) = result {
+            println!("Error catching up on vote chess: {:?}", why);
+        }
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[sub_commands(start, stop)]
+#[description("Play the bot as a channel! Everyone suggests moves with `.suggest` and votes on them, and the most popular move is played.")]
+async fn votechess(ctx: &Context, msg: &Message) -> CommandResult {
+    let poll = with_vote(&ctx.data, msg.channel_id, |vote| vote.poll_message).await;
+    let text = match poll {
+        Some(Some(poll)) => format!(
+            "The vote is on: {}",
+            MessageId(poll).link(msg.channel_id, msg.guild_id)
+        ),
+        Some(None) => "The bot is thinking...".to_string(),
+        None => "No vote chess game here. Admins can start one with `.votechess start [white|black] [level] [minutes to vote]`".to_string(),
+    };
+    msg.reply(&ctx.http, text).await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("Start a vote chess game in this channel. The channel plays white unless you say otherwise, and has 5 minutes to vote on each move.")]
+#[usage("[white|black] [level] [minutes]")]
+#[example("black 4 10")]
+async fn start(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let usage = "Use `.votechess start [white|black] [level from 1 to 8] [minutes to vote]`";
+    let mut team = Color::White;
+    let mut level = DEFAULT_LEVEL;
+    let mut minutes = DEFAULT_MINUTES;
+    let mut numbers = 0;
+    for arg in args.iter::<String>().flatten() {
+        match (arg.to_lowercase().as_str(), arg.parse::<u64>()) {
+            ("white", _) => team = Color::White,
+            ("black", _) => team = Color::Black,
+            (_, Ok(n)) if numbers == 0 && n <= 8 && engine::LEVELS.contains(&(n as u8)) => {
+                level = n as u8;
+                numbers += 1;
+            }
+            (_, Ok(n)) if numbers == 1 && (1..=MAX_MINUTES).contains(&n) => {
+                minutes = n;
+                numbers += 1;
+            }
+            _ => {
+                msg.reply(&ctx.http, usage).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    let bot = crate::bot_id(&ctx.data).await.0;
+    let players = match team {
+        Color::White => (0, bot),
+        Color::Black => (bot, 0),
+    };
+    let game = Game {
+        white: players.0,
+        black: players.1,
+        engine_level: Some(level),
+        ..GameOptions::default().new_game(msg.guild_id.map(|id| id.0), msg.channel_id.0, players)
+    };
+    let started = game::with_games(&ctx.data, |store| {
+        if store
+            .vote_games
+            .iter()
+            .any(|vote| vote.game.channel_id == msg.channel_id.0)
+        {
+            return false;
+        }
+        store.vote_games.push(VoteGame {
+            game,
+            window_secs: minutes * 60,
+            candidates: Vec::new(),
+            poll_message: None,
+            closes_at: 0,
+        });
+        true
+    })
+    .await;
+    if !started {
+        msg.reply(
+            &ctx.http,
+            "There's already a vote chess game here, `.votechess stop` ends it.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    open_poll(ctx.http.clone(), ctx.data.clone(), msg.channel_id).await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[description("End this channel's vote chess game.")]
+async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    let stopped = game::with_games(&ctx.data, |store| {
+        let before = store.vote_games.len();
+        store
+            .vote_games
+            .retain(|vote| vote.game.channel_id != msg.channel_id.0);
+        store.vote_games.len() < before
+    })
+    .await;
+    let text = if stopped {
+        "Vote chess is over for now."
+    } else {
+        "There's no vote chess game here."
+    };
+    msg.reply(&ctx.http, text).await?;
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases("propose")]
+#[description("Suggest a move for this channel's vote chess game. It's added to the poll for everyone to vote on.")]
+#[usage("<move>")]
+#[example("Nf3")]
+async fn suggest(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest().trim();
+    if text.is_empty() {
+        msg.reply(&ctx.http, "Use `.suggest <move>`, like `.suggest e4`")
+            .await?;
+        return Ok(());
+    }
+    let notation = game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await;
+
+    let added = with_vote(&ctx.data, msg.channel_id, |vote| {
+        if vote.poll_message.is_none() || vote.game.is_over() {
+            return Err("Wait for the bot's move first.".to_string());
+        }
+        let pos = vote.game.position();
+        let m = game::parse_move(&pos, text, notation).map_err(|why| format!("Hmm, {}.", why))?;
+        let uci = UciMove::from_move(m, vote.game.castling_mode()).to_string();
+        if let Some(index) = vote.candidates.iter().position(|c| *c == uci) {
+            return Ok((vote.clone(), index, false));
+        }
+        if vote.candidates.len() >= VOTE_EMOJI.len() {
+            return Err("The poll is full, vote for one of the moves on it.".to_string());
+        }
+        vote.candidates.push(uci);
+        Ok((vote.clone(), vote.candidates.len() - 1, true))
+    })
+    .await;
+
+    let (vote, index, new) = match added {
+        Some(Ok(added)) => added,
+        Some(Err(why)) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+        None => {
+            msg.reply(&ctx.http, "There's no vote chess game here.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let poll = MessageId(vote.poll_message.expect("checked above"));
+    let san = vote.candidate_san(&vote.candidates[index], notation);
+
+    if new {
+        msg.channel_id
+            .edit_message(&ctx.http, poll, |m| {
+                m.content(vote.candidates_text(notation))
+            })
+            .await?;
+        msg.channel_id
+            .create_reaction(
+                &ctx.http,
+                poll,
+                ReactionType::Unicode(VOTE_EMOJI[index].to_string()),
+            )
+            .await?;
+    }
+    msg.reply(
+        &ctx.http,
+        format!(
+            "{} is {} on the poll: {}",
+            san,
+            VOTE_EMOJI[index],
+            poll.link(msg.channel_id, msg.guild_id)
+        ),
+    )
+    .await?;
+    Ok(())
+}
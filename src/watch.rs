@@ -0,0 +1,395 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandGroup, CommandResult,
+    },
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, MessageId, UserId},
+    },
+    prelude::*,
+    utils,
+};
+use shakmaty::{san::SanPlus, uci::UciMove, Chess};
+
+use crate::{
+    accounts, fen, game,
+    lichess::{self, LineStream, Players, StreamEvent},
+    modules::BotModule,
+    notation::Notation,
+    web, EMBED_SIDE_COLOR,
+};
+
+// Games relayed at once, over every server.
+const MAX_WATCHES: usize = 10;
+
+// Discord rate limits edits, so moves played faster than this are shown together.
+const EDIT_INTERVAL: Duration = Duration::from_secs(3);
+
+// Correspondence games could go on for weeks, stop relaying after this long.
+const MAX_WATCH_TIME: Duration = Duration::from_secs(3 * 60 * 60);
+
+#[group]
+#[commands(watch)]
+struct Watches;
+
+pub struct WatchModule;
+
+impl BotModule for WatchModule {
+    fn name(&self) -> &'static str {
+        "watch"
+    }
+
+    fn description(&self) -> &'static str {
+        "Live Lichess games relayed move by move, see `.watch`."
+    }
+
+    fn group(&self) -> Option<&'static CommandGroup> {
+        Some(&WATCHES_GROUP)
+    }
+
+    fn insert_data(&self, data: &mut TypeMap) {
+        data.insert::<WatchContainer>(Mutex::default());
+    }
+}
+
+// The games being relayed, by channel and Lichess game id.
+pub struct WatchContainer;
+
+impl TypeMapKey for WatchContainer {
+    type Value = Mutex<HashSet<(u64, String)>>;
+}
+
+async fn with_watches<F, T>(data: &RwLock<TypeMap>, f: F) -> T
+where
+    F: FnOnce(&mut HashSet<(u64, String)>) -> T,
+{
+    let data = data.read().await;
+    let mut watches = data
+        .get::<WatchContainer>()
+        .expect("Expected watches in typemap.")
+        .lock()
+        .await;
+    f(&mut watches)
+}
+
+// Everything known about the game so far, from the lines of its stream.
+#[derive(Default)]
+struct LiveGame {
+    id: String,
+    players: Option<Players>,
+    fen: Option<String>,
+    last_move: Option<String>,
+    // The last move in SAN, worked out from the position before it.
+    last_san: Option<String>,
+    clocks: Option<(u32, u32)>,
+    status: String,
+    winner: Option<String>,
+    flipped: bool,
+    notation: Notation,
+}
+
+impl LiveGame {
+    fn apply(&mut self, event: StreamEvent) {
+        if let Some(players) = event.players {
+            self.players = Some(players);
+        }
+        if let Some(fen) = event.fen {
+            let before = self.fen.as_deref().and_then(fen::parse_position);
+            self.last_san = match (before, &event.last_move) {
+                (Some(before), Some(uci)) => san(before, uci, self.notation),
+                _ => None,
+            };
+            self.fen = Some(fen);
+            self.last_move = event.last_move;
+        }
+        if let (Some(white), Some(black)) = (event.wc, event.bc) {
+            self.clocks = Some((white, black));
+        }
+        if let Some(status) = event.status {
+            self.status = status.name;
+        }
+        if event.winner.is_some() {
+            self.winner = event.winner;
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        !matches!(self.status.as_str(), "" | "created" | "started")
+    }
+
+    // "1-0 · mate", once it's over.
+    fn result(&self) -> String {
+        let score = match (self.winner.as_deref(), self.status.as_str()) {
+            (_, "aborted") => return "Aborted".to_string(),
+            (Some("white"), _) => "1-0",
+            (Some("black"), _) => "0-1",
+            _ => "½-½",
+        };
+        format!("{} · {}", score, self.status)
+    }
+
+    fn url(&self) -> String {
+        format!("https://lichess.org/{}", self.id)
+    }
+}
+
+fn san(before: Chess, uci: &str, notation: Notation) -> Option<String> {
+    let m = uci.parse::<UciMove>().ok()?.to_move(&before).ok()?;
+    Some(notation.localize(&SanPlus::from_move(before, m).to_string()))
+}
+
+fn live_embed<'a>(e: &'a mut CreateEmbed, live: &LiveGame) -> &'a mut CreateEmbed {
+    let (white, black) = match &live.players {
+        Some(players) => (players.white.display_name(), players.black.display_name()),
+        None => ("White".to_string(), "Black".to_string()),
+    };
+    let clock = |seconds: Option<u32>| match seconds {
+        Some(seconds) => format!(" · {}", game::format_clock(i64::from(seconds) * 1000)),
+        None => String::new(),
+    };
+
+    let mut desc = format!(
+        "⬜ {}{}\n⬛ {}{}",
+        white,
+        clock(live.clocks.map(|clocks| clocks.0)),
+        black,
+        clock(live.clocks.map(|clocks| clocks.1))
+    );
+    if let Some(san) = &live.last_san {
+        desc.push_str(&format!("\nLast move: **{}**", san));
+    }
+
+    e.title(if live.is_over() {
+        format!("Finished: {} vs {}", white, black)
+    } else {
+        format!("🔴 Live: {} vs {}", white, black)
+    });
+    e.url(live.url());
+    e.color(EMBED_SIDE_COLOR);
+    e.description(desc);
+    if let Some(fen) = &live.fen {
+        e.image(lichess::position_image_url(
+            fen,
+            live.last_move.as_deref(),
+            live.flipped,
+        ));
+    }
+    e.footer(|f| {
+        if live.is_over() {
+            f.text(live.result())
+        } else {
+            f.text("lichess.org · updates as they play")
+        }
+    });
+    e
+}
+
+async fn show(http: &Http, channel_id: ChannelId, message_id: MessageId, live: &LiveGame) {
+    let result = channel_id
+        .edit_message(http, message_id, |m| m.embed(|e| live_embed(e, live)))
+        .await;
+    if let Err(why) = result {
+        println!("Error updating a watched game: {:?}", why);
+    }
+}
+
+// Relay the game into the message until the stream ends, editing it at most every few seconds.
+async fn relay(
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    mut live: LiveGame,
+    mut stream: LineStream,
+) {
+    let started = Instant::now();
+    let mut last_edit = Instant::now();
+    let mut dirty = false;
+    loop {
+        let left = MAX_WATCH_TIME.saturating_sub(started.elapsed());
+        let wait = if dirty {
+            EDIT_INTERVAL.saturating_sub(last_edit.elapsed()).min(left)
+        } else {
+            left
+        };
+        let line = match tokio::time::timeout(wait, stream.next_line()).await {
+            Ok(line) => line,
+            // Nothing new for a bit, show what's waiting.
+            Err(_) if dirty => {
+                show(&http, channel_id, message_id, &live).await;
+                last_edit = Instant::now();
+                dirty = false;
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        match line {
+            Ok(Some(line)) => match serde_json::from_str::<StreamEvent>(&line) {
+                Ok(event) => {
+                    live.apply(event);
+                    dirty = true;
+                }
+                Err(why) => println!("Could not read a Lichess stream line: {:?}", why),
+            },
+            Ok(None) => break,
+            Err(why) => {
+                println!("Error streaming Lichess game {}: {:?}", live.id, why);
+                break;
+            }
+        }
+        if live.is_over() {
+            break;
+        }
+        if dirty && last_edit.elapsed() >= EDIT_INTERVAL {
+            show(&http, channel_id, message_id, &live).await;
+            last_edit = Instant::now();
+            dirty = false;
+        }
+    }
+
+    if !live.is_over() {
+        // The stream stopped without an ending, or ran too long.
+        let result = channel_id
+            .edit_message(&http, message_id, |m| {
+                m.embed(|e| {
+                    live_embed(e, &live);
+                    e.footer(|f| f.text("Stopped relaying, follow the rest on lichess.org"))
+                })
+            })
+            .await;
+        if let Err(why) = result {
+            println!("Error updating a watched game: {:?}", why);
+        }
+    } else {
+        show(&http, channel_id, message_id, &live).await;
+    }
+}
+
+// The game to watch and the player asked for, from a link, a username or a member's linked
+// account.
+async fn find_game(data: &RwLock<TypeMap>, arg: &str) -> Result<(String, Option<String>), String> {
+    if let Some(id) = lichess::game_id_from_url(arg.trim_matches(|c| c == '<' || c == '>')) {
+        return Ok((id, None));
+    }
+    let username = match utils::parse_username(arg) {
+        Some(user_id) => accounts::lichess_username(data, UserId(user_id))
+            .await
+            .ok_or("They haven't linked a Lichess account with `.link`.")?,
+        None if lichess::is_username(arg) => arg.to_string(),
+        None => return Err("Use `.watch <Lichess game link or username>`".to_string()),
+    };
+    match lichess::playing_game(&web::client(data).await, &username).await {
+        Ok(Some(id)) => Ok((id, Some(username))),
+        Ok(None) => Err(format!("{} isn't playing on Lichess right now.", username)),
+        Err(why) => {
+            println!("Error getting {}'s Lichess status: {:?}", username, why);
+            Err("I couldn't reach Lichess right now.".to_string())
+        }
+    }
+}
+
+#[command]
+#[description(
+    "Relay a Lichess game as it's played, in one message that shows the board and clocks after every move until the game ends. Give a game link, or a username or member to watch the game they're playing now."
+)]
+#[usage("<game link | username | @member>")]
+#[example("DrNykterstein")]
+async fn watch(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let arg = args.rest().trim();
+    if arg.is_empty() {
+        msg.reply(&ctx.http, "Use `.watch <Lichess game link or username>`")
+            .await?;
+        return Ok(());
+    }
+    let (id, username) = match find_game(&ctx.data, arg).await {
+        Ok(found) => found,
+        Err(why) => {
+            msg.reply(&ctx.http, why).await?;
+            return Ok(());
+        }
+    };
+
+    let key = (msg.channel_id.0, id.clone());
+    let added = with_watches(&ctx.data, |watches| {
+        if watches.contains(&key) {
+            Err("I'm already relaying that game here.")
+        } else if watches.len() >= MAX_WATCHES {
+            Err("I'm relaying too many games right now, try again later.")
+        } else {
+            watches.insert(key.clone());
+            Ok(())
+        }
+    })
+    .await;
+    if let Err(why) = added {
+        msg.reply(&ctx.http, why).await?;
+        return Ok(());
+    }
+
+    let mut stream = match lichess::stream_game(&web::client(&ctx.data).await, &id).await {
+        Ok(stream) => stream,
+        Err(why) => {
+            println!("Error streaming Lichess game {}: {:?}", id, why);
+            with_watches(&ctx.data, |watches| watches.remove(&key)).await;
+            msg.reply(&ctx.http, "I couldn't find that game on Lichess.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // The first line has the players and the position, wait for it before posting.
+    let mut live = LiveGame {
+        id: id.clone(),
+        notation: game::guild_notation(&ctx.data, msg.guild_id.map(|id| id.0)).await,
+        ..LiveGame::default()
+    };
+    match stream.next_line().await {
+        Ok(Some(line)) => {
+            if let Ok(event) = serde_json::from_str::<StreamEvent>(&line) {
+                live.apply(event);
+            }
+        }
+        Ok(None) => {}
+        Err(why) => println!("Error streaming Lichess game {}: {:?}", id, why),
+    }
+    if let (Some(username), Some(players)) = (&username, &live.players) {
+        live.flipped = players.color_of(username) == Some("black");
+    }
+
+    let sent = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| live_embed(e, &live));
+            m.reference_message(msg);
+            m
+        })
+        .await;
+    let sent = match sent {
+        Ok(sent) => sent,
+        Err(why) => {
+            with_watches(&ctx.data, |watches| watches.remove(&key)).await;
+            return Err(why.into());
+        }
+    };
+    if live.is_over() {
+        with_watches(&ctx.data, |watches| watches.remove(&key)).await;
+        return Ok(());
+    }
+
+    let (http, data, channel_id) = (ctx.http.clone(), ctx.data.clone(), msg.channel_id);
+    tokio::spawn(async move {
+        relay(http, channel_id, sent.id, live, stream).await;
+        with_watches(&data, |watches| watches.remove(&key)).await;
+    });
+
+    Ok(())
+}
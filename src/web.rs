@@ -0,0 +1,22 @@
+use serenity::prelude::*;
+
+// Shared HTTP client for talking to chess sites.
+pub struct WebClientContainer;
+
+impl TypeMapKey for WebClientContainer {
+    type Value = reqwest::Client;
+}
+
+pub fn new_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(concat!("cute-chess-bot/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Err creating web client")
+}
+
+pub async fn client(data: &RwLock<TypeMap>) -> reqwest::Client {
+    let data = data.read().await;
+    data.get::<WebClientContainer>()
+        .expect("Expected web client in typemap.")
+        .clone()
+}